@@ -10,8 +10,8 @@
 #![allow(clippy::result_large_err)]
 
 use crate::program::{
-    Action, AwkRule, BuiltinFunction, Constant, DebugInfo, Function, OpCode, Pattern, Program,
-    SourceLocation, SpecialVar, VarId,
+    Action, AwkRule, BuiltinFunction, Constant, DebugInfo, Function, OpCode, Pattern, ProfilePoint,
+    Program, SourceLocation, SpecialVar, VarId,
 };
 use crate::regex::Regex;
 use pest::error::InputLocation;
@@ -150,6 +150,41 @@ lazy_static::lazy_static! {
             max_args: 1,
         })
     ]);
+
+    /// gawk extensions. They are parsed as regular function calls, so that
+    /// with `--posix` their names are ordinary identifiers.
+    static ref EXTENSION_FUNCTIONS: HashMap<&'static str, BuiltinFunctionInfo> = HashMap::from([
+        ("gensub", BuiltinFunctionInfo {
+            function: BuiltinFunction::Gensub,
+            min_args: 3,
+            max_args: 4,
+        }),
+        ("strftime", BuiltinFunctionInfo {
+            function: BuiltinFunction::Strftime,
+            min_args: 0,
+            max_args: 3,
+        }),
+        ("systime", BuiltinFunctionInfo {
+            function: BuiltinFunction::Systime,
+            min_args: 0,
+            max_args: 0,
+        }),
+        ("mktime", BuiltinFunctionInfo {
+            function: BuiltinFunction::Mktime,
+            min_args: 1,
+            max_args: 2,
+        }),
+        ("asort", BuiltinFunctionInfo {
+            function: BuiltinFunction::Asort,
+            min_args: 1,
+            max_args: 3,
+        }),
+        ("asorti", BuiltinFunctionInfo {
+            function: BuiltinFunction::Asorti,
+            min_args: 1,
+            max_args: 3,
+        }),
+    ]);
 }
 
 lazy_static::lazy_static! {
@@ -183,7 +218,7 @@ lazy_static::lazy_static! {
 
 #[derive(pest_derive::Parser, Default)]
 #[grammar = "grammar.pest"]
-struct AwkParser;
+pub struct AwkParser;
 
 type PestError = pest::error::Error<Rule>;
 
@@ -271,6 +306,10 @@ fn post_increment(val: &Cell<u32>) -> u32 {
     result
 }
 
+/// Largest integer such that it and all smaller integers are exactly
+/// representable as an `f64`.
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
 fn parse_float(val: &str) -> f64 {
     lexical::parse_partial_with_options::<f64, _, { lexical::format::C_LITERAL }>(
         val,
@@ -280,6 +319,18 @@ fn parse_float(val: &str) -> f64 {
     .0
 }
 
+/// Describes the rule in error messages: by its pattern if it has one,
+/// by its starting line otherwise
+fn rule_description(rule: &Pair<Rule>) -> Rc<str> {
+    if rule.as_rule() == Rule::action {
+        format!("rule at line {}", rule.line_col().0).into()
+    } else {
+        let pattern = rule.clone().into_inner().next().unwrap();
+        let pattern = pattern.as_str().split_whitespace().collect::<Vec<_>>();
+        format!("rule `{}`", pattern.join(" ")).into()
+    }
+}
+
 fn lvalue_to_scalar_ref(instructions: &mut [OpCode]) {
     let last_ref = instructions
         .last_mut()
@@ -325,6 +376,16 @@ fn normalize_builtin_function_arguments(
             args[0..2].rotate_right(1);
             (flatten(args), argc)
         }
+        BuiltinFunction::Gensub => {
+            let mut instructions = flatten(args);
+            if argc == 3 {
+                instructions.extend(Instructions::from_instructions_and_line_col(
+                    vec![OpCode::PushZero, OpCode::GetField],
+                    line_col,
+                ));
+            }
+            (instructions, 4)
+        }
         BuiltinFunction::Sub | BuiltinFunction::Gsub => {
             if argc == 2 {
                 let mut instructions = Instructions::from_instructions_and_line_col(
@@ -409,12 +470,13 @@ impl Instructions {
         self.source_locations.extend(instructions.source_locations);
     }
 
-    fn into_action(self, file: Rc<str>) -> Action {
+    fn into_action(self, file: Rc<str>, rule: Rc<str>) -> Action {
         Action {
             instructions: self.opcodes,
             debug_info: DebugInfo {
                 source_locations: self.source_locations,
                 file,
+                rule,
             },
         }
     }
@@ -438,6 +500,10 @@ struct Compiler {
     last_global_function_id: Cell<u32>,
     in_function: bool,
     loop_stack: Vec<LoopStubs>,
+    posix: bool,
+    profile: bool,
+    current_source: usize,
+    profile_points: Vec<ProfilePoint>,
 }
 
 impl Default for Compiler {
@@ -515,11 +581,29 @@ impl Default for Compiler {
             last_global_function_id: Cell::new(0),
             loop_stack: Vec::new(),
             in_function: false,
+            posix: false,
+            profile: false,
+            current_source: 0,
+            profile_points: Vec::new(),
         }
     }
 }
 
 impl Compiler {
+    /// Returns the instruction counting the executions of `pair`
+    /// if compiling for profiling
+    fn profile_counter(&mut self, pair: &Pair<Rule>) -> Option<OpCode> {
+        if !self.profile {
+            return None;
+        }
+        let counter = self.profile_points.len() as u32;
+        self.profile_points.push(ProfilePoint {
+            source: self.current_source,
+            offset: pair.as_span().start(),
+        });
+        Some(OpCode::CountExecution(counter))
+    }
+
     fn push_constant(&self, constant: Constant) -> u32 {
         let index = self.constants.borrow().len() as u32;
         self.constants.borrow_mut().push(constant);
@@ -570,6 +654,56 @@ impl Compiler {
         Ok(argc as u16)
     }
 
+    fn compile_builtin_function_call(
+        &self,
+        fn_info: &BuiltinFunctionInfo,
+        name: &str,
+        args: Pairs<Rule>,
+        span: pest::Span,
+        line_col: (usize, usize),
+        locals: &LocalMap,
+    ) -> Result<Expr, PestError> {
+        let mut arg_list = Vec::new();
+        for arg in args {
+            let mut arg_instructions = Instructions::default();
+            self.compile_expr(arg, &mut arg_instructions, locals)?;
+            arg_list.push(arg_instructions);
+            if arg_list.len() > u16::MAX as usize {
+                return Err(pest_error_from_span(
+                    span,
+                    "function call with too many arguments".to_string(),
+                ));
+            }
+        }
+        let argc = arg_list.len() as u16;
+        // gawk accepts an array as the third argument of match
+        let max_args = if fn_info.function == BuiltinFunction::Match && !self.posix {
+            3
+        } else {
+            fn_info.max_args
+        };
+        if (fn_info.min_args..=max_args).contains(&argc) {
+            let (mut instructions, argc) =
+                normalize_builtin_function_arguments(fn_info.function, arg_list, line_col);
+            instructions.push(
+                OpCode::CallBuiltin {
+                    function: fn_info.function,
+                    argc,
+                },
+                line_col,
+            );
+            Ok(Expr::new(ExprKind::Number, instructions))
+        } else {
+            Err(pest_error_from_span(
+                span,
+                format!(
+                    "incorrect number of arguments for builtin function '{}'",
+                    name
+                ),
+            ))
+        }
+    }
+
     fn map_primary(&self, primary: Pair<Rule>, locals: &LocalMap) -> Result<Expr, PestError> {
         match primary.as_rule() {
             Rule::expr => {
@@ -592,7 +726,14 @@ impl Compiler {
             }
             Rule::number => {
                 let num = parse_float(primary.as_str());
-                let index = self.push_constant(Constant::Number(num));
+                // Rounding never takes a literal of at least 2^53 below it, so
+                // every integer literal the f64 may have rounded is caught.
+                let constant = if num.abs() >= MAX_EXACT_INTEGER {
+                    Constant::LargeNumber(num, Rc::from(primary.as_str()))
+                } else {
+                    Constant::Number(num)
+                };
+                let index = self.push_constant(constant);
                 Ok(Expr::new(
                     ExprKind::Number,
                     Instructions::from_instructions_and_line_col(
@@ -625,6 +766,17 @@ impl Compiler {
                 let line_col = primary.line_col();
                 let mut inner = primary.into_inner();
                 let name = inner.next().unwrap().as_str();
+                let is_user_function = matches!(
+                    self.names.borrow().get(name),
+                    Some(GlobalName::Function { .. })
+                );
+                if !is_user_function && !self.posix {
+                    if let Some(fn_info) = EXTENSION_FUNCTIONS.get(name) {
+                        return self.compile_builtin_function_call(
+                            fn_info, name, inner, span, line_col, locals,
+                        );
+                    }
+                }
                 let mut instructions = Instructions::default();
                 let argc = self.compile_function_args(inner, &mut instructions, span, locals)?;
                 match self.names.borrow().get(name) {
@@ -668,42 +820,17 @@ impl Compiler {
                 let line_col = primary.line_col();
                 let mut inner = primary.into_inner();
                 let function = inner.next().unwrap();
-                let mut args = Vec::new();
-                for arg in inner {
-                    let mut arg_instructions = Instructions::default();
-                    self.compile_expr(arg, &mut arg_instructions, locals)?;
-                    args.push(arg_instructions);
-                    if args.len() > u16::MAX as usize {
-                        return Err(pest_error_from_span(
-                            span,
-                            "function call with too many arguments".to_string(),
-                        ));
-                    }
-                }
-                let argc = args.len() as u16;
                 let fn_info = BUILTIN_FUNCTIONS
                     .get(&function.as_rule())
                     .expect("missing builtin");
-                if (fn_info.min_args..=fn_info.max_args).contains(&argc) {
-                    let (mut instructions, argc) =
-                        normalize_builtin_function_arguments(fn_info.function, args, line_col);
-                    instructions.push(
-                        OpCode::CallBuiltin {
-                            function: fn_info.function,
-                            argc,
-                        },
-                        line_col,
-                    );
-                    Ok(Expr::new(ExprKind::Number, instructions))
-                } else {
-                    Err(pest_error_from_span(
-                        span,
-                        format!(
-                            "incorrect number of arguments for builtin function '{}'",
-                            function.as_str()
-                        ),
-                    ))
-                }
+                self.compile_builtin_function_call(
+                    fn_info,
+                    function.as_str(),
+                    inner,
+                    span,
+                    line_col,
+                    locals,
+                )
             }
             _ => unreachable!(
                 "encountered {:?} while compiling primary",
@@ -1451,6 +1578,11 @@ impl Compiler {
         instructions: &mut Instructions,
         locals: &LocalMap,
     ) -> Result<(), PestError> {
+        if stmt.as_rule() != Rule::action {
+            if let Some(counter) = self.profile_counter(&stmt) {
+                instructions.push(counter, stmt.line_col());
+            }
+        }
         match stmt.as_rule() {
             Rule::action => self.compile_action(stmt, instructions, locals),
             Rule::t_if => self.compile_if(stmt, instructions, locals),
@@ -1529,28 +1661,41 @@ impl Compiler {
         &mut self,
         pattern: Pair<Rule>,
         file: Rc<str>,
+        rule_description: Rc<str>,
     ) -> Result<Pattern, PestError> {
+        let counter = self.profile_counter(&pattern);
         let pattern = first_child(pattern);
         match pattern.as_rule() {
             Rule::expr => {
                 let mut instructions = Instructions::default();
+                if let Some(counter) = counter {
+                    instructions.push(counter, pattern.line_col());
+                }
                 self.compile_expr(pattern, &mut instructions, &HashMap::new())?;
-                Ok(Pattern::Expr(instructions.into_action(file)))
+                Ok(Pattern::Expr(
+                    instructions.into_action(file, rule_description),
+                ))
             }
             Rule::range_pattern => {
                 let mut inner = pattern.into_inner();
 
                 let start = inner.next().unwrap();
                 let mut start_instructions = Instructions::default();
+                if let Some(counter) = counter {
+                    start_instructions.push(counter, start.line_col());
+                }
                 self.compile_expr(start, &mut start_instructions, &HashMap::new())?;
 
                 let end = inner.next().unwrap();
                 let mut end_instructions = Instructions::default();
+                if let Some(counter) = counter {
+                    end_instructions.push(counter, end.line_col());
+                }
                 self.compile_expr(end, &mut end_instructions, &HashMap::new())?;
 
                 Ok(Pattern::Range {
-                    start: start_instructions.into_action(file.clone()),
-                    end: end_instructions.into_action(file),
+                    start: start_instructions.into_action(file.clone(), rule_description.clone()),
+                    end: end_instructions.into_action(file, rule_description),
                 })
             }
             _ => unreachable!(
@@ -1562,30 +1707,42 @@ impl Compiler {
 
     fn compile_rule(&mut self, rule: Pair<Rule>, file: Rc<str>) -> Result<AwkRule, PestError> {
         let rule = first_child(rule);
+        let description = rule_description(&rule);
         match rule.as_rule() {
             Rule::action => {
                 let mut instructions = Instructions::default();
+                if let Some(counter) = self.profile_counter(&rule) {
+                    instructions.push(counter, rule.line_col());
+                }
                 self.compile_action(rule, &mut instructions, &HashMap::new())?;
                 Ok(AwkRule {
                     pattern: Pattern::All,
-                    action: instructions.into_action(file),
+                    action: instructions.into_action(file, description),
                 })
             }
             Rule::pattern_and_action => {
                 let mut inner = rule.into_inner();
-                let pattern = self.compile_normal_pattern(inner.next().unwrap(), file.clone())?;
+                let pattern = self.compile_normal_pattern(
+                    inner.next().unwrap(),
+                    file.clone(),
+                    description.clone(),
+                )?;
                 let action = inner.next().unwrap();
                 let mut instructions = Instructions::default();
+                if let Some(counter) = self.profile_counter(&action) {
+                    instructions.push(counter, action.line_col());
+                }
                 let locals = HashMap::new();
                 self.compile_action(action, &mut instructions, &locals)?;
                 Ok(AwkRule {
                     pattern,
-                    action: instructions.into_action(file),
+                    action: instructions.into_action(file, description),
                 })
             }
             Rule::normal_pattern => {
                 let rule_line_col = rule.line_col();
-                let pattern = self.compile_normal_pattern(rule, file.clone())?;
+                let pattern =
+                    self.compile_normal_pattern(rule, file.clone(), description.clone())?;
                 let instructions = Instructions::from_instructions_and_line_col(
                     vec![
                         OpCode::PushZero,
@@ -1599,7 +1756,7 @@ impl Compiler {
                 );
                 Ok(AwkRule {
                     pattern,
-                    action: instructions.into_action(file),
+                    action: instructions.into_action(file, description),
                 })
             }
             _ => unreachable!("encountered {:?} while compiling rule", rule.as_rule()),
@@ -1611,6 +1768,10 @@ impl Compiler {
         function: Pair<Rule>,
         file: Rc<str>,
     ) -> Result<Function, PestError> {
+        let mut instructions = Instructions::default();
+        if let Some(counter) = self.profile_counter(&function) {
+            instructions.push(counter, function.line_col());
+        }
         let mut inner = function.into_inner();
         let name = inner.next().unwrap();
         let mut param_map = HashMap::new();
@@ -1635,7 +1796,6 @@ impl Compiler {
         } else {
             maybe_param_list
         };
-        let mut instructions = Instructions::default();
         self.in_function = true;
         self.compile_action(body, &mut instructions, &param_map)?;
         self.in_function = false;
@@ -1653,6 +1813,7 @@ impl Compiler {
             instructions: instructions.opcodes,
             debug_info: DebugInfo {
                 file,
+                rule: "".into(),
                 source_locations: instructions.source_locations,
             },
         })
@@ -1766,15 +1927,45 @@ impl SourceFile {
     }
 }
 
-pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors> {
+/// Special variables that are gawk extensions, not available with `--posix`
+const EXTENSION_VARIABLES: [(&str, SpecialVar); 2] = [
+    ("PROCINFO", SpecialVar::Procinfo),
+    ("FPAT", SpecialVar::Fpat),
+];
+
+/// Returns true if `name` is a builtin function that is a gawk extension
+pub fn is_extension_function(name: &str) -> bool {
+    EXTENSION_FUNCTIONS.contains_key(name)
+}
+
+/// Returns true if `name` is a special variable that is a gawk extension
+pub fn is_extension_variable(name: &str) -> bool {
+    EXTENSION_VARIABLES.iter().any(|(var, _)| *var == name)
+}
+
+/// Returns true if `name` is a special variable defined by POSIX
+pub fn is_special_variable(name: &str) -> bool {
+    matches!(
+        Compiler::default().names.borrow().get(name),
+        Some(GlobalName::SpecialVar(_))
+    )
+}
+
+/// Compiles the given sources. If `profile` is true, the program counts how many
+/// times each statement, pattern, action and function is executed
+pub fn compile_program(
+    sources: &[SourceFile],
+    posix: bool,
+    profile: bool,
+) -> Result<Program, CompilerErrors> {
     let mut parsed_sources = Vec::new();
     let mut errors = Vec::new();
-    for source_file in sources {
+    for (source_index, source_file) in sources.iter().enumerate() {
         let filename: Rc<str> = source_file.filename.clone().into();
         match AwkParser::parse(Rule::program, &source_file.contents) {
             Ok(mut program) => {
                 let program = program.next().unwrap();
-                parsed_sources.push((filename, program.into_inner()));
+                parsed_sources.push((source_index, filename, program.into_inner()));
             }
             Err(err) => {
                 gather_errors(err, &source_file.contents, &mut errors, &filename);
@@ -1782,8 +1973,19 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
         };
     }
 
-    let mut compiler = Compiler::default();
-    for (filename, program_iter) in &parsed_sources {
+    let mut compiler = Compiler {
+        posix,
+        profile,
+        ..Default::default()
+    };
+    if !posix {
+        compiler.names.borrow_mut().extend(
+            EXTENSION_VARIABLES
+                .iter()
+                .map(|(name, var)| (name.to_string(), GlobalName::SpecialVar(*var as u32))),
+        );
+    }
+    for (_, filename, program_iter) in &parsed_sources {
         compiler.declare_program_functions(program_iter.clone(), filename, &mut errors);
     }
 
@@ -1791,7 +1993,8 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
     let mut rules = Vec::new();
     let mut end_actions = Vec::new();
     let mut functions = Vec::new();
-    for (filename, program_iter) in parsed_sources {
+    for (source_index, filename, program_iter) in parsed_sources {
+        compiler.current_source = source_index;
         for item in program_iter {
             match item.as_rule() {
                 Rule::begin_action | Rule::end_action => {
@@ -1806,9 +2009,10 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
                         errors.push(improve_error(err, &filename));
                     }
                     if is_begin_action {
-                        begin_actions.push(instructions.into_action(filename.clone()));
+                        begin_actions
+                            .push(instructions.into_action(filename.clone(), "BEGIN".into()));
                     } else {
-                        end_actions.push(instructions.into_action(filename.clone()));
+                        end_actions.push(instructions.into_action(filename.clone(), "END".into()));
                     }
                 }
                 Rule::rule => match compiler.compile_rule(item, filename.clone()) {
@@ -1840,6 +2044,7 @@ pub fn compile_program(sources: &[SourceFile]) -> Result<Program, CompilerErrors
     if errors.is_empty() {
         Ok(Program {
             constants: compiler.constants.into_inner(),
+            profile_points: compiler.profile_points,
             begin_actions,
             rules,
             end_actions,
@@ -1866,8 +2071,12 @@ mod test {
     const FIRST_GLOBAL_VAR: u32 = SpecialVar::Count as u32;
 
     fn compile_expr(expr: &str) -> (Vec<OpCode>, Vec<Constant>) {
-        let mut program = compile_program(&[SourceFile::stdin(format!("BEGIN {{ {} }}", expr))])
-            .expect("error compiling expression");
+        let mut program = compile_program(
+            &[SourceFile::stdin(format!("BEGIN {{ {} }}", expr))],
+            false,
+            false,
+        )
+        .expect("error compiling expression");
         // remove OpCode::Pop
         program.begin_actions[0].instructions.pop();
         (
@@ -1877,8 +2086,12 @@ mod test {
     }
 
     fn compile_stmt(stmt: &str) -> (Vec<OpCode>, Vec<Constant>) {
-        let program = compile_program(&[SourceFile::stdin(format!("BEGIN {{ {} }}", stmt))])
-            .expect("error compiling statement");
+        let program = compile_program(
+            &[SourceFile::stdin(format!("BEGIN {{ {} }}", stmt))],
+            false,
+            false,
+        )
+        .expect("error compiling statement");
         (
             program.begin_actions[0].instructions.clone(),
             program.constants,
@@ -1886,11 +2099,12 @@ mod test {
     }
 
     fn compile_correct_program(text: &str) -> Program {
-        compile_program(&[SourceFile::stdin(text.to_string())]).expect("error compiling program")
+        compile_program(&[SourceFile::stdin(text.to_string())], false, false)
+            .expect("error compiling program")
     }
 
     fn does_not_compile(text: &str) {
-        compile_program(&[SourceFile::stdin(text.to_string())])
+        compile_program(&[SourceFile::stdin(text.to_string())], false, false)
             .expect_err("expected error compiling program");
     }
 
@@ -3098,6 +3312,49 @@ mod test {
         assert_eq!(instructions, vec![OpCode::PushConstant(0), OpCode::Exit]);
     }
 
+    #[test]
+    fn test_compile_with_profiling() {
+        let program = compile_program(
+            &[SourceFile::stdin("$1 { next }\nBEGIN { exit }".to_string())],
+            false,
+            true,
+        )
+        .expect("error compiling program");
+        assert_eq!(
+            program.profile_points,
+            vec![
+                ProfilePoint {
+                    source: 0,
+                    offset: 0
+                },
+                ProfilePoint {
+                    source: 0,
+                    offset: 3
+                },
+                ProfilePoint {
+                    source: 0,
+                    offset: 5
+                },
+                ProfilePoint {
+                    source: 0,
+                    offset: 20
+                },
+            ]
+        );
+        assert_eq!(
+            program.rules[0].action.instructions,
+            vec![
+                OpCode::CountExecution(1),
+                OpCode::CountExecution(2),
+                OpCode::Next
+            ]
+        );
+        assert_eq!(
+            program.begin_actions[0].instructions,
+            vec![OpCode::CountExecution(3), OpCode::PushZero, OpCode::Exit]
+        );
+    }
+
     #[test]
     fn test_compile_do_while() {
         let (instructions, constant) = compile_stmt("do 1; while (1);");
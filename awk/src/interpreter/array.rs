@@ -8,6 +8,7 @@
 //
 
 use std::{
+    cmp::Ordering,
     collections::{hash_map::Entry, HashMap},
     rc::Rc,
 };
@@ -131,6 +132,34 @@ impl Array {
         }
     }
 
+    /// Get the value at the given key, without inserting it.
+    pub fn get(&self, key: &str) -> Option<&AwkValue> {
+        self.key_map
+            .get(key)
+            .and_then(|index| self.pairs[*index].as_ref())
+            .map(|(_, value)| value)
+    }
+
+    /// Iterate over the elements of the array, in iteration order.
+    pub fn iter(&self) -> impl Iterator<Item = &KeyValuePair> {
+        self.pairs.iter().flatten()
+    }
+
+    /// Reorder the elements of the array, changing the iteration order.
+    /// Arrays with an active iterator are left untouched.
+    pub fn sort_by<F: FnMut(&KeyValuePair, &KeyValuePair) -> Ordering>(&mut self, mut compare: F) {
+        if self.iterator_count != 0 {
+            return;
+        }
+        self.pairs.retain(Option::is_some);
+        self.empty_slots = 0;
+        self.pairs
+            .sort_by(|a, b| compare(a.as_ref().unwrap(), b.as_ref().unwrap()));
+        for (index, (key, _)) in self.pairs.iter().flatten().enumerate() {
+            *self.key_map.get_mut(key).unwrap() = index;
+        }
+    }
+
     pub fn contains(&self, key: &str) -> bool {
         self.key_map.contains_key(key)
     }
@@ -184,6 +213,22 @@ fn insert_pair(
 mod tests {
     use super::*;
 
+    #[test]
+    fn sort_array() {
+        let mut array = Array::default();
+        array.set("b".to_string(), 1.0).unwrap();
+        array.set("c".to_string(), 2.0).unwrap();
+        array.set("a".to_string(), 3.0).unwrap();
+        array.delete("c");
+        array.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let keys = array.iter().map(|(k, _)| k.to_string()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert!(array.get("a").is_some());
+        assert!(array.get("c").is_none());
+        array.set("a".to_string(), 4.0).unwrap();
+        assert_eq!(array.len(), 2);
+    }
+
     #[test]
     fn iterate_through_empty_array() {
         let mut array = Array::default();
@@ -0,0 +1,186 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Arbitrary precision arithmetic, used for numbers when running with `-M`
+
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, One, RoundingMode, ToPrimitive, Zero};
+use std::str::FromStr;
+
+/// Exponents larger than this are computed using floating point arithmetic
+const MAX_EXACT_EXPONENT: u32 = 1 << 16;
+
+/// Converts a double to the decimal number with the shortest representation
+/// that rounds to it, so that `0.1` stays `0.1`.
+/// Infinities and NaN are converted to zero.
+pub fn from_f64(value: f64) -> BigDecimal {
+    if value.is_finite() {
+        BigDecimal::from_str(&value.to_string()).expect("invalid float representation")
+    } else {
+        BigDecimal::zero()
+    }
+}
+
+/// Parses the longest prefix of `s` that is a decimal number, like `strtod`.
+/// Returns zero if `s` does not start with a number.
+pub fn parse(s: &str) -> BigDecimal {
+    let s = s.trim_start();
+    let bytes = s.as_bytes();
+    let skip_digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = if matches!(bytes.first(), Some(b'+' | b'-')) {
+        1
+    } else {
+        0
+    };
+    let integer_start = end;
+    end = skip_digits(end);
+    let mut digit_count = end - integer_start;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_start = end + 1;
+        end = skip_digits(fraction_start);
+        digit_count += end - fraction_start;
+    }
+    if digit_count == 0 {
+        // hexadecimal numbers, infinities and NaN
+        return from_f64(super::strtod(s));
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_start = end + 1;
+        if matches!(bytes.get(exponent_start), Some(b'+' | b'-')) {
+            exponent_start += 1;
+        }
+        let exponent_end = skip_digits(exponent_start);
+        if exponent_end > exponent_start {
+            end = exponent_end;
+        }
+    }
+    let number = s[..end].trim_end_matches('.');
+    BigDecimal::from_str(number).unwrap_or_else(|_| from_f64(super::strtod(s)))
+}
+
+/// Truncates the value towards zero
+pub fn to_integer(value: &BigDecimal) -> BigInt {
+    value
+        .with_scale_round(0, RoundingMode::Down)
+        .into_bigint_and_exponent()
+        .0
+}
+
+pub fn div(lhs: BigDecimal, rhs: BigDecimal) -> Result<BigDecimal, String> {
+    if rhs.is_zero() {
+        Err("division by zero".to_string())
+    } else {
+        Ok(lhs / rhs)
+    }
+}
+
+pub fn rem(lhs: BigDecimal, rhs: BigDecimal) -> Result<BigDecimal, String> {
+    if rhs.is_zero() {
+        Err("division by zero in %".to_string())
+    } else {
+        Ok(lhs % rhs)
+    }
+}
+
+pub fn pow(base: BigDecimal, exponent: BigDecimal) -> Result<BigDecimal, String> {
+    let integer_exponent = exponent
+        .is_integer()
+        .then(|| to_integer(&exponent).to_i64())
+        .flatten()
+        .filter(|e| e.unsigned_abs() <= MAX_EXACT_EXPONENT as u64);
+    if let Some(e) = integer_exponent {
+        let mut result = BigDecimal::one();
+        let mut square = base;
+        let mut remaining = e.unsigned_abs();
+        while remaining != 0 {
+            if remaining & 1 == 1 {
+                result *= &square;
+            }
+            remaining >>= 1;
+            if remaining != 0 {
+                square = square.square();
+            }
+        }
+        if e < 0 {
+            div(BigDecimal::one(), result)
+        } else {
+            Ok(result)
+        }
+    } else {
+        let base = base.to_f64().unwrap_or(f64::NAN);
+        let exponent = exponent.to_f64().unwrap_or(f64::NAN);
+        let result = base.powf(exponent);
+        if result.is_finite() {
+            Ok(from_f64(result))
+        } else {
+            Err(format!("{} ^ {} is not a finite number", base, exponent))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("18446744073709551617"), big("18446744073709551617"));
+        assert_eq!(parse("  -12.5abc"), big("-12.5"));
+        assert_eq!(parse("1e3"), big("1000"));
+        assert_eq!(parse("2e"), big("2"));
+        assert_eq!(parse("7."), big("7"));
+        assert_eq!(parse(".5"), big("0.5"));
+        assert_eq!(parse("abc"), BigDecimal::zero());
+        assert_eq!(parse(""), BigDecimal::zero());
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(from_f64(0.1), big("0.1"));
+        assert_eq!(from_f64(-3.0), big("-3"));
+        assert_eq!(from_f64(f64::INFINITY), BigDecimal::zero());
+    }
+
+    #[test]
+    fn test_to_integer() {
+        assert_eq!(to_integer(&big("-3.9")).to_string(), "-3");
+        assert_eq!(
+            to_integer(&big("12345678901234567890.5")).to_string(),
+            "12345678901234567890"
+        );
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(
+            pow(big("2"), big("100")).unwrap(),
+            big("1267650600228229401496703205376")
+        );
+        assert_eq!(pow(big("2"), big("-2")).unwrap(), big("0.25"));
+        assert_eq!(pow(big("4"), big("0.5")).unwrap(), big("2"));
+        assert!(pow(big("0"), big("-1")).is_err());
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert!(div(big("1"), BigDecimal::zero()).is_err());
+        assert!(rem(big("1"), BigDecimal::zero()).is_err());
+        assert_eq!(rem(big("-7"), big("3")).unwrap(), big("-1"));
+    }
+}
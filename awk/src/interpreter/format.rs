@@ -323,38 +323,46 @@ pub enum IntegerFormat {
     HexUpper,
 }
 
+impl IntegerFormat {
+    pub fn base(self) -> u32 {
+        match self {
+            IntegerFormat::Decimal => 10,
+            IntegerFormat::Octal => 8,
+            IntegerFormat::HexLower | IntegerFormat::HexUpper => 16,
+        }
+    }
+}
+
 pub fn fmt_write_unsigned(
     target: &mut String,
     value: u64,
     integer_format: IntegerFormat,
     args: &FormatArgs,
 ) {
-    let base;
-    let digits: &[char];
-    match integer_format {
-        IntegerFormat::Decimal => {
-            base = 10;
-            digits = &BASE_10_DIGITS;
-        }
-        IntegerFormat::Octal => {
-            base = 8;
-            digits = &BASE_8_DIGITS;
-        }
-        IntegerFormat::HexLower => {
-            base = 16;
-            digits = &BASE_16_DIGITS_LOWER;
-        }
-        IntegerFormat::HexUpper => {
-            base = 16;
-            digits = &BASE_16_DIGITS_UPPER;
-        }
-    }
+    let digits: &[char] = match integer_format {
+        IntegerFormat::Decimal => &BASE_10_DIGITS,
+        IntegerFormat::Octal => &BASE_8_DIGITS,
+        IntegerFormat::HexLower => &BASE_16_DIGITS_LOWER,
+        IntegerFormat::HexUpper => &BASE_16_DIGITS_UPPER,
+    };
 
     // 22 is the maximum number of digits needed to represent a u64 in base 8 (the lowest base)
     // 22 = ceil(log8(u64::MAX))
     let mut buffer = [0u8; 22];
-    let buffer_length = number_to_digits(&mut buffer, value, base, digits);
+    let buffer_length = number_to_digits(&mut buffer, value, integer_format.base() as u64, digits);
     let buffer_start = buffer.len() - buffer_length;
+    fmt_write_unsigned_digits(target, &buffer[buffer_start..], integer_format, args);
+}
+
+/// Like `fmt_write_unsigned`, for a number already converted to digits.
+/// Zero is represented by an empty slice.
+pub fn fmt_write_unsigned_digits(
+    target: &mut String,
+    digits: &[u8],
+    integer_format: IntegerFormat,
+    args: &FormatArgs,
+) {
+    let buffer_length = digits.len();
 
     let mut precision = args.precision.unwrap_or(1);
 
@@ -382,7 +390,7 @@ pub fn fmt_write_unsigned(
         if precision > buffer_length {
             pad_target(target, precision - buffer_length, b'0');
         }
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
         pad_target(target, args.width.saturating_sub(number_length), b' ');
     } else if args.precision.is_none() && args.zero_padded {
         // > For d, i, o, u, x, and X conversion specifiers, if a precision
@@ -392,14 +400,14 @@ pub fn fmt_write_unsigned(
         if precision > buffer_length {
             pad_target(target, precision - buffer_length, b'0');
         }
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
     } else {
         pad_target(target, args.width.saturating_sub(number_length), b' ');
         target.push_str(hex_prefix);
         if precision > buffer_length {
             pad_target(target, precision - buffer_length, b'0');
         }
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
     }
 }
 
@@ -409,10 +417,22 @@ pub fn fmt_write_signed(target: &mut String, value: i64, args: &FormatArgs) {
     let mut buffer = [0u8; 20];
     let buffer_length = number_to_digits(&mut buffer, unsigned_value, 10, &BASE_10_DIGITS);
     let buffer_start = buffer.len() - buffer_length;
+    fmt_write_signed_digits(target, value.is_negative(), &buffer[buffer_start..], args);
+}
+
+/// Like `fmt_write_signed`, for the decimal digits of the absolute value of
+/// a number. Zero is represented by an empty slice.
+pub fn fmt_write_signed_digits(
+    target: &mut String,
+    is_negative: bool,
+    digits: &[u8],
+    args: &FormatArgs,
+) {
+    let buffer_length = digits.len();
 
     let precision = args.precision.unwrap_or(1);
 
-    let sign = sign_str(value.is_negative(), args);
+    let sign = sign_str(is_negative, args);
     let number_length = buffer_length.max(precision) + sign.len();
 
     // left justified:
@@ -424,18 +444,18 @@ pub fn fmt_write_signed(target: &mut String, value: i64, args: &FormatArgs) {
     if args.left_justified {
         target.push_str(sign);
         pad_target(target, precision.saturating_sub(buffer_length), b'0');
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
         pad_target(target, args.width.saturating_sub(number_length), b' ');
     } else if args.zero_padded {
         target.push_str(sign);
         pad_target(target, args.width.saturating_sub(number_length), b'0');
         pad_target(target, precision.saturating_sub(buffer_length), b'0');
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
     } else {
         pad_target(target, args.width.saturating_sub(number_length), b' ');
         target.push_str(sign);
         pad_target(target, precision.saturating_sub(buffer_length), b'0');
-        copy_buffer_to_target(&buffer[buffer_start..], target);
+        copy_buffer_to_target(digits, target);
     }
 }
 
@@ -21,6 +21,8 @@ use super::string::AwkString;
 pub enum RecordSeparator {
     Char(u8),
     Null,
+    /// Records are separated by newlines that are not inside a quoted field
+    Csv,
 }
 
 impl TryFrom<AwkString> for RecordSeparator {
@@ -92,6 +94,25 @@ pub trait RecordReader: Iterator<Item = ReadResult> {
                 }
                 Ok(Some(str))
             }
+            RecordSeparator::Csv => {
+                let mut str = String::new();
+                let mut in_quotes = false;
+                let mut next = read_iter_next!(self);
+                while next != b'\n' || in_quotes {
+                    if next == b'"' {
+                        in_quotes = !in_quotes;
+                    }
+                    str.push(next as char);
+                    next = match self.next() {
+                        Some(byte_result) => byte_result?,
+                        None => break,
+                    };
+                }
+                if str.ends_with('\r') {
+                    str.pop();
+                }
+                Ok(Some(str))
+            }
         }
     }
 }
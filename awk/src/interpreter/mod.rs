@@ -7,7 +7,9 @@
 // SPDX-License-Identifier: MIT
 //
 
-use array::{Array, KeyIterator, ValueIndex};
+use array::{Array, KeyIterator, KeyValuePair, ValueIndex};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
 use io::{
     EmptyRecordReader, FileStream, ReadFiles, ReadPipes, RecordReader, RecordSeparator,
     StdinRecordReader, WriteFiles, WritePipes,
@@ -24,10 +26,11 @@ use crate::program::{
 use crate::regex::Regex;
 use format::{
     fmt_write_decimal_float, fmt_write_float_general, fmt_write_hex_float,
-    fmt_write_scientific_float, fmt_write_signed, fmt_write_string, fmt_write_unsigned,
-    parse_conversion_specifier_args, IntegerFormat,
+    fmt_write_scientific_float, fmt_write_signed, fmt_write_signed_digits, fmt_write_string,
+    fmt_write_unsigned, fmt_write_unsigned_digits, parse_conversion_specifier_args, IntegerFormat,
 };
 use std::cell::{RefCell, UnsafeCell};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::Write;
@@ -37,12 +40,15 @@ use std::rc::Rc;
 use std::time::SystemTime;
 
 mod array;
+mod bignum;
 mod format;
 mod io;
 mod string;
 
 const STACK_SIZE: usize = 2048;
 
+const DEFAULT_STRFTIME_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
 fn bool_to_f64(p: bool) -> f64 {
     if p {
         1.0
@@ -109,10 +115,12 @@ fn maybe_numeric_string<S: Into<AwkString>>(str: S) -> AwkString {
     str
 }
 
+/// With `bignum` set, integer conversions use the exact value of their argument
 fn sprintf(
     format_string: &str,
     values: &mut [AwkValue],
     float_format: &str,
+    bignum: bool,
 ) -> Result<AwkString, String> {
     let mut result = String::with_capacity(format_string.len());
     let mut iter = format_string.chars();
@@ -134,15 +142,16 @@ fn sprintf(
                 current_arg -= 1;
                 let value = swap_with_default(&mut values[current_arg]);
                 match specifier {
+                    'd' | 'i' if bignum => {
+                        let value = bignum::to_integer(&value.scalar_as_bignum());
+                        let digits = integer_digits(&value, 10);
+                        fmt_write_signed_digits(&mut result, value.is_negative(), &digits, &args);
+                    }
                     'd' | 'i' => {
                         let value = value.scalar_as_f64() as i64;
                         fmt_write_signed(&mut result, value, &args);
                     }
                     'u' | 'o' | 'x' | 'X' => {
-                        let value = value.scalar_as_f64() as i64;
-                        if value.is_negative() {
-                            return Err("negative value for unsigned format specifier".to_string());
-                        }
                         let format = match specifier {
                             'u' => IntegerFormat::Decimal,
                             'o' => IntegerFormat::Octal,
@@ -150,7 +159,27 @@ fn sprintf(
                             'X' => IntegerFormat::HexUpper,
                             _ => unreachable!(),
                         };
-                        fmt_write_unsigned(&mut result, value as u64, format, &args);
+                        if bignum {
+                            let value = bignum::to_integer(&value.scalar_as_bignum());
+                            if value.is_negative() {
+                                return Err(
+                                    "negative value for unsigned format specifier".to_string()
+                                );
+                            }
+                            let mut digits = integer_digits(&value, format.base());
+                            if specifier == 'X' {
+                                digits.make_ascii_uppercase();
+                            }
+                            fmt_write_unsigned_digits(&mut result, &digits, format, &args);
+                        } else {
+                            let value = value.scalar_as_f64() as i64;
+                            if value.is_negative() {
+                                return Err(
+                                    "negative value for unsigned format specifier".to_string()
+                                );
+                            }
+                            fmt_write_unsigned(&mut result, value as u64, format, &args);
+                        }
                     }
                     'a' | 'A' => {
                         let value = value.scalar_as_f64();
@@ -189,6 +218,15 @@ fn sprintf(
     Ok(result.into())
 }
 
+/// The digits of the absolute value of `value`. Zero has no digits.
+fn integer_digits(value: &BigInt, radix: u32) -> Vec<u8> {
+    if value.is_zero() {
+        Vec::new()
+    } else {
+        value.magnitude().to_str_radix(radix).into_bytes()
+    }
+}
+
 fn builtin_sprintf(
     stack: &mut Stack,
     argc: u16,
@@ -198,18 +236,55 @@ fn builtin_sprintf(
     let format_string = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    sprintf(&format_string, &mut values, &global_env.convfmt)
+    sprintf(
+        &format_string,
+        &mut values,
+        &global_env.convfmt,
+        global_env.bignum,
+    )
 }
 
-fn builtin_match(stack: &mut Stack, global_env: &mut GlobalEnv) -> Result<(f64, f64), String> {
+fn builtin_match(
+    stack: &mut Stack,
+    argc: u16,
+    subsep: &str,
+    global_env: &mut GlobalEnv,
+) -> Result<(f64, f64), String> {
+    let array = if argc == 3 {
+        Some(stack.pop_array_ptr()?)
+    } else {
+        None
+    };
     let ere = stack.pop_value().into_ere()?;
     let string = stack
         .pop_scalar_value()?
         .scalar_to_string(&global_env.convfmt)?;
-    let mut locations = ere.match_locations(string.try_into()?);
+    let first_match;
+    if let Some(array) = array {
+        // the array is a variable, so it outlives this function
+        let array = unsafe { &mut *array }.as_array()?;
+        array.clear();
+        let captures = ere.captures(string.clone().try_into()?).next();
+        for (i, capture) in captures.iter().flatten().enumerate() {
+            if let Some(capture) = capture {
+                array.set(
+                    i.to_string(),
+                    maybe_numeric_string(&string[capture.start..capture.end]),
+                )?;
+                array.set(format!("{i}{subsep}start"), capture.start as f64 + 1.0)?;
+                array.set(
+                    format!("{i}{subsep}length"),
+                    (capture.end - capture.start) as f64,
+                )?;
+            }
+        }
+        first_match = captures.map(|captures| captures[0].expect("match without location"));
+    } else {
+        first_match = ere.match_locations(string.try_into()?).next();
+    }
     let start;
     let len;
-    if let Some(first_match) = locations.next() {
+    if let Some(first_match) = first_match {
         start = first_match.start as i64 + 1;
         len = first_match.end as i64 - start + 1;
     } else {
@@ -296,6 +371,327 @@ fn builtin_gsub(
     result
 }
 
+enum ReplacementPart {
+    Literal(String),
+    Subexpression(usize),
+}
+
+/// Splits a `gensub` replacement string into literal text and references to
+/// subexpressions. `&` and `\\0` refer to the whole match.
+fn parse_gensub_replacement(repl: &str) -> Vec<ReplacementPart> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut push_subexpression = |literal: &mut String, n: usize| {
+        if !literal.is_empty() {
+            parts.push(ReplacementPart::Literal(std::mem::take(literal)));
+        }
+        parts.push(ReplacementPart::Subexpression(n));
+    };
+    let mut repl_iter = repl.chars();
+    while let Some(c) = repl_iter.next() {
+        match c {
+            '\\' => match repl_iter.next() {
+                Some(digit @ '0'..='9') => {
+                    push_subexpression(&mut literal, digit as usize - '0' as usize)
+                }
+                Some('&') => literal.push('&'),
+                Some('\\') => literal.push('\\'),
+                Some(other) => {
+                    literal.push('\\');
+                    literal.push(other);
+                }
+                None => literal.push('\\'),
+            },
+            '&' => push_subexpression(&mut literal, 0),
+            other => literal.push(other),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(ReplacementPart::Literal(literal));
+    }
+    parts
+}
+
+fn gensub(ere: &Regex, repl: &str, how: &str, in_str: &str) -> Result<AwkString, String> {
+    let global = how.starts_with(['g', 'G']);
+    // gawk treats values less than one as one
+    let nth_match = (strtod(how) as usize).max(1);
+    let repl_parts = parse_gensub_replacement(repl);
+
+    let mut result = String::with_capacity(in_str.len());
+    let mut last_match_end = 0;
+    for (i, captures) in ere
+        .captures(AwkString::from(in_str).try_into()?)
+        .enumerate()
+    {
+        if !global && i + 1 != nth_match {
+            continue;
+        }
+        let whole_match = captures[0].expect("match without location");
+        result.push_str(&in_str[last_match_end..whole_match.start]);
+        for part in &repl_parts {
+            match part {
+                ReplacementPart::Literal(s) => result.push_str(s),
+                ReplacementPart::Subexpression(n) => {
+                    if let Some(Some(capture)) = captures.get(*n) {
+                        result.push_str(&in_str[capture.start..capture.end]);
+                    }
+                }
+            }
+        }
+        last_match_end = whole_match.end;
+        if !global {
+            break;
+        }
+    }
+    result.push_str(&in_str[last_match_end..]);
+    Ok(result.into())
+}
+
+fn builtin_gensub(stack: &mut Stack, global_env: &mut GlobalEnv) -> Result<(), String> {
+    let target = stack
+        .pop_scalar_value()?
+        .scalar_to_string(&global_env.convfmt)?;
+    let how = stack
+        .pop_scalar_value()?
+        .scalar_to_string(&global_env.convfmt)?;
+    let repl = stack
+        .pop_scalar_value()?
+        .scalar_to_string(&global_env.convfmt)?;
+    let ere = stack.pop_value().into_ere()?;
+    let result = gensub(&ere, &repl, &how, &target)?;
+    stack.push_value(result)
+}
+
+/// Orderings used by `asort`, `asorti` and `PROCINFO["sorted_in"]`
+#[derive(Clone, Copy)]
+enum SortOrder {
+    IndexString,
+    IndexNumber,
+    ValueType,
+    ValueString,
+    ValueNumber,
+}
+
+/// Parses the name of a predefined gawk ordering (e.g. `@ind_str_asc`),
+/// returning the ordering and whether it is descending.
+fn parse_sort_order(name: &str) -> Option<(SortOrder, bool)> {
+    let (order, direction) = name.strip_prefix('@')?.rsplit_once('_')?;
+    let order = match order {
+        "ind_str" => SortOrder::IndexString,
+        "ind_num" => SortOrder::IndexNumber,
+        "val_type" => SortOrder::ValueType,
+        "val_str" => SortOrder::ValueString,
+        "val_num" => SortOrder::ValueNumber,
+        _ => return None,
+    };
+    match direction {
+        "asc" => Some((order, false)),
+        "desc" => Some((order, true)),
+        _ => None,
+    }
+}
+
+fn compare_elements(
+    order: SortOrder,
+    descending: bool,
+    (lhs_key, lhs): &KeyValuePair,
+    (rhs_key, rhs): &KeyValuePair,
+    convfmt: &str,
+) -> Ordering {
+    let as_string = |value: &AwkValue| value.clone().scalar_to_string(convfmt).unwrap_or_default();
+    let compare_numbers = |a: f64, b: f64| a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+    let compare_strings = || as_string(lhs).as_str().cmp(as_string(rhs).as_str());
+    let is_number = |value: &AwkValue| match &value.value {
+        AwkValueVariant::String(s) => s.is_numeric,
+        _ => true,
+    };
+    let ordering = match order {
+        SortOrder::IndexString => lhs_key.cmp(rhs_key),
+        SortOrder::IndexNumber => compare_numbers(strtod(lhs_key), strtod(rhs_key)),
+        SortOrder::ValueString => compare_strings(),
+        SortOrder::ValueNumber => {
+            compare_numbers(lhs.scalar_as_f64(), rhs.scalar_as_f64()).then_with(compare_strings)
+        }
+        SortOrder::ValueType => match (is_number(lhs), is_number(rhs)) {
+            (true, true) => compare_numbers(lhs.scalar_as_f64(), rhs.scalar_as_f64()),
+            (false, false) => compare_strings(),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+        },
+    }
+    // like gawk, break ties using the index
+    .then_with(|| lhs_key.cmp(rhs_key));
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+fn builtin_asort(
+    stack: &mut Stack,
+    argc: u16,
+    global_env: &mut GlobalEnv,
+    sort_indices: bool,
+) -> Result<(), String> {
+    let how = if argc == 3 {
+        Some(
+            stack
+                .pop_scalar_value()?
+                .scalar_to_string(&global_env.convfmt)?,
+        )
+    } else {
+        None
+    };
+    let dest = if argc >= 2 {
+        Some(stack.pop_array_ptr()?)
+    } else {
+        None
+    };
+    let (order, descending) = match how {
+        Some(how) => parse_sort_order(&how)
+            .ok_or_else(|| format!("invalid sorting order '{}'", how.as_str()))?,
+        None if sort_indices => (SortOrder::IndexString, false),
+        None => (SortOrder::ValueType, false),
+    };
+    let source = stack.pop_array()?;
+    let mut elements = source.iter().cloned().collect::<Vec<KeyValuePair>>();
+    elements.sort_by(|a, b| compare_elements(order, descending, a, b, &global_env.convfmt));
+
+    let target = match dest {
+        // the destination is a variable, so it outlives this function
+        Some(dest) => unsafe { &mut *dest }.as_array()?,
+        None => source,
+    };
+    target.clear();
+    let count = elements.len();
+    for (i, (key, value)) in elements.into_iter().enumerate() {
+        if sort_indices {
+            target.set((i + 1).to_string(), key.to_string())?;
+        } else {
+            target.set((i + 1).to_string(), value)?;
+        }
+    }
+    stack.push_value(count as f64)
+}
+
+/// `tolower` and `toupper`. As an extension, an array argument has its string
+/// elements converted in place, and the number of elements is returned.
+fn builtin_change_case(
+    stack: &mut Stack,
+    global_env: &GlobalEnv,
+    to_upper: bool,
+) -> Result<(), String> {
+    let change_case = |s: &str| {
+        if to_upper {
+            s.to_uppercase()
+        } else {
+            s.to_lowercase()
+        }
+    };
+    let mut value = stack.pop().expect("empty stack");
+    if let StackValue::ValueRef(value_ref) = &value {
+        // safe by type invariance
+        if let AwkValueVariant::Array(array) = &mut unsafe { &mut **value_ref }.value {
+            let strings = array
+                .iter()
+                .filter_map(|(key, element)| match &element.value {
+                    AwkValueVariant::String(s) => Some((key.to_string(), change_case(s))),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for (key, s) in strings {
+                array.set(key, s)?;
+            }
+            return stack.push_value(array.len() as f64);
+        }
+    }
+    // safe by type invariance
+    let value = unsafe {
+        value.ensure_value_is_scalar()?;
+        value.into_owned()
+    };
+    let s = value.scalar_to_string(&global_env.convfmt)?;
+    stack.push_value(change_case(&s))
+}
+
+fn current_time() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs() as i64
+}
+
+fn strftime(format: AwkString, timestamp: i64, utc: bool) -> Result<AwkString, String> {
+    if format.is_empty() {
+        return Ok(AwkString::default());
+    }
+    let format: CString = format.try_into()?;
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        if utc {
+            libc::gmtime_r(&time, &mut tm)
+        } else {
+            libc::localtime_r(&time, &mut tm)
+        }
+    };
+    if result.is_null() {
+        return Err(format!("invalid timestamp {}", timestamp));
+    }
+    let mut buffer = vec![0u8; 256];
+    loop {
+        let len = unsafe {
+            libc::strftime(
+                buffer.as_mut_ptr() as *mut libc::c_char,
+                buffer.len(),
+                format.as_ptr(),
+                &tm,
+            )
+        };
+        if len > 0 {
+            buffer.truncate(len);
+            return Ok(String::from_utf8_lossy(&buffer).into_owned().into());
+        }
+        // strftime returns 0 both when the buffer is too small and
+        // when the result is empty
+        if buffer.len() >= 1 << 16 {
+            return Ok(AwkString::default());
+        }
+        buffer.resize(buffer.len() * 2, 0);
+    }
+}
+
+/// Converts a date specification of the form `YYYY MM DD HH MM SS [DST]`
+/// into a timestamp. Returns -1 if the specification is invalid.
+fn mktime(datespec: &str, utc: bool) -> i64 {
+    let fields = match datespec
+        .split_ascii_whitespace()
+        .map(|field| field.parse::<libc::c_int>())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(fields) if (6..=7).contains(&fields.len()) => fields,
+        _ => return -1,
+    };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = fields[0] - 1900;
+    tm.tm_mon = fields[1] - 1;
+    tm.tm_mday = fields[2];
+    tm.tm_hour = fields[3];
+    tm.tm_min = fields[4];
+    tm.tm_sec = fields[5];
+    tm.tm_isdst = fields.get(6).copied().unwrap_or(-1);
+    let time = unsafe {
+        if utc {
+            libc::timegm(&mut tm)
+        } else {
+            libc::mktime(&mut tm)
+        }
+    };
+    time as i64
+}
+
 fn call_simple_builtin(
     function: BuiltinFunction,
     argc: u16,
@@ -328,6 +724,10 @@ fn call_simple_builtin(
             let value = stack.pop_scalar_value()?.scalar_as_f64();
             stack.push_value(value.sqrt())?;
         }
+        BuiltinFunction::Int if global_env.bignum => {
+            let value = stack.pop_scalar_value()?.scalar_as_bignum();
+            stack.push_value(BigDecimal::from(bignum::to_integer(&value)))?;
+        }
         BuiltinFunction::Int => {
             let value = stack.pop_scalar_value()?.scalar_as_f64();
             stack.push_value(value.trunc())?;
@@ -367,7 +767,7 @@ fn call_simple_builtin(
             let s = stack
                 .pop_scalar_value()?
                 .scalar_to_string(&global_env.convfmt)?;
-            let array = stack.pop_ref().as_array()?;
+            let array = stack.pop_array()?;
             array.clear();
 
             split_record(
@@ -397,21 +797,30 @@ fn call_simple_builtin(
             let substr = s.chars().skip(m - 1).take(n).collect::<String>();
             stack.push_value(substr)?;
         }
-        BuiltinFunction::ToLower => {
-            let value = stack
-                .pop_scalar_value()?
-                .scalar_to_string(&global_env.convfmt)?;
-            stack.push_value(value.to_lowercase())?;
-        }
-        BuiltinFunction::ToUpper => {
-            let value = stack
-                .pop_scalar_value()?
-                .scalar_to_string(&global_env.convfmt)?;
-            stack.push_value(value.to_uppercase())?;
+        BuiltinFunction::ToLower | BuiltinFunction::ToUpper => {
+            builtin_change_case(stack, global_env, function == BuiltinFunction::ToUpper)?
         }
         BuiltinFunction::Gsub | BuiltinFunction::Sub => {
             return builtin_gsub(stack, global_env, function == BuiltinFunction::Sub)
         }
+        BuiltinFunction::Gensub => builtin_gensub(stack, global_env)?,
+        BuiltinFunction::Asort | BuiltinFunction::Asorti => {
+            builtin_asort(stack, argc, global_env, function == BuiltinFunction::Asorti)?
+        }
+        BuiltinFunction::Systime => {
+            stack.push_value(current_time() as f64)?;
+        }
+        BuiltinFunction::Mktime => {
+            let utc = if argc == 2 {
+                stack.pop_scalar_value()?.scalar_as_bool()
+            } else {
+                false
+            };
+            let datespec = stack
+                .pop_scalar_value()?
+                .scalar_to_string(&global_env.convfmt)?;
+            stack.push_value(mktime(&datespec, utc) as f64)?;
+        }
         BuiltinFunction::System => {
             let command: CString = stack
                 .pop_scalar_value()?
@@ -437,6 +846,45 @@ enum FieldSeparator {
     Default,
     Char(u8),
     Ere(Rc<Regex>),
+    /// Fields are the matches of the regex (gawk's `FPAT`)
+    Pattern(Rc<Regex>),
+    /// Fields are separated by commas and can be quoted, as described by RFC 4180
+    Csv,
+}
+
+/// Splits a CSV record into fields, removing the quotes around quoted fields.
+/// An empty record has no fields.
+fn split_csv_record(record: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    if record.is_empty() {
+        return fields;
+    }
+    let mut field = String::new();
+    let mut field_start = true;
+    let mut in_quotes = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field_start => in_quotes = true,
+            ',' if !in_quotes => {
+                fields.push(std::mem::take(&mut field));
+                field_start = true;
+                continue;
+            }
+            other => field.push(other),
+        }
+        field_start = false;
+    }
+    fields.push(field);
+    fields
 }
 
 /// Splits a record into fields and calls the provided closure for each field.
@@ -474,6 +922,14 @@ fn split_record<S: FnMut(usize, AwkString) -> Result<(), String>>(
             }
             store_result(index, string(&record[split_start..]))
         }
+        FieldSeparator::Pattern(re) => re
+            .match_locations(record.clone().try_into()?)
+            .enumerate()
+            .try_for_each(|(i, field)| store_result(i, string(&record[field.start..field.end]))),
+        FieldSeparator::Csv => split_csv_record(&record)
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, s)| store_result(i, string(&s))),
     }
 }
 
@@ -495,6 +951,12 @@ impl TryFrom<AwkString> for FieldSeparator {
 struct GlobalEnv {
     convfmt: AwkString,
     fs: FieldSeparator,
+    /// Set when fields are defined by `FPAT` instead of `FS`
+    fpat: Option<FieldSeparator>,
+    /// In CSV mode `FS` and `RS` are ignored
+    csv: bool,
+    /// Use arbitrary precision numbers
+    bignum: bool,
     ofs: AwkString,
     ors: AwkString,
     ofmt: AwkString,
@@ -505,15 +967,27 @@ struct GlobalEnv {
 }
 
 impl GlobalEnv {
+    /// The separator used to split records into fields
+    fn field_separator(&self) -> &FieldSeparator {
+        self.fpat.as_ref().unwrap_or(&self.fs)
+    }
+
     fn set(&mut self, var: SpecialVar, value: &mut AwkValue) -> Result<(), String> {
         let as_string = |value: &mut AwkValue| value.clone().scalar_to_string(&self.convfmt);
         match var {
             SpecialVar::Convfmt => self.convfmt = as_string(value)?,
-            SpecialVar::Fs => self.fs = as_string(value)?.try_into()?,
+            SpecialVar::Fs if !self.csv => {
+                self.fs = as_string(value)?.try_into()?;
+                self.fpat = None;
+            }
+            SpecialVar::Fpat if !self.csv => {
+                let ere = Regex::new(as_string(value)?.try_into()?)?;
+                self.fpat = Some(FieldSeparator::Pattern(Rc::new(ere)));
+            }
             SpecialVar::Ofmt => self.ofmt = as_string(value)?,
             SpecialVar::Ofs => self.ofs = as_string(value)?,
             SpecialVar::Ors => self.ors = as_string(value)?,
-            SpecialVar::Rs => self.rs = as_string(value)?.try_into()?,
+            SpecialVar::Rs if !self.csv => self.rs = as_string(value)?.try_into()?,
             SpecialVar::Nr => self.nr = value.scalar_as_f64() as u32,
             SpecialVar::Fnr => self.fnr = value.scalar_as_f64() as u32,
             SpecialVar::Nf => self.nf = value.scalar_as_f64() as usize,
@@ -538,6 +1012,9 @@ impl Default for GlobalEnv {
             nr: 1,
             fnr: 1,
             nf: 0,
+            fpat: None,
+            csv: false,
+            bignum: false,
         }
     }
 }
@@ -632,7 +1109,7 @@ impl Record {
         let record_str = (*self.fields[0].get())
             .to_owned()
             .scalar_to_string(&global_env.convfmt)?;
-        split_record(record_str.clone(), &global_env.fs, |i, s| {
+        split_record(record_str.clone(), global_env.field_separator(), |i, s| {
             let field_index = i + 1;
             last_field += 1;
             *self.fields[field_index].get() = AwkValue::field_ref(s, field_index as u16);
@@ -679,6 +1156,8 @@ fn is_valid_record_index(index: usize) -> Result<(), String> {
 #[derive(Clone, PartialEq)]
 enum AwkValueVariant {
     Number(f64),
+    /// Only used in arbitrary precision mode (`-M`)
+    BigNumber(BigDecimal),
     String(AwkString),
     Array(Array),
     Regex {
@@ -710,6 +1189,7 @@ impl AwkValue {
     fn scalar_as_f64(&self) -> f64 {
         match &self.value {
             AwkValueVariant::Number(x) => *x,
+            AwkValueVariant::BigNumber(x) => x.to_f64().unwrap_or(f64::NAN),
             AwkValueVariant::String(s) => strtod(s.as_str()),
             AwkValueVariant::UninitializedScalar => 0.0,
             AwkValueVariant::Regex { matches_record, .. } => bool_to_f64(*matches_record),
@@ -719,9 +1199,25 @@ impl AwkValue {
         }
     }
 
+    fn scalar_as_bignum(&self) -> BigDecimal {
+        match &self.value {
+            AwkValueVariant::Number(x) => bignum::from_f64(*x),
+            AwkValueVariant::BigNumber(x) => x.clone(),
+            AwkValueVariant::String(s) => bignum::parse(s.as_str()),
+            AwkValueVariant::UninitializedScalar => BigDecimal::zero(),
+            AwkValueVariant::Regex { matches_record, .. } => {
+                BigDecimal::from(*matches_record as u8)
+            }
+            AwkValueVariant::Array(_) | AwkValueVariant::Uninitialized => {
+                panic!("not a scalar")
+            }
+        }
+    }
+
     fn scalar_as_bool(&self) -> bool {
         match &self.value {
             AwkValueVariant::Number(x) => *x != 0.0,
+            AwkValueVariant::BigNumber(x) => !x.is_zero(),
             AwkValueVariant::String(s) => !s.is_empty(),
             AwkValueVariant::Regex { matches_record, .. } => *matches_record,
             AwkValueVariant::UninitializedScalar => false,
@@ -737,7 +1233,15 @@ impl AwkValue {
                 if is_integer(num) {
                     Ok((num as i64).to_string().into())
                 } else {
-                    sprintf(num_fmt, &mut [num.into()], num_fmt)
+                    sprintf(num_fmt, &mut [num.into()], num_fmt, false)
+                }
+            }
+            AwkValueVariant::BigNumber(num) => {
+                if num.is_integer() {
+                    Ok(bignum::to_integer(&num).to_string().into())
+                } else {
+                    let num = num.to_f64().unwrap_or(f64::NAN);
+                    sprintf(num_fmt, &mut [num.into()], num_fmt, false)
                 }
             }
             AwkValueVariant::String(s) => Ok(s),
@@ -862,6 +1366,15 @@ impl From<f64> for AwkValue {
     }
 }
 
+impl From<BigDecimal> for AwkValue {
+    fn from(value: BigDecimal) -> Self {
+        Self {
+            value: AwkValueVariant::BigNumber(value),
+            ref_type: AwkRefType::None,
+        }
+    }
+}
+
 impl From<AwkString> for AwkValue {
     fn from(value: AwkString) -> Self {
         Self {
@@ -1106,6 +1619,21 @@ impl<'i, 's> Stack<'i, 's> {
         unsafe { &mut *self.pop().expect("empty stack").unwrap_ptr() }
     }
 
+    /// Pops a reference to a value used as an array. Scalars are pushed on
+    /// the stack by value, so they cannot be used as arrays
+    fn pop_array_ptr(&mut self) -> Result<*mut AwkValue, String> {
+        match self.pop().expect("empty stack") {
+            StackValue::Value(_) => Err("scalar used in array context".to_string()),
+            // safe by type invariance
+            value => Ok(unsafe { value.unwrap_ptr() }),
+        }
+    }
+
+    fn pop_array(&mut self) -> Result<&mut Array, String> {
+        // safe by type invariance
+        unsafe { &mut *self.pop_array_ptr()? }.as_array()
+    }
+
     fn push_value<V: Into<AwkValue>>(&mut self, value: V) -> Result<(), String> {
         // a `StackValue::Value` is always valid, so this is safe
         unsafe { self.push(StackValue::from(value.into())) }
@@ -1152,6 +1680,9 @@ impl<'i, 's> Stack<'i, 's> {
         self.bp = caller_frame.bp;
         self.sp = caller_frame.sp;
         self.instructions = caller_frame.instructions;
+        self.source_locations = caller_frame.source_locations;
+        self.current_function_name = caller_frame.function_name;
+        self.current_function_file = caller_frame.function_file;
         self.ip = caller_frame.ip;
     }
 
@@ -1162,7 +1693,7 @@ impl<'i, 's> Stack<'i, 's> {
         let stack_end = unsafe { bp.add(stack_len) };
         Self {
             current_function_file: main.debug_info.file.clone(),
-            current_function_name: "<start>".into(),
+            current_function_name: main.debug_info.rule.clone(),
             instructions: &main.instructions,
             source_locations: &main.debug_info.source_locations,
             ip: 0,
@@ -1204,21 +1735,39 @@ struct Interpreter {
     read_pipes: ReadPipes,
     rand_seed: u64,
     rng: SmallRng,
+    profile_counts: Vec<u64>,
 }
 
 macro_rules! numeric_op {
-    ($stack:expr, $op:tt) => {
-        let rhs = $stack.pop_scalar_value()?.scalar_as_f64();
-        let lhs = $stack.pop_scalar_value()?.scalar_as_f64();
-        $stack.push_value(lhs $op rhs)?;
+    ($stack:expr, $global_env:expr, $op:tt) => {
+        numeric_op!(
+            $stack,
+            $global_env,
+            |lhs, rhs| lhs $op rhs,
+            |lhs, rhs| Ok::<_, String>(lhs $op rhs)
+        )
+    };
+    ($stack:expr, $global_env:expr, $f64_op:expr, $bignum_op:expr) => {
+        let rhs = $stack.pop_scalar_value()?;
+        let lhs = $stack.pop_scalar_value()?;
+        if $global_env.bignum {
+            let result: BigDecimal = $bignum_op(lhs.scalar_as_bignum(), rhs.scalar_as_bignum())?;
+            $stack.push_value(result)?;
+        } else {
+            let result: f64 = $f64_op(lhs.scalar_as_f64(), rhs.scalar_as_f64());
+            $stack.push_value(result)?;
+        }
     };
 }
 
 macro_rules! compare_op {
-    ($stack:expr, $convfmt:expr, $op:tt) => {
+    ($stack:expr, $global_env:expr, $op:tt) => {
+        let convfmt = &$global_env.convfmt;
         let rhs = $stack.pop_scalar_value()?;
         let lhs = $stack.pop_scalar_value()?;
-        match (&lhs.value, &rhs.value) {
+        if $global_env.bignum && compares_numerically(&lhs, &rhs) {
+            $stack.push_value(bool_to_f64(lhs.scalar_as_bignum() $op rhs.scalar_as_bignum()))?;
+        } else { match (&lhs.value, &rhs.value) {
             (AwkValueVariant::Number(lhs), AwkValueVariant::Number(rhs)) => {
                 $stack.push_value(bool_to_f64(lhs $op rhs))?;
             }
@@ -1242,38 +1791,97 @@ macro_rules! compare_op {
                 $stack.push_value(bool_to_f64(*x $op rhs.scalar_as_f64()))?;
             }
             (_, _) => {
-                $stack.push_value(bool_to_f64(lhs.scalar_to_string($convfmt)?.as_str() $op rhs.scalar_to_string($convfmt)?.as_str()))?;
+                $stack.push_value(bool_to_f64(lhs.scalar_to_string(convfmt)?.as_str() $op rhs.scalar_to_string(convfmt)?.as_str()))?;
             }
-        }
+        } }
     };
 }
 
+/// Returns true if the comparison of the two values is numeric, following the
+/// same rules as `compare_op!`
+fn compares_numerically(lhs: &AwkValue, rhs: &AwkValue) -> bool {
+    let is_number = |value: &AwkValue| {
+        matches!(
+            value.value,
+            AwkValueVariant::Number(_) | AwkValueVariant::BigNumber(_)
+        )
+    };
+    let is_numeric_string =
+        |value: &AwkValue| matches!(&value.value, AwkValueVariant::String(s) if s.is_numeric);
+    let is_uninitialized =
+        |value: &AwkValue| matches!(value.value, AwkValueVariant::UninitializedScalar);
+    (is_number(lhs) && (is_number(rhs) || is_numeric_string(rhs) || is_uninitialized(rhs)))
+        || (is_numeric_string(lhs) && (is_number(rhs) || is_numeric_string(rhs)))
+        || (is_uninitialized(lhs) && is_number(rhs))
+}
+
+/// Adds `delta` to the numeric value of `value`, returning the
+/// numeric value before and after the addition
+fn increment(value: &AwkValue, delta: i8, bignum: bool) -> (AwkValue, AwkValue) {
+    if bignum {
+        let old_value = value.scalar_as_bignum();
+        let new_value = &old_value + BigDecimal::from(delta);
+        (old_value.into(), new_value.into())
+    } else {
+        let old_value = value.scalar_as_f64();
+        (old_value.into(), (old_value + delta as f64).into())
+    }
+}
+
+/// Name used in error messages for programs given on the command line
+fn display_file_name(file: &str) -> &str {
+    if file.is_empty() {
+        "cmd. line"
+    } else {
+        file
+    }
+}
+
+/// Formats a runtime error as `awk: file:line: message`, followed by the
+/// chain of function calls leading to the error and the rule containing it
 fn stack_trace(error: String, stack: Stack) -> String {
-    let mut result = format!("runtime error: {}\ncall trace:\n", error);
     let error_location = stack.source_locations[stack.ip as usize];
-    writeln!(
-        result,
-        "=> {} at {}:{}:{}",
-        stack.current_function_name,
-        stack.current_function_file,
+    let mut result = format!(
+        "awk: {}:{}: {}",
+        display_file_name(&stack.current_function_file),
         error_location.line,
-        error_location.column
-    )
-    .expect("error writing to string");
+        error
+    );
+    let mut function_name = &stack.current_function_name;
     for frame in stack.call_frames.iter().rev() {
-        let source_location = frame.source_locations[frame.ip as usize];
-        writeln!(
+        let call_location = frame.source_locations[frame.ip as usize];
+        write!(
             result,
-            "=> {} at {}:{}:{}",
-            frame.function_name, frame.function_file, source_location.line, source_location.column
+            "\n\tin function {} called from {}:{}",
+            function_name,
+            display_file_name(&frame.function_file),
+            call_location.line
         )
         .expect("error writing to string");
+        function_name = &frame.function_name;
     }
-
+    write!(result, "\n\tin {}", function_name).expect("error writing to string");
     result
 }
 
 impl Interpreter {
+    fn procinfo(&self, key: &str) -> Option<AwkValue> {
+        // globals outlive the stack, so this is safe
+        match unsafe { &(*self.globals[SpecialVar::Procinfo as usize].get()).value } {
+            AwkValueVariant::Array(procinfo) => procinfo.get(key).cloned(),
+            _ => None,
+        }
+    }
+
+    /// The order in which `for (key in array)` iterates, if set by
+    /// `PROCINFO["sorted_in"]`
+    fn sorted_in(&self, convfmt: &str) -> Result<Option<(SortOrder, bool)>, String> {
+        match self.procinfo("sorted_in") {
+            Some(order) => Ok(parse_sort_order(&order.scalar_to_string(convfmt)?)),
+            None => Ok(None),
+        }
+    }
+
     fn run(
         &mut self,
         action: &Action,
@@ -1308,42 +1916,40 @@ impl Interpreter {
             let mut ip_increment: isize = 1;
             match instruction {
                 OpCode::Add => {
-                    numeric_op!(stack, +);
+                    numeric_op!(stack, global_env, +);
                 }
                 OpCode::Sub => {
-                    numeric_op!(stack,  -);
+                    numeric_op!(stack, global_env, -);
                 }
                 OpCode::Mul => {
-                    numeric_op!(stack,  *);
+                    numeric_op!(stack, global_env, *);
                 }
                 OpCode::Div => {
-                    numeric_op!(stack,  /);
+                    numeric_op!(stack, global_env, |lhs, rhs| lhs / rhs, bignum::div);
                 }
                 OpCode::Mod => {
-                    numeric_op!(stack,  %);
+                    numeric_op!(stack, global_env, |lhs, rhs| lhs % rhs, bignum::rem);
                 }
                 OpCode::Pow => {
-                    let rhs = stack.pop_scalar_value()?.scalar_as_f64();
-                    let lhs = stack.pop_scalar_value()?.scalar_as_f64();
-                    stack.push_value(lhs.powf(rhs))?;
+                    numeric_op!(stack, global_env, f64::powf, bignum::pow);
                 }
                 OpCode::Le => {
-                    compare_op!(stack, &global_env.convfmt, <=);
+                    compare_op!(stack, global_env, <=);
                 }
                 OpCode::Lt => {
-                    compare_op!(stack, &global_env.convfmt, <);
+                    compare_op!(stack, global_env, <);
                 }
                 OpCode::Ge => {
-                    compare_op!(stack, &global_env.convfmt, >=);
+                    compare_op!(stack, global_env, >=);
                 }
                 OpCode::Gt => {
-                    compare_op!(stack, &global_env.convfmt, >);
+                    compare_op!(stack, global_env, >);
                 }
                 OpCode::Eq => {
-                    compare_op!(stack, &global_env.convfmt, ==);
+                    compare_op!(stack, global_env, ==);
                 }
                 OpCode::Ne => {
-                    compare_op!(stack, &global_env.convfmt, !=);
+                    compare_op!(stack, global_env, !=);
                 }
                 OpCode::Match => {
                     let ere = stack.pop_value().into_ere()?;
@@ -1367,13 +1973,17 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     let result = array.contains(&key);
                     stack.push_value(bool_to_f64(result))?;
                 }
                 OpCode::Negate => {
-                    let value = stack.pop_scalar_value()?.scalar_as_f64();
-                    stack.push_value(-value)?;
+                    let value = stack.pop_scalar_value()?;
+                    if global_env.bignum {
+                        stack.push_value(-value.scalar_as_bignum())?;
+                    } else {
+                        stack.push_value(-value.scalar_as_f64())?;
+                    }
                 }
                 OpCode::Not => {
                     let value = stack.pop_scalar_value()?.scalar_as_bool();
@@ -1382,37 +1992,44 @@ impl Interpreter {
                 OpCode::PostInc => {
                     let lvalue = stack.pop_ref();
                     lvalue.ensure_value_is_scalar()?;
-                    let expr_result = lvalue.scalar_as_f64();
-                    fields_state = lvalue.assign(expr_result + 1.0, global_env)?;
-                    stack.push_value(expr_result)?;
+                    let (old_value, new_value) = increment(lvalue, 1, global_env.bignum);
+                    fields_state = lvalue.assign(new_value, global_env)?;
+                    stack.push_value(old_value)?;
                 }
                 OpCode::PostDec => {
                     let lvalue = stack.pop_ref();
                     lvalue.ensure_value_is_scalar()?;
-                    let expr_result = lvalue.scalar_as_f64();
-                    fields_state = lvalue.assign(expr_result - 1.0, global_env)?;
-                    stack.push_value(expr_result)?;
+                    let (old_value, new_value) = increment(lvalue, -1, global_env.bignum);
+                    fields_state = lvalue.assign(new_value, global_env)?;
+                    stack.push_value(old_value)?;
                 }
                 OpCode::PreInc => {
                     let lvalue = stack.pop_ref();
                     lvalue.ensure_value_is_scalar()?;
-                    let expr_result = lvalue.scalar_as_f64() + 1.0;
-                    fields_state = lvalue.assign(expr_result, global_env)?;
-                    stack.push_value(expr_result)?;
+                    let (_, new_value) = increment(lvalue, 1, global_env.bignum);
+                    fields_state = lvalue.assign(new_value.clone(), global_env)?;
+                    stack.push_value(new_value)?;
                 }
                 OpCode::PreDec => {
                     let lvalue = stack.pop_ref();
                     lvalue.ensure_value_is_scalar()?;
-                    let expr_result = lvalue.scalar_as_f64() - 1.0;
-                    fields_state = lvalue.assign(expr_result, global_env)?;
-                    stack.push_value(expr_result)?;
+                    let (_, new_value) = increment(lvalue, -1, global_env.bignum);
+                    fields_state = lvalue.assign(new_value.clone(), global_env)?;
+                    stack.push_value(new_value)?;
                 }
                 OpCode::CreateGlobalIterator(index) => {
                     let iter_var = stack.pop_ref();
                     iter_var.ensure_value_is_scalar()?;
                     let iter_var = iter_var as *mut AwkValue;
+                    let sorted_in = self.sorted_in(&global_env.convfmt)?;
                     let array = self.globals[index as usize].get();
-                    let key_iter = unsafe { &mut *array }.as_array()?.key_iter();
+                    let array_ref = unsafe { &mut *array }.as_array()?;
+                    if let Some((order, descending)) = sorted_in {
+                        array_ref.sort_by(|a, b| {
+                            compare_elements(order, descending, a, b, &global_env.convfmt)
+                        });
+                    }
+                    let key_iter = array_ref.key_iter();
                     // both iter_var and array are valid until the stack value is popped.
                     // The first from stack invariance, the second because its a global,
                     // so it will outlive the stack.
@@ -1428,11 +2045,18 @@ impl Interpreter {
                     let iter_var = stack.pop_ref();
                     iter_var.ensure_value_is_scalar()?;
                     let iter_var = iter_var as *mut AwkValue;
+                    let sorted_in = self.sorted_in(&global_env.convfmt)?;
                     let array = stack
                         .get_mut_value_ptr(index as usize)
                         .expect("invalid local index");
                     // has to be valid, by stack invariance
-                    let key_iter = unsafe { &mut *array }.as_array()?.key_iter();
+                    let array_ref = unsafe { &mut *array }.as_array()?;
+                    if let Some((order, descending)) = sorted_in {
+                        array_ref.sort_by(|a, b| {
+                            compare_elements(order, descending, a, b, &global_env.convfmt)
+                        });
+                    }
+                    let key_iter = array_ref.key_iter();
                     // both iter_var and array are valid, at least until this stack value is popped.
                     unsafe {
                         stack.push(StackValue::Iterator(ArrayIterator {
@@ -1461,7 +2085,11 @@ impl Interpreter {
                 }
                 OpCode::AsNumber => {
                     let val = stack.pop_scalar_value()?;
-                    stack.push_value(val.scalar_as_f64())?;
+                    if global_env.bignum {
+                        stack.push_value(val.scalar_as_bignum())?;
+                    } else {
+                        stack.push_value(val.scalar_as_f64())?;
+                    }
                 }
                 OpCode::GetGlobal(index) => unsafe {
                     // globals outlive the stack, so this is safe even if the global is an array
@@ -1485,7 +2113,7 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     let element = array.get_value(key.into())?.clone();
                     stack.push_value(element)?
                 }
@@ -1511,7 +2139,7 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = unsafe { stack.pop().expect("empty stack").unwrap_ptr() };
+                    let array = stack.pop_array_ptr()?;
                     // safe by type invariance
                     let value_index = unsafe { &mut *array }
                         .as_array()?
@@ -1536,11 +2164,11 @@ impl Interpreter {
                     let key = stack
                         .pop_scalar_value()?
                         .scalar_to_string(&global_env.convfmt)?;
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     array.delete(&key);
                 }
                 OpCode::ClearArray => {
-                    let array = stack.pop_ref().as_array()?;
+                    let array = stack.pop_array()?;
                     array.clear();
                 }
                 OpCode::JumpIfFalse(offset) => {
@@ -1564,7 +2192,14 @@ impl Interpreter {
                 }
                 OpCode::CallBuiltin { function, argc } => match function {
                     BuiltinFunction::Match => {
-                        let (start, len) = builtin_match(stack, global_env)?;
+                        let subsep = if argc == 3 {
+                            // globals outlive the stack, so this is safe
+                            unsafe { (*self.globals[SpecialVar::Subsep as usize].get()).clone() }
+                                .scalar_to_string(&global_env.convfmt)?
+                        } else {
+                            AwkString::default()
+                        };
+                        let (start, len) = builtin_match(stack, argc, &subsep, global_env)?;
                         // borrowing `self.globas` mutably here breaks the stacked borrows rules
                         // so we have to use unsafe code to get around that
                         unsafe {
@@ -1667,6 +2302,29 @@ impl Interpreter {
                             stack.push_value(0.0)?;
                         }
                     }
+                    BuiltinFunction::Strftime => {
+                        let utc = if argc == 3 {
+                            stack.pop_scalar_value()?.scalar_as_bool()
+                        } else {
+                            false
+                        };
+                        let timestamp = if argc >= 2 {
+                            stack.pop_scalar_value()?.scalar_as_f64() as i64
+                        } else {
+                            current_time()
+                        };
+                        let format = if argc >= 1 {
+                            stack
+                                .pop_scalar_value()?
+                                .scalar_to_string(&global_env.convfmt)?
+                        } else {
+                            match self.procinfo("strftime") {
+                                Some(format) => format.scalar_to_string(&global_env.convfmt)?,
+                                None => AwkString::from(DEFAULT_STRFTIME_FORMAT),
+                            }
+                        };
+                        stack.push_value(strftime(format, timestamp, utc)?)?;
+                    }
                     BuiltinFunction::Rand => {
                         let rand = self.rng.gen_range(0.0..1.0);
                         stack.push_value(rand)?;
@@ -1688,6 +2346,10 @@ impl Interpreter {
                 },
                 OpCode::PushConstant(index) => match self.constants[index as usize].clone() {
                     Constant::Number(num) => stack.push_value(num)?,
+                    Constant::LargeNumber(_, literal) if global_env.bignum => {
+                        stack.push_value(bignum::parse(&literal))?
+                    }
+                    Constant::LargeNumber(num, _) => stack.push_value(num)?,
                     Constant::String(s) => stack.push_value(AwkString::from(s))?,
                     Constant::Regex(ere) => {
                         stack.push_value(AwkValue::from_ere(ere, &record.record.borrow()))?
@@ -1731,6 +2393,9 @@ impl Interpreter {
                     stack.restore_caller();
                     stack.push_value(return_value)?;
                 }
+                OpCode::CountExecution(counter) => {
+                    self.profile_counts[counter as usize] += 1;
+                }
                 OpCode::Invalid => panic!("invalid opcode"),
             }
             match fields_state {
@@ -1802,6 +2467,10 @@ impl Interpreter {
             AwkValue::from(0.0).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Rstart));
         *globals[SpecialVar::Subsep as usize].get_mut() = AwkValue::from(" ".to_string())
             .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Subsep));
+        *globals[SpecialVar::Procinfo as usize].get_mut() =
+            AwkValue::from(procinfo()).into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Procinfo));
+        *globals[SpecialVar::Fpat as usize].get_mut() = AwkValue::from("[^[:space:]]+")
+            .into_ref(AwkRefType::SpecialGlobalVar(SpecialVar::Fpat));
 
         Self {
             globals,
@@ -1812,10 +2481,41 @@ impl Interpreter {
             read_pipes: ReadPipes::default(),
             rand_seed: 0,
             rng: SmallRng::seed_from_u64(0),
+            profile_counts: Vec::new(),
         }
     }
 }
 
+fn procinfo() -> Array {
+    // these calls cannot fail
+    let (pid, ppid, pgrpid, uid, euid, gid, egid) = unsafe {
+        (
+            libc::getpid(),
+            libc::getppid(),
+            libc::getpgrp(),
+            libc::getuid(),
+            libc::geteuid(),
+            libc::getgid(),
+            libc::getegid(),
+        )
+    };
+    [
+        ("version", AwkValue::from(env!("CARGO_PKG_VERSION"))),
+        ("platform", AwkValue::from("posix")),
+        ("FS", AwkValue::from("FS")),
+        ("strftime", AwkValue::from(DEFAULT_STRFTIME_FORMAT)),
+        ("pid", AwkValue::from(pid as f64)),
+        ("ppid", AwkValue::from(ppid as f64)),
+        ("pgrpid", AwkValue::from(pgrpid as f64)),
+        ("uid", AwkValue::from(uid as f64)),
+        ("euid", AwkValue::from(euid as f64)),
+        ("gid", AwkValue::from(gid as f64)),
+        ("egid", AwkValue::from(egid as f64)),
+    ]
+    .into_iter()
+    .collect()
+}
+
 fn is_valid_variable(s: &str) -> bool {
     s.chars()
         .next()
@@ -1857,6 +2557,9 @@ pub fn interpret(
     args: &[String],
     assignments: &[String],
     separator: Option<String>,
+    csv: bool,
+    bignum: bool,
+    profile_counts: &mut Vec<u64>,
 ) -> Result<i32, String> {
     let args = iter::once(("0".to_string(), AwkValue::from("awk")))
         .chain(args.iter().enumerate().map(|(index, s)| {
@@ -1876,7 +2579,16 @@ pub fn interpret(
         .collect::<Vec<StackValue>>();
     let mut current_record = Record::default();
     let mut interpreter = Interpreter::new(args, env, program.constants, program.globals_count);
-    let mut global_env = GlobalEnv::default();
+    interpreter.profile_counts = vec![0; program.profile_points.len()];
+    let mut global_env = GlobalEnv {
+        bignum,
+        ..Default::default()
+    };
+    if csv {
+        global_env.fs = FieldSeparator::Csv;
+        global_env.rs = RecordSeparator::Csv;
+        global_env.csv = true;
+    }
     let mut range_pattern_started = vec![false; program.rules.len()];
     let mut return_value = 0;
 
@@ -1909,6 +2621,7 @@ pub fn interpret(
     }
 
     if program.rules.is_empty() && program.end_actions.is_empty() {
+        *profile_counts = interpreter.profile_counts;
         return Ok(return_value);
     }
 
@@ -1970,7 +2683,7 @@ pub fn interpret(
 
         global_env.fnr = 1;
         'record_loop: while let Some(record) = reader.read_next_record(&global_env.rs)? {
-            current_record.reset(record, &global_env.fs)?;
+            current_record.reset(record, global_env.field_separator())?;
             interpreter.globals[SpecialVar::Nf as usize].get_mut().value =
                 AwkValue::from(current_record.get_last_field() as f64).value;
             global_env.nf = current_record.get_last_field();
@@ -2068,6 +2781,7 @@ pub fn interpret(
         }
     }
 
+    *profile_counts = interpreter.profile_counts;
     Ok(return_value)
 }
 
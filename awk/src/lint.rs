@@ -0,0 +1,295 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Static checks performed with `--lint`
+
+use crate::compiler::{
+    is_extension_function, is_extension_variable, is_special_variable, AwkParser, Rule, SourceFile,
+};
+use pest::iterators::Pair;
+use pest::Parser;
+use std::collections::HashSet;
+
+/// Index of the source file, line and column
+type Location = (usize, usize, usize);
+
+#[derive(Default)]
+struct Linter<'a> {
+    source: usize,
+    /// global variables read as scalars, with the location of their first use
+    reads: Vec<(&'a str, Location)>,
+    /// global variables that are assigned, used as arrays or passed to functions
+    initialized: HashSet<&'a str>,
+    functions: Vec<(&'a str, Location)>,
+    calls: Vec<(&'a str, Location)>,
+    warnings: Vec<(Location, String)>,
+}
+
+/// Returns the name if the expression consists only of a variable name
+fn plain_name<'a>(expr: &Pair<'a, Rule>) -> Option<&'a str> {
+    let mut pair = expr.clone();
+    loop {
+        match pair.as_rule() {
+            Rule::name => return Some(pair.as_str()),
+            Rule::field_var => return None,
+            _ => {}
+        }
+        let mut inner = pair.into_inner();
+        let child = inner.next()?;
+        if inner.next().is_some() {
+            return None;
+        }
+        pair = child;
+    }
+}
+
+fn is_increment(pair: Option<&Pair<Rule>>) -> bool {
+    pair.is_some_and(|p| {
+        matches!(
+            p.as_rule(),
+            Rule::pre_inc | Rule::pre_dec | Rule::post_inc | Rule::post_dec
+        )
+    })
+}
+
+impl<'a> Linter<'a> {
+    fn location(&self, pair: &Pair<'a, Rule>) -> Location {
+        let (line, column) = pair.line_col();
+        (self.source, line, column)
+    }
+
+    fn use_name(&mut self, name: Pair<'a, Rule>, locals: &HashSet<&'a str>, initialize: bool) {
+        let text = name.as_str();
+        if locals.contains(text) {
+            return;
+        }
+        if is_extension_variable(text) {
+            let location = self.location(&name);
+            self.warnings
+                .push((location, format!("`{text}` is a gawk extension")));
+        } else if initialize {
+            self.initialized.insert(text);
+        } else if !self.reads.iter().any(|(read, _)| *read == text) {
+            let location = self.location(&name);
+            self.reads.push((text, location));
+        }
+    }
+
+    fn function_args(
+        &mut self,
+        args: impl Iterator<Item = Pair<'a, Rule>>,
+        locals: &HashSet<&'a str>,
+    ) {
+        for arg in args {
+            // variables passed to functions may be arrays filled by the callee
+            if plain_name(&arg).is_some() {
+                let name = arg
+                    .into_inner()
+                    .flatten()
+                    .find(|p| p.as_rule() == Rule::name);
+                self.use_name(name.unwrap(), locals, true);
+            } else {
+                self.walk(arg, locals);
+            }
+        }
+    }
+
+    /// Walks an lvalue that is the target of an assignment
+    fn assigned_lvalue(&mut self, lvalue: Pair<'a, Rule>, locals: &HashSet<&'a str>) {
+        let target = lvalue.into_inner().next().unwrap();
+        if target.as_rule() == Rule::name {
+            self.use_name(target, locals, true);
+        } else {
+            self.walk(target, locals);
+        }
+    }
+
+    fn walk(&mut self, pair: Pair<'a, Rule>, locals: &HashSet<&'a str>) {
+        match pair.as_rule() {
+            Rule::function_definition => {
+                let location = self.location(&pair);
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str();
+                self.functions.push((name, location));
+                let mut params = HashSet::new();
+                for part in inner {
+                    if part.as_rule() == Rule::param_list {
+                        params.extend(part.into_inner().map(|p| p.as_str()));
+                    } else {
+                        self.walk(part, &params);
+                    }
+                }
+            }
+            Rule::function_call => {
+                let location = self.location(&pair);
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str();
+                self.calls.push((name, location));
+                self.function_args(inner, locals);
+            }
+            Rule::builtin_function_call => {
+                let mut inner = pair.into_inner();
+                inner.next();
+                self.function_args(inner, locals);
+            }
+            Rule::assignment | Rule::print_assignment => {
+                let mut inner = pair.into_inner();
+                self.assigned_lvalue(inner.next().unwrap(), locals);
+                for part in inner {
+                    self.walk(part, locals);
+                }
+            }
+            Rule::simple_binary_expr | Rule::simple_binary_print_expr => {
+                let parts = pair.into_inner().collect::<Vec<_>>();
+                for (i, part) in parts.iter().enumerate() {
+                    let previous = i.checked_sub(1).map(|i| &parts[i]);
+                    let incremented = is_increment(previous) || is_increment(parts.get(i + 1));
+                    let is_array = previous.is_some_and(|p| p.as_rule() == Rule::in_op);
+                    if part.as_rule() == Rule::lvalue && (incremented || is_array) {
+                        self.assigned_lvalue(part.clone(), locals);
+                    } else {
+                        self.walk(part.clone(), locals);
+                    }
+                }
+            }
+            Rule::simple_getline | Rule::getline_from_file | Rule::piped_getline => {
+                for part in pair.into_inner() {
+                    if part.as_rule() == Rule::lvalue {
+                        self.assigned_lvalue(part, locals);
+                    } else {
+                        self.walk(part, locals);
+                    }
+                }
+            }
+            Rule::t_foreach
+            | Rule::ut_foreach
+            | Rule::array_delete
+            | Rule::multidimensional_in
+            | Rule::array_element => {
+                for part in pair.into_inner() {
+                    if part.as_rule() == Rule::name {
+                        self.use_name(part, locals, true);
+                    } else {
+                        self.walk(part, locals);
+                    }
+                }
+            }
+            Rule::name => self.use_name(pair, locals, false),
+            _ => {
+                for part in pair.into_inner() {
+                    self.walk(part, locals);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self, externally_assigned: &[&str]) -> Vec<(Location, String)> {
+        for (name, location) in &self.reads {
+            if !self.initialized.contains(name)
+                && !externally_assigned.contains(name)
+                && !is_special_variable(name)
+            {
+                self.warnings.push((
+                    *location,
+                    format!("variable `{name}` is used but never assigned"),
+                ));
+            }
+        }
+        for (name, location) in &self.functions {
+            if !self.calls.iter().any(|(called, _)| called == name) {
+                self.warnings.push((
+                    *location,
+                    format!("function `{name}` is defined but never called"),
+                ));
+            }
+        }
+        for (name, location) in &self.calls {
+            if is_extension_function(name) && !self.functions.iter().any(|(f, _)| f == name) {
+                self.warnings
+                    .push((*location, format!("`{name}` is a gawk extension")));
+            }
+        }
+        self.warnings.sort_by_key(|(location, _)| *location);
+        self.warnings
+    }
+}
+
+/// Checks the program for uninitialized variables, unused functions and
+/// constructs that are not portable to other POSIX awk implementations.
+/// Variables in `externally_assigned` are set from the command line, so they
+/// are considered initialized.
+///
+/// # Panics
+/// Panics if the sources are not valid awk programs
+pub fn lint(sources: &[SourceFile], externally_assigned: &[&str]) -> Vec<String> {
+    let mut linter = Linter::default();
+    let programs = sources
+        .iter()
+        .map(|source| {
+            AwkParser::parse(Rule::program, &source.contents)
+                .expect("program was already compiled")
+                .next()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    for (index, program) in programs.into_iter().enumerate() {
+        linter.source = index;
+        linter.walk(program, &HashSet::new());
+    }
+    linter
+        .finish(externally_assigned)
+        .into_iter()
+        .map(|((source, line, _), message)| {
+            let file = sources[source].filename.as_str();
+            let file = if file.is_empty() { "cmd. line" } else { file };
+            format!("awk: {file}:{line}: warning: {message}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_str(text: &str) -> Vec<String> {
+        lint(&[SourceFile::stdin(text.to_string())], &[])
+    }
+
+    #[test]
+    fn test_lint_uninitialized_variables() {
+        assert_eq!(
+            lint_str(
+                "BEGIN { x = 1; n++; split(\"a\", arr); getline line\nprint x y n arr[1] line NR }"
+            ),
+            vec!["awk: cmd. line:2: warning: variable `y` is used but never assigned"]
+        );
+        assert!(lint_str("function f(a) { return a + b }\nBEGIN { b = 1; f() }").is_empty());
+        assert!(lint(&[SourceFile::stdin("{ print v }".to_string())], &["v"]).is_empty());
+    }
+
+    #[test]
+    fn test_lint_unused_functions() {
+        assert_eq!(
+            lint_str("function used() {}\n\nfunction unused() {}\nBEGIN { used() }"),
+            vec!["awk: cmd. line:3: warning: function `unused` is defined but never called"]
+        );
+    }
+
+    #[test]
+    fn test_lint_gawk_extensions() {
+        assert_eq!(
+            lint_str("BEGIN { print strftime(); PROCINFO[\"sorted_in\"] = \"@ind_num_asc\" }"),
+            vec![
+                "awk: cmd. line:1: warning: `strftime` is a gawk extension",
+                "awk: cmd. line:1: warning: `PROCINFO` is a gawk extension"
+            ]
+        );
+        assert!(lint_str("function systime() { return 0 }\nBEGIN { systime() }").is_empty());
+    }
+}
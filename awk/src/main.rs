@@ -9,16 +9,20 @@
 
 use crate::compiler::compile_program;
 use crate::interpreter::interpret;
+use crate::lint::lint;
+use crate::pretty_print::pretty_print;
 use clap::Parser;
 use compiler::SourceFile;
 use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
 use plib::PROJECT_NAME;
 use std::error::Error;
 use std::fmt::Display;
-use std::io::Read;
+use std::io::{Read, Write};
 
 mod compiler;
 mod interpreter;
+mod lint;
+mod pretty_print;
 mod program;
 mod regex;
 
@@ -42,6 +46,48 @@ struct Args {
     )]
     assignments: Vec<String>,
 
+    #[arg(long, help = gettext("Disable gawk extensions"))]
+    posix: bool,
+
+    #[arg(
+        long,
+        help = gettext("Read the input as comma-separated values, ignoring FS and RS")
+    )]
+    csv: bool,
+
+    #[arg(
+        short = 'M',
+        long,
+        help = gettext("Use arbitrary precision arithmetic for numbers")
+    )]
+    bignum: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "awkprof.out",
+        help = gettext("Write the program in a canonical format to FILE instead of running it")
+    )]
+    pretty_print: Option<String>,
+
+    #[arg(
+        long,
+        help = gettext("Warn about uninitialized variables, unused functions and nonportable constructs")
+    )]
+    lint: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "awkprof.out",
+        help = gettext("Write the program annotated with execution counts to FILE after running it")
+    )]
+    profile: Option<String>,
+
     arguments: Vec<String>,
 }
 
@@ -55,13 +101,23 @@ fn exit_if_error<T, U: Display>(r: Result<T, U>) -> T {
     }
 }
 
+/// Writes the listing to the file at `path`, or to the standard output if `path` is "-"
+fn write_listing(path: &str, listing: &str) -> Result<(), Box<dyn Error>> {
+    if path == "-" {
+        std::io::stdout().write_all(listing.as_bytes())?;
+    } else {
+        std::fs::write(path, listing).map_err(|_| gettext!("could not write file '{}'", path))?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
     let args = Args::parse();
 
-    let return_status = if !args.program_files.is_empty() {
+    let (sources, arguments) = if !args.program_files.is_empty() {
         let mut sources = Vec::new();
         for source_file in &args.program_files {
             let mut file = std::fs::File::open(source_file)
@@ -74,26 +130,53 @@ fn main() -> Result<(), Box<dyn Error>> {
                 filename: source_file.clone(),
             });
         }
-        let program = exit_if_error(compile_program(&sources));
-        exit_if_error(interpret(
-            program,
-            &args.arguments,
-            &args.assignments,
-            args.separator_string,
-        ))
+        (sources, &args.arguments[..])
     } else if !args.arguments.is_empty() {
-        let program = exit_if_error(compile_program(&[SourceFile::stdin(
-            args.arguments[0].clone(),
-        )]));
-        exit_if_error(interpret(
-            program,
+        (
+            vec![SourceFile::stdin(args.arguments[0].clone())],
             &args.arguments[1..],
-            &args.assignments,
-            args.separator_string,
-        ))
+        )
     } else {
         eprintln!("{}", gettext("missing program argument"));
-        1
+        std::process::exit(1);
     };
+
+    let program = exit_if_error(compile_program(
+        &sources,
+        args.posix,
+        args.profile.is_some(),
+    ));
+    if args.lint {
+        // variables assigned with -v or with assignment operands
+        let externally_assigned = args
+            .assignments
+            .iter()
+            .chain(arguments)
+            .filter_map(|arg| arg.split_once('=').map(|(name, _)| name))
+            .collect::<Vec<_>>();
+        for warning in lint(&sources, &externally_assigned) {
+            eprintln!("{warning}");
+        }
+    }
+    if let Some(path) = &args.pretty_print {
+        write_listing(path, &pretty_print(&sources, None))?;
+        return Ok(());
+    }
+
+    let profile_points = program.profile_points.clone();
+    let mut profile_counts = Vec::new();
+    let return_status = exit_if_error(interpret(
+        program,
+        arguments,
+        &args.assignments,
+        args.separator_string,
+        args.csv,
+        args.bignum,
+        &mut profile_counts,
+    ));
+    if let Some(path) = &args.profile {
+        let counts = profile_points.into_iter().zip(profile_counts).collect();
+        write_listing(path, &pretty_print(&sources, Some(&counts)))?;
+    }
     std::process::exit(return_status);
 }
@@ -0,0 +1,481 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Regenerates awk source code from the parse tree, used for `--pretty-print`
+//! and for the listing written by `--profile`.
+
+use crate::compiler::{AwkParser, Rule, SourceFile};
+use crate::program::ProfilePoint;
+use pest::iterators::Pair;
+use pest::Parser;
+use std::collections::HashMap;
+
+struct PrettyPrinter<'c> {
+    output: String,
+    indent: usize,
+    counts: Option<&'c HashMap<ProfilePoint, u64>>,
+    source: usize,
+}
+
+fn join_exprs<'i>(exprs: impl Iterator<Item = Pair<'i, Rule>>) -> String {
+    exprs.map(expr_to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn optional_lvalue(pair: Option<Pair<Rule>>) -> String {
+    pair.filter(|p| p.as_rule() == Rule::lvalue)
+        .map(|lvalue| format!(" {}", expr_to_string(lvalue)))
+        .unwrap_or_default()
+}
+
+fn primary_to_string(primary: Pair<Rule>) -> String {
+    match primary.as_rule() {
+        Rule::expr => format!("({})", expr_to_string(primary)),
+        Rule::ere | Rule::number | Rule::string | Rule::name => primary.as_str().to_string(),
+        Rule::builtin_function_call => {
+            let text = primary.as_str();
+            let mut inner = primary.into_inner();
+            let name = inner.next().unwrap().as_str();
+            if text[name.len()..].trim_start().starts_with('(') {
+                format!("{}({})", name, join_exprs(inner))
+            } else {
+                name.to_string()
+            }
+        }
+        Rule::function_call => {
+            let mut inner = primary.into_inner();
+            let name = inner.next().unwrap().as_str();
+            format!("{}({})", name, join_exprs(inner))
+        }
+        Rule::lvalue => primary_to_string(primary.into_inner().next().unwrap()),
+        Rule::array_element => {
+            let mut inner = primary.into_inner();
+            let name = inner.next().unwrap().as_str();
+            format!("{}[{}]", name, join_exprs(inner))
+        }
+        Rule::field_var => format!(
+            "${}",
+            primary_to_string(primary.into_inner().next().unwrap())
+        ),
+        _ => unreachable!("encountered {:?} while printing primary", primary.as_rule()),
+    }
+}
+
+fn binary_expr_to_string(expr: Pair<Rule>) -> String {
+    let mut result = String::new();
+    for part in expr.into_inner() {
+        match part.as_rule() {
+            Rule::pre_inc
+            | Rule::pre_dec
+            | Rule::not
+            | Rule::unary_plus
+            | Rule::negate
+            | Rule::post_inc
+            | Rule::post_dec => result.push_str(part.as_str()),
+            Rule::concat => result.push(' '),
+            Rule::pow
+            | Rule::mul
+            | Rule::div
+            | Rule::modulus
+            | Rule::add
+            | Rule::binary_sub
+            | Rule::match_op
+            | Rule::not_match
+            | Rule::in_op
+            | Rule::and
+            | Rule::or
+            | Rule::comp_op => {
+                result.push(' ');
+                result.push_str(part.as_str());
+                result.push(' ');
+            }
+            _ => result.push_str(&primary_to_string(part)),
+        }
+    }
+    result
+}
+
+fn expr_to_string(expr: Pair<Rule>) -> String {
+    match expr.as_rule() {
+        Rule::expr
+        | Rule::print_expr
+        | Rule::input_function
+        | Rule::unpiped_expr
+        | Rule::unpiped_input_function
+        | Rule::binary_expr
+        | Rule::binary_print_expr
+        | Rule::lvalue => expr_to_string(expr.into_inner().next().unwrap()),
+        Rule::simple_binary_expr | Rule::simple_binary_print_expr => binary_expr_to_string(expr),
+        Rule::assignment | Rule::print_assignment => {
+            let mut inner = expr.into_inner();
+            let lvalue = expr_to_string(inner.next().unwrap());
+            let op = inner.next().unwrap().as_str();
+            let value = expr_to_string(inner.next().unwrap());
+            format!("{lvalue} {op} {value}")
+        }
+        Rule::ternary_expr | Rule::ternary_print_expr => {
+            let mut inner = expr.into_inner();
+            let condition = expr_to_string(inner.next().unwrap());
+            let if_true = expr_to_string(inner.next().unwrap());
+            let if_false = expr_to_string(inner.next().unwrap());
+            format!("{condition} ? {if_true} : {if_false}")
+        }
+        Rule::multidimensional_in => {
+            let mut inner = expr.into_inner();
+            let index = join_exprs(inner.next().unwrap().into_inner());
+            let array = inner.next().unwrap().as_str();
+            format!("({index}) in {array}")
+        }
+        Rule::simple_getline => format!("getline{}", optional_lvalue(expr.into_inner().next())),
+        Rule::getline_from_file => {
+            let mut inner = expr.into_inner().peekable();
+            let lvalue = optional_lvalue(inner.next_if(|p| p.as_rule() == Rule::lvalue));
+            let file = expr_to_string(inner.next().unwrap());
+            format!("getline{lvalue} < {file}")
+        }
+        Rule::getline_from_pipe => {
+            let mut inner = expr.into_inner();
+            let mut result = expr_to_string(inner.next().unwrap());
+            for getline in inner {
+                result.push_str(" | getline");
+                result.push_str(&optional_lvalue(getline.into_inner().next()));
+            }
+            result
+        }
+        _ => primary_to_string(expr),
+    }
+}
+
+fn simple_statement_to_string(stmt: Pair<Rule>) -> String {
+    let stmt = stmt.into_inner().next().unwrap();
+    match stmt.as_rule() {
+        Rule::array_delete => {
+            let mut inner = stmt.into_inner();
+            let name = inner.next().unwrap().as_str();
+            match inner.next() {
+                Some(index) => format!("delete {}[{}]", name, join_exprs(index.into_inner())),
+                None => format!("delete {name}"),
+            }
+        }
+        Rule::print_stmt => {
+            let mut inner = stmt.into_inner();
+            let print = inner.next().unwrap();
+            let keyword = if matches!(print.as_rule(), Rule::printf_call | Rule::simple_printf) {
+                "printf"
+            } else {
+                "print"
+            };
+            let mut result = match print.as_rule() {
+                Rule::print_call | Rule::printf_call => {
+                    format!("{}({})", keyword, join_exprs(print.into_inner()))
+                }
+                _ => {
+                    let args = join_exprs(print.into_inner());
+                    if args.is_empty() {
+                        keyword.to_string()
+                    } else {
+                        format!("{keyword} {args}")
+                    }
+                }
+            };
+            if let Some(redirection) = inner.next() {
+                let op = match redirection.as_rule() {
+                    Rule::truncate => ">",
+                    Rule::append => ">>",
+                    Rule::pipe => "|",
+                    _ => unreachable!("invalid output redirection"),
+                };
+                let target = expr_to_string(redirection.into_inner().next().unwrap());
+                result.push_str(&format!(" {op} {target}"));
+            }
+            result
+        }
+        _ => expr_to_string(stmt),
+    }
+}
+
+impl PrettyPrinter<'_> {
+    fn count(&self, pair: &Pair<Rule>) -> Option<u64> {
+        let point = ProfilePoint {
+            source: self.source,
+            offset: pair.as_span().start(),
+        };
+        self.counts
+            .and_then(|counts| counts.get(&point))
+            .copied()
+            .filter(|count| *count > 0)
+    }
+
+    fn line(&mut self, count: Option<u64>, text: &str) {
+        if self.counts.is_some() {
+            match count {
+                Some(count) => self.output.push_str(&format!("{count:>6}  ")),
+                None => self.output.push_str("        "),
+            }
+        }
+        for _ in 0..self.indent {
+            self.output.push('\t');
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn action_body(&mut self, action: Pair<Rule>) {
+        self.indent += 1;
+        for stmt in action.into_inner() {
+            self.statement(stmt);
+        }
+        self.indent -= 1;
+    }
+
+    /// Prints the body of a compound statement, which is always enclosed in braces
+    fn body(&mut self, body: Pair<Rule>) {
+        if body.as_rule() == Rule::action {
+            self.action_body(body);
+        } else {
+            self.indent += 1;
+            self.statement(body);
+            self.indent -= 1;
+        }
+    }
+
+    fn if_statement(&mut self, stmt: Pair<Rule>, prefix: &str) {
+        let count = self.count(&stmt);
+        let mut inner = stmt.into_inner();
+        let condition = expr_to_string(inner.next().unwrap());
+        self.line(count, &format!("{prefix}if ({condition}) {{"));
+        self.body(inner.next().unwrap());
+        match inner.next() {
+            Some(else_body) if matches!(else_body.as_rule(), Rule::t_if | Rule::ut_if) => {
+                self.if_statement(else_body, "} else ");
+            }
+            Some(else_body) => {
+                self.line(None, "} else {");
+                self.body(else_body);
+                self.line(None, "}");
+            }
+            None => self.line(None, "}"),
+        }
+    }
+
+    fn for_statement(&mut self, stmt: Pair<Rule>, count: Option<u64>) {
+        let start = stmt.as_span().start();
+        let text = stmt.as_str();
+        let mut init = String::new();
+        let mut condition = String::new();
+        let mut update = String::new();
+        let mut parts = stmt.into_inner().collect::<Vec<_>>();
+        let body = parts.pop().unwrap();
+        for part in parts {
+            match part.as_rule() {
+                Rule::simple_statement => {
+                    // the initialization is the only part that precedes the first ';'
+                    let offset = part.as_span().start() - start;
+                    if text[..offset].contains(';') {
+                        update = simple_statement_to_string(part);
+                    } else {
+                        init = simple_statement_to_string(part);
+                    }
+                }
+                _ => condition = expr_to_string(part),
+            }
+        }
+        self.line(count, &format!("for ({init}; {condition}; {update}) {{"));
+        self.body(body);
+        self.line(None, "}");
+    }
+
+    fn statement(&mut self, stmt: Pair<Rule>) {
+        let count = self.count(&stmt);
+        match stmt.as_rule() {
+            Rule::action => {
+                self.line(None, "{");
+                self.action_body(stmt);
+                self.line(None, "}");
+            }
+            Rule::t_if | Rule::ut_if => self.if_statement(stmt, ""),
+            Rule::t_while | Rule::ut_while => {
+                let mut inner = stmt.into_inner();
+                let condition = expr_to_string(inner.next().unwrap());
+                self.line(count, &format!("while ({condition}) {{"));
+                self.body(inner.next().unwrap());
+                self.line(None, "}");
+            }
+            Rule::do_while => {
+                let mut inner = stmt.into_inner();
+                let body = inner.next().unwrap();
+                let condition = expr_to_string(inner.next().unwrap());
+                self.line(count, "do {");
+                self.body(body);
+                self.line(None, &format!("}} while ({condition})"));
+            }
+            Rule::t_for | Rule::ut_for => self.for_statement(stmt, count),
+            Rule::t_foreach | Rule::ut_foreach => {
+                let mut inner = stmt.into_inner().filter(|p| p.as_rule() != Rule::in_op);
+                let key = inner.next().unwrap().as_str();
+                let array = inner.next().unwrap().as_str();
+                self.line(count, &format!("for ({key} in {array}) {{"));
+                self.body(inner.next().unwrap());
+                self.line(None, "}");
+            }
+            Rule::simple_statement => self.line(count, &simple_statement_to_string(stmt)),
+            Rule::exit_stmt | Rule::return_stmt => {
+                let keyword = if stmt.as_rule() == Rule::exit_stmt {
+                    "exit"
+                } else {
+                    "return"
+                };
+                match stmt.into_inner().next() {
+                    Some(expr) => self.line(count, &format!("{keyword} {}", expr_to_string(expr))),
+                    None => self.line(count, keyword),
+                }
+            }
+            Rule::next | Rule::nextfile | Rule::break_stmt | Rule::continue_stmt => {
+                self.line(count, stmt.as_str())
+            }
+            _ => unreachable!("encountered {:?} while printing statement", stmt.as_rule()),
+        }
+    }
+
+    fn pattern(&mut self, pattern: Pair<Rule>) -> String {
+        let pattern = pattern.into_inner().next().unwrap();
+        if pattern.as_rule() == Rule::range_pattern {
+            join_exprs(pattern.into_inner())
+        } else {
+            expr_to_string(pattern)
+        }
+    }
+
+    fn item(&mut self, item: Pair<Rule>) {
+        match item.as_rule() {
+            Rule::begin_action | Rule::end_action => {
+                let keyword = if item.as_rule() == Rule::begin_action {
+                    "BEGIN"
+                } else {
+                    "END"
+                };
+                self.line(None, &format!("{keyword} {{"));
+                self.action_body(item.into_inner().next().unwrap());
+                self.line(None, "}");
+            }
+            Rule::rule => {
+                let rule = item.into_inner().next().unwrap();
+                match rule.as_rule() {
+                    Rule::action => {
+                        self.line(self.count(&rule), "{");
+                        self.action_body(rule);
+                        self.line(None, "}");
+                    }
+                    Rule::pattern_and_action => {
+                        let mut inner = rule.into_inner();
+                        let pattern = inner.next().unwrap();
+                        let pattern_count = self.count(&pattern);
+                        let pattern = self.pattern(pattern);
+                        let action = inner.next().unwrap();
+                        let text = match self.count(&action) {
+                            Some(count) => format!("{pattern} {{ # {count}"),
+                            None => format!("{pattern} {{"),
+                        };
+                        self.line(pattern_count, &text);
+                        self.action_body(action);
+                        self.line(None, "}");
+                    }
+                    _ => {
+                        let count = self.count(&rule);
+                        let pattern = self.pattern(rule);
+                        self.line(count, &pattern);
+                    }
+                }
+            }
+            Rule::function_definition => {
+                let count = self.count(&item);
+                let mut inner = item.into_inner();
+                let name = inner.next().unwrap().as_str();
+                let mut next = inner.next().unwrap();
+                let mut params = Vec::new();
+                if next.as_rule() == Rule::param_list {
+                    params.extend(next.into_inner().map(|p| p.as_str()));
+                    next = inner.next().unwrap();
+                }
+                self.line(count, &format!("function {}({})", name, params.join(", ")));
+                self.line(None, "{");
+                self.action_body(next);
+                self.line(None, "}");
+            }
+            _ => unreachable!("encountered {:?} while printing program", item.as_rule()),
+        }
+    }
+}
+
+/// Returns the source code of the program in a canonical format.
+/// If `counts` is present, each line is prefixed by the number of times the
+/// statement on it was executed. Comments are not preserved.
+///
+/// # Panics
+/// Panics if the sources are not valid awk programs
+pub fn pretty_print(sources: &[SourceFile], counts: Option<&HashMap<ProfilePoint, u64>>) -> String {
+    let mut printer = PrettyPrinter {
+        output: String::new(),
+        indent: 0,
+        counts,
+        source: 0,
+    };
+    for (index, source) in sources.iter().enumerate() {
+        printer.source = index;
+        let program = AwkParser::parse(Rule::program, &source.contents)
+            .expect("program was already compiled")
+            .next()
+            .unwrap();
+        for item in program.into_inner() {
+            if item.as_rule() == Rule::EOI {
+                continue;
+            }
+            if !printer.output.is_empty() {
+                printer.output.push('\n');
+            }
+            printer.item(item);
+        }
+    }
+    printer.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pretty_print_str(text: &str) -> String {
+        pretty_print(&[SourceFile::stdin(text.to_string())], None)
+    }
+
+    #[test]
+    fn test_pretty_print_rules() {
+        assert_eq!(
+            pretty_print_str("BEGIN{FS=\":\"}\n$1>0{print $1,$2>\"out\"}\nNR==1,/end/\nEND{print NR}"),
+            "BEGIN {\n\tFS = \":\"\n}\n\n$1 > 0 {\n\tprint $1, $2 > \"out\"\n}\n\nNR == 1, /end/\n\nEND {\n\tprint NR\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_statements() {
+        assert_eq!(
+            pretty_print_str(
+                "function f(a,b){if(a)return b;else if(b)x++;else{y=-a}\nfor(i=0;i<3;i++)s=s i\nfor(k in arr)delete arr[k]\ndo n--;while(n>0)}"
+            ),
+            "function f(a, b)\n{\n\tif (a) {\n\t\treturn b\n\t} else if (b) {\n\t\tx++\n\t} else {\n\t\ty = -a\n\t}\n\tfor (i = 0; i < 3; i++) {\n\t\ts = s i\n\t}\n\tfor (k in arr) {\n\t\tdelete arr[k]\n\t}\n\tdo {\n\t\tn--\n\t} while (n > 0)\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_is_idempotent() {
+        let program = "BEGIN { while ((\"cmd\" | getline line) > 0) n[line]++; x = a ? b : c\n\
+                       if ((1, 2) in n) printf(\"%s\\n\", substr($0, 2)) | \"cat\"; length }\n\
+                       /re/ { getline x < \"file\"; $(NF - 1) = toupper($1) \"-\" 2 ^ -3 }";
+        let printed = pretty_print_str(program);
+        assert_eq!(pretty_print_str(&printed), printed);
+    }
+}
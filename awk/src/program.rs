@@ -104,6 +104,10 @@ pub enum OpCode {
     Exit,
     Return,
 
+    // increments the execution counter with the given index.
+    // Only emitted when compiling for `--profile`
+    CountExecution(u32),
+
     // invalid opcode. Cannot be inside a valid program
     Invalid,
 }
@@ -112,6 +116,9 @@ pub enum OpCode {
 #[derive(Clone, PartialEq)]
 pub enum Constant {
     Number(f64),
+    /// A number literal too large to be represented exactly as an `f64`.
+    /// The source text is kept so that it can be read exactly with -M.
+    LargeNumber(f64, Rc<str>),
     String(Rc<str>),
     Regex(Rc<Regex>),
 }
@@ -145,6 +152,9 @@ pub struct SourceLocation {
 #[derive(PartialEq, Default)]
 pub struct DebugInfo {
     pub file: Rc<str>,
+    /// Description of the rule containing the instructions, used in error
+    /// messages. Empty for functions
+    pub rule: Rc<str>,
     pub source_locations: Vec<SourceLocation>,
 }
 
@@ -170,6 +180,16 @@ pub struct AwkRule {
     pub action: Action,
 }
 
+/// The start of a statement, pattern, action or function in the source
+#[cfg_attr(test, derive(Debug))]
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ProfilePoint {
+    /// index of the source file in the order it was passed to the compiler
+    pub source: usize,
+    /// byte offset inside the source file
+    pub offset: usize,
+}
+
 #[cfg_attr(test, derive(Debug))]
 #[derive(PartialEq, Default)]
 pub struct Function {
@@ -181,6 +201,8 @@ pub struct Function {
 
 pub struct Program {
     pub constants: Vec<Constant>,
+    /// The locations counted by `OpCode::CountExecution`, indexed by counter
+    pub profile_points: Vec<ProfilePoint>,
     pub globals_count: usize,
     pub globals: HashMap<String, u32>,
     pub begin_actions: Vec<Action>,
@@ -274,6 +296,9 @@ pub enum SpecialVar {
     Rs,
     Rstart,
     Subsep,
+    // gawk extensions, not available with `--posix`
+    Procinfo,
+    Fpat,
 
     /// the total number of special variables
     Count,
@@ -317,6 +342,16 @@ pub enum BuiltinFunction {
     ToLower,
     ToUpper,
 
+    // gawk extensions, not available with `--posix`
+    /// The target string defaults to `$0` and is always passed
+    /// as the fourth argument
+    Gensub,
+    Strftime,
+    Systime,
+    Mktime,
+    Asort,
+    Asorti,
+
     // I/O functions
     Close,
     FFlush,
@@ -7,35 +7,12 @@
 // SPDX-License-Identifier: MIT
 //
 
+use plib::regex::RegexFlags;
 use std::ffi::CString;
-use std::ptr;
-
-fn regex_compilation_result(
-    status_integer: libc::c_int,
-    regex: &libc::regex_t,
-) -> Result<(), String> {
-    if status_integer != 0 {
-        let mut error_buffer = vec![b'\0'; 128];
-        unsafe {
-            libc::regerror(
-                status_integer,
-                ptr::from_ref(regex),
-                error_buffer.as_mut_ptr() as *mut libc::c_char,
-                128,
-            )
-        };
-        let error = CString::from_vec_with_nul(error_buffer)
-            .expect("error message returned from `libc::regerror` is an invalid CString");
-        Err(error
-            .into_string()
-            .expect("error message from `libc::regerror' contains invalid utf-8"))
-    } else {
-        Ok(())
-    }
-}
+use std::ops::Range;
 
 pub struct Regex {
-    raw_regex: libc::regex_t,
+    regex: plib::regex::Regex,
     regex_string: CString,
 }
 
@@ -46,82 +23,136 @@ pub struct RegexMatch {
     pub end: usize,
 }
 
-pub struct MatchIter<'re> {
+struct MatchState {
     string: CString,
     next_start: usize,
+    last_match_end: Option<usize>,
+}
+
+impl MatchState {
+    /// Advances the search past `found`. Returns false if the match
+    /// has to be skipped.
+    fn advance(&mut self, found: &Range<usize>) -> bool {
+        let string = self.string.as_bytes();
+        if found.is_empty() {
+            // an empty match advances the search by one character, and is
+            // not allowed right after the previous match
+            self.next_start = found.end
+                + std::str::from_utf8(&string[found.end..])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map_or(1, char::len_utf8);
+            if self.last_match_end == Some(found.start) {
+                return false;
+            }
+        } else {
+            self.next_start = found.end;
+        }
+        self.last_match_end = Some(found.end);
+        true
+    }
+}
+
+pub struct MatchIter<'re> {
+    state: MatchState,
     regex: &'re Regex,
 }
 
 impl Iterator for MatchIter<'_> {
     type Item = RegexMatch;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.next_start >= self.string.as_bytes().len() {
-            return None;
+        loop {
+            if self.state.next_start > self.state.string.as_bytes().len() {
+                return None;
+            }
+            let found = self
+                .regex
+                .regex
+                .find_at(self.state.string.as_bytes(), self.state.next_start)?;
+            if self.state.advance(&found) {
+                return Some(RegexMatch {
+                    start: found.start,
+                    end: found.end,
+                });
+            }
         }
-        let mut match_range = libc::regmatch_t {
-            rm_so: -1,
-            rm_eo: -1,
-        };
-        let exec_status = unsafe {
-            libc::regexec(
-                ptr::from_ref(&self.regex.raw_regex),
-                self.string.as_ptr().add(self.next_start),
-                1,
-                ptr::from_mut(&mut match_range),
-                0,
-            )
-        };
-        if exec_status == libc::REG_NOMATCH {
-            return None;
+    }
+}
+
+/// Iterates over the matches of a regex, returning the location of each
+/// subexpression. The first element is the location of the whole match.
+pub struct CapturesIter<'re> {
+    state: MatchState,
+    regex: &'re Regex,
+}
+
+impl Iterator for CapturesIter<'_> {
+    type Item = Vec<Option<RegexMatch>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.state.next_start > self.state.string.as_bytes().len() {
+                return None;
+            }
+            let captures = self
+                .regex
+                .regex
+                .captures_at(self.state.string.as_bytes(), self.state.next_start)?;
+            let found = captures[0].clone().expect("match without location");
+            if self.state.advance(&found) {
+                return Some(
+                    captures
+                        .into_iter()
+                        .map(|c| {
+                            c.map(|range| RegexMatch {
+                                start: range.start,
+                                end: range.end,
+                            })
+                        })
+                        .collect(),
+                );
+            }
         }
-        let result = RegexMatch {
-            start: self.next_start + match_range.rm_so as usize,
-            end: self.next_start + match_range.rm_eo as usize,
-        };
-        self.next_start += match_range.rm_eo as usize;
-        Some(result)
     }
 }
 
 impl Regex {
     pub fn new(regex: CString) -> Result<Self, String> {
-        let mut raw = unsafe { std::mem::zeroed::<libc::regex_t>() };
-        let compilation_status =
-            unsafe { libc::regcomp(ptr::from_mut(&mut raw), regex.as_ptr(), libc::REG_EXTENDED) };
-        regex_compilation_result(compilation_status, &raw)?;
+        let flags = RegexFlags {
+            extended: true,
+            ..Default::default()
+        };
+        let compiled =
+            plib::regex::Regex::new(regex.as_bytes(), flags).map_err(|e| e.to_string())?;
         Ok(Self {
-            raw_regex: raw,
+            regex: compiled,
             regex_string: regex,
         })
     }
 
-    pub fn match_locations(&self, string: CString) -> MatchIter {
+    pub fn match_locations(&self, string: CString) -> MatchIter<'_> {
         MatchIter {
-            next_start: 0,
+            state: MatchState {
+                string,
+                next_start: 0,
+                last_match_end: None,
+            },
             regex: self,
-            string,
         }
     }
 
-    pub fn matches(&self, string: &CString) -> bool {
-        let exec_status = unsafe {
-            libc::regexec(
-                ptr::from_ref(&self.raw_regex),
-                string.as_ptr(),
-                0,
-                ptr::null_mut(),
-                0,
-            )
-        };
-        exec_status != libc::REG_NOMATCH
+    pub fn captures(&self, string: CString) -> CapturesIter<'_> {
+        CapturesIter {
+            state: MatchState {
+                string,
+                next_start: 0,
+                last_match_end: None,
+            },
+            regex: self,
+        }
     }
-}
 
-impl Drop for Regex {
-    fn drop(&mut self) {
-        unsafe {
-            libc::regfree(ptr::from_mut(&mut self.raw_regex));
-        }
+    pub fn matches(&self, string: &CString) -> bool {
+        self.regex.is_match(string.as_bytes())
     }
 }
 
@@ -172,4 +203,19 @@ mod tests {
         assert_eq!(iter.next(), Some(RegexMatch { start: 24, end: 29 }));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn test_regex_match_locations_anchored_and_empty() {
+        let ere = regex_from_str("^a");
+        let mut iter = ere.match_locations(CString::new("aaa").unwrap());
+        assert_eq!(iter.next(), Some(RegexMatch { start: 0, end: 1 }));
+        assert_eq!(iter.next(), None);
+
+        let ere = regex_from_str("b*");
+        let locations: Vec<_> = ere
+            .match_locations(CString::new("abc").unwrap())
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(locations, vec![(0, 0), (1, 2), (3, 3)]);
+    }
 }
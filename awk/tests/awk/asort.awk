@@ -0,0 +1,28 @@
+function dump(array, n,    i) {
+    for (i = 1; i <= n; ++i) {
+        printf "%s ", array[i]
+    }
+    print ""
+}
+
+BEGIN {
+    a["x"] = 3
+    a["y"] = 1
+    a["z"] = "b"
+    a["w"] = 10
+    a["v"] = "a"
+
+    dump(b, asort(a, b))
+    dump(c, asorti(a, c))
+    dump(d, asort(a, d, "@val_num_desc"))
+    dump(e, asorti(a, e, "@ind_str_desc"))
+    print a["x"]
+
+    n = asort(a)
+    dump(a, n)
+    print ("x" in a)
+
+    m["k"] = "MiXeD"
+    m["n"] = 42
+    print tolower(m), m["k"], toupper(m), m["k"], m["n"]
+}
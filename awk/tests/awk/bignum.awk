@@ -0,0 +1,16 @@
+{
+    sum += $1
+    print $1 + 0, $1 + 1, $1 * $2, ($1 + 1 > $1)
+    printf "%d %x %i\n", $1, $1, -$1
+}
+
+END {
+    print sum
+    print 2 ^ 64, 2 ^ 64 - 1, -(2 ^ 63)
+    x = 18446744073709551615
+    x++
+    print x, x % 1000, int(x / 7)
+    print 1 / 4, 10 / 3
+    y = 9007199254740993
+    print y, y * 3, (y > 9007199254740992)
+}
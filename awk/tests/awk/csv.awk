@@ -0,0 +1,13 @@
+{
+    printf "%d:", NF
+    for (i = 1; i <= NF; ++i) {
+        printf " [%s]", $i
+    }
+    print ""
+}
+
+END {
+    FS = ";"
+    n = split("a,\"b,c\",d", parts)
+    print n, parts[2]
+}
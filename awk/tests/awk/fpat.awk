@@ -0,0 +1,16 @@
+BEGIN {
+    FPAT = "([^,]*)|(\"[^\"]+\")"
+}
+
+{
+    print NF
+    for (i = 1; i <= NF; ++i) {
+        printf "$%d = <%s>\n", i, $i
+    }
+    FS = ","
+    $0 = $0
+    print NF
+    FPAT = "[0-9]+"
+    $0 = $0
+    print NF, $1, $2
+}
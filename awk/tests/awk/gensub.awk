@@ -0,0 +1,13 @@
+BEGIN {
+    s = "hello world foo"
+    print gensub(/(o+)/, "[\\1]", "g", s)
+    print gensub(/o/, "0", 2, s)
+    print gensub(/(l+)(o)/, "\\2\\1&\\&", 1, "xllo")
+    print gensub(/x/, "y", "g", s)
+    print s
+}
+
+{
+    print gensub(/([a-z]+) ([a-z]+)/, "\\2 \\1", "G")
+    print $0
+}
@@ -0,0 +1,9 @@
+BEGIN {
+    SUBSEP = ":"
+    if (match("key=value;", /([a-z]+)=([a-z]+)/, m)) {
+        print m[0], m[1], m[2]
+        print m[2, "start"], m[2, "length"], RSTART, RLENGTH
+    }
+    print match("abc", /(x)?b/, m), length(m), ("1" in m)
+    print match("abc", /z/, m), length(m)
+}
@@ -0,0 +1,17 @@
+BEGIN {
+    print PROCINFO["platform"], PROCINFO["strftime"]
+    print (PROCINFO["pid"] > 0), ("version" in PROCINFO)
+
+    a["b"] = 2
+    a["c"] = 10
+    a["a"] = 3
+    PROCINFO["sorted_in"] = "@ind_str_asc"
+    for (k in a) printf "%s ", k
+    print ""
+    PROCINFO["sorted_in"] = "@val_num_desc"
+    for (k in a) printf "%s ", k
+    print ""
+    PROCINFO["sorted_in"] = "@val_str_asc"
+    for (k in a) printf "%s ", k
+    print ""
+}
@@ -0,0 +1,10 @@
+# count words
+function add(w) { count[w]++ }
+BEGIN { FS = " " }
+NF > 1 { for (i = 1; i <= NF; i++) add($i) }
+/skip/ { next }
+{ lines++ }
+END {
+    for (w in count) if (count[w] > 1) n++
+    print n, lines
+}
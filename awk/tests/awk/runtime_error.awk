@@ -0,0 +1,7 @@
+function g(a) {
+  a[1] = 2
+}
+$1 > 0 {
+  x = 1
+  g(x)
+}
@@ -0,0 +1,9 @@
+BEGIN {
+    print strftime("%Y-%m-%d %H:%M:%S", 86400 + 3661, 1)
+    t = mktime("2024 02 29 12 30 45", 1)
+    print t
+    print strftime("%d/%m/%Y %T", t, 1)
+    print mktime("not a date")
+    print (systime() >= t)
+    print strftime("", t)
+}
@@ -567,3 +567,170 @@ fn test_awk_modifying_nf_recomputes_the_record() {
         "tests/awk/test_data.txt"
     );
 }
+
+#[test]
+fn test_awk_gensub() {
+    test_awk!(gensub, "tests/awk/gensub.txt");
+}
+
+#[test]
+fn test_awk_asort() {
+    test_awk!(asort);
+}
+
+#[test]
+fn test_awk_match_array() {
+    test_awk!(match_array);
+}
+
+#[test]
+fn test_awk_time_functions() {
+    test_awk!(time_functions);
+}
+
+#[test]
+fn test_awk_procinfo() {
+    test_awk!(procinfo);
+}
+
+#[test]
+fn test_awk_posix_allows_extension_names_as_identifiers() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--posix".to_string(),
+            "function gensub(s) { return s \"!\" } BEGIN { systime = 1; print gensub(\"a\"), systime, length(PROCINFO) }".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::from("a! 1 0\n"),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_posix_disables_extensions() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--posix".to_string(),
+            "BEGIN { print strftime() }".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(
+            " --> 1:15\n  |\n1 | BEGIN { print strftime() }\n  |               ^--------^\n  |\n  = call to undefined function 'strftime'\n\n",
+        ),
+        expected_exit_code: 1,
+    })
+}
+
+#[test]
+fn test_awk_fpat() {
+    test_awk!(fpat, "tests/awk/fpat.txt");
+}
+
+#[test]
+fn test_awk_csv() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--csv".to_string(),
+            "-f".to_string(),
+            "tests/awk/csv.awk".to_string(),
+            "tests/awk/quoted.csv".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::from(include_str!("awk/csv.out")),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_bignum() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "-M".to_string(),
+            "-f".to_string(),
+            "tests/awk/bignum.awk".to_string(),
+            "tests/awk/bignum.txt".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::from(include_str!("awk/bignum.out")),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_pretty_print() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--pretty-print=-".to_string(),
+            "-f".to_string(),
+            "tests/awk/profile.awk".to_string(),
+            "tests/awk/profile.txt".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::from(include_str!("awk/pretty_print.out")),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_profile() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--profile=-".to_string(),
+            "-f".to_string(),
+            "tests/awk/profile.awk".to_string(),
+            "tests/awk/profile.txt".to_string(),
+        ],
+        stdin_data: String::new(),
+        expected_out: String::from(include_str!("awk/profile.out")),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    })
+}
+
+#[test]
+fn test_awk_runtime_error_location() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec!["-f".to_string(), "tests/awk/runtime_error.awk".to_string()],
+        stdin_data: String::from("5\n"),
+        expected_out: String::new(),
+        expected_err: String::from(
+            "awk: tests/awk/runtime_error.awk:2: scalar used in array context\n\
+             \tin function g called from tests/awk/runtime_error.awk:6\n\
+             \tin rule `$1 > 0`\n",
+        ),
+        expected_exit_code: 1,
+    })
+}
+
+#[test]
+fn test_awk_lint() {
+    run_test(TestPlan {
+        cmd: String::from("awk"),
+        args: vec![
+            "--lint".to_string(),
+            "-v".to_string(),
+            "limit=2".to_string(),
+            "function unused() { return 1 }\nNR <= limit { total += $1; print total, extra }"
+                .to_string(),
+        ],
+        stdin_data: String::from("1\n2\n3\n"),
+        expected_out: String::from("1 \n3 \n"),
+        expected_err: String::from(
+            "awk: cmd. line:1: warning: function `unused` is defined but never called\n\
+             awk: cmd. line:2: warning: variable `extra` is used but never assigned\n",
+        ),
+        expected_exit_code: 0,
+    })
+}
@@ -8,26 +8,40 @@
 //
 
 use std::ffi::OsString;
+use std::io::{self, BufRead, IsTerminal};
+use std::process::ExitCode;
 
 use bc_util::{
     interpreter::{ExecutionResult, Interpreter},
-    parser::parse_program,
+    parser::{parse_program, parse_program_part},
 };
 use clap::Parser;
 
-use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
 use plib::PROJECT_NAME;
-use rustyline::{error::ReadlineError, DefaultEditor, Result};
+use rustyline::{error::ReadlineError, DefaultEditor};
 
 mod bc_util;
 
+/// Name used in error messages for programs read from a non-interactive standard input
+const STDIN_NAME: &str = "(standard_in)";
+
 /// bc - arbitrary-precision arithmetic language
 #[derive(Parser)]
 #[command(version, about)]
 struct Args {
+    /// Define the math functions and set scale to 20
     #[arg(short = 'l')]
     define_math_functions: bool,
 
+    /// Only accept the POSIX bc language: programs must end with a newline
+    #[arg(short = 's', long = "standard")]
+    strict: bool,
+
+    /// Do not print the welcome message in interactive sessions
+    #[arg(short = 'q', long = "quiet")]
+    quiet: bool,
+
     files: Vec<OsString>,
 }
 
@@ -43,38 +57,64 @@ fn print_output_or_error(result: ExecutionResult<String>) {
     }
 }
 
-fn main() -> Result<()> {
-    setlocale(LocaleCategory::LcAll, "");
-    textdomain(PROJECT_NAME)?;
-    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
-
-    let args = Args::parse();
-    let mut interpreter = Interpreter::default();
-
-    if args.define_math_functions {
-        let lib = parse_program(include_str!("bc_util/math_functions.bc"), None)
-            .expect("error parsing standard math functions");
-        interpreter
-            .exec(lib)
-            .expect("error loading standard math functions");
+/// Parses and executes a whole program, reporting parse errors on the
+/// standard error. Returns `false` if the program could not be parsed.
+fn exec_source(interpreter: &mut Interpreter, text: &str, file: &str, strict: bool) -> bool {
+    let mut text = text.to_string();
+    // a program without a final newline is an extension
+    if !strict && !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
     }
+    match parse_program(&text, Some(file)) {
+        Ok(program) => {
+            print_output_or_error(interpreter.exec(program));
+            true
+        }
+        Err(e) => {
+            eprint!("{}", e);
+            false
+        }
+    }
+}
 
-    for file in args.files {
-        match std::fs::read_to_string(&file) {
-            Ok(s) => match parse_program(&s, file.to_str()) {
-                Ok(program) => print_output_or_error(interpreter.exec(program)),
-                Err(e) => println!("{}", e),
-            },
-            Err(_) => {
-                eprintln!("Could not read file: {}", file.to_string_lossy());
-                return Ok(());
+/// Reads the standard input one line at a time and executes each statement
+/// as soon as it is complete, so that a parse error only discards the
+/// statement it is in. Returns `false` if there were parse errors.
+fn run_stdin(interpreter: &mut Interpreter, strict: bool) -> io::Result<bool> {
+    let mut stdin = io::stdin().lock();
+    let mut success = true;
+    let mut text = String::new();
+    let mut first_line = 1;
+    while !interpreter.has_quit() {
+        let at_end = stdin.read_line(&mut text)? == 0 || !text.ends_with('\n');
+        if text.is_empty() {
+            break;
+        }
+        // a program without a final newline is an extension
+        if at_end && !strict && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        match parse_program_part(&text, STDIN_NAME, first_line, !at_end) {
+            Ok(program) => print_output_or_error(interpreter.exec(program)),
+            Err(e) if e.is_incomplete => continue,
+            Err(e) => {
+                eprint!("{}", e);
+                success = false;
             }
-        };
-        if interpreter.has_quit() {
-            return Ok(());
         }
+        if at_end {
+            break;
+        }
+        first_line += text.matches('\n').count();
+        text.clear();
     }
+    Ok(success)
+}
 
+fn run_interactive(interpreter: &mut Interpreter, quiet: bool) -> rustyline::Result<()> {
+    if !quiet {
+        println!("bc {}", env!("CARGO_PKG_VERSION"));
+    }
     let mut repl = DefaultEditor::new()?;
     let mut line_buffer = String::new();
     while !interpreter.has_quit() {
@@ -93,7 +133,7 @@ fn main() -> Result<()> {
                         line_buffer.clear();
                     }
                     Err(e) if !e.is_incomplete => {
-                        println!("{}", e);
+                        eprint!("{}", e);
                         line_buffer.clear();
                     }
                     _ => {}
@@ -116,3 +156,60 @@ fn main() -> Result<()> {
     }
     Ok(())
 }
+
+fn main() -> ExitCode {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME).unwrap();
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8").unwrap();
+
+    let args = Args::parse();
+    let mut interpreter = Interpreter::default();
+    let mut exit_code = ExitCode::SUCCESS;
+
+    if args.define_math_functions {
+        let lib = parse_program(include_str!("bc_util/math_functions.bc"), None)
+            .expect("error parsing standard math functions");
+        interpreter
+            .exec(lib)
+            .expect("error loading standard math functions");
+    }
+
+    for file in args.files {
+        match std::fs::read_to_string(&file) {
+            Ok(s) => {
+                if !exec_source(&mut interpreter, &s, &file.to_string_lossy(), args.strict) {
+                    exit_code = ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "bc: {} {}: {}",
+                    gettext("could not read file"),
+                    file.to_string_lossy(),
+                    e
+                );
+                return ExitCode::FAILURE;
+            }
+        };
+        if interpreter.has_quit() {
+            return exit_code;
+        }
+    }
+
+    if io::stdin().is_terminal() {
+        if let Err(e) = run_interactive(&mut interpreter, args.quiet) {
+            eprintln!("bc: {}", e);
+            return ExitCode::FAILURE;
+        }
+    } else {
+        match run_stdin(&mut interpreter, args.strict) {
+            Ok(true) => {}
+            Ok(false) => exit_code = ExitCode::FAILURE,
+            Err(e) => {
+                eprintln!("bc: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    exit_code
+}
@@ -9,7 +9,12 @@
 
 use std::rc::Rc;
 
-use pest::{error::InputLocation, iterators::Pair, pratt_parser::PrattParser, Parser, Position};
+use pest::{
+    error::{InputLocation, LineColLocation},
+    iterators::Pair,
+    pratt_parser::PrattParser,
+    Parser, Position,
+};
 
 use super::instructions::*;
 
@@ -450,12 +455,15 @@ fn is_incomplete(text: &str, error: &PestError) -> bool {
 }
 
 fn improve_pest_error(err: PestError, text: &str, file_path: Option<&str>) -> PestError {
-    let err = if let Some(path) = file_path {
+    let err = improve_pest_error_message(err, text);
+    if let Some(path) = file_path {
         err.with_path(path)
     } else {
         err
-    };
+    }
+}
 
+fn improve_pest_error_message(err: PestError, text: &str) -> PestError {
     // special case: if the error is at the end of the input and the last character
     // is not a newline, we can provide a more helpful error message.
     let err_loc = location_end(&err.location);
@@ -533,12 +541,17 @@ fn next_checkpoint(text: &str) -> usize {
     index
 }
 
-fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) -> ParseError {
+fn gather_errors(
+    text: &str,
+    file_path: Option<&str>,
+    allow_incomplete: bool,
+    first_error: PestError,
+) -> ParseError {
     let last_location_end = location_end(&first_error.location);
 
-    // if the text is not from a file and the error is consistent
+    // if more text may follow and the error is consistent
     // with an incomplete program we can return early
-    if file_path.is_none() && is_incomplete(text, &first_error) {
+    if allow_incomplete && is_incomplete(text, &first_error) {
         return ParseError {
             errors: vec![first_error],
             is_incomplete: true,
@@ -572,9 +585,58 @@ fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) ->
 }
 
 pub fn parse_program(text: &str, file_path: Option<&str>) -> Result<Program, ParseError> {
+    parse(text, file_path, file_path.is_none())
+}
+
+/// Parses the text of a program read so far, which starts on line
+/// `first_line` of `file_path`. If `more_text` is `true`, errors that the
+/// text still to be read could fix make the program incomplete.
+pub fn parse_program_part(
+    text: &str,
+    file_path: &str,
+    first_line: usize,
+    more_text: bool,
+) -> Result<Program, ParseError> {
+    let lines = first_line - 1;
+    let shift = |line_col| match line_col {
+        LineColLocation::Pos((line, col)) => LineColLocation::Pos((line + lines, col)),
+        LineColLocation::Span((line1, col1), (line2, col2)) => {
+            LineColLocation::Span((line1 + lines, col1), (line2 + lines, col2))
+        }
+    };
+    match parse(text, Some(file_path), more_text) {
+        Ok(mut program) => {
+            for line in &mut program.source_locations {
+                *line += lines;
+            }
+            for instruction in &mut program.instructions {
+                if let StmtInstruction::DefineFunction { function, .. } = instruction {
+                    function.source_locations = function
+                        .source_locations
+                        .iter()
+                        .map(|line| line + lines)
+                        .collect();
+                }
+            }
+            Ok(program)
+        }
+        Err(mut e) => {
+            for err in &mut e.errors {
+                err.line_col = shift(err.line_col.clone());
+            }
+            Err(e)
+        }
+    }
+}
+
+fn parse(
+    text: &str,
+    file_path: Option<&str>,
+    allow_incomplete: bool,
+) -> Result<Program, ParseError> {
     let program = match BcParser::parse(Rule::program, text) {
         Ok(mut program) => program.next().unwrap(),
-        Err(err) => return Err(gather_errors(text, file_path, err)),
+        Err(err) => return Err(gather_errors(text, file_path, allow_incomplete, err)),
     };
     let file = Rc::<str>::from(file_path.unwrap_or(""));
     let mut instructions = Vec::new();
@@ -1561,4 +1623,29 @@ mod test {
             .expect("error parsing program");
         assert_eq!(program.source_locations, [1, 2, 3, 6]);
     }
+
+    #[test]
+    fn test_program_part_counts_lines_from_first_line() {
+        let program =
+            parse_program_part("1\n2\n", "file.bc", 5, true).expect("error parsing program");
+        assert_eq!(program.source_locations, [5, 6]);
+        let program = parse_program_part("define f() {\n return 1\n}\n", "file.bc", 3, true)
+            .expect("error parsing program");
+        let StmtInstruction::DefineFunction { function, .. } = &program.instructions[0] else {
+            panic!("expected function definition")
+        };
+        assert_eq!(function.source_locations[0], 4);
+    }
+
+    #[test]
+    fn test_program_part_is_incomplete_only_with_more_text() {
+        assert!(
+            parse_program_part("define f() {\n", "file.bc", 1, true)
+                .unwrap_err()
+                .is_incomplete
+        );
+        let err = parse_program_part("define f() {\n", "file.bc", 2, false).unwrap_err();
+        assert!(!err.is_incomplete);
+        assert!(err.to_string().starts_with(" --> file.bc:3:1"));
+    }
 }
@@ -0,0 +1,129 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use std::ffi::OsString;
+use std::io::{self, BufRead};
+use std::process::ExitCode;
+
+use clap::Parser;
+use dc_util::interpreter::Interpreter;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::PROJECT_NAME;
+
+mod dc_util;
+// the number type is shared with bc, which uses all of its interface
+#[allow(dead_code)]
+#[path = "bc_util/number.rs"]
+mod number;
+
+/// dc - desk calculator
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Execute the given commands
+    #[arg(short = 'e', long = "expression", action = clap::ArgAction::Append)]
+    expressions: Vec<String>,
+
+    /// Execute the commands in the given file
+    #[arg(short = 'f', long = "file", action = clap::ArgAction::Append)]
+    script_files: Vec<OsString>,
+
+    /// Files containing commands, executed after the expressions and script files
+    files: Vec<OsString>,
+}
+
+/// Returns true if all the strings in `code` are terminated
+fn is_complete(code: &[u8]) -> bool {
+    let mut depth = 0;
+    let mut in_comment = false;
+    for &c in code {
+        match c {
+            b'\n' => in_comment = false,
+            b'#' if depth == 0 => in_comment = true,
+            b'[' if !in_comment => depth += 1,
+            b']' if !in_comment && depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+fn main() -> ExitCode {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME).unwrap();
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8").unwrap();
+
+    let args = Args::parse();
+    let mut interpreter = Interpreter::default();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout().lock();
+    let mut exit_code = ExitCode::SUCCESS;
+
+    let mut run = |interpreter: &mut Interpreter, code: &[u8], input: &mut dyn BufRead| {
+        if let Err(e) = interpreter.execute(code, input, &mut output) {
+            eprintln!("dc: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    for expression in &args.expressions {
+        run(&mut interpreter, expression.as_bytes(), &mut input);
+        if interpreter.has_quit() {
+            return exit_code;
+        }
+    }
+
+    let read_from_stdin =
+        args.expressions.is_empty() && args.script_files.is_empty() && args.files.is_empty();
+    for file in args.script_files.iter().chain(&args.files) {
+        match std::fs::read(file) {
+            Ok(code) => run(&mut interpreter, &code, &mut input),
+            Err(e) => {
+                eprintln!(
+                    "dc: {} '{}': {}",
+                    gettext("could not read file"),
+                    file.to_string_lossy(),
+                    e
+                );
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+        if interpreter.has_quit() {
+            return exit_code;
+        }
+    }
+
+    if read_from_stdin {
+        // the program is executed line by line, so that the `?` command
+        // reads the lines following the one being executed
+        let mut code = Vec::new();
+        loop {
+            match input.read_until(b'\n', &mut code) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("dc: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            if is_complete(&code) {
+                run(&mut interpreter, &code, &mut input);
+                code.clear();
+                if interpreter.has_quit() {
+                    break;
+                }
+            }
+        }
+        if !code.is_empty() {
+            run(&mut interpreter, &code, &mut input);
+        }
+    }
+    exit_code
+}
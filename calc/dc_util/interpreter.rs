@@ -0,0 +1,680 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::number::Number;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(Number),
+    String(Rc<[u8]>),
+}
+
+impl Value {
+    fn number(self) -> Result<Number, String> {
+        match self {
+            Value::Number(n) => Ok(n),
+            Value::String(_) => Err("non-numeric value".to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RegisterEntry {
+    value: Option<Value>,
+    array: HashMap<u64, Value>,
+}
+
+/// A register is a stack of values, each with its own array
+#[derive(Default)]
+struct Register {
+    stack: Vec<RegisterEntry>,
+}
+
+impl Register {
+    fn top(&mut self) -> &mut RegisterEntry {
+        if self.stack.is_empty() {
+            self.stack.push(RegisterEntry::default());
+        }
+        self.stack.last_mut().unwrap()
+    }
+}
+
+/// A string being executed, either the program text or a macro
+struct Frame {
+    code: Rc<[u8]>,
+    pos: usize,
+}
+
+impl Frame {
+    fn is_finished(&self) -> bool {
+        self.code[self.pos..]
+            .iter()
+            .all(|c| c.is_ascii_whitespace())
+    }
+}
+
+fn register_display(name: u8) -> String {
+    format!("register '{}' ({:04o})", name as char, name)
+}
+
+fn is_number_start(c: u8) -> bool {
+    matches!(c, b'0'..=b'9' | b'A'..=b'F' | b'.' | b'_')
+}
+
+pub struct Interpreter {
+    stack: Vec<Value>,
+    registers: Vec<Register>,
+    scale: u64,
+    ibase: u64,
+    obase: u64,
+    has_quit: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self {
+            stack: Vec::new(),
+            registers: (0..256).map(|_| Register::default()).collect(),
+            scale: 0,
+            ibase: 10,
+            obase: 10,
+            has_quit: false,
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn has_quit(&self) -> bool {
+        self.has_quit
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), String> {
+        if self.stack.len() < depth {
+            Err("stack empty".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value, String> {
+        self.stack.pop().ok_or_else(|| "stack empty".to_string())
+    }
+
+    fn pop_number(&mut self) -> Result<Number, String> {
+        self.check_depth(1)?;
+        if let Some(Value::String(_)) = self.stack.last() {
+            return Err("non-numeric value".to_string());
+        }
+        self.pop()?.number()
+    }
+
+    /// Pops the two numbers on top of the stack, returning the one
+    /// below the top first
+    fn pop_two_numbers(&mut self) -> Result<(Number, Number), String> {
+        self.check_depth(2)?;
+        let len = self.stack.len();
+        if self.stack[len - 2..]
+            .iter()
+            .any(|v| matches!(v, Value::String(_)))
+        {
+            return Err("non-numeric value".to_string());
+        }
+        let rhs = self.pop()?.number()?;
+        let lhs = self.pop()?.number()?;
+        Ok((lhs, rhs))
+    }
+
+    fn pop_u64(&mut self, error: &str) -> Result<u64, String> {
+        let value = self.pop_number()?;
+        value.as_u64().ok_or_else(|| error.to_string())
+    }
+
+    fn binary_op(
+        &mut self,
+        op: impl FnOnce(Number, &Number, u64) -> Result<Number, &'static str>,
+    ) -> Result<(), String> {
+        let (lhs, rhs) = self.pop_two_numbers()?;
+        match op(lhs.clone(), &rhs, self.scale) {
+            Ok(result) => {
+                self.stack.push(Value::Number(result));
+                Ok(())
+            }
+            Err(e) => {
+                // leave the operands on the stack
+                self.stack.push(Value::Number(lhs));
+                self.stack.push(Value::Number(rhs));
+                Err(e.to_string())
+            }
+        }
+    }
+
+    fn format_value(&self, value: &Value) -> Vec<u8> {
+        match value {
+            Value::Number(n) => n.to_string(self.obase).into_bytes(),
+            Value::String(s) => s.to_vec(),
+        }
+    }
+
+    /// Computes `base ^ exponent mod modulus` for integers
+    fn modular_exponentiation(
+        &mut self,
+        base: Number,
+        exponent: Number,
+        modulus: Number,
+    ) -> Result<Number, String> {
+        if base.scale() != 0 || exponent.scale() != 0 || modulus.scale() != 0 {
+            return Err("modular exponentiation of non-integer".to_string());
+        }
+        if exponent < Number::zero() {
+            return Err("negative exponent".to_string());
+        }
+        let two = Number::from(2);
+        let mut result = Number::from(1).modulus(&modulus, 0)?;
+        let mut base = base.modulus(&modulus, 0)?;
+        let mut exponent = exponent;
+        while !exponent.is_zero() {
+            if !exponent.clone().modulus(&two, 0)?.is_zero() {
+                result = result.mul(&base, 0).modulus(&modulus, 0)?;
+            }
+            exponent = exponent.div(&two, 0)?;
+            base = base.clone().mul(&base, 0).modulus(&modulus, 0)?;
+        }
+        Ok(result)
+    }
+
+    fn parse_number(&self, frame: &mut Frame, first: u8) -> Result<Number, String> {
+        let negative = first == b'_';
+        let mut digits = String::new();
+        if !negative {
+            digits.push(first as char);
+        }
+        let mut seen_point = first == b'.';
+        while let Some(&c) = frame.code.get(frame.pos) {
+            match c {
+                b'0'..=b'9' | b'A'..=b'F' => digits.push(c as char),
+                b'.' if !seen_point => {
+                    seen_point = true;
+                    digits.push('.');
+                }
+                _ => break,
+            }
+            frame.pos += 1;
+        }
+        if digits.is_empty() || digits == "." {
+            return Ok(Number::zero());
+        }
+        let number =
+            Number::parse(&digits, self.ibase).ok_or_else(|| "digit out of range".to_string())?;
+        Ok(if negative { number.negate() } else { number })
+    }
+
+    fn next_byte(frames: &mut [Frame]) -> Option<u8> {
+        let frame = frames.last_mut()?;
+        let c = frame.code.get(frame.pos).copied();
+        if c.is_some() {
+            frame.pos += 1;
+        }
+        c
+    }
+
+    fn register_name(frames: &mut [Frame]) -> Result<u8, String> {
+        Self::next_byte(frames).ok_or_else(|| "register name expected".to_string())
+    }
+
+    /// Pushes a macro frame, removing the current one if it has nothing left
+    /// to execute so that tail-recursive macros run in constant space
+    fn execute_macro(frames: &mut Vec<Frame>, code: Rc<[u8]>) {
+        if frames.last().is_some_and(Frame::is_finished) {
+            frames.pop();
+        }
+        frames.push(Frame { code, pos: 0 });
+    }
+
+    fn execute_value(&mut self, frames: &mut Vec<Frame>, value: Value) {
+        match value {
+            Value::String(code) => Self::execute_macro(frames, code),
+            number => self.stack.push(number),
+        }
+    }
+
+    fn conditional(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        op: u8,
+        negated: bool,
+    ) -> Result<(), String> {
+        let register = Self::register_name(frames)?;
+        let (lhs, rhs) = self.pop_two_numbers()?;
+        // the original top of the stack is compared to the value below it
+        let condition = match op {
+            b'<' => rhs < lhs,
+            b'>' => rhs > lhs,
+            _ => rhs == lhs,
+        };
+        if condition != negated {
+            let value = self.registers[register as usize]
+                .stack
+                .last()
+                .and_then(|entry| entry.value.clone())
+                .ok_or_else(|| format!("{} is empty", register_display(register)))?;
+            self.execute_value(frames, value);
+        }
+        Ok(())
+    }
+
+    fn print_stream(&mut self, output: &mut dyn Write) -> Result<(), String> {
+        let bytes = match self.pop()? {
+            Value::String(s) => s.to_vec(),
+            Value::Number(n) => {
+                // the integer part is printed as a base 256 number
+                let mut n = n.div(&Number::from(1), 0)?;
+                if n < Number::zero() {
+                    n = n.negate();
+                }
+                let mut bytes = Vec::new();
+                let byte_base = Number::from(256);
+                while !n.is_zero() {
+                    let digit = n.clone().modulus(&byte_base, 0)?;
+                    bytes.push(digit.as_u64().unwrap() as u8);
+                    n = n.div(&byte_base, 0)?;
+                }
+                bytes.reverse();
+                bytes
+            }
+        };
+        output.write_all(&bytes).map_err(|e| e.to_string())
+    }
+
+    fn convert_to_string(&mut self) -> Result<(), String> {
+        let value = self.pop()?;
+        let string: Rc<[u8]> = match value {
+            Value::Number(n) => {
+                let n = n.div(&Number::from(1), 0)?;
+                let byte = n.modulus(&Number::from(256), 0)?;
+                let byte = if byte < Number::zero() {
+                    byte.add(&Number::from(256))
+                } else {
+                    byte
+                };
+                Rc::from([byte.as_u64().unwrap() as u8].as_slice())
+            }
+            Value::String(s) => Rc::from(&s[..s.len().min(1)]),
+        };
+        self.stack.push(Value::String(string));
+        Ok(())
+    }
+
+    fn run_shell_command(frames: &mut [Frame], output: &mut dyn Write) -> Result<(), String> {
+        let frame = frames.last_mut().unwrap();
+        let end = frame.code[frame.pos..]
+            .iter()
+            .position(|c| *c == b'\n')
+            .map(|i| frame.pos + i)
+            .unwrap_or(frame.code.len());
+        let command = String::from_utf8_lossy(&frame.code[frame.pos..end]).into_owned();
+        frame.pos = end;
+        output.flush().map_err(|e| e.to_string())?;
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn command(
+        &mut self,
+        c: u8,
+        frames: &mut Vec<Frame>,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<(), String> {
+        match c {
+            b' ' | b'\t' | b'\n' | b'\r' => {}
+            c if is_number_start(c) => {
+                let number = self.parse_number(frames.last_mut().unwrap(), c)?;
+                self.stack.push(Value::Number(number));
+            }
+            b'[' => {
+                let frame = frames.last_mut().unwrap();
+                let start = frame.pos;
+                let mut depth = 1;
+                while depth > 0 {
+                    match frame.code.get(frame.pos) {
+                        Some(b'[') => depth += 1,
+                        Some(b']') => depth -= 1,
+                        Some(_) => {}
+                        None => return Err("unterminated string".to_string()),
+                    }
+                    frame.pos += 1;
+                }
+                let string = Rc::from(&frame.code[start..frame.pos - 1]);
+                self.stack.push(Value::String(string));
+            }
+            b'#' => {
+                let frame = frames.last_mut().unwrap();
+                while frame.pos < frame.code.len() && frame.code[frame.pos] != b'\n' {
+                    frame.pos += 1;
+                }
+            }
+            b'+' => self.binary_op(|a, b, _| Ok(a.add(b)))?,
+            b'-' => self.binary_op(|a, b, _| Ok(a.sub(b)))?,
+            b'*' => self.binary_op(|a, b, scale| Ok(a.mul(b, scale)))?,
+            b'/' => self.binary_op(Number::div)?,
+            b'%' => self.binary_op(Number::modulus)?,
+            b'^' => self.binary_op(Number::pow)?,
+            b'~' => {
+                let (lhs, rhs) = self.pop_two_numbers()?;
+                let quotient = lhs.clone().div(&rhs, self.scale);
+                let remainder = lhs.clone().modulus(&rhs, self.scale);
+                match (quotient, remainder) {
+                    (Ok(quotient), Ok(remainder)) => {
+                        self.stack.push(Value::Number(quotient));
+                        self.stack.push(Value::Number(remainder));
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        self.stack.push(Value::Number(lhs));
+                        self.stack.push(Value::Number(rhs));
+                        return Err(e.to_string());
+                    }
+                }
+            }
+            b'|' => {
+                self.check_depth(3)?;
+                let (exponent, modulus) = self.pop_two_numbers()?;
+                let base = self.pop_number()?;
+                let result =
+                    self.modular_exponentiation(base.clone(), exponent.clone(), modulus.clone());
+                match result {
+                    Ok(result) => self.stack.push(Value::Number(result)),
+                    Err(e) => {
+                        self.stack.push(Value::Number(base));
+                        self.stack.push(Value::Number(exponent));
+                        self.stack.push(Value::Number(modulus));
+                        return Err(e);
+                    }
+                }
+            }
+            b'v' => {
+                let value = self.pop_number()?;
+                match value.clone().sqrt(self.scale) {
+                    Ok(result) => self.stack.push(Value::Number(result)),
+                    Err(e) => {
+                        self.stack.push(Value::Number(value));
+                        return Err(e.to_string());
+                    }
+                }
+            }
+            b'p' => {
+                let value = self.stack.last().ok_or("stack empty")?;
+                let mut text = self.format_value(value);
+                text.push(b'\n');
+                output.write_all(&text).map_err(|e| e.to_string())?;
+            }
+            b'n' => {
+                let value = self.pop()?;
+                let text = self.format_value(&value);
+                output.write_all(&text).map_err(|e| e.to_string())?;
+            }
+            b'P' => self.print_stream(output)?,
+            b'f' => {
+                for value in self.stack.iter().rev() {
+                    let mut text = self.format_value(value);
+                    text.push(b'\n');
+                    output.write_all(&text).map_err(|e| e.to_string())?;
+                }
+            }
+            b'c' => self.stack.clear(),
+            b'd' => {
+                let value = self.stack.last().ok_or("stack empty")?.clone();
+                self.stack.push(value);
+            }
+            b'r' => {
+                self.check_depth(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - 2);
+            }
+            b'z' => self
+                .stack
+                .push(Value::Number(Number::from(self.stack.len() as u64))),
+            b'Z' => {
+                let length = match self.pop()? {
+                    Value::Number(n) => n.length(),
+                    Value::String(s) => s.len() as u64,
+                };
+                self.stack.push(Value::Number(Number::from(length)));
+            }
+            b'X' => {
+                let scale = match self.pop()? {
+                    Value::Number(n) => n.scale(),
+                    Value::String(_) => 0,
+                };
+                self.stack.push(Value::Number(Number::from(scale)));
+            }
+            b'a' => self.convert_to_string()?,
+            b'i' => {
+                let base = self.pop_u64("input base must be a number between 2 and 16")?;
+                if !(2..=16).contains(&base) {
+                    return Err("input base must be a number between 2 and 16".to_string());
+                }
+                self.ibase = base;
+            }
+            b'o' => {
+                let base = self.pop_u64("output base must be a number greater than 1")?;
+                if base < 2 {
+                    return Err("output base must be a number greater than 1".to_string());
+                }
+                self.obase = base;
+            }
+            b'k' => self.scale = self.pop_u64("scale must be a nonnegative number")?,
+            b'I' => self.stack.push(Value::Number(Number::from(self.ibase))),
+            b'O' => self.stack.push(Value::Number(Number::from(self.obase))),
+            b'K' => self.stack.push(Value::Number(Number::from(self.scale))),
+            b's' => {
+                let register = Self::register_name(frames)?;
+                let value = self.pop()?;
+                self.registers[register as usize].top().value = Some(value);
+            }
+            b'l' => {
+                let register = Self::register_name(frames)?;
+                let value = self.registers[register as usize]
+                    .stack
+                    .last()
+                    .map(|entry| entry.value.clone().unwrap_or(Value::Number(Number::zero())))
+                    .ok_or_else(|| format!("{} is empty", register_display(register)))?;
+                self.stack.push(value);
+            }
+            b'S' => {
+                let register = Self::register_name(frames)?;
+                let value = self.pop()?;
+                self.registers[register as usize].stack.push(RegisterEntry {
+                    value: Some(value),
+                    array: HashMap::new(),
+                });
+            }
+            b'L' => {
+                let register = Self::register_name(frames)?;
+                let entry = self.registers[register as usize]
+                    .stack
+                    .pop()
+                    .ok_or_else(|| format!("stack {} is empty", register_display(register)))?;
+                self.stack
+                    .push(entry.value.unwrap_or(Value::Number(Number::zero())));
+            }
+            b':' => {
+                let register = Self::register_name(frames)?;
+                self.check_depth(2)?;
+                let index = self.pop_u64("array index must be a nonnegative integer")?;
+                let value = self.pop()?;
+                self.registers[register as usize]
+                    .top()
+                    .array
+                    .insert(index, value);
+            }
+            b';' => {
+                let register = Self::register_name(frames)?;
+                let index = self.pop_u64("array index must be a nonnegative integer")?;
+                let value = self.registers[register as usize]
+                    .top()
+                    .array
+                    .get(&index)
+                    .cloned()
+                    .unwrap_or(Value::Number(Number::zero()));
+                self.stack.push(value);
+            }
+            b'x' => {
+                let value = self.pop()?;
+                self.execute_value(frames, value);
+            }
+            b'<' | b'>' | b'=' => self.conditional(frames, c, false)?,
+            b'!' => match frames.last().and_then(|f| f.code.get(f.pos)).copied() {
+                Some(op @ (b'<' | b'>' | b'=')) => {
+                    frames.last_mut().unwrap().pos += 1;
+                    self.conditional(frames, op, true)?;
+                }
+                _ => Self::run_shell_command(frames, output)?,
+            },
+            b'?' => {
+                let mut line = Vec::new();
+                input
+                    .read_until(b'\n', &mut line)
+                    .map_err(|e| e.to_string())?;
+                Self::execute_macro(frames, Rc::from(line));
+            }
+            b'q' => {
+                if frames.len() <= 2 {
+                    self.has_quit = true;
+                } else {
+                    frames.truncate(frames.len() - 2);
+                }
+            }
+            b'Q' => {
+                let levels = self.pop_u64("Q command requires a number >= 1")?;
+                // Q never exits the top level
+                let levels = (levels as usize).min(frames.len().saturating_sub(1));
+                frames.truncate(frames.len() - levels);
+            }
+            c => return Err(format!("'{}' ({:04o}) unimplemented", c.escape_ascii(), c)),
+        }
+        Ok(())
+    }
+
+    /// Executes `code`. Lines read by the `?` command come from `input`.
+    /// Errors are reported on the standard error and do not stop the execution.
+    pub fn execute(
+        &mut self,
+        code: &[u8],
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut frames = vec![Frame {
+            code: Rc::from(code),
+            pos: 0,
+        }];
+        while let Some(frame) = frames.last_mut() {
+            let Some(&c) = frame.code.get(frame.pos) else {
+                frames.pop();
+                continue;
+            };
+            frame.pos += 1;
+            if let Err(message) = self.command(c, &mut frames, input, output) {
+                output.flush()?;
+                eprintln!("dc: {message}");
+            }
+            if self.has_quit {
+                break;
+            }
+        }
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &str) -> String {
+        let mut interpreter = Interpreter::default();
+        let mut output = Vec::new();
+        interpreter
+            .execute(code.as_bytes(), &mut io::empty(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run("2 3+p"), "5\n");
+        assert_eq!(run("2 3-p"), "-1\n");
+        assert_eq!(run("_2 3*p"), "-6\n");
+        assert_eq!(run("7 2/p 2k 7 2/p"), "3\n3.50\n");
+        assert_eq!(run("7 3%p"), "1\n");
+        assert_eq!(run("7 3~f"), "1\n2\n");
+        assert_eq!(run("2 10^p"), "1024\n");
+        assert_eq!(run("4 13 497|p"), "445\n");
+        assert_eq!(run("3k 2vp"), "1.414\n");
+    }
+
+    #[test]
+    fn test_stack_commands() {
+        assert_eq!(run("1 2 3f"), "3\n2\n1\n");
+        assert_eq!(run("1 2rf"), "1\n2\n");
+        assert_eq!(run("1dzp"), "2\n");
+        assert_eq!(run("1 2cz p"), "0\n");
+        assert_eq!(run("1 2 n p"), "21\n");
+    }
+
+    #[test]
+    fn test_registers() {
+        assert_eq!(run("5sa la la+p"), "10\n");
+        assert_eq!(run("1Sa 2Sa la p Lap Lap"), "2\n2\n1\n");
+        assert_eq!(run("10 1:a 20 2:a 1;a 2;a 3;a f"), "0\n20\n10\n");
+    }
+
+    #[test]
+    fn test_bases() {
+        assert_eq!(run("16i FF p"), "255\n");
+        assert_eq!(run("2o 10p"), "1010\n");
+        assert_eq!(run("16o 255p Ip Op"), "FF\nA\n10\n");
+        assert_eq!(run("5k Kp"), "5\n");
+    }
+
+    #[test]
+    fn test_strings_and_macros() {
+        assert_eq!(run("[hello]p"), "hello\n");
+        assert_eq!(run("[1 2+]xp"), "3\n");
+        assert_eq!(run("[[nested]]p"), "[nested]\n");
+        assert_eq!(run("65aP 10aP"), "A\n");
+        assert_eq!(run("[abc]Zp 1.25Zp 1.25Xp"), "3\n3\n2\n");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        assert_eq!(run("[[yes]p]sa 1 2>a"), "yes\n");
+        assert_eq!(run("[[yes]p]sa 2 1>a"), "");
+        assert_eq!(run("[[yes]p]sa 2 1!>a"), "yes\n");
+        assert_eq!(run("[[yes]p]sa 2 2=a"), "yes\n");
+    }
+
+    #[test]
+    fn test_tail_recursive_loop() {
+        // counts down from 100000 without exhausting the native stack
+        assert_eq!(run("100000sn [ln1-dsn0<l]sl llx lnp"), "0\n");
+    }
+
+    #[test]
+    fn test_quit() {
+        assert_eq!(run("1p q 2p"), "1\n");
+        assert_eq!(run("[[1p 2Q 3p]x 4p]x 5p"), "1\n5\n");
+    }
+}
@@ -8,8 +8,8 @@
 //
 
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::regex::{Regex, RegexFlags};
 use plib::PROJECT_NAME;
-use regex::Regex;
 
 #[derive(Clone, Debug, PartialEq)]
 enum Token {
@@ -239,35 +239,35 @@ fn logop(lhs: &Token, rhs: &Token, is_and: bool) -> Token {
     }
 }
 
-// regex match operation
+// regex match operation: a BRE anchored at the start of the string
 fn matchop(lhs: &Token, rhs: &Token) -> Result<Token, &'static str> {
     let lhs = token_to_string(lhs)?;
     let rhs = token_to_string(rhs)?;
 
-    let re = match Regex::new(&rhs) {
+    let re = match Regex::new(&rhs, RegexFlags::default()) {
         Ok(re_res) => re_res,
         Err(_) => {
             return Err("invalid regex");
         }
     };
 
-    match re.captures(&lhs) {
-        // no regex match: zero
-        None => Ok(Token::Integer(0)),
-
-        // regex matched
-        Some(caps) => {
-            let cap1 = caps.get(1);
-
-            // if regex subexpression #1 matched, return as string
-            if let Some(mtch) = cap1 {
-                Ok(Token::Str(String::from(mtch.as_str())))
-
-            // otherwise, return length of overall match as int
-            } else {
-                Ok(Token::Integer(caps.get(0).unwrap().len() as i64))
-            }
-        }
+    let caps = re
+        .captures(lhs.as_bytes())
+        .filter(|caps| caps[0].as_ref().is_some_and(|whole| whole.start == 0));
+
+    if re.subexpressions() > 0 {
+        // with a subexpression, return the string it matched, or null
+        let text = caps
+            .and_then(|caps| caps[1].clone())
+            .map(|range| String::from_utf8_lossy(&lhs.as_bytes()[range]).into_owned())
+            .unwrap_or_default();
+        Ok(Token::Str(text))
+    } else {
+        // otherwise, return the number of characters matched, or zero
+        let len = caps
+            .and_then(|caps| caps[0].clone())
+            .map_or(0, |range| lhs[range].chars().count());
+        Ok(Token::Integer(len as i64))
     }
 }
 
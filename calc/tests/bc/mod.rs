@@ -293,3 +293,41 @@ fn test_bc_sin_to_scale_18() {
 fn test_bc_cos_to_scale_18() {
     test_bc_l!(cos_to_scale_18)
 }
+
+#[test]
+fn test_bc_program_from_pipe_without_quit() {
+    test_bc("2 + 2\n", "4\n");
+}
+
+#[test]
+fn test_bc_missing_final_newline_is_accepted() {
+    test_bc("2 + 2", "4\n");
+}
+
+#[test]
+fn test_bc_strict_mode_requires_final_newline() {
+    run_test(TestPlan {
+        cmd: String::from("bc"),
+        args: vec!["-s".to_string()],
+        stdin_data: String::from("2 + 2"),
+        expected_out: String::from(""),
+        expected_err: String::from(
+            " --> (standard_in):1:6\n  |\n1 | 2 + 2\n  |      ^---\n  |\n  = missing newline\n\n",
+        ),
+        expected_exit_code: 1,
+    });
+}
+
+#[test]
+fn test_bc_parse_error_is_reported_with_location() {
+    run_test(TestPlan {
+        cmd: String::from("bc"),
+        args: vec!["-q".to_string()],
+        stdin_data: String::from("1 + 1\n2 +\n"),
+        expected_out: String::from("2\n"),
+        expected_err: String::from(
+            " --> (standard_in):2:4\n  |\n2 | 2 +␊\n  |    ^---\n  |\n  = expected expression\n\n",
+        ),
+        expected_exit_code: 1,
+    });
+}
@@ -8,4 +8,5 @@
 //
 
 mod bc;
+mod dc;
 mod expr;
@@ -0,0 +1,69 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, TestPlan};
+
+fn test_dc(args: &[&str], program: &str, expected_output: &str) {
+    run_test(TestPlan {
+        cmd: String::from("dc"),
+        args: args.iter().map(|s| s.to_string()).collect(),
+        stdin_data: program.to_string(),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code: 0,
+    });
+}
+
+macro_rules! test_dc {
+    ($test_name:ident) => {
+        test_dc(
+            &[],
+            include_str!(concat!("./", stringify!($test_name), ".dc")),
+            include_str!(concat!("./", stringify!($test_name), ".out")),
+        )
+    };
+}
+
+#[test]
+fn test_factorial() {
+    test_dc!(factorial);
+}
+
+#[test]
+fn test_fibonacci() {
+    test_dc!(fibonacci);
+}
+
+#[test]
+fn test_expression_argument() {
+    test_dc(&["-e", "2 3+p", "-e", "4*p"], "", "5\n20\n");
+}
+
+#[test]
+fn test_read_input() {
+    // `?` reads the line after the one being executed
+    test_dc(&[], "?2*p\n21\n", "42\n");
+}
+
+#[test]
+fn test_multiline_string() {
+    test_dc(&[], "[first\nsecond]p\n", "first\nsecond\n");
+}
+
+#[test]
+fn test_error_does_not_stop_execution() {
+    run_test(TestPlan {
+        cmd: String::from("dc"),
+        args: vec![],
+        stdin_data: String::from("1 0/ p\n"),
+        expected_out: String::from("0\n"),
+        expected_err: String::from("dc: division by zero\n"),
+        expected_exit_code: 0,
+    });
+}
@@ -59,3 +59,11 @@ fn expr_cmpstr() {
     expr_test(&["aaa", "=", "bbb", "+", "1"], "1\n");
     expr_test(&["aaa", "!=", "bbb", "+", "1"], "2\n");
 }
+
+#[test]
+fn expr_match() {
+    expr_test(&["abcd", ":", "ab*c"], "3\n");
+    expr_test(&["abcabc", ":", "a\\(b*\\)c"], "b\n");
+    expr_test(&["aa-aa", ":", "\\(a*\\)-\\1"], "aa\n");
+    expr_test(&["déjà", ":", ".*"], "4\n");
+}
@@ -7,13 +7,14 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod archive;
+
+use archive::{ArResult, Archive, ArchiveMember, MEMBER_HEADER_SIZE};
 use chrono::DateTime;
 use clap::{Parser, Subcommand};
-use object::{Object, ObjectSymbol, SymbolKind};
 use std::ffi::{OsStr, OsString};
 use std::io::{stdout, Write};
-use std::os::unix::ffi::{OsStrExt, OsStringExt};
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
 #[derive(clap::Args)]
@@ -163,61 +164,9 @@ struct Args {
     command: Commands,
 }
 
-const MEMBER_HEADER_SIZE: u64 = 60;
 const DATE_FORMAT: &str = "%b %e %H:%M %Y";
 
-type ArResult<T> = Result<T, Box<dyn std::error::Error>>;
-
-#[derive(Default)]
-struct ArchiveMember {
-    name: OsString,
-    date: u64,
-    uid: u64,
-    gid: u64,
-    mode: u64,
-    size: u64,
-    data: Vec<u8>,
-    symbols: Vec<String>,
-    symbol_bytes: u64,
-}
-
 impl ArchiveMember {
-    fn read(file_path: &Path) -> ArResult<Self> {
-        if !file_path.exists() {
-            return Err(format!("ar: {}: No such file or directory", file_path.display()).into());
-        }
-
-        if !file_path.is_file() {
-            return Err(format!("ar: {}: Is a directory", file_path.display()).into());
-        }
-
-        let file_metadata = file_path.metadata()?;
-        // we already checked that the path is to a file so unwrap is safe
-        let name = file_path.file_name().unwrap().to_os_string();
-
-        let data = std::fs::read(file_path)?;
-        let symbols = read_member_symbols(&data);
-        let symbol_bytes = symbols.iter().map(|s| s.len() as u64 + 1).sum::<u64>();
-
-        let date = file_metadata
-            .modified()
-            .ok()
-            .map(|t| t.elapsed().ok().map(|d| d.as_secs()).unwrap_or_default())
-            .unwrap_or_default();
-
-        Ok(ArchiveMember {
-            name,
-            date,
-            uid: file_metadata.uid() as u64,
-            gid: file_metadata.gid() as u64,
-            mode: file_metadata.mode() as u64,
-            size: file_metadata.len(),
-            data,
-            symbols,
-            symbol_bytes,
-        })
-    }
-
     fn write<W: Write>(&self, writer: &mut W) -> ArResult<()> {
         // format definition taken from: https://en.wikipedia.org/wiki/Ar_(Unix)
 
@@ -247,63 +196,7 @@ enum InsertPosition {
     End,
 }
 
-#[derive(Default)]
-struct Archive {
-    members: Vec<ArchiveMember>,
-    symbol_count: u64,
-    symbol_bytes: u64,
-    archive_size: u64,
-}
-
 impl Archive {
-    fn read_from_file(path: &Path) -> ArResult<Self> {
-        if !path.exists() {
-            return Err(format!("ar: {}: No such file or directory", path.display()).into());
-        }
-
-        if !path.is_file() {
-            return Err(format!("ar: {}: Is a directory", path.display()).into());
-        }
-
-        let file_data = std::fs::read(path)?;
-        let parsed_archive = object::read::archive::ArchiveFile::parse(&*file_data)?;
-        let mut members = Vec::new();
-        let mut archive_symbol_count = 0;
-        let mut archive_symbol_bytes = 0;
-        let mut archive_size = 0;
-
-        for member in parsed_archive.members() {
-            let member = member.map_err(|_| "ar: invalid archive format")?;
-
-            let data = member.data(&*file_data)?;
-            let name = OsString::from_vec(member.name().to_vec());
-            let symbols = read_member_symbols(data);
-
-            archive_symbol_count += symbols.len() as u64;
-            let symbol_bytes = member_symbol_bytes(&symbols);
-            archive_symbol_bytes += symbol_bytes;
-            archive_size += MEMBER_HEADER_SIZE + data.len() as u64;
-
-            members.push(ArchiveMember {
-                name,
-                date: member.date().ok_or("ar: invalid archive format")?,
-                uid: member.uid().ok_or("ar: invalid archive format")?,
-                gid: member.gid().ok_or("ar: invalid archive format")?,
-                mode: member.mode().ok_or("ar: invalid archive format")?,
-                size: data.len() as u64,
-                data: data.to_vec(),
-                symbols,
-                symbol_bytes,
-            });
-        }
-        Ok(Archive {
-            members,
-            symbol_count: archive_symbol_count,
-            symbol_bytes: archive_symbol_bytes,
-            archive_size,
-        })
-    }
-
     fn write<W: Write>(&self, writer: &mut W) -> ArResult<()> {
         writer.write_all(&object::archive::MAGIC)?;
         self.write_symbol_table(writer)?;
@@ -468,29 +361,6 @@ fn format_name_for_header(name: &OsStr) -> ArResult<[u8; 16]> {
     Ok(result)
 }
 
-fn member_symbol_bytes(member_symbols: &[String]) -> u64 {
-    // we add 1 for the null terminator that is required for each symbol
-    // in the archives symbol table
-    member_symbols.iter().map(|s| s.len() as u64 + 1).sum()
-}
-
-fn read_member_symbols(member_data: &[u8]) -> Vec<String> {
-    if let Ok(object_file) = object::read::File::parse(member_data) {
-        let symbols = object_file
-            .symbols()
-            .filter(|s| {
-                s.kind() == SymbolKind::Text
-                    || s.kind() == SymbolKind::Data
-                    || s.kind() == SymbolKind::Tls
-            })
-            .map(|s| s.name().unwrap().to_string())
-            .collect();
-        symbols
-    } else {
-        Vec::new()
-    }
-}
-
 fn delete_cmd(args: DeleteArgs) -> ArResult<()> {
     let archive_path = Path::new(&args.archive);
     let mut archive = Archive::read_from_file(archive_path)?;
@@ -0,0 +1,153 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Reading of System V (GNU) archives, shared by `ar` and `make`.
+
+use object::{Object, ObjectSymbol, SymbolKind};
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+pub const MEMBER_HEADER_SIZE: u64 = 60;
+
+pub type ArResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Default)]
+pub struct ArchiveMember {
+    pub name: OsString,
+    pub date: u64,
+    pub uid: u64,
+    pub gid: u64,
+    pub mode: u64,
+    pub size: u64,
+    pub data: Vec<u8>,
+    pub symbols: Vec<String>,
+    pub symbol_bytes: u64,
+}
+
+impl ArchiveMember {
+    pub fn read(file_path: &Path) -> ArResult<Self> {
+        if !file_path.exists() {
+            return Err(format!("ar: {}: No such file or directory", file_path.display()).into());
+        }
+
+        if !file_path.is_file() {
+            return Err(format!("ar: {}: Is a directory", file_path.display()).into());
+        }
+
+        let file_metadata = file_path.metadata()?;
+        // we already checked that the path is to a file so unwrap is safe
+        let name = file_path.file_name().unwrap().to_os_string();
+
+        let data = std::fs::read(file_path)?;
+        let symbols = read_member_symbols(&data);
+        let symbol_bytes = symbols.iter().map(|s| s.len() as u64 + 1).sum::<u64>();
+
+        let date = file_metadata
+            .modified()
+            .ok()
+            .map(|t| t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+            .unwrap_or_default();
+
+        Ok(ArchiveMember {
+            name,
+            date,
+            uid: file_metadata.uid() as u64,
+            gid: file_metadata.gid() as u64,
+            mode: file_metadata.mode() as u64,
+            size: file_metadata.len(),
+            data,
+            symbols,
+            symbol_bytes,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Archive {
+    pub members: Vec<ArchiveMember>,
+    pub symbol_count: u64,
+    pub symbol_bytes: u64,
+    pub archive_size: u64,
+}
+
+impl Archive {
+    pub fn read_from_file(path: &Path) -> ArResult<Self> {
+        if !path.exists() {
+            return Err(format!("ar: {}: No such file or directory", path.display()).into());
+        }
+
+        if !path.is_file() {
+            return Err(format!("ar: {}: Is a directory", path.display()).into());
+        }
+
+        let file_data = std::fs::read(path)?;
+        let parsed_archive = object::read::archive::ArchiveFile::parse(&*file_data)?;
+        let mut members = Vec::new();
+        let mut archive_symbol_count = 0;
+        let mut archive_symbol_bytes = 0;
+        let mut archive_size = 0;
+
+        for member in parsed_archive.members() {
+            let member = member.map_err(|_| "ar: invalid archive format")?;
+
+            let data = member.data(&*file_data)?;
+            let name = OsString::from_vec(member.name().to_vec());
+            let symbols = read_member_symbols(data);
+
+            archive_symbol_count += symbols.len() as u64;
+            let symbol_bytes = member_symbol_bytes(&symbols);
+            archive_symbol_bytes += symbol_bytes;
+            archive_size += MEMBER_HEADER_SIZE + data.len() as u64;
+
+            members.push(ArchiveMember {
+                name,
+                date: member.date().ok_or("ar: invalid archive format")?,
+                uid: member.uid().ok_or("ar: invalid archive format")?,
+                gid: member.gid().ok_or("ar: invalid archive format")?,
+                mode: member.mode().ok_or("ar: invalid archive format")?,
+                size: data.len() as u64,
+                data: data.to_vec(),
+                symbols,
+                symbol_bytes,
+            });
+        }
+        Ok(Archive {
+            members,
+            symbol_count: archive_symbol_count,
+            symbol_bytes: archive_symbol_bytes,
+            archive_size,
+        })
+    }
+}
+
+fn member_symbol_bytes(member_symbols: &[String]) -> u64 {
+    // we add 1 for the null terminator that is required for each symbol
+    // in the archives symbol table
+    member_symbols.iter().map(|s| s.len() as u64 + 1).sum()
+}
+
+fn read_member_symbols(member_data: &[u8]) -> Vec<String> {
+    if let Ok(object_file) = object::read::File::parse(member_data) {
+        let symbols = object_file
+            .symbols()
+            .filter(|s| {
+                s.kind() == SymbolKind::Text
+                    || s.kind() == SymbolKind::Data
+                    || s.kind() == SymbolKind::Tls
+            })
+            .map(|s| s.name().unwrap().to_string())
+            .collect();
+        symbols
+    } else {
+        Vec::new()
+    }
+}
@@ -0,0 +1,270 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+// The rest of the archive reader is used by ar.
+#[allow(dead_code)]
+mod archive;
+mod make_util;
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use make_util::{
+    catch_signals, Macros, Make, Makefile, Options, Origin, BUILTIN_MACROS, BUILTIN_RULES,
+};
+use plib::PROJECT_NAME;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// make - maintain, update, and regenerate groups of programs
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Let environment variables override macro definitions in makefiles.
+    #[arg(short = 'e')]
+    environment_overrides: bool,
+
+    /// Read MAKEFILE instead of makefile or Makefile; - is the standard input.
+    #[arg(short = 'f', value_name = "MAKEFILE")]
+    makefile: Vec<String>,
+
+    /// Ignore errors from commands.
+    #[arg(short = 'i')]
+    ignore_errors: bool,
+
+    /// Run up to JOBS commands at the same time.
+    #[arg(short = 'j', value_name = "JOBS", default_value_t = 1)]
+    jobs: usize,
+
+    /// Keep making targets that do not depend on a target that failed.
+    #[arg(short = 'k', overrides_with = "stop")]
+    keep_going: bool,
+
+    /// Write the commands that would be run without running them.
+    #[arg(short = 'n')]
+    dry_run: bool,
+
+    /// Write the macro definitions and rules.
+    #[arg(short = 'p')]
+    print: bool,
+
+    /// Exit with status 1 if any target is out of date, without running commands.
+    #[arg(short = 'q')]
+    question: bool,
+
+    /// Do not use the built-in inference rules.
+    #[arg(short = 'r')]
+    no_builtin_rules: bool,
+
+    /// Stop at the first error; cancels -k.
+    #[arg(short = 'S', overrides_with = "keep_going")]
+    stop: bool,
+
+    /// Do not write the commands as they are run.
+    #[arg(short = 's')]
+    silent: bool,
+
+    /// Touch out of date targets instead of running their commands.
+    #[arg(short = 't')]
+    touch: bool,
+
+    /// Macro definitions (NAME=value) and targets to make.
+    operands: Vec<String>,
+}
+
+impl Args {
+    /// The option letters passed on to recursive makes in MAKEFLAGS.
+    fn flag_letters(&self) -> String {
+        [
+            (self.environment_overrides, 'e'),
+            (self.ignore_errors, 'i'),
+            (self.keep_going, 'k'),
+            (self.dry_run, 'n'),
+            (self.question, 'q'),
+            (self.no_builtin_rules, 'r'),
+            (self.silent, 's'),
+            (self.touch, 't'),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, letter)| *letter)
+        .collect()
+    }
+
+    /// Applies the option letters of MAKEFLAGS.
+    fn apply_flag_letters(&mut self, letters: &str) {
+        for letter in letters.chars() {
+            match letter {
+                'e' => self.environment_overrides = true,
+                'i' => self.ignore_errors = true,
+                'k' if !self.stop => self.keep_going = true,
+                'n' => self.dry_run = true,
+                'p' => self.print = true,
+                'q' => self.question = true,
+                'r' => self.no_builtin_rules = true,
+                's' => self.silent = true,
+                'S' => self.keep_going = false,
+                't' => self.touch = true,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Splits MAKEFLAGS into words at spaces not escaped by a backslash.
+fn split_makeflags(makeflags: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut chars = makeflags.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word.extend(chars.next()),
+            ' ' | '\t' => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn escape_makeflags(word: &str) -> String {
+    word.replace('\\', "\\\\").replace(' ', "\\ ")
+}
+
+fn make(mut args: Args) -> Result<i32, Box<dyn std::error::Error>> {
+    // MAKEFLAGS holds option letters, possibly with a leading -, and macro
+    // definitions from the command line of a parent make.
+    let mut inherited_macros = Vec::new();
+    if let Ok(makeflags) = std::env::var("MAKEFLAGS") {
+        for word in split_makeflags(&makeflags) {
+            if word.contains('=') {
+                inherited_macros.push(word);
+            } else {
+                args.apply_flag_letters(word.trim_start_matches('-'));
+            }
+        }
+    }
+
+    let (definitions, targets): (Vec<String>, Vec<String>) =
+        args.operands.iter().cloned().partition(|o| o.contains('='));
+
+    let mut macros = Macros::new(args.environment_overrides);
+    for (name, value) in BUILTIN_MACROS {
+        macros.define(name, value.to_string(), Origin::Default, false);
+    }
+    if let Some(program) = std::env::args().next() {
+        macros.define("MAKE", program, Origin::Default, false);
+    }
+    for (name, value) in std::env::vars() {
+        if name != "SHELL" {
+            macros.define(&name, value, Origin::Environment, true);
+        }
+    }
+    for (definitions, origin) in [
+        (&inherited_macros, Origin::MakeFlags),
+        (&definitions, Origin::CommandLine),
+    ] {
+        for definition in definitions {
+            let (name, value) = definition.split_once('=').unwrap();
+            macros.define(name, value.to_string(), origin, false);
+        }
+    }
+
+    let mut makeflags = args.flag_letters();
+    for definition in inherited_macros.iter().chain(&definitions) {
+        makeflags.push(' ');
+        makeflags.push_str(&escape_makeflags(definition));
+    }
+    macros.define("MAKEFLAGS", makeflags, Origin::CommandLine, true);
+
+    let mut makefile = Makefile::new(macros);
+    if !args.no_builtin_rules {
+        makefile.parse(BUILTIN_RULES, "<builtin>")?;
+    }
+
+    let files = if args.makefile.is_empty() {
+        ["makefile", "Makefile"]
+            .into_iter()
+            .find(|name| Path::new(name).exists())
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default()
+    } else {
+        args.makefile.clone()
+    };
+    for file in &files {
+        let text = if file == "-" {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        } else {
+            std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?
+        };
+        makefile.parse(&text, file)?;
+    }
+
+    if args.print {
+        makefile.print();
+    }
+
+    let goals = if targets.is_empty() {
+        match &makefile.first_target {
+            Some(target) => vec![target.clone()],
+            None if args.print => return Ok(0),
+            None if files.is_empty() => {
+                return Err(gettext("no targets specified and no makefile found").into())
+            }
+            None => return Err(gettext("no targets").into()),
+        }
+    } else {
+        targets
+    };
+
+    catch_signals();
+    let mut make = Make::new(
+        &makefile,
+        Options {
+            ignore_errors: args.ignore_errors,
+            keep_going: args.keep_going,
+            dry_run: args.dry_run,
+            question: args.question,
+            touch: args.touch,
+            silent: args.silent,
+            jobs: args.jobs,
+        },
+    );
+    make.make(&goals);
+
+    Ok(if make.failed {
+        2
+    } else if make.out_of_date {
+        1
+    } else {
+        0
+    })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let exit_code = make(args).unwrap_or_else(|err| {
+        eprintln!("make: {}", err);
+        2
+    });
+
+    std::process::exit(exit_code)
+}
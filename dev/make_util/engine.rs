@@ -0,0 +1,686 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Deciding what is out of date and running the commands to update it, up
+//! to a number of jobs at a time.
+
+use super::macros::{Internal, Origin};
+use super::parser::{split_member, Makefile};
+use crate::archive::Archive;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The signal that interrupted make, if any.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Catches the signals on which the targets being made are removed.
+pub fn catch_signals() {
+    for signal in [libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM] {
+        unsafe {
+            libc::signal(
+                signal,
+                on_signal as *const extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub ignore_errors: bool,
+    pub keep_going: bool,
+    pub dry_run: bool,
+    pub question: bool,
+    pub touch: bool,
+    pub silent: bool,
+    pub jobs: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+struct Node {
+    name: String,
+    prerequisites: Vec<usize>,
+    commands: Option<Vec<String>>,
+
+    /// The prerequisite that an inference rule was chosen for.
+    source: Option<String>,
+    stem: String,
+
+    /// Neither a rule nor an inference rule names the target.
+    no_rule: bool,
+
+    state: State,
+
+    /// Commands were run, or would have been, to update the target.
+    remade: bool,
+
+    /// A job was started to update the target.
+    launched: bool,
+
+    /// The modification time after the target was considered; `None` if it
+    /// does not exist.
+    time: Option<SystemTime>,
+}
+
+/// One step of the job that updates a target.
+enum Step {
+    Run {
+        command: String,
+        echo: bool,
+        ignore: bool,
+    },
+    Print(String),
+    Touch(String),
+}
+
+struct Job {
+    target: String,
+    steps: Vec<Step>,
+    shell: String,
+    env: HashMap<String, String>,
+}
+
+/// What starting a target led to.
+enum Started {
+    Launched,
+    Finished,
+}
+
+pub struct Make<'a> {
+    makefile: &'a Makefile,
+    options: Options,
+    nodes: Vec<Node>,
+    index: HashMap<String, usize>,
+    env: HashMap<String, String>,
+
+    /// Some command failed or some target could not be made.
+    pub failed: bool,
+
+    /// With `-q`, some target is out of date.
+    pub out_of_date: bool,
+
+    stopping: bool,
+}
+
+/// The modification time of a file, or of a library member.
+fn modification_time(name: &str) -> Option<SystemTime> {
+    if let Some((library, member)) = split_member(name) {
+        let archive = Archive::read_from_file(Path::new(library)).ok()?;
+        let member = archive
+            .members
+            .iter()
+            .find(|m| m.name.to_str() == Some(member))?;
+        return Some(UNIX_EPOCH + Duration::from_secs(member.date));
+    }
+    fs::metadata(name).and_then(|m| m.modified()).ok()
+}
+
+fn touch(name: &str) -> io::Result<()> {
+    let path = split_member(name).map_or(name, |(library, _)| library);
+    let file = File::options().create(true).append(true).open(path)?;
+    file.set_modified(SystemTime::now())
+}
+
+impl Job {
+    /// Runs the steps in order, stopping at the first error not ignored.
+    fn run(self) -> bool {
+        for step in self.steps {
+            match step {
+                Step::Print(text) => {
+                    println!("{}", text);
+                }
+                Step::Touch(name) => {
+                    if let Err(e) = touch(&name) {
+                        eprintln!("make: {}: {}", name, e);
+                        return false;
+                    }
+                }
+                Step::Run {
+                    command,
+                    echo,
+                    ignore,
+                } => {
+                    if echo {
+                        println!("{}", command);
+                    }
+                    let _ = io::stdout().flush();
+                    let status = Command::new(&self.shell)
+                        .arg("-c")
+                        .arg(&command)
+                        .env_clear()
+                        .envs(&self.env)
+                        .status();
+                    let error = match status {
+                        Ok(status) if status.success() => continue,
+                        Ok(status) => match status.code() {
+                            Some(code) => format!("Error {}", code),
+                            None => {
+                                use std::os::unix::process::ExitStatusExt;
+                                format!("Signal {}", status.signal().unwrap_or_default())
+                            }
+                        },
+                        Err(e) => e.to_string(),
+                    };
+                    if ignore {
+                        eprintln!("make: [{}] {} (ignored)", self.target, error);
+                    } else {
+                        eprintln!("make: *** [{}] {}", self.target, error);
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<'a> Make<'a> {
+    pub fn new(makefile: &'a Makefile, options: Options) -> Self {
+        let mut env: HashMap<String, String> = std::env::vars().collect();
+        for (name, m) in makefile.macros.iter() {
+            if matches!(
+                m.origin,
+                Origin::Environment | Origin::MakeFlags | Origin::CommandLine
+            ) {
+                if let Ok(value) = makefile.macros.expand(&format!("$({})", name), None) {
+                    env.insert(name.clone(), value);
+                }
+            }
+        }
+        Make {
+            makefile,
+            options,
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            env,
+            failed: false,
+            out_of_date: false,
+            stopping: false,
+        }
+    }
+
+    /// Whether `name` exists or a rule names it.
+    fn can_make(&self, name: &str) -> bool {
+        self.makefile.rules.contains_key(name) || Path::new(name).exists()
+    }
+
+    /// Finds an inference rule for `name`, returning the source, the
+    /// commands and the stem.
+    fn infer(&self, name: &str) -> Option<(String, Vec<String>, String)> {
+        let mf = self.makefile;
+        let rule = |source_suffix: &str, target_suffix: &str| {
+            mf.inference
+                .get(&format!("{}{}", source_suffix, target_suffix))
+        };
+
+        if let Some((_, member)) = split_member(name) {
+            let stem = match member.rfind('.') {
+                Some(dot) if dot > 0 => &member[..dot],
+                _ => member,
+            };
+            for suffix in &mf.suffixes {
+                let source = format!("{}{}", stem, suffix);
+                if let Some(commands) = rule(suffix, ".a") {
+                    if self.can_make(&source) {
+                        return Some((source, commands.clone(), stem.to_string()));
+                    }
+                }
+            }
+            return None;
+        }
+
+        for target_suffix in mf.suffixes.iter().filter(|s| name.len() > s.len()) {
+            let Some(stem) = name.strip_suffix(target_suffix.as_str()) else {
+                continue;
+            };
+            for suffix in &mf.suffixes {
+                let source = format!("{}{}", stem, suffix);
+                if let Some(commands) = rule(suffix, target_suffix) {
+                    if self.can_make(&source) {
+                        return Some((source, commands.clone(), stem.to_string()));
+                    }
+                }
+            }
+        }
+        for suffix in &mf.suffixes {
+            let source = format!("{}{}", name, suffix);
+            if let Some(commands) = rule(suffix, "") {
+                if self.can_make(&source) {
+                    return Some((source, commands.clone(), name.to_string()));
+                }
+            }
+        }
+        None
+    }
+
+    /// The stem of a target made by an explicit rule: the name without a
+    /// known suffix.
+    fn explicit_stem(&self, name: &str) -> String {
+        let name = split_member(name).map_or(name, |(_, member)| member);
+        self.makefile
+            .suffixes
+            .iter()
+            .find_map(|s| {
+                name.strip_suffix(s.as_str())
+                    .filter(|stem| !stem.is_empty())
+            })
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Adds `name` and everything it depends on to the graph. Returns `None`
+    /// if `name` depends on itself.
+    fn resolve(&mut self, name: &str, chain: &mut Vec<usize>) -> Option<usize> {
+        if let Some(&i) = self.index.get(name) {
+            if chain.contains(&i) {
+                let from = &self.nodes[*chain.last().unwrap()].name;
+                eprintln!("make: Circular {} <- {} dependency dropped.", from, name);
+                return None;
+            }
+            return Some(i);
+        }
+
+        let rule = self.makefile.rules.get(name);
+        let mut prerequisites = rule.map(|r| r.prerequisites.clone()).unwrap_or_default();
+        let mut commands = rule.and_then(|r| r.commands.clone());
+        let mut source = None;
+        let mut stem = self.explicit_stem(name);
+        if commands.is_none() && !self.makefile.phony.contains(name) {
+            if let Some((inferred, inferred_commands, inferred_stem)) = self.infer(name) {
+                if !prerequisites.contains(&inferred) {
+                    prerequisites.insert(0, inferred.clone());
+                }
+                source = Some(inferred);
+                commands = Some(inferred_commands);
+                stem = inferred_stem;
+            }
+        }
+
+        let i = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            prerequisites: Vec::new(),
+            no_rule: rule.is_none() && source.is_none(),
+            commands,
+            source,
+            stem,
+            state: State::Pending,
+            remade: false,
+            launched: false,
+            time: None,
+        });
+        self.index.insert(name.to_string(), i);
+
+        chain.push(i);
+        let prerequisites = prerequisites
+            .iter()
+            .filter_map(|p| self.resolve(p, chain))
+            .collect();
+        chain.pop();
+        self.nodes[i].prerequisites = prerequisites;
+        Some(i)
+    }
+
+    /// The nodes the goals depend on, prerequisites before their targets and
+    /// otherwise in makefile order, with the range of the nodes each goal
+    /// added to those of the goals before it.
+    fn order(&self, goals: &[usize]) -> (Vec<usize>, Vec<Range<usize>>) {
+        fn visit(nodes: &[Node], i: usize, seen: &mut Vec<bool>, order: &mut Vec<usize>) {
+            if seen[i] {
+                return;
+            }
+            seen[i] = true;
+            for &p in &nodes[i].prerequisites {
+                visit(nodes, p, seen, order);
+            }
+            order.push(i);
+        }
+        let mut seen = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut added = Vec::new();
+        for &goal in goals {
+            let start = order.len();
+            visit(&self.nodes, goal, &mut seen, &mut order);
+            added.push(start..order.len());
+        }
+        (order, added)
+    }
+
+    fn is_ready(&self, i: usize) -> bool {
+        self.nodes[i].state == State::Pending
+            && self.nodes[i]
+                .prerequisites
+                .iter()
+                .all(|&p| matches!(self.nodes[p].state, State::Done | State::Failed))
+    }
+
+    /// Brings the goals up to date, sharing the job slots among all of them.
+    pub fn make(&mut self, goals: &[String]) {
+        let goal_indices: Vec<usize> = goals
+            .iter()
+            .filter_map(|goal| self.resolve(goal, &mut Vec::new()))
+            .collect();
+        let (order, added) = self.order(&goal_indices);
+        let (sender, receiver) = channel();
+        let mut running = 0;
+
+        loop {
+            let mut progressed = false;
+            if !self.stopping {
+                for &i in &order {
+                    if running >= self.options.jobs.max(1) || self.stopping {
+                        break;
+                    }
+                    if !self.is_ready(i) {
+                        continue;
+                    }
+                    match self.start(i, &sender) {
+                        Started::Launched => running += 1,
+                        Started::Finished => progressed = true,
+                    }
+                }
+            }
+            if progressed && !self.stopping {
+                continue;
+            }
+            if running == 0 {
+                break;
+            }
+            let (i, ok) = receiver.recv().unwrap();
+            running -= 1;
+            self.finish(i, ok);
+        }
+
+        let signal = SIGNAL.load(Ordering::SeqCst);
+        if signal != 0 {
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+
+        for (&goal_index, added) in goal_indices.iter().zip(added) {
+            let node = &self.nodes[goal_index];
+            let launched = order[added].iter().any(|&i| self.nodes[i].launched);
+            match node.state {
+                State::Failed if self.options.keep_going => {
+                    eprintln!("make: Target '{}' not remade because of errors.", node.name);
+                }
+                State::Done if !launched && !self.options.question && !self.options.silent => {
+                    if node.commands.as_ref().is_some_and(|c| !c.is_empty()) {
+                        println!("make: '{}' is up to date.", node.name);
+                    } else {
+                        println!("make: Nothing to be done for '{}'.", node.name);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn fail(&mut self, i: usize) {
+        self.nodes[i].state = State::Failed;
+        self.failed = true;
+        if !self.options.keep_going {
+            self.stopping = true;
+        }
+    }
+
+    /// Decides whether node `i` is out of date and, if so, starts the job
+    /// that updates it.
+    fn start(&mut self, i: usize, sender: &Sender<(usize, bool)>) -> Started {
+        let node = &self.nodes[i];
+        if node
+            .prerequisites
+            .iter()
+            .any(|&p| self.nodes[p].state == State::Failed)
+        {
+            self.nodes[i].state = State::Failed;
+            return Started::Finished;
+        }
+
+        let name = node.name.clone();
+        let phony = self.makefile.phony.contains(&name);
+        let time = if phony {
+            None
+        } else {
+            modification_time(&name)
+        };
+        let member = split_member(&name).is_some();
+        let newer: Vec<usize> = node
+            .prerequisites
+            .iter()
+            .copied()
+            .filter(|&p| match (time, self.nodes[p].time) {
+                _ if self.nodes[p].remade => true,
+                // Archives only record whole seconds.
+                (Some(target), Some(prerequisite)) if member => {
+                    let seconds =
+                        |t: SystemTime| t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                    seconds(prerequisite) > seconds(target)
+                }
+                (Some(target), Some(prerequisite)) => prerequisite > target,
+                _ => true,
+            })
+            .collect();
+        let out_of_date = time.is_none() || !newer.is_empty();
+        let latest = node
+            .prerequisites
+            .iter()
+            .filter_map(|&p| self.nodes[p].time)
+            .chain(time)
+            .max();
+
+        let mut commands = node.commands.clone();
+        let mut source = node.source.clone();
+        if node.no_rule && time.is_none() && !phony {
+            match &self.makefile.default {
+                Some(default) => {
+                    commands = Some(default.clone());
+                    source = Some(name.clone());
+                }
+                None if node.prerequisites.is_empty() => {
+                    eprintln!("make: *** No rule to make target '{}'.", name);
+                    self.fail(i);
+                    return Started::Finished;
+                }
+                None => {}
+            }
+        }
+
+        let commands = commands.unwrap_or_default();
+        if !out_of_date || commands.is_empty() {
+            let node = &mut self.nodes[i];
+            node.state = State::Done;
+            node.remade = out_of_date;
+            node.time = if out_of_date {
+                latest.or_else(|| Some(SystemTime::now()))
+            } else {
+                time
+            };
+            return Started::Finished;
+        }
+
+        let prerequisite_names = |list: &[usize]| -> Vec<String> {
+            list.iter().map(|&p| self.nodes[p].name.clone()).collect()
+        };
+        let (target, member) = match split_member(&name) {
+            Some((library, member)) => (library.to_string(), member.to_string()),
+            None => (name.clone(), String::new()),
+        };
+        let internal = Internal {
+            target,
+            member,
+            source: source.unwrap_or_else(|| {
+                prerequisite_names(&node.prerequisites)
+                    .first()
+                    .cloned()
+                    .unwrap_or_default()
+            }),
+            stem: node.stem.clone(),
+            newer: prerequisite_names(&newer),
+            prerequisites: prerequisite_names(&node.prerequisites),
+        };
+
+        let Some(steps) = self.steps(&name, &commands, &internal) else {
+            self.fail(i);
+            return Started::Finished;
+        };
+        if self.options.question {
+            self.out_of_date = true;
+        }
+        if steps.is_empty() {
+            self.nodes[i].state = State::Done;
+            self.nodes[i].remade = true;
+            self.nodes[i].time = Some(SystemTime::now());
+            return Started::Finished;
+        }
+
+        let job = Job {
+            target: name,
+            steps,
+            shell: self.shell(),
+            env: self.env.clone(),
+        };
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let ok = job.run();
+            let _ = sender.send((i, ok));
+        });
+        self.nodes[i].state = State::Running;
+        self.nodes[i].launched = true;
+        Started::Launched
+    }
+
+    fn shell(&self) -> String {
+        self.makefile
+            .macros
+            .get("SHELL")
+            .and_then(|_| self.makefile.macros.expand("$(SHELL)", None).ok())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "/bin/sh".to_string())
+    }
+
+    /// Expands the commands that update `name` into the steps of its job,
+    /// according to `-n`, `-q` and `-t`.
+    fn steps(&self, name: &str, commands: &[String], internal: &Internal) -> Option<Vec<Step>> {
+        let mf = self.makefile;
+        let options = &self.options;
+        let silent = options.silent || mf.all_silent || mf.silent.contains(name);
+        let ignore = options.ignore_errors || mf.all_ignore || mf.ignore.contains(name);
+
+        let mut steps = Vec::new();
+        for command in commands {
+            let expanded = match mf.macros.expand(command, Some(internal)) {
+                Ok(expanded) => expanded,
+                Err(e) => {
+                    eprintln!("make: {}: {}", name, e);
+                    return None;
+                }
+            };
+            let mut text = expanded.trim_start();
+            let (mut quiet, mut ignore_line, mut always) = (silent, ignore, false);
+            while let Some(c) = text.chars().next() {
+                match c {
+                    '@' => quiet = true,
+                    '-' => ignore_line = true,
+                    '+' => always = true,
+                    _ => break,
+                }
+                text = text[1..].trim_start();
+            }
+            always |= command.contains("$(MAKE)") || command.contains("${MAKE}");
+            if text.is_empty() {
+                continue;
+            }
+
+            let simulated = options.dry_run || options.question || options.touch;
+            if !simulated || always {
+                steps.push(Step::Run {
+                    command: text.to_string(),
+                    echo: !quiet || options.dry_run,
+                    ignore: ignore_line,
+                });
+            } else if options.dry_run {
+                steps.push(Step::Print(text.to_string()));
+            }
+        }
+        // Phony targets are not files, so they are never touched.
+        if options.touch && !options.question && !mf.phony.contains(name) {
+            if !silent {
+                steps.push(Step::Print(format!("touch {}", name)));
+            }
+            if !options.dry_run {
+                steps.push(Step::Touch(name.to_string()));
+            }
+        }
+        Some(steps)
+    }
+
+    /// Records the end of the job that updated node `i`.
+    fn finish(&mut self, i: usize, ok: bool) {
+        if SIGNAL.load(Ordering::SeqCst) != 0 {
+            self.stopping = true;
+            if !ok {
+                self.remove_target(i);
+            }
+            self.failed = true;
+            self.nodes[i].state = State::Failed;
+            return;
+        }
+        if !ok {
+            self.fail(i);
+            return;
+        }
+        let node = &mut self.nodes[i];
+        node.state = State::Done;
+        node.remade = true;
+        let simulated = self.options.dry_run || self.options.question;
+        node.time = match modification_time(&node.name) {
+            Some(time) if !simulated => Some(time),
+            _ => Some(SystemTime::now()),
+        };
+    }
+
+    /// Removes a target whose commands were interrupted, unless it is
+    /// precious, phony, a library member or a directory.
+    fn remove_target(&self, i: usize) {
+        let name = &self.nodes[i].name;
+        let mf = self.makefile;
+        if mf.all_precious
+            || mf.precious.contains(name)
+            || mf.phony.contains(name)
+            || split_member(name).is_some()
+            || !Path::new(name).is_file()
+        {
+            return;
+        }
+        eprintln!("make: *** Deleting file '{}'", name);
+        let _ = fs::remove_file(name);
+    }
+}
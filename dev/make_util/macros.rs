@@ -0,0 +1,344 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The macro table and macro expansion.
+
+use std::collections::HashMap;
+
+/// Where a macro definition came from, lowest precedence first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    Environment,
+    Makefile,
+    MakeFlags,
+    CommandLine,
+}
+
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub value: String,
+
+    /// The value was expanded when it was defined, and is not expanded
+    /// again when referenced.
+    pub immediate: bool,
+
+    pub origin: Origin,
+}
+
+/// The internal macros of the target being made.
+#[derive(Default)]
+pub struct Internal {
+    /// `$@`: the target, or the library for a library member.
+    pub target: String,
+
+    /// `$%`: the member, for a library member target.
+    pub member: String,
+
+    /// `$<`: the prerequisite an inference rule was chosen for.
+    pub source: String,
+
+    /// `$*`: the target without its suffix.
+    pub stem: String,
+
+    /// `$?`: the prerequisites newer than the target.
+    pub newer: Vec<String>,
+
+    /// `$^`: all prerequisites.
+    pub prerequisites: Vec<String>,
+}
+
+impl Internal {
+    fn get(&self, name: &str) -> Option<String> {
+        let (base, part) = match name.len() {
+            1 => (name, None),
+            2 if name.ends_with('D') || name.ends_with('F') => {
+                (&name[..1], name[1..].chars().next())
+            }
+            _ => return None,
+        };
+        let words: Vec<String> = match base {
+            "@" => vec![self.target.clone()],
+            "%" => vec![self.member.clone()],
+            "<" => vec![self.source.clone()],
+            "*" => vec![self.stem.clone()],
+            "?" => self.newer.clone(),
+            "^" => self.prerequisites.clone(),
+            _ => return None,
+        };
+        let words = words.into_iter().filter(|w| !w.is_empty());
+        let value: Vec<String> = match part {
+            None => words.collect(),
+            Some('D') => words
+                .map(|w| match w.rfind('/') {
+                    Some(0) => "/".to_string(),
+                    Some(i) => w[..i].to_string(),
+                    None => ".".to_string(),
+                })
+                .collect(),
+            Some(_) => words
+                .map(|w| match w.rfind('/') {
+                    Some(i) => w[i + 1..].to_string(),
+                    None => w,
+                })
+                .collect(),
+        };
+        Some(value.join(" "))
+    }
+}
+
+pub struct Macros {
+    table: HashMap<String, Macro>,
+
+    /// Environment variables override makefile definitions (`-e`).
+    pub environment_overrides: bool,
+}
+
+impl Macros {
+    pub fn new(environment_overrides: bool) -> Self {
+        Macros {
+            table: HashMap::new(),
+            environment_overrides,
+        }
+    }
+
+    fn rank(&self, origin: Origin) -> u8 {
+        match origin {
+            Origin::Default => 0,
+            Origin::Environment if self.environment_overrides => 3,
+            Origin::Environment => 1,
+            Origin::Makefile => 2,
+            Origin::MakeFlags => 4,
+            Origin::CommandLine => 5,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.table.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Macro)> {
+        self.table.iter()
+    }
+
+    /// Whether a definition from `origin` may replace the current one.
+    fn may_define(&self, name: &str, origin: Origin) -> bool {
+        self.table
+            .get(name)
+            .map_or(true, |m| self.rank(origin) >= self.rank(m.origin))
+    }
+
+    pub fn define(&mut self, name: &str, value: String, origin: Origin, immediate: bool) {
+        if self.may_define(name, origin) {
+            self.table.insert(
+                name.to_string(),
+                Macro {
+                    value,
+                    immediate,
+                    origin,
+                },
+            );
+        }
+    }
+
+    /// `name ?= value`: defines the macro only if it is not defined.
+    pub fn define_default(&mut self, name: &str, value: String, origin: Origin) {
+        if !self.table.contains_key(name) {
+            self.define(name, value, origin, false);
+        }
+    }
+
+    /// `name += value`: appends to the macro, expanding `value` first if the
+    /// macro was immediately expanded.
+    pub fn append(&mut self, name: &str, value: &str, origin: Origin) -> Result<(), String> {
+        if !self.may_define(name, origin) {
+            return Ok(());
+        }
+        let Some(current) = self.table.get(name) else {
+            self.define(name, value.to_string(), origin, false);
+            return Ok(());
+        };
+        let immediate = current.immediate;
+        let value = if immediate {
+            self.expand(value, None)?
+        } else {
+            value.to_string()
+        };
+        let current = &self.table[name].value;
+        let joined = if current.is_empty() {
+            value
+        } else if value.is_empty() {
+            current.clone()
+        } else {
+            format!("{} {}", current, value)
+        };
+        self.define(name, joined, origin, immediate);
+        Ok(())
+    }
+
+    /// Expands the macro references in `text`, using `internal` for the
+    /// internal macros.
+    pub fn expand(&self, text: &str, internal: Option<&Internal>) -> Result<String, String> {
+        let mut out = String::new();
+        self.expand_into(text, internal, &mut out, &mut Vec::new())?;
+        Ok(out)
+    }
+
+    fn expand_into(
+        &self,
+        text: &str,
+        internal: Option<&Internal>,
+        out: &mut String,
+        active: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let Some(c) = rest.chars().next() else {
+                out.push('$');
+                break;
+            };
+            let reference = match c {
+                '$' => {
+                    out.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+                '(' | '{' => {
+                    let close = if c == '(' { ')' } else { '}' };
+                    let Some(end) = matching_close(&rest[1..], c, close) else {
+                        return Err(format!("unterminated macro reference '${}'", rest));
+                    };
+                    let inner = &rest[1..1 + end];
+                    rest = &rest[end + 2..];
+                    inner
+                }
+                _ => {
+                    let len = c.len_utf8();
+                    let name = &rest[..len];
+                    rest = &rest[len..];
+                    name
+                }
+            };
+            self.expand_reference(reference, internal, out, active)?;
+        }
+        out.push_str(rest);
+        Ok(())
+    }
+
+    /// Expands `name` or `name:from=to`.
+    fn expand_reference(
+        &self,
+        reference: &str,
+        internal: Option<&Internal>,
+        out: &mut String,
+        active: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let (name, substitution) = match top_level_colon(reference) {
+            Some(i) => (&reference[..i], Some(&reference[i + 1..])),
+            None => (reference, None),
+        };
+        let mut expanded_name = String::new();
+        self.expand_into(name, internal, &mut expanded_name, active)?;
+
+        let value = self.value_of(&expanded_name, internal, active)?;
+        match substitution {
+            None => out.push_str(&value),
+            Some(substitution) => {
+                let mut expanded = String::new();
+                self.expand_into(substitution, internal, &mut expanded, active)?;
+                match expanded.split_once('=') {
+                    Some((from, to)) => out.push_str(&substitute(&value, from, to)),
+                    None => out.push_str(&value),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn value_of(
+        &self,
+        name: &str,
+        internal: Option<&Internal>,
+        active: &mut Vec<String>,
+    ) -> Result<String, String> {
+        if let Some(value) = internal.and_then(|i| i.get(name)) {
+            return Ok(value);
+        }
+        let Some(m) = self.table.get(name) else {
+            return Ok(String::new());
+        };
+        if m.immediate {
+            return Ok(m.value.clone());
+        }
+        if active.iter().any(|a| a == name) {
+            return Err(format!("macro '{}' references itself", name));
+        }
+        active.push(name.to_string());
+        let mut value = String::new();
+        let result = self.expand_into(&m.value, internal, &mut value, active);
+        active.pop();
+        result.map(|_| value)
+    }
+}
+
+/// Finds the `close` that ends a reference, allowing nested references.
+fn matching_close(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Finds the `:` that starts a substitution, outside nested references.
+fn top_level_colon(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            ':' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Applies the substitution `from=to` to each word of `value`: either a
+/// suffix replacement or, if `from` contains `%`, a pattern replacement.
+pub fn substitute(value: &str, from: &str, to: &str) -> String {
+    let words = value.split_whitespace().map(|word| {
+        if let Some((prefix, suffix)) = from.split_once('%') {
+            match word
+                .strip_prefix(prefix)
+                .and_then(|w| w.strip_suffix(suffix))
+            {
+                Some(stem) => to.replacen('%', stem, 1),
+                None => word.to_string(),
+            }
+        } else if from.is_empty() {
+            format!("{}{}", word, to)
+        } else {
+            match word.strip_suffix(from) {
+                Some(stem) => format!("{}{}", stem, to),
+                None => word.to_string(),
+            }
+        }
+    });
+    words.collect::<Vec<_>>().join(" ")
+}
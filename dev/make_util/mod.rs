@@ -0,0 +1,16 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod engine;
+mod macros;
+mod parser;
+
+pub use engine::{catch_signals, Make, Options};
+pub use macros::{Macros, Origin};
+pub use parser::{Makefile, BUILTIN_MACROS, BUILTIN_RULES};
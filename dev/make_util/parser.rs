@@ -0,0 +1,502 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Makefile parsing.
+
+use super::macros::{Macros, Origin};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::process::{Command, Stdio};
+
+/// The macros defined before any makefile is read.
+pub const BUILTIN_MACROS: &[(&str, &str)] = &[
+    ("MAKE", "make"),
+    ("AR", "ar"),
+    ("ARFLAGS", "-rv"),
+    ("YACC", "yacc"),
+    ("YFLAGS", ""),
+    ("LEX", "lex"),
+    ("LFLAGS", ""),
+    ("LDFLAGS", ""),
+    ("CC", "c99"),
+    ("CFLAGS", "-O1"),
+    ("FC", "fort77"),
+    ("FFLAGS", "-O1"),
+    ("GET", "get"),
+    ("GFLAGS", ""),
+    ("SCCSFLAGS", ""),
+    ("SCCSGETFLAGS", "-s"),
+];
+
+/// The default suffix list and inference rules, unless `-r` is given.
+pub const BUILTIN_RULES: &str = "\
+.SUFFIXES: .o .c .y .l .a .sh .f
+
+.c:
+\t$(CC) $(CFLAGS) $(LDFLAGS) -o $@ $<
+.f:
+\t$(FC) $(FFLAGS) $(LDFLAGS) -o $@ $<
+.sh:
+\tcp $< $@
+\tchmod a+x $@
+
+.c.o:
+\t$(CC) $(CFLAGS) -c $<
+.f.o:
+\t$(FC) $(FFLAGS) -c $<
+.y.o:
+\t$(YACC) $(YFLAGS) $<
+\t$(CC) $(CFLAGS) -c y.tab.c
+\trm -f y.tab.c
+\tmv y.tab.o $@
+.l.o:
+\t$(LEX) $(LFLAGS) $<
+\t$(CC) $(CFLAGS) -c lex.yy.c
+\trm -f lex.yy.c
+\tmv lex.yy.o $@
+.y.c:
+\t$(YACC) $(YFLAGS) $<
+\tmv y.tab.c $@
+.l.c:
+\t$(LEX) $(LFLAGS) $<
+\tmv lex.yy.c $@
+.c.a:
+\t$(CC) -c $(CFLAGS) $<
+\t$(AR) $(ARFLAGS) $@ $*.o
+\trm -f $*.o
+.f.a:
+\t$(FC) -c $(FFLAGS) $<
+\t$(AR) $(ARFLAGS) $@ $*.o
+\trm -f $*.o
+";
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The prerequisites and commands of one target, gathered from all the rules
+/// that name it.
+#[derive(Clone, Debug, Default)]
+pub struct Rule {
+    pub prerequisites: Vec<String>,
+
+    /// `None` if no rule for the target has commands.
+    pub commands: Option<Vec<String>>,
+}
+
+/// What the command lines that follow a rule line belong to.
+#[derive(Clone)]
+enum Sink {
+    Target(String),
+    Inference(String),
+    Default,
+}
+
+pub struct Makefile {
+    pub macros: Macros,
+    pub rules: HashMap<String, Rule>,
+
+    /// Inference rules by name, such as `.c.o` or `.c`.
+    pub inference: HashMap<String, Vec<String>>,
+
+    pub suffixes: Vec<String>,
+    pub phony: HashSet<String>,
+    pub precious: HashSet<String>,
+    pub silent: HashSet<String>,
+    pub ignore: HashSet<String>,
+    pub all_precious: bool,
+    pub all_silent: bool,
+    pub all_ignore: bool,
+
+    /// The commands of `.DEFAULT`.
+    pub default: Option<Vec<String>>,
+
+    /// The first target that is not special nor an inference rule.
+    pub first_target: Option<String>,
+
+    /// Targets in the order they were first named.
+    pub targets: Vec<String>,
+}
+
+/// Splits a target or prerequisite list into names, expanding
+/// `lib(member1 member2)` into `lib(member1) lib(member2)`.
+pub fn split_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        let Some(open) = word.find('(').filter(|_| !word.ends_with(')')) else {
+            names.push(word.to_string());
+            continue;
+        };
+        let library = &word[..open];
+        let mut members = vec![word[open + 1..].to_string()];
+        for next in words.by_ref() {
+            match next.strip_suffix(')') {
+                Some(last) => {
+                    members.push(last.to_string());
+                    break;
+                }
+                None => members.push(next.to_string()),
+            }
+        }
+        for member in members.into_iter().filter(|m| !m.is_empty()) {
+            names.push(format!("{}({})", library, member));
+        }
+    }
+    names
+}
+
+/// Splits `lib(member)` into the library and the member.
+pub fn split_member(name: &str) -> Option<(&str, &str)> {
+    let open = name.find('(')?;
+    let member = name[open + 1..].strip_suffix(')')?;
+    (open > 0 && !member.is_empty()).then(|| (&name[..open], member))
+}
+
+/// Whether `line` ends with a backslash that is not itself escaped.
+fn is_continued(line: &str) -> bool {
+    line.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
+/// Removes a comment, unless the `#` is inside a macro reference.
+fn strip_comment(line: &str) -> &str {
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' if depth > 0 => depth -= 1,
+            '#' if depth == 0 => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The assignment operators, longest first.
+const ASSIGNMENTS: &[&str] = &[":::=", "::=", ":=", "+=", "?=", "!=", "="];
+
+/// If `line` is a macro definition, returns the name, operator and value.
+fn macro_definition(line: &str) -> Option<(&str, &str, &str)> {
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' if depth > 0 => depth -= 1,
+            ':' | '=' | '+' | '?' | '!' if depth == 0 => {
+                let rest = &line[i..];
+                if let Some(op) = ASSIGNMENTS.iter().find(|op| rest.starts_with(**op)) {
+                    let name = line[..i].trim();
+                    return Some((name, op, rest[op.len()..].trim()));
+                }
+                if c == ':' {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Makefile {
+    pub fn new(macros: Macros) -> Self {
+        Makefile {
+            macros,
+            rules: HashMap::new(),
+            inference: HashMap::new(),
+            suffixes: Vec::new(),
+            phony: HashSet::new(),
+            precious: HashSet::new(),
+            silent: HashSet::new(),
+            ignore: HashSet::new(),
+            all_precious: false,
+            all_silent: false,
+            all_ignore: false,
+            default: None,
+            first_target: None,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Whether `name` is `.s1` or `.s1.s2` for known suffixes.
+    pub fn is_inference_rule(&self, name: &str) -> bool {
+        if !name.starts_with('.') || name.contains('/') {
+            return false;
+        }
+        self.suffixes.iter().any(|s1| {
+            name.strip_prefix(s1.as_str())
+                .is_some_and(|s2| s2.is_empty() || self.suffixes.iter().any(|s| s == s2))
+        })
+    }
+
+    pub fn parse(&mut self, text: &str, file: &str) -> Result<(), ParseError> {
+        let lines: Vec<&str> = text.lines().collect();
+        let error = |line: usize, message: String| ParseError {
+            file: file.to_string(),
+            line,
+            message,
+        };
+
+        let mut sinks: Vec<Sink> = Vec::new();
+        let mut has_commands = false;
+        let mut i = 0;
+        while i < lines.len() {
+            let line_number = i + 1;
+            let raw = lines[i];
+            i += 1;
+
+            if raw.starts_with('\t') && !sinks.is_empty() {
+                let mut command = raw[1..].to_string();
+                while is_continued(&command) && i < lines.len() {
+                    command.push('\n');
+                    command.push_str(lines[i].strip_prefix('\t').unwrap_or(lines[i]));
+                    i += 1;
+                }
+                if command.trim().is_empty() {
+                    continue;
+                }
+                self.add_command(&sinks, command, !has_commands);
+                has_commands = true;
+                continue;
+            }
+
+            let mut logical = raw.to_string();
+            while is_continued(&logical) && i < lines.len() {
+                logical.pop();
+                logical.truncate(logical.trim_end().len());
+                logical.push(' ');
+                logical.push_str(lines[i].trim_start());
+                i += 1;
+            }
+            let line = strip_comment(&logical).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            sinks.clear();
+            has_commands = false;
+
+            let directive = line.split_whitespace().next().unwrap_or("");
+            if matches!(directive, "include" | "-include" | "sinclude") {
+                let names = self
+                    .macros
+                    .expand(line[directive.len()..].trim(), None)
+                    .map_err(|e| error(line_number, e))?;
+                for name in names.split_whitespace() {
+                    match std::fs::read_to_string(name) {
+                        Ok(text) => self.parse(&text, name)?,
+                        Err(_) if directive != "include" => {}
+                        Err(e) => return Err(error(line_number, format!("{}: {}", name, e))),
+                    }
+                }
+                continue;
+            }
+
+            if let Some((name, op, value)) = macro_definition(line) {
+                self.define_macro(name, op, value)
+                    .map_err(|e| error(line_number, e))?;
+                continue;
+            }
+
+            let Some(colon) = line.find(':') else {
+                return Err(error(line_number, "missing separator".to_string()));
+            };
+            let (targets, rest) = (&line[..colon], &line[colon + 1..]);
+            let rest = rest.strip_prefix(':').unwrap_or(rest);
+            let (prerequisites, command) = match rest.split_once(';') {
+                Some((prerequisites, command)) => (prerequisites, Some(command.trim_start())),
+                None => (rest, None),
+            };
+            let expand = |text: &str| self.macros.expand(text, None);
+            let targets = split_names(&expand(targets).map_err(|e| error(line_number, e))?);
+            let prerequisites =
+                split_names(&expand(prerequisites).map_err(|e| error(line_number, e))?);
+            if targets.is_empty() {
+                return Err(error(line_number, "missing target".to_string()));
+            }
+
+            for target in targets {
+                if let Some(sink) = self.add_rule(target, &prerequisites) {
+                    sinks.push(sink);
+                }
+            }
+            if let Some(command) = command {
+                self.add_command(&sinks, command.to_string(), true);
+                has_commands = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self, name: &str, op: &str, value: &str) -> Result<(), String> {
+        let name = self.macros.expand(name, None)?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid macro name '{}'", name));
+        }
+        match op {
+            "=" => self
+                .macros
+                .define(&name, value.to_string(), Origin::Makefile, false),
+            "?=" => self
+                .macros
+                .define_default(&name, value.to_string(), Origin::Makefile),
+            "+=" => self.macros.append(&name, value, Origin::Makefile)?,
+            "!=" => {
+                let command = self.macros.expand(value, None)?;
+                let output = Command::new("/bin/sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(|e| format!("{}: {}", command, e))?;
+                let output = String::from_utf8_lossy(&output.stdout);
+                let output = output.strip_suffix('\n').unwrap_or(&output);
+                let value = output.replace('\n', " ");
+                self.macros.define(&name, value, Origin::Makefile, true);
+            }
+            ":::=" => {
+                let value = self.macros.expand(value, None)?.replace('$', "$$");
+                self.macros.define(&name, value, Origin::Makefile, false);
+            }
+            _ => {
+                let value = self.macros.expand(value, None)?;
+                self.macros.define(&name, value, Origin::Makefile, true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a rule line for `target`, returning where its commands go.
+    fn add_rule(&mut self, target: String, prerequisites: &[String]) -> Option<Sink> {
+        let names = || prerequisites.iter().cloned();
+        match target.as_str() {
+            ".SUFFIXES" => {
+                if prerequisites.is_empty() {
+                    self.suffixes.clear();
+                }
+                for name in names() {
+                    if !self.suffixes.contains(&name) {
+                        self.suffixes.push(name);
+                    }
+                }
+                None
+            }
+            ".PHONY" => {
+                self.phony.extend(names());
+                None
+            }
+            ".PRECIOUS" => {
+                self.all_precious |= prerequisites.is_empty();
+                self.precious.extend(names());
+                None
+            }
+            ".SILENT" => {
+                self.all_silent |= prerequisites.is_empty();
+                self.silent.extend(names());
+                None
+            }
+            ".IGNORE" => {
+                self.all_ignore |= prerequisites.is_empty();
+                self.ignore.extend(names());
+                None
+            }
+            ".DEFAULT" => Some(Sink::Default),
+            ".POSIX" | ".NOTPARALLEL" | ".WAIT" | ".SCCS_GET" => None,
+            _ if self.is_inference_rule(&target) => Some(Sink::Inference(target)),
+            _ => {
+                if self.first_target.is_none() && (!target.starts_with('.') || target.contains('/'))
+                {
+                    self.first_target = Some(target.clone());
+                }
+                if !self.rules.contains_key(&target) {
+                    self.targets.push(target.clone());
+                }
+                let rule = self.rules.entry(target.clone()).or_default();
+                for name in names() {
+                    if !rule.prerequisites.contains(&name) {
+                        rule.prerequisites.push(name);
+                    }
+                }
+                Some(Sink::Target(target))
+            }
+        }
+    }
+
+    /// Adds a command line to each of `sinks`; the first command line of a
+    /// rule replaces any commands given by an earlier rule.
+    fn add_command(&mut self, sinks: &[Sink], command: String, first: bool) {
+        for sink in sinks {
+            let commands = match sink {
+                Sink::Target(name) => &mut self.rules.get_mut(name).unwrap().commands,
+                Sink::Inference(name) => {
+                    let commands = self.inference.entry(name.clone()).or_default();
+                    if first {
+                        commands.clear();
+                    }
+                    commands.push(command.clone());
+                    continue;
+                }
+                Sink::Default => &mut self.default,
+            };
+            if first || commands.is_none() {
+                *commands = Some(Vec::new());
+            }
+            if !command.trim().is_empty() {
+                commands.as_mut().unwrap().push(command.clone());
+            }
+        }
+    }
+
+    /// Writes the macros and rules, for `-p`.
+    pub fn print(&self) {
+        let mut macros: Vec<_> = self.macros.iter().collect();
+        macros.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, m) in macros {
+            let op = if m.immediate { "::=" } else { "=" };
+            println!("{} {} {}", name, op, m.value);
+        }
+        println!();
+        println!(".SUFFIXES: {}", self.suffixes.join(" "));
+
+        let print_commands = |commands: &[String]| {
+            for command in commands {
+                println!("\t{}", command);
+            }
+        };
+        let mut inference: Vec<_> = self.inference.iter().collect();
+        inference.sort();
+        for (name, commands) in inference {
+            println!();
+            println!("{}:", name);
+            print_commands(commands);
+        }
+        if let Some(commands) = &self.default {
+            println!();
+            println!(".DEFAULT:");
+            print_commands(commands);
+        }
+        for target in &self.targets {
+            let rule = &self.rules[target];
+            println!();
+            println!("{}: {}", target, rule.prerequisites.join(" "));
+            print_commands(rule.commands.as_deref().unwrap_or_default());
+        }
+    }
+}
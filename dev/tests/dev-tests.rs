@@ -2,6 +2,8 @@ use object::{Object, ObjectSection, ObjectSymbol};
 use plib::{run_test, run_test_with_checker, TestPlan};
 use std::fs;
 
+mod make;
+
 fn ar_compare_test(
     args: &[&str],
     stdout: &str,
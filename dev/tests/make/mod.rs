@@ -0,0 +1,345 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, scratch_dir, TestPlan};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+/// Runs make on `makefile`, read from the standard input.
+fn make_test(args: &[&str], makefile: &str, expected_out: &str, expected_exit_code: i32) {
+    let mut all_args = vec!["-f", "-"];
+    all_args.extend(args);
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: all_args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(makefile),
+        expected_out: String::from(expected_out),
+        expected_err: String::new(),
+        expected_exit_code,
+    });
+}
+
+fn run_make(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_make"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("MAKEFLAGS")
+        .output()
+        .expect("failed to run make")
+}
+
+/// Moves the modification time of `path` into the past, so that files made
+/// afterwards are newer even on file systems with coarse timestamps.
+fn age(path: &Path) {
+    let file = fs::File::options().append(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_make_macro_substitution() {
+    make_test(
+        &[],
+        "\
+OBJS = main.o util.o
+SRCS = $(OBJS:.o=.c)
+DEPS = ${OBJS:%.o=deps/%.d}
+all:
+\t@echo $(SRCS) $(DEPS) '$$HOME'
+",
+        "main.c util.c deps/main.d deps/util.d $HOME\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_macro_assignments() {
+    make_test(
+        &[],
+        "\
+A = $(B)
+B = late
+C ::= $(B)
+B = later
+C += more
+D ?= default
+D ?= ignored
+E != echo from shell
+all:
+\t@echo '$(A)' '$(C)' '$(D)' '$(E)'
+",
+        "later late more default from shell\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_command_line_macros() {
+    make_test(
+        &["NAME=command line"],
+        "NAME = makefile\nall:\n\t@echo $(NAME)\n",
+        "command line\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_internal_macros() {
+    make_test(
+        &["dir/prog.o"],
+        "\
+.SUFFIXES: .o .c
+dir/prog.o: dir/prog.c lib.h lib.h
+\t@echo '$@|$(@D)|$(@F)|$*|$<|$^'
+dir/prog.c lib.h:
+",
+        "dir/prog.o|dir|prog.o|dir/prog|dir/prog.c|dir/prog.c lib.h\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_command_prefixes() {
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: vec![String::from("-f"), String::from("-")],
+        stdin_data: String::from("all:\n\techo shown\n\t@echo quiet\n\t-@exit 3\n\t@echo after\n"),
+        expected_out: String::from("echo shown\nshown\nquiet\nafter\n"),
+        expected_err: String::from("make: [all] Error 3 (ignored)\n"),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_make_silent_and_dry_run() {
+    let makefile = "all:\n\techo one\n\t@echo two\n";
+    make_test(&["-s"], makefile, "one\ntwo\n", 0);
+    make_test(&["-n"], makefile, "echo one\necho two\n", 0);
+    make_test(&[], ".SILENT:\nall:\n\techo quiet\n", "quiet\n", 0);
+}
+
+#[test]
+fn test_make_errors_and_keep_going() {
+    let makefile = "\
+all: bad good
+bad:
+\t@exit 4
+good:
+\t@echo good
+";
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: vec![String::from("-f"), String::from("-")],
+        stdin_data: String::from(makefile),
+        expected_out: String::new(),
+        expected_err: String::from("make: *** [bad] Error 4\n"),
+        expected_exit_code: 2,
+    });
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: vec![String::from("-k"), String::from("-f"), String::from("-")],
+        stdin_data: String::from(makefile),
+        expected_out: String::from("good\n"),
+        expected_err: String::from(
+            "make: *** [bad] Error 4\nmake: Target 'all' not remade because of errors.\n",
+        ),
+        expected_exit_code: 2,
+    });
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: vec![String::from("-i"), String::from("-f"), String::from("-")],
+        stdin_data: String::from(makefile),
+        expected_out: String::from("good\n"),
+        expected_err: String::from("make: [bad] Error 4 (ignored)\n"),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_make_no_rule() {
+    run_test(TestPlan {
+        cmd: String::from("make"),
+        args: vec![
+            String::from("-f"),
+            String::from("-"),
+            String::from("nowhere"),
+        ],
+        stdin_data: String::from("all:\n"),
+        expected_out: String::new(),
+        expected_err: String::from("make: *** No rule to make target 'nowhere'.\n"),
+        expected_exit_code: 2,
+    });
+    make_test(
+        &["nowhere"],
+        ".DEFAULT:\n\t@echo default for $@\n",
+        "default for nowhere\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_phony_and_parallel() {
+    make_test(
+        &["-j", "3"],
+        "\
+.PHONY: all one two three
+all: one two three
+\t@echo done
+one two three:
+\t@sleep 0.1
+",
+        "done\n",
+        0,
+    );
+}
+
+#[test]
+fn test_make_parallel_goals() {
+    // Each goal waits for the other to start, which only happens if both
+    // run at once.
+    let dir = scratch_dir("make-parallel-goals");
+    fs::write(
+        dir.join("makefile"),
+        "\
+.PHONY: one two
+one two:
+\t@touch $@.started; i=0; \\
+\twhile [ ! -f one.started ] || [ ! -f two.started ]; do \\
+\t\t[ $$i -lt 50 ] || exit 1; sleep 0.1; i=$$((i + 1)); \\
+\tdone
+",
+    )
+    .unwrap();
+    let output = run_make(&dir, &["-j", "2", "one", "two"]);
+    assert!(output.status.success());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_make_suffix_rules_and_timestamps() {
+    let dir = scratch_dir("make-suffix");
+    fs::write(
+        dir.join("makefile"),
+        "tool: helper\nhelper:\n\ttouch helper\n",
+    )
+    .unwrap();
+    fs::write(dir.join("tool.sh"), "echo tool\n").unwrap();
+    age(&dir.join("tool.sh"));
+
+    // tool is made from tool.sh by the built-in .sh rule.
+    let output = run_make(&dir, &[]);
+    assert_eq!(
+        stdout(&output),
+        "touch helper\ncp tool.sh tool\nchmod a+x tool\n"
+    );
+    assert!(output.status.success());
+
+    let output = run_make(&dir, &[]);
+    assert_eq!(stdout(&output), "make: 'tool' is up to date.\n");
+    assert_eq!(run_make(&dir, &["-q"]).status.code(), Some(0));
+
+    fs::write(dir.join("helper"), "").unwrap();
+    let output = run_make(&dir, &["-q"]);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_make(&dir, &["-t"]);
+    assert_eq!(stdout(&output), "touch tool\n");
+    assert_eq!(fs::read_to_string(dir.join("tool")).unwrap(), "echo tool\n");
+    assert_eq!(run_make(&dir, &["-q"]).status.code(), Some(0));
+
+    let output = run_make(&dir, &["-r", "-f", "makefile", "tool"]);
+    assert_eq!(stdout(&output), "make: Nothing to be done for 'tool'.\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_make_touch_skips_phony_targets() {
+    let dir = scratch_dir("make-touch-phony");
+    fs::write(
+        dir.join("makefile"),
+        ".PHONY: clean\nclean:\n\trm -f junk\n",
+    )
+    .unwrap();
+    let output = run_make(&dir, &["-t", "clean"]);
+    assert!(!stdout(&output).contains("touch"));
+    assert!(output.status.success());
+    assert!(!dir.join("clean").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_make_include() {
+    let dir = scratch_dir("make-include");
+    fs::write(dir.join("config.mk"), "GREETING = hello\n").unwrap();
+    fs::write(
+        dir.join("Makefile"),
+        "include config.mk\n-include missing.mk\nall:\n\t@echo $(GREETING)\n",
+    )
+    .unwrap();
+    let output = run_make(&dir, &[]);
+    assert_eq!(stdout(&output), "hello\n");
+    assert!(output.status.success());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_make_library_members() {
+    let dir = scratch_dir("make-library");
+    fs::write(
+        dir.join("makefile"),
+        format!(
+            "\
+AR = {}
+ARFLAGS = -r
+lib.a: lib.a(one.o two.o)
+\t@echo updated $@
+lib.a(one.o): one.o
+\t$(AR) $(ARFLAGS) $@ $%
+lib.a(two.o): two.o
+\t$(AR) $(ARFLAGS) $@ $%
+",
+            env!("CARGO_BIN_EXE_ar")
+        ),
+    )
+    .unwrap();
+    for name in ["one.o", "two.o"] {
+        fs::write(dir.join(name), name).unwrap();
+        age(&dir.join(name));
+    }
+
+    let output = run_make(&dir, &[]);
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{ar} -r lib.a one.o\n{ar} -r lib.a two.o\nupdated lib.a\n",
+            ar = env!("CARGO_BIN_EXE_ar")
+        )
+    );
+    assert!(output.status.success());
+    let output = run_make(&dir, &[]);
+    assert_eq!(stdout(&output), "make: 'lib.a' is up to date.\n");
+
+    fs::write(dir.join("two.o"), "changed").unwrap();
+    let output = run_make(&dir, &["-n"]);
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{} -r lib.a two.o\necho updated lib.a\n",
+            env!("CARGO_BIN_EXE_ar")
+        )
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,317 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Code shared by `ed` and by `ex` and `vi`, which are separate programs.
+
+use plib::regex::{bracket_expression_end, Regex};
+use std::{
+    io, mem,
+    ops::Range,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set by the signal handlers of the editors.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+pub static HANGUP: AtomicBool = AtomicBool::new(false);
+
+/// A line of the buffer.  Lines are shared so that undo snapshots are cheap,
+/// and compared by identity so that marks follow the lines they were set on.
+pub type Line = Rc<Vec<u8>>;
+
+/// Splits the contents of a file into lines.  A missing newline at the end
+/// of the last line is supplied.
+pub fn split_lines(content: &[u8]) -> Vec<Vec<u8>> {
+    if content.is_empty() {
+        return Vec::new();
+    }
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    content.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+}
+
+/// Finds the next line matching `regex` among lines 1 to `len`, searching
+/// forward or backward from line `from`, and around the buffer if `wrap` is
+/// set.  `line` returns the text of a line given its number.
+pub fn search<'a>(
+    regex: &Regex,
+    len: usize,
+    from: usize,
+    forward: bool,
+    wrap: bool,
+    line: impl Fn(usize) -> &'a [u8],
+) -> Option<usize> {
+    for i in 1..=len {
+        let n = if forward {
+            if !wrap && from + i > len {
+                break;
+            }
+            (from + i - 1) % len + 1
+        } else {
+            if !wrap && i >= from {
+                break;
+            }
+            (from + len - i - 1) % len + 1
+        };
+        if regex.is_match(line(n)) {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// The text of a command, read from left to right.
+pub struct CommandLine {
+    pub text: Vec<u8>,
+    pub pos: usize,
+}
+
+impl CommandLine {
+    pub fn new(text: Vec<u8>) -> Self {
+        CommandLine { text, pos: 0 }
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    pub fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    pub fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads a decimal number, or returns `None` if it is out of range.
+    pub fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .unwrap()
+            .parse()
+            .ok()
+    }
+
+    /// Applies the offsets that follow an address, each relative to what
+    /// precedes it or, without an address, to line `current`.  Returns `None`
+    /// if a number is out of range.
+    pub fn offsets(&mut self, address: &mut Option<i64>, current: i64) -> Option<()> {
+        loop {
+            self.skip_blanks();
+            let sign = match self.peek() {
+                Some(b'+') => 1,
+                Some(b'-' | b'^') => -1,
+                Some(b'0'..=b'9') if address.is_some() => {
+                    let n = self.number()? as i64;
+                    *address = address.map(|a| a + n);
+                    continue;
+                }
+                _ => return Some(()),
+            };
+            self.next();
+            let n = if matches!(self.peek(), Some(b'0'..=b'9')) {
+                self.number()? as i64
+            } else {
+                1
+            };
+            *address = Some(address.unwrap_or(current) + sign * n);
+        }
+    }
+
+    /// Takes the rest of the text, separators included.
+    pub fn rest(&mut self) -> Vec<u8> {
+        let rest = self.text[self.pos..].to_vec();
+        self.pos = self.text.len();
+        rest
+    }
+
+    /// Copies a bracket expression, whose `[` has already been copied, up to
+    /// and including its closing `]`.
+    pub fn copy_bracket_expression(&mut self, pattern: &mut Vec<u8>) -> Option<()> {
+        let end = bracket_expression_end(&self.text, self.pos - 1)?;
+        pattern.extend_from_slice(&self.text[self.pos..end]);
+        self.pos = end;
+        Some(())
+    }
+}
+
+/// Why a line could not be read from the standard input.
+pub enum ReadError {
+    Interrupted,
+    HungUp,
+    Io(io::Error),
+}
+
+/// The standard input, read in lines.  Reads are not retried after a
+/// signal, unlike those of `io::Stdin`.
+#[derive(Default)]
+pub struct Input {
+    buffer: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Input {
+    /// Reads a line without its newline, or `None` at end of file.
+    pub fn read_line(&mut self) -> Result<Option<Vec<u8>>, ReadError> {
+        loop {
+            if let Some(n) = self.buffer[self.pos..].iter().position(|&b| b == b'\n') {
+                let line = self.buffer[self.pos..self.pos + n].to_vec();
+                self.pos += n + 1;
+                return Ok(Some(line));
+            }
+            if self.eof {
+                if self.pos < self.buffer.len() {
+                    let line = self.buffer[self.pos..].to_vec();
+                    self.pos = self.buffer.len();
+                    return Ok(Some(line));
+                }
+                return Ok(None);
+            }
+            self.buffer.drain(..self.pos);
+            self.pos = 0;
+
+            let mut chunk = [0u8; 4096];
+            let n = unsafe { libc::read(0, chunk.as_mut_ptr() as *mut libc::c_void, chunk.len()) };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(ReadError::Io(err));
+                }
+                if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                    self.buffer.clear();
+                    return Err(ReadError::Interrupted);
+                }
+                if HANGUP.load(Ordering::SeqCst) {
+                    return Err(ReadError::HungUp);
+                }
+                continue;
+            }
+            if n == 0 {
+                self.eof = true;
+            }
+            self.buffer.extend_from_slice(&chunk[..n as usize]);
+        }
+    }
+}
+
+/// A change of case requested in a replacement.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Case {
+    /// `\u` and `\l`: the next character only.
+    UpperNext,
+    LowerNext,
+
+    /// `\U` and `\L`: up to `\E` or `\e`.
+    Upper,
+    Lower,
+    End,
+}
+
+pub enum ReplacementPart {
+    Literal(Vec<u8>),
+    /// `&` is group 0, `\1` to `\9` are groups 1 to 9.
+    Group(usize),
+    Case(Case),
+}
+
+/// Parses the replacement of a `substitute` command, in which `&` stands for
+/// the matched text, `\1` to `\9` for subexpressions, and an escaped newline
+/// splits the line.  Without `magic`, `&` is special only when escaped.  With
+/// `case_changes`, `\u`, `\l`, `\U`, `\L`, `\E` and `\e` change case.
+pub fn parse_replacement(raw: &[u8], magic: bool, case_changes: bool) -> Vec<ReplacementPart> {
+    let mut parts = Vec::new();
+    let mut literal = Vec::new();
+    let mut i = 0;
+    while i < raw.len() {
+        let part = match raw[i] {
+            b'&' if magic => Some(ReplacementPart::Group(0)),
+            b'\\' if i + 1 < raw.len() => {
+                i += 1;
+                match raw[i] {
+                    d @ b'1'..=b'9' => Some(ReplacementPart::Group((d - b'0') as usize)),
+                    b'&' if !magic => Some(ReplacementPart::Group(0)),
+                    b'u' if case_changes => Some(ReplacementPart::Case(Case::UpperNext)),
+                    b'l' if case_changes => Some(ReplacementPart::Case(Case::LowerNext)),
+                    b'U' if case_changes => Some(ReplacementPart::Case(Case::Upper)),
+                    b'L' if case_changes => Some(ReplacementPart::Case(Case::Lower)),
+                    b'E' | b'e' if case_changes => Some(ReplacementPart::Case(Case::End)),
+                    c => {
+                        literal.push(c);
+                        None
+                    }
+                }
+            }
+            c => {
+                literal.push(c);
+                None
+            }
+        };
+        if let Some(part) = part {
+            if !literal.is_empty() {
+                parts.push(ReplacementPart::Literal(mem::take(&mut literal)));
+            }
+            parts.push(part);
+        }
+        i += 1;
+    }
+    if !literal.is_empty() {
+        parts.push(ReplacementPart::Literal(literal));
+    }
+    parts
+}
+
+/// Appends replacement text, applying the case changes in effect.
+fn push_cased(result: &mut Vec<u8>, text: &[u8], next: &mut Option<Case>, span: Option<Case>) {
+    for &c in text {
+        let c = match next.take() {
+            Some(Case::UpperNext) => c.to_ascii_uppercase(),
+            Some(Case::LowerNext) => c.to_ascii_lowercase(),
+            _ => match span {
+                Some(Case::Upper) => c.to_ascii_uppercase(),
+                Some(Case::Lower) => c.to_ascii_lowercase(),
+                _ => c,
+            },
+        };
+        result.push(c);
+    }
+}
+
+/// Builds the replacement for one match of a regex in `line`.
+pub fn expand_replacement(
+    replacement: &[ReplacementPart],
+    line: &[u8],
+    captures: &[Option<Range<usize>>],
+) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut next = None;
+    let mut span = None;
+    for part in replacement {
+        match part {
+            ReplacementPart::Literal(literal) => push_cased(&mut result, literal, &mut next, span),
+            ReplacementPart::Group(n) => {
+                if let Some(Some(group)) = captures.get(*n) {
+                    push_cased(&mut result, &line[group.clone()], &mut next, span);
+                }
+            }
+            ReplacementPart::Case(case @ (Case::UpperNext | Case::LowerNext)) => {
+                next = Some(*case);
+            }
+            ReplacementPart::Case(Case::End) => span = None,
+            ReplacementPart::Case(case) => span = Some(*case),
+        }
+    }
+    result
+}
@@ -0,0 +1,1178 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod common;
+
+use clap::Parser;
+use common::{
+    expand_replacement, parse_replacement, split_lines, CommandLine, Input, Line, ReadError,
+    ReplacementPart, HANGUP, INTERRUPTED,
+};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::regex::{Regex, RegexFlags};
+use plib::PROJECT_NAME;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    mem,
+    process::{Command, Stdio},
+    ptr,
+    rc::Rc,
+    sync::atomic::Ordering,
+};
+
+/// ed - edit text
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Use STRING as the prompt in command mode.
+    #[arg(short = 'p', value_name = "STRING")]
+    prompt: Option<String>,
+
+    /// Suppress the byte counts of the e, E, r and w commands, and the `!`
+    /// written after shell commands.
+    #[arg(short = 's')]
+    silent: bool,
+
+    /// The file to edit.
+    file: Option<String>,
+}
+
+/// Replaces matches of `regex` in `line`: the `occurrence`th match, and with
+/// `global` every one after it.  Returns `None` if nothing was replaced.
+fn substitute(
+    regex: &Regex,
+    replacement: &[ReplacementPart],
+    line: &[u8],
+    occurrence: usize,
+    global: bool,
+) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(line.len());
+    let mut copied = 0;
+    let mut start = 0;
+    let mut count = 0;
+    let mut previous_end = None;
+    let mut replaced = false;
+
+    while start <= line.len() {
+        let Some(captures) = regex.captures_at(line, start) else {
+            break;
+        };
+        let whole = captures[0].clone().unwrap();
+        let (match_start, match_end) = (whole.start, whole.end);
+
+        // An empty match right after the previous match is not a match of
+        // its own.
+        if match_start == match_end && previous_end == Some(match_start) {
+            if match_start >= line.len() {
+                break;
+            }
+            start = match_start + 1;
+            continue;
+        }
+
+        count += 1;
+        if count >= occurrence {
+            result.extend_from_slice(&line[copied..match_start]);
+            result.extend(expand_replacement(replacement, line, &captures));
+            copied = match_end;
+            replaced = true;
+            if !global {
+                break;
+            }
+        }
+
+        previous_end = Some(match_end);
+        if match_start == match_end {
+            if match_end >= line.len() {
+                break;
+            }
+            start = match_end + 1;
+        } else {
+            start = match_end;
+        }
+    }
+
+    if !replaced {
+        return None;
+    }
+    result.extend_from_slice(&line[copied..]);
+    Some(result)
+}
+
+/// Formats a line in the unambiguous form of the `l` command.
+fn list_line(line: &[u8]) -> Vec<u8> {
+    const LINE_LENGTH: usize = 72;
+
+    let mut output = Vec::new();
+    let mut column = 0;
+    for &b in line {
+        let escaped = match b {
+            b'\\' => "\\\\".to_string(),
+            0x07 => "\\a".to_string(),
+            0x08 => "\\b".to_string(),
+            0x0c => "\\f".to_string(),
+            b'\r' => "\\r".to_string(),
+            b'\t' => "\\t".to_string(),
+            0x0b => "\\v".to_string(),
+            b' '..=b'~' => char::from(b).to_string(),
+            b => format!("\\{:03o}", b),
+        };
+        if column + escaped.len() > LINE_LENGTH - 1 {
+            output.extend_from_slice(b"\\\n");
+            column = 0;
+        }
+        column += escaped.len();
+        output.extend_from_slice(escaped.as_bytes());
+    }
+    output.extend_from_slice(b"$\n");
+    output
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == libc::SIGHUP {
+        HANGUP.store(true, Ordering::SeqCst);
+    } else {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Catches SIGINT and SIGHUP without restarting reads, so that an interrupt
+/// abandons the command being typed.
+fn install_signal_handlers() {
+    for signal in [libc::SIGINT, libc::SIGHUP] {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction =
+                handle_signal as *const extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+    unsafe { libc::signal(libc::SIGQUIT, libc::SIG_IGN) };
+}
+
+/// The `l`, `n` and `p` suffixes of a command.
+#[derive(Clone, Copy, Default)]
+struct Print {
+    list: bool,
+    number: bool,
+    print: bool,
+}
+
+impl Print {
+    fn any(&self) -> bool {
+        self.list || self.number || self.print
+    }
+}
+
+/// The state `u` returns to.
+struct Snapshot {
+    lines: Vec<Line>,
+    current: usize,
+    modified: bool,
+}
+
+struct Ed {
+    lines: Vec<Line>,
+
+    /// The current line number, 0 only when the buffer is empty.
+    current: usize,
+    marks: HashMap<u8, Line>,
+    modified: bool,
+
+    /// The previous command was a `q` or `e` refused because the buffer was
+    /// modified, so repeating it succeeds.
+    warned: bool,
+    filename: Option<String>,
+    last_regex: Option<Rc<Regex>>,
+    last_replacement: Option<Vec<u8>>,
+    last_shell_command: Option<String>,
+    last_global_command: Option<Vec<u8>>,
+    prompt: String,
+    prompting: bool,
+    silent: bool,
+    explain: bool,
+    last_error: Option<String>,
+    undo: Option<Snapshot>,
+    input: Input,
+
+    /// The remaining lines of the command list of a global command, which
+    /// are its commands and their input text.
+    pending: Option<VecDeque<Vec<u8>>>,
+    interactive: bool,
+    out: io::Stdout,
+}
+
+impl Ed {
+    fn new(args: &Args) -> Self {
+        Ed {
+            lines: Vec::new(),
+            current: 0,
+            marks: HashMap::new(),
+            modified: false,
+            warned: false,
+            filename: None,
+            last_regex: None,
+            last_replacement: None,
+            last_shell_command: None,
+            last_global_command: None,
+            prompt: args.prompt.clone().unwrap_or_else(|| String::from("*")),
+            prompting: args.prompt.is_some(),
+            silent: args.silent,
+            explain: false,
+            last_error: None,
+            undo: None,
+            input: Input::default(),
+            pending: None,
+            interactive: unsafe { libc::isatty(0) } == 1,
+            out: io::stdout(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let _ = self.out.write_all(bytes);
+    }
+
+    /// Reports an error: a `?`, followed by the message in help mode.
+    fn report(&mut self, message: String) {
+        self.write(b"?\n");
+        if self.explain {
+            self.write(format!("{}\n", message).as_bytes());
+        }
+        let _ = self.out.flush();
+        self.last_error = Some(message);
+    }
+
+    /// Reads a line of input: the next line of a global command's list, or
+    /// a line of the standard input.
+    fn next_input_line(&mut self) -> Result<Option<Vec<u8>>, String> {
+        match &mut self.pending {
+            Some(pending) => Ok(pending.pop_front()),
+            None => {
+                let _ = self.out.flush();
+                self.read_input_line()
+            }
+        }
+    }
+
+    /// Reads a line of the standard input.
+    fn read_input_line(&mut self) -> Result<Option<Vec<u8>>, String> {
+        self.input.read_line().map_err(|err| match err {
+            ReadError::Interrupted => gettext("interrupt"),
+            ReadError::HungUp => gettext("hangup"),
+            ReadError::Io(err) => err.to_string(),
+        })
+    }
+
+    /// Reads the text of `a`, `c` and `i`, up to a line holding only `.`.
+    fn read_text(&mut self) -> Result<Vec<Line>, String> {
+        let mut text = Vec::new();
+        while let Some(line) = self.next_input_line()? {
+            if line == b"." {
+                break;
+            }
+            text.push(Rc::new(line));
+        }
+        Ok(text)
+    }
+
+    fn checkpoint(&mut self) {
+        if self.pending.is_none() {
+            self.undo = Some(Snapshot {
+                lines: self.lines.clone(),
+                current: self.current,
+                modified: self.modified,
+            });
+        }
+    }
+
+    fn print_lines(&mut self, first: usize, last: usize, print: Print) {
+        for n in first..=last {
+            let line = Rc::clone(&self.lines[n - 1]);
+            if print.number {
+                self.write(format!("{}\t", n).as_bytes());
+            }
+            if print.list {
+                self.write(&list_line(&line));
+            } else {
+                self.write(&line);
+                self.write(b"\n");
+            }
+        }
+        self.current = last;
+    }
+
+    fn line_of_mark(&self, mark: u8) -> Result<usize, String> {
+        self.marks
+            .get(&mark)
+            .and_then(|line| self.lines.iter().position(|l| Rc::ptr_eq(l, line)))
+            .map(|index| index + 1)
+            .ok_or_else(|| gettext("invalid address"))
+    }
+
+    /// Parses a regular expression ended by `delimiter`, or by the end of
+    /// the command unless `closed` is required.  An empty one stands for
+    /// the last regular expression used.
+    fn parse_regex(
+        &mut self,
+        cmd: &mut CommandLine,
+        delimiter: u8,
+        closed: bool,
+    ) -> Result<Rc<Regex>, String> {
+        let mut pattern = Vec::new();
+        loop {
+            match cmd.next() {
+                None if closed => return Err(gettext("missing pattern delimiter")),
+                None => break,
+                Some(c) if c == delimiter => break,
+                Some(b'\\') => match cmd.next() {
+                    None => return Err(gettext("trailing backslash (\\)")),
+                    Some(c) if c == delimiter => pattern.push(c),
+                    Some(c) => pattern.extend_from_slice(&[b'\\', c]),
+                },
+                Some(b'[') => {
+                    pattern.push(b'[');
+                    cmd.copy_bracket_expression(&mut pattern)
+                        .ok_or_else(|| gettext("unbalanced brackets ([)"))?;
+                }
+                Some(c) => pattern.push(c),
+            }
+        }
+        if pattern.is_empty() {
+            return self
+                .last_regex
+                .clone()
+                .ok_or_else(|| gettext("no previous regular expression"));
+        }
+        let regex = Regex::new(&pattern, RegexFlags::default()).map_err(|err| err.to_string())?;
+        let regex = Rc::new(regex);
+        self.last_regex = Some(Rc::clone(&regex));
+        Ok(regex)
+    }
+
+    /// Finds the next line matching `regex`, searching forward or backward
+    /// from the current line and wrapping around the buffer.
+    fn search(&self, regex: &Regex, forward: bool) -> Result<usize, String> {
+        common::search(regex, self.lines.len(), self.current, forward, true, |n| {
+            &self.lines[n - 1]
+        })
+        .ok_or_else(|| gettext("no match"))
+    }
+
+    fn parse_address(&mut self, cmd: &mut CommandLine) -> Result<Option<usize>, String> {
+        cmd.skip_blanks();
+        let mut address = match cmd.peek() {
+            Some(b'.') => {
+                cmd.next();
+                Some(self.current as i64)
+            }
+            Some(b'$') => {
+                cmd.next();
+                Some(self.lines.len() as i64)
+            }
+            Some(b'0'..=b'9') => {
+                Some(cmd.number().ok_or_else(|| gettext("number out of range"))? as i64)
+            }
+            Some(b'\'') => {
+                cmd.next();
+                match cmd.next() {
+                    Some(mark @ b'a'..=b'z') => Some(self.line_of_mark(mark)? as i64),
+                    _ => return Err(gettext("invalid mark character")),
+                }
+            }
+            Some(delimiter @ (b'/' | b'?')) => {
+                cmd.next();
+                let regex = self.parse_regex(cmd, delimiter, false)?;
+                Some(self.search(&regex, delimiter == b'/')? as i64)
+            }
+            _ => None,
+        };
+
+        cmd.offsets(&mut address, self.current as i64)
+            .ok_or_else(|| gettext("number out of range"))?;
+
+        match address {
+            Some(a) if a < 0 || a > self.lines.len() as i64 => Err(gettext("invalid address")),
+            a => Ok(a.map(|a| a as usize)),
+        }
+    }
+
+    /// Parses the addresses of a command, returning how many were given
+    /// and the last two.
+    fn parse_addresses(&mut self, cmd: &mut CommandLine) -> Result<(usize, usize, usize), String> {
+        let mut addresses = Vec::new();
+        let mut address = self.parse_address(cmd)?;
+        loop {
+            cmd.skip_blanks();
+            let separator = match cmd.peek() {
+                Some(c @ (b',' | b';')) => c,
+                _ => break,
+            };
+            cmd.next();
+            let explicit = address.is_some();
+            let first = match address {
+                Some(a) => a,
+                None if separator == b',' => 1,
+                None => self.current,
+            };
+            if separator == b';' {
+                self.current = first;
+            }
+            addresses.push(first);
+            address = self.parse_address(cmd)?;
+            if address.is_none() {
+                address = Some(if explicit { first } else { self.lines.len() });
+            }
+        }
+        if let Some(a) = address {
+            addresses.push(a);
+        }
+        Ok(match addresses.len() {
+            0 => (0, self.current, self.current),
+            1 => (1, addresses[0], addresses[0]),
+            n => (n, addresses[n - 2], addresses[n - 1]),
+        })
+    }
+
+    /// Parses the `l`, `n` and `p` suffixes that may end a command.
+    fn parse_suffix(&self, cmd: &mut CommandLine) -> Result<Print, String> {
+        let mut print = Print::default();
+        loop {
+            match cmd.next() {
+                Some(b'l') => print.list = true,
+                Some(b'n') => print.number = true,
+                Some(b'p') => print.print = true,
+                None => return Ok(print),
+                Some(_) => return Err(gettext("invalid command suffix")),
+            }
+        }
+    }
+
+    fn expect_end(&self, cmd: &mut CommandLine) -> Result<(), String> {
+        cmd.skip_blanks();
+        match cmd.peek() {
+            None => Ok(()),
+            Some(_) => Err(gettext("invalid command suffix")),
+        }
+    }
+
+    /// Parses the file operand of `e`, `E`, `f`, `r` and `w`: a file name,
+    /// or `!` and a shell command.
+    fn parse_filename(&self, cmd: &mut CommandLine) -> Result<Option<String>, String> {
+        let rest = cmd.rest();
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if !matches!(rest[0], b' ' | b'\t') {
+            return Err(gettext("unexpected command suffix"));
+        }
+        let name = String::from_utf8_lossy(&rest).trim_start().to_string();
+        Ok((!name.is_empty()).then_some(name))
+    }
+
+    fn current_filename(&self, name: Option<String>) -> Result<String, String> {
+        name.or_else(|| self.filename.clone())
+            .ok_or_else(|| gettext("no current filename"))
+    }
+
+    /// Reads a file, or the output of a shell command for a name that starts
+    /// with `!`, returning its lines and size in bytes.
+    fn read_file(&mut self, name: &str) -> Result<(Vec<Line>, usize), String> {
+        let content = match name.strip_prefix('!') {
+            Some(command) => {
+                let _ = self.out.flush();
+                Command::new("sh")
+                    .args(["-c", command])
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(|err| format!("{}: {}", command, err))?
+                    .stdout
+            }
+            None => fs::read(name).map_err(|err| {
+                eprintln!("{}: {}", name, err);
+                gettext("cannot open input file")
+            })?,
+        };
+        Ok((
+            split_lines(&content).into_iter().map(Rc::new).collect(),
+            content.len(),
+        ))
+    }
+
+    /// Writes lines `first` to `last` to a file, or to a shell command for a
+    /// name that starts with `!`, returning the number of bytes written.
+    fn write_file(
+        &mut self,
+        name: &str,
+        first: usize,
+        last: usize,
+        append: bool,
+    ) -> Result<usize, String> {
+        let mut content = Vec::new();
+        if first > 0 {
+            for line in &self.lines[first - 1..last] {
+                content.extend_from_slice(line);
+                content.push(b'\n');
+            }
+        }
+        match name.strip_prefix('!') {
+            Some(command) => {
+                let _ = self.out.flush();
+                let mut child = Command::new("sh")
+                    .args(["-c", command])
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|err| format!("{}: {}", command, err))?;
+                let _ = child.stdin.take().unwrap().write_all(&content);
+                let _ = child.wait();
+            }
+            None => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(name)
+                    .map_err(|err| {
+                        eprintln!("{}: {}", name, err);
+                        gettext("cannot open output file")
+                    })?;
+                file.write_all(&content).map_err(|err| {
+                    eprintln!("{}: {}", name, err);
+                    gettext("cannot write file")
+                })?;
+            }
+        }
+        Ok(content.len())
+    }
+
+    fn print_size(&mut self, size: usize) {
+        if !self.silent {
+            self.write(format!("{}\n", size).as_bytes());
+        }
+    }
+
+    /// Runs a shell command for `!`, in which an unescaped `%` stands for
+    /// the current file name and a leading `!` for the previous command.
+    fn shell_escape(&mut self, text: &[u8]) -> Result<(), String> {
+        let text = String::from_utf8_lossy(text).into_owned();
+        let mut command = String::new();
+        let mut expanded = false;
+        let mut chars = text.chars().peekable();
+        if chars.peek() == Some(&'!') {
+            chars.next();
+            let previous = self
+                .last_shell_command
+                .clone()
+                .ok_or_else(|| gettext("no previous command"))?;
+            command.push_str(&previous);
+            expanded = true;
+        }
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'%') => command.push(chars.next().unwrap()),
+                '%' => {
+                    let name = self.current_filename(None)?;
+                    command.push_str(&name);
+                    expanded = true;
+                }
+                c => command.push(c),
+            }
+        }
+        if expanded {
+            self.write(format!("{}\n", command).as_bytes());
+        }
+        let _ = self.out.flush();
+        let _ = Command::new("sh").args(["-c", &command]).status();
+        self.last_shell_command = Some(command);
+        if !self.silent {
+            self.write(b"!\n");
+        }
+        Ok(())
+    }
+
+    /// Runs a global command: `commands` for each line in the range that
+    /// matches (or with `invert`, does not match) `regex`.
+    fn global(
+        &mut self,
+        first: usize,
+        last: usize,
+        regex: &Regex,
+        invert: bool,
+        commands: Option<Vec<u8>>,
+    ) -> Result<(), String> {
+        let marked: Vec<Line> = self.lines[first - 1..last]
+            .iter()
+            .filter(|line| regex.is_match(line) != invert)
+            .cloned()
+            .collect();
+        self.checkpoint();
+
+        // Marked lines are found by identity, searching on from where the
+        // previous one was, since commands may have moved or deleted lines.
+        let mut position = 0;
+        for line in marked {
+            let len = self.lines.len();
+            let found = (position..len)
+                .chain(0..position.min(len))
+                .find(|&i| Rc::ptr_eq(&self.lines[i], &line));
+            let Some(index) = found else {
+                continue;
+            };
+            position = index;
+            self.current = index + 1;
+
+            let list = match &commands {
+                Some(list) => list.clone(),
+                None => {
+                    // Interactive: show the line and read its command.
+                    self.print_lines(index + 1, index + 1, Print::default());
+                    let _ = self.out.flush();
+                    let Some(list) = self.read_input_line()? else {
+                        return Ok(());
+                    };
+                    match list.as_slice() {
+                        b"" => continue,
+                        b"&" => self
+                            .last_global_command
+                            .clone()
+                            .ok_or_else(|| gettext("no previous command"))?,
+                        _ => {
+                            self.last_global_command = Some(list.clone());
+                            list
+                        }
+                    }
+                }
+            };
+
+            let pending = list.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect();
+            self.pending = Some(pending);
+            let result = self.run_pending();
+            self.pending = None;
+            result?;
+        }
+        Ok(())
+    }
+
+    fn run_pending(&mut self) -> Result<(), String> {
+        while let Some(command) = self.pending.as_mut().and_then(VecDeque::pop_front) {
+            if self.execute(command)?.is_some() {
+                return Err(gettext("invalid command"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one command, returning the exit status if it quits the editor.
+    fn execute(&mut self, text: Vec<u8>) -> Result<Option<i32>, String> {
+        let warned = mem::take(&mut self.warned);
+        let mut cmd = CommandLine::new(text);
+        let (count, first, last) = self.parse_addresses(&mut cmd)?;
+        let len = self.lines.len();
+
+        // Checks the addresses of a command taking a range of lines.
+        let range = |default_first: usize, default_last: usize| {
+            let (first, last) = if count == 0 {
+                (default_first, default_last)
+            } else {
+                (first, last)
+            };
+            if first == 0 || first > last || last > len {
+                Err(gettext("invalid address"))
+            } else {
+                Ok((first, last))
+            }
+        };
+        let no_address = || {
+            if count > 0 {
+                Err(gettext("unexpected address"))
+            } else {
+                Ok(())
+            }
+        };
+
+        let Some(command) = cmd.next() else {
+            // A null command prints the next line, or the addressed one.
+            let n = if count == 0 { self.current + 1 } else { last };
+            if n == 0 || n > len {
+                return Err(gettext("invalid address"));
+            }
+            self.print_lines(n, n, Print::default());
+            return Ok(None);
+        };
+
+        let mut print = Print::default();
+        match command {
+            b'a' | b'i' => {
+                print = self.parse_suffix(&mut cmd)?;
+                let address = last;
+                let at = if command == b'a' {
+                    address
+                } else {
+                    address.saturating_sub(1)
+                };
+                self.checkpoint();
+                let text = self.read_text()?;
+                let n = text.len();
+                self.lines.splice(at..at, text);
+                self.current = if n > 0 { at + n } else { address.max(at) };
+                self.modified |= n > 0;
+            }
+            b'c' => {
+                let (first, last) = range(self.current, self.current)?;
+                print = self.parse_suffix(&mut cmd)?;
+                self.checkpoint();
+                let text = self.read_text()?;
+                let n = text.len();
+                self.lines.splice(first - 1..last, text);
+                self.current = if n > 0 {
+                    first - 1 + n
+                } else {
+                    first.min(self.lines.len())
+                };
+                self.modified = true;
+            }
+            b'd' => {
+                let (first, last) = range(self.current, self.current)?;
+                print = self.parse_suffix(&mut cmd)?;
+                self.checkpoint();
+                self.lines.drain(first - 1..last);
+                self.current = first.min(self.lines.len());
+                self.modified = true;
+            }
+            b'e' | b'E' => {
+                no_address()?;
+                let name = self.parse_filename(&mut cmd)?;
+                if command == b'e' && self.modified && !warned {
+                    self.warned = true;
+                    return Err(gettext("warning: buffer modified"));
+                }
+                let name = self.current_filename(name)?;
+                let (lines, size) = self.read_file(&name)?;
+                if !name.starts_with('!') {
+                    self.filename = Some(name);
+                }
+                self.lines = lines;
+                self.current = self.lines.len();
+                self.marks.clear();
+                self.modified = false;
+                self.undo = None;
+                self.print_size(size);
+            }
+            b'f' => {
+                no_address()?;
+                if let Some(name) = self.parse_filename(&mut cmd)? {
+                    if name.starts_with('!') {
+                        return Err(gettext("invalid redirection"));
+                    }
+                    self.filename = Some(name);
+                }
+                let name = self.current_filename(None)?;
+                self.write(format!("{}\n", name).as_bytes());
+            }
+            b'g' | b'v' | b'G' | b'V' => {
+                if self.pending.is_some() {
+                    return Err(gettext("cannot nest global commands"));
+                }
+                let (first, last) = range(1, len)?;
+                let delimiter = match cmd.next() {
+                    Some(c) if !matches!(c, b' ' | b'\n') => c,
+                    _ => return Err(gettext("invalid pattern delimiter")),
+                };
+                let regex = self.parse_regex(&mut cmd, delimiter, false)?;
+                let invert = matches!(command, b'v' | b'V');
+                let commands = if matches!(command, b'g' | b'v') {
+                    // A command list continues over lines ending in `\`.
+                    let mut list = cmd.rest();
+                    while list.last() == Some(&b'\\') {
+                        list.pop();
+                        list.push(b'\n');
+                        match self.read_input_line()? {
+                            Some(line) => list.extend_from_slice(&line),
+                            None => break,
+                        }
+                    }
+                    if list.is_empty() {
+                        list.push(b'p');
+                    }
+                    Some(list)
+                } else {
+                    self.expect_end(&mut cmd)?;
+                    None
+                };
+                self.global(first, last, &regex, invert, commands)?;
+            }
+            b'h' => {
+                no_address()?;
+                self.expect_end(&mut cmd)?;
+                if let Some(error) = self.last_error.clone() {
+                    self.write(format!("{}\n", error).as_bytes());
+                }
+            }
+            b'H' => {
+                no_address()?;
+                self.expect_end(&mut cmd)?;
+                self.explain = !self.explain;
+                if self.explain {
+                    if let Some(error) = self.last_error.clone() {
+                        self.write(format!("{}\n", error).as_bytes());
+                    }
+                }
+            }
+            b'j' => {
+                let (first, last) = range(self.current, self.current + 1)?;
+                print = self.parse_suffix(&mut cmd)?;
+                if first < last {
+                    self.checkpoint();
+                    let joined: Vec<u8> = self.lines[first - 1..last]
+                        .iter()
+                        .flat_map(|line| line.iter().copied())
+                        .collect();
+                    self.lines.splice(first - 1..last, [Rc::new(joined)]);
+                    self.current = first;
+                    self.modified = true;
+                }
+            }
+            b'k' => {
+                let (_, address) = range(self.current, self.current)?;
+                match cmd.next() {
+                    Some(mark @ b'a'..=b'z') => {
+                        self.expect_end(&mut cmd)?;
+                        let line = Rc::clone(&self.lines[address - 1]);
+                        self.marks.insert(mark, line);
+                    }
+                    _ => return Err(gettext("invalid mark character")),
+                }
+            }
+            b'l' | b'n' | b'p' => {
+                let (first, last) = range(self.current, self.current)?;
+                cmd.pos -= 1;
+                let print = self.parse_suffix(&mut cmd)?;
+                self.print_lines(first, last, print);
+                return Ok(None);
+            }
+            b'm' | b't' => {
+                let (first, last) = range(self.current, self.current)?;
+                let destination = self
+                    .parse_address(&mut cmd)?
+                    .ok_or_else(|| gettext("destination expected"))?;
+                print = self.parse_suffix(&mut cmd)?;
+                if command == b'm' {
+                    if destination >= first && destination < last {
+                        return Err(gettext("invalid destination"));
+                    }
+                    self.checkpoint();
+                    let n = last - first + 1;
+                    if destination == last || destination + 1 == first {
+                        self.current = last;
+                    } else {
+                        let block: Vec<Line> = self.lines.drain(first - 1..last).collect();
+                        let at = if destination < first {
+                            destination
+                        } else {
+                            destination - n
+                        };
+                        self.lines.splice(at..at, block);
+                        self.current = at + n;
+                        self.modified = true;
+                    }
+                } else {
+                    self.checkpoint();
+                    let block: Vec<Line> = self.lines[first - 1..last]
+                        .iter()
+                        .map(|line| Rc::new(line.to_vec()))
+                        .collect();
+                    let n = block.len();
+                    self.lines.splice(destination..destination, block);
+                    self.current = destination + n;
+                    self.modified = true;
+                }
+            }
+            b'P' => {
+                no_address()?;
+                self.expect_end(&mut cmd)?;
+                self.prompting = !self.prompting;
+            }
+            b'q' | b'Q' => {
+                no_address()?;
+                self.expect_end(&mut cmd)?;
+                if self.pending.is_some() {
+                    return Err(gettext("invalid command"));
+                }
+                if command == b'q' && self.modified && !warned {
+                    self.warned = true;
+                    return Err(gettext("warning: buffer modified"));
+                }
+                return Ok(Some(0));
+            }
+            b'r' => {
+                let address = if count == 0 { len } else { last };
+                let name = self.parse_filename(&mut cmd)?;
+                let name = self.current_filename(name)?;
+                let (lines, size) = self.read_file(&name)?;
+                if self.filename.is_none() && !name.starts_with('!') {
+                    self.filename = Some(name);
+                }
+                self.checkpoint();
+                let n = lines.len();
+                self.lines.splice(address..address, lines);
+                self.current = address + n;
+                self.modified |= n > 0;
+                self.print_size(size);
+            }
+            b's' => {
+                let (first, last) = range(self.current, self.current)?;
+                print = self.substitute_command(&mut cmd, first, last)?;
+            }
+            b'u' => {
+                no_address()?;
+                print = self.parse_suffix(&mut cmd)?;
+                let snapshot = self.undo.take().ok_or_else(|| gettext("nothing to undo"))?;
+                self.undo = Some(Snapshot {
+                    lines: mem::replace(&mut self.lines, snapshot.lines),
+                    current: mem::replace(&mut self.current, snapshot.current),
+                    modified: mem::replace(&mut self.modified, snapshot.modified),
+                });
+            }
+            b'w' | b'W' => {
+                let (first, last) = if count == 0 && len == 0 {
+                    (0, 0)
+                } else {
+                    range(1, len)?
+                };
+                let quit = command == b'w' && cmd.peek() == Some(b'q');
+                if quit {
+                    cmd.next();
+                }
+                let name = self.parse_filename(&mut cmd)?;
+                let name = self.current_filename(name)?;
+                let size = self.write_file(&name, first, last, command == b'W')?;
+                if self.filename.is_none() && !name.starts_with('!') {
+                    self.filename = Some(name.clone());
+                }
+                if !name.starts_with('!') && first <= 1 && last == len {
+                    self.modified = false;
+                }
+                self.print_size(size);
+                if quit {
+                    return self.execute(b"q".to_vec());
+                }
+            }
+            b'=' => {
+                let address = if count == 0 { len } else { last };
+                self.expect_end(&mut cmd)?;
+                self.write(format!("{}\n", address).as_bytes());
+            }
+            b'!' => {
+                no_address()?;
+                let text = cmd.rest();
+                self.shell_escape(&text)?;
+            }
+            _ => return Err(gettext("unknown command")),
+        }
+
+        if print.any() {
+            if self.current == 0 {
+                return Err(gettext("invalid address"));
+            }
+            self.print_lines(self.current, self.current, print);
+        }
+        Ok(None)
+    }
+
+    /// Parses and runs an `s` command on lines `first` to `last`, returning
+    /// how to print the last line changed.
+    fn substitute_command(
+        &mut self,
+        cmd: &mut CommandLine,
+        first: usize,
+        last: usize,
+    ) -> Result<Print, String> {
+        let delimiter = match cmd.next() {
+            Some(c) if !matches!(c, b' ' | b'\n' | b'\\') => c,
+            _ => return Err(gettext("invalid pattern delimiter")),
+        };
+        let regex = self.parse_regex(cmd, delimiter, true)?;
+
+        // The replacement, which an escaped newline continues onto the next
+        // line of input.
+        let mut raw = Vec::new();
+        let mut terminated = false;
+        loop {
+            match cmd.next() {
+                None => break,
+                Some(c) if c == delimiter => {
+                    terminated = true;
+                    break;
+                }
+                Some(b'\\') => match cmd.next() {
+                    Some(c) => raw.extend_from_slice(&[b'\\', c]),
+                    None => {
+                        let line = self
+                            .next_input_line()?
+                            .ok_or_else(|| gettext("unexpected end of file"))?;
+                        raw.extend_from_slice(b"\\\n");
+                        cmd.text.extend_from_slice(&line);
+                    }
+                },
+                Some(c) => raw.push(c),
+            }
+        }
+        if raw == b"%" {
+            raw = self
+                .last_replacement
+                .clone()
+                .ok_or_else(|| gettext("no previous substitution"))?;
+        }
+        self.last_replacement = Some(raw.clone());
+        let replacement = parse_replacement(&raw, true, false);
+
+        let mut global = false;
+        let mut occurrence = 1;
+        let mut print = Print {
+            print: !terminated,
+            ..Print::default()
+        };
+        loop {
+            match cmd.peek() {
+                Some(b'g') => global = true,
+                Some(b'0'..=b'9') => {
+                    occurrence = cmd.number().ok_or_else(|| gettext("number out of range"))?;
+                    if occurrence == 0 {
+                        return Err(gettext("invalid command suffix"));
+                    }
+                    continue;
+                }
+                Some(b'l') => print.list = true,
+                Some(b'n') => print.number = true,
+                Some(b'p') => print.print = true,
+                None => break,
+                Some(_) => return Err(gettext("invalid command suffix")),
+            }
+            cmd.next();
+        }
+
+        let mut changed = None;
+        let mut n = first;
+        let mut last = last;
+        while n <= last {
+            let line = Rc::clone(&self.lines[n - 1]);
+            let Some(result) = substitute(&regex, &replacement, &line, occurrence, global) else {
+                n += 1;
+                continue;
+            };
+            if changed.is_none() {
+                self.checkpoint();
+            }
+            let new_lines: Vec<Line> = result
+                .split(|&b| b == b'\n')
+                .map(|line| Rc::new(line.to_vec()))
+                .collect();
+            let count = new_lines.len();
+
+            // Marks on the line move to its replacement.
+            for mark in self.marks.values_mut() {
+                if Rc::ptr_eq(mark, &line) {
+                    *mark = Rc::clone(&new_lines[0]);
+                }
+            }
+            self.lines.splice(n - 1..n, new_lines);
+            n += count;
+            last += count - 1;
+            changed = Some(n - 1);
+        }
+
+        match changed {
+            Some(n) => {
+                self.current = n;
+                self.modified = true;
+                Ok(print)
+            }
+            None if self.pending.is_some() => Ok(Print::default()),
+            None => Err(gettext("no match")),
+        }
+    }
+
+    /// Saves a modified buffer after a hangup.
+    fn hangup(&mut self) -> ! {
+        if self.modified && !self.lines.is_empty() {
+            let len = self.lines.len();
+            if self.write_file("ed.hup", 1, len, false).is_err() {
+                if let Ok(home) = std::env::var("HOME") {
+                    let _ = self.write_file(&format!("{}/ed.hup", home), 1, len, false);
+                }
+            }
+        }
+        std::process::exit(2)
+    }
+
+    /// Reads and runs commands until `q` or the end of input.
+    fn run(&mut self) -> i32 {
+        loop {
+            if self.prompting {
+                let prompt = self.prompt.clone();
+                self.write(prompt.as_bytes());
+            }
+            let _ = self.out.flush();
+            let result = match self.read_input_line() {
+                Ok(Some(line)) => self.execute(line),
+                // The end of input acts like `q`.
+                Ok(None) => self.execute(b"q".to_vec()),
+                Err(message) => {
+                    if HANGUP.load(Ordering::SeqCst) {
+                        self.hangup();
+                    }
+                    self.write(b"\n");
+                    Err(message)
+                }
+            };
+            match result {
+                Ok(Some(status)) => return status,
+                Ok(None) => {}
+                Err(message) => {
+                    self.report(message);
+                    if !self.interactive {
+                        return 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    install_signal_handlers();
+    let mut ed = Ed::new(&args);
+    if let Some(file) = &args.file {
+        match ed.read_file(file) {
+            Ok((lines, size)) => {
+                ed.lines = lines;
+                ed.current = ed.lines.len();
+                ed.print_size(size);
+            }
+            // The file name is remembered even if the file cannot be read.
+            Err(message) => ed.last_error = Some(message),
+        }
+        if !file.starts_with('!') {
+            ed.filename = Some(file.clone());
+        }
+    }
+
+    let status = ed.run();
+    let _ = ed.out.flush();
+    std::process::exit(status)
+}
@@ -0,0 +1,229 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, scratch_dir, TestPlan};
+use std::{fs, process::Command};
+
+const LINES: &str = "tests/ed/lines.txt";
+const OLD: &str = "tests/ed/old.txt";
+const NEW: &str = "tests/ed/new.txt";
+
+fn ed_test(args: &[&str], script: &str, expected_output: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("ed"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(script),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code,
+    });
+}
+
+/// Runs the workspace's `diff`, which the tests check `ed` against.
+fn diff(args: &[&str]) -> String {
+    let relpath = if cfg!(debug_assertions) {
+        "target/debug/diff"
+    } else {
+        "target/release/diff"
+    };
+    let path = std::env::current_dir()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join(relpath);
+    let output = Command::new(path)
+        .args(args)
+        .output()
+        .expect("failed to run diff");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_ed_byte_count() {
+    ed_test(&[LINES], "q\n", "24\n", 0);
+}
+
+#[test]
+fn test_ed_print_addresses() {
+    ed_test(
+        &["-s", LINES],
+        "2p\n$p\n1\n\n-p\n2;+1p\n,n\n",
+        "two\nfive\none\ntwo\none\ntwo\nthree\n1\tone\n2\ttwo\n3\tthree\n4\tfour\n5\tfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_regex_addresses() {
+    ed_test(
+        &["-s", LINES],
+        "1\n/e/p\n//p\n?o?p\n/^t/,/^f/n\n",
+        "one\nthree\nfive\nfour\n2\ttwo\n3\tthree\n4\tfour\n5\tfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_marks() {
+    ed_test(
+        &["-s", LINES],
+        "2kx\n4ky\n'x,'yp\n1d\n'xp\nQ\n",
+        "two\nthree\nfour\ntwo\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_line_numbers() {
+    ed_test(&["-s", LINES], "=\n2=\n/four/=\n", "5\n2\n4\n", 0);
+}
+
+#[test]
+fn test_ed_list() {
+    ed_test(&["-s"], "a\na\tb\\c\n.\nl\nQ\n", "a\\tb\\\\c$\n", 0);
+}
+
+#[test]
+fn test_ed_text_input() {
+    ed_test(
+        &["-s", LINES],
+        "1a\nafter one\n.\n0i\nfirst\n.\n$c\nlast\n.\n,p\nQ\n",
+        "first\none\nafter one\ntwo\nthree\nfour\nlast\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_substitute() {
+    ed_test(
+        &["-s", LINES],
+        "1s/o/0/p\n2s/\\(t\\)\\(w\\)/\\2\\1/p\n3s/e/E/2p\n4s/[ou]/<&>/gp\n5s/i/%/p\n5s/f/\\%/p\nQ\n",
+        "0ne\nwto\nthreE\nf<o><u>r\nf<i>ve\n%<i>ve\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_substitute_newline() {
+    ed_test(
+        &["-s", LINES],
+        "2s/w/&\\\n/\n,n\nQ\n",
+        "1\tone\n2\ttw\n3\to\n4\tthree\n5\tfour\n6\tfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_substitute_previous_regex() {
+    ed_test(&["-s", LINES], "/ee/s//EE/p\nQ\n", "thrEE\n", 0);
+}
+
+#[test]
+fn test_ed_move_copy_join() {
+    ed_test(
+        &["-s", LINES],
+        "1,2m$\n,p\n1t0\n1,2j\n,p\nQ\n",
+        "three\nfour\nfive\none\ntwo\nthreethree\nfour\nfive\none\ntwo\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_global() {
+    ed_test(
+        &["-s", LINES],
+        "g/o/s/$/!/\\\ns/^/> /\n,p\nv/!/d\n,p\nQ\n",
+        "> one!\n> two!\nthree\n> four!\nfive\n> one!\n> two!\n> four!\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_global_text_input() {
+    ed_test(
+        &["-s", LINES],
+        "g/^t/a\\\n--\n,p\nQ\n",
+        "one\ntwo\n--\nthree\n--\nfour\nfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_undo() {
+    ed_test(
+        &["-s", LINES],
+        "g/e/d\n,p\nu\n,p\nu\n,p\nQ\n",
+        "two\nfour\none\ntwo\nthree\nfour\nfive\ntwo\nfour\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_write() {
+    let dir = scratch_dir("ed-write");
+    let file = dir.join("out.txt");
+    let file = file.to_str().unwrap();
+    ed_test(&["-s", LINES], &format!("2,4d\nw {}\nq\n", file), "", 0);
+    assert_eq!(fs::read_to_string(file).unwrap(), "one\nfive\n");
+    ed_test(
+        &[file],
+        &format!("r {}\n1,2W {}\nQ\n", LINES, file),
+        "9\n24\n9\n",
+        0,
+    );
+    assert_eq!(fs::read_to_string(file).unwrap(), "one\nfive\none\nfive\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_ed_shell_commands() {
+    ed_test(
+        &[LINES],
+        "!echo hello\nr !echo world\n$p\nQ\n",
+        "24\nhello\n!\n6\nworld\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_modified_buffer_warning() {
+    ed_test(&["-s", LINES], "1d\nq\n", "?\n", 1);
+}
+
+#[test]
+fn test_ed_error_messages() {
+    ed_test(&["-s", LINES], "/none/\n", "?\n", 1);
+    ed_test(&["-s", LINES], "H\n9p\n", "?\ninvalid address\n", 1);
+}
+
+#[test]
+fn test_ed_prompt() {
+    ed_test(
+        &["-s", "-p", "> ", LINES],
+        "1p\nP\n2p\n",
+        "> one\n> two\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ed_runs_diff_edit_scripts() {
+    let mut script = diff(&["-e", OLD, NEW]);
+    script.push_str(",p\nQ\n");
+    let expected = fs::read_to_string(NEW).unwrap();
+    ed_test(&["-s", OLD], &script, &expected, 0);
+}
+
+#[test]
+fn test_ed_runs_diff_edit_scripts_in_reverse() {
+    let mut script = diff(&["-e", NEW, OLD]);
+    script.push_str(",p\nQ\n");
+    let expected = fs::read_to_string(OLD).unwrap();
+    ed_test(&["-s", NEW], &script, &expected, 0);
+}
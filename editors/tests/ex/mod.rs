@@ -0,0 +1,183 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, scratch_dir, TestPlan};
+use std::fs;
+
+const LINES: &str = "tests/ex/lines.txt";
+
+fn ex_test(args: &[&str], script: &str, expected_output: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("ex"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(script),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_ex_print() {
+    ex_test(&["-s", LINES], "%p\n", "one\ntwo\nthree\nfour\nfive\n", 0);
+    ex_test(&["-s", LINES], "2;+1p\n", "two\nthree\n", 0);
+    ex_test(&["-s", LINES], "1\n", "one\n", 0);
+    ex_test(&["-s", LINES], "1,2l\n", "one$\ntwo$\n", 0);
+    ex_test(
+        &["-s", LINES],
+        "set nu\n4,5p\n",
+        "     4  four\n     5  five\n",
+        0,
+    );
+    ex_test(&["-s", LINES], "=\n2=\n", "5\n2\n", 0);
+}
+
+#[test]
+fn test_ex_search_addresses() {
+    ex_test(&["-s", LINES], "/fo/p\n?on?p\n", "four\none\n", 0);
+    ex_test(
+        &["-s", LINES],
+        "2ka\n4\n'a,.p\n",
+        "four\ntwo\nthree\nfour\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ex_substitute() {
+    ex_test(
+        &["-s", LINES],
+        "%s/o/0/g\n%p\n",
+        "0ne\ntw0\nthree\nf0ur\nfive\n",
+        0,
+    );
+    ex_test(
+        &["-s", LINES],
+        "%s/\\(.\\)\\(.*\\)/\\u\\2\\1/\n%p\n",
+        "Neo\nWot\nHreet\nOurf\nIvef\n",
+        0,
+    );
+    ex_test(&["-s", LINES], "2s/t/T/|p\n", "Two\n", 0);
+    ex_test(&["-s", LINES], "1s/o/O/\n2&\n1,2p\n", "One\ntwO\n", 0);
+}
+
+#[test]
+fn test_ex_global() {
+    ex_test(&["-s", LINES], "g/e/p\n", "one\nthree\nfive\n", 0);
+    ex_test(&["-s", LINES], "v/e/d\n%p\n", "one\nthree\nfive\n", 0);
+    ex_test(
+        &["-s", LINES],
+        "g/o/s//0/\n%p\n",
+        "0ne\ntw0\nthree\nf0ur\nfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ex_edit_commands() {
+    ex_test(
+        &["-s", LINES],
+        "2,3j\n%p\n",
+        "one\ntwo three\nfour\nfive\n",
+        0,
+    );
+    ex_test(
+        &["-s", LINES],
+        "1m$\n%p\n",
+        "two\nthree\nfour\nfive\none\n",
+        0,
+    );
+    ex_test(
+        &["-s", LINES],
+        "1,2t0\n%p\n",
+        "one\ntwo\none\ntwo\nthree\nfour\nfive\n",
+        0,
+    );
+    ex_test(&["-s", LINES], "2c\nX\n.\n1,3p\n", "one\nX\nthree\n", 0);
+    ex_test(&["-s", LINES], "$a\nsix\n.\n4,$p\n", "four\nfive\nsix\n", 0);
+    ex_test(&["-s", LINES], "2y a\n$put a\n5,$p\n", "five\ntwo\n", 0);
+    ex_test(&["-s", LINES], "set sw=4\n1>\n1p\n", "    one\n", 0);
+    ex_test(&["-s", LINES], "1,3!sort\n1,3p\n", "one\nthree\ntwo\n", 0);
+}
+
+#[test]
+fn test_ex_undo() {
+    ex_test(
+        &["-s", LINES],
+        "1,4d\nu\n%p\n",
+        "one\ntwo\nthree\nfour\nfive\n",
+        0,
+    );
+    ex_test(
+        &["-s", LINES],
+        "1d\nu\nu\n%p\n",
+        "two\nthree\nfour\nfive\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ex_set() {
+    ex_test(
+        &["-s", LINES],
+        "set ts?\nset sw=3\nset sw\n",
+        "tabstop=8\nshiftwidth=3\n",
+        0,
+    );
+}
+
+#[test]
+fn test_ex_plus_command() {
+    ex_test(&["-s", "-c", "3", LINES], "p\n", "three\nthree\n", 0);
+    ex_test(&["-s", "+/fo", LINES], "", "four\n", 0);
+}
+
+#[test]
+fn test_ex_write() {
+    let dir = scratch_dir("ex-write");
+    let file = dir.join("out.txt");
+    let file = file.to_str().unwrap();
+    ex_test(&["-s", LINES], &format!("2,4d\nw {}\nq!\n", file), "", 0);
+    assert_eq!(fs::read_to_string(file).unwrap(), "one\nfive\n");
+    ex_test(
+        &["-s", file],
+        &format!("r {}\nw >> {}\nq!\n", LINES, file),
+        "",
+        0,
+    );
+    assert_eq!(
+        fs::read_to_string(file).unwrap(),
+        "one\nfive\none\nfive\none\ntwo\nthree\nfour\nfive\n"
+    );
+    ex_test(&["-s", file], "%d\nx\n", "", 0);
+    assert_eq!(fs::read_to_string(file).unwrap(), "");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_ex_errors() {
+    // Errors end a session whose input is not a terminal.
+    run_test(TestPlan {
+        cmd: String::from("ex"),
+        args: vec![String::from("-s"), String::from(LINES)],
+        stdin_data: String::from("bogus\n%p\n"),
+        expected_out: String::from(""),
+        expected_err: String::from("bogus: Not an editor command\n"),
+        expected_exit_code: 1,
+    });
+    run_test(TestPlan {
+        cmd: String::from("ex"),
+        args: vec![String::from("-s"), String::from(LINES)],
+        stdin_data: String::from("1d\nq\n"),
+        expected_out: String::from(""),
+        expected_err: String::from("No write since last change (:quit! overrides)\n"),
+        expected_exit_code: 1,
+    });
+    ex_test(&["-s", LINES], "1d\nq!\n", "", 0);
+}
@@ -0,0 +1,15 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod common;
+mod vi_util;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    vi_util::main(false)
+}
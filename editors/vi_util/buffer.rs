@@ -0,0 +1,236 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::common::Line;
+use std::{collections::HashMap, rc::Rc};
+
+/// The state `undo` returns to.
+struct Snapshot {
+    lines: Vec<Line>,
+    line: usize,
+    col: usize,
+}
+
+/// The lines being edited, with the cursor, marks and undo state.
+pub struct Buffer {
+    lines: Vec<Line>,
+
+    /// The current line, 0 only when the buffer is empty.
+    pub line: usize,
+
+    /// The byte offset of the cursor in the current line.
+    pub col: usize,
+    pub modified: bool,
+    marks: HashMap<u8, (Line, usize)>,
+    undo: Option<Snapshot>,
+
+    /// Set at the start of each command, so that its first change saves the
+    /// state `undo` returns to.
+    checkpoint_pending: bool,
+
+    /// The latest version of the last line changed, and its text before the
+    /// changes began, for the `U` command.
+    line_undo: Option<(Line, Line)>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer {
+            lines: Vec::new(),
+            line: 0,
+            col: 0,
+            modified: false,
+            marks: HashMap::new(),
+            undo: None,
+            checkpoint_pending: true,
+            line_undo: None,
+        }
+    }
+
+    /// Replaces the contents with the lines of a newly edited file.
+    pub fn load(&mut self, lines: Vec<Vec<u8>>) {
+        *self = Buffer::new();
+        self.lines = lines.into_iter().map(Rc::new).collect();
+        self.line = self.lines.len().min(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Returns line `n`, counted from 1.
+    pub fn get(&self, n: usize) -> &[u8] {
+        &self.lines[n - 1]
+    }
+
+    /// Returns the current line, or an empty one in an empty buffer.
+    pub fn current(&self) -> &[u8] {
+        if self.line == 0 {
+            &[]
+        } else {
+            self.get(self.line)
+        }
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Lets the next change save the state that `undo` returns to.
+    pub fn begin_command(&mut self) {
+        self.checkpoint_pending = true;
+    }
+
+    fn save_undo(&mut self) {
+        if self.checkpoint_pending {
+            self.undo = Some(Snapshot {
+                lines: self.lines.clone(),
+                line: self.line,
+                col: self.col,
+            });
+            self.checkpoint_pending = false;
+        }
+        self.modified = true;
+    }
+
+    pub fn replace(&mut self, n: usize, text: Vec<u8>) {
+        if *self.lines[n - 1] == text {
+            return;
+        }
+        self.save_undo();
+        let old = Rc::clone(&self.lines[n - 1]);
+        let new = Rc::new(text);
+        match &mut self.line_undo {
+            Some((latest, _)) if Rc::ptr_eq(latest, &old) => *latest = Rc::clone(&new),
+            _ => self.line_undo = Some((Rc::clone(&new), Rc::clone(&old))),
+        }
+        for (line, _) in self.marks.values_mut() {
+            if Rc::ptr_eq(line, &old) {
+                *line = Rc::clone(&new);
+            }
+        }
+        self.lines[n - 1] = new;
+    }
+
+    /// Inserts lines after line `after`, which is 0 to insert at the top.
+    pub fn insert(&mut self, after: usize, text: Vec<Vec<u8>>) {
+        if text.is_empty() {
+            return;
+        }
+        self.save_undo();
+        self.lines
+            .splice(after..after, text.into_iter().map(Rc::new));
+    }
+
+    /// Deletes lines `first` to `last` and returns their text.
+    pub fn delete(&mut self, first: usize, last: usize) -> Vec<Vec<u8>> {
+        self.save_undo();
+        self.lines
+            .drain(first - 1..last)
+            .map(|line| line.to_vec())
+            .collect()
+    }
+
+    /// Swaps the buffer with the state before the last change, so that a
+    /// second undo redoes it.
+    pub fn undo(&mut self) -> bool {
+        let Some(snapshot) = self.undo.take() else {
+            return false;
+        };
+        self.undo = Some(Snapshot {
+            lines: std::mem::replace(&mut self.lines, snapshot.lines),
+            line: self.line,
+            col: self.col,
+        });
+        self.line = snapshot.line.min(self.lines.len());
+        self.col = snapshot.col;
+        self.modified = true;
+        self.checkpoint_pending = false;
+        true
+    }
+
+    /// Restores the last changed line to its text before the changes, if
+    /// it is still in the buffer.  Returns its line number.
+    pub fn undo_line(&mut self) -> Option<usize> {
+        let (latest, original) = self.line_undo.clone()?;
+        let n = self.lines.iter().position(|l| Rc::ptr_eq(l, &latest))? + 1;
+        let text = original.to_vec();
+        self.replace(n, text);
+        self.line_undo = Some((Rc::clone(&self.lines[n - 1]), latest));
+        Some(n)
+    }
+
+    pub fn set_mark(&mut self, mark: u8, n: usize, col: usize) {
+        if n > 0 {
+            self.marks
+                .insert(mark, (Rc::clone(&self.lines[n - 1]), col));
+        }
+    }
+
+    /// Returns the line and column of a mark whose line is still in the
+    /// buffer.
+    pub fn mark(&self, mark: u8) -> Option<(usize, usize)> {
+        let (line, col) = self.marks.get(&mark)?;
+        let n = self.lines.iter().position(|l| Rc::ptr_eq(l, line))?;
+        Some((n + 1, *col))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::split_lines;
+
+    fn text(buffer: &Buffer) -> Vec<&[u8]> {
+        buffer.lines().iter().map(|line| line.as_slice()).collect()
+    }
+
+    #[test]
+    fn test_undo_is_its_own_inverse() {
+        let mut buffer = Buffer::new();
+        buffer.load(split_lines(b"a\nb\nc\n"));
+        buffer.begin_command();
+        buffer.delete(1, 2);
+        buffer.replace(1, b"C".to_vec());
+        assert_eq!(text(&buffer), [b"C"]);
+        assert!(buffer.undo());
+        assert_eq!(text(&buffer), [b"a", b"b", b"c"]);
+        assert!(buffer.undo());
+        assert_eq!(text(&buffer), [b"C"]);
+    }
+
+    #[test]
+    fn test_marks_follow_lines() {
+        let mut buffer = Buffer::new();
+        buffer.load(split_lines(b"a\nb\nc\n"));
+        buffer.set_mark(b'x', 3, 0);
+        buffer.delete(1, 1);
+        assert_eq!(buffer.mark(b'x'), Some((2, 0)));
+        buffer.replace(2, b"changed".to_vec());
+        assert_eq!(buffer.mark(b'x'), Some((2, 0)));
+        buffer.delete(2, 2);
+        assert_eq!(buffer.mark(b'x'), None);
+    }
+
+    #[test]
+    fn test_undo_line() {
+        let mut buffer = Buffer::new();
+        buffer.load(split_lines(b"abc\n"));
+        buffer.replace(1, b"abcd".to_vec());
+        buffer.replace(1, b"abcde".to_vec());
+        assert_eq!(buffer.undo_line(), Some(1));
+        assert_eq!(buffer.get(1), b"abc");
+        assert_eq!(buffer.undo_line(), Some(1));
+        assert_eq!(buffer.get(1), b"abcde");
+    }
+}
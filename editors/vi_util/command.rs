@@ -0,0 +1,1808 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::editor::{Editor, Mode, Register, Result};
+use super::regex::{parse_replacement, substitute};
+use crate::common::{split_lines, CommandLine};
+use gettextrs::gettext;
+use std::{collections::HashSet, collections::VecDeque, fs, rc::Rc};
+
+impl CommandLine {
+    fn at_end(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    /// Takes the argument of a command: the text up to an unescaped `|`,
+    /// which is consumed, or the end.  `\|` and `^V|` stand for `|`.
+    fn argument(&mut self) -> Vec<u8> {
+        let mut arg = Vec::new();
+        while let Some(c) = self.next() {
+            match c {
+                b'|' => break,
+                b'\\' | 0x16 if self.peek() == Some(b'|') => {
+                    arg.push(b'|');
+                    self.pos += 1;
+                }
+                c => arg.push(c),
+            }
+        }
+        arg
+    }
+
+    /// Consumes the end of a command, which must come next.
+    fn end(&mut self) -> Result<()> {
+        self.skip_blanks();
+        match self.next() {
+            None | Some(b'|') => Ok(()),
+            Some(b'"') => {
+                self.pos = self.text.len();
+                Ok(())
+            }
+            Some(_) => Err(gettext("Extra characters at end of command")),
+        }
+    }
+
+    /// Reads a pattern ended by `delimiter` or by the end of the command,
+    /// with escaped delimiters unescaped.
+    fn pattern(&mut self, delimiter: u8) -> Result<Vec<u8>> {
+        let mut pattern = Vec::new();
+        loop {
+            match self.next() {
+                None => break,
+                Some(c) if c == delimiter => break,
+                Some(b'\\') => match self.next() {
+                    None => pattern.push(b'\\'),
+                    Some(c) if c == delimiter && c != b'&' => pattern.push(c),
+                    Some(c) => pattern.extend_from_slice(&[b'\\', c]),
+                },
+                Some(b'[') => {
+                    pattern.push(b'[');
+                    self.copy_bracket_expression(&mut pattern)
+                        .ok_or_else(|| gettext("Missing ]"))?;
+                }
+                Some(c) => pattern.push(c),
+            }
+        }
+        Ok(pattern)
+    }
+}
+
+/// The names of the commands, with the number of letters each may be
+/// abbreviated to.  Earlier entries win when an abbreviation is ambiguous.
+const COMMANDS: &[(&str, usize)] = &[
+    ("append", 1),
+    ("abbreviate", 2),
+    ("args", 2),
+    ("change", 1),
+    ("chdir", 3),
+    ("cd", 2),
+    ("copy", 2),
+    ("delete", 1),
+    ("edit", 1),
+    ("file", 1),
+    ("global", 1),
+    ("insert", 1),
+    ("join", 1),
+    ("list", 1),
+    ("mark", 2),
+    ("map", 3),
+    ("move", 1),
+    ("next", 1),
+    ("number", 2),
+    ("open", 1),
+    ("preserve", 3),
+    ("print", 1),
+    ("put", 2),
+    ("quit", 1),
+    ("read", 1),
+    ("recover", 3),
+    ("rewind", 3),
+    ("set", 2),
+    ("shell", 2),
+    ("source", 2),
+    ("stop", 2),
+    ("suspend", 2),
+    ("substitute", 1),
+    ("tag", 2),
+    ("t", 1),
+    ("unabbreviate", 3),
+    ("undo", 1),
+    ("unmap", 3),
+    ("version", 2),
+    ("visual", 2),
+    ("v", 1),
+    ("write", 1),
+    ("wq", 2),
+    ("xit", 1),
+    ("yank", 1),
+    ("z", 1),
+    ("k", 1),
+];
+
+fn lookup(name: &[u8]) -> Option<&'static str> {
+    let name = std::str::from_utf8(name).ok()?;
+    COMMANDS
+        .iter()
+        .find(|(full, min)| name.len() >= *min && full.starts_with(name))
+        .map(|(full, _)| *full)
+}
+
+/// How a command asks for the current line to be written after it runs:
+/// the `#`, `l`, `p`, `+` and `-` flags.
+#[derive(Clone, Copy, Default)]
+struct Flags {
+    number: bool,
+    list: bool,
+    print: bool,
+    offset: isize,
+}
+
+impl Flags {
+    fn any(&self) -> bool {
+        self.number || self.list || self.print || self.offset != 0
+    }
+}
+
+/// The replacement and options of the last `substitute`, which `&` and `~`
+/// repeat.
+#[derive(Clone, Default)]
+pub struct Substitution {
+    replacement: Vec<u8>,
+    global: bool,
+    confirm: bool,
+}
+
+/// The lines a command applies to.
+#[derive(Clone, Copy)]
+struct Range {
+    first: usize,
+    last: usize,
+
+    /// How many addresses were given.
+    given: usize,
+}
+
+/// Formats a line the way `list` does: tabs and other control characters
+/// as `^` and a letter, other unprintable bytes in octal, and the end of
+/// the line marked with `$`.
+pub fn list_line(line: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    for &b in line {
+        match b {
+            0..=0x1f => output.extend_from_slice(&[b'^', b + b'@']),
+            0x7f => output.extend_from_slice(b"^?"),
+            b' '..=b'~' => output.push(b),
+            _ => output.extend_from_slice(format!("\\{:03o}", b).as_bytes()),
+        }
+    }
+    output.push(b'$');
+    output
+}
+
+/// Returns the whitespace a line starts with.
+pub fn indentation(line: &[u8]) -> &[u8] {
+    let n = line
+        .iter()
+        .position(|&b| b != b' ' && b != b'\t')
+        .unwrap_or(line.len());
+    &line[..n]
+}
+
+/// Returns the width of leading whitespace with tabs every `tabstop`
+/// columns.
+pub fn indent_width(line: &[u8], tabstop: usize) -> usize {
+    indentation(line).iter().fold(0, |width, &b| {
+        if b == b'\t' {
+            (width / tabstop + 1) * tabstop
+        } else {
+            width + 1
+        }
+    })
+}
+
+/// Builds indentation of `width` columns from tabs and spaces.
+pub fn make_indent(width: usize, tabstop: usize) -> Vec<u8> {
+    let mut indent = vec![b'\t'; width / tabstop];
+    indent.resize(indent.len() + width % tabstop, b' ');
+    indent
+}
+
+/// Joins two lines the way `join` does: leading whitespace of the second
+/// is removed, and a space separates them unless the first ends with a
+/// blank or the second starts with `)`, or two if the first ends with `.`.
+pub fn join_lines(first: &[u8], second: &[u8], exact: bool) -> Vec<u8> {
+    let mut joined = first.to_vec();
+    if exact {
+        joined.extend_from_slice(second);
+        return joined;
+    }
+    let second = &second[indentation(second).len()..];
+    if !second.is_empty() && !first.is_empty() {
+        match (first.last(), second[0]) {
+            (Some(b' ' | b'\t'), _) | (_, b')') => {}
+            (Some(b'.'), _) => joined.extend_from_slice(b"  "),
+            _ => joined.push(b' '),
+        }
+    }
+    joined.extend_from_slice(second);
+    joined
+}
+
+impl Editor {
+    /// Writes a line as `print`, `number` and `list` do, also following
+    /// the `number` and `list` options.
+    fn print_line(&mut self, n: usize, flags: Flags) {
+        let line = self.buffer.get(n).to_vec();
+        let mut output = Vec::new();
+        if flags.number || self.options.flag("number") {
+            output.extend(format!("{:6}  ", n).as_bytes());
+        }
+        if flags.list || self.options.flag("list") {
+            output.extend(list_line(&line));
+        } else {
+            output.extend(line);
+        }
+        self.print(&output);
+    }
+
+    /// Runs a command line, whose commands are separated by `|`.
+    pub fn execute(&mut self, text: &[u8]) -> Result<()> {
+        let mut cmd = CommandLine::new(text.to_vec());
+        loop {
+            self.execute_one(&mut cmd)?;
+            if cmd.at_end() || self.quit {
+                return Ok(());
+            }
+        }
+    }
+
+    fn line_of_mark(&self, mark: u8) -> Result<usize> {
+        self.buffer
+            .mark(mark)
+            .map(|(line, _)| line)
+            .ok_or_else(|| gettext("Mark not set"))
+    }
+
+    fn parse_address(&mut self, cmd: &mut CommandLine) -> Result<Option<usize>> {
+        cmd.skip_blanks();
+        let current = self.buffer.line as i64;
+        let mut address = match cmd.peek() {
+            Some(b'.') => {
+                cmd.next();
+                Some(current)
+            }
+            Some(b'$') => {
+                cmd.next();
+                Some(self.buffer.len() as i64)
+            }
+            Some(b'0'..=b'9') => {
+                Some(cmd.number().ok_or_else(|| gettext("Number out of range"))? as i64)
+            }
+            Some(b'\'') => {
+                cmd.next();
+                match cmd.next() {
+                    Some(mark @ (b'a'..=b'z' | b'\'')) => Some(self.line_of_mark(mark)? as i64),
+                    _ => return Err(gettext("Invalid mark name")),
+                }
+            }
+            Some(delimiter @ (b'/' | b'?')) => {
+                cmd.next();
+                let pattern = cmd.pattern(delimiter)?;
+                let regex = self.compile(&pattern)?;
+                let forward = delimiter == b'/';
+                self.last_search_forward = forward;
+                Some(self.search_lines(&regex, self.buffer.line, forward)? as i64)
+            }
+            Some(b'\\') => {
+                // `\/` and `\?` search for the last pattern.
+                cmd.next();
+                let forward = match cmd.next() {
+                    Some(b'/') => true,
+                    Some(b'?') => false,
+                    _ => return Err(gettext("Invalid address")),
+                };
+                let regex = self.compile(b"")?;
+                Some(self.search_lines(&regex, self.buffer.line, forward)? as i64)
+            }
+            _ => None,
+        };
+
+        cmd.offsets(&mut address, current)
+            .ok_or_else(|| gettext("Number out of range"))?;
+
+        match address {
+            Some(a) if a < 0 || a > self.buffer.len() as i64 => {
+                Err(gettext("Address out of range"))
+            }
+            Some(a) => Ok(Some(a as usize)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the addresses before a command name.
+    fn parse_range(&mut self, cmd: &mut CommandLine) -> Result<Range> {
+        let current = self.buffer.line;
+        let mut range = Range {
+            first: current,
+            last: current,
+            given: 0,
+        };
+        cmd.skip_blanks();
+        if cmd.peek() == Some(b'%') {
+            cmd.next();
+            return Ok(Range {
+                first: self.buffer.len().min(1),
+                last: self.buffer.len(),
+                given: 2,
+            });
+        }
+        loop {
+            let address = self.parse_address(cmd)?;
+            cmd.skip_blanks();
+            let separator = cmd.peek();
+            match (address, separator) {
+                (Some(a), Some(b',' | b';')) | (Some(a), _) => {
+                    range.first = range.last;
+                    range.last = a;
+                    range.given += 1;
+                }
+                (None, Some(b',' | b';')) => {
+                    range.first = range.last;
+                    range.last = current;
+                    range.given += 1;
+                }
+                (None, _) => {}
+            }
+            match separator {
+                Some(b',') => {
+                    cmd.next();
+                }
+                Some(b';') => {
+                    cmd.next();
+                    self.buffer.line = range.last;
+                }
+                _ => break,
+            }
+        }
+        self.buffer.line = current;
+        if range.given == 1 {
+            range.first = range.last;
+        }
+        range.given = range.given.min(2);
+        if range.first > range.last {
+            return Err(gettext("The second address is smaller than the first"));
+        }
+        Ok(range)
+    }
+
+    /// Checks that the range names lines of the buffer, or line 0 where a
+    /// command allows it.
+    fn check_range(&self, range: Range, zero: bool) -> Result<()> {
+        if range.first == 0 && !(zero && range.last == 0) {
+            return Err(if self.buffer.is_empty() {
+                gettext("Empty file")
+            } else {
+                gettext("Address out of range")
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies a count, which makes the range start at its last line.
+    fn apply_count(&self, range: Range, count: Option<usize>) -> Result<Range> {
+        match count {
+            None => Ok(range),
+            Some(0) => Err(gettext("Count must be positive")),
+            Some(count) => {
+                let first = range.last.max(1);
+                Ok(Range {
+                    first,
+                    last: (first + count - 1).min(self.buffer.len()),
+                    given: 2,
+                })
+            }
+        }
+    }
+
+    fn parse_count(cmd: &mut CommandLine) -> Result<Option<usize>> {
+        cmd.skip_blanks();
+        if matches!(cmd.peek(), Some(b'0'..=b'9')) {
+            Ok(Some(
+                cmd.number().ok_or_else(|| gettext("Number out of range"))?,
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_flags(cmd: &mut CommandLine) -> Result<Flags> {
+        let mut flags = Flags::default();
+        loop {
+            cmd.skip_blanks();
+            match cmd.peek() {
+                Some(b'#') => flags.number = true,
+                Some(b'l') => flags.list = true,
+                Some(b'p') => flags.print = true,
+                Some(b'+') => flags.offset += 1,
+                Some(b'-') => flags.offset -= 1,
+                _ => break,
+            }
+            cmd.next();
+        }
+        cmd.end()?;
+        Ok(flags)
+    }
+
+    /// Parses an optional count and flags, which end a command.
+    fn count_and_flags(cmd: &mut CommandLine) -> Result<(Option<usize>, Flags)> {
+        let count = Self::parse_count(cmd)?;
+        Ok((count, Self::parse_flags(cmd)?))
+    }
+
+    /// Parses an optional register name.
+    fn parse_register(cmd: &mut CommandLine, digits: bool) -> Option<u8> {
+        cmd.skip_blanks();
+        match cmd.peek() {
+            Some(c @ (b'a'..=b'z' | b'A'..=b'Z')) => {
+                cmd.next();
+                Some(c)
+            }
+            Some(c @ b'1'..=b'9') if digits => {
+                cmd.next();
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses and runs one command, leaving `cmd` after its separator.
+    fn execute_one(&mut self, cmd: &mut CommandLine) -> Result<()> {
+        // Leading colons and blanks are ignored, and `"` starts a comment.
+        while matches!(cmd.peek(), Some(b':' | b' ' | b'\t')) {
+            cmd.next();
+        }
+        if cmd.peek() == Some(b'"') {
+            cmd.rest();
+            return Ok(());
+        }
+
+        let range = self.parse_range(cmd)?;
+        cmd.skip_blanks();
+
+        let start = cmd.pos;
+        while matches!(cmd.peek(), Some(c) if c.is_ascii_alphabetic()) {
+            cmd.next();
+        }
+        let name: &'static str = if cmd.pos > start {
+            // The longest command name the letters start with, so that
+            // arguments may follow some names directly, as in `ka`.
+            let letters = cmd.text[start..cmd.pos].to_vec();
+            let mut len = letters.len();
+            loop {
+                if let Some(name) = lookup(&letters[..len]) {
+                    cmd.pos = start + len;
+                    break name;
+                }
+                len -= 1;
+                if len == 0 {
+                    return Err(format!(
+                        "{}: {}",
+                        String::from_utf8_lossy(&letters),
+                        gettext("Not an editor command")
+                    ));
+                }
+            }
+        } else {
+            match cmd.peek() {
+                None | Some(b'|') => {
+                    cmd.next();
+                    return self.goto_line(range);
+                }
+                Some(c @ (b'!' | b'<' | b'>' | b'=' | b'&' | b'~' | b'@' | b'*' | b'#' | 0x04)) => {
+                    cmd.next();
+                    match c {
+                        b'!' => "!",
+                        b'<' => "<",
+                        b'>' => ">",
+                        b'=' => "=",
+                        b'&' => "&",
+                        b'~' => "~",
+                        b'@' | b'*' => "@",
+                        b'#' => "number",
+                        _ => "^D",
+                    }
+                }
+                Some(_) => return Err(gettext("Unknown command character")),
+            }
+        };
+
+        let bang = name != "!" && cmd.peek() == Some(b'!');
+        if bang {
+            cmd.next();
+        }
+
+        let flags = match name {
+            "append" | "insert" | "change" => self.text_input(cmd, name, range, bang)?,
+            "abbreviate" => self.abbreviate(cmd)?,
+            "args" => self.args(cmd)?,
+            "cd" | "chdir" => self.chdir(cmd, bang)?,
+            "copy" | "t" | "move" => self.copy_move(cmd, name == "move", range)?,
+            "delete" | "yank" => self.delete_yank(cmd, name == "delete", range)?,
+            "edit" => self.edit_command(cmd, bang)?,
+            "file" => self.file_command(cmd)?,
+            "global" | "v" => self.global(cmd, range, bang || name == "v")?,
+            "join" => self.join(cmd, range, bang)?,
+            "list" | "number" | "print" => self.print_command(cmd, name, range)?,
+            "k" | "mark" => self.mark_command(cmd, range)?,
+            "map" => self.map(cmd, bang)?,
+            "next" => self.next_command(cmd, bang)?,
+            "open" | "visual" => self.visual_command(cmd, bang)?,
+            "preserve" => {
+                cmd.end()?;
+                let path = self.preserve()?;
+                self.info(&format!(
+                    "{} {}",
+                    gettext("File preserved as"),
+                    path.display()
+                ));
+                Flags::default()
+            }
+            "put" => self.put_command(cmd, range)?,
+            "quit" => {
+                cmd.end()?;
+                self.quit_command(bang)?;
+                Flags::default()
+            }
+            "read" => self.read_command(cmd, range)?,
+            "recover" => self.recover_command(cmd, bang)?,
+            "rewind" => {
+                cmd.end()?;
+                if self.files.is_empty() {
+                    return Err(gettext("No files in the argument list"));
+                }
+                self.check_modified(bang, "rewind")?;
+                self.file_index = 0;
+                self.more_files_warned = false;
+                let file = self.files[0].clone();
+                self.edit(Some(file))?;
+                Flags::default()
+            }
+            "set" => self.set_command(cmd)?,
+            "shell" => {
+                cmd.end()?;
+                let shell = self.options.text("shell").to_string();
+                let _ = self.run_shell(&format!("exec {}", shell), None, false);
+                Flags::default()
+            }
+            "source" => self.source_command(cmd)?,
+            "stop" | "suspend" => {
+                cmd.end()?;
+                self.check_modified(bang || !self.options.flag("autowrite"), "stop")?;
+                self.suspend();
+                Flags::default()
+            }
+            "substitute" | "&" | "~" => self.substitute_command(cmd, name, range)?,
+            "tag" => {
+                let tag = cmd.argument();
+                let tag = String::from_utf8_lossy(&tag).trim().to_string();
+                if tag.is_empty() {
+                    return Err(gettext("No tag specified"));
+                }
+                self.goto_tag(&tag, bang)?;
+                Flags::default()
+            }
+            "unabbreviate" => self.unabbreviate(cmd)?,
+            "undo" => {
+                cmd.end()?;
+                if !self.buffer.undo() {
+                    return Err(gettext("Nothing to undo"));
+                }
+                Flags::default()
+            }
+            "unmap" => self.unmap(cmd, bang)?,
+            "version" => {
+                cmd.end()?;
+                self.print(
+                    format!("Version {} (posixutils-rs)", env!("CARGO_PKG_VERSION")).as_bytes(),
+                );
+                Flags::default()
+            }
+            "write" | "wq" | "xit" => self.write_command(cmd, name, range, bang)?,
+            "z" => self.z_command(cmd, range)?,
+            "!" => self.bang_command(cmd, range)?,
+            "<" | ">" => self.shift_command(cmd, name == ">", range)?,
+            "=" => {
+                let flags = Self::parse_flags(cmd)?;
+                let n = if range.given == 0 {
+                    self.buffer.len()
+                } else {
+                    range.last
+                };
+                self.print(n.to_string().as_bytes());
+                flags
+            }
+            "@" => self.execute_register(cmd)?,
+            _ => self.scroll_command(cmd, range)?,
+        };
+
+        let autoprint = matches!(
+            name,
+            "delete"
+                | "copy"
+                | "t"
+                | "join"
+                | "move"
+                | "put"
+                | "undo"
+                | "<"
+                | ">"
+                | "substitute"
+                | "&"
+                | "~"
+        );
+        self.after_command(flags, autoprint)
+    }
+
+    /// Writes the current line as the flags of a command ask, or as the
+    /// `autoprint` option does after commands that change the buffer.
+    fn after_command(&mut self, flags: Flags, autoprint: bool) -> Result<()> {
+        if self.buffer.line == 0 {
+            return Ok(());
+        }
+        let line = self.buffer.line as isize + flags.offset;
+        if line < 1 || line > self.buffer.len() as isize {
+            return Err(gettext("Address out of range"));
+        }
+        self.buffer.line = line as usize;
+        if flags.any()
+            || (autoprint
+                && self.options.flag("autoprint")
+                && self.mode == Mode::Ex
+                && !self.batch
+                && self.pending.is_none())
+        {
+            self.print_line(self.buffer.line, flags);
+        }
+        Ok(())
+    }
+
+    /// An address alone moves to the line, which `ex` writes; an empty
+    /// command writes the next line.
+    fn goto_line(&mut self, range: Range) -> Result<()> {
+        let line = if range.given == 0 {
+            if self.mode == Mode::Visual {
+                return Ok(());
+            }
+            self.buffer.line + 1
+        } else {
+            range.last
+        };
+        if line == 0 || line > self.buffer.len() {
+            return Err(if range.given == 0 {
+                gettext("At end-of-file")
+            } else {
+                gettext("Address out of range")
+            });
+        }
+        self.buffer
+            .set_mark(b'\'', self.buffer.line, self.buffer.col);
+        self.buffer.line = line;
+        if self.mode == Mode::Ex {
+            self.print_line(line, Flags::default());
+        } else {
+            self.buffer.col = indentation(self.buffer.get(line)).len();
+        }
+        Ok(())
+    }
+
+    /// Reads the lines of `append`, `insert` and `change`, up to a line
+    /// holding only `.`.  With `autoindent`, reversed by `!`, each line
+    /// starts with the indentation of the line before it.
+    fn read_text(&mut self, after: usize, autoindent: bool) -> Result<Vec<Vec<u8>>> {
+        let mut text: Vec<Vec<u8>> = Vec::new();
+        while let Some(mut line) = self.read_text_line()? {
+            if line == b"." {
+                break;
+            }
+            if autoindent && !line.is_empty() {
+                let previous = match text.last() {
+                    Some(previous) => previous.clone(),
+                    None if after > 0 => self.buffer.get(after).to_vec(),
+                    None => Vec::new(),
+                };
+                let mut indented = indentation(&previous).to_vec();
+                indented.extend(&line[indentation(&line).len()..]);
+                line = indented;
+            }
+            text.push(line);
+        }
+        Ok(text)
+    }
+
+    fn text_input(
+        &mut self,
+        cmd: &mut CommandLine,
+        name: &str,
+        range: Range,
+        bang: bool,
+    ) -> Result<Flags> {
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        let range = self.apply_count(range, count)?;
+        let autoindent = self.options.flag("autoindent") != bang;
+        match name {
+            "append" | "insert" => {
+                let after = if name == "insert" {
+                    range.last.saturating_sub(1)
+                } else {
+                    range.last
+                };
+                if name == "insert" {
+                    self.check_range(range, self.buffer.is_empty())?;
+                }
+                let text = self.read_text(after, autoindent)?;
+                let n = text.len();
+                self.buffer.insert(after, text);
+                self.buffer.line = if n > 0 { after + n } else { range.last };
+            }
+            _ => {
+                self.check_range(range, false)?;
+                let before = self.buffer.len();
+                let text = self.read_text(range.first - 1, autoindent)?;
+                let deleted = self.buffer.delete(range.first, range.last);
+                self.store_register(
+                    None,
+                    Register {
+                        linewise: true,
+                        text: deleted,
+                    },
+                    true,
+                );
+                let n = text.len();
+                self.buffer.insert(range.first - 1, text);
+                self.buffer.line = if n > 0 {
+                    range.first - 1 + n
+                } else {
+                    (range.first - 1).max(self.buffer.len().min(range.first))
+                };
+                self.report_lines(before, None);
+            }
+        }
+        self.buffer.col = 0;
+        Ok(flags)
+    }
+
+    fn abbreviate(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        let arg = cmd.argument();
+        let arg = arg.trim_ascii_start();
+        if arg.is_empty() {
+            for (lhs, rhs) in self.abbreviations.clone() {
+                let mut line = lhs;
+                line.push(b'\t');
+                line.extend(rhs);
+                self.print(&line);
+            }
+            return Ok(Flags::default());
+        }
+        let (lhs, rhs) = split_word(arg);
+        if rhs.is_empty() {
+            return Err(gettext("Usage: abbreviate lhs rhs"));
+        }
+        self.abbreviations.retain(|(l, _)| l != lhs);
+        self.abbreviations.push((lhs.to_vec(), rhs.to_vec()));
+        Ok(Flags::default())
+    }
+
+    fn unabbreviate(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        let arg = cmd.argument();
+        let lhs = arg.trim_ascii();
+        let before = self.abbreviations.len();
+        self.abbreviations.retain(|(l, _)| l != lhs);
+        if self.abbreviations.len() == before {
+            return Err(format!(
+                "{}: {}",
+                String::from_utf8_lossy(lhs),
+                gettext("not an abbreviation")
+            ));
+        }
+        Ok(Flags::default())
+    }
+
+    fn map(&mut self, cmd: &mut CommandLine, input_mode: bool) -> Result<Flags> {
+        let arg = cmd.argument();
+        let arg = arg.trim_ascii_start();
+        let maps = if input_mode {
+            &mut self.input_maps
+        } else {
+            &mut self.maps
+        };
+        if arg.is_empty() {
+            let list = maps.clone();
+            for (lhs, rhs) in list {
+                let mut line = list_line(&lhs);
+                line.pop();
+                line.push(b'\t');
+                let mut rhs = list_line(&rhs);
+                rhs.pop();
+                line.extend(rhs);
+                self.print(&line);
+            }
+            return Ok(Flags::default());
+        }
+        let (lhs, rhs) = split_word(arg);
+        if rhs.is_empty() {
+            return Err(gettext("Usage: map lhs rhs"));
+        }
+        let lhs = unescape_literal_next(lhs);
+        maps.retain(|(l, _)| *l != lhs);
+        maps.push((lhs, unescape_literal_next(rhs)));
+        Ok(Flags::default())
+    }
+
+    fn unmap(&mut self, cmd: &mut CommandLine, input_mode: bool) -> Result<Flags> {
+        let arg = cmd.argument();
+        let lhs = unescape_literal_next(arg.trim_ascii());
+        let maps = if input_mode {
+            &mut self.input_maps
+        } else {
+            &mut self.maps
+        };
+        let before = maps.len();
+        maps.retain(|(l, _)| *l != lhs);
+        if maps.len() == before {
+            return Err(format!(
+                "{}: {}",
+                String::from_utf8_lossy(&lhs),
+                gettext("is not mapped")
+            ));
+        }
+        Ok(Flags::default())
+    }
+
+    fn args(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        cmd.end()?;
+        let list: Vec<String> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                if i == self.file_index {
+                    format!("[{}]", file)
+                } else {
+                    file.clone()
+                }
+            })
+            .collect();
+        if list.is_empty() {
+            return Err(gettext("No files in the argument list"));
+        }
+        self.print(list.join(" ").as_bytes());
+        Ok(Flags::default())
+    }
+
+    fn chdir(&mut self, cmd: &mut CommandLine, bang: bool) -> Result<Flags> {
+        let arg = cmd.argument();
+        let dir = match self.expand_filename(&arg)? {
+            Some(dir) => dir,
+            None => std::env::var("HOME").map_err(|_| gettext("No HOME directory"))?,
+        };
+        let relative = self
+            .filename
+            .as_ref()
+            .is_some_and(|name| !name.starts_with('/'));
+        if relative && self.buffer.modified && !bang {
+            return Err(gettext(
+                "File modified since last write; write or use ! to override",
+            ));
+        }
+        std::env::set_current_dir(&dir).map_err(|err| format!("{}: {}", dir, err))?;
+        Ok(Flags::default())
+    }
+
+    fn copy_move(&mut self, cmd: &mut CommandLine, is_move: bool, range: Range) -> Result<Flags> {
+        self.check_range(range, false)?;
+        let Some(dest) = self.parse_address(cmd)? else {
+            return Err(gettext("Destination address required"));
+        };
+        let flags = Self::parse_flags(cmd)?;
+        let text: Vec<Vec<u8>> = (range.first..=range.last)
+            .map(|n| self.buffer.get(n).to_vec())
+            .collect();
+        let n = text.len();
+        if is_move {
+            if dest >= range.first && dest < range.last {
+                return Err(gettext("Destination is inside the range moved"));
+            }
+            if dest == range.last || dest + 1 == range.first {
+                self.buffer.line = if dest == range.last {
+                    range.last
+                } else {
+                    dest + n
+                };
+                return Ok(flags);
+            }
+            self.buffer.delete(range.first, range.last);
+            let dest = if dest > range.last { dest - n } else { dest };
+            self.buffer.insert(dest, text);
+            self.buffer.line = dest + n;
+        } else {
+            self.buffer.insert(dest, text);
+            self.buffer.line = dest + n;
+            self.report_lines(self.buffer.len() - n, None);
+        }
+        Ok(flags)
+    }
+
+    fn delete_yank(&mut self, cmd: &mut CommandLine, delete: bool, range: Range) -> Result<Flags> {
+        let register = Self::parse_register(cmd, false);
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        let range = self.apply_count(range, count)?;
+        self.check_range(range, false)?;
+        let n = range.last - range.first + 1;
+        if delete {
+            let before = self.buffer.len();
+            let text = self.buffer.delete(range.first, range.last);
+            self.store_register(
+                register,
+                Register {
+                    linewise: true,
+                    text,
+                },
+                true,
+            );
+            self.buffer.line = if range.first <= self.buffer.len() {
+                range.first
+            } else {
+                self.buffer.len()
+            };
+            self.buffer.col = 0;
+            self.report_lines(before, None);
+        } else {
+            let text = (range.first..=range.last)
+                .map(|n| self.buffer.get(n).to_vec())
+                .collect();
+            self.store_register(
+                register,
+                Register {
+                    linewise: true,
+                    text,
+                },
+                false,
+            );
+            self.report_lines(n, Some(&gettext("lines yanked")));
+        }
+        Ok(flags)
+    }
+
+    /// Parses the `+command` that `edit`, `next` and `visual` may run on
+    /// the file they edit.
+    fn parse_plus_command(arg: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let text = arg.trim_ascii_start();
+        let rest = text.strip_prefix(b"+")?;
+        let end = rest
+            .iter()
+            .position(|&b| b == b' ' || b == b'\t')
+            .unwrap_or(rest.len());
+        let mut command = rest[..end].to_vec();
+        if command.is_empty() {
+            command = b"$".to_vec();
+        }
+        *arg = rest[end..].to_vec();
+        Some(command)
+    }
+
+    fn edit_command(&mut self, cmd: &mut CommandLine, bang: bool) -> Result<Flags> {
+        let mut arg = cmd.argument();
+        let plus = Self::parse_plus_command(&mut arg);
+        let name = self.expand_filename(&arg)?;
+        self.check_modified(bang, "edit")?;
+        self.edit(name)?;
+        if let Some(plus) = plus {
+            self.execute(&plus)?;
+        }
+        Ok(Flags::default())
+    }
+
+    fn file_command(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        let arg = cmd.argument();
+        if let Some(name) = self.expand_filename(&arg)? {
+            self.set_filename(name);
+            self.buffer.modified = true;
+        }
+        let status = self.file_status();
+        self.print(status.as_bytes());
+        Ok(Flags::default())
+    }
+
+    fn global(&mut self, cmd: &mut CommandLine, range: Range, invert: bool) -> Result<Flags> {
+        if self.pending.is_some() {
+            return Err(gettext("Global command cannot be nested"));
+        }
+        let range = if range.given == 0 {
+            Range {
+                first: self.buffer.len().min(1),
+                last: self.buffer.len(),
+                given: 2,
+            }
+        } else {
+            range
+        };
+        cmd.skip_blanks();
+        let delimiter = match cmd.next() {
+            Some(c) if !c.is_ascii_alphanumeric() && c != b'\\' && c != b'|' && c != b'"' => c,
+            _ => return Err(gettext("Usage: global /pattern/ commands")),
+        };
+        let pattern = cmd.pattern(delimiter)?;
+        let regex = self.compile(&pattern)?;
+
+        // The command list, which continues onto further lines while they
+        // end with a backslash.
+        let mut list = cmd.rest();
+        while list.ends_with(b"\\") {
+            list.pop();
+            list.push(b'\n');
+            match self.read_text_line()? {
+                Some(line) => list.extend(line),
+                None => break,
+            }
+        }
+        let list: Vec<Vec<u8>> = if list.trim_ascii().is_empty() {
+            vec![b"p".to_vec()]
+        } else {
+            list.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+        };
+
+        if range.first == 0 {
+            return Ok(Flags::default());
+        }
+        let mut marked: HashSet<*const Vec<u8>> = (range.first..=range.last)
+            .filter(|&n| regex.is_match(self.buffer.get(n)) != invert)
+            .map(|n| Rc::as_ptr(&self.buffer.lines()[n - 1]))
+            .collect();
+
+        let before = self.buffer.len();
+        let mut index = 0;
+        let result = (|| {
+            while !marked.is_empty() {
+                // Take the next marked line still in the buffer, searching
+                // on from the last one, since commands may move lines.
+                let lines = self.buffer.lines();
+                let found = (index..lines.len())
+                    .chain(0..index.min(lines.len()))
+                    .find(|&i| marked.contains(&Rc::as_ptr(&lines[i])));
+                let Some(i) = found else {
+                    break;
+                };
+                marked.remove(&Rc::as_ptr(&lines[i]));
+                index = i;
+                self.buffer.line = i + 1;
+
+                self.pending = Some(list.iter().cloned().collect::<VecDeque<_>>());
+                while let Some(line) = self.pending.as_mut().and_then(VecDeque::pop_front) {
+                    self.execute(&line)?;
+                    if self.quit {
+                        return Ok(());
+                    }
+                }
+                if super::editor::INTERRUPTED.swap(false, std::sync::atomic::Ordering::SeqCst) {
+                    return Err(gettext("Interrupt"));
+                }
+            }
+            Ok(())
+        })();
+        self.pending = None;
+        result?;
+        self.report_lines(before, None);
+        if self.mode == Mode::Ex
+            && self.options.flag("autoprint")
+            && !self.batch
+            && self.buffer.line > 0
+        {
+            // The commands of the list did not print, so show where the
+            // global command left off.
+            if self.buffer.len() != before {
+                self.print_line(self.buffer.line, Flags::default());
+            }
+        }
+        Ok(Flags::default())
+    }
+
+    fn join(&mut self, cmd: &mut CommandLine, range: Range, exact: bool) -> Result<Flags> {
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        let mut range = range;
+        if let Some(count) = count {
+            if count == 0 {
+                return Err(gettext("Count must be positive"));
+            }
+            let first = range.last;
+            range = Range {
+                first,
+                last: (first + count - if range.given == 2 { 0 } else { 1 }).min(self.buffer.len()),
+                given: 2,
+            };
+        } else if range.given < 2 {
+            range.last = (range.first + 1).min(self.buffer.len());
+        }
+        self.check_range(range, false)?;
+        if range.first == range.last {
+            return Ok(flags);
+        }
+        let mut joined = self.buffer.get(range.first).to_vec();
+        for n in range.first + 1..=range.last {
+            joined = join_lines(&joined, self.buffer.get(n), exact);
+        }
+        self.buffer.delete(range.first + 1, range.last);
+        self.buffer.replace(range.first, joined);
+        self.buffer.line = range.first;
+        Ok(flags)
+    }
+
+    fn print_command(&mut self, cmd: &mut CommandLine, name: &str, range: Range) -> Result<Flags> {
+        let (count, mut flags) = Self::count_and_flags(cmd)?;
+        let range = self.apply_count(range, count)?;
+        self.check_range(range, false)?;
+        match name {
+            "list" => flags.list = true,
+            "number" => flags.number = true,
+            _ => {}
+        }
+        for n in range.first..range.last {
+            self.print_line(n, flags);
+        }
+        self.buffer.line = range.last;
+        // The last line is written by the flags.
+        flags.print = true;
+        Ok(flags)
+    }
+
+    fn mark_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        cmd.skip_blanks();
+        let mark = match cmd.next() {
+            Some(c @ b'a'..=b'z') => c,
+            _ => return Err(gettext("Mark must be a single lowercase letter")),
+        };
+        cmd.end()?;
+        self.check_range(range, false)?;
+        self.buffer.set_mark(mark, range.last, 0);
+        Ok(Flags::default())
+    }
+
+    fn next_command(&mut self, cmd: &mut CommandLine, bang: bool) -> Result<Flags> {
+        let mut arg = cmd.argument();
+        let plus = Self::parse_plus_command(&mut arg);
+        let files = self.expand_filenames(&arg)?;
+        self.check_modified(bang, "next")?;
+        if !files.is_empty() {
+            self.files = files;
+            self.file_index = 0;
+        } else if self.file_index + 1 >= self.files.len() {
+            return Err(gettext("No more files to edit"));
+        } else {
+            self.file_index += 1;
+        }
+        self.more_files_warned = false;
+        let file = self.files[self.file_index].clone();
+        self.edit(Some(file))?;
+        if let Some(plus) = plus {
+            self.execute(&plus)?;
+        }
+        Ok(Flags::default())
+    }
+
+    fn visual_command(&mut self, cmd: &mut CommandLine, bang: bool) -> Result<Flags> {
+        let mut arg = cmd.argument();
+        let plus = Self::parse_plus_command(&mut arg);
+        if let Some(name) = self.expand_filename(&arg)? {
+            self.check_modified(bang, "visual")?;
+            self.edit(Some(name))?;
+        }
+        if let Some(plus) = plus {
+            self.execute(&plus)?;
+        }
+        if self.mode == Mode::Ex {
+            if !self.interactive {
+                return Err(gettext("Visual mode requires a terminal"));
+            }
+            self.mode = Mode::Visual;
+        }
+        Ok(Flags::default())
+    }
+
+    fn put_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        let name = Self::parse_register(cmd, true);
+        let flags = Self::parse_flags(cmd)?;
+        let register = self
+            .register(name.unwrap_or(b'"'))
+            .cloned()
+            .ok_or_else(|| gettext("Nothing in the register"))?;
+        let n = register.text.len();
+        let before = self.buffer.len();
+        self.buffer.insert(range.last, register.text);
+        self.buffer.line = range.last + n;
+        self.buffer.col = 0;
+        self.report_lines(before, None);
+        Ok(flags)
+    }
+
+    /// Quits, unless the buffer is modified or files of the argument list
+    /// were not edited, which `!` overrides.
+    pub fn quit_command(&mut self, force: bool) -> Result<()> {
+        if !force {
+            if self.buffer.modified {
+                return Err(gettext("No write since last change (:quit! overrides)"));
+            }
+            let more = self.files.len().saturating_sub(self.file_index + 1);
+            if more > 0 && !self.more_files_warned {
+                self.more_files_warned = true;
+                return Err(format!("{} {}", more, gettext("more files to edit")));
+            }
+        }
+        if !self.buffer.modified {
+            self.discard_preserved();
+        }
+        self.quit = true;
+        Ok(())
+    }
+
+    fn read_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        let arg = cmd.argument();
+        let arg = arg.trim_ascii_start();
+        let after = range.last;
+        let (content, source) = if let Some(command) = arg.strip_prefix(b"!") {
+            let command = self.expand_shell_command(command)?;
+            self.last_shell_command = Some(command.clone());
+            (self.run_shell(&command, None, true)?, None)
+        } else {
+            let name = match self.expand_filename(arg)? {
+                Some(name) => name,
+                None => self
+                    .filename
+                    .clone()
+                    .ok_or_else(|| gettext("No current filename"))?,
+            };
+            let content = fs::read(&name).map_err(|err| format!("{}: {}", name, err))?;
+            if self.filename.is_none() {
+                self.filename = Some(name.clone());
+            } else if self.filename.as_ref() != Some(&name) {
+                self.alternate = Some(name.clone());
+            }
+            (content, Some(name))
+        };
+        let lines = split_lines(&content);
+        let n = lines.len();
+        if let Some(name) = source {
+            self.info(&format!(
+                "\"{}\" {} {}, {} {}",
+                name,
+                n,
+                gettext("lines"),
+                content.len(),
+                gettext("characters")
+            ));
+        }
+        self.buffer.insert(after, lines);
+        if n > 0 {
+            self.buffer.line = after + n;
+            self.buffer.col = 0;
+        }
+        Ok(Flags::default())
+    }
+
+    fn recover_command(&mut self, cmd: &mut CommandLine, bang: bool) -> Result<Flags> {
+        let arg = cmd.argument();
+        let name = match self.expand_filename(&arg)? {
+            Some(name) => name,
+            None => self
+                .filename
+                .clone()
+                .ok_or_else(|| gettext("No current filename"))?,
+        };
+        self.check_modified(bang, "recover")?;
+        self.recover(&name)?;
+        Ok(Flags::default())
+    }
+
+    fn set_command(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        let arg = cmd.argument();
+        let words = split_set_arguments(&arg);
+        if words.is_empty() || words == ["all"] {
+            let lines = self.options.list(!words.is_empty());
+            self.print(lines.join(" ").as_bytes());
+            return Ok(Flags::default());
+        }
+        let mut shown = Vec::new();
+        for word in &words {
+            if let Some(text) = self.options.set(word)? {
+                shown.push(text);
+            }
+        }
+        if !shown.is_empty() {
+            self.print(shown.join(" ").as_bytes());
+        }
+        if words
+            .iter()
+            .any(|w| w.starts_with("window") || w.starts_with("wi="))
+        {
+            self.visual.resize_window(self.options.number("window"));
+        }
+        Ok(Flags::default())
+    }
+
+    /// Runs the commands of a file, such as `.exrc`.
+    pub fn source(&mut self, path: &str) -> Result<()> {
+        let content = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        let pending = self.pending.take();
+        let result = (|| {
+            for line in content.split(|&b| b == b'\n') {
+                self.execute(line)?;
+            }
+            Ok(())
+        })();
+        self.pending = pending;
+        result
+    }
+
+    fn source_command(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        let arg = cmd.argument();
+        let name = self
+            .expand_filename(&arg)?
+            .ok_or_else(|| gettext("No file name given"))?;
+        self.source(&name)?;
+        Ok(Flags::default())
+    }
+
+    fn substitute_command(
+        &mut self,
+        cmd: &mut CommandLine,
+        name: &str,
+        range: Range,
+    ) -> Result<Flags> {
+        self.check_range(range, false)?;
+        cmd.skip_blanks();
+        let mut substitution = self.last_substitute.clone();
+        let pattern: Vec<u8>;
+        let delimiter = cmd.peek();
+        let explicit = name == "substitute"
+            && matches!(delimiter, Some(c) if !c.is_ascii_alphanumeric() && !b" \t|\"\\\n".contains(&c));
+        if explicit {
+            let delimiter = cmd.next().unwrap();
+            pattern = cmd.pattern(delimiter)?;
+            let mut raw = Vec::new();
+            loop {
+                match cmd.next() {
+                    None => break,
+                    Some(c) if c == delimiter => break,
+                    Some(b'\\') => match cmd.next() {
+                        Some(c) if c == delimiter => raw.push(c),
+                        Some(c) => raw.extend_from_slice(&[b'\\', c]),
+                        None => raw.push(b'\\'),
+                    },
+                    Some(c) => raw.push(c),
+                }
+            }
+            // `%` alone stands for the previous replacement.
+            let raw = if raw == b"%" {
+                self.last_replacement.clone().unwrap_or_default()
+            } else {
+                raw
+            };
+            substitution = Some(Substitution {
+                replacement: raw,
+                global: false,
+                confirm: false,
+            });
+        } else {
+            pattern = Vec::new();
+            if name == "~" && self.last_pattern.is_none() {
+                return Err(gettext("No previous regular expression"));
+            }
+        }
+        let Some(mut substitution) = substitution else {
+            return Err(gettext("No previous substitute to repeat"));
+        };
+
+        // Options: `&` keeps those of the previous substitute.
+        let previous = self.last_substitute.clone().unwrap_or_default();
+        if !explicit {
+            substitution.global = false;
+            substitution.confirm = false;
+        }
+        loop {
+            match cmd.peek() {
+                Some(b'&') => {
+                    substitution.global = previous.global;
+                    substitution.confirm = previous.confirm;
+                }
+                Some(b'g') => substitution.global = !substitution.global,
+                Some(b'c') => substitution.confirm = true,
+                Some(b'r') => {}
+                _ => break,
+            }
+            cmd.next();
+        }
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        let range = self.apply_count(range, count)?;
+
+        let regex = self.compile(&pattern)?;
+        let (parts, expanded) = parse_replacement(
+            &substitution.replacement,
+            self.options.flag("magic"),
+            self.last_replacement.as_deref(),
+        );
+        self.last_replacement = Some(expanded.clone());
+        self.last_substitute = Some(Substitution {
+            replacement: if explicit {
+                expanded
+            } else {
+                substitution.replacement.clone()
+            },
+            ..substitution.clone()
+        });
+
+        let before = self.buffer.len();
+        let mut n = range.first;
+        let mut last = range.last;
+        let mut changed = None;
+        while n <= last {
+            let line = self.buffer.get(n).to_vec();
+            let mut confirm = |start: usize, end: usize| {
+                !substitution.confirm || self.confirm_substitution(&line, start, end)
+            };
+            if let Some(result) =
+                substitute(&regex, &parts, &line, substitution.global, &mut confirm)
+            {
+                let lines: Vec<Vec<u8>> =
+                    result.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect();
+                let added = lines.len() - 1;
+                self.buffer.replace(n, lines[0].clone());
+                self.buffer.insert(n, lines[1..].to_vec());
+                n += added;
+                last += added;
+                changed = Some(n);
+            }
+            n += 1;
+        }
+        match changed {
+            Some(n) => {
+                self.buffer.line = n;
+                self.buffer.col = indentation(self.buffer.get(n)).len();
+                self.report_lines(before, None);
+                Ok(flags)
+            }
+            None if self.pending.is_some() => Ok(Flags::default()),
+            None => Err(gettext("No match found for substitute")),
+        }
+    }
+
+    /// Asks whether to replace a match, showing the line with the match
+    /// marked below it.
+    fn confirm_substitution(&mut self, line: &[u8], start: usize, end: usize) -> bool {
+        if self.mode == Mode::Visual {
+            return self.confirm_visual(line, start, end);
+        }
+        self.print(line);
+        let mut marker: Vec<u8> = line[..start]
+            .iter()
+            .map(|&b| if b == b'\t' { b'\t' } else { b' ' })
+            .collect();
+        marker.extend(std::iter::repeat(b'^').take((end - start).max(1)));
+        self.print(&marker);
+        matches!(self.read_input_line(), Ok(Some(answer)) if answer.starts_with(b"y"))
+    }
+
+    fn write_command(
+        &mut self,
+        cmd: &mut CommandLine,
+        name: &str,
+        range: Range,
+        bang: bool,
+    ) -> Result<Flags> {
+        let range = if range.given == 0 {
+            Range {
+                first: self.buffer.len().min(1),
+                last: self.buffer.len(),
+                given: 2,
+            }
+        } else {
+            range
+        };
+        cmd.skip_blanks();
+        if name == "write" && cmd.peek() == Some(b'!') && !bang {
+            // `w !command` writes the lines to a command.
+            cmd.next();
+            let command = cmd.rest();
+            let command = self.expand_shell_command(&command)?;
+            self.last_shell_command = Some(command.clone());
+            let mut input = Vec::new();
+            if range.first > 0 {
+                for n in range.first..=range.last {
+                    input.extend_from_slice(self.buffer.get(n));
+                    input.push(b'\n');
+                }
+            }
+            self.run_shell(&command, Some(input), false)?;
+            return Ok(Flags::default());
+        }
+        let append = cmd.text[cmd.pos..].starts_with(b">>");
+        if append {
+            cmd.pos += 2;
+        }
+        let arg = cmd.argument();
+        let file = self.expand_filename(&arg)?;
+        if name == "xit" && !self.buffer.modified && (file.is_none() || file == self.filename) {
+            return self.quit_command(bang).map(|_| Flags::default());
+        }
+        self.write(range.first, range.last, file, append, bang)?;
+        if name != "write" {
+            // Writing the whole buffer, to any file, is enough to quit.
+            let whole = range.first <= 1 && range.last == self.buffer.len();
+            self.quit_command(bang || whole)?;
+        }
+        Ok(Flags::default())
+    }
+
+    fn z_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        cmd.skip_blanks();
+        let kind = match cmd.peek() {
+            Some(c @ (b'+' | b'-' | b'.' | b'^' | b'=')) => {
+                cmd.next();
+                c
+            }
+            _ => b'+',
+        };
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        if self.buffer.is_empty() {
+            return Err(gettext("Empty file"));
+        }
+        let count = count.unwrap_or(self.options.number("window")).max(1);
+        let len = self.buffer.len();
+        let line = if range.given == 0 && kind == b'+' {
+            (self.buffer.line + 1).min(len)
+        } else {
+            range.last.max(1)
+        };
+        let (first, last) = match kind {
+            b'+' => (line, (line + count - 1).min(len)),
+            b'-' => (line.saturating_sub(count - 1).max(1), line),
+            b'^' => {
+                let last = line.saturating_sub(count).max(1);
+                (last.saturating_sub(count - 1).max(1), last)
+            }
+            _ => {
+                let half = count / 2;
+                (line.saturating_sub(half).max(1), (line + half).min(len))
+            }
+        };
+        let flags = Flags {
+            print: false,
+            ..flags
+        };
+        for n in first..=last {
+            if kind == b'=' && n == line {
+                let dashes = vec![b'-'; 20];
+                self.print(&dashes);
+                self.print_line(n, flags);
+                self.print(&dashes);
+            } else {
+                self.print_line(n, flags);
+            }
+        }
+        self.buffer.line = if kind == b'=' || kind == b'.' {
+            line
+        } else {
+            last
+        };
+        Ok(Flags::default())
+    }
+
+    fn bang_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        let text = cmd.rest();
+        let command = self.expand_shell_command(&text)?;
+        if command.trim().is_empty() {
+            return Err(gettext("Missing shell command"));
+        }
+        self.last_shell_command = Some(command.clone());
+        if range.given == 0 {
+            if self.buffer.modified && self.options.flag("warn") && !self.options.flag("autowrite")
+            {
+                self.info(&gettext("[No write since last change]"));
+            }
+            self.autowrite()?;
+            self.run_shell(&command, None, false)?;
+            return Ok(Flags::default());
+        }
+        self.check_range(range, false)?;
+        self.filter(range.first, range.last, &command)?;
+        Ok(Flags::default())
+    }
+
+    /// Replaces lines with the output of a command that reads them.
+    pub fn filter(&mut self, first: usize, last: usize, command: &str) -> Result<()> {
+        let mut input = Vec::new();
+        for n in first..=last {
+            input.extend_from_slice(self.buffer.get(n));
+            input.push(b'\n');
+        }
+        let output = self.run_shell(command, Some(input), true)?;
+        let lines = split_lines(&output);
+        let n = lines.len();
+        let before = self.buffer.len();
+        self.buffer.delete(first, last);
+        self.buffer.insert(first - 1, lines);
+        self.buffer.line = if n > 0 {
+            first
+        } else {
+            (first - 1).max(self.buffer.len().min(1))
+        };
+        self.buffer.col = 0;
+        self.report_lines(before, None);
+        Ok(())
+    }
+
+    fn shift_command(&mut self, cmd: &mut CommandLine, right: bool, range: Range) -> Result<Flags> {
+        let symbol = if right { b'>' } else { b'<' };
+        let mut times = 1;
+        loop {
+            cmd.skip_blanks();
+            if cmd.peek() != Some(symbol) {
+                break;
+            }
+            cmd.next();
+            times += 1;
+        }
+        let (count, flags) = Self::count_and_flags(cmd)?;
+        let range = self.apply_count(range, count)?;
+        self.check_range(range, false)?;
+        self.shift_lines(range.first, range.last, right, times);
+        self.buffer.line = range.last;
+        Ok(flags)
+    }
+
+    /// Shifts lines by `times` times the `shiftwidth` option.
+    pub fn shift_lines(&mut self, first: usize, last: usize, right: bool, times: usize) {
+        let tabstop = self.options.number("tabstop").max(1);
+        let amount = self.options.number("shiftwidth") * times;
+        for n in first..=last {
+            let line = self.buffer.get(n);
+            if line.is_empty() {
+                continue;
+            }
+            let width = indent_width(line, tabstop);
+            let width = if right {
+                width + amount
+            } else {
+                width.saturating_sub(amount)
+            };
+            let mut shifted = make_indent(width, tabstop);
+            shifted.extend_from_slice(&line[indentation(line).len()..]);
+            self.buffer.replace(n, shifted);
+        }
+    }
+
+    fn execute_register(&mut self, cmd: &mut CommandLine) -> Result<Flags> {
+        cmd.skip_blanks();
+        let name = match cmd.next() {
+            Some(c) if c.is_ascii_alphanumeric() => c,
+            _ => b'"',
+        };
+        cmd.end()?;
+        let register = self
+            .register(name)
+            .cloned()
+            .ok_or_else(|| gettext("Nothing in the register"))?;
+        for line in register.text {
+            self.execute(&line)?;
+            if self.quit {
+                break;
+            }
+        }
+        Ok(Flags::default())
+    }
+
+    fn scroll_command(&mut self, cmd: &mut CommandLine, range: Range) -> Result<Flags> {
+        let count = Self::parse_count(cmd)?;
+        cmd.end()?;
+        if let Some(count) = count {
+            self.options.set_number("scroll", count);
+        }
+        let len = self.buffer.len();
+        let first = range.last + 1;
+        if first > len {
+            return Err(gettext("At end-of-file"));
+        }
+        let last = (range.last + self.options.number("scroll")).min(len);
+        for n in first..=last {
+            self.print_line(n, Flags::default());
+        }
+        self.buffer.line = last;
+        Ok(Flags::default())
+    }
+}
+
+/// Splits off the first blank-separated word of an argument.
+fn split_word(arg: &[u8]) -> (&[u8], &[u8]) {
+    let end = arg
+        .iter()
+        .position(|&b| b == b' ' || b == b'\t')
+        .unwrap_or(arg.len());
+    (&arg[..end], arg[end..].trim_ascii_start())
+}
+
+/// Removes the `^V` that quotes the next character in map arguments.
+fn unescape_literal_next(text: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == 0x16 && i + 1 < text.len() {
+            i += 1;
+        }
+        result.push(text[i]);
+        i += 1;
+    }
+    result
+}
+
+/// Splits the arguments of `set` at blanks, which a backslash escapes.
+fn split_set_arguments(arg: &[u8]) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = Vec::new();
+    let mut i = 0;
+    while i < arg.len() {
+        match arg[i] {
+            b'\\' if i + 1 < arg.len() => {
+                word.push(arg[i + 1]);
+                i += 1;
+            }
+            b' ' | b'\t' => {
+                if !word.is_empty() {
+                    words.push(String::from_utf8_lossy(&std::mem::take(&mut word)).into_owned());
+                }
+            }
+            c => word.push(c),
+        }
+        i += 1;
+    }
+    if !word.is_empty() {
+        words.push(String::from_utf8_lossy(&word).into_owned());
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_abbreviations() {
+        assert_eq!(lookup(b"a"), Some("append"));
+        assert_eq!(lookup(b"ab"), Some("abbreviate"));
+        assert_eq!(lookup(b"ma"), Some("mark"));
+        assert_eq!(lookup(b"map"), Some("map"));
+        assert_eq!(lookup(b"s"), Some("substitute"));
+        assert_eq!(lookup(b"su"), Some("suspend"));
+        assert_eq!(lookup(b"unm"), Some("unmap"));
+        assert_eq!(lookup(b"vi"), Some("visual"));
+        assert_eq!(lookup(b"ka"), None);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        assert_eq!(join_lines(b"a", b"  b", false), b"a b");
+        assert_eq!(join_lines(b"end.", b"next", false), b"end.  next");
+        assert_eq!(join_lines(b"f(x", b")", false), b"f(x)");
+        assert_eq!(join_lines(b"a ", b"b", false), b"a b");
+        assert_eq!(join_lines(b"a", b"  b", true), b"a  b");
+    }
+
+    #[test]
+    fn test_indentation() {
+        assert_eq!(indent_width(b"\t  x", 8), 10);
+        assert_eq!(make_indent(10, 8), b"\t  ");
+        assert_eq!(list_line(b"a\tb\x7f"), b"a^Ib^?$");
+    }
+}
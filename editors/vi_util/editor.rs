@@ -0,0 +1,827 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::buffer::Buffer;
+use super::options::Options;
+use super::regex::{compile_regex, translate};
+use super::screen::Visual;
+use crate::common::{search, split_lines, Input, ReadError};
+use gettextrs::gettext;
+use plib::regex::Regex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+pub type Result<T> = std::result::Result<T, String>;
+
+pub use crate::common::{HANGUP, INTERRUPTED};
+pub static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGINT => INTERRUPTED.store(true, Ordering::SeqCst),
+        libc::SIGWINCH => RESIZED.store(true, Ordering::SeqCst),
+        _ => HANGUP.store(true, Ordering::SeqCst),
+    }
+}
+
+/// Catches the signals the editor handles, without restarting reads, so
+/// that a signal wakes up an editor waiting for input.
+pub fn install_signal_handlers() {
+    for signal in [libc::SIGINT, libc::SIGHUP, libc::SIGTERM, libc::SIGWINCH] {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction =
+                handle_signal as *const extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+    unsafe { libc::signal(libc::SIGQUIT, libc::SIG_IGN) };
+}
+
+/// The first line of a recovery file, followed by the name of the file
+/// being edited.
+const RECOVERY_MAGIC: &[u8] = b"posixutils-vi recovery file: ";
+
+/// The contents of a named or numbered register.
+#[derive(Clone, Default)]
+pub struct Register {
+    /// Whether the text is whole lines, rather than part of a line or a
+    /// span across lines.
+    pub linewise: bool,
+    pub text: Vec<Vec<u8>>,
+}
+
+/// Whether the editor runs line-oriented or full-screen.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Ex,
+    Visual,
+}
+
+/// The state shared by `ex` and `vi`.
+pub struct Editor {
+    pub buffer: Buffer,
+    pub options: Options,
+    pub registers: HashMap<u8, Register>,
+
+    /// The argument list, and the index of the file edited from it.
+    pub files: Vec<String>,
+    pub file_index: usize,
+    pub more_files_warned: bool,
+    pub filename: Option<String>,
+    pub alternate: Option<String>,
+    pub last_pattern: Option<Vec<u8>>,
+    pub last_search_forward: bool,
+    pub last_replacement: Option<Vec<u8>>,
+    pub last_substitute: Option<super::command::Substitution>,
+    pub last_shell_command: Option<String>,
+
+    /// Mappings of command mode and of text input mode, and abbreviations.
+    pub maps: Vec<(Vec<u8>, Vec<u8>)>,
+    pub input_maps: Vec<(Vec<u8>, Vec<u8>)>,
+    pub abbreviations: Vec<(Vec<u8>, Vec<u8>)>,
+    pub input: Input,
+
+    /// The remaining lines of the command list of a global command.
+    pub pending: Option<VecDeque<Vec<u8>>>,
+    pub mode: Mode,
+    pub visual: Visual,
+
+    /// `ex -s`: no prompts, informational messages or startup files.
+    pub batch: bool,
+    pub interactive: bool,
+    pub quit: bool,
+    pub status: i32,
+
+    /// The recovery file written by `preserve`, and the one the buffer was
+    /// recovered from.
+    pub preserved: Option<PathBuf>,
+    pub recovered_from: Option<PathBuf>,
+}
+
+impl Editor {
+    pub fn new(mode: Mode, batch: bool) -> Self {
+        let (rows, _) = super::terminal::window_size();
+        Editor {
+            buffer: Buffer::new(),
+            options: Options::new(rows),
+            registers: HashMap::new(),
+            files: Vec::new(),
+            file_index: 0,
+            more_files_warned: false,
+            filename: None,
+            alternate: None,
+            last_pattern: None,
+            last_search_forward: true,
+            last_replacement: None,
+            last_substitute: None,
+            last_shell_command: None,
+            maps: Vec::new(),
+            input_maps: Vec::new(),
+            abbreviations: Vec::new(),
+            input: Input::default(),
+            pending: None,
+            mode,
+            visual: Visual::new(),
+            batch,
+            interactive: !batch && unsafe { libc::isatty(0) } == 1,
+            quit: false,
+            status: 0,
+            preserved: None,
+            recovered_from: None,
+        }
+    }
+
+    /// Writes a line of command output: to the standard output in `ex`,
+    /// or to the bottom of the screen in `vi`.
+    pub fn print(&mut self, text: &[u8]) {
+        if self.mode == Mode::Visual {
+            self.visual.messages.push(text.to_vec());
+        } else {
+            let mut out = io::stdout().lock();
+            let _ = out.write_all(text);
+            let _ = out.write_all(b"\n");
+            let _ = out.flush();
+        }
+    }
+
+    /// Writes an informational message, which batch mode suppresses.
+    pub fn info(&mut self, text: &str) {
+        if !self.batch {
+            self.print(text.as_bytes());
+        }
+    }
+
+    /// Reports that lines were added or removed, if they are more than the
+    /// `report` option.
+    pub fn report_lines(&mut self, before: usize, what: Option<&str>) {
+        let after = self.buffer.len();
+        let report = self.options.number("report");
+        let message = match what {
+            Some(what) if before > report => format!("{} {}", before, what),
+            Some(_) => return,
+            None if after > before && after - before > report => {
+                format!("{} {}", after - before, gettext("more lines"))
+            }
+            None if before > after && before - after > report => {
+                format!("{} {}", before - after, gettext("fewer lines"))
+            }
+            None => return,
+        };
+        self.info(&message);
+    }
+
+    /// Reads a line of text input for `append`, `change` and `insert`.
+    pub fn read_text_line(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(pending) = &mut self.pending {
+            return Ok(pending.pop_front());
+        }
+        if self.mode == Mode::Visual {
+            return self.read_bottom_line(b"");
+        }
+        self.read_input_line()
+    }
+
+    /// Reads a line of the standard input.  A hangup ends the input.
+    pub fn read_input_line(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.input.read_line() {
+            Ok(line) => Ok(line),
+            Err(ReadError::Interrupted) => Err(gettext("Interrupt")),
+            Err(ReadError::HungUp) => Ok(None),
+            Err(ReadError::Io(err)) => Err(err.to_string()),
+        }
+    }
+
+    pub fn register(&self, name: u8) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Stores text in a register: the named one, with an uppercase name
+    /// appending, or else the unnamed one.  Deleted text that is not named
+    /// also shifts through the numbered registers.
+    pub fn store_register(&mut self, name: Option<u8>, register: Register, deleted: bool) {
+        match name {
+            Some(name @ b'A'..=b'Z') => {
+                let entry = self.registers.entry(name.to_ascii_lowercase()).or_default();
+                if entry.linewise || register.linewise || entry.text.is_empty() {
+                    entry.text.extend(register.text.iter().cloned());
+                } else {
+                    let mut text = register.text.iter().cloned();
+                    if let (Some(last), Some(first)) = (entry.text.last_mut(), text.next()) {
+                        last.extend(first);
+                    }
+                    entry.text.extend(text);
+                }
+                entry.linewise |= register.linewise;
+            }
+            Some(name) => {
+                self.registers.insert(name, register.clone());
+            }
+            None if deleted && (register.linewise || register.text.len() > 1) => {
+                for n in (b'1'..b'9').rev() {
+                    if let Some(r) = self.registers.remove(&n) {
+                        self.registers.insert(n + 1, r);
+                    }
+                }
+                self.registers.insert(b'1', register.clone());
+            }
+            None => {}
+        }
+        let unnamed = match name {
+            Some(name) => self.registers[&name.to_ascii_lowercase()].clone(),
+            None => register,
+        };
+        self.registers.insert(b'"', unnamed);
+    }
+
+    /// Compiles a pattern as written in a command.  An empty one stands for
+    /// the previous pattern.
+    pub fn compile(&mut self, pattern: &[u8]) -> Result<Regex> {
+        let pattern = if pattern.is_empty() {
+            self.last_pattern
+                .clone()
+                .ok_or_else(|| gettext("No previous regular expression"))?
+        } else {
+            self.last_pattern = Some(pattern.to_vec());
+            pattern.to_vec()
+        };
+        let translated = translate(
+            &pattern,
+            self.options.flag("magic"),
+            self.last_replacement.as_deref(),
+        );
+        compile_regex(&translated, self.options.flag("ignorecase"))
+    }
+
+    /// Finds the next line matching `regex`, searching forward or backward
+    /// from line `from`, and around the buffer if `wrapscan` is set.
+    pub fn search_lines(&self, regex: &Regex, from: usize, forward: bool) -> Result<usize> {
+        let len = self.buffer.len();
+        let wrapscan = self.options.flag("wrapscan");
+        search(regex, len, from, forward, wrapscan, |n| self.buffer.get(n)).ok_or_else(|| {
+            if wrapscan {
+                gettext("Pattern not found")
+            } else if forward {
+                gettext("Pattern not found before end of file")
+            } else {
+                gettext("Pattern not found before beginning of file")
+            }
+        })
+    }
+
+    /// Expands a file name argument: `%` is the current file and `#` the
+    /// alternate one, and patterns are expanded by the shell.
+    pub fn expand_filenames(&self, arg: &[u8]) -> Result<Vec<String>> {
+        let mut expanded = Vec::new();
+        let mut i = 0;
+        while i < arg.len() {
+            match arg[i] {
+                b'\\' if matches!(arg.get(i + 1), Some(b'%' | b'#')) => {
+                    expanded.push(arg[i + 1]);
+                    i += 1;
+                }
+                b'%' => expanded.extend(
+                    self.filename
+                        .as_ref()
+                        .ok_or_else(|| gettext("No current filename"))?
+                        .as_bytes(),
+                ),
+                b'#' => expanded.extend(
+                    self.alternate
+                        .as_ref()
+                        .ok_or_else(|| gettext("No alternate filename"))?
+                        .as_bytes(),
+                ),
+                c => expanded.push(c),
+            }
+            i += 1;
+        }
+        let expanded = String::from_utf8_lossy(&expanded).trim().to_string();
+        if expanded.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !expanded.contains(|c| "*?[{~$`'\"\\ \t".contains(c)) {
+            return Ok(vec![expanded]);
+        }
+        let output = Command::new(self.options.text("shell"))
+            .arg("-c")
+            .arg(format!(
+                "for f in {}; do printf '%s\\n' \"$f\"; done",
+                expanded
+            ))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| err.to_string())?;
+        let names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() {
+            return Err(format!("{}: {}", expanded, gettext("No match")));
+        }
+        Ok(names)
+    }
+
+    /// Expands a file name argument that must name a single file.
+    pub fn expand_filename(&self, arg: &[u8]) -> Result<Option<String>> {
+        let mut names = self.expand_filenames(arg)?;
+        match names.len() {
+            0 => Ok(None),
+            1 => Ok(names.pop()),
+            _ => Err(gettext("Too many file names")),
+        }
+    }
+
+    /// Reports the name and size of the edited file, as `^G` and `file` do.
+    pub fn file_status(&self) -> String {
+        let name = self
+            .filename
+            .clone()
+            .unwrap_or_else(|| gettext("[No file]"));
+        let mut status = format!("\"{}\"", name);
+        if self.options.flag("readonly") {
+            status.push_str(&format!(" [{}]", gettext("Read only")));
+        }
+        if self.buffer.modified {
+            status.push_str(&format!(" [{}]", gettext("Modified")));
+        }
+        let len = self.buffer.len();
+        if len == 0 {
+            status.push_str(&format!(" {}", gettext("--No lines in buffer--")));
+        } else {
+            status.push_str(&format!(
+                " {} {} {} {} --{}%--",
+                gettext("line"),
+                self.buffer.line,
+                gettext("of"),
+                len,
+                self.buffer.line * 100 / len
+            ));
+        }
+        status
+    }
+
+    /// Makes `name` the edited file, without reading it.
+    pub fn set_filename(&mut self, name: String) {
+        if self.filename.as_ref() != Some(&name) {
+            self.alternate = self.filename.replace(name);
+        }
+    }
+
+    /// Reads a file into the buffer in place of its contents.
+    pub fn edit(&mut self, name: Option<String>) -> Result<()> {
+        if let Some(name) = name {
+            self.set_filename(name);
+        }
+        self.discard_preserved();
+        self.recovered_from = None;
+        let Some(name) = self.filename.clone() else {
+            self.buffer.load(Vec::new());
+            return Ok(());
+        };
+        match fs::read(&name) {
+            Ok(content) => {
+                self.buffer.load(split_lines(&content));
+                let writable = OpenOptions::new().append(true).open(&name).is_ok();
+                self.options
+                    .set_flag("readonly", !writable && !self.writeany());
+                let mut message = format!("\"{}\"", name);
+                if !writable {
+                    message.push_str(&format!(" [{}]", gettext("Read only")));
+                }
+                if !content.is_empty() && !content.ends_with(b"\n") {
+                    message.push_str(&format!(" [{}]", gettext("Incomplete last line")));
+                }
+                message.push_str(&format!(
+                    " {} {}, {} {}",
+                    self.buffer.len(),
+                    gettext("lines"),
+                    content.len(),
+                    gettext("characters")
+                ));
+                self.info(&message);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.buffer.load(Vec::new());
+                self.info(&format!("\"{}\" [{}]", name, gettext("New file")));
+            }
+            Err(err) => {
+                self.buffer.load(Vec::new());
+                return Err(format!("{}: {}", name, err));
+            }
+        }
+        if self.mode == Mode::Ex {
+            self.buffer.line = self.buffer.len();
+        }
+        Ok(())
+    }
+
+    fn writeany(&self) -> bool {
+        self.options.flag("writeany")
+    }
+
+    /// Writes lines `first` to `last` to a file, or appends them to it.
+    /// Without `force`, an existing file other than the edited one is not
+    /// overwritten, nor is a read-only edited file.
+    pub fn write(
+        &mut self,
+        first: usize,
+        last: usize,
+        name: Option<String>,
+        append: bool,
+        force: bool,
+    ) -> Result<()> {
+        let current = name.is_none() || name == self.filename;
+        let Some(name) = name.or_else(|| self.filename.clone()) else {
+            return Err(gettext("No current filename"));
+        };
+        let force = force || self.writeany();
+        if !force && !append {
+            if current && self.options.flag("readonly") {
+                return Err(format!(
+                    "\"{}\" {}",
+                    name,
+                    gettext("File is read only (use ! to override)")
+                ));
+            }
+            if !current && Path::new(&name).exists() {
+                return Err(format!(
+                    "\"{}\" {}",
+                    name,
+                    gettext("File exists (use ! to override)")
+                ));
+            }
+        }
+
+        let mut content = Vec::new();
+        if first > 0 {
+            for line in &self.buffer.lines()[first - 1..last] {
+                content.extend_from_slice(line);
+                content.push(b'\n');
+            }
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(&name)
+            .map_err(|err| format!("{}: {}", name, err))?;
+        file.write_all(&content)
+            .map_err(|err| format!("{}: {}", name, err))?;
+
+        let whole = first <= 1 && last == self.buffer.len();
+        if current && whole && !append {
+            self.buffer.modified = false;
+            self.discard_preserved();
+            if let Some(path) = self.recovered_from.take() {
+                let _ = fs::remove_file(path);
+            }
+        }
+        if self.filename.is_none() {
+            self.filename = Some(name.clone());
+        }
+        let lines = if first == 0 { 0 } else { last - first + 1 };
+        self.info(&format!(
+            "\"{}\" {}{} {}, {} {}",
+            name,
+            if append {
+                format!("{} ", gettext("appended"))
+            } else {
+                String::new()
+            },
+            lines,
+            gettext("lines"),
+            content.len(),
+            gettext("characters")
+        ));
+        Ok(())
+    }
+
+    /// Writes the buffer if `autowrite` is set and it is modified.  Returns
+    /// whether the buffer may be abandoned.
+    pub fn autowrite(&mut self) -> Result<bool> {
+        if !self.buffer.modified {
+            return Ok(true);
+        }
+        if self.options.flag("autowrite") && self.filename.is_some() {
+            self.write(1, self.buffer.len(), None, false, false)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Fails if the buffer is modified and may not be abandoned.
+    pub fn check_modified(&mut self, force: bool, command: &str) -> Result<()> {
+        if force || self.autowrite()? {
+            return Ok(());
+        }
+        Err(format!(
+            "{} (:{}! {})",
+            gettext("No write since last change"),
+            command,
+            gettext("overrides")
+        ))
+    }
+
+    /// Runs a shell command with the terminal restored to its normal modes.
+    /// The command reads `input`, if given, and its standard output is
+    /// returned when `capture` is set.
+    pub fn run_shell(
+        &mut self,
+        command: &str,
+        input: Option<Vec<u8>>,
+        capture: bool,
+    ) -> Result<Vec<u8>> {
+        let visual = self.mode == Mode::Visual;
+        if visual && !capture {
+            self.suspend_screen();
+        }
+        let mut child = Command::new(self.options.text("shell"));
+        child.arg("-c").arg(command);
+        if input.is_some() {
+            child.stdin(Stdio::piped());
+        }
+        if capture {
+            child.stdout(Stdio::piped());
+        }
+        let result = child
+            .spawn()
+            .map_err(|err| err.to_string())
+            .and_then(|mut child| {
+                if let Some(input) = input {
+                    let mut stdin = child.stdin.take().unwrap();
+                    // Feed the input from a thread, so that a command producing
+                    // much output cannot block on a full pipe.
+                    let writer = std::thread::spawn(move || {
+                        let _ = stdin.write_all(&input);
+                    });
+                    let output = child.wait_with_output().map_err(|err| err.to_string());
+                    let _ = writer.join();
+                    output.map(|output| output.stdout)
+                } else {
+                    child
+                        .wait_with_output()
+                        .map(|output| output.stdout)
+                        .map_err(|err| err.to_string())
+                }
+            });
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        if visual && !capture {
+            self.wait_for_return();
+            self.resume_screen();
+        } else if !capture && !self.batch {
+            self.print(b"!");
+        }
+        result
+    }
+
+    /// Expands `!` to the previous shell command, and `%` and `#` to file
+    /// names, in the text of a shell command.
+    pub fn expand_shell_command(&self, text: &[u8]) -> Result<String> {
+        let mut command = Vec::new();
+        let mut i = 0;
+        while i < text.len() {
+            match text[i] {
+                b'\\' if matches!(text.get(i + 1), Some(b'!' | b'%' | b'#')) => {
+                    command.push(text[i + 1]);
+                    i += 1;
+                }
+                b'!' => command.extend(
+                    self.last_shell_command
+                        .as_ref()
+                        .ok_or_else(|| gettext("No previous command to substitute for !"))?
+                        .as_bytes(),
+                ),
+                b'%' => command.extend(
+                    self.filename
+                        .as_ref()
+                        .ok_or_else(|| gettext("No filename to substitute for %"))?
+                        .as_bytes(),
+                ),
+                b'#' => command.extend(
+                    self.alternate
+                        .as_ref()
+                        .ok_or_else(|| gettext("No alternate filename to substitute for #"))?
+                        .as_bytes(),
+                ),
+                c => command.push(c),
+            }
+            i += 1;
+        }
+        Ok(String::from_utf8_lossy(&command).into_owned())
+    }
+
+    fn recovery_path(&self) -> PathBuf {
+        Path::new(self.options.text("directory")).join(format!(
+            "vi.{}.{}",
+            unsafe { libc::getuid() },
+            std::process::id()
+        ))
+    }
+
+    /// Writes the buffer to a recovery file, which `-r` can read back after
+    /// the editor or the system fails.
+    pub fn preserve(&mut self) -> Result<PathBuf> {
+        let path = self.recovery_path();
+        let mut content = RECOVERY_MAGIC.to_vec();
+        content.extend(self.filename.as_deref().unwrap_or("").as_bytes());
+        content.push(b'\n');
+        for line in self.buffer.lines() {
+            content.extend_from_slice(line);
+            content.push(b'\n');
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        file.write_all(&content)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.preserved = Some(path.clone());
+        Ok(path)
+    }
+
+    /// Removes the recovery file written by `preserve`, once the buffer it
+    /// holds is no longer needed.
+    pub fn discard_preserved(&mut self) {
+        if let Some(path) = self.preserved.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Lists the recovery files of the user in the `directory` option, with
+    /// the names of the files they hold, most recent first.
+    pub fn recovery_files(&self) -> Vec<(PathBuf, String, SystemTime)> {
+        let uid = unsafe { libc::getuid() };
+        let prefix = format!("vi.{}.", uid);
+        let Ok(entries) = fs::read_dir(self.options.text("directory")) else {
+            return Vec::new();
+        };
+        let mut files: Vec<_> = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                if metadata.uid() != uid {
+                    return None;
+                }
+                let content = fs::read(entry.path()).ok()?;
+                let header = content.split(|&b| b == b'\n').next()?;
+                let name = header.strip_prefix(RECOVERY_MAGIC)?;
+                Some((
+                    entry.path(),
+                    String::from_utf8_lossy(name).into_owned(),
+                    metadata.modified().ok()?,
+                ))
+            })
+            .collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.2));
+        files
+    }
+
+    /// Reads the most recent recovery file of `name` into the buffer.
+    pub fn recover(&mut self, name: &str) -> Result<()> {
+        let Some((path, _, _)) = self
+            .recovery_files()
+            .into_iter()
+            .find(|(_, file, _)| file == name)
+        else {
+            return Err(format!("{}: {}", name, gettext("No recovery file found")));
+        };
+        let content = fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let start = content
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(content.len(), |n| n + 1);
+        self.set_filename(name.to_string());
+        self.buffer.load(split_lines(&content[start..]));
+        self.buffer.modified = true;
+        if self.mode == Mode::Ex {
+            self.buffer.line = self.buffer.len();
+        }
+        self.recovered_from = Some(path);
+        self.info(&format!(
+            "\"{}\" {} {} {}",
+            name,
+            gettext("recovered:"),
+            self.buffer.len(),
+            gettext("lines")
+        ));
+        Ok(())
+    }
+
+    /// Preserves a modified buffer and exits, after a hangup or a request
+    /// to terminate.
+    pub fn hangup(&mut self) -> ! {
+        if self.mode == Mode::Visual {
+            self.suspend_screen();
+        }
+        if self.buffer.modified {
+            let _ = self.preserve();
+        }
+        std::process::exit(1)
+    }
+
+    /// Finds a tag in the files of the `tags` option, returning the file
+    /// and the address it names.
+    pub fn find_tag(&self, tag: &str) -> Result<(String, Vec<u8>)> {
+        let significant = match self.options.number("taglength") {
+            0 => usize::MAX,
+            n => n,
+        };
+        let truncate = |name: &str| name.chars().take(significant).collect::<String>();
+        let wanted = truncate(tag);
+        for tags_file in self.options.text("tags").split_whitespace() {
+            let Ok(content) = fs::read(tags_file) else {
+                continue;
+            };
+            for line in content.split(|&b| b == b'\n') {
+                let mut fields = line.splitn(3, |&b| b == b'\t');
+                let (Some(name), Some(file), Some(address)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                if truncate(&String::from_utf8_lossy(name)) == wanted {
+                    // A tag's file is relative to the directory of its tags
+                    // file.
+                    let file = String::from_utf8_lossy(file).into_owned();
+                    let file = match Path::new(tags_file).parent() {
+                        Some(dir) if !dir.as_os_str().is_empty() && !file.starts_with('/') => {
+                            dir.join(file).to_string_lossy().into_owned()
+                        }
+                        _ => file,
+                    };
+                    return Ok((file, address.to_vec()));
+                }
+            }
+        }
+        Err(format!("{}: {}", tag, gettext("tag not found")))
+    }
+
+    /// Edits the file of a tag and moves to the line it names.
+    pub fn goto_tag(&mut self, tag: &str, force: bool) -> Result<()> {
+        let (file, address) = self.find_tag(tag)?;
+        if self.filename.as_deref() != Some(file.as_str()) {
+            self.check_modified(force, "tag")?;
+            self.edit(Some(file))?;
+        }
+        let address = address.strip_suffix(b";\"").unwrap_or(&address);
+        let line = if address.iter().all(u8::is_ascii_digit) {
+            String::from_utf8_lossy(address)
+                .parse::<usize>()
+                .map_err(|_| gettext("Invalid tag address"))?
+        } else {
+            let delimiter = *address.first().unwrap_or(&b'/');
+            let pattern = address
+                .strip_prefix(&[delimiter])
+                .map(|p| p.strip_suffix(&[delimiter]).unwrap_or(p))
+                .ok_or_else(|| gettext("Invalid tag address"))?;
+            // Tag patterns match literally, but for `^` and `$`.
+            let mut unescaped = Vec::new();
+            let mut i = 0;
+            while i < pattern.len() {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    i += 1;
+                }
+                unescaped.push(pattern[i]);
+                i += 1;
+            }
+            let regex = compile_regex(&translate(&unescaped, false, None), false)?;
+            let from = if delimiter == b'?' {
+                1
+            } else {
+                self.buffer.len()
+            };
+            self.search_lines(&regex, from, delimiter != b'?')
+                .map_err(|_| format!("{}: {}", tag, gettext("tag address not found")))?
+        };
+        if line == 0 || line > self.buffer.len() {
+            return Err(gettext("Invalid tag address"));
+        }
+        self.buffer.line = line;
+        self.buffer.col = 0;
+        Ok(())
+    }
+}
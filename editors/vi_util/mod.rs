@@ -0,0 +1,241 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The editor shared by `ex` and `vi`: one program with a line-oriented
+//! and a full-screen mode, as POSIX describes them.
+
+mod buffer;
+mod command;
+mod editor;
+mod options;
+mod regex;
+mod screen;
+mod terminal;
+mod visual;
+
+use clap::Parser;
+use editor::{install_signal_handlers, Editor, Mode, HANGUP};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::PROJECT_NAME;
+use std::{
+    io::{self, Write},
+    path::Path,
+    sync::atomic::Ordering,
+};
+
+/// ex, vi - text editors
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Run the ex command after reading the first file.
+    #[arg(short = 'c', value_name = "COMMAND")]
+    command: Option<String>,
+
+    /// Recover the named files, or list the files that can be recovered.
+    #[arg(short = 'r')]
+    recover: bool,
+
+    /// Set the readonly option.
+    #[arg(short = 'R')]
+    readonly: bool,
+
+    /// Batch mode: no prompts, informational messages or startup files
+    /// (ex only).
+    #[arg(short = 's')]
+    silent: bool,
+
+    /// Edit the file containing the tag.
+    #[arg(short = 't', value_name = "TAG")]
+    tag: Option<String>,
+
+    /// Start in visual mode (ex only).
+    #[arg(short = 'v')]
+    visual: bool,
+
+    /// Set the window option to SIZE lines.
+    #[arg(short = 'w', value_name = "SIZE")]
+    window: Option<usize>,
+
+    /// The files to edit.
+    files: Vec<String>,
+}
+
+/// Rewrites the historical `+command` and `-` arguments as `-c command`
+/// and `-s`, which precede the file operands.
+fn preprocess_args(ex: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut operands = false;
+    for (i, arg) in std::env::args().enumerate() {
+        if i == 0 || operands {
+            args.push(arg);
+        } else if arg == "--" {
+            operands = true;
+            args.push(arg);
+        } else if arg == "+" {
+            args.extend(["-c".to_string(), "$".to_string()]);
+        } else if let Some(command) = arg.strip_prefix('+') {
+            args.extend(["-c".to_string(), command.to_string()]);
+        } else if arg == "-" && ex {
+            args.push("-s".to_string());
+        } else {
+            args.push(arg);
+        }
+    }
+    args
+}
+
+fn report(message: &str) {
+    let _ = writeln!(io::stderr(), "{}", message);
+}
+
+/// Runs the startup commands: `EXINIT` or `$HOME/.exrc`, then `./.exrc` if
+/// the `exrc` option allows it.
+fn startup(editor: &mut Editor) -> editor::Result<()> {
+    let home = std::env::var("HOME")
+        .ok()
+        .map(|home| Path::new(&home).join(".exrc"));
+    match std::env::var("EXINIT") {
+        Ok(commands) => editor.execute(commands.as_bytes())?,
+        Err(_) => {
+            if let Some(home) = home.as_ref().filter(|path| path.is_file()) {
+                editor.source(&home.to_string_lossy())?;
+            }
+        }
+    }
+    let local = Path::new(".exrc");
+    if editor.options.flag("exrc") && local.is_file() {
+        let same = match (&home, local.canonicalize()) {
+            (Some(home), Ok(local)) => home.canonicalize().is_ok_and(|home| home == local),
+            _ => false,
+        };
+        if !same {
+            editor.source(".exrc")?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads and runs ex commands until the editor quits or switches to
+/// visual mode.  Returns false after an error that ends a non-interactive
+/// session.
+fn run_ex(editor: &mut Editor) -> bool {
+    while editor.mode == Mode::Ex && !editor.quit {
+        if editor.interactive && editor.options.flag("prompt") {
+            let mut out = io::stdout().lock();
+            let _ = out.write_all(b":");
+            let _ = out.flush();
+        }
+        let result = match editor.read_input_line() {
+            Ok(Some(line)) => editor.execute(&line),
+            Ok(None) => {
+                if HANGUP.load(Ordering::SeqCst) {
+                    editor.hangup();
+                }
+                // The end of input acts like `quit`.
+                if editor.interactive {
+                    editor.quit_command(false)
+                } else {
+                    editor.quit = true;
+                    Ok(())
+                }
+            }
+            Err(message) => Err(message),
+        };
+        if let Err(message) = result {
+            report(&message);
+            if !editor.interactive {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+pub fn main(ex: bool) -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse_from(preprocess_args(ex));
+    install_signal_handlers();
+
+    let mode = if ex && !args.visual {
+        Mode::Ex
+    } else {
+        Mode::Visual
+    };
+    let mut editor = Editor::new(mode, ex && args.silent);
+
+    if args.recover && args.files.is_empty() {
+        let files = editor.recovery_files();
+        if files.is_empty() {
+            println!("{}", gettext("No files to recover"));
+        }
+        for (path, name, _) in files {
+            println!("{}: {}", name, path.display());
+        }
+        return Ok(());
+    }
+
+    if !editor.batch {
+        if let Err(message) = startup(&mut editor) {
+            report(&message);
+        }
+    }
+    if args.readonly {
+        editor.options.set_flag("readonly", true);
+    }
+    if let Some(window) = args.window.filter(|&size| size > 0) {
+        editor.options.set_number("window", window);
+    }
+
+    editor.files.clone_from(&args.files);
+    let result = if let Some(tag) = &args.tag {
+        editor.goto_tag(tag, true)
+    } else if let Some(first) = args.files.first() {
+        if args.recover {
+            editor.recover(first)
+        } else {
+            editor.edit(Some(first.clone()))
+        }
+    } else {
+        editor.edit(None)
+    };
+    if let Err(message) = result {
+        report(&message);
+    }
+    if args.readonly {
+        editor.options.set_flag("readonly", true);
+    }
+    if let Some(command) = &args.command {
+        if let Err(message) = editor.execute(command.as_bytes()) {
+            report(&message);
+        }
+    }
+
+    while !editor.quit {
+        match editor.mode {
+            Mode::Visual => {
+                if let Err(message) = editor.run_visual() {
+                    report(&message);
+                    std::process::exit(1);
+                }
+                if editor.visual.exhausted {
+                    break;
+                }
+            }
+            Mode::Ex => {
+                if !run_ex(&mut editor) {
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    std::process::exit(editor.status)
+}
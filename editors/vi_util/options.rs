@@ -0,0 +1,230 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use gettextrs::gettext;
+
+#[derive(Clone, PartialEq)]
+pub enum Value {
+    Flag(bool),
+    Number(usize),
+    Text(String),
+}
+
+struct Entry {
+    name: &'static str,
+    abbreviation: &'static str,
+    value: Value,
+    default: Value,
+}
+
+/// The options of the `set` command.
+pub struct Options {
+    entries: Vec<Entry>,
+}
+
+impl Options {
+    /// Creates the options with their default values, `lines` being the
+    /// number of lines of the terminal.
+    pub fn new(lines: usize) -> Self {
+        let env = |name: &str, default: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        let window = lines.saturating_sub(1).max(1);
+        let defaults = [
+            ("autoindent", "ai", Value::Flag(false)),
+            ("autoprint", "ap", Value::Flag(true)),
+            ("autowrite", "aw", Value::Flag(false)),
+            ("beautify", "bf", Value::Flag(false)),
+            ("directory", "dir", Value::Text(env("TMPDIR", "/var/tmp"))),
+            ("edcompatible", "ed", Value::Flag(false)),
+            ("errorbells", "eb", Value::Flag(false)),
+            ("exrc", "ex", Value::Flag(false)),
+            ("ignorecase", "ic", Value::Flag(false)),
+            ("list", "list", Value::Flag(false)),
+            ("magic", "magic", Value::Flag(true)),
+            ("mesg", "mesg", Value::Flag(true)),
+            ("number", "nu", Value::Flag(false)),
+            (
+                "paragraphs",
+                "para",
+                Value::Text(String::from("IPLPPPQPP LIpplpipbp")),
+            ),
+            ("prompt", "prompt", Value::Flag(true)),
+            ("readonly", "ro", Value::Flag(false)),
+            ("redraw", "redraw", Value::Flag(false)),
+            ("remap", "remap", Value::Flag(true)),
+            ("report", "report", Value::Number(5)),
+            ("scroll", "scr", Value::Number((window / 2).max(1))),
+            (
+                "sections",
+                "sect",
+                Value::Text(String::from("NHSHH HUnhsh")),
+            ),
+            ("shell", "sh", Value::Text(env("SHELL", "/bin/sh"))),
+            ("shiftwidth", "sw", Value::Number(8)),
+            ("showmatch", "sm", Value::Flag(false)),
+            ("showmode", "smd", Value::Flag(false)),
+            ("slowopen", "slow", Value::Flag(false)),
+            ("tabstop", "ts", Value::Number(8)),
+            ("taglength", "tl", Value::Number(0)),
+            ("tags", "tags", Value::Text(String::from("tags"))),
+            ("term", "term", Value::Text(env("TERM", "dumb"))),
+            ("terse", "terse", Value::Flag(false)),
+            ("warn", "warn", Value::Flag(true)),
+            ("window", "wi", Value::Number(window)),
+            ("wrapmargin", "wm", Value::Number(0)),
+            ("wrapscan", "ws", Value::Flag(true)),
+            ("writeany", "wa", Value::Flag(false)),
+        ];
+        Options {
+            entries: defaults
+                .into_iter()
+                .map(|(name, abbreviation, value)| Entry {
+                    name,
+                    abbreviation,
+                    default: value.clone(),
+                    value,
+                })
+                .collect(),
+        }
+    }
+
+    fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name || entry.abbreviation == name)
+    }
+
+    fn entry_mut(&mut self, name: &str) -> Option<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.name == name || entry.abbreviation == name)
+    }
+
+    /// Returns a boolean option, looked up by its full name.
+    pub fn flag(&self, name: &str) -> bool {
+        matches!(self.entry(name).map(|e| &e.value), Some(Value::Flag(true)))
+    }
+
+    pub fn number(&self, name: &str) -> usize {
+        match self.entry(name).map(|e| &e.value) {
+            Some(Value::Number(n)) => *n,
+            _ => 0,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> &str {
+        match self.entry(name).map(|e| &e.value) {
+            Some(Value::Text(text)) => text,
+            _ => "",
+        }
+    }
+
+    pub fn set_flag(&mut self, name: &str, value: bool) {
+        if let Some(entry) = self.entry_mut(name) {
+            entry.value = Value::Flag(value);
+        }
+    }
+
+    pub fn set_number(&mut self, name: &str, value: usize) {
+        if let Some(entry) = self.entry_mut(name) {
+            entry.value = Value::Number(value);
+        }
+    }
+
+    /// Sets an option as written in a `set` command: `name`, `noname`,
+    /// `name=value`, or `name?` to query it.  Returns the text to display
+    /// for a query.
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let invalid = || format!("{}: {}", arg, gettext("unknown option"));
+        if let Some(name) = arg.strip_suffix('?') {
+            let entry = self.entry(name).ok_or_else(invalid)?;
+            return Ok(Some(format_entry(entry)));
+        }
+        if let Some((name, value)) = arg.split_once('=') {
+            let entry = self.entry_mut(name).ok_or_else(invalid)?;
+            entry.value = match entry.value {
+                Value::Flag(_) => {
+                    return Err(format!("{}: {}", name, gettext("option is not a value")))
+                }
+                Value::Number(_) => Value::Number(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{}: {}", value, gettext("invalid number")))?,
+                ),
+                Value::Text(_) => Value::Text(value.to_string()),
+            };
+            return Ok(None);
+        }
+        if let Some(entry) = self.entry_mut(arg) {
+            return match entry.value {
+                Value::Flag(_) => {
+                    entry.value = Value::Flag(true);
+                    Ok(None)
+                }
+                _ => Ok(Some(format_entry(entry))),
+            };
+        }
+        let name = arg.strip_prefix("no").ok_or_else(invalid)?;
+        match self.entry_mut(name) {
+            Some(entry) if matches!(entry.value, Value::Flag(_)) => {
+                entry.value = Value::Flag(false);
+                Ok(None)
+            }
+            Some(_) => Err(format!("{}: {}", name, gettext("option is not a flag"))),
+            None => Err(invalid()),
+        }
+    }
+
+    /// Lists the options: all of them, or those changed from their default.
+    pub fn list(&self, all: bool) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| all || entry.value != entry.default)
+            .map(format_entry)
+            .collect()
+    }
+}
+
+fn format_entry(entry: &Entry) -> String {
+    match &entry.value {
+        Value::Flag(true) => entry.name.to_string(),
+        Value::Flag(false) => format!("no{}", entry.name),
+        Value::Number(n) => format!("{}={}", entry.name, n),
+        Value::Text(text) => format!("{}={}", entry.name, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_options() {
+        let mut options = Options::new(24);
+        assert_eq!(options.number("window"), 23);
+        options.set("nu").unwrap();
+        options.set("sw=4").unwrap();
+        options.set("nowrapscan").unwrap();
+        assert!(options.flag("number"));
+        assert_eq!(options.number("shiftwidth"), 4);
+        assert!(!options.flag("wrapscan"));
+        assert_eq!(options.set("ts?").unwrap(), Some(String::from("tabstop=8")));
+        assert_eq!(
+            options.set("ws?").unwrap(),
+            Some(String::from("nowrapscan"))
+        );
+        assert!(options.set("bogus").is_err());
+        assert!(options.set("sw=x").is_err());
+        assert!(options.set("noreport").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::common::{self, expand_replacement, ReplacementPart};
+use plib::regex::{bracket_expression_end, Regex, RegexFlags};
+
+/// Compiles a basic regular expression, as rewritten by [`translate`].
+pub fn compile_regex(pattern: &[u8], ignore_case: bool) -> Result<Regex, String> {
+    let flags = RegexFlags {
+        ignore_case,
+        ..Default::default()
+    };
+    Regex::new(pattern, flags).map_err(|err| err.to_string())
+}
+
+/// Returns the start offsets of the non-overlapping matches of `regex` in
+/// `line`.
+pub fn match_starts(regex: &Regex, line: &[u8]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut start = 0;
+    while start <= line.len() {
+        let Some(found) = regex.find_at(line, start) else {
+            break;
+        };
+        starts.push(found.start);
+        start = if found.end > found.start {
+            found.end
+        } else {
+            found.end + 1
+        };
+    }
+    starts
+}
+
+/// Rewrites a pattern as written in `ex` into a basic regular expression:
+/// `~` stands for the previous replacement text, and without the `magic`
+/// option only `^` and `$` are special unless escaped.
+pub fn translate(pattern: &[u8], magic: bool, last_replacement: Option<&[u8]>) -> Vec<u8> {
+    let mut result = Vec::with_capacity(pattern.len());
+    let mut i = 0;
+    while i < pattern.len() {
+        let c = pattern[i];
+        match c {
+            b'\\' if i + 1 < pattern.len() => {
+                let next = pattern[i + 1];
+                match next {
+                    b'.' | b'[' | b'*' | b'~' if !magic => {
+                        if next == b'~' {
+                            push_literal(&mut result, last_replacement.unwrap_or_default());
+                        } else if next == b'[' {
+                            let end =
+                                bracket_expression_end(pattern, i + 1).unwrap_or(pattern.len());
+                            result.push(b'[');
+                            result.extend_from_slice(&pattern[i + 2..end]);
+                            i = end;
+                            continue;
+                        } else {
+                            result.push(next);
+                        }
+                    }
+                    b'~' => result.push(b'~'),
+                    _ => result.extend_from_slice(&[b'\\', next]),
+                }
+                i += 2;
+            }
+            b'[' if magic => {
+                let end = bracket_expression_end(pattern, i).unwrap_or(pattern.len());
+                result.extend_from_slice(&pattern[i..end]);
+                i = end;
+            }
+            b'~' if magic => {
+                push_literal(&mut result, last_replacement.unwrap_or_default());
+                i += 1;
+            }
+            b'.' | b'[' | b'*' if !magic => {
+                result.extend_from_slice(&[b'\\', c]);
+                i += 1;
+            }
+            _ => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Appends `text` to a pattern, escaped so that it matches itself.
+fn push_literal(pattern: &mut Vec<u8>, text: &[u8]) {
+    for &c in text {
+        if matches!(c, b'\\' | b'.' | b'[' | b'*' | b'^' | b'$') {
+            pattern.push(b'\\');
+        }
+        pattern.push(c);
+    }
+}
+
+/// Parses the replacement of a `substitute` command, in which `~` stands for
+/// the previous replacement and, unlike in `ed`, the case of the text can be
+/// changed.  Without the `magic` option `&` and `~` are special only when
+/// escaped.
+///
+/// Returns the parts along with the text with `~` expanded, which becomes
+/// the new previous replacement.
+pub fn parse_replacement(
+    raw: &[u8],
+    magic: bool,
+    last_replacement: Option<&[u8]>,
+) -> (Vec<ReplacementPart>, Vec<u8>) {
+    // Expand `~` first, so that the result can be remembered.
+    let mut expanded = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i] {
+            b'\\' if raw.get(i + 1) == Some(&b'~') && !magic => {
+                expanded.extend_from_slice(last_replacement.unwrap_or_default());
+                i += 2;
+            }
+            b'\\' if i + 1 < raw.len() => {
+                expanded.extend_from_slice(&raw[i..i + 2]);
+                i += 2;
+            }
+            b'~' if magic => {
+                expanded.extend_from_slice(last_replacement.unwrap_or_default());
+                i += 1;
+            }
+            c => {
+                expanded.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    let parts = common::parse_replacement(&expanded, magic, true);
+    (parts, expanded)
+}
+
+/// Replaces matches of `regex` in `line`: the first one, or with `global`
+/// every one.  `confirm` is asked about each match, given its offsets, and
+/// only the accepted ones are replaced.  Returns `None` if nothing was
+/// replaced.
+pub fn substitute(
+    regex: &Regex,
+    replacement: &[ReplacementPart],
+    line: &[u8],
+    global: bool,
+    confirm: &mut dyn FnMut(usize, usize) -> bool,
+) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(line.len());
+    let mut copied = 0;
+    let mut start = 0;
+    let mut previous_end = None;
+    let mut replaced = false;
+
+    while start <= line.len() {
+        let Some(captures) = regex.captures_at(line, start) else {
+            break;
+        };
+        let whole = captures[0].clone().unwrap();
+        let (match_start, match_end) = (whole.start, whole.end);
+
+        // An empty match right after the previous match is not a match of
+        // its own.
+        if match_start == match_end && previous_end == Some(match_start) {
+            if match_start >= line.len() {
+                break;
+            }
+            start = match_start + 1;
+            continue;
+        }
+
+        if confirm(match_start, match_end) {
+            result.extend_from_slice(&line[copied..match_start]);
+            result.extend(expand_replacement(replacement, line, &captures));
+            copied = match_end;
+            replaced = true;
+        }
+        if !global {
+            break;
+        }
+
+        previous_end = Some(match_end);
+        if match_start == match_end {
+            if match_end >= line.len() {
+                break;
+            }
+            start = match_end + 1;
+        } else {
+            start = match_end;
+        }
+    }
+
+    if !replaced {
+        return None;
+    }
+    result.extend_from_slice(&line[copied..]);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, replacement: &str, line: &str, global: bool) -> Option<String> {
+        let regex = compile_regex(pattern.as_bytes(), false).unwrap();
+        let (parts, _) = parse_replacement(replacement.as_bytes(), true, None);
+        substitute(&regex, &parts, line.as_bytes(), global, &mut |_, _| true)
+            .map(|line| String::from_utf8(line).unwrap())
+    }
+
+    #[test]
+    fn test_translate_nomagic() {
+        assert_eq!(translate(b"a.b*", false, None), b"a\\.b\\*");
+        assert_eq!(translate(b"a\\.b\\*", false, None), b"a.b*");
+        assert_eq!(translate(b"\\[ab]", false, None), b"[ab]");
+        assert_eq!(translate(b"x~y", true, Some(b"a.")), b"xa\\.y");
+    }
+
+    #[test]
+    fn test_substitute_case_changes() {
+        assert_eq!(
+            replace("\\(h\\)\\(.*\\)", "\\u\\1\\U\\2\\E!", "hello", false).unwrap(),
+            "HELLO!"
+        );
+        assert_eq!(replace("o", "0", "foo boo", true).unwrap(), "f00 b00");
+        assert_eq!(replace("x", "y", "foo", true), None);
+    }
+
+    #[test]
+    fn test_parse_replacement_tilde() {
+        let (_, expanded) = parse_replacement(b"<~>", true, Some(b"old"));
+        assert_eq!(expanded, b"<old>");
+        let (_, expanded) = parse_replacement(b"<~>", false, Some(b"old"));
+        assert_eq!(expanded, b"<~>");
+    }
+}
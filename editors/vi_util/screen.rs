@@ -0,0 +1,698 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::editor::{Editor, Mode, Result};
+use super::terminal::{ctrl, Key, Terminal, ESC};
+use super::visual::Change;
+use gettextrs::gettext;
+use std::collections::VecDeque;
+
+/// Returns the length of the UTF-8 character at `i`, taking an invalid
+/// sequence one byte at a time.
+pub fn char_len(line: &[u8], i: usize) -> usize {
+    let len = match line[i] {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return 1,
+    };
+    let valid = i + len <= line.len()
+        && line[i + 1..i + len].iter().all(|&b| b & 0xc0 == 0x80)
+        && std::str::from_utf8(&line[i..i + len]).is_ok();
+    if valid {
+        len
+    } else {
+        1
+    }
+}
+
+/// Returns the offset of the character after the one at `i`.
+pub fn next_char(line: &[u8], i: usize) -> usize {
+    if i >= line.len() {
+        line.len()
+    } else {
+        i + char_len(line, i)
+    }
+}
+
+/// Returns the offset of the character before the one at `i`.
+pub fn prev_char(line: &[u8], i: usize) -> usize {
+    let mut start = i.saturating_sub(1);
+    while start > 0 && i - start < 4 && line[start] & 0xc0 == 0x80 {
+        start -= 1;
+    }
+    if start < i && char_len(line, start) == i - start {
+        start
+    } else {
+        i.saturating_sub(1)
+    }
+}
+
+/// How a line appears on the screen.
+pub struct Layout {
+    /// What each column shows.
+    pub cells: Vec<Vec<u8>>,
+
+    /// For each character: its byte offset, first column and width.
+    pub chars: Vec<(usize, usize, usize)>,
+
+    /// The length of the line in bytes.
+    len: usize,
+}
+
+/// Lays out a line: tabs expand to the next multiple of `tabstop`, or show
+/// as `^I` with the `list` option, which also marks the end of the line
+/// with `$`.  Other control characters show as `^X`, and bytes that are
+/// not UTF-8 in octal.
+pub fn layout(line: &[u8], tabstop: usize, list: bool) -> Layout {
+    let mut cells: Vec<Vec<u8>> = Vec::with_capacity(line.len());
+    let mut chars = Vec::with_capacity(line.len());
+    let mut i = 0;
+    while i < line.len() {
+        let start = cells.len();
+        let len = char_len(line, i);
+        match line[i] {
+            b'\t' if !list => {
+                let width = tabstop - start % tabstop;
+                cells.extend(std::iter::repeat(b" ".to_vec()).take(width));
+            }
+            c @ (0..=0x1f | 0x7f) => {
+                cells.push(b"^".to_vec());
+                cells.push(vec![if c == 0x7f { b'?' } else { c + b'@' }]);
+            }
+            c if len == 1 && c >= 0x80 => {
+                for digit in format!("\\{:03o}", c).bytes() {
+                    cells.push(vec![digit]);
+                }
+            }
+            _ => cells.push(line[i..i + len].to_vec()),
+        }
+        chars.push((i, start, cells.len() - start));
+        i += len;
+    }
+    if list {
+        cells.push(b"$".to_vec());
+    }
+    Layout {
+        cells,
+        chars,
+        len: line.len(),
+    }
+}
+
+impl Layout {
+    /// Returns the columns of the character at byte `offset`, or the column
+    /// after the line at its end.
+    pub fn columns_at(&self, offset: usize) -> (usize, usize) {
+        match self
+            .chars
+            .binary_search_by(|&(start, _, _)| start.cmp(&offset))
+        {
+            Ok(i) => (self.chars[i].1, self.chars[i].2),
+            Err(_) if offset >= self.len => (
+                self.chars.last().map_or(0, |&(_, col, width)| col + width),
+                1,
+            ),
+            Err(0) => (0, 1),
+            Err(i) => (self.chars[i - 1].1, self.chars[i - 1].2),
+        }
+    }
+
+    /// Returns the byte offset of the character shown at column `col`, or
+    /// of the last character if the line is shorter.
+    pub fn offset_at(&self, col: usize) -> usize {
+        self.chars
+            .iter()
+            .rev()
+            .find(|&&(_, start, _)| start <= col)
+            .map_or(0, |&(offset, _, _)| offset)
+    }
+}
+
+/// The state of the screen editor.
+pub struct Visual {
+    pub term: Option<Terminal>,
+
+    /// Runs without a terminal, on queued keys only, as the tests do.
+    pub headless: bool,
+    pub rows: usize,
+    pub cols: usize,
+
+    /// The number of lines of text shown, from the `window` option.
+    window: usize,
+
+    /// The first line of the buffer on the screen.
+    pub top: usize,
+
+    /// What each row of the terminal shows, `None` when unknown.
+    shown: Vec<Option<Vec<u8>>>,
+
+    /// Output of `ex` commands waiting to be shown.
+    pub messages: Vec<Vec<u8>>,
+
+    /// The message on the bottom line, and whether it reports an error.
+    pub status: Option<(Vec<u8>, bool)>,
+
+    /// The mode shown with the `showmode` option.
+    pub mode_name: Option<String>,
+
+    /// Keys to read before those typed: keys replayed by `.` or produced by
+    /// mappings, which are not mapped again, and typed-ahead keys, which
+    /// are.
+    pub replay: VecDeque<Key>,
+    pub typeahead: VecDeque<Key>,
+
+    /// The keys of the command being read, while it may be repeated by `.`.
+    pub recording: Option<Vec<Key>>,
+
+    /// No more keys: the queued ones ran out in headless mode.
+    pub exhausted: bool,
+
+    /// The column `j` and `k` keep to, `usize::MAX` after `$`.
+    pub want_col: Option<usize>,
+
+    /// The last `f`, `F`, `t` or `T` command and its character.
+    pub last_find: Option<(u8, Vec<u8>)>,
+    pub last_change: Option<Change>,
+
+    /// Mappings expanded since a key was last typed, to stop mappings that
+    /// expand forever.
+    pub map_expansions: usize,
+}
+
+impl Visual {
+    pub fn new() -> Self {
+        Visual {
+            term: None,
+            headless: false,
+            rows: 24,
+            cols: 80,
+            window: 0,
+            top: 1,
+            shown: Vec::new(),
+            messages: Vec::new(),
+            status: None,
+            mode_name: None,
+            replay: VecDeque::new(),
+            typeahead: VecDeque::new(),
+            recording: None,
+            exhausted: false,
+            want_col: None,
+            last_find: None,
+            last_change: None,
+            map_expansions: 0,
+        }
+    }
+
+    /// Follows a change of the `window` option.
+    pub fn resize_window(&mut self, window: usize) {
+        self.window = window;
+        self.invalidate();
+    }
+
+    /// Forgets what the screen shows, so that it is all drawn again.
+    pub fn invalidate(&mut self) {
+        self.shown = vec![None; self.rows];
+    }
+}
+
+impl Editor {
+    /// The number of rows showing text, above the bottom line.
+    pub fn text_rows(&self) -> usize {
+        let available = self.visual.rows.saturating_sub(1).max(1);
+        match self.visual.window {
+            0 => available,
+            window => window.min(available),
+        }
+    }
+
+    /// The number of columns showing text, after any line number.
+    fn text_cols(&self) -> usize {
+        let number = if self.options.flag("number") { 8 } else { 0 };
+        self.visual.cols.saturating_sub(number).max(1)
+    }
+
+    pub fn line_layout(&self, n: usize) -> Layout {
+        layout(
+            self.buffer.get(n),
+            self.options.number("tabstop").max(1),
+            self.options.flag("list"),
+        )
+    }
+
+    /// The number of rows line `n` takes on the screen.
+    fn line_rows(&self, n: usize) -> usize {
+        if n == 0 || n > self.buffer.len() {
+            return 1;
+        }
+        let cells = self.line_layout(n).cells.len();
+        cells.div_ceil(self.text_cols()).max(1)
+    }
+
+    /// Makes the screen start at a line that puts line `n` in the middle.
+    pub fn center_on(&mut self, n: usize) {
+        let half = self.text_rows() / 2;
+        let mut top = n.max(1);
+        let mut used = self.line_rows(top);
+        while top > 1 && used + self.line_rows(top - 1) <= half + 1 {
+            top -= 1;
+            used += self.line_rows(top);
+        }
+        self.visual.top = top;
+    }
+
+    /// Scrolls so that the current line is on the screen, centering it if
+    /// it is far away.
+    pub fn scroll_to_cursor(&mut self) {
+        let len = self.buffer.len();
+        let line = self.buffer.line.max(1);
+        let rows = self.text_rows();
+        if len == 0 {
+            self.visual.top = 1;
+            return;
+        }
+        if self.visual.top == 0 || self.visual.top > len {
+            self.visual.top = 1;
+        }
+        if line < self.visual.top {
+            if self.visual.top - line > rows / 2 {
+                self.center_on(line);
+            } else {
+                self.visual.top = line;
+            }
+            return;
+        }
+        let mut used = 0;
+        for n in self.visual.top..=line {
+            used += self.line_rows(n);
+            if used > rows * 2 {
+                break;
+            }
+        }
+        if used <= rows {
+            return;
+        }
+        if used > rows + rows / 2 {
+            self.center_on(line);
+            return;
+        }
+        while used > rows && self.visual.top < line {
+            used -= self.line_rows(self.visual.top);
+            self.visual.top += 1;
+        }
+    }
+
+    /// Returns the last line entirely on the screen.
+    pub fn bottom_line(&self) -> usize {
+        let rows = self.text_rows();
+        let mut used = 0;
+        let mut n = self.visual.top;
+        while n <= self.buffer.len() {
+            used += self.line_rows(n);
+            if used > rows {
+                break;
+            }
+            n += 1;
+        }
+        n.saturating_sub(1)
+            .max(self.visual.top.min(self.buffer.len()))
+    }
+
+    /// Returns the screen column of the cursor in the current line: on the
+    /// last column of a tab in command mode, and on its first in text input.
+    pub fn cursor_column(&self, input: bool) -> usize {
+        if self.buffer.line == 0 {
+            return 0;
+        }
+        let layout = self.line_layout(self.buffer.line);
+        let (col, width) = layout.columns_at(self.buffer.col);
+        if input {
+            col
+        } else {
+            col + width - 1
+        }
+    }
+
+    /// Draws the screen, writing only the rows that changed.
+    pub fn render(&mut self) {
+        self.scroll_to_cursor();
+        if self.visual.term.is_none() {
+            return;
+        }
+        let text_rows = self.text_rows();
+        let text_cols = self.text_cols();
+        let input = self.visual.mode_name.is_some();
+        let number = self.options.flag("number");
+        let mut rows: Vec<Vec<u8>> = Vec::with_capacity(self.visual.rows);
+        let mut cursor = (0, 0);
+        let mut n = self.visual.top;
+        while rows.len() < text_rows {
+            if n == 0 || n > self.buffer.len() {
+                rows.push(b"~".to_vec());
+                n += 1;
+                continue;
+            }
+            let layout = self.line_layout(n);
+            let chunks: Vec<&[Vec<u8>]> = if layout.cells.is_empty() {
+                vec![&[]]
+            } else {
+                layout.cells.chunks(text_cols).collect()
+            };
+            if rows.len() + chunks.len() > text_rows && n != self.visual.top {
+                // A line that does not fit is shown as `@` rows.
+                while rows.len() < text_rows {
+                    rows.push(b"@".to_vec());
+                }
+                break;
+            }
+            if n == self.buffer.line {
+                let col = self.cursor_column(input);
+                let col = if layout.cells.is_empty() { 0 } else { col };
+                cursor = (
+                    rows.len() + col / text_cols,
+                    col % text_cols + if number { 8 } else { 0 },
+                );
+            }
+            for (i, chunk) in chunks.iter().enumerate() {
+                let mut row = Vec::new();
+                if number {
+                    if i == 0 {
+                        row.extend(format!("{:6}  ", n).as_bytes());
+                    } else {
+                        row.extend(b"        ");
+                    }
+                }
+                for cell in chunk.iter() {
+                    row.extend_from_slice(cell);
+                }
+                if rows.len() < text_rows {
+                    rows.push(row);
+                }
+            }
+            n += 1;
+        }
+        while rows.len() < self.visual.rows - 1 {
+            rows.push(Vec::new());
+        }
+        rows.push(self.status_row());
+
+        let cols = self.visual.cols;
+        let term = self.visual.term.as_mut().unwrap();
+        if self.visual.shown.len() != rows.len() {
+            self.visual.shown = vec![None; rows.len()];
+        }
+        for (i, row) in rows.into_iter().enumerate() {
+            if self.visual.shown[i].as_ref() == Some(&row) {
+                continue;
+            }
+            term.move_to(i, 0);
+            if i == self.visual.rows - 1 {
+                if let Some((_, true)) = self.visual.status {
+                    term.standout(true);
+                    term.write(&row);
+                    term.standout(false);
+                } else {
+                    term.write(&row);
+                }
+            } else {
+                term.write(&row);
+            }
+            if display_width(&row) < cols {
+                term.clear_to_eol();
+            }
+            self.visual.shown[i] = Some(row);
+        }
+        term.move_to(cursor.0, cursor.1.min(cols - 1));
+        term.flush();
+    }
+
+    /// The bottom line: a message, or the mode with `showmode`.
+    fn status_row(&self) -> Vec<u8> {
+        let cols = self.visual.cols;
+        let mut row = match &self.visual.status {
+            Some((message, _)) => printable(message),
+            None => Vec::new(),
+        };
+        if self.options.flag("showmode") {
+            if let Some(mode) = &self.visual.mode_name {
+                let width = display_width(&row);
+                if width + mode.len() + 1 < cols {
+                    row.resize(row.len() + cols - 1 - width - mode.len(), b' ');
+                    row.extend(mode.as_bytes());
+                }
+            }
+        }
+        truncate_to_width(&row, cols.saturating_sub(1))
+    }
+
+    /// Shows a message on the bottom line.
+    pub fn set_status(&mut self, message: &str, error: bool) {
+        self.visual.status = Some((message.as_bytes().to_vec(), error));
+    }
+
+    /// Draws everything again, as `^L` asks.
+    pub fn redraw(&mut self) {
+        if let Some(term) = &mut self.visual.term {
+            term.clear_screen();
+        }
+        self.visual.invalidate();
+        self.render();
+    }
+
+    /// Shows the output of an `ex` command: on the bottom line if it fits,
+    /// or else scrolled up from the bottom until a key is typed.
+    pub fn show_messages(&mut self) {
+        let messages = std::mem::take(&mut self.visual.messages);
+        if messages.is_empty() {
+            return;
+        }
+        if messages.len() == 1 && display_width(&messages[0]) < self.visual.cols {
+            self.visual.status = Some((messages[0].clone(), false));
+            return;
+        }
+        let Some(term) = &mut self.visual.term else {
+            self.visual.status = messages.last().map(|m| (m.clone(), false));
+            return;
+        };
+        term.move_to(self.visual.rows - 1, 0);
+        term.clear_to_eol();
+        for message in &messages {
+            term.write(&printable(message));
+            term.write(b"\r\n");
+        }
+        self.visual.invalidate();
+        self.wait_for_key();
+    }
+
+    /// Asks for a key on the bottom line before the screen is drawn again.
+    /// A `:` typed there starts another command.
+    fn wait_for_key(&mut self) {
+        let Some(term) = &mut self.visual.term else {
+            return;
+        };
+        term.standout(true);
+        term.write(gettext("[Hit return to continue]").as_bytes());
+        term.standout(false);
+        term.flush();
+        let key = self.read_terminal_key();
+        if key == Key::Byte(b':') {
+            self.visual.typeahead.push_front(key);
+        }
+    }
+
+    /// Reads a line on the bottom line of the screen after `prompt`.
+    /// Returns `None` if the input is abandoned, by `<ESC>` or erasing past
+    /// its start.
+    pub fn read_bottom_line(&mut self, prompt: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut text: Vec<u8> = Vec::new();
+        loop {
+            if let Some(term) = &mut self.visual.term {
+                let mut row = prompt.to_vec();
+                row.extend(printable(&text));
+                let row = tail_to_width(&row, self.visual.cols.saturating_sub(1));
+                term.move_to(self.visual.rows - 1, 0);
+                term.write(&row);
+                term.clear_to_eol();
+                term.flush();
+                if let Some(last) = self.visual.shown.last_mut() {
+                    *last = None;
+                }
+            }
+            let key = self.next_key(None);
+            match key {
+                Key::Byte(b'\r' | b'\n') => return Ok(Some(text)),
+                ESC | Key::Byte(0x03) => return Ok(None),
+                Key::Byte(0x08 | 0x7f) => {
+                    if text.is_empty() {
+                        return Ok(None);
+                    }
+                    let start = prev_char(&text, text.len());
+                    text.truncate(start);
+                }
+                k if k == ctrl(b'U') => text.clear(),
+                k if k == ctrl(b'W') => {
+                    while text.last().is_some_and(|c| c.is_ascii_whitespace()) {
+                        text.pop();
+                    }
+                    while text.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                        text.pop();
+                    }
+                }
+                k if k == ctrl(b'V') => {
+                    if let Key::Byte(c) = self.next_key(None) {
+                        text.push(c);
+                    }
+                }
+                Key::Byte(c) => text.push(c),
+                _ => {}
+            }
+            if self.visual.exhausted {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Gives the terminal back to the shell: normal modes and screen.
+    pub fn suspend_screen(&mut self) {
+        if let Some(term) = &mut self.visual.term {
+            term.leave();
+        }
+    }
+
+    pub fn resume_screen(&mut self) {
+        if let Some(term) = &mut self.visual.term {
+            term.enter();
+            self.visual.rows = term.rows;
+            self.visual.cols = term.cols;
+        }
+        self.redraw();
+    }
+
+    /// Waits for a key after a shell command's output, before the screen
+    /// is drawn again.
+    pub fn wait_for_return(&mut self) {
+        if let Some(term) = &mut self.visual.term {
+            term.set_raw(true);
+            term.write(b"\r\n");
+        }
+        self.wait_for_key();
+    }
+
+    /// Stops the editor, as `^Z` and `stop` do, until it is continued.
+    pub fn suspend(&mut self) {
+        let visual = self.mode == Mode::Visual;
+        if visual {
+            self.suspend_screen();
+        }
+        unsafe { libc::raise(libc::SIGTSTP) };
+        if visual {
+            self.resume_screen();
+        }
+    }
+
+    /// Asks whether to replace a match, showing its line on the bottom line
+    /// with the match standing out.
+    pub fn confirm_visual(&mut self, line: &[u8], start: usize, end: usize) -> bool {
+        if let Some(term) = &mut self.visual.term {
+            let cols = self.visual.cols;
+            term.move_to(self.visual.rows - 1, 0);
+            term.write(&truncate_to_width(&printable(&line[..start]), cols / 2));
+            term.standout(true);
+            term.write(&printable(&line[start..end]));
+            term.standout(false);
+            term.write(&printable(&line[end..]));
+            term.clear_to_eol();
+            term.flush();
+            if let Some(last) = self.visual.shown.last_mut() {
+                *last = None;
+            }
+        }
+        self.next_key(None) == Key::Byte(b'y')
+    }
+}
+
+/// Shows control characters of a message as `^X`.
+fn printable(text: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(text.len());
+    for &c in text {
+        match c {
+            0..=0x1f => result.extend_from_slice(&[b'^', c + b'@']),
+            0x7f => result.extend_from_slice(b"^?"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// The number of columns text takes, counting each UTF-8 character as one.
+fn display_width(text: &[u8]) -> usize {
+    text.iter().filter(|&&b| b & 0xc0 != 0x80).count()
+}
+
+fn truncate_to_width(text: &[u8], width: usize) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut columns = 0;
+    for &b in text {
+        if b & 0xc0 != 0x80 {
+            if columns == width {
+                break;
+            }
+            columns += 1;
+        }
+        result.push(b);
+    }
+    result
+}
+
+/// Keeps the end of text that is too wide, as when a long command is typed.
+fn tail_to_width(text: &[u8], width: usize) -> Vec<u8> {
+    let total = display_width(text);
+    if total <= width {
+        return text.to_vec();
+    }
+    let mut skip = total - width;
+    let mut i = 0;
+    while skip > 0 || (i < text.len() && text[i] & 0xc0 == 0x80) {
+        if text[i] & 0xc0 != 0x80 {
+            skip -= 1;
+        }
+        i += 1;
+    }
+    while i < text.len() && text[i] & 0xc0 == 0x80 {
+        i += 1;
+    }
+    text[i..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let layout = layout(b"a\tb\x01", 4, false);
+        assert_eq!(layout.cells.len(), 7);
+        assert_eq!(layout.columns_at(1), (1, 3));
+        assert_eq!(layout.columns_at(2), (4, 1));
+        assert_eq!(layout.columns_at(3), (5, 2));
+        assert_eq!(layout.columns_at(4), (7, 1));
+        assert_eq!(layout.offset_at(2), 1);
+        assert_eq!(layout.offset_at(9), 3);
+    }
+
+    #[test]
+    fn test_utf8_characters() {
+        let line = "aé€".as_bytes();
+        assert_eq!(next_char(line, 1), 3);
+        assert_eq!(prev_char(line, 6), 3);
+        assert_eq!(prev_char(line, 3), 1);
+        assert_eq!(layout(line, 8, true).cells.len(), 4);
+        assert_eq!(layout(b"\xff", 8, false).cells.len(), 4);
+    }
+}
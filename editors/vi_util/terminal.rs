@@ -0,0 +1,332 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use gettextrs::gettext;
+use std::{collections::VecDeque, io};
+use terminfo::{capability as cap, Database};
+use termios::{cfmakeraw, tcsetattr, Termios, TCSADRAIN};
+
+/// A key typed at the terminal.  Keys that send escape sequences are
+/// decoded using the terminal's description.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    Byte(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+}
+
+pub const ESC: Key = Key::Byte(0x1b);
+
+/// Returns the key typed with Control and `c`.
+pub const fn ctrl(c: u8) -> Key {
+    Key::Byte(c & 0x1f)
+}
+
+/// The terminal on the standard input and output, in raw mode while the
+/// screen editor runs.
+pub struct Terminal {
+    original: Termios,
+    db: Option<Database>,
+    pub rows: usize,
+    pub cols: usize,
+    out: Vec<u8>,
+    keys: Vec<(Vec<u8>, Key)>,
+    input: VecDeque<u8>,
+}
+
+/// Returns the size of the terminal on the standard output, from the
+/// kernel or else from `LINES` and `COLUMNS`.
+pub fn window_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_row > 0
+        && size.ws_col > 0
+    {
+        return (size.ws_row as usize, size.ws_col as usize);
+    }
+    let env = |name: &str, default: usize| {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(default)
+    };
+    (env("LINES", 24), env("COLUMNS", 80))
+}
+
+impl Terminal {
+    pub fn open() -> Result<Terminal, String> {
+        if unsafe { libc::isatty(0) } != 1 || unsafe { libc::isatty(1) } != 1 {
+            return Err(gettext("standard input and output must be a terminal"));
+        }
+        let original = Termios::from_fd(0).map_err(|err| err.to_string())?;
+        let db = Database::from_env().ok();
+        let (rows, cols) = window_size();
+
+        let mut keys = vec![
+            (b"\x1b[A".to_vec(), Key::Up),
+            (b"\x1b[B".to_vec(), Key::Down),
+            (b"\x1b[C".to_vec(), Key::Right),
+            (b"\x1b[D".to_vec(), Key::Left),
+            (b"\x1b[H".to_vec(), Key::Home),
+            (b"\x1b[F".to_vec(), Key::End),
+            (b"\x1b[2~".to_vec(), Key::Insert),
+            (b"\x1b[3~".to_vec(), Key::Delete),
+            (b"\x1b[5~".to_vec(), Key::PageUp),
+            (b"\x1b[6~".to_vec(), Key::PageDown),
+        ];
+        if let Some(db) = &db {
+            let mut add = |sequence: Option<&[u8]>, key| {
+                if let Some(sequence) = sequence {
+                    if sequence.len() > 1 && sequence[0] == 0x1b {
+                        keys.push((sequence.to_vec(), key));
+                    }
+                }
+            };
+            add(db.get::<cap::KeyUp>().as_ref().map(|c| c.as_ref()), Key::Up);
+            add(
+                db.get::<cap::KeyDown>().as_ref().map(|c| c.as_ref()),
+                Key::Down,
+            );
+            add(
+                db.get::<cap::KeyLeft>().as_ref().map(|c| c.as_ref()),
+                Key::Left,
+            );
+            add(
+                db.get::<cap::KeyRight>().as_ref().map(|c| c.as_ref()),
+                Key::Right,
+            );
+            add(
+                db.get::<cap::KeyHome>().as_ref().map(|c| c.as_ref()),
+                Key::Home,
+            );
+            add(
+                db.get::<cap::KeyEnd>().as_ref().map(|c| c.as_ref()),
+                Key::End,
+            );
+            add(
+                db.get::<cap::KeyIc>().as_ref().map(|c| c.as_ref()),
+                Key::Insert,
+            );
+            add(
+                db.get::<cap::KeyDc>().as_ref().map(|c| c.as_ref()),
+                Key::Delete,
+            );
+            add(
+                db.get::<cap::KeyPPage>().as_ref().map(|c| c.as_ref()),
+                Key::PageUp,
+            );
+            add(
+                db.get::<cap::KeyNPage>().as_ref().map(|c| c.as_ref()),
+                Key::PageDown,
+            );
+        }
+
+        Ok(Terminal {
+            original,
+            db,
+            rows,
+            cols,
+            out: Vec::new(),
+            keys,
+            input: VecDeque::new(),
+        })
+    }
+
+    /// Switches between raw mode and the modes the editor started with,
+    /// without changing screens.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.flush();
+        let mut modes = self.original;
+        if raw {
+            cfmakeraw(&mut modes);
+        }
+        let _ = tcsetattr(0, TCSADRAIN, &modes);
+    }
+
+    /// Puts the terminal in raw mode and switches to the editing screen.
+    pub fn enter(&mut self) {
+        self.set_raw(true);
+        let (rows, cols) = window_size();
+        self.rows = rows;
+        self.cols = cols;
+        if let Some(db) = &self.db {
+            if let Some(cap) = db.get::<cap::EnterCaMode>() {
+                self.out.extend(cap.expand().to_vec().unwrap_or_default());
+            }
+            if let Some(cap) = db.get::<cap::KeypadXmit>() {
+                self.out.extend(cap.expand().to_vec().unwrap_or_default());
+            }
+        }
+        self.flush();
+    }
+
+    /// Restores the terminal modes the editor started with.
+    pub fn leave(&mut self) {
+        self.move_to(self.rows - 1, 0);
+        self.clear_to_eol();
+        if let Some(db) = &self.db {
+            if let Some(cap) = db.get::<cap::KeypadLocal>() {
+                self.out.extend(cap.expand().to_vec().unwrap_or_default());
+            }
+            if let Some(cap) = db.get::<cap::ExitCaMode>() {
+                self.out.extend(cap.expand().to_vec().unwrap_or_default());
+            }
+        }
+        self.set_raw(false);
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.out.len() {
+            let n = unsafe {
+                libc::write(
+                    1,
+                    self.out[written..].as_ptr() as *const libc::c_void,
+                    self.out.len() - written,
+                )
+            };
+            if n < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+            written += n as usize;
+        }
+        self.out.clear();
+    }
+
+    pub fn move_to(&mut self, row: usize, col: usize) {
+        let sequence = self.db.as_ref().and_then(|db| {
+            db.get::<cap::CursorAddress>()?
+                .expand()
+                .parameters(row as u32, col as u32)
+                .to_vec()
+                .ok()
+        });
+        match sequence {
+            Some(sequence) => self.out.extend(sequence),
+            None => self
+                .out
+                .extend(format!("\x1b[{};{}H", row + 1, col + 1).as_bytes()),
+        }
+    }
+
+    pub fn clear_screen(&mut self) {
+        let sequence = self
+            .db
+            .as_ref()
+            .and_then(|db| db.get::<cap::ClearScreen>()?.expand().to_vec().ok());
+        self.out
+            .extend(sequence.unwrap_or_else(|| b"\x1b[H\x1b[2J".to_vec()));
+    }
+
+    pub fn clear_to_eol(&mut self) {
+        let sequence = self
+            .db
+            .as_ref()
+            .and_then(|db| db.get::<cap::ClrEol>()?.expand().to_vec().ok());
+        self.out
+            .extend(sequence.unwrap_or_else(|| b"\x1b[K".to_vec()));
+    }
+
+    pub fn standout(&mut self, on: bool) {
+        let sequence = self.db.as_ref().and_then(|db| {
+            if on {
+                db.get::<cap::EnterStandoutMode>()?.expand().to_vec().ok()
+            } else {
+                db.get::<cap::ExitStandoutMode>()?.expand().to_vec().ok()
+            }
+        });
+        let fallback: &[u8] = if on { b"\x1b[7m" } else { b"\x1b[m" };
+        self.out
+            .extend(sequence.unwrap_or_else(|| fallback.to_vec()));
+    }
+
+    pub fn bell(&mut self) {
+        self.out.push(0x07);
+        self.flush();
+    }
+
+    /// Waits up to `timeout` milliseconds, or indefinitely if negative, for
+    /// input.  Returns whether there is some.
+    pub fn poll(&self, timeout: i32) -> io::Result<bool> {
+        if !self.input.is_empty() {
+            return Ok(true);
+        }
+        let mut fds = libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fds, 1, timeout) } {
+            n if n < 0 => Err(io::Error::last_os_error()),
+            n => Ok(n > 0),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if let Some(b) = self.input.pop_front() {
+            return Ok(b);
+        }
+        let mut chunk = [0u8; 256];
+        let n = unsafe { libc::read(0, chunk.as_mut_ptr() as *mut libc::c_void, chunk.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        self.input.extend(&chunk[1..n as usize]);
+        Ok(chunk[0])
+    }
+
+    /// Reads a key.  An escape sequence must arrive quickly to be taken as
+    /// a key other than `<ESC>`.  Fails with `Interrupted` when a signal
+    /// arrives first.
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        self.flush();
+        let b = self.read_byte()?;
+        if b != 0x1b {
+            return Ok(Key::Byte(b));
+        }
+        let mut sequence = vec![b];
+        loop {
+            if let Some((_, key)) = self.keys.iter().find(|(s, _)| *s == sequence) {
+                return Ok(*key);
+            }
+            let prefix = self
+                .keys
+                .iter()
+                .any(|(s, _)| s.len() > sequence.len() && s.starts_with(&sequence));
+            if !prefix || !self.poll(50).unwrap_or(false) {
+                break;
+            }
+            sequence.push(self.read_byte()?);
+        }
+        // Not a key of the terminal: hand the bytes over one at a time.
+        for &b in sequence[1..].iter().rev() {
+            self.input.push_front(b);
+        }
+        Ok(Key::Byte(0x1b))
+    }
+}
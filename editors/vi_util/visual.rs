@@ -0,0 +1,2451 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use super::command::{indent_width, indentation, join_lines, make_indent};
+use super::editor::{Editor, Mode, Register, Result, HANGUP, INTERRUPTED, RESIZED};
+use super::regex::match_starts;
+use super::screen::{char_len, next_char, prev_char};
+use super::terminal::{ctrl, window_size, Key, Terminal, ESC};
+use gettextrs::gettext;
+use plib::regex::Regex;
+use std::{collections::VecDeque, io, sync::atomic::Ordering};
+
+/// The mappings that apply to keys being read.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Maps {
+    Command,
+    Input,
+}
+
+/// The last command that changed the buffer, which `.` repeats.
+#[derive(Clone)]
+pub struct Change {
+    register: Option<u8>,
+    count: Option<usize>,
+
+    /// The keys of the command after its register and count.
+    keys: Vec<Key>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Pos {
+    line: usize,
+    col: usize,
+}
+
+/// Where a motion moves the cursor, and the text an operator applies to.
+struct Motion {
+    pos: Pos,
+    linewise: bool,
+
+    /// The character at the end of a motion is part of the text.
+    inclusive: bool,
+}
+
+/// Classes of characters for word motions.  A word is a run of the same
+/// class, and an empty line counts as a word too.
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Empty,
+    Blank,
+    Word,
+    Punctuation,
+}
+
+/// Prepends keys to a queue, keeping their order.
+fn push_keys(queue: &mut VecDeque<Key>, keys: impl DoubleEndedIterator<Item = Key>) {
+    for key in keys.rev() {
+        queue.push_front(key);
+    }
+}
+
+fn is_word_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+/// The error of a command that only rings the bell.
+fn beep<T>() -> Result<T> {
+    Err(String::new())
+}
+
+impl Editor {
+    /// Reads a key from the terminal, handling the signals that arrive
+    /// while it waits.
+    pub fn read_terminal_key(&mut self) -> Key {
+        loop {
+            let Some(term) = &mut self.visual.term else {
+                self.visual.exhausted = true;
+                return ESC;
+            };
+            match term.read_key() {
+                Ok(key) => return key,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    if HANGUP.load(Ordering::SeqCst) {
+                        self.hangup();
+                    }
+                    if RESIZED.swap(false, Ordering::SeqCst) {
+                        self.resize();
+                    }
+                    if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                        return ctrl(b'C');
+                    }
+                }
+                Err(_) => self.hangup(),
+            }
+        }
+    }
+
+    /// Follows a change in the size of the terminal.
+    fn resize(&mut self) {
+        let (rows, cols) = window_size();
+        if let Some(term) = &mut self.visual.term {
+            term.rows = rows;
+            term.cols = cols;
+        }
+        self.visual.rows = rows;
+        self.visual.cols = cols;
+        self.options
+            .set_number("window", rows.saturating_sub(1).max(1));
+        self.visual.resize_window(rows.saturating_sub(1).max(1));
+        self.redraw();
+    }
+
+    fn typed_key(&mut self) -> Key {
+        if let Some(key) = self.visual.typeahead.pop_front() {
+            return key;
+        }
+        self.visual.map_expansions = 0;
+        self.read_terminal_key()
+    }
+
+    /// Whether a key arrives within `timeout` milliseconds.
+    fn key_pending(&self, timeout: i32) -> bool {
+        if !self.visual.typeahead.is_empty() {
+            return true;
+        }
+        match &self.visual.term {
+            Some(term) => term.poll(timeout).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Reads a key, expanding the mappings of `maps`.  The keys read are
+    /// recorded while a command that `.` may repeat is read.
+    pub fn next_key(&mut self, maps: Option<Maps>) -> Key {
+        let key = self.mapped_key(maps);
+        if let Some(recording) = &mut self.visual.recording {
+            recording.push(key);
+        }
+        key
+    }
+
+    fn mapped_key(&mut self, maps: Option<Maps>) -> Key {
+        loop {
+            if let Some(key) = self.visual.replay.pop_front() {
+                return key;
+            }
+            let key = self.typed_key();
+            let table = match maps {
+                Some(Maps::Command) => &self.maps,
+                Some(Maps::Input) => &self.input_maps,
+                None => return key,
+            };
+            let Key::Byte(b) = key else {
+                return key;
+            };
+            if !table.iter().any(|(lhs, _)| lhs[0] == b) {
+                return key;
+            }
+            let table = table.clone();
+
+            // Read on while the keys typed start a longer mapping.
+            let mut typed = vec![b];
+            let rhs = loop {
+                let longer = table
+                    .iter()
+                    .any(|(lhs, _)| lhs.len() > typed.len() && lhs.starts_with(&typed));
+                if longer && self.key_pending(1000) {
+                    match self.typed_key() {
+                        Key::Byte(c) => {
+                            typed.push(c);
+                            continue;
+                        }
+                        other => self.visual.typeahead.push_front(other),
+                    }
+                }
+                break table
+                    .iter()
+                    .find(|(lhs, _)| *lhs == typed)
+                    .map(|(_, rhs)| rhs.clone());
+            };
+            match rhs {
+                Some(rhs) => {
+                    self.visual.map_expansions += 1;
+                    if self.visual.map_expansions > 1000 {
+                        self.visual.typeahead.clear();
+                        self.set_status(&gettext("Mapping expands too many times"), true);
+                        return ESC;
+                    }
+                    let keys = rhs.into_iter().map(Key::Byte);
+                    if self.options.flag("remap") {
+                        push_keys(&mut self.visual.typeahead, keys);
+                    } else {
+                        push_keys(&mut self.visual.replay, keys);
+                    }
+                }
+                None => {
+                    push_keys(
+                        &mut self.visual.typeahead,
+                        typed[1..].iter().map(|&c| Key::Byte(c)),
+                    );
+                    return Key::Byte(b);
+                }
+            }
+        }
+    }
+
+    /// Reads the bytes of one character, as the argument of `f` or `r`.
+    fn read_char(&mut self) -> Result<Vec<u8>> {
+        let Key::Byte(first) = self.next_key(None) else {
+            return beep();
+        };
+        if first == 0x1b || first == 0x03 {
+            return beep();
+        }
+        let first = if first == 0x16 {
+            match self.next_key(None) {
+                Key::Byte(c) => c,
+                _ => return beep(),
+            }
+        } else {
+            first
+        };
+        let mut bytes = vec![first];
+        let len = match first {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 1,
+        };
+        while bytes.len() < len {
+            match self.next_key(None) {
+                Key::Byte(c) if c & 0xc0 == 0x80 => bytes.push(c),
+                _ => break,
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Runs the screen editor until it quits or `Q` switches to `ex`.
+    pub fn run_visual(&mut self) -> Result<()> {
+        if self.visual.term.is_none() && !self.visual.headless {
+            let mut term = Terminal::open()?;
+            term.enter();
+            self.visual.rows = term.rows;
+            self.visual.cols = term.cols;
+            self.visual.term = Some(term);
+        }
+        let window = self.options.number("window");
+        self.visual.resize_window(window);
+        if self.buffer.line == 0 && !self.buffer.is_empty() {
+            self.buffer.line = 1;
+        }
+        self.clamp_cursor();
+        self.redraw();
+
+        while self.mode == Mode::Visual && !self.quit && !self.visual.exhausted {
+            self.show_messages();
+            if self.visual.replay.is_empty() && self.visual.typeahead.is_empty() {
+                self.render();
+            }
+            self.buffer.begin_command();
+            if let Err(message) = self.screen_command() {
+                self.visual.replay.clear();
+                self.visual.recording = None;
+                self.visual.mode_name = None;
+                if !message.is_empty() {
+                    self.set_status(&message, true);
+                }
+                if let Some(term) = &mut self.visual.term {
+                    term.bell();
+                }
+            }
+            self.clamp_cursor();
+        }
+
+        let messages = std::mem::take(&mut self.visual.messages);
+        if let Some(mut term) = self.visual.term.take() {
+            term.leave();
+        }
+        // Messages not yet shown carry over to `ex` after `Q`.
+        if self.mode == Mode::Ex {
+            for message in messages {
+                self.print(&message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the cursor on a character of an existing line.
+    fn clamp_cursor(&mut self) {
+        let len = self.buffer.len();
+        if len == 0 {
+            self.buffer.line = 0;
+            self.buffer.col = 0;
+            return;
+        }
+        self.buffer.line = self.buffer.line.clamp(1, len);
+        let last = self.last_char(self.buffer.line);
+        if self.buffer.col > last {
+            self.buffer.col = last;
+        } else {
+            // Keep to the start of a character.
+            let line = self.buffer.current();
+            let mut col = self.buffer.col;
+            while col > 0 && col < line.len() && line[col] & 0xc0 == 0x80 {
+                col -= 1;
+            }
+            self.buffer.col = col;
+        }
+    }
+
+    fn pos(&self) -> Pos {
+        Pos {
+            line: self.buffer.line,
+            col: self.buffer.col,
+        }
+    }
+
+    /// The offset of the last character of line `n`.
+    fn last_char(&self, n: usize) -> usize {
+        let line = self.buffer.get(n);
+        if line.is_empty() {
+            0
+        } else {
+            prev_char(line, line.len())
+        }
+    }
+
+    fn first_nonblank(&self, n: usize) -> usize {
+        let line = self.buffer.get(n);
+        indentation(line).len().min(self.last_char(n))
+    }
+
+    /// Reads a command and runs it.
+    fn screen_command(&mut self) -> Result<()> {
+        let mut register = None;
+        let mut count: Option<usize> = None;
+        let key = loop {
+            let key = self.next_key(Some(Maps::Command));
+            self.visual.status = None;
+            match key {
+                Key::Byte(b'"') => match self.next_key(None) {
+                    Key::Byte(c) if c.is_ascii_alphanumeric() || c == b'"' => register = Some(c),
+                    _ => return beep(),
+                },
+                Key::Byte(d @ b'1'..=b'9') => {
+                    count = Some(count.unwrap_or(0).saturating_mul(10) + (d - b'0') as usize)
+                }
+                Key::Byte(b'0') if count.is_some() => {
+                    count = count.map(|n| n.saturating_mul(10));
+                }
+                key => break key,
+            }
+        };
+
+        self.visual.recording = Some(vec![key]);
+        let result = self.dispatch(key, count, register);
+        let keys = self.visual.recording.take();
+        if let (Ok(true), Some(keys)) = (&result, keys) {
+            self.visual.last_change = Some(Change {
+                register,
+                count,
+                keys,
+            });
+        }
+        result.map(|_| ())
+    }
+
+    /// Runs a command.  Returns whether `.` may repeat it.
+    fn dispatch(&mut self, key: Key, count: Option<usize>, register: Option<u8>) -> Result<bool> {
+        let n = count.unwrap_or(1).max(1);
+        let Key::Byte(c) = key else {
+            let motion = match key {
+                Key::PageDown => return self.scroll_pages(n, true).map(|_| false),
+                Key::PageUp => return self.scroll_pages(n, false).map(|_| false),
+                Key::Insert => return self.insert_at(b'i', n),
+                Key::Delete => {
+                    return self.operator_with_motion(b'd', register, Key::Byte(b'l'), count)
+                }
+                key => self.motion(key, count, None)?,
+            };
+            self.move_to(motion, key);
+            return Ok(false);
+        };
+        match c {
+            b'd' | b'c' | b'y' | b'<' | b'>' | b'!' => self.operator(c, count, register),
+            b'x' => {
+                if self.buffer.current().is_empty() {
+                    return beep();
+                }
+                self.operator_with_motion(b'd', register, Key::Byte(b'l'), count)
+            }
+            b'X' => self.operator_with_motion(b'd', register, Key::Byte(b'h'), count),
+            b'D' => self.operator_with_motion(b'd', register, Key::Byte(b'$'), count),
+            b'C' => self.operator_with_motion(b'c', register, Key::Byte(b'$'), count),
+            b's' => self.operator_with_motion(b'c', register, Key::Byte(b'l'), count),
+            b'S' => self.operator_with_motion(b'c', register, Key::Byte(b'c'), count),
+            b'Y' => self.operator_with_motion(b'y', register, Key::Byte(b'y'), count),
+            b'p' | b'P' => self.put(c == b'p', n, register),
+            b'J' => self.join_command(n.max(2)),
+            b'r' => self.replace_chars(n),
+            b'~' => self.toggle_case(n),
+            b'i' | b'a' | b'I' | b'A' | b'o' | b'O' | b'R' => self.insert_at(c, n),
+            b'u' => {
+                if !self.buffer.undo() {
+                    return Err(gettext("Nothing to undo"));
+                }
+                self.visual.want_col = None;
+                Ok(false)
+            }
+            b'U' => match self.buffer.undo_line() {
+                Some(line) => {
+                    self.buffer.line = line;
+                    self.buffer.col = 0;
+                    Ok(false)
+                }
+                None => beep(),
+            },
+            b'.' => self.repeat_change(count),
+            b'm' => {
+                let mark = match self.next_key(None) {
+                    Key::Byte(c @ b'a'..=b'z') => c,
+                    _ => return beep(),
+                };
+                self.buffer
+                    .set_mark(mark, self.buffer.line, self.buffer.col);
+                Ok(false)
+            }
+            b'Z' => {
+                if self.next_key(None) != Key::Byte(b'Z') {
+                    return beep();
+                }
+                self.execute(b"x")?;
+                Ok(false)
+            }
+            b':' => {
+                self.visual.recording = None;
+                let Some(line) = self.read_bottom_line(b":")? else {
+                    return Ok(false);
+                };
+                self.execute(&line)?;
+                self.visual.want_col = None;
+                Ok(false)
+            }
+            b'Q' => {
+                self.mode = Mode::Ex;
+                Ok(false)
+            }
+            b'&' => {
+                self.execute(b"&")?;
+                Ok(true)
+            }
+            b'@' => {
+                let name = match self.next_key(None) {
+                    Key::Byte(c) if c.is_ascii_alphanumeric() || c == b'"' => c,
+                    _ => return beep(),
+                };
+                let register = self
+                    .register(name)
+                    .cloned()
+                    .ok_or_else(|| gettext("Nothing in the register"))?;
+                let mut keys = Vec::new();
+                for _ in 0..n {
+                    for (i, line) in register.text.iter().enumerate() {
+                        keys.extend(line.iter().map(|&c| Key::Byte(c)));
+                        if register.linewise || i + 1 < register.text.len() {
+                            keys.push(Key::Byte(b'\n'));
+                        }
+                    }
+                }
+                push_keys(&mut self.visual.typeahead, keys.into_iter());
+                Ok(false)
+            }
+            0x07 => {
+                let status = self.file_status();
+                self.set_status(&status, false);
+                Ok(false)
+            }
+            0x0c | 0x12 => {
+                self.redraw();
+                Ok(false)
+            }
+            0x1e => {
+                self.execute(b"edit #")?;
+                Ok(false)
+            }
+            0x1d => {
+                let word = self.word_at_cursor();
+                if word.is_empty() {
+                    return beep();
+                }
+                self.goto_tag(&String::from_utf8_lossy(&word), false)?;
+                Ok(false)
+            }
+            0x1a => {
+                self.suspend();
+                Ok(false)
+            }
+            0x06 => self.scroll_pages(n, true).map(|_| false),
+            0x02 => self.scroll_pages(n, false).map(|_| false),
+            0x04 | 0x15 => {
+                if let Some(count) = count {
+                    self.options.set_number("scroll", count);
+                }
+                self.scroll_half(c == 0x04).map(|_| false)
+            }
+            0x05 | 0x19 => self.scroll_lines(n, c == 0x05).map(|_| false),
+            b'z' => self.z_screen(count).map(|_| false),
+            _ => {
+                let motion = self.motion(key, count, None)?;
+                self.move_to(motion, key);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Moves the cursor as a motion command does.
+    fn move_to(&mut self, motion: Motion, key: Key) {
+        let vertical = matches!(
+            key,
+            Key::Byte(b'j' | b'k' | 0x0a | 0x0e | 0x10) | Key::Up | Key::Down
+        );
+        if !vertical {
+            self.visual.want_col = if matches!(key, Key::Byte(b'$') | Key::End) {
+                Some(usize::MAX)
+            } else {
+                None
+            };
+        }
+        self.buffer.line = motion.pos.line;
+        self.buffer.col = motion.pos.col;
+        self.clamp_cursor();
+    }
+
+    /// Returns the column of line `n` nearest the wanted screen column.
+    fn column_in_line(&mut self, n: usize) -> usize {
+        let want = match self.visual.want_col {
+            Some(col) => col,
+            None => {
+                let col = self.cursor_column(false);
+                self.visual.want_col = Some(col);
+                col
+            }
+        };
+        if want == usize::MAX {
+            return self.last_char(n);
+        }
+        let layout = self.line_layout(n);
+        layout.offset_at(want)
+    }
+
+    fn class_at(&self, p: Pos, big: bool) -> Class {
+        let line = self.buffer.get(p.line);
+        if line.is_empty() {
+            return Class::Empty;
+        }
+        if p.col >= line.len() {
+            return Class::Blank;
+        }
+        match line[p.col] {
+            b' ' | b'\t' => Class::Blank,
+            _ if big => Class::Word,
+            c if is_word_byte(c) => Class::Word,
+            _ => Class::Punctuation,
+        }
+    }
+
+    /// The next position, where the end of each line counts as a position
+    /// holding a blank.
+    fn next_pos(&self, p: Pos) -> Option<Pos> {
+        let line = self.buffer.get(p.line);
+        if p.col < line.len() {
+            Some(Pos {
+                line: p.line,
+                col: next_char(line, p.col),
+            })
+        } else if p.line < self.buffer.len() {
+            Some(Pos {
+                line: p.line + 1,
+                col: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn prev_pos(&self, p: Pos) -> Option<Pos> {
+        if p.col > 0 {
+            Some(Pos {
+                line: p.line,
+                col: prev_char(self.buffer.get(p.line), p.col),
+            })
+        } else if p.line > 1 {
+            Some(Pos {
+                line: p.line - 1,
+                col: self.buffer.get(p.line - 1).len(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn word_forward(&self, mut p: Pos, big: bool, n: usize) -> Pos {
+        for _ in 0..n {
+            let class = self.class_at(p, big);
+            if class == Class::Empty {
+                match self.next_pos(p) {
+                    Some(q) => p = q,
+                    None => break,
+                }
+            } else if class != Class::Blank {
+                while let Some(q) = self.next_pos(p) {
+                    p = q;
+                    if self.class_at(p, big) != class {
+                        break;
+                    }
+                }
+            }
+            while self.class_at(p, big) == Class::Blank {
+                match self.next_pos(p) {
+                    Some(q) => p = q,
+                    None => break,
+                }
+            }
+        }
+        p
+    }
+
+    /// Moves to the end of a word; with `stay` the first one may be the
+    /// word under the cursor, as `cw` wants.
+    fn word_end(&self, mut p: Pos, big: bool, n: usize, stay: bool) -> Pos {
+        for i in 0..n {
+            if !(stay && i == 0) {
+                match self.next_pos(p) {
+                    Some(q) => p = q,
+                    None => break,
+                }
+                while matches!(self.class_at(p, big), Class::Blank | Class::Empty) {
+                    match self.next_pos(p) {
+                        Some(q) => p = q,
+                        None => break,
+                    }
+                }
+            }
+            let class = self.class_at(p, big);
+            while let Some(q) = self.next_pos(p) {
+                if self.class_at(q, big) != class {
+                    break;
+                }
+                p = q;
+            }
+        }
+        p
+    }
+
+    fn word_backward(&self, mut p: Pos, big: bool, n: usize) -> Pos {
+        for _ in 0..n {
+            match self.prev_pos(p) {
+                Some(q) => p = q,
+                None => break,
+            }
+            while self.class_at(p, big) == Class::Blank {
+                match self.prev_pos(p) {
+                    Some(q) => p = q,
+                    None => break,
+                }
+            }
+            let class = self.class_at(p, big);
+            if class == Class::Empty {
+                continue;
+            }
+            while let Some(q) = self.prev_pos(p) {
+                if self.class_at(q, big) != class {
+                    break;
+                }
+                p = q;
+            }
+        }
+        p
+    }
+
+    /// Whether line `n` is a `.` line starting one of the macros listed in
+    /// an option, two characters each.
+    fn is_macro_line(&self, n: usize, option: &str) -> bool {
+        let line = self.buffer.get(n);
+        let Some(name) = line.strip_prefix(b".") else {
+            return false;
+        };
+        self.options
+            .text(option)
+            .as_bytes()
+            .chunks(2)
+            .any(|pair| match pair {
+                [a, b' '] | [a] => {
+                    name.first() == Some(a) && matches!(name.get(1), None | Some(b' ' | b'\t'))
+                }
+                pair => name.starts_with(pair),
+            })
+    }
+
+    fn is_section_boundary(&self, n: usize) -> bool {
+        let line = self.buffer.get(n);
+        matches!(line.first(), Some(b'{' | 0x0c)) || self.is_macro_line(n, "sections")
+    }
+
+    fn is_paragraph_boundary(&self, n: usize) -> bool {
+        self.buffer.get(n).is_empty()
+            || self.is_macro_line(n, "paragraphs")
+            || self.is_section_boundary(n)
+    }
+
+    fn paragraph_motion(&self, forward: bool, n: usize) -> Result<Pos> {
+        let len = self.buffer.len();
+        let mut line = self.buffer.line;
+        for _ in 0..n {
+            if forward {
+                if line >= len {
+                    break;
+                }
+                line += 1;
+                while line < len && self.is_paragraph_boundary(line) {
+                    line += 1;
+                }
+                while line < len && !self.is_paragraph_boundary(line) {
+                    line += 1;
+                }
+            } else {
+                if line <= 1 {
+                    break;
+                }
+                line -= 1;
+                while line > 1 && self.is_paragraph_boundary(line) {
+                    line -= 1;
+                }
+                while line > 1 && !self.is_paragraph_boundary(line) {
+                    line -= 1;
+                }
+            }
+        }
+        let col = if forward && line == len && !self.is_paragraph_boundary(line) {
+            self.buffer.get(line).len()
+        } else {
+            0
+        };
+        let pos = Pos { line, col };
+        if pos == self.pos() {
+            return beep();
+        }
+        Ok(pos)
+    }
+
+    fn section_motion(&self, forward: bool, n: usize) -> Result<Pos> {
+        let len = self.buffer.len();
+        let mut line = self.buffer.line;
+        for _ in 0..n {
+            if forward {
+                line += 1;
+                while line < len && !self.is_section_boundary(line) {
+                    line += 1;
+                }
+            } else {
+                line = line.saturating_sub(1).max(1);
+                while line > 1 && !self.is_section_boundary(line) {
+                    line -= 1;
+                }
+            }
+        }
+        Ok(Pos {
+            line: line.clamp(1, len),
+            col: 0,
+        })
+    }
+
+    fn byte_at(&self, p: Pos) -> u8 {
+        self.buffer.get(p.line).get(p.col).copied().unwrap_or(b'\n')
+    }
+
+    /// Whether a sentence starts at `p`: after the end of one, which is a
+    /// `.`, `!` or `?`, with any closing brackets and quotes, and the end of
+    /// a line or two blanks, or at the start of a paragraph.
+    fn is_sentence_start(&self, p: Pos) -> bool {
+        match self.class_at(p, true) {
+            Class::Empty => {
+                return self
+                    .prev_pos(p)
+                    .map_or(true, |q| self.class_at(q, true) != Class::Empty)
+            }
+            Class::Blank => return false,
+            _ => {}
+        }
+        let mut q = self.prev_pos(p);
+        let mut blanks = 0;
+        let mut newline = false;
+        while let Some(r) = q {
+            if self.class_at(r, true) != Class::Blank {
+                break;
+            }
+            if self.byte_at(r) == b'\n' {
+                newline = true;
+            } else {
+                blanks += 1;
+            }
+            q = self.prev_pos(r);
+        }
+        let Some(mut r) = q else {
+            return true;
+        };
+        if self.class_at(r, true) == Class::Empty {
+            return true;
+        }
+        if !newline && blanks < 2 {
+            return false;
+        }
+        while matches!(self.byte_at(r), b')' | b']' | b'"' | b'\'') {
+            match self.prev_pos(r) {
+                Some(s) => r = s,
+                None => return false,
+            }
+        }
+        matches!(self.byte_at(r), b'.' | b'!' | b'?')
+    }
+
+    fn sentence_motion(&self, forward: bool, n: usize) -> Result<Pos> {
+        let mut p = self.pos();
+        for _ in 0..n {
+            loop {
+                let next = if forward {
+                    self.next_pos(p)
+                } else {
+                    self.prev_pos(p)
+                };
+                let Some(q) = next else {
+                    if forward {
+                        p = Pos {
+                            line: p.line,
+                            col: self.last_char(p.line),
+                        };
+                    }
+                    break;
+                };
+                p = q;
+                if self.is_sentence_start(p) {
+                    break;
+                }
+            }
+        }
+        if p == self.pos() {
+            return beep();
+        }
+        Ok(p)
+    }
+
+    /// Finds the bracket matching the first one at or after the cursor.
+    fn match_bracket(&self) -> Result<Pos> {
+        let line = self.buffer.current();
+        let Some(offset) = line[self.buffer.col..]
+            .iter()
+            .position(|c| b"()[]{}".contains(c))
+        else {
+            return beep();
+        };
+        let mut p = Pos {
+            line: self.buffer.line,
+            col: self.buffer.col + offset,
+        };
+        let this = self.byte_at(p);
+        let (other, forward) = match this {
+            b'(' => (b')', true),
+            b'[' => (b']', true),
+            b'{' => (b'}', true),
+            b')' => (b'(', false),
+            b']' => (b'[', false),
+            _ => (b'{', false),
+        };
+        let mut depth = 0;
+        loop {
+            let next = if forward {
+                self.next_pos(p)
+            } else {
+                self.prev_pos(p)
+            };
+            let Some(q) = next else {
+                return beep();
+            };
+            p = q;
+            let c = self.byte_at(p);
+            if c == this {
+                depth += 1;
+            } else if c == other {
+                if depth == 0 {
+                    return Ok(p);
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    /// Finds the `n`th occurrence of a character in the line, for `f`, `F`,
+    /// `t` and `T`.
+    fn find_in_line(&self, kind: u8, target: &[u8], n: usize) -> Result<Pos> {
+        let line = self.buffer.current();
+        let mut col = self.buffer.col;
+        for i in 0..n {
+            let found = match kind {
+                b'f' | b't' => {
+                    // `t` repeated from just before a match skips it.
+                    let mut from = next_char(line, col);
+                    if kind == b't' && i == 0 && line[from..].starts_with(target) && n > 1 {
+                        from = next_char(line, from);
+                    }
+                    (from..line.len()).find(|&j| line[j..].starts_with(target))
+                }
+                _ => (0..col).rev().find(|&j| line[j..].starts_with(target)),
+            };
+            let Some(j) = found else {
+                return beep();
+            };
+            col = j;
+        }
+        let col = match kind {
+            b't' => prev_char(line, col),
+            b'T' => next_char(line, col),
+            _ => col,
+        };
+        Ok(Pos {
+            line: self.buffer.line,
+            col,
+        })
+    }
+
+    /// Finds the next match of a pattern after `from`, or the previous one
+    /// before it, wrapping around the buffer if `wrapscan` is set.
+    fn find_match(&self, regex: &Regex, from: Pos, forward: bool) -> Result<Pos> {
+        let len = self.buffer.len();
+        let wrapscan = self.options.flag("wrapscan");
+        for i in 0..=len {
+            let line = if forward {
+                (from.line + i - 1) % len + 1
+            } else {
+                (from.line + len - i - 1) % len + 1
+            };
+            if !wrapscan && ((forward && line < from.line) || (!forward && line > from.line)) {
+                break;
+            }
+            let starts = match_starts(regex, self.buffer.get(line));
+            let found = if i == 0 {
+                if forward {
+                    starts.into_iter().find(|&s| s > from.col)
+                } else {
+                    starts.into_iter().rev().find(|&s| s < from.col)
+                }
+            } else if i == len {
+                // Back at the starting line after wrapping around.
+                if forward {
+                    starts.into_iter().find(|&s| s <= from.col)
+                } else {
+                    starts.into_iter().rev().find(|&s| s >= from.col)
+                }
+            } else if forward {
+                starts.first().copied()
+            } else {
+                starts.last().copied()
+            };
+            if let Some(col) = found {
+                return Ok(Pos { line, col });
+            }
+        }
+        Err(gettext("Pattern not found"))
+    }
+
+    /// Searches for a pattern, possibly followed by its delimiter and a
+    /// line offset that makes the motion linewise.
+    fn search_motion(&mut self, text: &[u8], delimiter: u8, n: usize) -> Result<Motion> {
+        let mut pattern = Vec::new();
+        let mut offset = None;
+        let mut i = 0;
+        while i < text.len() {
+            match text[i] {
+                b'\\' if i + 1 < text.len() => {
+                    if text[i + 1] == delimiter {
+                        pattern.push(delimiter);
+                    } else {
+                        pattern.extend_from_slice(&text[i..i + 2]);
+                    }
+                    i += 2;
+                    continue;
+                }
+                c if c == delimiter => {
+                    offset = Some(&text[i + 1..]);
+                    break;
+                }
+                c => pattern.push(c),
+            }
+            i += 1;
+        }
+        let forward = delimiter == b'/';
+        self.last_search_forward = forward;
+        let regex = self.compile(&pattern)?;
+        let mut p = self.pos();
+        for _ in 0..n {
+            p = self.find_match(&regex, p, forward)?;
+        }
+        let offset = match offset {
+            Some(b"") | None => None,
+            Some(text) => {
+                let text = String::from_utf8_lossy(text);
+                let value = match text.trim() {
+                    "+" => 1,
+                    "-" => -1,
+                    t => t
+                        .parse::<i64>()
+                        .map_err(|_| gettext("Invalid search offset"))?,
+                };
+                Some(value)
+            }
+        };
+        match offset {
+            None => Ok(Motion {
+                pos: p,
+                linewise: false,
+                inclusive: false,
+            }),
+            Some(offset) => {
+                let line = p.line as i64 + offset;
+                if line < 1 || line > self.buffer.len() as i64 {
+                    return Err(gettext("Address out of range"));
+                }
+                let line = line as usize;
+                Ok(Motion {
+                    pos: Pos {
+                        line,
+                        col: self.first_nonblank(line),
+                    },
+                    linewise: true,
+                    inclusive: false,
+                })
+            }
+        }
+    }
+
+    /// Moves to a line as the linewise motions do: to its first non-blank.
+    fn line_motion(&self, line: usize) -> Result<Motion> {
+        if line == 0 || line > self.buffer.len() {
+            return beep();
+        }
+        Ok(Motion {
+            pos: Pos {
+                line,
+                col: self.first_nonblank(line),
+            },
+            linewise: true,
+            inclusive: false,
+        })
+    }
+
+    /// Reads and runs a motion, the one that `key` starts.
+    fn motion(&mut self, key: Key, count: Option<usize>, operator: Option<u8>) -> Result<Motion> {
+        if self.buffer.is_empty() {
+            return beep();
+        }
+        let n = count.unwrap_or(1).max(1);
+        let cur = self.pos();
+        let line = self.buffer.current().to_vec();
+        let charwise = |pos: Pos, inclusive: bool| Motion {
+            pos,
+            linewise: false,
+            inclusive,
+        };
+        let at = |col: usize| Pos {
+            line: cur.line,
+            col,
+        };
+        let key = match key {
+            Key::Left => Key::Byte(b'h'),
+            Key::Right => Key::Byte(b'l'),
+            Key::Up => Key::Byte(b'k'),
+            Key::Down => Key::Byte(b'j'),
+            Key::Home => Key::Byte(b'0'),
+            Key::End => Key::Byte(b'$'),
+            key => key,
+        };
+        let Key::Byte(c) = key else {
+            return beep();
+        };
+
+        // Jumps to another part of the buffer remember where they started.
+        let jump = matches!(
+            c,
+            b'G' | b'/'
+                | b'?'
+                | b'n'
+                | b'N'
+                | b'%'
+                | b'('
+                | b')'
+                | b'{'
+                | b'}'
+                | b'['
+                | b']'
+                | b'\''
+                | b'`'
+                | b'H'
+                | b'M'
+                | b'L'
+        );
+
+        let motion = match c {
+            b'h' | 0x08 => {
+                if cur.col == 0 {
+                    return beep();
+                }
+                let mut col = cur.col;
+                for _ in 0..n {
+                    col = prev_char(&line, col);
+                }
+                charwise(at(col), false)
+            }
+            b'l' | b' ' => {
+                let last = self.last_char(cur.line);
+                if line.is_empty() || (cur.col >= last && operator.is_none()) {
+                    return beep();
+                }
+                let mut col = cur.col;
+                for _ in 0..n {
+                    col = next_char(&line, col);
+                }
+                if operator.is_none() {
+                    col = col.min(last);
+                }
+                charwise(at(col), false)
+            }
+            b'j' | 0x0a | 0x0e => {
+                let target = cur.line + n;
+                if target > self.buffer.len() {
+                    return beep();
+                }
+                let col = self.column_in_line(target);
+                Motion {
+                    pos: Pos { line: target, col },
+                    linewise: true,
+                    inclusive: false,
+                }
+            }
+            b'k' | 0x10 => {
+                if n >= cur.line {
+                    return beep();
+                }
+                let target = cur.line - n;
+                let col = self.column_in_line(target);
+                Motion {
+                    pos: Pos { line: target, col },
+                    linewise: true,
+                    inclusive: false,
+                }
+            }
+            b'+' | b'\r' => self.line_motion(cur.line + n)?,
+            b'-' => self.line_motion(cur.line.saturating_sub(n))?,
+            b'_' => self.line_motion(cur.line + n - 1)?,
+            b'0' => charwise(at(0), false),
+            b'^' => charwise(at(self.first_nonblank(cur.line)), false),
+            b'$' => {
+                let target = cur.line + n - 1;
+                if target > self.buffer.len() {
+                    return beep();
+                }
+                let col = self.last_char(target);
+                Motion {
+                    pos: Pos { line: target, col },
+                    linewise: false,
+                    inclusive: !self.buffer.get(target).is_empty(),
+                }
+            }
+            b'|' => {
+                let layout = self.line_layout(cur.line);
+                charwise(at(layout.offset_at(n - 1)), false)
+            }
+            b'w' | b'W' => {
+                let pos = self.word_forward(cur, c == b'W', n);
+                if pos == cur {
+                    return beep();
+                }
+                charwise(pos, false)
+            }
+            b'e' | b'E' => {
+                let pos = self.word_end(cur, c == b'E', n, false);
+                if pos == cur {
+                    return beep();
+                }
+                charwise(pos, true)
+            }
+            b'b' | b'B' => {
+                let pos = self.word_backward(cur, c == b'B', n);
+                if pos == cur {
+                    return beep();
+                }
+                charwise(pos, false)
+            }
+            b'f' | b'F' | b't' | b'T' => {
+                let target = self.read_char()?;
+                self.visual.last_find = Some((c, target.clone()));
+                let pos = self.find_in_line(c, &target, n)?;
+                charwise(pos, matches!(c, b'f' | b't'))
+            }
+            b';' | b',' => {
+                let Some((kind, target)) = self.visual.last_find.clone() else {
+                    return beep();
+                };
+                let kind = if c == b',' {
+                    match kind {
+                        b'f' => b'F',
+                        b'F' => b'f',
+                        b't' => b'T',
+                        _ => b't',
+                    }
+                } else {
+                    kind
+                };
+                let pos = self.find_in_line(kind, &target, n)?;
+                charwise(pos, matches!(kind, b'f' | b't'))
+            }
+            b'G' => self.line_motion(count.unwrap_or(self.buffer.len()))?,
+            b'H' | b'M' | b'L' => {
+                self.scroll_to_cursor();
+                let top = self.visual.top;
+                let bottom = self.bottom_line();
+                let line = match c {
+                    b'H' => (top + n - 1).min(bottom),
+                    b'L' => bottom.saturating_sub(n - 1).max(top),
+                    _ => (top + bottom) / 2,
+                };
+                self.line_motion(line)?
+            }
+            b'{' | b'}' => charwise(self.paragraph_motion(c == b'}', n)?, false),
+            b'(' | b')' => charwise(self.sentence_motion(c == b')', n)?, false),
+            b'[' | b']' => {
+                if self.next_key(None) != key {
+                    return beep();
+                }
+                self.line_motion(self.section_motion(c == b']', n)?.line)
+                    .map(|m| Motion {
+                        linewise: operator.is_some(),
+                        ..m
+                    })?
+            }
+            b'%' => charwise(self.match_bracket()?, true),
+            b'/' | b'?' => {
+                let Some(text) = self.read_bottom_line(&[c])? else {
+                    return beep();
+                };
+                self.search_motion(&text, c, n)?
+            }
+            b'n' | b'N' => {
+                if self.last_pattern.is_none() {
+                    return Err(gettext("No previous regular expression"));
+                }
+                let saved = self.last_search_forward;
+                let forward = saved == (c == b'n');
+                let delimiter = if forward { b'/' } else { b'?' };
+                let motion = self.search_motion(b"", delimiter, n);
+                self.last_search_forward = saved;
+                motion?
+            }
+            b'\'' | b'`' => {
+                let mark = match self.next_key(None) {
+                    Key::Byte(m @ b'a'..=b'z') => m,
+                    Key::Byte(b'\'' | b'`') => b'\'',
+                    _ => return beep(),
+                };
+                let (line, col) = self
+                    .buffer
+                    .mark(mark)
+                    .ok_or_else(|| gettext("Mark not set"))?;
+                if c == b'\'' {
+                    self.line_motion(line)?
+                } else {
+                    charwise(
+                        Pos {
+                            line,
+                            col: col.min(self.buffer.get(line).len()),
+                        },
+                        false,
+                    )
+                }
+            }
+            _ => return beep(),
+        };
+        if jump {
+            self.buffer.set_mark(b'\'', cur.line, cur.col);
+        }
+        Ok(motion)
+    }
+
+    /// Reads the motion of an operator, or the operator repeated for whole
+    /// lines, and applies the operator.
+    fn operator(&mut self, op: u8, count: Option<usize>, register: Option<u8>) -> Result<bool> {
+        let mut second: Option<usize> = None;
+        let key = loop {
+            match self.next_key(Some(Maps::Command)) {
+                Key::Byte(d @ b'1'..=b'9') => {
+                    second = Some(second.unwrap_or(0) * 10 + (d - b'0') as usize)
+                }
+                Key::Byte(b'0') if second.is_some() => second = second.map(|n| n * 10),
+                key => break key,
+            }
+        };
+        let count = match (count, second) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(1) * b.unwrap_or(1)),
+        };
+        self.operator_with_motion(op, register, key, count)
+    }
+
+    fn operator_with_motion(
+        &mut self,
+        op: u8,
+        register: Option<u8>,
+        key: Key,
+        count: Option<usize>,
+    ) -> Result<bool> {
+        if key == ESC {
+            return beep();
+        }
+        let n = count.unwrap_or(1).max(1);
+        let cur = self.pos();
+        if self.buffer.is_empty() && !(op == b'c' && key == Key::Byte(b'c')) {
+            return beep();
+        }
+        let motion = if key == Key::Byte(op) || (op == b'c' && key == Key::Byte(b'c')) {
+            let last = (cur.line + n - 1).min(self.buffer.len());
+            Motion {
+                pos: Pos { line: last, col: 0 },
+                linewise: true,
+                inclusive: true,
+            }
+        } else if op == b'c'
+            && matches!(key, Key::Byte(b'w' | b'W'))
+            && !matches!(self.class_at(cur, false), Class::Blank | Class::Empty)
+        {
+            // `cw` changes to the end of the word, not up to the next one.
+            Motion {
+                pos: self.word_end(cur, key == Key::Byte(b'W'), n, true),
+                linewise: false,
+                inclusive: true,
+            }
+        } else {
+            let mut motion = self.motion(key, count, Some(op))?;
+            if matches!(key, Key::Byte(b'w' | b'W')) && motion.pos.line > cur.line {
+                // A word motion that crosses lines stops at the end of the
+                // last word instead of taking in the next line.
+                let line = motion.pos.line;
+                if motion.pos.col == indentation(self.buffer.get(line)).len() {
+                    motion.pos = Pos {
+                        line: line - 1,
+                        col: self.buffer.get(line - 1).len(),
+                    };
+                }
+            }
+            motion
+        };
+
+        // The region the operator applies to, its end excluded.
+        let (mut start, mut end) = if motion.pos < cur {
+            (motion.pos, cur)
+        } else {
+            (cur, motion.pos)
+        };
+        let mut linewise = motion.linewise;
+        if !linewise {
+            if motion.inclusive {
+                end.col = next_char(self.buffer.get(end.line), end.col);
+            } else if end.line > start.line && end.col == 0 {
+                // An exclusive motion to the start of a line stops at the
+                // end of the line before.
+                end = Pos {
+                    line: end.line - 1,
+                    col: self.buffer.get(end.line - 1).len(),
+                };
+                if start.col <= indentation(self.buffer.get(start.line)).len() {
+                    linewise = true;
+                }
+            }
+        }
+        if linewise {
+            start.col = 0;
+            end.col = self.buffer.get(end.line).len();
+        }
+
+        match op {
+            b'y' => {
+                let text = self.region_text(start, end, linewise);
+                let lines = text.len();
+                self.store_register(register, Register { linewise, text }, false);
+                if linewise {
+                    self.report_lines(lines, Some(&gettext("lines yanked")));
+                    if start.line < cur.line {
+                        self.buffer.line = start.line;
+                    }
+                } else {
+                    self.buffer.line = start.line;
+                    self.buffer.col = start.col;
+                }
+                Ok(false)
+            }
+            b'd' => {
+                let before = self.buffer.len();
+                self.delete_region(register, start, end, linewise);
+                if linewise {
+                    self.buffer.line = start.line.min(self.buffer.len());
+                    if self.buffer.line > 0 {
+                        self.buffer.col = self.first_nonblank(self.buffer.line);
+                    }
+                } else {
+                    self.buffer.line = start.line;
+                    self.buffer.col = start.col;
+                }
+                self.report_lines(before, None);
+                Ok(true)
+            }
+            b'c' => {
+                if linewise {
+                    let indent = if self.buffer.is_empty() || !self.options.flag("autoindent") {
+                        Vec::new()
+                    } else {
+                        indentation(self.buffer.get(start.line)).to_vec()
+                    };
+                    if !self.buffer.is_empty() {
+                        let text = self.region_text(start, end, true);
+                        self.store_register(register, Register { linewise, text }, true);
+                        self.buffer.delete(start.line, end.line);
+                    }
+                    let col = indent.len();
+                    self.buffer.insert(start.line.max(1) - 1, vec![indent]);
+                    self.buffer.line = start.line.max(1);
+                    self.buffer.col = col;
+                } else {
+                    self.delete_region(register, start, end, false);
+                    self.buffer.line = start.line;
+                    self.buffer.col = start.col;
+                }
+                self.input_text(1, false, false)?;
+                Ok(true)
+            }
+            b'<' | b'>' => {
+                self.shift_lines(start.line, end.line, op == b'>', 1);
+                self.buffer.line = start.line;
+                self.buffer.col = self.first_nonblank(start.line);
+                Ok(true)
+            }
+            _ => {
+                let Some(command) = self.read_bottom_line(b"!")? else {
+                    return beep();
+                };
+                let command = self.expand_shell_command(&command)?;
+                if command.trim().is_empty() {
+                    return beep();
+                }
+                self.last_shell_command = Some(command.clone());
+                self.filter(start.line, end.line, &command)?;
+                if self.buffer.line > 0 {
+                    self.buffer.col = self.first_nonblank(self.buffer.line);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns the text from `start` up to `end`.
+    fn region_text(&self, start: Pos, end: Pos, linewise: bool) -> Vec<Vec<u8>> {
+        if linewise {
+            return (start.line..=end.line)
+                .map(|n| self.buffer.get(n).to_vec())
+                .collect();
+        }
+        if start.line == end.line {
+            return vec![self.buffer.get(start.line)[start.col..end.col].to_vec()];
+        }
+        let mut text = vec![self.buffer.get(start.line)[start.col..].to_vec()];
+        for n in start.line + 1..end.line {
+            text.push(self.buffer.get(n).to_vec());
+        }
+        text.push(self.buffer.get(end.line)[..end.col].to_vec());
+        text
+    }
+
+    /// Deletes the text from `start` up to `end` into a register.
+    fn delete_region(&mut self, register: Option<u8>, start: Pos, end: Pos, linewise: bool) {
+        let text = self.region_text(start, end, linewise);
+        self.store_register(register, Register { linewise, text }, true);
+        if linewise {
+            self.buffer.delete(start.line, end.line);
+            return;
+        }
+        let mut joined = self.buffer.get(start.line)[..start.col].to_vec();
+        joined.extend_from_slice(&self.buffer.get(end.line)[end.col..]);
+        if end.line > start.line {
+            self.buffer.delete(start.line + 1, end.line);
+        }
+        self.buffer.replace(start.line, joined);
+    }
+
+    fn put(&mut self, after: bool, n: usize, register: Option<u8>) -> Result<bool> {
+        let register = self
+            .register(register.unwrap_or(b'"'))
+            .cloned()
+            .ok_or_else(|| gettext("Nothing in the register"))?;
+        let before = self.buffer.len();
+        if register.linewise {
+            let mut lines = Vec::new();
+            for _ in 0..n {
+                lines.extend(register.text.iter().cloned());
+            }
+            let at = if after || self.buffer.is_empty() {
+                self.buffer.line
+            } else {
+                self.buffer.line - 1
+            };
+            self.buffer.insert(at, lines);
+            self.buffer.line = at + 1;
+            self.buffer.col = self.first_nonblank(at + 1);
+            self.report_lines(before, None);
+            return Ok(true);
+        }
+
+        if self.buffer.is_empty() {
+            self.buffer.insert(0, vec![Vec::new()]);
+            self.buffer.line = 1;
+        }
+        let mut text = Vec::new();
+        for _ in 0..n {
+            for (i, piece) in register.text.iter().enumerate() {
+                if i > 0 {
+                    text.push(b'\n');
+                }
+                text.extend_from_slice(piece);
+            }
+        }
+        let line = self.buffer.current().to_vec();
+        let offset = if after && !line.is_empty() {
+            next_char(&line, self.buffer.col)
+        } else {
+            self.buffer.col
+        };
+        let mut pieces: Vec<Vec<u8>> = text.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect();
+        if pieces.len() == 1 {
+            let mut new = line[..offset].to_vec();
+            new.extend_from_slice(&pieces[0]);
+            let end = new.len();
+            new.extend_from_slice(&line[offset..]);
+            self.buffer.replace(self.buffer.line, new);
+            self.buffer.col = if end > offset {
+                prev_char(self.buffer.current(), end)
+            } else {
+                offset
+            };
+        } else {
+            let mut first = line[..offset].to_vec();
+            first.extend_from_slice(&pieces[0]);
+            pieces
+                .last_mut()
+                .unwrap()
+                .extend_from_slice(&line[offset..]);
+            let n = self.buffer.line;
+            self.buffer.replace(n, first);
+            self.buffer.insert(n, pieces.split_off(1));
+            self.buffer.col = offset;
+        }
+        Ok(true)
+    }
+
+    fn join_command(&mut self, n: usize) -> Result<bool> {
+        let first = self.buffer.line;
+        let last = first + n - 1;
+        if first == 0 || last > self.buffer.len() {
+            return beep();
+        }
+        let mut joined = self.buffer.get(first).to_vec();
+        let mut col = 0;
+        for line in first + 1..=last {
+            let next = self.buffer.get(line);
+            let length = joined.len();
+            joined = join_lines(&joined, next, false);
+            col = if joined.len() > length {
+                length
+            } else {
+                length.saturating_sub(1)
+            };
+            if joined.get(col).is_some_and(|&c| c == b' ') && joined.len() > col + 1 {
+                col = joined.len() - (joined.len() - col);
+            }
+        }
+        self.buffer.delete(first + 1, last);
+        self.buffer.replace(first, joined);
+        self.buffer.col = col;
+        Ok(true)
+    }
+
+    fn replace_chars(&mut self, n: usize) -> Result<bool> {
+        let with = self.read_char()?;
+        let line = self.buffer.current().to_vec();
+        let start = self.buffer.col;
+        let mut end = start;
+        for _ in 0..n {
+            if end >= line.len() {
+                return beep();
+            }
+            end = next_char(&line, end);
+        }
+        let number = self.buffer.line;
+        if with == b"\r" || with == b"\n" {
+            // Replacing with a newline splits the line.
+            let left = line[..start].to_vec();
+            let right = line[end..].trim_ascii_start().to_vec();
+            let indent = if self.options.flag("autoindent") {
+                indentation(&left).to_vec()
+            } else {
+                Vec::new()
+            };
+            let mut new = indent.clone();
+            new.extend(right);
+            self.buffer.replace(number, left);
+            self.buffer.insert(number, vec![new]);
+            self.buffer.line = number + 1;
+            self.buffer.col = indent.len();
+            return Ok(true);
+        }
+        let mut new = line[..start].to_vec();
+        for _ in 0..n {
+            new.extend_from_slice(&with);
+        }
+        let last = new.len() - with.len();
+        new.extend_from_slice(&line[end..]);
+        self.buffer.replace(number, new);
+        self.buffer.col = last;
+        Ok(true)
+    }
+
+    fn toggle_case(&mut self, n: usize) -> Result<bool> {
+        let line = self.buffer.current().to_vec();
+        if line.is_empty() {
+            return beep();
+        }
+        let mut new = line[..self.buffer.col].to_vec();
+        let mut col = self.buffer.col;
+        for _ in 0..n {
+            if col >= line.len() {
+                break;
+            }
+            let next = next_char(&line, col);
+            match std::str::from_utf8(&line[col..next])
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) if c.is_lowercase() => new.extend(c.to_uppercase().to_string().bytes()),
+                Some(c) if c.is_uppercase() => new.extend(c.to_lowercase().to_string().bytes()),
+                _ => new.extend_from_slice(&line[col..next]),
+            }
+            col = next;
+        }
+        let end = new.len();
+        new.extend_from_slice(&line[col..]);
+        self.buffer.replace(self.buffer.line, new);
+        self.buffer.col = end;
+        Ok(true)
+    }
+
+    fn repeat_change(&mut self, count: Option<usize>) -> Result<bool> {
+        let Some(change) = self.visual.last_change.clone() else {
+            return beep();
+        };
+        self.visual.recording = None;
+        let mut keys = Vec::new();
+        if let Some(register) = change.register {
+            // A numbered register advances to the next one each time.
+            let register = match register {
+                b'1'..=b'8' => register + 1,
+                r => r,
+            };
+            keys.extend([Key::Byte(b'"'), Key::Byte(register)]);
+        }
+        if let Some(count) = count.or(change.count) {
+            keys.extend(count.to_string().bytes().map(Key::Byte));
+        }
+        keys.extend(change.keys);
+        push_keys(&mut self.visual.replay, keys.into_iter());
+        Ok(false)
+    }
+
+    /// The word under the cursor, as `^]` looks up.
+    fn word_at_cursor(&self) -> Vec<u8> {
+        let line = self.buffer.current();
+        let mut start = self.buffer.col.min(line.len());
+        while start > 0 && is_word_byte(line[start - 1]) {
+            start -= 1;
+        }
+        let mut end = self.buffer.col.min(line.len());
+        while end < line.len() && is_word_byte(line[end]) {
+            end += 1;
+        }
+        line[start..end].to_vec()
+    }
+
+    fn scroll_pages(&mut self, n: usize, forward: bool) -> Result<()> {
+        self.scroll_to_cursor();
+        let len = self.buffer.len();
+        for _ in 0..n {
+            if forward {
+                let bottom = self.bottom_line();
+                if bottom >= len && self.visual.top >= len {
+                    return beep();
+                }
+                self.visual.top = bottom.saturating_sub(1).max(self.visual.top + 1).min(len);
+            } else {
+                if self.visual.top <= 1 {
+                    return beep();
+                }
+                // The old top line, and the one after it, end the new page.
+                let mut top = (self.visual.top + 1).min(len);
+                let mut used = 0;
+                let rows = self.text_rows();
+                while top > 1 && used + 1 < rows {
+                    top -= 1;
+                    used += 1;
+                }
+                self.visual.top = top;
+            }
+        }
+        self.buffer.line = if forward {
+            self.visual.top
+        } else {
+            self.bottom_line()
+        };
+        self.buffer.col = self.first_nonblank(self.buffer.line);
+        Ok(())
+    }
+
+    fn scroll_half(&mut self, forward: bool) -> Result<()> {
+        let amount = self.options.number("scroll").max(1);
+        let len = self.buffer.len();
+        if (forward && self.buffer.line >= len) || (!forward && self.buffer.line <= 1) {
+            return beep();
+        }
+        self.scroll_to_cursor();
+        if forward {
+            self.visual.top = (self.visual.top + amount).min(len);
+            self.buffer.line = (self.buffer.line + amount).min(len);
+        } else {
+            self.visual.top = self.visual.top.saturating_sub(amount).max(1);
+            self.buffer.line = self.buffer.line.saturating_sub(amount).max(1);
+        }
+        self.buffer.col = self.first_nonblank(self.buffer.line);
+        Ok(())
+    }
+
+    fn scroll_lines(&mut self, n: usize, forward: bool) -> Result<()> {
+        self.scroll_to_cursor();
+        let len = self.buffer.len();
+        if forward {
+            if self.visual.top >= len {
+                return beep();
+            }
+            self.visual.top = (self.visual.top + n).min(len);
+            if self.buffer.line < self.visual.top {
+                self.buffer.line = self.visual.top;
+                self.buffer.col = self.first_nonblank(self.buffer.line);
+            }
+        } else {
+            if self.visual.top <= 1 {
+                return beep();
+            }
+            self.visual.top = self.visual.top.saturating_sub(n).max(1);
+            let bottom = self.bottom_line();
+            if self.buffer.line > bottom {
+                self.buffer.line = bottom;
+                self.buffer.col = self.first_nonblank(self.buffer.line);
+            }
+        }
+        Ok(())
+    }
+
+    /// `z`: redraws with the line at the top, middle or bottom of the
+    /// screen.
+    fn z_screen(&mut self, count: Option<usize>) -> Result<()> {
+        let line = count.unwrap_or(self.buffer.line);
+        if line == 0 || line > self.buffer.len() {
+            return beep();
+        }
+        let mut key = self.next_key(None);
+        let mut window: Option<usize> = None;
+        while let Key::Byte(d @ b'0'..=b'9') = key {
+            window = Some(window.unwrap_or(0) * 10 + (d - b'0') as usize);
+            key = self.next_key(None);
+        }
+        if let Some(window) = window.filter(|&w| w > 0) {
+            self.options.set_number("window", window);
+            self.visual.resize_window(window);
+        }
+        self.buffer.line = line;
+        self.buffer.col = self.first_nonblank(line);
+        match key {
+            Key::Byte(b'\r' | b'\n' | b'+') => self.visual.top = line,
+            Key::Byte(b'.') => self.center_on(line),
+            Key::Byte(b'-' | b'^') => {
+                let rows = self.text_rows();
+                let mut top = line;
+                let mut used = 1;
+                while top > 1 && used < rows {
+                    top -= 1;
+                    used += 1;
+                }
+                self.visual.top = top;
+            }
+            _ => return beep(),
+        }
+        self.visual.invalidate();
+        Ok(())
+    }
+
+    /// Starts text input for `i`, `a`, `I`, `A`, `o`, `O` and `R`.
+    fn insert_at(&mut self, command: u8, n: usize) -> Result<bool> {
+        if self.buffer.is_empty() {
+            self.buffer.insert(0, vec![Vec::new()]);
+            self.buffer.line = 1;
+            self.buffer.col = 0;
+        }
+        let line = self.buffer.current().to_vec();
+        match command {
+            b'a' if !line.is_empty() => self.buffer.col = next_char(&line, self.buffer.col),
+            b'I' => self.buffer.col = indentation(&line).len(),
+            b'A' => self.buffer.col = line.len(),
+            b'o' | b'O' => {
+                let indent = if self.options.flag("autoindent") {
+                    indentation(&line).to_vec()
+                } else {
+                    Vec::new()
+                };
+                let at = if command == b'o' {
+                    self.buffer.line
+                } else {
+                    self.buffer.line - 1
+                };
+                self.buffer.col = indent.len();
+                self.buffer.insert(at, vec![indent]);
+                self.buffer.line = at + 1;
+            }
+            _ => {}
+        }
+        self.input_text(n, command == b'R', matches!(command, b'o' | b'O'))?;
+        Ok(true)
+    }
+
+    /// Expands an abbreviation ending at `col`, as typing a character that
+    /// cannot be part of a word does.
+    fn expand_abbreviation(&self, text: &mut Vec<u8>, col: &mut usize, start: usize) {
+        let mut begin = *col;
+        while begin > start && is_word_byte(text[begin - 1]) {
+            begin -= 1;
+        }
+        if begin == *col || (begin > 0 && is_word_byte(text[begin - 1])) {
+            return;
+        }
+        let word = &text[begin..*col];
+        if let Some((_, rhs)) = self.abbreviations.iter().find(|(lhs, _)| lhs == word) {
+            text.splice(begin..*col, rhs.iter().copied());
+            *col = begin + rhs.len();
+        }
+    }
+
+    /// Shows the bracket matching one just typed, briefly, if it is on the
+    /// screen.
+    fn show_match(&mut self) {
+        let Ok(pos) = self.match_bracket_before_cursor() else {
+            return;
+        };
+        if pos.line < self.visual.top || pos.line > self.bottom_line() {
+            return;
+        }
+        let saved = self.pos();
+        self.buffer.line = pos.line;
+        self.buffer.col = pos.col;
+        self.render();
+        if let Some(term) = &self.visual.term {
+            let _ = term.poll(1000);
+        }
+        self.buffer.line = saved.line;
+        self.buffer.col = saved.col;
+    }
+
+    fn match_bracket_before_cursor(&self) -> Result<Pos> {
+        let saved = self.buffer.col;
+        let line = self.buffer.current();
+        if saved == 0 {
+            return beep();
+        }
+        let col = prev_char(line, saved);
+        let mut copy = Editor::match_from(
+            self,
+            Pos {
+                line: self.buffer.line,
+                col,
+            },
+        )?;
+        copy.col = copy.col.min(self.buffer.get(copy.line).len());
+        Ok(copy)
+    }
+
+    fn match_from(&self, p: Pos) -> Result<Pos> {
+        let this = self.byte_at(p);
+        let (other, forward) = match this {
+            b'(' => (b')', true),
+            b'[' => (b']', true),
+            b'{' => (b'}', true),
+            b')' => (b'(', false),
+            b']' => (b'[', false),
+            b'}' => (b'{', false),
+            _ => return beep(),
+        };
+        let mut p = p;
+        let mut depth = 0;
+        loop {
+            let next = if forward {
+                self.next_pos(p)
+            } else {
+                self.prev_pos(p)
+            };
+            let Some(q) = next else {
+                return beep();
+            };
+            p = q;
+            let c = self.byte_at(p);
+            if c == this {
+                depth += 1;
+            } else if c == other {
+                if depth == 0 {
+                    return Ok(p);
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    /// Text input mode: keys insert text at the cursor, or with `replace`
+    /// overwrite it, until `<ESC>`.  A count repeats the text typed, on
+    /// new lines for `o` and `O`.
+    fn input_text(&mut self, count: usize, replace: bool, open: bool) -> Result<()> {
+        self.visual.mode_name = Some(if replace {
+            gettext("REPLACE")
+        } else {
+            gettext("INSERT")
+        });
+        let tabstop = self.options.number("tabstop").max(1);
+        let shiftwidth = self.options.number("shiftwidth").max(1);
+        let mut text = self.buffer.current().to_vec();
+        let mut col = self.buffer.col.min(text.len());
+
+        // Input may not be erased back past where it started.
+        let mut start = col;
+        let mut overwritten: Vec<Vec<u8>> = Vec::new();
+        let mut typed: Vec<Key> = Vec::new();
+        let mut repeats = count.saturating_sub(1);
+        let mut repeating = false;
+
+        loop {
+            self.buffer.replace(self.buffer.line, text.clone());
+            self.buffer.col = col;
+            if self.visual.replay.is_empty() && self.visual.typeahead.is_empty() {
+                self.render();
+            }
+            let key = self.next_key(Some(Maps::Input));
+            if !repeating && key != ESC {
+                typed.push(key);
+            }
+            match key {
+                ESC | Key::Byte(0x03) => {
+                    if !self.abbreviations.is_empty() {
+                        self.expand_abbreviation(&mut text, &mut col, start);
+                    }
+                    if key == ESC && repeats > 0 && !self.visual.exhausted {
+                        repeats -= 1;
+                        repeating = true;
+                        let mut keys = Vec::new();
+                        if open {
+                            keys.push(Key::Byte(b'\r'));
+                        }
+                        keys.extend(typed.iter().copied());
+                        keys.push(ESC);
+                        push_keys(&mut self.visual.replay, keys.into_iter());
+                        continue;
+                    }
+                    break;
+                }
+                Key::Byte(b'\r' | b'\n') => {
+                    if !self.abbreviations.is_empty() {
+                        self.expand_abbreviation(&mut text, &mut col, start);
+                    }
+                    let autoindent = self.options.flag("autoindent");
+                    let right = text.split_off(col);
+                    let indent = if autoindent {
+                        indentation(&text).to_vec()
+                    } else {
+                        Vec::new()
+                    };
+                    if autoindent && indentation(&text).len() == text.len() {
+                        text.clear();
+                    }
+                    self.buffer.replace(self.buffer.line, text);
+                    let right = if autoindent {
+                        right.trim_ascii_start().to_vec()
+                    } else {
+                        right
+                    };
+                    text = indent.clone();
+                    text.extend(right);
+                    self.buffer.insert(self.buffer.line, vec![text.clone()]);
+                    self.buffer.line += 1;
+                    col = indent.len();
+                    start = col;
+                    overwritten.clear();
+                }
+                Key::Byte(0x08 | 0x7f) => {
+                    if col <= start {
+                        if let Some(term) = &mut self.visual.term {
+                            term.bell();
+                        }
+                        continue;
+                    }
+                    let previous = prev_char(&text, col);
+                    match overwritten.pop() {
+                        Some(original) if replace => {
+                            text.splice(previous..col, original);
+                        }
+                        _ => {
+                            text.drain(previous..col);
+                        }
+                    }
+                    col = previous;
+                }
+                k if k == ctrl(b'W') => {
+                    let mut to = col;
+                    while to > start && matches!(text[to - 1], b' ' | b'\t') {
+                        to -= 1;
+                    }
+                    if to > start {
+                        let word = is_word_byte(text[to - 1]);
+                        while to > start
+                            && !matches!(text[to - 1], b' ' | b'\t')
+                            && is_word_byte(text[to - 1]) == word
+                        {
+                            to -= 1;
+                        }
+                    }
+                    text.drain(to..col);
+                    col = to;
+                    overwritten.clear();
+                }
+                k if k == ctrl(b'U') => {
+                    text.drain(start..col);
+                    col = start;
+                    overwritten.clear();
+                }
+                k if k == ctrl(b'V') => {
+                    if let Key::Byte(c) = self.next_key(None) {
+                        if !repeating {
+                            typed.push(Key::Byte(c));
+                        }
+                        self.insert_byte(&mut text, &mut col, c, replace, &mut overwritten);
+                    }
+                }
+                k if k == ctrl(b'D') => {
+                    // Backs up over autoindent: `0^D` removes all of it, and
+                    // `^^D` removes it for this line only.
+                    let before = &text[..col];
+                    let blank = |b: &[u8]| b.iter().all(|&c| c == b' ' || c == b'\t');
+                    if col > 0
+                        && matches!(before[col - 1], b'0' | b'^')
+                        && blank(&before[..col - 1])
+                    {
+                        text.drain(..col);
+                        col = 0;
+                    } else if blank(before) && col > 0 {
+                        let width = indent_width(before, tabstop);
+                        let width = (width - 1) / shiftwidth * shiftwidth;
+                        let indent = make_indent(width, tabstop);
+                        let new_col = indent.len();
+                        text.splice(..col, indent);
+                        col = new_col;
+                    } else if let Some(term) = &mut self.visual.term {
+                        term.bell();
+                    }
+                    start = start.min(col);
+                }
+                k if k == ctrl(b'T') => {
+                    let old = indentation(&text).len();
+                    let width = indent_width(&text, tabstop);
+                    let indent = make_indent((width / shiftwidth + 1) * shiftwidth, tabstop);
+                    let new = indent.len();
+                    text.splice(..old, indent);
+                    col = (col + new).saturating_sub(old);
+                    start = (start + new).saturating_sub(old).min(col);
+                }
+                Key::Left | Key::Right | Key::Up | Key::Down | Key::Home | Key::End => {
+                    self.buffer.replace(self.buffer.line, text.clone());
+                    match key {
+                        Key::Left => col = prev_char(&text, col),
+                        Key::Right => col = next_char(&text, col),
+                        Key::Home => col = 0,
+                        Key::End => col = text.len(),
+                        Key::Up if self.buffer.line > 1 => {
+                            self.buffer.line -= 1;
+                            text = self.buffer.current().to_vec();
+                            col = col.min(text.len());
+                        }
+                        Key::Down if self.buffer.line < self.buffer.len() => {
+                            self.buffer.line += 1;
+                            text = self.buffer.current().to_vec();
+                            col = col.min(text.len());
+                        }
+                        _ => {}
+                    }
+                    start = col;
+                    overwritten.clear();
+                }
+                Key::Byte(c) => {
+                    if !is_word_byte(c) && !self.abbreviations.is_empty() {
+                        self.expand_abbreviation(&mut text, &mut col, start);
+                    }
+                    self.insert_byte(&mut text, &mut col, c, replace, &mut overwritten);
+                    self.wrap_margin(&mut text, &mut col, &mut start, c);
+                    if matches!(c, b')' | b']' | b'}') && self.options.flag("showmatch") {
+                        self.buffer.replace(self.buffer.line, text.clone());
+                        self.buffer.col = col;
+                        self.show_match();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.buffer.replace(self.buffer.line, text);
+        self.visual.mode_name = None;
+        self.buffer.col = if col > 0 {
+            prev_char(self.buffer.current(), col)
+        } else {
+            0
+        };
+        self.visual.want_col = None;
+        Ok(())
+    }
+
+    fn insert_byte(
+        &mut self,
+        text: &mut Vec<u8>,
+        col: &mut usize,
+        c: u8,
+        replace: bool,
+        overwritten: &mut Vec<Vec<u8>>,
+    ) {
+        // Overwrite a whole character when the first byte of one is typed.
+        if replace && c & 0xc0 != 0x80 && *col < text.len() {
+            let end = *col + char_len(text, *col);
+            overwritten.push(text.drain(*col..end).collect());
+        } else if replace && c & 0xc0 != 0x80 {
+            overwritten.push(Vec::new());
+        }
+        text.insert(*col, c);
+        *col += 1;
+    }
+
+    /// Breaks the line at a blank once text reaches `wrapmargin` columns
+    /// from the right edge of the screen.
+    fn wrap_margin(&mut self, text: &mut Vec<u8>, col: &mut usize, start: &mut usize, typed: u8) {
+        let margin = self.options.number("wrapmargin");
+        if margin == 0 || typed == b' ' || typed == b'\t' {
+            return;
+        }
+        let tabstop = self.options.number("tabstop").max(1);
+        let layout = super::screen::layout(&text[..*col], tabstop, false);
+        if layout.cells.len() <= self.visual.cols.saturating_sub(margin) {
+            return;
+        }
+        let indent_len = indentation(text).len();
+        let Some(blank) = text[indent_len..*col]
+            .iter()
+            .rposition(|&c| c == b' ' || c == b'\t')
+            .map(|i| i + indent_len)
+        else {
+            return;
+        };
+        let mut end = blank;
+        while end > indent_len && matches!(text[end - 1], b' ' | b'\t') {
+            end -= 1;
+        }
+        let right = text.split_off(blank + 1);
+        text.truncate(end);
+        let indent = if self.options.flag("autoindent") {
+            indentation(text).to_vec()
+        } else {
+            Vec::new()
+        };
+        self.buffer.replace(self.buffer.line, text.clone());
+        let moved = *col - (blank + 1);
+        *text = indent.clone();
+        text.extend(right);
+        self.buffer.insert(self.buffer.line, vec![text.clone()]);
+        self.buffer.line += 1;
+        *col = indent.len() + moved;
+        *start = indent.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::split_lines;
+
+    /// Runs the keys on a buffer holding `text`, without a terminal.
+    fn run(text: &str, keys: &str) -> Editor {
+        let mut editor = Editor::new(Mode::Visual, false);
+        editor.visual.headless = true;
+        editor.buffer.load(split_lines(text.as_bytes()));
+        editor.visual.typeahead.extend(keys.bytes().map(Key::Byte));
+        editor.run_visual().unwrap();
+        editor
+    }
+
+    fn text(editor: &Editor) -> String {
+        let mut text = String::new();
+        for line in editor.buffer.lines() {
+            text.push_str(&String::from_utf8_lossy(line));
+            text.push('\n');
+        }
+        text
+    }
+
+    fn cursor(editor: &Editor) -> (usize, usize) {
+        (editor.buffer.line, editor.buffer.col)
+    }
+
+    #[test]
+    fn test_motions() {
+        let sample = "one two three\n  four five\n\nsix.  Seven\n";
+        assert_eq!(cursor(&run(sample, "w")), (1, 4));
+        assert_eq!(cursor(&run(sample, "3w")), (2, 2));
+        assert_eq!(cursor(&run(sample, "$")), (1, 12));
+        assert_eq!(cursor(&run(sample, "e")), (1, 2));
+        assert_eq!(cursor(&run(sample, "jb")), (1, 8));
+        assert_eq!(cursor(&run(sample, "j^")), (2, 2));
+        assert_eq!(cursor(&run(sample, "G")), (4, 0));
+        assert_eq!(cursor(&run(sample, "2G")), (2, 2));
+        assert_eq!(cursor(&run(sample, "fe")), (1, 2));
+        assert_eq!(cursor(&run(sample, "2fe;")), (1, 12));
+        assert_eq!(cursor(&run(sample, "tt")), (1, 3));
+        assert_eq!(cursor(&run(sample, "$Fo,")), (1, 6));
+        assert_eq!(cursor(&run(sample, "}")), (3, 0));
+        assert_eq!(cursor(&run(sample, "G)")), (4, 6));
+        assert_eq!(cursor(&run(sample, "/fi\r")), (2, 7));
+        assert_eq!(cursor(&run(sample, "/e\rnnN")), (1, 11));
+        assert_eq!(cursor(&run(sample, "G?o\r")), (2, 3));
+        assert_eq!(cursor(&run(sample, "7|")), (1, 6));
+    }
+
+    #[test]
+    fn test_vertical_motion_keeps_column() {
+        let editor = run("abcdef\nab\nabcdef\n", "4ljj");
+        assert_eq!(cursor(&editor), (3, 4));
+        let editor = run("abcdef\nab\nabcdef\n", "$jj");
+        assert_eq!(cursor(&editor), (3, 5));
+    }
+
+    #[test]
+    fn test_bracket_matching() {
+        let editor = run("if (a[1] == b) {\n}\n", "%");
+        assert_eq!(cursor(&editor), (1, 13));
+        let editor = run("if (a[1] == b) {\n}\n", "$%");
+        assert_eq!(cursor(&editor), (2, 0));
+    }
+
+    #[test]
+    fn test_delete_operators() {
+        let sample = "one two three\nfour five\nsix\n";
+        assert_eq!(text(&run(sample, "dw")), "two three\nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "d2w")), "three\nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "wD")), "one \nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "dd")), "four five\nsix\n");
+        assert_eq!(text(&run(sample, "2dd")), "six\n");
+        assert_eq!(text(&run(sample, "dj")), "six\n");
+        assert_eq!(text(&run(sample, "jdk")), "six\n");
+        assert_eq!(text(&run(sample, "dG")), "");
+        assert_eq!(text(&run(sample, "3x")), " two three\nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "$X")), "one two thre\nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "dfw")), "o three\nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "wwd$")), "one two \nfour five\nsix\n");
+        assert_eq!(text(&run(sample, "$dw")), "one two thre\nfour five\nsix\n");
+    }
+
+    #[test]
+    fn test_change_operators() {
+        let sample = "one two three\n";
+        assert_eq!(text(&run(sample, "cwONE\x1b")), "ONE two three\n");
+        assert_eq!(text(&run(sample, "c2wx\x1b")), "x three\n");
+        assert_eq!(text(&run(sample, "wCend\x1b")), "one end\n");
+        assert_eq!(text(&run(sample, "ccnew\x1b")), "new\n");
+        assert_eq!(text(&run(sample, "3sX\x1b")), "X two three\n");
+        assert_eq!(text(&run(sample, "Snew\x1b")), "new\n");
+        assert_eq!(text(&run(sample, "wc$\x1b")), "one \n");
+    }
+
+    #[test]
+    fn test_yank_and_put() {
+        let sample = "one\ntwo\nthree\n";
+        assert_eq!(text(&run(sample, "yyp")), "one\none\ntwo\nthree\n");
+        assert_eq!(text(&run(sample, "jyyP")), "one\ntwo\ntwo\nthree\n");
+        assert_eq!(text(&run(sample, "ddp")), "two\none\nthree\n");
+        assert_eq!(text(&run(sample, "yw$p")), "oneone\ntwo\nthree\n");
+        assert_eq!(
+            text(&run(sample, "\"ayyj\"Ayy\"ap")),
+            "one\ntwo\none\ntwo\nthree\n"
+        );
+        assert_eq!(text(&run(sample, "xp")), "noe\ntwo\nthree\n");
+        assert_eq!(text(&run(sample, "3p")), "one\ntwo\nthree\n");
+        assert_eq!(text(&run(sample, "yy2p")), "one\none\none\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn test_numbered_registers() {
+        let sample = "one\ntwo\nthree\n";
+        let editor = run(sample, "dddd\"2p");
+        assert_eq!(text(&editor), "three\none\n");
+        let editor = run("a\nb\nc\nd\n", "dddddd\"1p..");
+        assert_eq!(text(&editor), "d\nc\nb\na\n");
+    }
+
+    #[test]
+    fn test_insert_commands() {
+        let sample = "one two\n";
+        assert_eq!(text(&run(sample, "ix\x1b")), "xone two\n");
+        assert_eq!(text(&run(sample, "ax\x1b")), "oxne two\n");
+        assert_eq!(text(&run(sample, "Ax\x1b")), "one twox\n");
+        assert_eq!(text(&run(sample, "$Ix\x1b")), "xone two\n");
+        assert_eq!(text(&run(sample, "onew\x1b")), "one two\nnew\n");
+        assert_eq!(text(&run(sample, "Onew\x1b")), "new\none two\n");
+        assert_eq!(text(&run(sample, "3ix\x1b")), "xxxone two\n");
+        assert_eq!(text(&run(sample, "2oa\x1b")), "one two\na\na\n");
+        assert_eq!(text(&run(sample, "wi\rx\x1b")), "one \nxtwo\n");
+        assert_eq!(text(&run(sample, "ione\x08\x08\x08x\x1b")), "xone two\n");
+        assert_eq!(text(&run(sample, "Aa b\x17c\x1b")), "one twoa c\n");
+        assert_eq!(text(&run(sample, "RXY\x1b")), "XYe two\n");
+        assert_eq!(text(&run(sample, "RXY\x08\x1b")), "Xne two\n");
+        assert_eq!(text(&run(sample, "i\x16\x01\x1b")), "\x01one two\n");
+    }
+
+    #[test]
+    fn test_autoindent() {
+        let mut editor = Editor::new(Mode::Visual, false);
+        editor.visual.headless = true;
+        editor.options.set("ai").unwrap();
+        editor.options.set("sw=4").unwrap();
+        editor.buffer.load(split_lines(b"    one\n"));
+        editor.visual.typeahead.extend(
+            b"otwo\r\x14three\r\x04four\x1b"
+                .iter()
+                .map(|&c| Key::Byte(c)),
+        );
+        editor.run_visual().unwrap();
+        assert_eq!(text(&editor), "    one\n    two\n\tthree\n    four\n");
+    }
+
+    #[test]
+    fn test_repeat() {
+        let sample = "a b c d e\n";
+        assert_eq!(text(&run(sample, "dw..")), "d e\n");
+        assert_eq!(text(&run(sample, "dw2.")), "d e\n");
+        assert_eq!(text(&run(sample, "ix\x1bw.")), "xa xb c d e\n");
+        assert_eq!(text(&run("x\ny\nz\n", "A!\x1bj.j.")), "x!\ny!\nz!\n");
+    }
+
+    #[test]
+    fn test_undo() {
+        let sample = "one\ntwo\n";
+        assert_eq!(text(&run(sample, "ddu")), "one\ntwo\n");
+        assert_eq!(text(&run(sample, "dduu")), "two\n");
+        assert_eq!(text(&run(sample, "xxxU")), "one\ntwo\n");
+        assert_eq!(text(&run(sample, "ixy\x1bu")), "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_join_replace_case() {
+        assert_eq!(text(&run("a\n  b\nc\n", "J")), "a b\nc\n");
+        assert_eq!(text(&run("a\n  b\nc\n", "3J")), "a b c\n");
+        assert_eq!(text(&run("abc\n", "2rx")), "xxc\n");
+        assert_eq!(text(&run("abc\n", "lr\r")), "a\nc\n");
+        assert_eq!(text(&run("abc\n", "2~")), "ABc\n");
+        assert_eq!(text(&run("aBc\n", "~~~")), "AbC\n");
+    }
+
+    #[test]
+    fn test_shift_and_filter() {
+        let mut editor = Editor::new(Mode::Visual, false);
+        editor.visual.headless = true;
+        editor.options.set("sw=2").unwrap();
+        editor.buffer.load(split_lines(b"a\nb\nc\n"));
+        editor
+            .visual
+            .typeahead
+            .extend(b">jj.".iter().map(|&c| Key::Byte(c)));
+        editor.run_visual().unwrap();
+        assert_eq!(text(&editor), "  a\n    b\n  c\n");
+        assert_eq!(text(&run("c\nb\na\n", "!Gsort\r")), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_marks() {
+        let sample = "one\ntwo\nthree\n";
+        assert_eq!(cursor(&run(sample, "jlmaG`a")), (2, 1));
+        assert_eq!(cursor(&run(sample, "jlmaG'a")), (2, 0));
+        assert_eq!(text(&run(sample, "mjGd'j")), "");
+        assert_eq!(cursor(&run(sample, "G''")), (1, 0));
+    }
+
+    #[test]
+    fn test_ex_commands() {
+        let editor = run("one\ntwo\n", ":%s/o/0/g\r");
+        assert_eq!(text(&editor), "0ne\ntw0\n");
+        let editor = run("one\ntwo\n", ":2\r");
+        assert_eq!(cursor(&editor), (2, 0));
+        let editor = run("one\ntwo\n", ":q!\r");
+        assert!(editor.quit);
+        let editor = run("one\ntwo\n", "Q");
+        assert!(editor.mode == Mode::Ex);
+    }
+
+    #[test]
+    fn test_maps_and_abbreviations() {
+        let mut editor = Editor::new(Mode::Visual, false);
+        editor.visual.headless = true;
+        editor.buffer.load(split_lines(b"one two\n"));
+        editor.execute(b"map q dw").unwrap();
+        editor.execute(b"ab tt TEST").unwrap();
+        editor
+            .visual
+            .typeahead
+            .extend(b"qitt \x1b".iter().map(|&c| Key::Byte(c)));
+        editor.run_visual().unwrap();
+        assert_eq!(text(&editor), "TEST two\n");
+    }
+}
@@ -7,494 +7,841 @@
 // SPDX-License-Identifier: MIT
 //
 
-use gettextrs::{bind_textdomain_codeset, textdomain};
-use plib::PROJECT_NAME;
-use regex::Regex;
-use std::collections::HashSet;
-use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
-use std::path::PathBuf;
+use ftw::{self, traverse_directory};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::{fnmatch::fnmatch, io::error_string, modestr, PROJECT_NAME};
+use std::cell::RefCell;
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{self, BufRead, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, fs};
-use walkdir::{DirEntry, WalkDir};
 
-#[derive(Clone)]
+/// Seconds in a day, the unit of `-atime`, `-ctime` and `-mtime`.
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Space reserved below `ARG_MAX` when batching `-exec ... {} +` arguments.
+const ARG_MAX_SLACK: usize = 4096;
+
+/// How symbolic links are treated, selected with `-H` or `-L`.
+#[derive(Clone, Copy, PartialEq)]
+enum Follow {
+    Never,
+    CommandLine,
+    Always,
+}
+
+/// Numeric primary argument: `+n` (more than n), `-n` (less than n) or
+/// `n` (exactly n).
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Numeric {
+    Less(u64),
+    Exactly(u64),
+    Greater(u64),
+}
+
+impl Numeric {
+    fn parse(s: &str) -> Option<Numeric> {
+        let (ctor, digits): (fn(u64) -> Numeric, &str) = if let Some(n) = s.strip_prefix('+') {
+            (Numeric::Greater, n)
+        } else if let Some(n) = s.strip_prefix('-') {
+            (Numeric::Less, n)
+        } else {
+            (Numeric::Exactly, s)
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok().map(ctor)
+    }
+
+    fn matches(&self, value: u64) -> bool {
+        match *self {
+            Numeric::Less(n) => value < n,
+            Numeric::Exactly(n) => value == n,
+            Numeric::Greater(n) => value > n,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeField {
+    Access,
+    StatusChange,
+    Modification,
+}
+
+/// State of an `-exec`/`-ok` primary.
+#[derive(Debug)]
+struct Exec {
+    /// Utility name and arguments, with `{}` still unexpanded.
+    argv: Vec<OsString>,
+    /// Prompt before running (`-ok`).
+    prompt: bool,
+    /// For `-exec ... {} +`, the pathnames waiting for the next invocation
+    /// and the number of bytes they will occupy in the argument list.
+    batch: Option<(Vec<OsString>, usize)>,
+}
+
+#[derive(Debug)]
 enum Expr {
-    And(Box<Expr>),
-    Or(Box<Expr>),
     Not(Box<Expr>),
-    Name(String),
-    MTime(i64),
-    Path(String),
-    Type(FileType),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Name(CString),
+    Path(CString),
     NoUser,
     NoGroup,
-    XDev,
+    /// `-xdev` and `-depth` only change how the tree is walked and always
+    /// evaluate to true.
+    True,
     Prune,
-    Perm(u32),
-    Links(u64),
-    User(String),
-    Group(String),
-    Size(u64, bool),
+    Perm {
+        mode: u32,
+        at_least: bool,
+    },
+    Type(ftw::FileType),
+    Links(Numeric),
+    User(Option<u32>),
+    Group(Option<u32>),
+    Size(Numeric, bool),
+    Time(TimeField, Numeric),
+    Newer(i64, i64),
+    Exec(Exec),
     Print,
-    Newer(PathBuf),
 }
 
-#[derive(Clone)]
-enum FileType {
-    BlockDevice,
-    CharDevice,
-    Dir,
-    Symlink,
-    Fifo,
-    File,
-    Socket,
-    Unknown,
+/// Options set by the primaries that affect the traversal as a whole.
+#[derive(Default)]
+struct GlobalOpts {
+    depth_first: bool,
+    xdev: bool,
 }
 
-/// Parses a list of tokens representing search criteria, the result is stored in enum Expr
-///
-/// # Arguments
-///
-/// * `tokens` - A vector of string slices representing the tokens to parse.
-///
-/// # Returns
+/// Recursive descent parser for the expression operands.
 ///
-/// * A vector of `Expr` expressions parsed from the tokens.
-fn parse_expression(tokens: &mut Vec<&str>) -> Vec<Expr> {
-    let mut stack: Vec<Expr> = Vec::new();
-
-    while let Some(&token) = tokens.last() {
-        match token {
-            "-name" => {
-                tokens.pop();
-                if let Some(name) = tokens.pop() {
-                    stack.push(Expr::Name(name.to_string()));
-                }
+/// ```text
+/// or_expr   := and_expr [ -o and_expr ]...
+/// and_expr  := not_expr [ [-a] not_expr ]...
+/// not_expr  := ! not_expr | primary
+/// primary   := ( or_expr ) | -name pattern | ...
+/// ```
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    has_action: bool,
+    opts: GlobalOpts,
+    follow: Follow,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn next_arg(&mut self, primary: &str) -> Result<&'a str, String> {
+        match self.tokens.get(self.pos) {
+            Some(arg) => {
+                self.pos += 1;
+                Ok(arg)
             }
-            "-path" => {
-                tokens.pop();
-                if let Some(path) = tokens.pop() {
-                    stack.push(Expr::Path(path.to_string()));
-                }
+            None => Err(gettext!("missing argument to `{}'", primary)),
+        }
+    }
+
+    fn next_numeric(&mut self, primary: &str) -> Result<Numeric, String> {
+        let arg = self.next_arg(primary)?;
+        Numeric::parse(arg).ok_or_else(|| gettext!("invalid argument `{}' to `{}'", arg, primary))
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and_expr()?;
+        while self.peek() == Some("-o") {
+            self.pos += 1;
+            let rhs = self.and_expr()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.not_expr()?;
+        loop {
+            match self.peek() {
+                None | Some("-o") | Some(")") => break,
+                Some("-a") => self.pos += 1,
+                Some(_) => {}
             }
-            "-mtime" => {
-                tokens.pop();
-                if let Some(mtime) = tokens.pop() {
-                    if let Ok(mtime) = mtime.parse::<i64>() {
-                        stack.push(Expr::MTime(mtime));
-                    }
+            let rhs = self.not_expr()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some("!") {
+            self.pos += 1;
+            Ok(Expr::Not(Box::new(self.not_expr()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.peek() else {
+            return Err(gettext("expected an expression"));
+        };
+        self.pos += 1;
+
+        let expr = match token {
+            "(" => {
+                if self.peek() == Some(")") {
+                    return Err(gettext("empty parentheses are not allowed"));
                 }
-            }
-            "-type" => {
-                tokens.pop();
-                if let Some(t) = tokens.pop() {
-                    if t.len() == 1 {
-                        let filetype = match t {
-                            "b" => FileType::BlockDevice,
-                            "c" => FileType::CharDevice,
-                            "d" => FileType::Dir,
-                            "l" => FileType::Symlink,
-                            "p" => FileType::Fifo,
-                            "f" => FileType::File,
-                            "s" => FileType::Socket,
-                            _ => FileType::Unknown,
-                        };
-                        stack.push(Expr::Type(filetype));
-                    }
+                let expr = self.or_expr()?;
+                if self.peek() != Some(")") {
+                    return Err(gettext("missing `)'"));
                 }
+                self.pos += 1;
+                expr
             }
-            "-nouser" => {
-                tokens.pop();
-                stack.push(Expr::NoUser);
-            }
-            "-nogroup" => {
-                tokens.pop();
-                stack.push(Expr::NoGroup);
+            ")" => return Err(gettext("unexpected `)'")),
+            "-o" | "-a" => {
+                return Err(gettext!(
+                    "invalid expression; binary operator `{}' has no left operand",
+                    token
+                ))
             }
+            "-name" => Expr::Name(pattern_cstring(self.next_arg(token)?)?),
+            "-path" => Expr::Path(pattern_cstring(self.next_arg(token)?)?),
+            "-nouser" => Expr::NoUser,
+            "-nogroup" => Expr::NoGroup,
             "-xdev" => {
-                tokens.pop();
-                stack.push(Expr::XDev);
+                self.opts.xdev = true;
+                Expr::True
             }
-            "-prune" => {
-                tokens.pop();
-                stack.push(Expr::Prune);
+            "-depth" => {
+                self.opts.depth_first = true;
+                Expr::True
             }
+            "-prune" => Expr::Prune,
             "-perm" => {
-                tokens.pop();
-                if let Some(perm) = tokens.pop() {
-                    if let Ok(perm) = u32::from_str_radix(perm, 8) {
-                        stack.push(Expr::Perm(perm));
-                    }
-                }
+                let arg = self.next_arg(token)?;
+                parse_perm(arg).ok_or_else(|| gettext!("invalid mode `{}'", arg))?
             }
-            "-links" => {
-                tokens.pop();
-                if let Some(links) = tokens.pop() {
-                    if let Ok(links) = links.parse::<u64>() {
-                        stack.push(Expr::Links(links));
-                    }
-                }
+            "-type" => {
+                let arg = self.next_arg(token)?;
+                let file_type = match arg {
+                    "b" => ftw::FileType::BlockDevice,
+                    "c" => ftw::FileType::CharacterDevice,
+                    "d" => ftw::FileType::Directory,
+                    "l" => ftw::FileType::SymbolicLink,
+                    "p" => ftw::FileType::Fifo,
+                    "f" => ftw::FileType::RegularFile,
+                    "s" => ftw::FileType::Socket,
+                    _ => return Err(gettext!("unknown argument to -type: {}", arg)),
+                };
+                Expr::Type(file_type)
             }
+            "-links" => Expr::Links(self.next_numeric(token)?),
             "-user" => {
-                tokens.pop();
-                if let Some(user) = tokens.pop() {
-                    stack.push(Expr::User(user.to_string()));
-                }
+                let arg = self.next_arg(token)?;
+                let uid = users::get_user_by_name(arg)
+                    .map(|u| u.uid())
+                    .or_else(|| arg.parse().ok());
+                Expr::User(uid)
             }
             "-group" => {
-                tokens.pop();
-                if let Some(group) = tokens.pop() {
-                    stack.push(Expr::Group(group.to_string()));
-                }
+                let arg = self.next_arg(token)?;
+                let gid = users::get_group_by_name(arg)
+                    .map(|g| g.gid())
+                    .or_else(|| arg.parse().ok());
+                Expr::Group(gid)
             }
             "-size" => {
-                tokens.pop();
-                if let Some(size) = tokens.pop() {
-                    let (size, in_bytes) = if let Some(st) = size.strip_suffix('c') {
-                        (st.parse::<u64>().unwrap_or(0), true)
-                    } else {
-                        (size.parse::<u64>().unwrap_or(0), false)
-                    };
-                    stack.push(Expr::Size(size, in_bytes));
-                }
+                let arg = self.next_arg(token)?;
+                let (arg, in_bytes) = match arg.strip_suffix('c') {
+                    Some(n) => (n, true),
+                    None => (arg, false),
+                };
+                let n = Numeric::parse(arg)
+                    .ok_or_else(|| gettext!("invalid argument `{}' to `{}'", arg, token))?;
+                Expr::Size(n, in_bytes)
             }
+            "-atime" => Expr::Time(TimeField::Access, self.next_numeric(token)?),
+            "-ctime" => Expr::Time(TimeField::StatusChange, self.next_numeric(token)?),
+            "-mtime" => Expr::Time(TimeField::Modification, self.next_numeric(token)?),
             "-newer" => {
-                tokens.pop();
-                if let Some(file) = tokens.pop() {
-                    stack.push(Expr::Newer(PathBuf::from(file)));
-                }
+                let file = self.next_arg(token)?;
+                let metadata = if self.follow == Follow::Never {
+                    fs::symlink_metadata(file)
+                } else {
+                    fs::metadata(file)
+                };
+                let metadata = metadata.map_err(|e| format!("{}: {}", file, error_string(&e)))?;
+                Expr::Newer(metadata.mtime(), metadata.mtime_nsec())
             }
-            "-print" => {
-                tokens.pop();
-                stack.push(Expr::Print);
-            }
-            "-a" => {
-                tokens.pop();
-                let expr = parse_expression(tokens);
-                stack.push(Expr::And(Box::new(expr[0].clone())));
+            "-exec" | "-ok" => {
+                self.has_action = true;
+                Expr::Exec(self.exec(token)?)
             }
-            "-o" => {
-                tokens.pop();
-                let expr = parse_expression(tokens);
-                stack.push(Expr::Or(Box::new(expr[0].clone())));
+            "-print" => {
+                self.has_action = true;
+                Expr::Print
             }
-            "!" => {
-                tokens.pop();
-                let expr = parse_expression(tokens);
-                stack.push(Expr::Not(Box::new(expr[0].clone())));
+            _ if token.starts_with('-') => return Err(gettext!("unknown predicate `{}'", token)),
+            _ => return Err(gettext!("paths must precede expression: `{}'", token)),
+        };
+        Ok(expr)
+    }
+
+    /// Parses the operands of `-exec` and `-ok` up to the terminating `;`,
+    /// or, for `-exec`, a `{}` immediately followed by `+`.
+    fn exec(&mut self, primary: &str) -> Result<Exec, String> {
+        let start = self.pos;
+        loop {
+            let Some(token) = self.peek() else {
+                return Err(gettext!("missing argument to `{}'", primary));
+            };
+            self.pos += 1;
+            if token == ";" {
+                break;
             }
-            _ => {
-                tokens.pop();
-                stack.push(Expr::Path(
-                    PathBuf::from(token).to_string_lossy().to_string(),
-                ));
+            if token == "+"
+                && primary == "-exec"
+                && self.pos - start >= 3
+                && self.tokens[self.pos - 2] == "{}"
+            {
+                let argv = self.tokens[start..self.pos - 2]
+                    .iter()
+                    .map(OsString::from)
+                    .collect();
+                return Ok(Exec {
+                    argv,
+                    prompt: false,
+                    batch: Some((Vec::new(), 0)),
+                });
             }
         }
+        let argv: Vec<OsString> = self.tokens[start..self.pos - 1]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        if argv.is_empty() {
+            return Err(gettext!("missing argument to `{}'", primary));
+        }
+        Ok(Exec {
+            argv,
+            prompt: primary == "-ok",
+            batch: None,
+        })
     }
+}
 
-    stack
+fn pattern_cstring(pattern: &str) -> Result<CString, String> {
+    CString::new(pattern).map_err(|_| gettext!("invalid pattern `{}'", pattern))
 }
 
-/// Converts a shell pattern to a regular expression.
-///
-/// # Arguments
-///
-/// * `pattern` - A string slice representing the pattern to convert.
-///
-/// # Returns
-///
-/// * A `Regex` object representing the converted pattern.
-fn pattern_to_regex(pattern: &str) -> Regex {
-    let mut regex_pattern = pattern.replace('?', ".");
-    regex_pattern = regex_pattern.replace('*', ".*");
-
-    let bracket_regex = Regex::new(r"\[(?:[^\]]+)\]").unwrap();
-    regex_pattern = bracket_regex
-        .replace_all(&regex_pattern, |caps: &regex::Captures| {
-            let bracket_content = &caps[0][1..caps[0].len() - 1];
-            format!("[{}]", bracket_content)
-        })
-        .to_string();
+/// Parses the `-perm [-]mode` argument, where `mode` is either octal or a
+/// symbolic mode applied to an initial mode of 000.
+fn parse_perm(arg: &str) -> Option<Expr> {
+    let (mode, at_least) = match arg.strip_prefix('-') {
+        Some(mode) => (mode, true),
+        None => (arg, false),
+    };
+    let mode = match modestr::parse(mode).ok()? {
+        modestr::ChmodMode::Absolute(mode) => mode,
+        modestr::ChmodMode::Symbolic(sym) => modestr::mutate(0, &sym),
+    };
+    Some(Expr::Perm {
+        mode: mode & 0o7777,
+        at_least,
+    })
+}
 
-    Regex::new(&format!("^{}$", regex_pattern)).unwrap()
+/// Matches `s` against a shell pattern; `*`, `?` and `[` also match `/` and
+/// a leading `.`, as required for `-name` and `-path`.
+fn pattern_matches(pattern: &CString, s: &[u8]) -> bool {
+    fnmatch(pattern, s, 0)
 }
 
-/// Executes a command based on the list of expressions and returns the matching file paths.
-///
-/// # Arguments
-///
-/// * `expr` - A slice of `Expr` expressions to execute.
-/// * `files` - A vector of `DirEntry` objects representing the files to be evaluated.
-/// * `root_dev` - A u64 value representing the root device number for `-xdev` expression.
-///
-/// # Returns
-///
-/// * A `Result` containing a vector of `PathBuf` objects representing the paths of the files that match the expressions,
-///   or an error message as a `String`.
-fn evaluate_expression(
-    expr: &[Expr],
-    files: Vec<DirEntry>,
-    root_dev: u64,
-) -> Result<Vec<PathBuf>, String> {
-    let f_path = &expr[0];
-    let mut not_res: Vec<PathBuf> = Vec::new();
-    let mut or_res: Vec<PathBuf> = Vec::new();
-    let mut and_res: Vec<PathBuf> = Vec::new();
-    let mut c_files = files
-        .clone()
-        .into_iter()
-        .map(|f| f.path().to_path_buf())
-        .collect::<HashSet<PathBuf>>();
-    let mut result = Vec::new();
-    let mut first = true;
-    for expression in expr {
-        match expression {
-            Expr::Not(inner) => {
-                let i: Vec<Expr> = vec![f_path.clone(), *inner.clone()];
-                not_res = evaluate_expression(i.as_slice(), files.clone(), root_dev)?;
+/// Returns the last pathname component, which `-name` matches against.
+/// Unlike `entry.file_name()` this is also right for the operand itself.
+fn basename(path: &[u8]) -> &[u8] {
+    let trimmed = match path.iter().rposition(|&b| b != b'/') {
+        Some(end) => &path[..=end],
+        None => return if path.is_empty() { path } else { b"/" },
+    };
+    match trimmed.iter().rposition(|&b| b == b'/') {
+        Some(pos) => &trimmed[pos + 1..],
+        None => trimmed,
+    }
+}
+
+/// A file being evaluated.
+struct File<'a> {
+    path: &'a OsStr,
+    name: &'a [u8],
+    metadata: &'a ftw::Metadata,
+}
+
+/// Per-run evaluation state.
+struct Context {
+    /// Time of invocation, for `-atime`, `-ctime` and `-mtime`.
+    now: i64,
+    /// Largest argument list for `-exec ... {} +`.
+    arg_max: usize,
+    /// Set by `-prune` for the file being evaluated.
+    prune: bool,
+    /// Set if an error occurred that must be reflected in the exit status.
+    failed: bool,
+}
+
+fn run_command(argv: &[OsString], ctx: &mut Context) -> bool {
+    let _ = io::stdout().flush();
+    match Command::new(&argv[0]).args(&argv[1..]).status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("find: {}: {}", argv[0].to_string_lossy(), error_string(&e));
+            ctx.failed = true;
+            false
+        }
+    }
+}
+
+/// Asks the user to confirm an `-ok` invocation.
+fn confirm(argv: &[OsString]) -> bool {
+    let words: Vec<_> = argv.iter().map(|a| a.to_string_lossy()).collect();
+    eprint!("< {} >? ", words.join(" "));
+    let _ = io::stderr().flush();
+
+    let mut response = String::new();
+    match io::stdin().lock().read_line(&mut response) {
+        Ok(n) if n > 0 => response.starts_with(['y', 'Y']),
+        _ => false,
+    }
+}
+
+/// Replaces every `{}` in `arg` with `path`.
+fn substitute(arg: &OsStr, path: &OsStr) -> OsString {
+    let arg = arg.as_bytes();
+    let mut out = Vec::with_capacity(arg.len());
+    let mut i = 0;
+    while i < arg.len() {
+        if arg[i..].starts_with(b"{}") {
+            out.extend_from_slice(path.as_bytes());
+            i += 2;
+        } else {
+            out.push(arg[i]);
+            i += 1;
+        }
+    }
+    OsString::from_vec(out)
+}
+
+/// Size of one argument in the argument list: the string, its terminator
+/// and the pointer to it.
+fn arg_size(arg: &OsStr) -> usize {
+    arg.len() + 1 + std::mem::size_of::<*const libc::c_char>()
+}
+
+fn arg_max() -> usize {
+    let limit = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    let limit = if limit > 0 {
+        limit as usize
+    } else {
+        128 * 1024
+    };
+    let env_size: usize = env::vars_os()
+        .map(|(k, v)| k.len() + v.len() + 2 + std::mem::size_of::<*const libc::c_char>())
+        .sum();
+    limit.saturating_sub(env_size + ARG_MAX_SLACK).max(4096)
+}
+
+/// Runs a pending `-exec ... {} +` batch, if any.
+fn flush_batch(exec: &mut Exec, ctx: &mut Context) {
+    let Some((paths, size)) = exec.batch.as_mut() else {
+        return;
+    };
+    if paths.is_empty() {
+        return;
+    }
+    let mut argv = exec.argv.clone();
+    argv.append(paths);
+    *size = 0;
+    if !run_command(&argv, ctx) {
+        ctx.failed = true;
+    }
+}
+
+fn flush_all(expr: &mut Expr, ctx: &mut Context) {
+    match expr {
+        Expr::Not(e) => flush_all(e, ctx),
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            flush_all(a, ctx);
+            flush_all(b, ctx);
+        }
+        Expr::Exec(exec) => flush_batch(exec, ctx),
+        _ => {}
+    }
+}
+
+fn eval_exec(exec: &mut Exec, file: &File, ctx: &mut Context) -> bool {
+    if exec.batch.is_some() {
+        let fixed: usize = exec.argv.iter().map(|a| arg_size(a)).sum();
+        let needed = arg_size(file.path);
+        let (_, size) = exec.batch.as_ref().unwrap();
+        if fixed + size + needed > ctx.arg_max {
+            flush_batch(exec, ctx);
+        }
+        let (paths, size) = exec.batch.as_mut().unwrap();
+        paths.push(file.path.to_os_string());
+        *size += needed;
+        return true;
+    }
+
+    let argv: Vec<OsString> = exec
+        .argv
+        .iter()
+        .map(|arg| substitute(arg, file.path))
+        .collect();
+    if exec.prompt && !confirm(&argv) {
+        return false;
+    }
+    run_command(&argv, ctx)
+}
+
+/// Evaluates `expr` for `file`, running any side effects (`-print`,
+/// `-exec`, ...) as the operators require.
+fn eval(expr: &mut Expr, file: &File, ctx: &mut Context) -> bool {
+    let md = file.metadata;
+    match expr {
+        Expr::Not(e) => !eval(e, file, ctx),
+        Expr::And(a, b) => eval(a, file, ctx) && eval(b, file, ctx),
+        Expr::Or(a, b) => eval(a, file, ctx) || eval(b, file, ctx),
+        Expr::Name(pattern) => pattern_matches(pattern, file.name),
+        Expr::Path(pattern) => pattern_matches(pattern, file.path.as_bytes()),
+        Expr::NoUser => users::get_user_by_uid(md.uid()).is_none(),
+        Expr::NoGroup => users::get_group_by_gid(md.gid()).is_none(),
+        Expr::True => true,
+        Expr::Prune => {
+            ctx.prune = true;
+            true
+        }
+        Expr::Perm { mode, at_least } => {
+            let file_mode = md.mode() & 0o7777;
+            if *at_least {
+                file_mode & *mode == *mode
+            } else {
+                file_mode == *mode
             }
-            Expr::Or(inner) => {
-                let i: Vec<Expr> = vec![f_path.clone(), *inner.clone()];
-                or_res = evaluate_expression(i.as_slice(), files.clone(), root_dev)?;
+        }
+        Expr::Type(file_type) => md.file_type() == *file_type,
+        Expr::Links(n) => n.matches(md.nlink()),
+        Expr::User(uid) => *uid == Some(md.uid()),
+        Expr::Group(gid) => *gid == Some(md.gid()),
+        Expr::Size(n, in_bytes) => {
+            let size = if *in_bytes {
+                md.size()
+            } else {
+                md.size().div_ceil(512)
+            };
+            n.matches(size)
+        }
+        Expr::Time(field, n) => {
+            let time = match field {
+                TimeField::Access => md.atime(),
+                TimeField::StatusChange => md.ctime(),
+                TimeField::Modification => md.mtime(),
+            };
+            let days = (ctx.now - time).div_euclid(SECONDS_PER_DAY);
+            days >= 0 && n.matches(days as u64)
+        }
+        Expr::Newer(sec, nsec) => (md.mtime(), md.mtime_nsec()) > (*sec, *nsec),
+        Expr::Exec(exec) => eval_exec(exec, file, ctx),
+        Expr::Print => {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(file.path.as_bytes());
+            let _ = stdout.write_all(b"\n");
+            true
+        }
+    }
+}
+
+struct Finder {
+    expr: Expr,
+    opts: GlobalOpts,
+    follow: Follow,
+    ctx: Context,
+}
+
+/// Per-operand traversal state.
+struct Walk<'a> {
+    operand: &'a str,
+    root_components: usize,
+    root_dev: Option<u64>,
+    /// Device and inode of the directories above the current entry, for
+    /// detecting loops when following symbolic links.
+    ancestors: Vec<(u64, u64, String)>,
+}
+
+impl Walk<'_> {
+    /// Number of levels below the operand; the operand itself is at 0.
+    fn depth(&self, path: &Path) -> usize {
+        path.components()
+            .count()
+            .saturating_sub(self.root_components)
+    }
+}
+
+impl Finder {
+    fn evaluate(&mut self, path: &OsStr, md: &ftw::Metadata) -> bool {
+        let file = File {
+            path,
+            name: basename(path.as_bytes()),
+            metadata: md,
+        };
+        self.ctx.prune = false;
+        eval(&mut self.expr, &file, &mut self.ctx);
+        self.ctx.prune
+    }
+
+    /// Processes an entry before its contents; returns whether to descend.
+    fn pre_visit(&mut self, walk: &mut Walk, entry: &ftw::Entry) -> bool {
+        let Some(md) = entry.metadata() else {
+            return false;
+        };
+        let entry_path = entry.path();
+        let depth = walk.depth(&entry_path);
+        let path = if depth == 0 {
+            OsStr::new(walk.operand)
+        } else {
+            entry_path.as_os_str()
+        };
+
+        if depth == 0 {
+            walk.root_dev = Some(md.dev());
+        }
+
+        let mut descend = md.is_dir();
+        if descend {
+            walk.ancestors.truncate(depth);
+            if let Some((_, _, ancestor)) = walk
+                .ancestors
+                .iter()
+                .find(|(dev, ino, _)| *dev == md.dev() && *ino == md.ino())
+            {
+                eprintln!(
+                    "find: {}",
+                    gettext!(
+                        "file system loop detected; `{}' is part of the same file system loop as `{}'",
+                        path.to_string_lossy(),
+                        ancestor
+                    )
+                );
+                self.ctx.failed = true;
+                descend = false;
             }
-            Expr::And(inner) => {
-                let i: Vec<Expr> = vec![f_path.clone(), *inner.clone()];
-                and_res = evaluate_expression(i.as_slice(), files.clone(), root_dev)?;
+            if self.opts.xdev && walk.root_dev != Some(md.dev()) {
+                descend = false;
             }
-            _ => {}
         }
-        for file in &files {
-            match expression {
-                Expr::And(_) => {
-                    continue;
-                }
-                Expr::Or(_) => {
-                    continue;
-                }
-                Expr::Not(_) => {
-                    continue;
-                }
-                Expr::Name(name) => {
-                    let regex = pattern_to_regex(name);
-                    if !regex.is_match(&file.file_name().to_string_lossy()) {
-                        c_files.remove(file.path());
-                    }
-                }
-                Expr::Path(path) => {
-                    let regex = pattern_to_regex(path);
 
-                    if !regex.is_match(&file.path().to_string_lossy()) && !first {
-                        c_files.remove(file.path());
-                    }
-                }
-                Expr::MTime(days) => {
-                    if let Ok(metadata) = file.metadata() {
-                        let modified = metadata.modified().unwrap();
-                        let duration = std::time::SystemTime::now()
-                            .duration_since(modified)
-                            .unwrap();
-                        if ((duration.as_secs() / 86400) as i64) < (*days) {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::Type(t) => {
-                    let file_type = file.file_type();
-                    let r = match *t {
-                        FileType::BlockDevice => file_type.is_block_device(),
-                        FileType::CharDevice => file_type.is_char_device(),
-                        FileType::Dir => file_type.is_dir(),
-                        FileType::Symlink => file_type.is_symlink(),
-                        FileType::Fifo => file_type.is_fifo(),
-                        FileType::File => file_type.is_file(),
-                        FileType::Socket => file_type.is_socket(),
-                        FileType::Unknown => return Err("Unknown argument to -type".to_string()),
-                    };
-                    if !r {
-                        c_files.remove(file.path());
-                    }
-                }
-                Expr::NoUser => {
-                    if let Ok(metadata) = file.metadata() {
-                        let uid = metadata.uid();
-                        if users::get_user_by_uid(uid).is_some() {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::NoGroup => {
-                    if let Ok(metadata) = file.metadata() {
-                        let gid = metadata.gid();
-                        if users::get_group_by_gid(gid).is_some() {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::XDev => {
-                    if let Ok(metadata) = file.metadata() {
-                        if metadata.dev() != root_dev {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::Prune => {}
-                Expr::Perm(perm) => {
-                    if let Ok(metadata) = file.metadata() {
-                        if metadata.permissions().mode() & 0o777 != *perm {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::Links(links) => {
-                    if let Ok(metadata) = file.metadata() {
-                        if metadata.nlink() != *links {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::User(user) => {
-                    if let Ok(metadata) = file.metadata() {
-                        let uid = metadata.uid();
-                        if let Ok(parsed_uid) = user.parse::<u32>() {
-                            if uid != parsed_uid {
-                                c_files.remove(file.path());
-                            }
-                        }
-                    }
-                }
-                Expr::Group(group) => {
-                    if let Ok(metadata) = file.metadata() {
-                        let gid = metadata.gid();
-                        if let Ok(parsed_gid) = group.parse::<u32>() {
-                            if gid != parsed_gid {
-                                c_files.remove(file.path());
-                            }
-                        } else {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::Size(size, in_bytes) => {
-                    if let Ok(metadata) = file.metadata() {
-                        let file_size = if *in_bytes {
-                            metadata.len()
-                        } else {
-                            (metadata.len() + 511) / 512
-                        };
-                        if file_size < *size {
-                            c_files.remove(file.path());
-                        }
-                    }
-                }
-                Expr::Newer(f) => {
-                    if let Ok(metadata) = fs::metadata(f) {
-                        if let Ok(file_metadata) = file.metadata() {
-                            if file_metadata.modified().unwrap() <= metadata.modified().unwrap() {
-                                c_files.remove(file.path());
-                            }
-                        }
-                    }
-                }
-                Expr::Print if c_files.contains(file.path()) => {
-                    result.push(file.path().to_path_buf());
-                }
-                Expr::Print if !c_files.contains(file.path()) => {
-                    continue;
-                }
-                _ => return Err("Error: Invalid expression".to_string()),
+        // A directory that cannot be searched gets no postorder visit (ftw
+        // reports the error instead), so it is evaluated now even with -depth.
+        let postorder = descend && md.is_executable();
+        if !self.opts.depth_first || !postorder {
+            let prune = self.evaluate(path, md);
+            if prune && !self.opts.depth_first {
+                descend = false;
             }
         }
-        first = false;
+
+        if descend {
+            walk.ancestors
+                .push((md.dev(), md.ino(), path.to_string_lossy().into_owned()));
+        }
+        descend
     }
 
-    if result.is_empty() {
-        result.extend(c_files.clone());
+    /// Processes a directory after its contents (for `-depth`).
+    fn post_visit(&mut self, walk: &mut Walk, entry: &ftw::Entry) {
+        if !self.opts.depth_first {
+            return;
+        }
+        let Some(md) = entry.metadata() else {
+            return;
+        };
+        let entry_path = entry.path();
+        let depth = walk.depth(&entry_path);
+        let path = if depth == 0 {
+            OsStr::new(walk.operand)
+        } else {
+            entry_path.as_os_str()
+        };
+        self.evaluate(path, md);
     }
 
-    let set: std::collections::HashSet<_> = not_res.iter().cloned().collect();
-    result.retain(|x| !set.contains(x));
+    fn walk(&mut self, operand: &str) {
+        let walk = RefCell::new(Walk {
+            operand,
+            root_components: Path::new(operand).components().count(),
+            root_dev: None,
+            ancestors: Vec::new(),
+        });
+        let finder = RefCell::new(self);
 
-    result.extend(or_res);
+        let opts = ftw::TraverseDirectoryOpts {
+            follow_symlinks_on_args: finder.borrow().follow == Follow::CommandLine,
+            follow_symlinks: finder.borrow().follow == Follow::Always,
+            ..Default::default()
+        };
 
-    let and_set: std::collections::HashSet<_> = and_res.iter().cloned().collect();
-    if !and_set.is_empty() {
-        result.retain(|x| and_set.contains(x));
+        traverse_directory(
+            operand,
+            |entry| {
+                let descend = finder
+                    .borrow_mut()
+                    .pre_visit(&mut walk.borrow_mut(), &entry);
+                Ok(descend)
+            },
+            |entry| {
+                finder
+                    .borrow_mut()
+                    .post_visit(&mut walk.borrow_mut(), &entry);
+                Ok(())
+            },
+            |entry, error| {
+                let path = entry.path();
+                let path = if walk.borrow().depth(&path) == 0 {
+                    operand.to_string()
+                } else {
+                    path.to_string_lossy().into_owned()
+                };
+                eprintln!("find: {}: {}", path, error_string(&error.inner()));
+                finder.borrow_mut().ctx.failed = true;
+            },
+            opts,
+        );
     }
-
-    result.sort();
-    Ok(result)
 }
 
-/// Retrieves the root path from a list of expressions.
-///
-/// # Arguments
-///
-/// * `expr` - A slice of `Expr` expressions.
-///
-/// # Returns
-///
-/// * A `String` representing the root path extracted from the expressions.
-fn get_root(expr: &[Expr]) -> String {
-    let mut first = true;
+/// Splits the arguments into the `-H`/`-L` options, the path operands and
+/// the expression.
+fn parse_args(args: &[String]) -> Result<(Follow, Vec<String>, &[String]), String> {
+    let mut follow = Follow::Never;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if arg.len() < 2 || !arg.starts_with('-') || !arg[1..].chars().all(|c| "HL".contains(c)) {
+            break;
+        }
+        for c in arg[1..].chars() {
+            follow = if c == 'H' {
+                Follow::CommandLine
+            } else {
+                Follow::Always
+            };
+        }
+        i += 1;
+    }
 
-    let path = expr
-        .iter()
-        .find_map(|i| match i {
-            Expr::Path(p) if first => {
-                first = false;
-                Some(p.to_string())
-            }
-            _ => None,
-        })
-        .unwrap_or_else(String::new);
+    let mut paths = Vec::new();
+    while i < args.len() {
+        let arg = &args[i];
+        if (arg.starts_with('-') && arg.len() > 1) || arg == "!" || arg == "(" {
+            break;
+        }
+        paths.push(arg.clone());
+        i += 1;
+    }
+    if paths.is_empty() {
+        paths.push(String::from("."));
+    }
 
-    path
+    Ok((follow, paths, &args[i..]))
 }
 
 /// Executes the find command with the provided arguments.
 ///
 /// # Arguments
 ///
-/// * `args` - A vector of `String` representing the command-line arguments.
+/// * `args` - The command-line arguments, without the program name.
 ///
 /// # Returns
 ///
-/// * A `Result` indicating success or containing an error message as a `String`.
-fn find(args: Vec<String>) -> Result<(), String> {
-    let mut tokens: Vec<&str> = args.iter().skip(1).rev().map(|s| s.as_str()).collect();
-    let binding = parse_expression(&mut tokens);
-    let expr = binding.as_slice();
-    let path = get_root(expr);
-
-    let root_dev = if let Ok(metadata) = fs::metadata(path.clone()) {
-        metadata.dev()
+/// * `Ok(true)` if no errors occurred while walking the trees, `Ok(false)`
+///   if some were reported, or a message for an invalid command line.
+fn find(args: &[String]) -> Result<bool, String> {
+    let (follow, paths, tokens) = parse_args(args)?;
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        has_action: false,
+        opts: GlobalOpts::default(),
+        follow,
+    };
+    let mut expr = if tokens.is_empty() {
+        Expr::True
     } else {
-        return Err("Error: Could not retrieve root device metadata".to_string());
+        let expr = parser.or_expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(gettext!("unexpected `{}'", token));
+        }
+        expr
     };
+    // Without -exec, -ok or -print, the expression is `( expr ) -print`.
+    if !parser.has_action {
+        expr = Expr::And(Box::new(expr), Box::new(Expr::Print));
+    }
 
-    let files = WalkDir::new(path)
-        .into_iter()
-        .map(|f| f.unwrap())
-        .collect::<Vec<DirEntry>>();
-    let result = evaluate_expression(expr, files, root_dev);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut finder = Finder {
+        expr,
+        opts: parser.opts,
+        follow,
+        ctx: Context {
+            now,
+            arg_max: arg_max(),
+            prune: false,
+            failed: false,
+        },
+    };
 
-    for res in result? {
-        println!("{}", res.display())
+    for path in &paths {
+        finder.walk(path);
     }
-    Ok(())
+    flush_all(&mut finder.expr, &mut finder.ctx);
+
+    Ok(!finder.ctx.failed)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    let args: Vec<String> = env::args().collect();
-
-    let mut exit_code = 0;
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    if let Err(err) = find(args) {
-        exit_code = 1;
-        eprint!("{}", err);
-    }
+    let exit_code = match find(&args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("find: {}", err);
+            1
+        }
+    };
 
     std::process::exit(exit_code)
 }
@@ -1,7 +1,7 @@
 use std::fs::{remove_file, File};
 use std::io::Write;
 
-use plib::{run_test, TestPlan};
+use plib::{run_test, run_test_with_checker, TestPlan};
 
 fn run_test_find(
     args: &[&str],
@@ -21,6 +21,31 @@ fn run_test_find(
     });
 }
 
+/// Like `run_test_find`, but ignores the order of the output lines, which
+/// follows the order of the directory entries on disk.
+fn run_test_find_unordered(args: &[&str], expected_output: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    let plan = TestPlan {
+        cmd: String::from("find"),
+        args: str_args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_output),
+        expected_err: String::new(),
+        expected_exit_code,
+    };
+    run_test_with_checker(plan, |plan, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines: Vec<&str> = stdout.lines().collect();
+        let mut expected: Vec<&str> = plan.expected_out.lines().collect();
+        lines.sort();
+        expected.sort();
+        assert_eq!(lines, expected);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), plan.expected_err);
+        assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+    });
+}
+
 #[test]
 fn find_size_test() {
     let project_root = env!("CARGO_MANIFEST_DIR");
@@ -61,7 +86,7 @@ fn find_type_test() {
         test_dir, test_dir, test_dir, test_dir
     );
 
-    run_test_find(&args, &expected_output, "", 0)
+    run_test_find_unordered(&args, &expected_output, 0)
 }
 
 #[test]
@@ -116,7 +141,7 @@ fn find_or_test() {
         test_dir, test_dir, test_dir, test_dir
     );
 
-    run_test_find(&args, &expected_output, "", 0)
+    run_test_find_unordered(&args, &expected_output, 0)
 }
 
 #[test]
@@ -170,7 +195,7 @@ fn find_x_dev_test() {
         test_dir, test_dir, test_dir, test_dir, test_dir
     );
 
-    run_test_find(&args, &expected_output, "", 0)
+    run_test_find_unordered(&args, &expected_output, 0)
 }
 
 #[test]
@@ -193,7 +218,7 @@ fn find_links_test() {
         test_dir, test_dir, test_dir, test_dir
     );
 
-    run_test_find(&args, &expected_output, "", 0)
+    run_test_find_unordered(&args, &expected_output, 0)
 }
 
 #[test]
@@ -218,3 +243,154 @@ fn find_newer_test() {
 
     remove_file(&path_to_test_file).unwrap();
 }
+
+#[test]
+fn find_parentheses_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [
+        &test_dir, "(", "-name", "*.rs", "-o", "-name", "empty_*", ")", "-print",
+    ];
+
+    let expected_output = format!("{}/empty_file.txt\n{}/rust_file.rs\n", test_dir, test_dir);
+
+    run_test_find_unordered(&args, &expected_output, 0)
+}
+
+#[test]
+fn find_precedence_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    // -a binds tighter than -o: the directory is printed by the second
+    // alternative only.
+    let args = [
+        &test_dir, "-name", "*.rs", "-o", "-type", "d", "!", "-name", "*.txt",
+    ];
+
+    let expected_output = format!("{}\n{}/rust_file.rs\n", test_dir, test_dir);
+
+    run_test_find_unordered(&args, &expected_output, 0)
+}
+
+#[test]
+fn find_prune_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find", project_root);
+    let args = [
+        &test_dir, "-name", "other", "-prune", "-o", "-name", "*.rs", "-print",
+    ];
+
+    // other/rust_file.rs is not reached.
+    let expected_output = format!("{}/mod.rs\n", test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_depth_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    let plan = TestPlan {
+        cmd: String::from("find"),
+        args: vec![test_dir.clone(), String::from("-depth")],
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    };
+    run_test_with_checker(plan, |_, output| {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines.last(), Some(&test_dir.as_str()));
+        assert_eq!(output.status.code(), Some(0));
+    });
+}
+
+#[test]
+fn find_exec_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "-name", "*.rs", "-exec", "echo", "found:{}", ";"];
+
+    let expected_output = format!("found:{}/rust_file.rs\n", test_dir);
+
+    run_test_find(&args, &expected_output, "", 0)
+}
+
+#[test]
+fn find_exec_plus_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "-type", "f", "-exec", "echo", "batch", "{}", "+"];
+
+    let plan = TestPlan {
+        cmd: String::from("find"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    };
+    run_test_with_checker(plan, |_, output| {
+        // All four files are passed to a single invocation.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("batch "));
+        assert_eq!(lines[0].matches(&test_dir).count(), 4);
+        assert_eq!(output.status.code(), Some(0));
+    });
+}
+
+#[test]
+fn find_exec_failure_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    // A failing -exec ... ; is just false, so nothing is printed.
+    let args = [&test_dir, "-name", "*.rs", "-exec", "false", ";", "-print"];
+    run_test_find(&args, "", "", 0);
+
+    // A failing -exec ... + is reflected in the exit status.
+    let args = [&test_dir, "-name", "*.rs", "-exec", "false", "{}", "+"];
+    run_test_find(&args, "", "", 1);
+}
+
+#[test]
+fn find_ok_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+    let args = [&test_dir, "-name", "*.rs", "-ok", "echo", "{}", ";"];
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("find"),
+        args: str_args,
+        stdin_data: String::from("y\n"),
+        expected_out: format!("{}/rust_file.rs\n", test_dir),
+        expected_err: format!("< echo {}/rust_file.rs >? ", test_dir),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn find_invalid_expression_test() {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let test_dir = format!("{}/tests/find/other", project_root);
+
+    run_test_find(&[&test_dir, "(", "-print"], "", "find: missing `)'\n", 1);
+    run_test_find(
+        &[&test_dir, "-exec", "echo", "{}"],
+        "",
+        "find: missing argument to `-exec'\n",
+        1,
+    );
+    run_test_find(
+        &[&test_dir, "-bogus"],
+        "",
+        "find: unknown predicate `-bogus'\n",
+        1,
+    );
+}
@@ -0,0 +1,342 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Reader for GNU `.mo` message catalogs.
+//!
+//! Layout of a catalog (all integers are 32-bit, in the byte order
+//! indicated by the magic number):
+//!
+//! | offset | contents                                  |
+//! |--------|-------------------------------------------|
+//! | 0      | magic number `0x950412de`                 |
+//! | 4      | file format revision                      |
+//! | 8      | number of strings N                       |
+//! | 12     | offset of table with original strings     |
+//! | 16     | offset of table with translated strings   |
+//! | 20     | size of hashing table                     |
+//! | 24     | offset of hashing table                   |
+//!
+//! Each string table holds N (length, offset) pairs.  The hashing table
+//! is optional and only speeds up lookups in the C implementation; since
+//! the whole catalog is indexed into a `HashMap` on load it is ignored.
+
+use crate::plural::PluralExpr;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: u32 = 0x950412de;
+const MAGIC_SWAPPED: u32 = 0xde120495;
+
+/// Separator between the context and the msgid of a `pgettext` entry.
+pub const CONTEXT_SEPARATOR: char = '\u{4}';
+
+/// Character sets a catalog may be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Charset {
+    Utf8,
+    Latin1,
+}
+
+#[derive(Debug)]
+pub struct Catalog {
+    messages: HashMap<String, Vec<String>>,
+    nplurals: usize,
+    plural: PluralExpr,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u32_at(&self, offset: usize) -> io::Result<u32> {
+        let bytes = self
+            .data
+            .get(offset..offset + 4)
+            .ok_or_else(|| invalid("truncated message catalog"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            Ok(u32::from_be_bytes(bytes))
+        } else {
+            Ok(u32::from_le_bytes(bytes))
+        }
+    }
+
+    /// Returns the `index`-th string of the descriptor table at `table`.
+    fn string(&self, table: usize, index: usize) -> io::Result<&[u8]> {
+        let desc = table + index * 8;
+        let len = self.u32_at(desc)? as usize;
+        let offset = self.u32_at(desc + 4)? as usize;
+        self.data
+            .get(offset..offset + len)
+            .ok_or_else(|| invalid("string outside of message catalog"))
+    }
+}
+
+fn decode(bytes: &[u8], charset: Charset) -> String {
+    match charset {
+        Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        Charset::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Returns the value of `field` in the catalog header entry, e.g.
+/// `header_field(h, "Plural-Forms")`.
+fn header_field<'a>(header: &'a str, field: &str) -> Option<&'a str> {
+    header.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case(field) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn header_charset(header: &str) -> Charset {
+    let charset = header_field(header, "Content-Type").and_then(|ct| {
+        let (_, cs) = ct.split_once("charset=")?;
+        Some(cs.trim().trim_end_matches(';').to_ascii_lowercase())
+    });
+    match charset.as_deref() {
+        Some("iso-8859-1") | Some("iso8859-1") | Some("latin1") => Charset::Latin1,
+        _ => Charset::Utf8,
+    }
+}
+
+/// Parses the `Plural-Forms` header value, e.g.
+/// `nplurals=2; plural=(n != 1);`.
+fn header_plural_forms(header: &str) -> Option<(usize, PluralExpr)> {
+    let forms = header_field(header, "Plural-Forms")?;
+    let mut nplurals = None;
+    let mut plural = None;
+    for part in forms.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "nplurals" => nplurals = value.trim().parse::<usize>().ok(),
+            "plural" => plural = PluralExpr::parse(value.trim()).ok(),
+            _ => {}
+        }
+    }
+    Some((nplurals?, plural?))
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> io::Result<Catalog> {
+        Catalog::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<Catalog> {
+        let magic = Reader {
+            data,
+            big_endian: false,
+        }
+        .u32_at(0)?;
+        let big_endian = match magic {
+            MAGIC => false,
+            MAGIC_SWAPPED => true,
+            _ => return Err(invalid("not a message catalog")),
+        };
+        let reader = Reader { data, big_endian };
+
+        // Only the major revision number matters: 0 and 1 share the
+        // layout used here.
+        let revision = reader.u32_at(4)?;
+        if revision >> 16 > 1 {
+            return Err(invalid("unsupported message catalog revision"));
+        }
+
+        let nstrings = reader.u32_at(8)? as usize;
+        let orig_table = reader.u32_at(12)? as usize;
+        let trans_table = reader.u32_at(16)? as usize;
+
+        // A corrupt string count must not be trusted for allocations:
+        // both descriptor tables have to fit into the catalog.
+        for table in [orig_table, trans_table] {
+            let fits = nstrings
+                .checked_mul(8)
+                .and_then(|size| size.checked_add(table))
+                .is_some_and(|end| end <= data.len());
+            if !fits {
+                return Err(invalid("string table outside of message catalog"));
+            }
+        }
+
+        let mut raw = Vec::with_capacity(nstrings);
+        let mut header = String::new();
+        for i in 0..nstrings {
+            let orig = reader.string(orig_table, i)?;
+            let trans = reader.string(trans_table, i)?;
+            if orig.is_empty() {
+                header = String::from_utf8_lossy(trans).into_owned();
+            }
+            raw.push((orig, trans));
+        }
+
+        let charset = header_charset(&header);
+        let (nplurals, plural) =
+            header_plural_forms(&header).unwrap_or((2, PluralExpr::germanic()));
+
+        let mut messages = HashMap::with_capacity(nstrings);
+        for (orig, trans) in raw {
+            // Plural entries store "msgid\0msgid_plural"; only the singular
+            // form is used as the key.
+            let key = orig.split(|&b| b == 0).next().unwrap_or_default();
+            let forms = trans
+                .split(|&b| b == 0)
+                .map(|form| decode(form, charset))
+                .collect();
+            messages.insert(decode(key, charset), forms);
+        }
+
+        Ok(Catalog {
+            messages,
+            nplurals,
+            plural,
+        })
+    }
+
+    /// Looks up the translation of `msgid`. `n` selects the plural form
+    /// for `ngettext`-style lookups.
+    pub fn lookup(&self, msgid: &str, n: Option<u64>) -> Option<&str> {
+        if msgid.is_empty() {
+            return None;
+        }
+        let forms = self.messages.get(msgid)?;
+        let index = match n {
+            Some(n) => {
+                let index = self.plural.eval(n) as usize;
+                if index < self.nplurals {
+                    index
+                } else {
+                    0
+                }
+            }
+            None => 0,
+        };
+        forms.get(index).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a catalog from (msgid, msgstr) pairs, which must already be
+    /// sorted by msgid.
+    pub(crate) fn build_mo(entries: &[(&str, &str)], big_endian: bool) -> Vec<u8> {
+        let put = |buf: &mut Vec<u8>, v: u32| {
+            if big_endian {
+                buf.extend_from_slice(&v.to_be_bytes())
+            } else {
+                buf.extend_from_slice(&v.to_le_bytes())
+            }
+        };
+
+        let n = entries.len() as u32;
+        let orig_table = 28;
+        let trans_table = orig_table + n * 8;
+        let mut strings_offset = trans_table + n * 8;
+
+        let mut buf = Vec::new();
+        put(&mut buf, MAGIC);
+        put(&mut buf, 0);
+        put(&mut buf, n);
+        put(&mut buf, orig_table);
+        put(&mut buf, trans_table);
+        put(&mut buf, 0);
+        put(&mut buf, 0);
+
+        let mut strings = Vec::new();
+        let columns: [Vec<&str>; 2] = [
+            entries.iter().map(|e| e.0).collect(),
+            entries.iter().map(|e| e.1).collect(),
+        ];
+        for column in columns {
+            for s in column {
+                put(&mut buf, s.len() as u32);
+                put(&mut buf, strings_offset);
+                strings.extend_from_slice(s.as_bytes());
+                strings.push(0);
+                strings_offset += s.len() as u32 + 1;
+            }
+        }
+        buf.extend_from_slice(&strings);
+        buf
+    }
+
+    const HEADER: &str = "Content-Type: text/plain; charset=UTF-8\n\
+                          Plural-Forms: nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);\n";
+
+    fn sample(big_endian: bool) -> Catalog {
+        let data = build_mo(
+            &[
+                ("", HEADER),
+                ("%d file\0%d files", "%d plik\0%d pliki\0%d plików"),
+                ("hello", "cześć"),
+                ("menu\u{4}Open", "Otwórz"),
+            ],
+            big_endian,
+        );
+        Catalog::parse(&data).unwrap()
+    }
+
+    #[test]
+    fn test_lookup_both_byte_orders() {
+        for big_endian in [false, true] {
+            let cat = sample(big_endian);
+            assert_eq!(cat.lookup("hello", None), Some("cześć"));
+            assert_eq!(cat.lookup("menu\u{4}Open", None), Some("Otwórz"));
+            assert_eq!(cat.lookup("missing", None), None);
+            assert_eq!(cat.lookup("", None), None);
+        }
+    }
+
+    #[test]
+    fn test_plural_forms() {
+        let cat = sample(false);
+        assert_eq!(cat.lookup("%d file", Some(1)), Some("%d plik"));
+        assert_eq!(cat.lookup("%d file", Some(3)), Some("%d pliki"));
+        assert_eq!(cat.lookup("%d file", Some(5)), Some("%d plików"));
+        assert_eq!(cat.lookup("%d file", Some(22)), Some("%d pliki"));
+    }
+
+    #[test]
+    fn test_latin1_catalog() {
+        let header = "Content-Type: text/plain; charset=ISO-8859-1\n";
+        let mut data = build_mo(&[("", header), ("yes", "oui")], false);
+        // Patch "oui" to "ou\xef" (LATIN SMALL LETTER I WITH DIAERESIS).
+        let pos = data.windows(3).position(|w| w == b"oui").unwrap();
+        data[pos + 2] = 0xef;
+        let cat = Catalog::parse(&data).unwrap();
+        assert_eq!(cat.lookup("yes", None), Some("ouï"));
+    }
+
+    #[test]
+    fn test_invalid_catalog() {
+        assert!(Catalog::parse(b"not a catalog at all").is_err());
+        assert!(Catalog::parse(&[0xde, 0x12, 0x04, 0x95]).is_err());
+    }
+
+    #[test]
+    fn test_string_count_beyond_catalog() {
+        let mut data = build_mo(&[("yes", "oui")], false);
+        data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Catalog::parse(&data).is_err());
+    }
+}
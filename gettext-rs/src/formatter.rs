@@ -0,0 +1,77 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Runtime formatting for the `gettext!` family of macros.
+//!
+//! `format!` needs its format string at compile time, but a translated
+//! message is only known at runtime, so the placeholders are substituted
+//! here instead.  Supported are `{}` (next argument), `{N}` (argument N)
+//! and the `{{`/`}}` escapes; anything else is copied through verbatim.
+
+use std::fmt::{Display, Write};
+
+pub fn format(msgstr: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(msgstr.len());
+    let mut next_arg = 0;
+    let mut rest = msgstr;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('}') {
+            out.push('}');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let spec = &rest[1..end];
+        let index = if spec.is_empty() {
+            next_arg += 1;
+            Some(next_arg - 1)
+        } else {
+            spec.parse::<usize>().ok()
+        };
+        match index.and_then(|i| args.get(i)) {
+            Some(arg) => {
+                let _ = write!(out, "{}", arg);
+            }
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format("plain", &[]), "plain");
+        assert_eq!(format("{} and {}", &[&1, &"two"]), "1 and two");
+        assert_eq!(format("{1} before {0}", &[&"a", &"b"]), "b before a");
+        assert_eq!(format("{{}} {}", &[&'x']), "{} x");
+        assert_eq!(format("{} {} {}", &[&1]), "1 {} {}");
+        assert_eq!(format("{name} {", &[&1]), "{name} {");
+        // Substituted text is never re-scanned for placeholders.
+        assert_eq!(format("{} {}", &[&"{}", &2]), "{} 2");
+    }
+}
@@ -1,59 +1,486 @@
-pub fn setlocale<T: Into<Vec<u8>>>(_category: LocaleCategory, locale: T) -> Option<Vec<u8>> {
-    Some(locale.into())
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! A pure Rust implementation of the subset of the `gettext-rs` API used
+//! by posixutils-rs.
+//!
+//! Messages are looked up in GNU `.mo` catalogs found at
+//! `<dir>/<locale>/LC_MESSAGES/<domain>.mo`, where `<dir>` is set with
+//! [`bindtextdomain`] (default `/usr/share/locale`) and `<locale>` is the
+//! `LC_MESSAGES` locale selected by [`setlocale`], or any entry of the
+//! `LANGUAGE` priority list.  No libintl is linked, and the C library's
+//! own locale is left untouched.
+
+mod catalog;
+#[doc(hidden)]
+pub mod formatter;
+mod plural;
+
+use catalog::{Catalog, CONTEXT_SEPARATOR};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// Directory searched for catalogs of domains without a binding.
+const DEFAULT_LOCALE_DIR: &str = "/usr/share/locale";
+
+/// Text domain in effect until [`textdomain`] is called.
+const DEFAULT_DOMAIN: &str = "messages";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocaleCategory {
+    LcCType,
+    LcNumeric,
+    LcTime,
+    LcCollate,
+    LcMonetary,
+    LcMessages,
+    LcAll,
+    LcPaper,
+    LcName,
+    LcAddress,
+    LcTelephone,
+    LcMeasurement,
+    LcIdentification,
+}
+
+impl LocaleCategory {
+    fn env_name(self) -> &'static str {
+        match self {
+            LocaleCategory::LcCType => "LC_CTYPE",
+            LocaleCategory::LcNumeric => "LC_NUMERIC",
+            LocaleCategory::LcTime => "LC_TIME",
+            LocaleCategory::LcCollate => "LC_COLLATE",
+            LocaleCategory::LcMonetary => "LC_MONETARY",
+            LocaleCategory::LcMessages => "LC_MESSAGES",
+            LocaleCategory::LcAll => "LC_ALL",
+            LocaleCategory::LcPaper => "LC_PAPER",
+            LocaleCategory::LcName => "LC_NAME",
+            LocaleCategory::LcAddress => "LC_ADDRESS",
+            LocaleCategory::LcTelephone => "LC_TELEPHONE",
+            LocaleCategory::LcMeasurement => "LC_MEASUREMENT",
+            LocaleCategory::LcIdentification => "LC_IDENTIFICATION",
+        }
+    }
+}
+
+struct State {
+    domain: String,
+    dirs: HashMap<String, PathBuf>,
+    codesets: HashMap<String, String>,
+    messages_locale: String,
+    /// Catalogs by path; `None` records a missing or unreadable file so it
+    /// is not probed again.
+    catalogs: HashMap<PathBuf, Option<Catalog>>,
+}
+
+fn state() -> MutexGuard<'static, State> {
+    static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+    STATE
+        .get_or_init(|| {
+            Mutex::new(State {
+                domain: DEFAULT_DOMAIN.to_string(),
+                dirs: HashMap::new(),
+                codesets: HashMap::new(),
+                messages_locale: "C".to_string(),
+                catalogs: HashMap::new(),
+            })
+        })
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
 }
 
+fn env_nonempty(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// Resolves the empty locale name as POSIX specifies: `LC_ALL`, then the
+/// category's own variable, then `LANG`, then the "C" locale.
+fn locale_from_env(category: LocaleCategory) -> String {
+    let category = match category {
+        LocaleCategory::LcAll => LocaleCategory::LcMessages,
+        c => c,
+    };
+    env_nonempty("LC_ALL")
+        .or_else(|| env_nonempty(category.env_name()))
+        .or_else(|| env_nonempty("LANG"))
+        .unwrap_or_else(|| "C".to_string())
+}
+
+fn normalize_codeset(codeset: &str) -> String {
+    let norm: String = codeset
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if norm.chars().all(|c| c.is_ascii_digit()) {
+        format!("iso{}", norm)
+    } else {
+        norm
+    }
+}
+
+/// Expands `language[_territory][.codeset][@modifier]` into the list of
+/// directory names to try, most specific first, in the order used by GNU
+/// gettext.
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, m)) => (rest, Some(m)),
+        None => (locale, None),
+    };
+    let (rest, codeset) = match rest.split_once('.') {
+        Some((rest, c)) => (rest, Some(c)),
+        None => (rest, None),
+    };
+    let (language, territory) = match rest.split_once('_') {
+        Some((l, t)) => (l, Some(t)),
+        None => (rest, None),
+    };
+    let norm_codeset = codeset
+        .map(normalize_codeset)
+        .filter(|n| Some(n.as_str()) != codeset);
+
+    let mut variants = Vec::new();
+    for with_modifier in [true, false] {
+        for with_territory in [true, false] {
+            let codesets = [codeset, norm_codeset.as_deref(), None];
+            for cs in codesets {
+                if (with_modifier && modifier.is_none()) || (with_territory && territory.is_none())
+                {
+                    continue;
+                }
+                let mut name = language.to_string();
+                if with_territory {
+                    name.push('_');
+                    name.push_str(territory.unwrap());
+                }
+                if let Some(cs) = cs {
+                    name.push('.');
+                    name.push_str(cs);
+                }
+                if with_modifier {
+                    name.push('@');
+                    name.push_str(modifier.unwrap());
+                }
+                if !variants.contains(&name) {
+                    variants.push(name);
+                }
+            }
+        }
+    }
+    variants
+}
+
+impl State {
+    /// Languages to search, in order of preference.  As in GNU gettext,
+    /// `LANGUAGE` is only honoured when a locale other than "C" is active.
+    fn languages(&self) -> Vec<String> {
+        if self.messages_locale == "C" || self.messages_locale == "POSIX" {
+            return Vec::new();
+        }
+        match env_nonempty("LANGUAGE") {
+            Some(list) => list
+                .split(':')
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect(),
+            None => vec![self.messages_locale.clone()],
+        }
+    }
+
+    fn catalog(&mut self, path: PathBuf) -> Option<&Catalog> {
+        self.catalogs
+            .entry(path)
+            .or_insert_with_key(|path| Catalog::open(path).ok())
+            .as_ref()
+    }
+
+    fn lookup(&mut self, domain: Option<&str>, msgid: &str, n: Option<u64>) -> Option<String> {
+        let domain = domain.unwrap_or(&self.domain).to_string();
+        let dir = self
+            .dirs
+            .get(&domain)
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LOCALE_DIR));
+
+        for language in self.languages() {
+            for variant in locale_variants(&language) {
+                let path = dir
+                    .join(variant)
+                    .join("LC_MESSAGES")
+                    .join(format!("{}.mo", domain));
+                if let Some(msgstr) = self.catalog(path).and_then(|c| c.lookup(msgid, n)) {
+                    return Some(msgstr.to_string());
+                }
+            }
+        }
+        None
+    }
+}
+
+fn translate(
+    domain: Option<&str>,
+    context: Option<&str>,
+    msgid: String,
+    msgid_plural: Option<String>,
+    n: u32,
+) -> String {
+    let key = match context {
+        Some(ctx) => format!("{}{}{}", ctx, CONTEXT_SEPARATOR, msgid),
+        None => msgid.clone(),
+    };
+    let n_arg = msgid_plural.as_ref().map(|_| n as u64);
+    if let Some(msgstr) = state().lookup(domain, &key, n_arg) {
+        return msgstr;
+    }
+    match msgid_plural {
+        Some(plural) if n != 1 => plural,
+        _ => msgid,
+    }
+}
+
+/// Sets the locale for `category`.  An empty `locale` selects the locale
+/// named by the environment.  Only `LcMessages` (and `LcAll`) affect this
+/// crate; the name is returned for every category.
+pub fn setlocale<T: Into<Vec<u8>>>(category: LocaleCategory, locale: T) -> Option<Vec<u8>> {
+    let locale = String::from_utf8(locale.into()).ok()?;
+    let locale = if locale.is_empty() {
+        locale_from_env(category)
+    } else {
+        locale
+    };
+    if matches!(category, LocaleCategory::LcMessages | LocaleCategory::LcAll) {
+        state().messages_locale = locale.clone();
+    }
+    Some(locale.into_bytes())
+}
+
+/// Records the codeset requested for `domainname`.  Messages are always
+/// returned as UTF-8 `String`s, converted from the catalog's charset.
 pub fn bind_textdomain_codeset<T, U>(
-    _domainname: T,
-    _codeset: U,
+    domainname: T,
+    codeset: U,
 ) -> Result<Option<String>, std::io::Error>
 where
     T: Into<Vec<u8>>,
     U: Into<String>,
 {
-    Ok(None)
+    let domain = String::from_utf8_lossy(&domainname.into()).into_owned();
+    let codeset = codeset.into();
+    state().codesets.insert(domain, codeset.clone());
+    Ok(Some(codeset))
 }
 
-pub fn bindtextdomain<T, U>(
-    _domainname: T,
-    dirname: U,
-) -> Result<std::path::PathBuf, std::io::Error>
+/// Sets the directory containing the catalogs of `domainname`.
+pub fn bindtextdomain<T, U>(domainname: T, dirname: U) -> Result<PathBuf, std::io::Error>
 where
     T: Into<Vec<u8>>,
-    U: Into<std::path::PathBuf>,
+    U: Into<PathBuf>,
 {
-    Ok(dirname.into())
+    let domain = String::from_utf8_lossy(&domainname.into()).into_owned();
+    if domain.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "empty text domain name",
+        ));
+    }
+    let dirname = dirname.into();
+    state().dirs.insert(domain, dirname.clone());
+    Ok(dirname)
+}
+
+/// Returns the directory bound to `domainname`.
+pub fn domain_directory<T: Into<Vec<u8>>>(domainname: T) -> Result<PathBuf, std::io::Error> {
+    let domain = String::from_utf8_lossy(&domainname.into()).into_owned();
+    Ok(state()
+        .dirs
+        .get(&domain)
+        .cloned()
+        .unwrap_or_else(|| Path::new(DEFAULT_LOCALE_DIR).to_path_buf()))
 }
 
+/// Sets the default text domain; an empty name restores "messages".
 pub fn textdomain<T: Into<Vec<u8>>>(domainname: T) -> Result<Vec<u8>, std::io::Error> {
-    Ok(domainname.into())
+    let domain = String::from_utf8_lossy(&domainname.into()).into_owned();
+    let mut state = state();
+    state.domain = if domain.is_empty() {
+        DEFAULT_DOMAIN.to_string()
+    } else {
+        domain
+    };
+    Ok(state.domain.clone().into_bytes())
 }
 
 pub fn gettext<T: Into<String>>(msgid: T) -> String {
-    msgid.into()
+    translate(None, None, msgid.into(), None, 1)
+}
+
+pub fn dgettext<T, U>(domainname: T, msgid: U) -> String
+where
+    T: Into<String>,
+    U: Into<String>,
+{
+    translate(Some(&domainname.into()), None, msgid.into(), None, 1)
+}
+
+pub fn ngettext<T, S>(msgid: T, msgid_plural: S, n: u32) -> String
+where
+    T: Into<String>,
+    S: Into<String>,
+{
+    translate(None, None, msgid.into(), Some(msgid_plural.into()), n)
+}
+
+pub fn dngettext<T, U, V>(domainname: T, msgid: U, msgid_plural: V, n: u32) -> String
+where
+    T: Into<String>,
+    U: Into<String>,
+    V: Into<String>,
+{
+    let domain = domainname.into();
+    translate(
+        Some(&domain),
+        None,
+        msgid.into(),
+        Some(msgid_plural.into()),
+        n,
+    )
+}
+
+pub fn pgettext<T, U>(msgctxt: T, msgid: U) -> String
+where
+    T: Into<String>,
+    U: Into<String>,
+{
+    translate(None, Some(&msgctxt.into()), msgid.into(), None, 1)
 }
 
+pub fn npgettext<T, U, V>(msgctxt: T, msgid: U, msgid_plural: V, n: u32) -> String
+where
+    T: Into<String>,
+    U: Into<String>,
+    V: Into<String>,
+{
+    let ctx = msgctxt.into();
+    translate(None, Some(&ctx), msgid.into(), Some(msgid_plural.into()), n)
+}
+
+/// Like `format!`, but the format string is translated first, so the
+/// placeholders are substituted at runtime (see `formatter::format`).
 #[macro_export]
 macro_rules! gettext {
-    ($fmt:expr) => {
-        format!($fmt)
+    ($msgid:expr $(,)?) => {
+        $crate::formatter::format(&$crate::gettext($msgid), &[])
     };
-    ($fmt:expr, $($arg:tt)*) => {
-        format!($fmt, $($arg)*)
+    ($msgid:expr, $($arg:expr),+ $(,)?) => {
+        $crate::formatter::format(
+            &$crate::gettext($msgid),
+            &[$(&$arg as &dyn ::std::fmt::Display),+],
+        )
     };
 }
 
-pub enum LocaleCategory {
-    LcCType,
-    LcNumeric,
-    LcTime,
-    LcCollate,
-    LcMonetary,
-    LcMessages,
-    LcAll,
-    LcPaper,
-    LcName,
-    LcAddress,
-    LcTelephone,
-    LcMeasurement,
-    LcIdentification,
+/// Plural-aware `gettext!`: `ngettext!("{} file", "{} files", n, n)`.
+#[macro_export]
+macro_rules! ngettext {
+    ($msgid:expr, $msgid_plural:expr, $n:expr $(,)?) => {
+        $crate::formatter::format(&$crate::ngettext($msgid, $msgid_plural, $n), &[])
+    };
+    ($msgid:expr, $msgid_plural:expr, $n:expr, $($arg:expr),+ $(,)?) => {
+        $crate::formatter::format(
+            &$crate::ngettext($msgid, $msgid_plural, $n),
+            &[$(&$arg as &dyn ::std::fmt::Display),+],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_locale_variants() {
+        assert_eq!(locale_variants("de"), vec!["de"]);
+        assert_eq!(locale_variants("pt_BR"), vec!["pt_BR", "pt"]);
+        assert_eq!(
+            locale_variants("de_DE.UTF-8@euro"),
+            vec![
+                "de_DE.UTF-8@euro",
+                "de_DE.utf8@euro",
+                "de_DE@euro",
+                "de.UTF-8@euro",
+                "de.utf8@euro",
+                "de@euro",
+                "de_DE.UTF-8",
+                "de_DE.utf8",
+                "de_DE",
+                "de.UTF-8",
+                "de.utf8",
+                "de",
+            ]
+        );
+        assert_eq!(
+            locale_variants("fr_FR.utf8"),
+            vec!["fr_FR.utf8", "fr_FR", "fr.utf8", "fr"]
+        );
+        assert_eq!(normalize_codeset("8859-1"), "iso88591");
+    }
+
+    // The catalog state is process-global, so the end-to-end behaviour is
+    // exercised in a single test.
+    #[test]
+    fn test_translate_from_bound_directory() {
+        let dir = env::temp_dir().join(format!("gettext-rs-test-{}", std::process::id()));
+        let msgdir = dir.join("fr").join("LC_MESSAGES");
+        fs::create_dir_all(&msgdir).unwrap();
+        let data = catalog::tests::build_mo(
+            &[
+                (
+                    "",
+                    "Content-Type: text/plain; charset=UTF-8\n\
+                     Plural-Forms: nplurals=2; plural=(n > 1);\n",
+                ),
+                ("cannot open {}", "impossible d'ouvrir {}"),
+                ("file\0files", "fichier\0fichiers"),
+                ("verb\u{4}open", "ouvrir"),
+            ],
+            false,
+        );
+        fs::write(msgdir.join("testdomain.mo"), data).unwrap();
+
+        textdomain("testdomain").unwrap();
+        bindtextdomain("testdomain", dir.clone()).unwrap();
+
+        // The "C" locale never translates.
+        setlocale(LocaleCategory::LcAll, "C");
+        assert_eq!(gettext("cannot open {}"), "cannot open {}");
+
+        setlocale(LocaleCategory::LcMessages, "fr_FR.UTF-8");
+        assert_eq!(gettext!("cannot open {}", "x"), "impossible d'ouvrir x");
+        assert_eq!(gettext("untranslated"), "untranslated");
+        assert_eq!(ngettext("file", "files", 0), "fichier");
+        assert_eq!(ngettext("file", "files", 2), "fichiers");
+        assert_eq!(ngettext("nope", "nopes", 2), "nopes");
+        assert_eq!(pgettext("verb", "open"), "ouvrir");
+        assert_eq!(gettext("open"), "open");
+        assert_eq!(dgettext("otherdomain", "cannot open {}"), "cannot open {}");
+
+        textdomain("").unwrap();
+        assert_eq!(gettext("cannot open {}"), "cannot open {}");
+        assert_eq!(
+            dgettext("testdomain", "cannot open {}"),
+            "impossible d'ouvrir {}"
+        );
+
+        setlocale(LocaleCategory::LcAll, "C");
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
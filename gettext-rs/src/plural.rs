@@ -0,0 +1,290 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Evaluator for the `plural=` expression of a catalog's `Plural-Forms`
+//! header.  The expression language is the subset of C accepted by GNU
+//! gettext: the variable `n`, unsigned integer constants, parentheses and
+//! the operators `! * / % + - < <= > >= == != && || ?:`.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    N,
+    Num(u64),
+    Not(Box<Node>),
+    Binary(BinOp, Box<Node>, Box<Node>),
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluralExpr(Node);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    N,
+    Num(u64),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Question,
+    Colon,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    // Longer operators first so that "<=" is not read as "<".
+    const OPERATORS: [&str; 14] = [
+        "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "*", "/", "%", "+", "-",
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_end_matches(';');
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_whitespace() {
+            rest = &rest[1..];
+        } else if c == 'n' {
+            tokens.push(Token::N);
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value = rest[..end]
+                .parse()
+                .map_err(|_| format!("number too large in plural expression: {}", s))?;
+            tokens.push(Token::Num(value));
+            rest = &rest[end..];
+        } else if c == '(' || c == ')' || c == '?' || c == ':' {
+            tokens.push(match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '?' => Token::Question,
+                _ => Token::Colon,
+            });
+            rest = &rest[1..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("invalid plural expression: {}", s))?;
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, tok: Token) -> Result<(), String> {
+        if self.next() == Some(tok) {
+            Ok(())
+        } else {
+            Err("malformed plural expression".to_string())
+        }
+    }
+
+    fn conditional(&mut self) -> Result<Node, String> {
+        let cond = self.binary(0)?;
+        if self.peek() == Some(&Token::Question) {
+            self.pos += 1;
+            let then = self.conditional()?;
+            self.expect(Token::Colon)?;
+            let other = self.conditional()?;
+            Ok(Node::Cond(Box::new(cond), Box::new(then), Box::new(other)))
+        } else {
+            Ok(cond)
+        }
+    }
+
+    /// Precedence climbing over the binary operators, lowest level first.
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        const LEVELS: [&[(&str, BinOp)]; 6] = [
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[("==", BinOp::Eq), ("!=", BinOp::Ne)],
+            &[
+                ("<", BinOp::Lt),
+                ("<=", BinOp::Le),
+                (">", BinOp::Gt),
+                (">=", BinOp::Ge),
+            ],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let Some((_, binop)) = LEVELS[level].iter().find(|(s, _)| s == op) else {
+                break;
+            };
+            let binop = *binop;
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Node::Binary(binop, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Op("!")) => Ok(Node::Not(Box::new(self.unary()?))),
+            Some(Token::N) => Ok(Node::N),
+            Some(Token::Num(v)) => Ok(Node::Num(v)),
+            Some(Token::LParen) => {
+                let node = self.conditional()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            _ => Err("malformed plural expression".to_string()),
+        }
+    }
+}
+
+fn eval(node: &Node, n: u64) -> u64 {
+    match node {
+        Node::N => n,
+        Node::Num(v) => *v,
+        Node::Not(e) => (eval(e, n) == 0) as u64,
+        Node::Cond(c, a, b) => {
+            if eval(c, n) != 0 {
+                eval(a, n)
+            } else {
+                eval(b, n)
+            }
+        }
+        Node::Binary(BinOp::And, a, b) => (eval(a, n) != 0 && eval(b, n) != 0) as u64,
+        Node::Binary(BinOp::Or, a, b) => (eval(a, n) != 0 || eval(b, n) != 0) as u64,
+        Node::Binary(op, a, b) => {
+            let (a, b) = (eval(a, n), eval(b, n));
+            match op {
+                BinOp::Mul => a.wrapping_mul(b),
+                // GNU gettext would raise SIGFPE here; selecting the first
+                // form is friendlier.
+                BinOp::Div => a.checked_div(b).unwrap_or(0),
+                BinOp::Rem => a.checked_rem(b).unwrap_or(0),
+                BinOp::Add => a.wrapping_add(b),
+                BinOp::Sub => a.wrapping_sub(b),
+                BinOp::Lt => (a < b) as u64,
+                BinOp::Le => (a <= b) as u64,
+                BinOp::Gt => (a > b) as u64,
+                BinOp::Ge => (a >= b) as u64,
+                BinOp::Eq => (a == b) as u64,
+                BinOp::Ne => (a != b) as u64,
+                BinOp::And | BinOp::Or => unreachable!(),
+            }
+        }
+    }
+}
+
+impl PluralExpr {
+    pub fn parse(s: &str) -> Result<PluralExpr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let node = parser.conditional()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("trailing garbage in plural expression: {}", s));
+        }
+        Ok(PluralExpr(node))
+    }
+
+    /// `n != 1`, the rule used when a catalog has no `Plural-Forms` header
+    /// and when no catalog is found at all.
+    pub fn germanic() -> PluralExpr {
+        PluralExpr(Node::Binary(
+            BinOp::Ne,
+            Box::new(Node::N),
+            Box::new(Node::Num(1)),
+        ))
+    }
+
+    pub fn eval(&self, n: u64) -> u64 {
+        eval(&self.0, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forms(expr: &str, ns: &[u64]) -> Vec<u64> {
+        let expr = PluralExpr::parse(expr).unwrap();
+        ns.iter().map(|&n| expr.eval(n)).collect()
+    }
+
+    #[test]
+    fn test_simple_rules() {
+        assert_eq!(forms("0", &[0, 1, 2]), vec![0, 0, 0]);
+        assert_eq!(forms("(n != 1)", &[0, 1, 2]), vec![1, 0, 1]);
+        assert_eq!(forms("n>1", &[0, 1, 2]), vec![0, 0, 1]);
+        assert_eq!(forms("!(n == 1);", &[1, 7]), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(forms("1 + 2 * 3", &[0]), vec![7]);
+        assert_eq!(forms("(1 + 2) * 3", &[0]), vec![9]);
+        assert_eq!(forms("10 - 4 - 3", &[0]), vec![3]);
+        assert_eq!(forms("n == 1 || n == 2 && 0", &[1, 2]), vec![1, 0]);
+        assert_eq!(forms("n/0 + n%0", &[5]), vec![0]);
+    }
+
+    #[test]
+    fn test_slavic_rule() {
+        let expr = "n%10==1 && n%100!=11 ? 0 : \
+                    n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2";
+        assert_eq!(
+            forms(expr, &[1, 2, 5, 11, 12, 21, 22, 25, 111]),
+            vec![0, 1, 2, 2, 2, 0, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(PluralExpr::parse("n = 1").is_err());
+        assert!(PluralExpr::parse("(n").is_err());
+        assert!(PluralExpr::parse("n 1").is_err());
+        assert!(PluralExpr::parse("x").is_err());
+        assert!(PluralExpr::parse("n ? 1").is_err());
+    }
+}
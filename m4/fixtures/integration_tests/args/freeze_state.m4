@@ -0,0 +1,6 @@
+define(`foo', `bar')dnl
+pushdef(`foo', `baz')dnl
+undefine(`eval')dnl
+changequote([, ])
+divert(1)diverted text
+divert(2)
@@ -0,0 +1,3 @@
+include(`file.m4')
+sinclude(`missing.m4')dnl
+include(`define_args.m4')dnl
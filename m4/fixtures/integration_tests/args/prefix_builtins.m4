@@ -0,0 +1,4 @@
+m4_define(`x', `defined')m4_dnl
+define(`x') len(`abc') x
+m4_len(`abc') m4_format(`%d', `7') m4___line__
+m4_indir(`m4_len', `hello') m4_builtin(`len', `hello')
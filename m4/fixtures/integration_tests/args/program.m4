@@ -0,0 +1 @@
+__program__
@@ -0,0 +1,7 @@
+[foo] is foo
+popdef([foo])[foo] is foo
+// foo is not expanded in a comment
+m4_define([greeting], [hello])greeting
+incr(41)
+define
+undivert(1)
@@ -0,0 +1,3 @@
+define(`foo', `bar $1')dnl
+foo(`a')
+len(foo(`b'))
@@ -0,0 +1 @@
+format(`%d', `1') patsubst(`a', `a', `b') __line__ len(`abc')
@@ -0,0 +1,7 @@
+debugmode(`aeq')dnl
+traceon(`len')dnl
+len(`shown')
+debugfile(`/dev/null')dnl
+len(`discarded')
+debugfile`'dnl
+len(`shown again')
@@ -0,0 +1,16 @@
+define(`foo', `bar $1')dnl
+define(`echo', `$@')dnl
+traceon(`foo', `echo')dnl
+foo
+debugmode(`aeq')dnl
+foo(`a', `b')
+echo(`gnus', `and gnats')
+debugmode(`-q')dnl
+foo(`a')
+debugmode(`+x')dnl
+foo(foo(`nested'))
+debugmode(`c')dnl
+foo(`collected')
+debugmode(`z')dnl
+debugmode
+foo(`silent')
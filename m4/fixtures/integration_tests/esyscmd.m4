@@ -0,0 +1,4 @@
+define(`hello', `Hello world')dnl
+esyscmd(`echo hello')dnl
+esyscmd(`printf "%s" "x"; exit 3') sysval
+define(`x', esyscmd(`echo "a b"'))x
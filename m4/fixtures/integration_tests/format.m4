@@ -0,0 +1,8 @@
+define(`foo', `The brown fox jumped over the lazy dog')dnl
+format(`The string "%s" uses %d characters', foo, len(foo))
+format(`%*.*d', `-1', `-1', `1')
+format(`%.0f', `56789.9876')
+format(`%10.3e|%-10g|%G', `12345.678', `0.0001', `1e10')
+format(`%x %X %#o %#x %c%c', `255', `255', `8', `255', `72', `105')
+format(`%+d % d %05d %-5d|', `5', `5', `-42', `3')
+format(`%.3s|%5s|%%', `abcdef', `ab')
@@ -0,0 +1,10 @@
+define(`$$internal$macro', `Internal macro (name `$0')')dnl
+$$internal$macro
+indir(`$$internal$macro')
+define(`f', `[$1|$2|$#]')dnl
+indir(`f', `a', `b')
+undefine(`define')dnl
+define(`x', `y')
+builtin(`define', `x', `z')dnl
+x
+builtin(`len', `hello')
@@ -0,0 +1,6 @@
+__line__
+line __line__ here
+
+define(`where', `line __line__')dnl
+where
+format(`%d', __line__)
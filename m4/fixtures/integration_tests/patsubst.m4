@@ -0,0 +1,8 @@
+patsubst(`GNUs not Unix', `^', `OBS: ')
+patsubst(`GNUs not Unix', `\<', `OBS: ')
+patsubst(`GNUs not Unix', `\w*', `(\&)')
+patsubst(`GNUs not Unix', `\w+', `(\&)')
+patsubst(`GNUs not Unix', `[A-Z][a-z]+')
+define(`upcase', `translit(`$*', `abcdefghijklmnopqrstuvwxyz', `ABCDEFGHIJKLMNOPQRSTUVWXYZ')')dnl
+define(`capitalize', `patsubst(`$1', `\(\w\)\(\w*\)', `upcase(`\1')`\2'')')dnl
+capitalize(`hello world')
@@ -0,0 +1,6 @@
+regexp(`GNUs not Unix', `\<[a-z]\w+')
+regexp(`GNUs not Unix', `\<Q\w*')
+regexp(`GNUs not Unix', `\w\(\w+\)$', `*** \& *** \1 ***')
+regexp(`GNUs not Unix', `\<Q\w*', `*** \& *** \1 ***')
+regexp(`abc', `\(b\)', `\\\1\\')
+regexp(`abc', `(b)', `x')
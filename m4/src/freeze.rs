@@ -0,0 +1,254 @@
+//! Frozen state files, in the format used by GNU m4. See [`crate::Args::freeze_state`] and
+//! [`crate::Args::reload_state`].
+//!
+//! A frozen file starts with the version line `V1`, followed by records which each start with a
+//! letter and the lengths of the strings that follow. For example `T3,5\nfoohello\n` defines the
+//! macro `foo` as `hello`. Lines starting with `#` are comments.
+//!
+//! * `Q` the open and close quote strings.
+//! * `C` the open and close comment strings.
+//! * `F` a macro name and the name of the builtin it calls.
+//! * `T` a macro name and its defining text.
+//! * `D` a diversion number and its contents, the last one selects the current diversion.
+//!
+//! Definitions of the same macro appear in the order they were pushed, so reloading restores the
+//! `pushdef` stacks.
+
+use std::{io::Write, rc::Rc};
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    lexer::{MacroName, ParseConfig},
+    macros::{BuiltinMacro, MacroDefinition, MacroDefinitionImplementation},
+    output::DivertBufferNumber,
+    state::State,
+};
+
+fn write_record(writer: &mut dyn Write, kind: char, first: &[u8], second: &[u8]) -> Result<()> {
+    writeln!(writer, "{kind}{},{}", first.len(), second.len())?;
+    writer.write_all(first)?;
+    writer.write_all(second)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Write the macro definitions, quote and comment strings and diversions of `state`.
+pub(crate) fn freeze(state: &State, writer: &mut dyn Write) -> Result<()> {
+    writeln!(writer, "# This is a frozen state file generated by m4")?;
+    writeln!(writer, "V1")?;
+
+    let config = &state.parse_config;
+    let default = ParseConfig::default();
+    if config.quote_open_tag != default.quote_open_tag
+        || config.quote_close_tag != default.quote_close_tag
+    {
+        write_record(writer, 'Q', &config.quote_open_tag, &config.quote_close_tag)?;
+    }
+    if !config.comment_enabled {
+        write_record(writer, 'C', b"", b"")?;
+    } else if config.comment_open_tag != default.comment_open_tag
+        || config.comment_close_tag != default.comment_close_tag
+    {
+        write_record(
+            writer,
+            'C',
+            &config.comment_open_tag,
+            &config.comment_close_tag,
+        )?;
+    }
+
+    // Sorted so that the same state always produces the same file.
+    let mut names: Vec<&MacroName> = state.macro_definitions.keys().collect();
+    names.sort_by(|a, b| a.0.cmp(&b.0));
+    for name in names {
+        for definition in &state.macro_definitions[name] {
+            match &definition.implementation {
+                MacroDefinitionImplementation::UserDefined(user_defined) => {
+                    write_record(writer, 'T', &name.0, &user_defined.definition)?;
+                }
+                implementation => {
+                    let builtin = implementation
+                        .builtin()
+                        .expect("Only user defined macros have no builtin");
+                    write_record(writer, 'F', &name.0, builtin.as_ref())?;
+                }
+            }
+        }
+    }
+
+    // Reloading a diversion also selects it, so the current diversion is restored afterwards.
+    let mut last_selected = 0;
+    for (buffer_number, contents) in state.output.output.diversions() {
+        writeln!(writer, "D{buffer_number},{}", contents.len())?;
+        writer.write_all(&contents)?;
+        writer.write_all(b"\n")?;
+        last_selected = buffer_number.divert_number();
+    }
+    let divert_number = state.output.output.divert_number();
+    if divert_number != last_selected {
+        writeln!(writer, "D{divert_number},0\n")?;
+    }
+    writeln!(writer, "# End of frozen state file")?;
+
+    Ok(())
+}
+
+struct FrozenReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> FrozenReader<'a> {
+    fn error(&self, message: &str) -> Error {
+        Error::new(ErrorKind::Parsing).add_context(format!(
+            "Invalid frozen file at byte {}: {message}",
+            self.position
+        ))
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.data.get(self.position).copied();
+        self.position += 1;
+        byte
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Read up to and including the next newline, returning the line without it.
+    fn line(&mut self) -> Result<&'a [u8]> {
+        let len = self.data[self.position.min(self.data.len())..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        let line = self.take(len)?;
+        self.position += 1;
+        Ok(line)
+    }
+
+    /// Read the two numbers of a record header, `first,second`.
+    fn header(&mut self) -> Result<(i64, usize)> {
+        let line = String::from_utf8_lossy(self.line()?).into_owned();
+        line.split_once(',')
+            .and_then(|(first, second)| Some((first.parse().ok()?, second.parse().ok()?)))
+            .ok_or_else(|| self.error(&format!("invalid record header {line:?}")))
+    }
+
+    /// Read a record with two strings.
+    fn strings(&mut self) -> Result<(&'a [u8], &'a [u8])> {
+        let (first_len, second_len) = self.header()?;
+        let first_len =
+            usize::try_from(first_len).map_err(|_| self.error("negative string length"))?;
+        let first = self.take(first_len)?;
+        let second = self.take(second_len)?;
+        self.end_of_record()?;
+        Ok((first, second))
+    }
+
+    fn end_of_record(&mut self) -> Result<()> {
+        if self.next_byte() != Some(b'\n') {
+            return Err(self.error("expected a newline at the end of the record"));
+        }
+        Ok(())
+    }
+}
+
+fn push_definition(state: &mut State, definition: MacroDefinition) {
+    state
+        .macro_definitions
+        .entry(definition.parse_config.name.clone())
+        .or_default()
+        .push(Rc::new(definition));
+}
+
+/// Restore a state written by [`freeze`] into `state`.
+pub(crate) fn reload(state: &mut State, data: &[u8]) -> Result<()> {
+    let mut reader = FrozenReader { data, position: 0 };
+    let mut version_read = false;
+    while let Some(kind) = reader.next_byte() {
+        match kind {
+            b'#' => {
+                reader.line()?;
+            }
+            b'\n' => {}
+            b'V' => {
+                let version = reader.line()?;
+                if version != b"1" {
+                    return Err(reader.error(&format!(
+                        "unsupported version {}",
+                        String::from_utf8_lossy(version)
+                    )));
+                }
+                version_read = true;
+            }
+            _ if !version_read => return Err(reader.error("expected a version record")),
+            b'Q' => {
+                let (open, close) = reader.strings()?;
+                // Empty quote strings, which disable quoting in GNU m4, are not supported.
+                if !open.is_empty() && !close.is_empty() {
+                    state.parse_config.quote_open_tag = open.to_vec();
+                    state.parse_config.quote_close_tag = close.to_vec();
+                }
+            }
+            b'C' => {
+                let (open, close) = reader.strings()?;
+                state.parse_config.comment_enabled = !open.is_empty();
+                if !open.is_empty() {
+                    state.parse_config.comment_open_tag = open.to_vec();
+                    state.parse_config.comment_close_tag = close.to_vec();
+                }
+            }
+            b'F' => {
+                let (name, builtin_name) = reader.strings()?;
+                let builtin = BuiltinMacro::from_name(builtin_name).ok_or_else(|| {
+                    reader.error(&format!(
+                        "unknown builtin {}",
+                        String::from_utf8_lossy(builtin_name)
+                    ))
+                })?;
+                push_definition(
+                    state,
+                    MacroDefinition::new_builtin(MacroName(name.to_vec()), builtin),
+                );
+            }
+            b'T' => {
+                let (name, definition) = reader.strings()?;
+                push_definition(
+                    state,
+                    MacroDefinition::new_user_defined(
+                        MacroName(name.to_vec()),
+                        definition.to_vec(),
+                    ),
+                );
+            }
+            b'D' => {
+                let (divert_number, len) = reader.header()?;
+                let contents = reader.take(len)?;
+                reader.end_of_record()?;
+                state.output.output.divert(divert_number)?;
+                if !contents.is_empty() {
+                    match usize::try_from(divert_number) {
+                        Ok(0) => state.output.output.write_all(contents)?,
+                        Ok(buffer_number) => state.output.output.append_diversion(
+                            DivertBufferNumber::try_from(buffer_number)?,
+                            contents,
+                        )?,
+                        // Negative diversions are discarded.
+                        Err(_) => {}
+                    }
+                }
+            }
+            _ => return Err(reader.error(&format!("unknown record type {:?}", char::from(kind)))),
+        }
+    }
+    if !version_read {
+        return Err(reader.error("expected a version record"));
+    }
+    Ok(())
+}
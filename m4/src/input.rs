@@ -49,6 +49,17 @@ impl InputState {
         Ok(input.get_next_character()?)
     }
 
+    /// Get the next character to be parsed without consuming it. Unlike reading a character and
+    /// pushing it back, this does not count a `<newline>` read from the input file as consumed,
+    /// which keeps [`Input::line_number`] accurate.
+    pub fn peek_next_character(&mut self) -> crate::error::Result<u8> {
+        let input = self.input.last_mut().unwrap();
+        if let Some(c) = input.pushback_buffer.last() {
+            return Ok(*c);
+        }
+        Ok(input.peek_next_character()?)
+    }
+
     pub fn pushback_character(&mut self, c: u8) {
         self.input.last_mut().unwrap().pushback_buffer.push(c);
     }
@@ -112,13 +123,6 @@ impl InputStateRef {
         Self(Rc::new(RefCell::new(input_state)))
     }
 
-    pub fn with<F, T>(&self, f: F) -> T
-    where
-        F: Fn(&InputState) -> T,
-    {
-        f(&self.0.borrow())
-    }
-
     pub fn input_pop(&self) -> Option<Input> {
         self.0.borrow_mut().input_pop()
     }
@@ -135,14 +139,78 @@ impl InputStateRef {
         self.0.borrow_mut().get_next_character()
     }
 
+    pub fn peek_next_character(&self) -> crate::error::Result<u8> {
+        self.0.borrow_mut().peek_next_character()
+    }
+
     pub fn pushback_character(&self, c: u8) {
         self.0.borrow_mut().pushback_character(c)
     }
 
+    /// The line number of the input currently being read.
+    pub fn line_number(&self) -> usize {
+        self.0
+            .borrow()
+            .input
+            .last()
+            .expect("at least one input")
+            .line_number
+    }
+
+    /// The name of the input currently being read, `stdin` for standard input.
+    pub fn file_name(&self) -> Vec<u8> {
+        match &self
+            .0
+            .borrow()
+            .input
+            .last()
+            .expect("at least one input")
+            .input
+        {
+            InputRead::File { path, .. } => path.as_os_str().as_encoded_bytes().to_vec(),
+            InputRead::Stdin(_) => b"stdin".to_vec(),
+        }
+    }
+
     pub fn pushback_string(&self, s: &[u8]) {
         self.0.borrow_mut().pushback_string(s)
     }
 
+    /// The current position in the pushback buffer, see [`InputStateRef::pushed_back_since`].
+    pub fn pushback_marker(&self) -> PushbackMarker {
+        let input_state = self.0.borrow();
+        PushbackMarker {
+            input_len: input_state.input.len(),
+            pushback_len: input_state
+                .input
+                .last()
+                .expect("at least one input")
+                .pushback_buffer
+                .len(),
+        }
+    }
+
+    /// The text which has been pushed back to be read next since `marker` was taken. This is
+    /// empty if the pushback buffer was consumed or a new input was pushed in the meantime.
+    pub fn pushed_back_since(&self, marker: PushbackMarker) -> Vec<u8> {
+        let input_state = self.0.borrow();
+        if input_state.input.len() != marker.input_len {
+            return Vec::new();
+        }
+        let pushback_buffer = &input_state
+            .input
+            .last()
+            .expect("at least one input")
+            .pushback_buffer;
+        pushback_buffer
+            .get(marker.pushback_len..)
+            .unwrap_or_default()
+            .iter()
+            .rev()
+            .copied()
+            .collect()
+    }
+
     pub fn look_ahead(&self, c: u8, token: &[u8]) -> crate::error::Result<bool> {
         self.0.borrow_mut().look_ahead(c, token)
     }
@@ -162,9 +230,18 @@ impl InputStateRef {
     }
 }
 
+/// See [`InputStateRef::pushback_marker`].
+pub struct PushbackMarker {
+    input_len: usize,
+    pushback_len: usize,
+}
+
 pub struct Input {
     pub input: InputRead,
     pub pushback_buffer: Vec<u8>,
+    /// A character read from [`Input::input`] by [`Input::peek_next_character`] which has not yet
+    /// been consumed.
+    peeked: Option<u8>,
     pub line_number: usize,
     pub syncline_line_number: usize,
 }
@@ -174,12 +251,35 @@ impl Input {
         Self {
             input,
             pushback_buffer: Vec::new(),
+            peeked: None,
             line_number: 1,
             syncline_line_number: 0,
         }
     }
 
     fn get_next_character(&mut self) -> std::io::Result<u8> {
+        let c = match self.peeked.take() {
+            Some(c) => c,
+            None => self.read_character()?,
+        };
+
+        if c == b'\n' {
+            self.line_number += 1;
+        }
+
+        Ok(c)
+    }
+
+    fn peek_next_character(&mut self) -> std::io::Result<u8> {
+        if let Some(c) = self.peeked {
+            return Ok(c);
+        }
+        let c = self.read_character()?;
+        self.peeked = Some(c);
+        Ok(c)
+    }
+
+    fn read_character(&mut self) -> std::io::Result<u8> {
         let mut buf: [u8; 1] = [0; 1];
         let n = match &mut self.input {
             InputRead::File { file, .. } => file.read(&mut buf),
@@ -190,13 +290,7 @@ impl Input {
             return Ok(EOF);
         }
 
-        let c = buf[0];
-
-        if c == b'\n' {
-            self.line_number += 1;
-        }
-
-        Ok(c)
+        Ok(buf[0])
     }
 
     fn emit_syncline(
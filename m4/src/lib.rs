@@ -1,11 +1,12 @@
-use error::{Error, ErrorKind, Result};
+use error::{Error, ErrorKind, Result, ResultExt};
 use input::{Input, InputRead};
 use lexer::MacroName;
-use macros::MacroDefinition;
+use macros::{trace::DebugOutput, BuiltinMacro, MacroDefinition};
 use state::State;
 use std::{cell::RefCell, ffi::OsStr, io::Write, path::PathBuf, rc::Rc};
 
 pub mod error;
+mod freeze;
 mod input;
 mod lexer;
 mod macros;
@@ -70,6 +71,26 @@ pub struct Args {
     pub line_synchronization: bool,
     /// See [`DefineDirective`].
     pub define_directives: Vec<DefineDirective>,
+    /// Suppress all GNU extensions, only the builtin macros specified by POSIX are defined.
+    pub traditional: bool,
+    /// Prefix the names of all builtin macros with `m4_`.
+    pub prefix_builtins: bool,
+    /// Directories searched, in order, for included files and input files which are not found
+    /// relative to the current directory.
+    pub include_directories: Vec<PathBuf>,
+    /// Once all input has been processed, write the state (macro definitions, quote and comment
+    /// strings and diversions) to this file instead of outputting the diversions.
+    pub freeze_state: Option<PathBuf>,
+    /// Restore the state from this file, previously written with [`Args::freeze_state`], before
+    /// processing any input. The builtin macros are then only the ones defined in the file.
+    pub reload_state: Option<PathBuf>,
+    /// Debug flags, as accepted by the `debugmode` macro. An empty string selects the default
+    /// flags `aeq`.
+    pub debug_flags: Option<String>,
+    /// Macros to trace from the start, as with `traceon`.
+    pub trace: Vec<MacroName>,
+    /// Append trace output to this file instead of standard error.
+    pub debugfile: Option<PathBuf>,
     /// Whether to read input from a file.
     pub files: Vec<PathBuf>,
 }
@@ -98,10 +119,113 @@ impl Args {
                     .num_args(1)
                     .action(clap::ArgAction::Append),
             )
+            .arg(
+                clap::Arg::new("traditional")
+                    .short('G')
+                    .long("traditional")
+                    .help("Suppress all GNU extensions")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("prefix_builtins")
+                    .short('P')
+                    .long("prefix-builtins")
+                    .help("Force a `m4_' prefix to all builtins")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("include")
+                    .short('I')
+                    .long("include")
+                    .value_name("directory")
+                    .help("Append directory to the include path")
+                    .num_args(1)
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(
+                clap::Arg::new("freeze_state")
+                    .short('F')
+                    .long("freeze-state")
+                    .value_name("file")
+                    .help("Produce a frozen state on file at the end")
+                    .num_args(1),
+            )
+            .arg(
+                clap::Arg::new("reload_state")
+                    .short('R')
+                    .long("reload-state")
+                    .value_name("file")
+                    .help("Reload a frozen state from file at the start")
+                    .num_args(1),
+            )
+            .arg(
+                clap::Arg::new("debug")
+                    .short('d')
+                    .long("debug")
+                    .value_name("flags")
+                    .help("Set debug level (no flags implies `aeq')")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value(""),
+            )
+            .arg(
+                clap::Arg::new("trace")
+                    .short('t')
+                    .long("trace")
+                    .value_name("name")
+                    .help("Trace name when it is defined")
+                    .num_args(1)
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(
+                clap::Arg::new("debugfile")
+                    .long("debugfile")
+                    .value_name("file")
+                    .help("Redirect debug and trace output to file")
+                    .num_args(1),
+            )
             .arg(clap::Arg::new("file").action(clap::ArgAction::Append))
-            .get_matches();
+            .get_matches_from(std::env::args_os().map(|arg| {
+                // Like GNU m4, flags are attached to `-d` (`-daeq`), which clap only supports in
+                // the form `-d=aeq` for an optional value.
+                match arg.as_encoded_bytes() {
+                    [b'-', b'd', flags @ ..] if !flags.is_empty() && flags[0] != b'=' => {
+                        let mut debug = std::ffi::OsString::from("--debug=");
+                        debug.push(&arg.to_string_lossy()[2..]);
+                        debug
+                    }
+                    _ => arg,
+                }
+            }));
 
         let line_synchronization = matches.get_flag("line_synchronization");
+        let traditional = matches.get_flag("traditional");
+        let prefix_builtins = matches.get_flag("prefix_builtins");
+
+        let include_directories = matches
+            .get_raw("include")
+            .unwrap_or_default()
+            .map(PathBuf::from)
+            .collect();
+        let freeze_state = matches
+            .get_raw("freeze_state")
+            .and_then(|mut values| values.next())
+            .map(PathBuf::from);
+        let reload_state = matches
+            .get_raw("reload_state")
+            .and_then(|mut values| values.next())
+            .map(PathBuf::from);
+
+        let debug_flags = matches.get_one::<String>("debug").cloned();
+        let trace = matches
+            .get_raw("trace")
+            .unwrap_or_default()
+            .map(|value| MacroName::parse_cmd(value).expect("Invalid -t argument trace"))
+            .collect();
+        let debugfile = matches
+            .get_raw("debugfile")
+            .and_then(|mut values| values.next())
+            .map(PathBuf::from);
 
         let files = matches
             .get_raw("file")
@@ -128,6 +252,14 @@ impl Args {
         Self {
             line_synchronization,
             define_directives,
+            traditional,
+            prefix_builtins,
+            include_directories,
+            freeze_state,
+            reload_state,
+            debug_flags,
+            trace,
+            debugfile,
             files,
         }
     }
@@ -159,6 +291,33 @@ pub fn run_impl<STDOUT: Write + 'static, STDERR: Write>(
 ) -> crate::error::Result<()> {
     let stdout = Rc::new(RefCell::new(stdout));
     let mut state = State::try_new(stdout.clone(), Vec::new(), args.line_synchronization)?;
+    if let Some(spec) = args.debug_flags {
+        state.trace.flags.update(spec.as_bytes()).map_err(|_| {
+            Error::new(ErrorKind::Parsing).add_context(format!("Bad debug flags: {spec:?}"))
+        })?;
+    }
+    for name in args.trace {
+        state.trace.include(name);
+    }
+    if let Some(path) = args.debugfile {
+        state.trace.output = DebugOutput::open(&path)
+            .map_err(Error::from)
+            .add_context(|| format!("Error opening debug file {path:?}"))?;
+    }
+    state.include_directories = args.include_directories;
+    state.freeze = args.freeze_state.is_some();
+    if let Some(path) = args.reload_state {
+        let path = state.resolve_path(path);
+        let data = std::fs::read(&path)
+            .map_err(Error::from)
+            .add_context(|| format!("Error reading frozen file {path:?}"))?;
+        state.macro_definitions.clear();
+        freeze::reload(&mut state, &data)
+            .add_context(|| format!("Error reloading frozen file {path:?}"))?;
+    } else {
+        state.macro_definitions =
+            BuiltinMacro::definitions(!args.traditional, args.prefix_builtins);
+    }
     if args.files.is_empty() {
         state.input.input_push(
             Input::new(InputRead::Stdin(std::io::stdin())),
@@ -166,6 +325,7 @@ pub fn run_impl<STDOUT: Write + 'static, STDERR: Write>(
         )?;
     } else {
         for file_path in args.files {
+            let file_path = state.resolve_path(file_path);
             state.input.input_push(
                 Input::new(InputRead::File {
                     file: std::fs::File::open(&file_path)?,
@@ -194,7 +354,15 @@ pub fn run_impl<STDOUT: Write + 'static, STDERR: Write>(
         }
     }
 
-    main_loop::main_loop(state, &mut stderr)?;
+    let state = main_loop::main_loop(state, &mut stderr)?;
+
+    if let Some(path) = args.freeze_state {
+        stdout.borrow_mut().flush()?;
+        let mut file = std::fs::File::create(&path)
+            .map_err(Error::from)
+            .add_context(|| format!("Error creating frozen file {path:?}"))?;
+        freeze::freeze(&state, &mut file)?;
+    }
 
     Ok(())
 }
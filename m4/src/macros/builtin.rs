@@ -54,16 +54,14 @@ impl DefineMacro {
         frame: StackFrame,
     ) -> Result<(State, Option<MacroDefinition>)> {
         let mut args = frame.args.into_iter();
+        // Like GNU m4, names which are not valid macro names can be defined. Such macros can
+        // only be called using `indir`.
         let name = if let Some(name_bytes) = args.next() {
-            if let Ok(name) = MacroName::try_from_slice(&name_bytes) {
-                name
-            } else {
-                log::warn!(
-                    "Invalid macro name {:?}, skipping definition",
-                    String::from_utf8_lossy(&name_bytes)
-                );
+            if name_bytes.is_empty() {
+                log::warn!("Empty macro name, skipping definition");
                 return Ok((state, None));
             }
+            MacroName(name_bytes)
         } else {
             log::warn!("No macro name specified, skipping definition");
             return Ok((state, None));
@@ -238,12 +236,15 @@ impl MacroImplementation for ErrprintMacro {
 /// The defining text for the include macro shall be the contents of the file named by the first
 /// argument. It shall be an error if the file cannot be read. The behavior is unspecified if
 /// include is not immediately followed by a `<left-parenthesis>`.
+///
+/// As in GNU m4, a relative path which doesn't exist in the current directory is searched for in
+/// each of the directories given with `-I`.
 pub struct IncludeMacro;
 
 impl IncludeMacro {
     fn get_file_path(frame: StackFrame, state: State) -> Result<(Option<PathBuf>, State)> {
         if let Some(arg) = frame.args.into_iter().next() {
-            let path = PathBuf::from(OsString::from_vec(arg));
+            let path = state.resolve_path(PathBuf::from(OsString::from_vec(arg)));
             Ok((Some(path), state))
         } else {
             Ok((None, state))
@@ -1062,12 +1063,7 @@ pub struct FileMacro;
 
 impl MacroImplementation for FileMacro {
     fn evaluate(&self, state: State, _stderr: &mut dyn Write, _frame: StackFrame) -> Result<State> {
-        let name = state.input.with(|input_state| {
-            match &input_state.input.last().expect("At least one input").input {
-                InputRead::File { path, .. } => path.as_os_str().as_encoded_bytes().to_vec(),
-                InputRead::Stdin(_) => b"stdin".to_vec(),
-            }
-        });
+        let name = state.input.file_name();
         state.input.pushback_string(&name);
         Ok(state)
     }
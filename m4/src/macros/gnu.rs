@@ -0,0 +1,650 @@
+//! Builtin macros which are extensions provided by GNU m4. They are not defined when running with
+//! `-G`/`--traditional`. See <https://www.gnu.org/software/m4/manual/m4.html>.
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::ops::Range;
+use std::os::unix::ffi::OsStrExt;
+use std::rc::Rc;
+
+use plib::regex::{Regex, RegexFlags};
+
+use super::{BuiltinMacro, MacroDefinition, MacroImplementation};
+use crate::error::Result;
+use crate::lexer::MacroName;
+use crate::state::{StackFrame, State};
+
+/// Translates a regular expression from the GNU Emacs syntax used by GNU m4, where `+` and `?` are
+/// operators and `\{` is a literal brace, to a POSIX basic regular expression with the GNU `\+`,
+/// `\?` and `\|` operators.
+fn translate_regex(pattern: &[u8]) -> Vec<u8> {
+    let mut translated = Vec::with_capacity(pattern.len());
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'\\' if i + 1 < pattern.len() => {
+                let c = pattern[i + 1];
+                if matches!(c, b'+' | b'?' | b'{' | b'}') {
+                    translated.push(c);
+                } else {
+                    translated.extend([b'\\', c]);
+                }
+                i += 2;
+                continue;
+            }
+            c @ (b'+' | b'?') => translated.extend([b'\\', c]),
+            b'[' => {
+                // Bracket expressions are copied unchanged, a `]` right after the opening bracket
+                // (or its negation) is part of the list.
+                let start = i;
+                i += 1;
+                if pattern.get(i) == Some(&b'^') {
+                    i += 1;
+                }
+                if pattern.get(i) == Some(&b']') {
+                    i += 1;
+                }
+                while i < pattern.len() && pattern[i] != b']' {
+                    if pattern[i] == b'[' && matches!(pattern.get(i + 1), Some(b':' | b'.' | b'='))
+                    {
+                        let delimiter = pattern[i + 1];
+                        i += 2;
+                        while i + 1 < pattern.len()
+                            && !(pattern[i] == delimiter && pattern[i + 1] == b']')
+                        {
+                            i += 1;
+                        }
+                        i += 2;
+                    } else {
+                        i += 1;
+                    }
+                }
+                let end = usize::min(i + 1, pattern.len());
+                translated.extend(&pattern[start..end]);
+                i = end;
+                continue;
+            }
+            c => translated.push(c),
+        }
+        i += 1;
+    }
+    translated
+}
+
+fn compile_regex(
+    pattern: &[u8],
+    macro_name: &str,
+    stderr: &mut dyn Write,
+) -> Result<Option<Regex>> {
+    match Regex::new(translate_regex(pattern), RegexFlags::default()) {
+        Ok(regex) => Ok(Some(regex)),
+        Err(error) => {
+            writeln!(
+                stderr,
+                "Warning: bad regular expression {:?} in builtin `{macro_name}': {error}",
+                String::from_utf8_lossy(pattern)
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Expands the `replacement` for a match, where `\N` is replaced by the text matched by the `N`th
+/// parenthesized subexpression, and `\0` or `\&` by the text of the entire match.
+fn substitute(replacement: &[u8], input: &[u8], captures: &[Option<Range<usize>>]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(replacement.len());
+    let mut i = 0;
+    while i < replacement.len() {
+        let c = replacement[i];
+        if c != b'\\' || i + 1 == replacement.len() {
+            result.push(c);
+            i += 1;
+            continue;
+        }
+        let group = match replacement[i + 1] {
+            b'&' => Some(0),
+            n @ b'0'..=b'9' => Some((n - b'0') as usize),
+            _ => None,
+        };
+        match group {
+            Some(group) => {
+                if let Some(Some(range)) = captures.get(group) {
+                    result.extend(&input[range.clone()]);
+                }
+            }
+            None if replacement[i + 1] == b'\\' => result.push(b'\\'),
+            None => result.extend(&replacement[i..i + 2]),
+        }
+        i += 2;
+    }
+    result
+}
+
+/// `patsubst(string, regexp, [replacement])` searches `string` for matches of `regexp`, and
+/// substitutes `replacement` for each match. Parts of `string` that are not matched are copied
+/// unchanged, so without a `replacement` the matches are deleted.
+pub struct PatsubstMacro;
+
+impl MacroImplementation for PatsubstMacro {
+    fn evaluate(&self, state: State, stderr: &mut dyn Write, frame: StackFrame) -> Result<State> {
+        let mut args = frame.args.into_iter();
+        let string = args.next().unwrap_or_default();
+        let pattern = match args.next() {
+            Some(pattern) => pattern,
+            None => {
+                writeln!(stderr, "Warning: too few arguments to builtin `patsubst'")?;
+                state.input.pushback_string(&string);
+                return Ok(state);
+            }
+        };
+        let replacement = args.next().unwrap_or_default();
+        let regex = match compile_regex(&pattern, "patsubst", stderr)? {
+            Some(regex) => regex,
+            None => return Ok(state),
+        };
+
+        let mut result = Vec::with_capacity(string.len());
+        let mut position = 0;
+        while position <= string.len() {
+            let captures = match regex.captures_at(&string, position) {
+                Some(captures) => captures,
+                None => break,
+            };
+            let matched = captures[0]
+                .clone()
+                .expect("the whole match is always captured");
+            result.extend(&string[position..matched.start]);
+            result.extend(substitute(&replacement, &string, &captures));
+            if matched.is_empty() {
+                // Skip a character so that an empty match is not found again at the same place.
+                if let Some(c) = string.get(matched.start) {
+                    result.push(*c);
+                }
+                position = matched.start + 1;
+            } else {
+                position = matched.end;
+            }
+        }
+        if position < string.len() {
+            result.extend(&string[position..]);
+        }
+
+        state.input.pushback_string(&result);
+        Ok(state)
+    }
+}
+
+/// `regexp(string, regexp, [replacement])` searches for `regexp` in `string`. Without a
+/// `replacement` it expands to the index of the first match, or `-1` if there is no match. With a
+/// `replacement`, it expands to the `replacement` for the first match, or to nothing.
+pub struct RegexpMacro;
+
+impl MacroImplementation for RegexpMacro {
+    fn evaluate(&self, state: State, stderr: &mut dyn Write, frame: StackFrame) -> Result<State> {
+        let mut args = frame.args.into_iter();
+        let string = args.next().unwrap_or_default();
+        let pattern = match args.next() {
+            Some(pattern) => pattern,
+            None => {
+                writeln!(stderr, "Warning: too few arguments to builtin `regexp'")?;
+                state.input.pushback_character(b'0');
+                return Ok(state);
+            }
+        };
+        let regex = match compile_regex(&pattern, "regexp", stderr)? {
+            Some(regex) => regex,
+            None => return Ok(state),
+        };
+
+        let captures = regex.captures(&string);
+        match (args.next(), captures) {
+            (None, Some(captures)) => {
+                let start = captures[0].as_ref().map(|m| m.start).unwrap_or_default();
+                state.input.pushback_string(start.to_string().as_bytes());
+            }
+            (None, None) => state.input.pushback_string(b"-1"),
+            (Some(replacement), Some(captures)) => {
+                state
+                    .input
+                    .pushback_string(&substitute(&replacement, &string, &captures));
+            }
+            (Some(_), None) => {}
+        }
+        Ok(state)
+    }
+}
+
+/// A conversion specification of [`FormatMacro`].
+#[derive(Default)]
+struct FormatSpec {
+    left_justify: bool,
+    plus_sign: bool,
+    space_sign: bool,
+    alternate: bool,
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus_sign {
+            "+"
+        } else if self.space_sign {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Writes `bytes` to `result`, padded with spaces to the field width.
+    fn pad_bytes(&self, bytes: &[u8], result: &mut Vec<u8>) {
+        let padding = std::iter::repeat(b' ').take(self.width.saturating_sub(bytes.len()));
+        if self.left_justify {
+            result.extend(bytes);
+            result.extend(padding);
+        } else {
+            result.extend(padding);
+            result.extend(bytes);
+        }
+    }
+
+    /// Pads the converted value to the field width. Zero padding is inserted between the `prefix`
+    /// (sign and base indicator) and the `body` when `zero_pad` is allowed.
+    fn pad(&self, prefix: &str, body: &str, zero_pad: bool) -> String {
+        let len = prefix.len() + body.len();
+        if len >= self.width {
+            return format!("{prefix}{body}");
+        }
+        let padding = self.width - len;
+        if self.left_justify {
+            format!("{prefix}{body}{}", " ".repeat(padding))
+        } else if zero_pad && self.zero_pad {
+            format!("{prefix}{}{body}", "0".repeat(padding))
+        } else {
+            format!("{}{prefix}{body}", " ".repeat(padding))
+        }
+    }
+}
+
+/// Formats a non-negative finite number like the `%e` conversion of `printf`.
+fn format_exponential(value: f64, precision: usize, uppercase: bool) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exponent) = formatted.split_once('e').expect("exponential format");
+    let exponent: i32 = exponent.parse().expect("valid exponent");
+    let e = if uppercase { 'E' } else { 'e' };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}{e}{sign}{:02}", exponent.abs())
+}
+
+fn strip_trailing_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+/// Formats a non-negative finite number like the `%f`, `%e` and `%g` conversions of `printf`.
+fn format_float(value: f64, conversion: u8, spec: &FormatSpec) -> String {
+    let uppercase = conversion.is_ascii_uppercase();
+    let precision = spec.precision.unwrap_or(6);
+    let mut formatted = match conversion.to_ascii_lowercase() {
+        b'f' => format!("{value:.precision$}"),
+        b'e' => format_exponential(value, precision, uppercase),
+        _ => {
+            let precision = precision.max(1);
+            let exponent = if value == 0.0 {
+                0
+            } else {
+                let formatted = format_exponential(value, precision - 1, false);
+                let (_, exponent) = formatted.split_once('e').expect("exponential format");
+                exponent.parse::<i64>().expect("valid exponent")
+            };
+            if exponent < -4 || exponent >= precision as i64 {
+                let formatted = format_exponential(value, precision - 1, uppercase);
+                if spec.alternate {
+                    formatted
+                } else {
+                    let (mantissa, exponent) = formatted
+                        .split_once(['e', 'E'])
+                        .expect("exponential format");
+                    let e = if uppercase { 'E' } else { 'e' };
+                    format!("{}{e}{exponent}", strip_trailing_zeros(mantissa))
+                }
+            } else {
+                let decimals = (precision as i64 - 1 - exponent) as usize;
+                let formatted = format!("{value:.decimals$}");
+                if spec.alternate {
+                    formatted
+                } else {
+                    strip_trailing_zeros(&formatted).to_string()
+                }
+            }
+        }
+    };
+    // The alternate form always contains a decimal point.
+    if spec.alternate && !formatted.contains('.') {
+        match formatted.find(['e', 'E']) {
+            Some(i) => formatted.insert(i, '.'),
+            None => formatted.push('.'),
+        }
+    }
+    formatted
+}
+
+/// Parses an argument of [`FormatMacro`] as an integer, like `strtol`.
+fn integer_argument(arg: &[u8], stderr: &mut dyn Write) -> Result<i64> {
+    let text = String::from_utf8_lossy(arg);
+    let trimmed = text.trim_start();
+    let digits_start = usize::from(trimmed.starts_with(['-', '+']));
+    let digits_end = trimmed[digits_start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map(|i| i + digits_start)
+        .unwrap_or(trimmed.len());
+    if digits_end != trimmed.len() || digits_end == digits_start {
+        writeln!(
+            stderr,
+            "Warning: non-numeric argument {text:?} to builtin `format'"
+        )?;
+    }
+    Ok(trimmed[..digits_end].parse().unwrap_or_default())
+}
+
+/// Parses an argument of [`FormatMacro`] as a floating point number, like `strtod`.
+fn float_argument(arg: &[u8], stderr: &mut dyn Write) -> Result<f64> {
+    let text = String::from_utf8_lossy(arg);
+    match text.trim().parse() {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            writeln!(
+                stderr,
+                "Warning: non-numeric argument {text:?} to builtin `format'"
+            )?;
+            Ok(0.0)
+        }
+    }
+}
+
+/// Formats `args` according to the `printf` style `format` string.
+fn format(format: &[u8], args: &[Vec<u8>], stderr: &mut dyn Write) -> Result<Vec<u8>> {
+    let mut args = args.iter();
+    let mut next_arg = || args.next().map(Vec::as_slice).unwrap_or_default();
+    let mut result = Vec::with_capacity(format.len());
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            result.push(format[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        let mut spec = FormatSpec::default();
+        while let Some(flag) = format.get(i) {
+            match flag {
+                b'-' => spec.left_justify = true,
+                b'+' => spec.plus_sign = true,
+                b' ' => spec.space_sign = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero_pad = true,
+                // Thousands grouping is not supported in the C locale.
+                b'\'' => {}
+                _ => break,
+            }
+            i += 1;
+        }
+        if format.get(i) == Some(&b'*') {
+            let width = integer_argument(next_arg(), stderr)?;
+            spec.left_justify |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+            i += 1;
+        } else {
+            while let Some(digit @ b'0'..=b'9') = format.get(i) {
+                spec.width = spec.width * 10 + (digit - b'0') as usize;
+                i += 1;
+            }
+        }
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            if format.get(i) == Some(&b'*') {
+                let precision = integer_argument(next_arg(), stderr)?;
+                spec.precision = usize::try_from(precision).ok();
+                i += 1;
+            } else {
+                let mut precision = 0;
+                while let Some(digit @ b'0'..=b'9') = format.get(i) {
+                    precision = precision * 10 + (digit - b'0') as usize;
+                    i += 1;
+                }
+                spec.precision = Some(precision);
+            }
+        }
+        // Length modifiers have no effect.
+        while matches!(
+            format.get(i),
+            Some(b'h' | b'l' | b'L' | b'q' | b'j' | b'z' | b't')
+        ) {
+            i += 1;
+        }
+
+        let conversion = match format.get(i) {
+            Some(conversion) => *conversion,
+            None => {
+                writeln!(
+                    stderr,
+                    "Warning: incomplete specification in builtin `format'"
+                )?;
+                break;
+            }
+        };
+        i += 1;
+
+        let converted = match conversion {
+            b'%' => "%".to_string(),
+            b'c' => {
+                let c = integer_argument(next_arg(), stderr)? as u8;
+                spec.pad_bytes(&[c], &mut result);
+                continue;
+            }
+            b's' => {
+                let arg = next_arg();
+                let arg = match spec.precision {
+                    Some(precision) if precision < arg.len() => &arg[..precision],
+                    _ => arg,
+                };
+                spec.pad_bytes(arg, &mut result);
+                continue;
+            }
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+                let value = integer_argument(next_arg(), stderr)?;
+                let (negative, magnitude) = match conversion {
+                    b'd' | b'i' => (value < 0, value.unsigned_abs()),
+                    _ => (false, value as u64),
+                };
+                let mut digits = match conversion {
+                    b'o' => format!("{magnitude:o}"),
+                    b'x' => format!("{magnitude:x}"),
+                    b'X' => format!("{magnitude:X}"),
+                    _ => magnitude.to_string(),
+                };
+                if spec.precision == Some(0) && magnitude == 0 {
+                    digits.clear();
+                }
+                if let Some(precision) = spec.precision {
+                    if digits.len() < precision {
+                        digits.insert_str(0, &"0".repeat(precision - digits.len()));
+                    }
+                }
+                let prefix = match conversion {
+                    b'o' if spec.alternate && !digits.starts_with('0') => "0",
+                    b'x' if spec.alternate && magnitude != 0 => "0x",
+                    b'X' if spec.alternate && magnitude != 0 => "0X",
+                    b'd' | b'i' => spec.sign(negative),
+                    _ => "",
+                };
+                spec.pad(prefix, &digits, spec.precision.is_none())
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let value = float_argument(next_arg(), stderr)?;
+                let sign = spec.sign(value.is_sign_negative() && !value.is_nan());
+                if value.is_finite() {
+                    spec.pad(sign, &format_float(value.abs(), conversion, &spec), true)
+                } else {
+                    let body = if value.is_nan() { "nan" } else { "inf" };
+                    let body = if conversion.is_ascii_uppercase() {
+                        body.to_ascii_uppercase()
+                    } else {
+                        body.to_string()
+                    };
+                    spec.pad(sign, &body, false)
+                }
+            }
+            _ => {
+                writeln!(
+                    stderr,
+                    "Warning: unrecognized specifier in builtin `format': %{}",
+                    conversion as char
+                )?;
+                continue;
+            }
+        };
+        result.extend(converted.as_bytes());
+    }
+    Ok(result)
+}
+
+/// `format(format-string, …)` works much like the C function `printf`. The first argument is a
+/// format string containing `%` specifications, and the expansion is the formatted string.
+pub struct FormatMacro;
+
+impl MacroImplementation for FormatMacro {
+    fn evaluate(&self, state: State, stderr: &mut dyn Write, frame: StackFrame) -> Result<State> {
+        if let Some((format_string, args)) = frame.args.split_first() {
+            let formatted = format(format_string, args, stderr)?;
+            state.input.pushback_string(&formatted);
+        }
+        Ok(state)
+    }
+}
+
+/// `esyscmd(shell-command)` is like [`super::builtin::SyscmdMacro`], except that the standard
+/// output of the command becomes the defining text, which is rescanned for macros.
+pub struct EsyscmdMacro;
+
+impl MacroImplementation for EsyscmdMacro {
+    fn evaluate(
+        &self,
+        mut state: State,
+        _stderr: &mut dyn Write,
+        frame: StackFrame,
+    ) -> Result<State> {
+        let first_arg = frame
+            .args
+            .into_iter()
+            .next()
+            .ok_or_else(|| crate::Error::new(crate::ErrorKind::NotEnoughArguments))?;
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(OsStr::from_bytes(&first_arg))
+            .stderr(std::process::Stdio::inherit())
+            .output()?;
+        state.last_syscmd_status = Some(output.status);
+        state.input.pushback_string(&output.stdout);
+        Ok(state)
+    }
+}
+
+/// Calls `definition` with the arguments after the first one.
+fn call(
+    state: State,
+    stderr: &mut dyn Write,
+    frame: StackFrame,
+    definition: Rc<MacroDefinition>,
+) -> Result<State> {
+    let frame = StackFrame {
+        parenthesis_level: 0,
+        args: frame.args.into_iter().skip(1).collect(),
+        definition: definition.clone(),
+        call_id: frame.call_id,
+    };
+    definition.implementation.evaluate(state, stderr, frame)
+}
+
+/// `indir(name, …)` calls the macro `name` with the remaining arguments. This can be used to call
+/// macros with names that are not valid identifiers.
+pub struct IndirMacro;
+
+impl MacroImplementation for IndirMacro {
+    fn evaluate(&self, state: State, stderr: &mut dyn Write, frame: StackFrame) -> Result<State> {
+        let name = MacroName(frame.args.first().cloned().unwrap_or_default());
+        let definition = state
+            .macro_definitions
+            .get(&name)
+            .and_then(|definitions| definitions.last())
+            .cloned();
+        match definition {
+            Some(definition) => call(state, stderr, frame, definition),
+            None => {
+                writeln!(stderr, "Warning: undefined macro `{name}'")?;
+                Ok(state)
+            }
+        }
+    }
+}
+
+/// `builtin(name, …)` calls the builtin macro `name` with the remaining arguments, even if `name`
+/// has been undefined or given another definition.
+pub struct CallBuiltinMacro;
+
+impl MacroImplementation for CallBuiltinMacro {
+    fn evaluate(&self, state: State, stderr: &mut dyn Write, frame: StackFrame) -> Result<State> {
+        let name = frame.args.first().cloned().unwrap_or_default();
+        let builtin = BuiltinMacro::enumerate()
+            .iter()
+            .find(|builtin| builtin.as_ref() == name.as_slice());
+        match builtin {
+            Some(builtin) => {
+                let definition = Rc::new(MacroDefinition {
+                    parse_config: builtin.parse_config(),
+                    implementation: builtin.implementation(),
+                });
+                call(state, stderr, frame, definition)
+            }
+            None => {
+                writeln!(
+                    stderr,
+                    "Warning: undefined builtin `{}'",
+                    String::from_utf8_lossy(&name)
+                )?;
+                Ok(state)
+            }
+        }
+    }
+}
+
+/// The defining text of `__line__` is the current line number of the input file.
+pub struct LineMacro;
+
+impl MacroImplementation for LineMacro {
+    fn evaluate(&self, state: State, _stderr: &mut dyn Write, _frame: StackFrame) -> Result<State> {
+        let line_number = state.input.line_number();
+        state
+            .input
+            .pushback_string(line_number.to_string().as_bytes());
+        Ok(state)
+    }
+}
+
+/// The defining text of `__program__` is the name that m4 was invoked with.
+pub struct ProgramMacro;
+
+impl MacroImplementation for ProgramMacro {
+    fn evaluate(&self, state: State, _stderr: &mut dyn Write, _frame: StackFrame) -> Result<State> {
+        let program = std::env::args_os().next().unwrap_or_default();
+        state.input.pushback_string(program.as_bytes());
+        Ok(state)
+    }
+}
@@ -1,13 +1,17 @@
 mod builtin;
 mod eval;
+mod gnu;
 pub mod trace;
 mod user_defined;
 
+use std::collections::HashMap;
 use std::io::Write;
+use std::rc::Rc;
 
 use builtin::*;
 use eval::EvalMacro;
-use trace::{TraceoffMacro, TraceonMacro};
+use gnu::*;
+use trace::{DebugfileMacro, DebugmodeMacro, TraceoffMacro, TraceonMacro};
 use user_defined::UserDefinedMacro;
 
 use crate::{
@@ -36,15 +40,32 @@ macro_rules! macro_enums {
         impl MacroImplementation for MacroDefinitionImplementation {
             fn evaluate(
                 &self,
-                state: State,
+                mut state: State,
                 stderr: &mut dyn Write,
                 f: StackFrame,
             ) -> Result<State> {
-                state.trace.trace(&state.output.stack, &f, stderr)?;
-                match self {
+                let traced_call = state.trace.call(
+                    &state.output.stack,
+                    &f,
+                    &state.input,
+                    &state.parse_config,
+                    stderr,
+                )?;
+                let pushback_marker = state.input.pushback_marker();
+                let mut state = match self {
                     $(Self::$variant_name(d) => d.evaluate(state, stderr, f)),*,
                     Self::UserDefined(d) => d.evaluate(state, stderr, f),
+                }?;
+                if let Some(traced_call) = traced_call {
+                    let expansion = state.input.pushed_back_since(pushback_marker);
+                    state.trace.call_finished(
+                        traced_call,
+                        &expansion,
+                        &state.parse_config,
+                        stderr,
+                    )?;
                 }
+                Ok(state)
             }
         }
 
@@ -59,6 +80,16 @@ macro_rules! macro_enums {
                 }
             }
         }
+
+        impl MacroDefinitionImplementation {
+            /// The builtin macro that this implements, or `None` if it is user defined.
+            pub fn builtin(&self) -> Option<BuiltinMacro> {
+                match self {
+                    $(Self::$variant_name(_) => Some(BuiltinMacro::$variant_name)),*,
+                    Self::UserDefined(_) => None,
+                }
+            }
+        }
     };
 }
 
@@ -66,8 +97,11 @@ macro_rules! macro_enums {
 macro_enums!(
     #[derive(Clone, Copy)]
     pub enum BuiltinMacroDefinition {
+        Builtin(CallBuiltinMacro),
         Changecom(ChangecomMacro),
         Changequote(ChangequoteMacro),
+        Debugfile(DebugfileMacro),
+        Debugmode(DebugmodeMacro),
         Decr(DecrMacro),
         Define(DefineMacro),
         Defn(DefnMacro),
@@ -76,20 +110,27 @@ macro_enums!(
         Dnl(DnlMacro),
         Dumpdef(DumpdefMacro),
         Errprint(ErrprintMacro),
+        Esyscmd(EsyscmdMacro),
         Eval(EvalMacro),
         File(FileMacro),
+        Format(FormatMacro),
         Ifdef(IfdefMacro),
         Ifelse(IfelseMacro),
         Include(IncludeMacro),
         Incr(IncrMacro),
         Index(IndexMacro),
+        Indir(IndirMacro),
         Len(LenMacro),
+        Line(LineMacro),
         M4exit(M4exitMacro),
         M4wrap(M4wrapMacro),
         Maketemp(MkstempMacro),
         Mkstemp(MkstempMacro),
+        Patsubst(PatsubstMacro),
         Popdef(PopdefMacro),
+        Program(ProgramMacro),
         Pushdef(PushdefMacro),
+        Regexp(RegexpMacro),
         Shift(ShiftMacro),
         Sinclude(SincludeMacro),
         Substr(SubstrMacro),
@@ -107,8 +148,11 @@ impl AsRef<[u8]> for BuiltinMacro {
     fn as_ref(&self) -> &'static [u8] {
         use BuiltinMacro::*;
         match self {
+            Builtin => b"builtin",
             Changecom => b"changecom",
             Changequote => b"changequote",
+            Debugfile => b"debugfile",
+            Debugmode => b"debugmode",
             Decr => b"decr",
             Define => b"define",
             Defn => b"defn",
@@ -117,20 +161,27 @@ impl AsRef<[u8]> for BuiltinMacro {
             Dnl => b"dnl",
             Dumpdef => b"dumpdef",
             Errprint => b"errprint",
+            Esyscmd => b"esyscmd",
             Eval => b"eval",
             File => b"__file__",
+            Format => b"format",
             Ifdef => b"ifdef",
             Ifelse => b"ifelse",
             Include => b"include",
             Incr => b"incr",
             Index => b"index",
+            Indir => b"indir",
             Len => b"len",
+            Line => b"__line__",
             M4exit => b"m4exit",
             M4wrap => b"m4wrap",
             Maketemp => b"maketemp",
             Mkstemp => b"mkstemp",
+            Patsubst => b"patsubst",
             Popdef => b"popdef",
+            Program => b"__program__",
             Pushdef => b"pushdef",
+            Regexp => b"regexp",
             Shift => b"shift",
             Sinclude => b"sinclude",
             Substr => b"substr",
@@ -150,13 +201,24 @@ impl BuiltinMacro {
         MacroName::try_from_slice(self.as_ref()).expect("Expected valid builtin macro name")
     }
 
+    /// Look up a builtin by its unprefixed name.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        Self::enumerate()
+            .iter()
+            .find(|builtin| builtin.as_ref() == name)
+            .copied()
+    }
+
     /// The minimum number of args that this macro requires in order for it to be parsed as a
     /// macro.
     pub fn min_args(&self) -> usize {
         use BuiltinMacro::*;
         match self {
+            Builtin => 1,
             Changecom => 0,
             Changequote => 0,
+            Debugfile => 0,
+            Debugmode => 0,
             Decr => 1,
             Define => 1,
             Defn => 1,
@@ -165,20 +227,27 @@ impl BuiltinMacro {
             Dnl => 0,
             Dumpdef => 1,
             Errprint => 1,
+            Esyscmd => 1,
             Eval => 1,
             File => 0,
+            Format => 1,
             Ifdef => 1,
             Ifelse => 1,
             Include => 1,
             Incr => 1,
             Index => 1,
+            Indir => 1,
             Len => 1,
+            Line => 0,
             M4exit => 0,
             M4wrap => 1,
             Maketemp => 1,
             Mkstemp => 1,
+            Patsubst => 1,
             Popdef => 1,
+            Program => 0,
             Pushdef => 1,
+            Regexp => 1,
             Shift => 1,
             Sinclude => 1,
             Substr => 1,
@@ -198,6 +267,48 @@ impl BuiltinMacro {
             min_args: self.min_args(),
         }
     }
+
+    /// Whether this macro is a GNU extension, rather than one specified by POSIX.
+    pub fn is_gnu_extension(&self) -> bool {
+        use BuiltinMacro::*;
+        matches!(
+            self,
+            Builtin
+                | Debugfile
+                | Debugmode
+                | Esyscmd
+                | File
+                | Format
+                | Indir
+                | Line
+                | Patsubst
+                | Program
+                | Regexp
+        )
+    }
+
+    /// The initial macro definitions. GNU extensions are only defined if `gnu_extensions` is
+    /// `true`, and with `prefix_builtins` the name of every builtin is prefixed with `m4_`.
+    pub fn definitions(
+        gnu_extensions: bool,
+        prefix_builtins: bool,
+    ) -> HashMap<MacroName, Vec<Rc<MacroDefinition>>> {
+        Self::enumerate()
+            .iter()
+            .filter(|builtin| gnu_extensions || !builtin.is_gnu_extension())
+            .map(|builtin| {
+                let name = if prefix_builtins {
+                    MacroName([b"m4_", builtin.as_ref()].concat())
+                } else {
+                    builtin.name()
+                };
+                (
+                    name.clone(),
+                    vec![Rc::new(MacroDefinition::new_builtin(name, *builtin))],
+                )
+            })
+            .collect()
+    }
 }
 
 pub(crate) struct MacroDefinition {
@@ -214,6 +325,17 @@ impl MacroDefinition {
             }),
         }
     }
+
+    /// A definition of `name` which calls `builtin`.
+    pub fn new_builtin(name: MacroName, builtin: BuiltinMacro) -> Self {
+        Self {
+            parse_config: MacroParseConfig {
+                name,
+                min_args: builtin.min_args(),
+            },
+            implementation: builtin.implementation(),
+        }
+    }
 }
 
 impl std::fmt::Debug for MacroDefinition {
@@ -1,7 +1,13 @@
-use std::io::Write;
+use std::{
+    ffi::OsString,
+    io::Write,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    lexer::MacroName,
+    input::InputStateRef,
+    lexer::{MacroName, ParseConfig},
     state::{StackFrame, State},
     Result,
 };
@@ -18,7 +24,9 @@ impl MacroImplementation for TraceoffMacro {
         frame: StackFrame,
     ) -> Result<State> {
         if frame.args.is_empty() {
-            state.trace = Trace::default();
+            state.trace.all = false;
+            state.trace.exclude.clear();
+            state.trace.include.clear();
         } else {
             for arg in frame.args {
                 let exclude = MacroName::try_from_slice(&arg)?;
@@ -45,35 +53,342 @@ impl MacroImplementation for TraceonMacro {
             state.trace.exclude.clear();
         } else {
             for arg in frame.args {
-                let include = MacroName::try_from_slice(&arg)?;
-                state.trace.exclude.retain(|exclude| exclude != &include);
-                state.trace.include.push(include);
+                state.trace.include(MacroName::try_from_slice(&arg)?);
             }
         }
         Ok(state)
     }
 }
 
+/// Flags which control the output of traced macro calls, see [`DebugmodeMacro`].
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DebugFlags {
+    /// `a`: show the arguments of macro calls.
+    pub args: bool,
+    /// `c`: also show a line when the macro name is recognized, before its arguments are
+    /// collected.
+    pub collect: bool,
+    /// `e`: show the expansion of macro calls.
+    pub expansion: bool,
+    /// `f`: show the name of the current input file.
+    pub file: bool,
+    /// `l`: show the current input line number.
+    pub line: bool,
+    /// `q`: quote arguments and expansions with the current quote strings.
+    pub quote: bool,
+    /// `t`: trace all macro calls, not just the ones enabled by [`TraceonMacro`].
+    pub trace_all: bool,
+    /// `x`: show a unique id for each macro call.
+    pub call_id: bool,
+}
+
+impl DebugFlags {
+    /// The flags used when `-d` or `debugmode` are given an empty argument.
+    pub const DEFAULT: Self = Self {
+        args: true,
+        collect: false,
+        expansion: true,
+        file: false,
+        line: false,
+        quote: true,
+        trace_all: false,
+        call_id: false,
+    };
+
+    /// All the flags, `V`.
+    const ALL: Self = Self {
+        args: true,
+        collect: true,
+        expansion: true,
+        file: true,
+        line: true,
+        quote: true,
+        trace_all: true,
+        call_id: true,
+    };
+
+    fn set(&mut self, flag: u8, value: bool) -> std::result::Result<(), ()> {
+        match flag {
+            b'a' => self.args = value,
+            b'c' => self.collect = value,
+            b'e' => self.expansion = value,
+            b'f' => self.file = value,
+            b'l' => self.line = value,
+            b'q' => self.quote = value,
+            b't' => self.trace_all = value,
+            b'x' => self.call_id = value,
+            b'V' => {
+                if value {
+                    *self = Self::ALL;
+                } else {
+                    *self = Self::default();
+                }
+            }
+            // Input file changes and path searches are not reported, but the flags are accepted
+            // for compatibility with GNU m4.
+            b'i' | b'p' => {}
+            _ => return Err(()),
+        }
+        Ok(())
+    }
+
+    /// Apply flags as accepted by `-d` and `debugmode`. A leading `+` adds the flags, a leading
+    /// `-` removes them, otherwise they replace the current flags. An empty `spec` selects
+    /// [`DebugFlags::DEFAULT`].
+    pub fn update(&mut self, spec: &[u8]) -> std::result::Result<(), ()> {
+        let mut flags = *self;
+        let (letters, value) = match spec.first() {
+            None => {
+                *self = Self::DEFAULT;
+                return Ok(());
+            }
+            Some(b'+') => (&spec[1..], true),
+            Some(b'-') => (&spec[1..], false),
+            Some(_) => {
+                flags = Self::default();
+                (spec, true)
+            }
+        };
+        for flag in letters {
+            flags.set(*flag, value)?;
+        }
+        *self = flags;
+        Ok(())
+    }
+}
+
+/// Where trace output is written, see [`DebugfileMacro`].
+#[derive(Default)]
+pub enum DebugOutput {
+    #[default]
+    Stderr,
+    Discard,
+    File(std::fs::File),
+}
+
+impl DebugOutput {
+    /// Open `path` for appending, an empty path discards the output.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        if path.as_os_str().is_empty() {
+            return Ok(Self::Discard);
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::File(file))
+    }
+}
+
+/// A traced macro call, whose line is completed once the macro has been evaluated.
+pub struct TracedCall {
+    /// The start of the line, up to and including the macro name.
+    prefix: Vec<u8>,
+    has_args: bool,
+    /// The arguments as they are displayed, if [`DebugFlags::args`] is set.
+    args: Vec<u8>,
+}
+
 #[derive(Default)]
 pub struct Trace {
     all: bool,
     exclude: Vec<MacroName>,
     include: Vec<MacroName>,
+    pub flags: DebugFlags,
+    pub output: DebugOutput,
+    /// The id of the most recent macro call.
+    call_id: usize,
 }
 
 impl Trace {
-    pub fn trace(
-        &self,
+    /// Trace calls to `name`, as with `-t`.
+    pub fn include(&mut self, name: MacroName) {
+        self.exclude.retain(|exclude| exclude != &name);
+        self.include.push(name);
+    }
+
+    fn is_traced(&self, name: &MacroName) -> bool {
+        self.flags.trace_all
+            || (self.all && !self.exclude.contains(name))
+            || (!self.all && self.include.contains(name))
+    }
+
+    /// Allocate the id for a new macro call, see [`DebugFlags::call_id`].
+    pub fn next_call_id(&mut self) -> usize {
+        self.call_id += 1;
+        self.call_id
+    }
+
+    fn write(&mut self, stderr: &mut dyn Write, line: &[u8]) -> crate::Result<()> {
+        match &mut self.output {
+            DebugOutput::Stderr => stderr.write_all(line)?,
+            DebugOutput::Discard => {}
+            DebugOutput::File(file) => file.write_all(line)?,
+        }
+        Ok(())
+    }
+
+    fn quote(&self, text: &[u8], parse_config: &ParseConfig) -> Vec<u8> {
+        if self.flags.quote {
+            [
+                &parse_config.quote_open_tag[..],
+                text,
+                &parse_config.quote_close_tag[..],
+            ]
+            .concat()
+        } else {
+            text.to_vec()
+        }
+    }
+
+    fn header(&self, input: &InputStateRef, level: usize, call_id: usize) -> Vec<u8> {
+        let mut header = b"m4trace:".to_vec();
+        if self.flags.file {
+            header.extend(input.file_name());
+            header.push(b':');
+        }
+        if self.flags.line {
+            header.extend(format!("{}:", input.line_number()).as_bytes());
+        }
+        header.extend(format!(" -{level}- ").as_bytes());
+        if self.flags.call_id {
+            header.extend(format!("id {call_id}: ").as_bytes());
+        }
+        header
+    }
+
+    /// Called when the name of a macro has been recognized, before its arguments are collected.
+    pub fn call_started(
+        &mut self,
         stack: &[StackFrame],
-        current_frame: &StackFrame,
+        frame: &StackFrame,
+        input: &InputStateRef,
         stderr: &mut dyn Write,
     ) -> crate::Result<()> {
-        let name = &current_frame.definition.parse_config.name;
-        let level = stack.len() + 1;
-        if (self.all && !self.exclude.contains(name)) || (!self.all && self.include.contains(name))
-        {
-            writeln!(stderr, "m4trace: -{level}- {name}")?;
+        let name = &frame.definition.parse_config.name;
+        if self.flags.collect && self.is_traced(name) {
+            let mut line = self.header(input, stack.len() + 1, frame.call_id);
+            line.extend(&name.0);
+            line.extend(b" ...\n");
+            self.write(stderr, &line)?;
         }
         Ok(())
     }
+
+    /// Called before a macro is evaluated, returns `Some` if the call is traced.
+    pub fn call(
+        &mut self,
+        stack: &[StackFrame],
+        current_frame: &StackFrame,
+        input: &InputStateRef,
+        parse_config: &ParseConfig,
+        stderr: &mut dyn Write,
+    ) -> crate::Result<Option<TracedCall>> {
+        let name = &current_frame.definition.parse_config.name;
+        if !self.is_traced(name) {
+            return Ok(None);
+        }
+        let mut prefix = self.header(input, stack.len() + 1, current_frame.call_id);
+        prefix.extend(&name.0);
+        let mut args = Vec::new();
+        if self.flags.args && !current_frame.args.is_empty() {
+            args.push(b'(');
+            for (i, arg) in current_frame.args.iter().enumerate() {
+                if i > 0 {
+                    args.extend(b", ");
+                }
+                args.extend(self.quote(arg, parse_config));
+            }
+            args.push(b')');
+        }
+        if self.flags.collect {
+            self.write(stderr, &[&prefix[..], &args, b"\n"].concat())?;
+        }
+        Ok(Some(TracedCall {
+            prefix,
+            has_args: !current_frame.args.is_empty(),
+            args,
+        }))
+    }
+
+    /// Complete the line for a traced call, once the macro has been evaluated.
+    pub fn call_finished(
+        &mut self,
+        call: TracedCall,
+        expansion: &[u8],
+        parse_config: &ParseConfig,
+        stderr: &mut dyn Write,
+    ) -> crate::Result<()> {
+        let mut line = call.prefix;
+        if self.flags.collect {
+            if call.has_args {
+                line.extend(b"(...)");
+            }
+        } else {
+            line.extend(call.args);
+        }
+        if self.flags.expansion {
+            line.extend(b" -> ");
+            line.extend(self.quote(expansion, parse_config));
+        }
+        line.push(b'\n');
+        self.write(stderr, &line)
+    }
+}
+
+/// `debugmode(flags)` sets the [`DebugFlags`] used for tracing. Without an argument all the flags
+/// are cleared.
+pub struct DebugmodeMacro;
+
+impl MacroImplementation for DebugmodeMacro {
+    fn evaluate(
+        &self,
+        mut state: State,
+        stderr: &mut dyn Write,
+        frame: StackFrame,
+    ) -> Result<State> {
+        match frame.args.first() {
+            None => state.trace.flags = DebugFlags::default(),
+            Some(spec) => {
+                if state.trace.flags.update(spec).is_err() {
+                    writeln!(
+                        stderr,
+                        "Warning: bad debug flags: `{}'",
+                        String::from_utf8_lossy(spec)
+                    )?;
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// `debugfile(file)` appends trace output to `file`. Without an argument it is written to
+/// standard error again.
+///
+/// NOTE: GNU m4 discards the output when given an empty argument, but an empty argument can't be
+/// told apart from no argument here, `debugfile(`/dev/null')` can be used instead.
+pub struct DebugfileMacro;
+
+impl MacroImplementation for DebugfileMacro {
+    fn evaluate(
+        &self,
+        mut state: State,
+        stderr: &mut dyn Write,
+        frame: StackFrame,
+    ) -> Result<State> {
+        match frame.args.into_iter().next() {
+            None => state.trace.output = DebugOutput::Stderr,
+            Some(path) => {
+                let path = PathBuf::from(OsString::from_vec(path));
+                match DebugOutput::open(&path) {
+                    Ok(output) => state.trace.output = output,
+                    Err(error) => {
+                        writeln!(stderr, "Warning: cannot set debug file {path:?}: {error}")?
+                    }
+                }
+            }
+        }
+        Ok(state)
+    }
 }
@@ -87,8 +87,7 @@ pub(crate) fn main_loop(mut state: State, stderr: &mut dyn Write) -> crate::erro
             // Possibly a macro to be evaluated.
             let definition = state.parse_macro(t, &mut token)?;
             if definition.is_some() {
-                l = state.input.get_next_character()?;
-                state.input.pushback_character(l);
+                l = state.input.peek_next_character()?;
             }
 
             // Check to see whether it's currently defined macro or it needs some arguments but
@@ -100,7 +99,11 @@ pub(crate) fn main_loop(mut state: State, stderr: &mut dyn Write) -> crate::erro
             } else {
                 let definition = definition.unwrap();
 
-                let frame = StackFrame::new(0, definition.clone());
+                let call_id = state.trace.next_call_id();
+                let frame = StackFrame::new(0, definition.clone(), call_id);
+                state
+                    .trace
+                    .call_started(&state.output.stack, &frame, &state.input, stderr)?;
 
                 if l == b'(' {
                     state.output.stack.push(frame);
@@ -212,8 +215,10 @@ pub(crate) fn main_loop(mut state: State, stderr: &mut dyn Write) -> crate::erro
         }
     }
 
-    state.output.output.divert(0)?;
-    state.output.output.undivert_all()?;
+    if !state.freeze {
+        state.output.output.divert(0)?;
+        state.output.output.undivert_all()?;
+    }
 
     for wrap in &state.m4wrap {
         state.output.write_all(wrap)?;
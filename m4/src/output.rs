@@ -61,6 +61,20 @@ impl OutputRef {
     pub fn stdout(&self) -> Rc<RefCell<dyn Write>> {
         self.0.borrow().stdout()
     }
+
+    pub fn diversions(&self) -> Vec<(DivertBufferNumber, Vec<u8>)> {
+        self.0.borrow().diversions()
+    }
+
+    pub fn append_diversion(
+        &mut self,
+        buffer_number: DivertBufferNumber,
+        contents: &[u8],
+    ) -> Result<()> {
+        self.0
+            .borrow_mut()
+            .append_diversion(buffer_number, contents)
+    }
 }
 
 impl Write for OutputRef {
@@ -161,6 +175,34 @@ impl Output {
         debug_assert!(buffer.0.get_ref().is_empty());
         Ok(())
     }
+
+    /// The contents of each divert buffer which is not empty, used when freezing the state.
+    pub fn diversions(&self) -> Vec<(DivertBufferNumber, Vec<u8>)> {
+        self.divert_buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| !buffer.borrow().0.get_ref().is_empty())
+            .map(|(index, buffer)| {
+                (
+                    DivertBufferNumber(index + 1),
+                    buffer.borrow().0.get_ref().clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Append `contents` directly to a divert buffer, used when reloading a frozen state.
+    pub fn append_diversion(
+        &mut self,
+        buffer_number: DivertBufferNumber,
+        contents: &[u8],
+    ) -> Result<()> {
+        self.divert_buffers[buffer_number.index()]
+            .borrow_mut()
+            .0
+            .write_all(contents)?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -170,6 +212,11 @@ impl DivertBufferNumber {
     fn index(&self) -> usize {
         self.0 - 1
     }
+
+    /// The number used to [`Output::divert`] to this buffer.
+    pub fn divert_number(&self) -> i64 {
+        self.0 as i64
+    }
 }
 
 impl std::fmt::Display for DivertBufferNumber {
@@ -1,11 +1,12 @@
-use std::{cell::RefCell, collections::HashMap, io::Write, process::ExitStatus, rc::Rc};
+use std::{
+    cell::RefCell, collections::HashMap, io::Write, path::PathBuf, process::ExitStatus, rc::Rc,
+};
 
 use crate::{
     input::{Input, InputState, InputStateRef},
     lexer::{is_alphnumeric, MacroName, ParseConfig},
     macros::{trace::Trace, BuiltinMacro, MacroDefinition},
     output::{Output, OutputState},
-    EOF,
 };
 
 pub struct State {
@@ -19,6 +20,12 @@ pub struct State {
     pub output: OutputState,
     pub input: InputStateRef,
     pub trace: Trace,
+    /// See [`crate::Args::include_directories`].
+    pub include_directories: Vec<PathBuf>,
+    /// Whether the state is going to be frozen once all input has been processed, see
+    /// [`crate::Args::freeze_state`]. Diversions are then kept for the frozen file instead of
+    /// being output.
+    pub freeze: bool,
 }
 
 impl State {
@@ -52,15 +59,13 @@ impl State {
         token.push(c);
 
         loop {
-            c = self.input.get_next_character()?;
+            c = self.input.peek_next_character()?;
             if !(is_alphnumeric(c) || c == b'_') {
                 break;
             }
+            self.input.get_next_character()?;
             token.push(c)
         }
-        if c != EOF {
-            self.input.pushback_character(c);
-        }
 
         Ok(self
             .macro_definitions
@@ -69,6 +74,20 @@ impl State {
             .unwrap_or_default()
             .cloned())
     }
+
+    /// Find a file to be read, first relative to the current directory and then in each of the
+    /// [`State::include_directories`]. If it can't be found `path` is returned unchanged, so that
+    /// opening it reports a sensible error.
+    pub fn resolve_path(&self, path: PathBuf) -> PathBuf {
+        if path.is_absolute() || path.exists() {
+            return path;
+        }
+        self.include_directories
+            .iter()
+            .map(|directory| directory.join(&path))
+            .find(|candidate| candidate.exists())
+            .unwrap_or(path)
+    }
 }
 
 impl std::fmt::Debug for State {
@@ -83,19 +102,7 @@ impl std::fmt::Debug for State {
 impl Default for State {
     fn default() -> Self {
         Self {
-            macro_definitions: BuiltinMacro::enumerate()
-                .iter()
-                .map(|builtin| {
-                    let parse_config = builtin.parse_config();
-                    (
-                        parse_config.name.clone(),
-                        vec![Rc::new(MacroDefinition {
-                            parse_config,
-                            implementation: builtin.implementation(),
-                        })],
-                    )
-                })
-                .collect(),
+            macro_definitions: BuiltinMacro::definitions(true, false),
             parse_config: ParseConfig::default(),
             exit_error: false,
             m4wrap: Vec::new(),
@@ -103,6 +110,8 @@ impl Default for State {
             output: OutputState::default(),
             input: InputStateRef::default(),
             trace: Trace::default(),
+            include_directories: Vec::new(),
+            freeze: false,
         }
     }
 }
@@ -111,14 +120,17 @@ pub struct StackFrame {
     pub parenthesis_level: usize,
     pub args: Vec<Vec<u8>>,
     pub definition: Rc<MacroDefinition>,
+    /// See [`crate::macros::trace::Trace::next_call_id`].
+    pub call_id: usize,
 }
 
 impl StackFrame {
-    pub fn new(parenthesis_level: usize, definition: Rc<MacroDefinition>) -> Self {
+    pub fn new(parenthesis_level: usize, definition: Rc<MacroDefinition>, call_id: usize) -> Self {
         Self {
             parenthesis_level,
             args: Vec::new(),
             definition,
+            call_id,
         }
     }
 }
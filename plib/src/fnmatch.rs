@@ -0,0 +1,44 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Shell pattern matching, as done by fnmatch(3).
+
+use std::ffi::{CStr, CString};
+
+// The `libc` crate does not bind fnmatch(3) on every platform.
+extern "C" {
+    #[link_name = "fnmatch"]
+    fn c_fnmatch(
+        pattern: *const libc::c_char,
+        string: *const libc::c_char,
+        flags: libc::c_int,
+    ) -> libc::c_int;
+}
+
+/// Matches `name` against the shell pattern `pattern`, with the `FNM_*`
+/// `flags` of fnmatch(3).  A name containing a NUL byte matches nothing.
+pub fn fnmatch(pattern: &CStr, name: &[u8], flags: libc::c_int) -> bool {
+    let Ok(name) = CString::new(name) else {
+        return false;
+    };
+    unsafe { c_fnmatch(pattern.as_ptr(), name.as_ptr(), flags) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnmatch() {
+        assert!(fnmatch(c"*.rs", b"lib.rs", 0));
+        assert!(fnmatch(c"[a-c]?", b"b/", 0));
+        assert!(!fnmatch(c"*.rs", b"lib.c", 0));
+        assert!(!fnmatch(c"*", b"a\0b", 0));
+    }
+}
@@ -11,6 +11,16 @@ use std::fs;
 use std::io::{self, Read};
 use std::path::PathBuf;
 
+/// Returns the message of an I/O error without the "(os error N)" suffix
+/// that Rust adds, as utilities report errors like strerror(3).
+pub fn error_string(err: &io::Error) -> String {
+    let s = err.to_string();
+    match s.find(" (os error ") {
+        Some(pos) => s[..pos].to_string(),
+        None => s,
+    }
+}
+
 pub fn input_stream(pathname: &PathBuf, dashed_stdin: bool) -> io::Result<Box<dyn Read>> {
     // open file, or stdin
     let path_str = pathname.as_os_str();
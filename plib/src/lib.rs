@@ -8,12 +8,14 @@
 //
 
 pub mod curuser;
+pub mod fnmatch;
 pub mod group;
 pub mod io;
 pub mod lzw;
 pub mod modestr;
 pub mod platform;
 pub mod priority;
+pub mod regex;
 pub mod sccsfile;
 pub mod testing;
 pub mod utmpx;
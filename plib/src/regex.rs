@@ -0,0 +1,1749 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! POSIX basic and extended regular expressions.
+//!
+//! Matching follows the POSIX rule: of the matches starting earliest in the
+//! input, the longest wins. Basic regular expressions support back-references
+//! and the GNU `\+`, `\?` and `\|` operators; extended ones support
+//! back-references as an extension. Both support the GNU word operators `\w`,
+//! `\W`, `\<`, `\>`, `\b` and `\B`. Patterns and input are bytes: in the
+//! default mode they are read as UTF-8, with each byte that is not part of a
+//! valid sequence standing for itself, and in byte mode every byte is a
+//! character.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// The largest count allowed in an interval expression.
+pub const RE_DUP_MAX: u32 = 0x7fff;
+
+/// The largest compiled program allowed.
+const MAX_PROGRAM: usize = 1 << 20;
+
+/// Units past the last character stand for bytes that are not valid UTF-8.
+const INVALID_BYTE: u32 = 0x110000;
+
+/// Options for compiling a regular expression.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RegexFlags {
+    /// Extended regular expressions, instead of basic ones.
+    pub extended: bool,
+    /// Match without regard to case.
+    pub ignore_case: bool,
+    /// Match single bytes, instead of UTF-8 characters.
+    pub bytes: bool,
+}
+
+/// Errors in regular expressions, with the messages of `regerror`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegexError {
+    Collate,
+    CharClass,
+    Escape,
+    BackReference,
+    Bracket,
+    Paren,
+    Brace,
+    BadBrace,
+    Range,
+    BadRepeat,
+    TooBig,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegexError::Collate => "Invalid collation character",
+            RegexError::CharClass => "Invalid character class name",
+            RegexError::Escape => "Trailing backslash",
+            RegexError::BackReference => "Invalid back reference",
+            RegexError::Bracket => "Unmatched [, [^, [:, [., or [=",
+            RegexError::Paren => "Unmatched ( or \\(",
+            RegexError::Brace => "Unmatched \\{",
+            RegexError::BadBrace => "Invalid content of \\{\\}",
+            RegexError::Range => "Invalid range end",
+            RegexError::BadRepeat => "Invalid preceding regular expression",
+            RegexError::TooBig => "Regular expression too big",
+        })
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// Decodes the unit at `input[pos..]`, returning it with its length in bytes.
+fn decode(input: &[u8], pos: usize, bytes: bool) -> Option<(u32, usize)> {
+    let lead = *input.get(pos)?;
+    if bytes || lead < 0x80 {
+        return Some((lead as u32, 1));
+    }
+    let width = match lead {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    };
+    if let Some(Ok(s)) = input.get(pos..pos + width).map(std::str::from_utf8) {
+        if let Some(c) = s.chars().next() {
+            return Some((c as u32, width));
+        }
+    }
+    Some((INVALID_BYTE + lead as u32, 1))
+}
+
+fn lower(u: u32) -> u32 {
+    match char::from_u32(u) {
+        Some(c) => {
+            let mut l = c.to_lowercase();
+            match (l.next(), l.next()) {
+                (Some(l), None) => l as u32,
+                _ => u,
+            }
+        }
+        None => u,
+    }
+}
+
+fn upper(u: u32) -> u32 {
+    match char::from_u32(u) {
+        Some(c) => {
+            let mut l = c.to_uppercase();
+            match (l.next(), l.next()) {
+                (Some(l), None) => l as u32,
+                _ => u,
+            }
+        }
+        None => u,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+}
+
+impl Class {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "alnum" => Class::Alnum,
+            "alpha" => Class::Alpha,
+            "blank" => Class::Blank,
+            "cntrl" => Class::Cntrl,
+            "digit" => Class::Digit,
+            "graph" => Class::Graph,
+            "lower" => Class::Lower,
+            "print" => Class::Print,
+            "punct" => Class::Punct,
+            "space" => Class::Space,
+            "upper" => Class::Upper,
+            "xdigit" => Class::Xdigit,
+            _ => return None,
+        })
+    }
+
+    /// Checks a unit against the class. In byte mode, only ASCII belongs to
+    /// classes.
+    fn contains(self, u: u32, bytes: bool) -> bool {
+        let Some(c) = char::from_u32(u).filter(|c| !bytes || c.is_ascii()) else {
+            return false;
+        };
+        match self {
+            Class::Alnum => c.is_alphanumeric(),
+            Class::Alpha => c.is_alphabetic(),
+            Class::Blank => c == ' ' || c == '\t',
+            Class::Cntrl => c.is_control(),
+            Class::Digit => c.is_ascii_digit(),
+            Class::Graph => !c.is_control() && !c.is_whitespace(),
+            Class::Lower => c.is_lowercase(),
+            Class::Print => !c.is_control() && (c == ' ' || !c.is_whitespace()),
+            Class::Punct => {
+                c.is_ascii_punctuation()
+                    || (!c.is_ascii()
+                        && !c.is_alphanumeric()
+                        && !c.is_whitespace()
+                        && !c.is_control())
+            }
+            Class::Space => c.is_whitespace(),
+            Class::Upper => c.is_uppercase(),
+            Class::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum SetItem {
+    Unit(u32),
+    Range(u32, u32),
+    Class(Class),
+}
+
+/// A bracket expression.
+#[derive(Clone, Debug)]
+struct Set {
+    negated: bool,
+    items: Vec<SetItem>,
+}
+
+impl Set {
+    fn contains_exactly(&self, u: u32, bytes: bool) -> bool {
+        self.items.iter().any(|item| match *item {
+            SetItem::Unit(v) => v == u,
+            SetItem::Range(lo, hi) => lo <= u && u <= hi,
+            SetItem::Class(class) => class.contains(u, bytes),
+        })
+    }
+
+    fn matches(&self, u: u32, flags: &RegexFlags) -> bool {
+        let found = self.contains_exactly(u, flags.bytes)
+            || (flags.ignore_case
+                && (self.contains_exactly(lower(u), flags.bytes)
+                    || self.contains_exactly(upper(u), flags.bytes)));
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Unit(u32),
+    Any,
+    Set(Set),
+    Bol,
+    Eol,
+    Word(WordAssertion),
+    Group(usize, Box<Node>),
+    BackReference(usize),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+/// The positions matched by the GNU word operators.
+#[derive(Clone, Copy, Debug)]
+enum WordAssertion {
+    /// `\<`
+    Start,
+    /// `\>`
+    End,
+    /// `\b`
+    Boundary,
+    /// `\B`
+    NotBoundary,
+}
+
+impl WordAssertion {
+    fn holds(self, input: &[u8], pos: usize, bytes: bool) -> bool {
+        let before = pos > 0 && is_word_unit(previous_unit(input, pos, bytes));
+        let after = decode(input, pos, bytes).is_some_and(|(u, _)| is_word_unit(u));
+        match self {
+            WordAssertion::Start => !before && after,
+            WordAssertion::End => before && !after,
+            WordAssertion::Boundary => before != after,
+            WordAssertion::NotBoundary => before == after,
+        }
+    }
+}
+
+fn is_word_unit(u: u32) -> bool {
+    char::from_u32(u).is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Decodes the unit that ends at `input[..pos]`.
+fn previous_unit(input: &[u8], pos: usize, bytes: bool) -> u32 {
+    let mut start = pos - 1;
+    while !bytes && start > 0 && pos - start < 4 && (input[start] & 0xc0) == 0x80 {
+        start -= 1;
+    }
+    match decode(input, start, bytes) {
+        Some((u, len)) if start + len == pos => u,
+        _ => INVALID_BYTE + input[pos - 1] as u32,
+    }
+}
+
+/// The set matched by `\w`, or by `\W` if `negated`.
+fn word_set(negated: bool) -> Set {
+    Set {
+        negated,
+        items: vec![SetItem::Class(Class::Alnum), SetItem::Unit(unit('_'))],
+    }
+}
+
+const fn unit(c: char) -> u32 {
+    c as u32
+}
+
+/// Parses a pattern, held as units, into a syntax tree.
+struct Parser {
+    units: Vec<u32>,
+    pos: usize,
+    extended: bool,
+    groups: usize,
+    closed_groups: Vec<usize>,
+    has_back_references: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<u32> {
+        self.units.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u32> {
+        self.units.get(self.pos + offset).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(unit(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Checks for the escaped character `c`, as in the BRE `\(`.
+    fn at_escaped(&self, c: char) -> bool {
+        self.peek() == Some(unit('\\')) && self.peek_at(1) == Some(unit(c))
+    }
+
+    fn at_alternation(&self) -> bool {
+        if self.extended {
+            self.peek() == Some(unit('|'))
+        } else {
+            self.at_escaped('|')
+        }
+    }
+
+    fn at_close(&self, depth: usize) -> bool {
+        depth > 0
+            && if self.extended {
+                self.peek() == Some(unit(')'))
+            } else {
+                self.at_escaped(')')
+            }
+    }
+
+    fn parse_alternation(&mut self, depth: usize) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_branch(depth)?];
+        while self.at_alternation() {
+            self.pos += if self.extended { 1 } else { 2 };
+            branches.push(self.parse_branch(depth)?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternation(branches)
+        })
+    }
+
+    fn parse_branch(&mut self, depth: usize) -> Result<Node, RegexError> {
+        let mut items: Vec<Node> = Vec::new();
+        // Whether a repetition here would have nothing to repeat.
+        let mut at_start = true;
+        while let Some(c) = self.peek() {
+            if self.at_alternation() || self.at_close(depth) {
+                break;
+            }
+
+            if !self.extended {
+                if c == unit('^') && items.is_empty() {
+                    self.pos += 1;
+                    items.push(Node::Bol);
+                    continue;
+                }
+                if c == unit('$') {
+                    let next = self.pos + 1;
+                    let at_end = next == self.units.len()
+                        || (self.units[next] == unit('\\')
+                            && (self.units.get(next + 1) == Some(&unit('|'))
+                                || (depth > 0 && self.units.get(next + 1) == Some(&unit(')')))));
+                    if at_end {
+                        self.pos += 1;
+                        items.push(Node::Eol);
+                        at_start = false;
+                        continue;
+                    }
+                }
+                if c == unit('*') && at_start {
+                    self.pos += 1;
+                    items.push(Node::Unit(c));
+                    at_start = false;
+                    continue;
+                }
+            } else if at_start && (c == unit('*') || c == unit('+') || c == unit('?')) {
+                self.pos += 1;
+                items.push(Node::Unit(c));
+                at_start = false;
+                continue;
+            } else if at_start && c == unit('{') && self.interval_follows() {
+                return Err(RegexError::BadRepeat);
+            }
+
+            let atom = self.parse_atom(depth)?;
+            if self.extended && matches!(atom, Node::Bol) {
+                // As in a BRE, a repetition right after a leading `^` stands
+                // for itself.
+                items.push(atom);
+                continue;
+            }
+            items.push(self.parse_repetitions(atom)?);
+            at_start = false;
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    /// Checks whether an ERE `{` starts an interval expression rather than
+    /// standing for itself.
+    fn interval_follows(&self) -> bool {
+        self.peek_at(1)
+            .is_some_and(|u| char::from_u32(u).is_some_and(|c| c.is_ascii_digit()))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        let mut value: u32 = 0;
+        while let Some(d) = self
+            .peek()
+            .and_then(char::from_u32)
+            .and_then(|c| c.to_digit(10))
+        {
+            value = value.saturating_mul(10).saturating_add(d);
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(value)
+    }
+
+    /// Parses the inside of an interval expression, after its opening brace.
+    fn parse_interval(&mut self) -> Result<(u32, Option<u32>), RegexError> {
+        let min = self.parse_number().ok_or(if self.peek().is_none() {
+            RegexError::Brace
+        } else {
+            RegexError::BadBrace
+        })?;
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            Some(min)
+        };
+        let closed = if self.extended {
+            self.eat('}')
+        } else if self.at_escaped('}') {
+            self.pos += 2;
+            true
+        } else {
+            false
+        };
+        if !closed {
+            return Err(if self.peek().is_none() {
+                RegexError::Brace
+            } else {
+                RegexError::BadBrace
+            });
+        }
+        if min > RE_DUP_MAX || max.is_some_and(|max| max > RE_DUP_MAX || max < min) {
+            return Err(RegexError::BadBrace);
+        }
+        Ok((min, max))
+    }
+
+    fn parse_repetitions(&mut self, mut atom: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = if self.eat('*') {
+                (0, None)
+            } else if self.extended && self.eat('+') {
+                (1, None)
+            } else if self.extended && self.eat('?') {
+                (0, Some(1))
+            } else if !self.extended && self.at_escaped('+') {
+                self.pos += 2;
+                (1, None)
+            } else if !self.extended && self.at_escaped('?') {
+                self.pos += 2;
+                (0, Some(1))
+            } else if self.extended && self.peek() == Some(unit('{')) && self.interval_follows() {
+                self.pos += 1;
+                self.parse_interval()?
+            } else if !self.extended && self.at_escaped('{') {
+                self.pos += 2;
+                self.parse_interval()?
+            } else {
+                return Ok(atom);
+            };
+            atom = Node::Repeat(Box::new(atom), min, max);
+        }
+    }
+
+    fn parse_atom(&mut self, depth: usize) -> Result<Node, RegexError> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        if c == unit('.') {
+            return Ok(Node::Any);
+        }
+        if c == unit('[') {
+            return self.parse_bracket().map(Node::Set);
+        }
+        if self.extended {
+            if c == unit('(') {
+                return self.parse_group(depth);
+            }
+            if c == unit('^') {
+                return Ok(Node::Bol);
+            }
+            if c == unit('$') {
+                return Ok(Node::Eol);
+            }
+        }
+        if c != unit('\\') {
+            return Ok(Node::Unit(c));
+        }
+
+        let Some(escaped) = self.peek() else {
+            return Err(RegexError::Escape);
+        };
+        self.pos += 1;
+        if !self.extended && escaped == unit('(') {
+            return self.parse_group(depth);
+        }
+        if !self.extended && escaped == unit(')') {
+            return Err(RegexError::Paren);
+        }
+        if !self.extended && escaped == unit('{') {
+            return Err(RegexError::BadRepeat);
+        }
+        match char::from_u32(escaped).and_then(|c| c.to_digit(10)) {
+            Some(n @ 1..=9) => {
+                let n = n as usize;
+                if !self.closed_groups.contains(&n) {
+                    return Err(RegexError::BackReference);
+                }
+                self.has_back_references = true;
+                Ok(Node::BackReference(n))
+            }
+            _ => Ok(match char::from_u32(escaped) {
+                Some('<') => Node::Word(WordAssertion::Start),
+                Some('>') => Node::Word(WordAssertion::End),
+                Some('b') => Node::Word(WordAssertion::Boundary),
+                Some('B') => Node::Word(WordAssertion::NotBoundary),
+                Some('w') => Node::Set(word_set(false)),
+                Some('W') => Node::Set(word_set(true)),
+                _ => Node::Unit(escaped),
+            }),
+        }
+    }
+
+    fn parse_group(&mut self, depth: usize) -> Result<Node, RegexError> {
+        self.groups += 1;
+        let index = self.groups;
+        let inner = self.parse_alternation(depth + 1)?;
+        let closed = if self.extended {
+            self.eat(')')
+        } else if self.at_escaped(')') {
+            self.pos += 2;
+            true
+        } else {
+            false
+        };
+        if !closed {
+            return Err(RegexError::Paren);
+        }
+        self.closed_groups.push(index);
+        Ok(Node::Group(index, Box::new(inner)))
+    }
+
+    /// Parses the name in `[:name:]`, `[=c=]` or `[.c.]`, after its opening
+    /// bracket and delimiter.
+    fn parse_bracket_name(&mut self, delimiter: char) -> Result<Vec<u32>, RegexError> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None => return Err(RegexError::Bracket),
+                Some(u) if u == unit(delimiter) && self.peek_at(1) == Some(unit(']')) => {
+                    let name = self.units[start..self.pos].to_vec();
+                    self.pos += 2;
+                    return Ok(name);
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Parses a bracket expression endpoint: a character, or a collating
+    /// symbol or equivalence class naming one.
+    fn parse_bracket_unit(&mut self) -> Result<Option<u32>, RegexError> {
+        let Some(c) = self.peek() else {
+            return Err(RegexError::Bracket);
+        };
+        if c == unit('[') {
+            if let Some(delimiter) = self
+                .peek_at(1)
+                .filter(|&d| d == unit('.') || d == unit('='))
+            {
+                self.pos += 2;
+                let name = self.parse_bracket_name(char::from_u32(delimiter).unwrap())?;
+                return match name[..] {
+                    [u] => Ok(Some(u)),
+                    _ => Err(RegexError::Collate),
+                };
+            }
+            if self.peek_at(1) == Some(unit(':')) {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(c))
+    }
+
+    fn parse_bracket(&mut self) -> Result<Set, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            match self.peek() {
+                None => return Err(RegexError::Bracket),
+                Some(u) if u == unit(']') && !first => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => {}
+            }
+            first = false;
+            if self.peek() == Some(unit('[')) && self.peek_at(1) == Some(unit(':')) {
+                self.pos += 2;
+                let name = self.parse_bracket_name(':')?;
+                let name: String = name.iter().filter_map(|&u| char::from_u32(u)).collect();
+                let class = Class::from_name(&name).ok_or(RegexError::CharClass)?;
+                items.push(SetItem::Class(class));
+                continue;
+            }
+            let Some(lo) = self.parse_bracket_unit()? else {
+                return Err(RegexError::Bracket);
+            };
+            if self.peek() == Some(unit('-')) && self.peek_at(1).is_some_and(|u| u != unit(']')) {
+                self.pos += 1;
+                let Some(hi) = self.parse_bracket_unit()? else {
+                    return Err(RegexError::Range);
+                };
+                if hi < lo {
+                    return Err(RegexError::Range);
+                }
+                items.push(SetItem::Range(lo, hi));
+            } else {
+                items.push(SetItem::Unit(lo));
+            }
+        }
+        Ok(Set { negated, items })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Unit(u32),
+    Any,
+    Set(usize),
+    Bol,
+    Eol,
+    Word(WordAssertion),
+    Save(usize),
+    BackReference(usize),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    sets: Vec<Set>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.program.len() >= MAX_PROGRAM {
+            return Err(RegexError::TooBig);
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Unit(u) => {
+                self.emit(Inst::Unit(*u))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Set(set) => {
+                self.sets.push(set.clone());
+                self.emit(Inst::Set(self.sets.len() - 1))?;
+            }
+            Node::Bol => {
+                self.emit(Inst::Bol)?;
+            }
+            Node::Eol => {
+                self.emit(Inst::Eol)?;
+            }
+            Node::Word(assertion) => {
+                self.emit(Inst::Word(*assertion))?;
+            }
+            Node::Group(index, inner) => {
+                self.emit(Inst::Save(2 * index))?;
+                self.compile(inner)?;
+                self.emit(Inst::Save(2 * index + 1))?;
+            }
+            Node::BackReference(n) => {
+                self.emit(Inst::BackReference(*n))?;
+            }
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Node::Alternation(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.compile(branch)?;
+                        jumps.push(self.emit(Inst::Jump(0))?);
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat(inner, min, max) => {
+                for _ in 0..*min {
+                    self.compile(inner)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.compile(inner)?;
+                        self.emit(Inst::Jump(split))?;
+                        let end = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, end);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.compile(inner)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// States already explored by the backtracking matcher. Without
+/// back-references, the outcome from a state depends only on the instruction
+/// and the input position, counted from an offset; with them, it also depends
+/// on the captures.
+enum Visited {
+    Positions {
+        bits: Vec<u64>,
+        width: usize,
+        offset: usize,
+    },
+    States(HashSet<(usize, usize, Vec<usize>)>),
+}
+
+impl Visited {
+    /// Marks a state, returning whether it was new.
+    fn insert(&mut self, pc: usize, pos: usize, captures: &[usize]) -> bool {
+        match self {
+            Visited::Positions {
+                bits,
+                width,
+                offset,
+            } => {
+                let i = pc * *width + pos - *offset;
+                let (word, bit) = (i / 64, 1u64 << (i % 64));
+                let new = bits[word] & bit == 0;
+                bits[word] |= bit;
+                new
+            }
+            Visited::States(states) => states.insert((pc, pos, captures[2..].to_vec())),
+        }
+    }
+}
+
+enum Job {
+    Explore(usize, usize),
+    Restore(usize, usize),
+}
+
+const UNSET: usize = usize::MAX;
+
+/// The most states a lazily built DFA may have before it is thrown away.
+const MAX_DFA_STATES: usize = 1000;
+
+/// Finds the first occurrence of the non-empty `needle` in `haystack`.
+fn find_literal(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let (&first, rest) = needle.split_first()?;
+    let mut from = 0;
+    while let Some(i) = haystack[from..].iter().position(|&b| b == first) {
+        let at = from + i;
+        if haystack[at + 1..].starts_with(rest) {
+            return Some(at);
+        }
+        from = at + 1;
+    }
+    None
+}
+
+/// Flattens the concatenations of a syntax tree into the units it matches
+/// literally, with `None` for anything else.
+fn flatten_literals(node: &Node, atoms: &mut Vec<Option<u32>>) {
+    match node {
+        Node::Empty => {}
+        Node::Unit(u) => atoms.push(Some(*u)),
+        Node::Concat(items) => items.iter().for_each(|item| flatten_literals(item, atoms)),
+        Node::Group(_, inner) => flatten_literals(inner, atoms),
+        _ => atoms.push(None),
+    }
+}
+
+/// Appends the bytes that the unit `u` is read from.
+fn push_unit_bytes(u: u32, bytes: bool, out: &mut Vec<u8>) {
+    if bytes {
+        out.push(u as u8);
+    } else if u >= INVALID_BYTE {
+        out.push((u - INVALID_BYTE) as u8);
+    } else if let Some(c) = char::from_u32(u) {
+        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+}
+
+/// The threads of the Pike VM at one input position: their instructions in
+/// priority order, with the position each of them started matching at.
+struct Threads {
+    pcs: Vec<usize>,
+    starts: Vec<usize>,
+    /// The index of each instruction in `pcs`, if it is there.
+    index: Vec<usize>,
+}
+
+impl Threads {
+    fn new(program_len: usize) -> Self {
+        Threads {
+            pcs: Vec::with_capacity(program_len),
+            starts: Vec::with_capacity(program_len),
+            index: vec![0; program_len],
+        }
+    }
+
+    fn contains(&self, pc: usize) -> bool {
+        self.pcs.get(self.index[pc]) == Some(&pc)
+    }
+
+    fn insert(&mut self, pc: usize, start: usize) {
+        self.index[pc] = self.pcs.len();
+        self.pcs.push(pc);
+        self.starts.push(start);
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        self.starts.clear();
+    }
+}
+
+/// A state of the DFA: the instructions that consume input, match or wait
+/// for the end of the input, once the others are followed.
+struct DfaState {
+    pcs: Vec<usize>,
+    matches: bool,
+    matches_at_end: bool,
+    /// Transitions on units below 256, as state numbers plus one.
+    next: Box<[u32; 256]>,
+}
+
+/// A DFA built lazily from the program, for [Regex::is_match]. It is kept
+/// between searches, so that matching many lines builds it once.
+#[derive(Default)]
+struct Dfa {
+    states: Vec<DfaState>,
+    ids: HashMap<Vec<usize>, usize>,
+    /// Transitions on the other units.
+    wide_next: HashMap<(usize, u32), usize>,
+}
+
+/// The cache of a [Dfa], which clones of a [Regex] do not share.
+#[derive(Default)]
+struct DfaCache(Mutex<Dfa>);
+
+impl Clone for DfaCache {
+    fn clone(&self) -> Self {
+        DfaCache::default()
+    }
+}
+
+impl fmt::Debug for DfaCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DfaCache")
+    }
+}
+
+/// Returns the offset just past the `]` that closes the bracket expression
+/// whose `[` is at `start`, or `None` if it is not closed.  Utilities need
+/// this to find where a delimited pattern ends, as a delimiter inside a
+/// bracket expression is literal.  Backslashes are literal too, and a `]`
+/// within `[:class:]`, `[=x=]` or `[.x.]` does not close the expression.
+pub fn bracket_expression_end(pattern: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if pattern.get(i) == Some(&b'^') {
+        i += 1;
+    }
+    if pattern.get(i) == Some(&b']') {
+        i += 1;
+    }
+    loop {
+        match *pattern.get(i)? {
+            b']' => return Some(i + 1),
+            b'[' if matches!(pattern.get(i + 1), Some(b':' | b'=' | b'.')) => {
+                let kind = pattern[i + 1];
+                i += 2;
+                while !(*pattern.get(i)? == kind && pattern.get(i + 1) == Some(&b']')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// A compiled POSIX regular expression.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+    sets: Vec<Set>,
+    groups: usize,
+    has_back_references: bool,
+    anchored: bool,
+    flags: RegexFlags,
+    /// Bytes that every match contains.
+    required: Vec<u8>,
+    /// Bytes that every match starts with.
+    prefix: Vec<u8>,
+    /// Whether [Regex::is_match] may use a DFA, which cannot follow
+    /// back-references or look at the next unit for word assertions.
+    use_dfa: bool,
+    dfa: DfaCache,
+}
+
+impl Regex {
+    /// Compiles a pattern.
+    pub fn new(pattern: impl AsRef<[u8]>, flags: RegexFlags) -> Result<Self, RegexError> {
+        let pattern = pattern.as_ref();
+        let mut units = Vec::new();
+        let mut pos = 0;
+        while let Some((u, len)) = decode(pattern, pos, flags.bytes) {
+            units.push(u);
+            pos += len;
+        }
+
+        let mut parser = Parser {
+            units,
+            pos: 0,
+            extended: flags.extended,
+            groups: 0,
+            closed_groups: Vec::new(),
+            has_back_references: false,
+        };
+        let node = parser.parse_alternation(0)?;
+        let mut regex = Self::build(node, parser.groups, parser.has_back_references, flags)?;
+        regex.pattern = String::from_utf8_lossy(pattern).into_owned();
+        Ok(regex)
+    }
+
+    fn build(
+        node: Node,
+        groups: usize,
+        has_back_references: bool,
+        flags: RegexFlags,
+    ) -> Result<Self, RegexError> {
+        let anchored = match &node {
+            Node::Bol => true,
+            Node::Concat(items) => matches!(items.first(), Some(Node::Bol)),
+            _ => false,
+        };
+        let (required, prefix) = Self::literals(&node, flags);
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            sets: Vec::new(),
+        };
+        compiler.emit(Inst::Save(0))?;
+        compiler.compile(&node)?;
+        compiler.emit(Inst::Save(1))?;
+        compiler.emit(Inst::Match)?;
+        let use_dfa = !has_back_references
+            && !compiler
+                .program
+                .iter()
+                .any(|inst| matches!(inst, Inst::Word(_)));
+        Ok(Regex {
+            pattern: String::new(),
+            program: compiler.program,
+            sets: compiler.sets,
+            groups,
+            has_back_references,
+            anchored,
+            flags,
+            required,
+            prefix,
+            use_dfa,
+            dfa: DfaCache::default(),
+        })
+    }
+
+    /// Finds the longest literal text every match contains, and the literal
+    /// text every match starts with.
+    fn literals(node: &Node, flags: RegexFlags) -> (Vec<u8>, Vec<u8>) {
+        if flags.ignore_case {
+            return (Vec::new(), Vec::new());
+        }
+        let mut atoms = Vec::new();
+        flatten_literals(node, &mut atoms);
+        let runs: Vec<&[Option<u32>]> = atoms.split(|atom| atom.is_none()).collect();
+        let to_bytes = |run: &[Option<u32>]| {
+            let mut out = Vec::new();
+            for u in run.iter().flatten() {
+                push_unit_bytes(*u, flags.bytes, &mut out);
+            }
+            out
+        };
+        let required = runs
+            .iter()
+            .max_by_key(|run| run.len())
+            .map(|run| to_bytes(run))
+            .unwrap_or_default();
+        // A match starts where its first unit does, which a byte that is not
+        // valid UTF-8 does not tell.
+        let prefix = match atoms.first() {
+            Some(Some(u)) if flags.bytes || *u < INVALID_BYTE => to_bytes(runs[0]),
+            _ => Vec::new(),
+        };
+        (required, prefix)
+    }
+
+    /// The source pattern.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The number of parenthesized subexpressions.
+    pub fn subexpressions(&self) -> usize {
+        self.groups
+    }
+
+    /// Checks whether the expression matches anywhere in `input`.
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        if !self.contains_required(input, 0) {
+            return false;
+        }
+        if self.use_dfa && !input.is_empty() {
+            if let Ok(mut dfa) = self.dfa.0.try_lock() {
+                if let Some(matches) = self.dfa_is_match(&mut dfa, input) {
+                    return matches;
+                }
+            }
+        }
+        self.search(input, 0, false, false).is_some()
+    }
+
+    /// Finds the leftmost-longest match in `input`.
+    pub fn find(&self, input: &[u8]) -> Option<Range<usize>> {
+        self.find_at(input, 0)
+    }
+
+    /// Finds the leftmost-longest match in `input` starting at or after byte
+    /// offset `start`. `^` still only matches at the start of `input`.
+    pub fn find_at(&self, input: &[u8], start: usize) -> Option<Range<usize>> {
+        self.search(input, start, true, false)
+            .map(|captures| captures[0]..captures[1])
+    }
+
+    /// Finds the leftmost-longest match in `input`, with the ranges matched by
+    /// the whole expression and by each subexpression; subexpressions that did
+    /// not take part in the match are `None`.
+    pub fn captures(&self, input: &[u8]) -> Option<Vec<Option<Range<usize>>>> {
+        self.captures_at(input, 0)
+    }
+
+    /// Like [Regex::captures], for a match starting at or after byte offset
+    /// `start`.
+    pub fn captures_at(&self, input: &[u8], start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let captures = self.search(input, start, true, true)?;
+        Some(
+            captures
+                .chunks(2)
+                .map(|pair| (pair[0] != UNSET && pair[1] != UNSET).then(|| pair[0]..pair[1]))
+                .collect(),
+        )
+    }
+
+    /// Checks whether the expression matches exactly `input[span]`, with the
+    /// rest of `input` as the context of anchors and word operators.
+    pub fn matches_span(&self, input: &[u8], span: Range<usize>) -> bool {
+        if span.start > span.end || span.end > input.len() {
+            return false;
+        }
+        let mut visited = if self.has_back_references {
+            Visited::States(HashSet::new())
+        } else {
+            let width = span.end - span.start + 1;
+            Visited::Positions {
+                bits: vec![0; (self.program.len() * width).div_ceil(64)],
+                width,
+                offset: span.start,
+            }
+        };
+        self.match_at(input, span.start, true, &mut visited, Some(span.end))
+            .is_some()
+    }
+
+    /// Checks for the literal text of every match in `input[start..]`.
+    fn contains_required(&self, input: &[u8], start: usize) -> bool {
+        self.required.is_empty()
+            || input
+                .get(start..)
+                .is_some_and(|rest| find_literal(rest, &self.required).is_some())
+    }
+
+    /// Finds a match, the leftmost-longest one if `longest`, with the
+    /// subexpressions only if `subexpressions`.
+    fn search(
+        &self,
+        input: &[u8],
+        start: usize,
+        longest: bool,
+        subexpressions: bool,
+    ) -> Option<Vec<usize>> {
+        if start > input.len() || !self.contains_required(input, start) {
+            return None;
+        }
+        if self.has_back_references {
+            return self.backtrack_search(input, start, longest);
+        }
+        let (match_start, match_end) = self.pike_search(input, start, longest)?;
+        if !subexpressions || self.groups == 0 {
+            let mut captures = vec![UNSET; 2 * (self.groups + 1)];
+            captures[0] = match_start;
+            captures[1] = match_end;
+            return Some(captures);
+        }
+        // Backtracking finds the subexpressions, only over the span of the
+        // match.
+        let width = match_end - match_start + 1;
+        let mut visited = Visited::Positions {
+            bits: vec![0; (self.program.len() * width).div_ceil(64)],
+            width,
+            offset: match_start,
+        };
+        self.match_at(input, match_start, true, &mut visited, Some(match_end))
+    }
+
+    fn backtrack_search(&self, input: &[u8], start: usize, longest: bool) -> Option<Vec<usize>> {
+        let mut visited = Visited::States(HashSet::new());
+        // States that failed from one start position fail from every other,
+        // so `visited` is shared by all of them.
+        let mut pos = start;
+        while pos <= input.len() {
+            if let Some(captures) = self.match_at(input, pos, longest, &mut visited, None) {
+                return Some(captures);
+            }
+            if self.anchored {
+                break;
+            }
+            match decode(input, pos, self.flags.bytes) {
+                Some((_, len)) => pos += len,
+                None => break,
+            }
+        }
+        None
+    }
+
+    fn units_equal(&self, a: u32, b: u32) -> bool {
+        a == b || (self.flags.ignore_case && lower(a) == lower(b))
+    }
+
+    /// Whether the instruction at `pc`, which consumes input, accepts `u`.
+    fn consumes(&self, pc: usize, u: u32) -> bool {
+        match &self.program[pc] {
+            Inst::Unit(v) => self.units_equal(*v, u),
+            Inst::Any => true,
+            Inst::Set(i) => self.sets[*i].matches(u, &self.flags),
+            _ => false,
+        }
+    }
+
+    /// Adds the thread at `pc` to `threads`, following the instructions that
+    /// do not consume input at `pos`.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        stack: &mut Vec<usize>,
+        pc: usize,
+        start: usize,
+        input: &[u8],
+        pos: usize,
+    ) {
+        stack.push(pc);
+        while let Some(mut pc) = stack.pop() {
+            while !threads.contains(pc) {
+                threads.insert(pc, start);
+                match &self.program[pc] {
+                    Inst::Bol if pos == 0 => pc += 1,
+                    Inst::Eol if pos == input.len() => pc += 1,
+                    Inst::Word(assertion) if assertion.holds(input, pos, self.flags.bytes) => {
+                        pc += 1
+                    }
+                    Inst::Save(_) => pc += 1,
+                    Inst::Split(first, second) => {
+                        stack.push(*second);
+                        pc = *first;
+                    }
+                    Inst::Jump(target) => pc = *target,
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// Finds the bounds of the leftmost-longest match, or of any match if not
+    /// `longest`, running all threads in step over the input. Threads are in
+    /// priority order, which puts earlier starts first.
+    fn pike_search(&self, input: &[u8], start: usize, longest: bool) -> Option<(usize, usize)> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut stack = Vec::new();
+        let mut found: Option<(usize, usize)> = None;
+        let mut pos = start;
+        loop {
+            if found.is_none() && (!self.anchored || pos == start) {
+                if current.pcs.is_empty() && !self.prefix.is_empty() {
+                    pos += find_literal(&input[pos..], &self.prefix)?;
+                }
+                self.add_thread(&mut current, &mut stack, 0, pos, input, pos);
+            }
+            if current.pcs.is_empty() {
+                break;
+            }
+            let unit = decode(input, pos, self.flags.bytes);
+            for (&pc, &thread_start) in current.pcs.iter().zip(&current.starts) {
+                if found.is_some_and(|(found_start, _)| thread_start > found_start) {
+                    continue;
+                }
+                if let Inst::Match = self.program[pc] {
+                    if found.map_or(true, |(found_start, found_end)| {
+                        thread_start < found_start || pos > found_end
+                    }) {
+                        found = Some((thread_start, pos));
+                    }
+                    if !longest {
+                        return found;
+                    }
+                    continue;
+                }
+                if let Some((_, len)) = unit.filter(|&(u, _)| self.consumes(pc, u)) {
+                    self.add_thread(
+                        &mut next,
+                        &mut stack,
+                        pc + 1,
+                        thread_start,
+                        input,
+                        pos + len,
+                    );
+                }
+            }
+            let Some((_, len)) = unit else {
+                break;
+            };
+            pos += len;
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        found
+    }
+
+    /// Follows the instructions that do not consume input from `seeds`,
+    /// collecting the others. `Eol` is followed at the end of the input and
+    /// kept otherwise, `Bol` only followed at its start.
+    fn dfa_closure(&self, seeds: &[usize], at_start: bool, at_end: bool) -> Vec<usize> {
+        let mut seen = vec![false; self.program.len()];
+        let mut pcs = Vec::new();
+        let mut stack: Vec<usize> = seeds.iter().rev().copied().collect();
+        while let Some(pc) = stack.pop() {
+            if std::mem::replace(&mut seen[pc], true) {
+                continue;
+            }
+            match &self.program[pc] {
+                Inst::Bol if at_start => stack.push(pc + 1),
+                Inst::Bol => {}
+                Inst::Eol if at_end => stack.push(pc + 1),
+                Inst::Save(_) => stack.push(pc + 1),
+                Inst::Split(first, second) => {
+                    stack.push(*second);
+                    stack.push(*first);
+                }
+                Inst::Jump(target) => stack.push(*target),
+                _ => pcs.push(pc),
+            }
+        }
+        pcs.sort_unstable();
+        pcs
+    }
+
+    /// Returns the number of the DFA state for `pcs`, adding it if new, or
+    /// `None` if the DFA is full.
+    fn dfa_state(&self, dfa: &mut Dfa, pcs: Vec<usize>) -> Option<usize> {
+        if let Some(&id) = dfa.ids.get(&pcs) {
+            return Some(id);
+        }
+        if dfa.states.len() >= MAX_DFA_STATES {
+            return None;
+        }
+        let is_match = |pc: &usize| matches!(self.program[*pc], Inst::Match);
+        let eol_pcs: Vec<usize> = pcs
+            .iter()
+            .filter(|&&pc| matches!(self.program[pc], Inst::Eol))
+            .copied()
+            .collect();
+        let state = DfaState {
+            matches: pcs.iter().any(is_match),
+            matches_at_end: self.dfa_closure(&eol_pcs, false, true).iter().any(is_match),
+            pcs: pcs.clone(),
+            next: Box::new([0; 256]),
+        };
+        dfa.states.push(state);
+        dfa.ids.insert(pcs, dfa.states.len() - 1);
+        Some(dfa.states.len() - 1)
+    }
+
+    /// The state the DFA goes to from `state` on `u`, where a new match may
+    /// also start.
+    fn dfa_next(&self, dfa: &mut Dfa, state: usize, u: u32) -> Option<usize> {
+        let known = if u < 256 {
+            dfa.states[state].next[u as usize].checked_sub(1)
+        } else {
+            dfa.wide_next.get(&(state, u)).map(|&id| id as u32)
+        };
+        if let Some(id) = known {
+            return Some(id as usize);
+        }
+        let mut seeds: Vec<usize> = dfa.states[state]
+            .pcs
+            .iter()
+            .filter(|&&pc| self.consumes(pc, u))
+            .map(|pc| pc + 1)
+            .collect();
+        seeds.push(0);
+        let pcs = self.dfa_closure(&seeds, false, false);
+        let id = self.dfa_state(dfa, pcs)?;
+        if u < 256 {
+            dfa.states[state].next[u as usize] = id as u32 + 1;
+        } else {
+            dfa.wide_next.insert((state, u), id);
+        }
+        Some(id)
+    }
+
+    /// Checks for a match with the DFA, or returns `None` if it grew too
+    /// large, in which case it is started afresh.
+    fn dfa_is_match(&self, dfa: &mut Dfa, input: &[u8]) -> Option<bool> {
+        let result = (|| {
+            let initial = self.dfa_closure(&[0], true, false);
+            let mut state = self.dfa_state(dfa, initial)?;
+            let mut pos = 0;
+            loop {
+                let current = &dfa.states[state];
+                if current.matches {
+                    return Some(true);
+                }
+                if current.pcs.is_empty() && self.anchored {
+                    return Some(false);
+                }
+                let Some((u, len)) = decode(input, pos, self.flags.bytes) else {
+                    return Some(current.matches_at_end);
+                };
+                state = self.dfa_next(dfa, state, u)?;
+                pos += len;
+            }
+        })();
+        if result.is_none() {
+            *dfa = Dfa::default();
+        }
+        result
+    }
+
+    /// Backtracks from `start`, for the longest match if `longest`. With
+    /// `end`, only matches ending there count, and the first one found wins.
+    fn match_at(
+        &self,
+        input: &[u8],
+        start: usize,
+        longest: bool,
+        visited: &mut Visited,
+        end: Option<usize>,
+    ) -> Option<Vec<usize>> {
+        let bytes = self.flags.bytes;
+        let mut captures = vec![UNSET; 2 * (self.groups + 1)];
+        let mut best: Option<Vec<usize>> = None;
+        let mut jobs = vec![Job::Explore(0, start)];
+
+        while let Some(job) = jobs.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Explore(pc, pos) => (pc, pos),
+                Job::Restore(slot, value) => {
+                    captures[slot] = value;
+                    continue;
+                }
+            };
+            loop {
+                if end.is_some_and(|end| pos > end) || !visited.insert(pc, pos, &captures) {
+                    break;
+                }
+                match &self.program[pc] {
+                    Inst::Unit(u) => match decode(input, pos, bytes) {
+                        Some((v, len)) if self.units_equal(*u, v) => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        _ => break,
+                    },
+                    Inst::Any => match decode(input, pos, bytes) {
+                        Some((_, len)) => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        None => break,
+                    },
+                    Inst::Set(i) => match decode(input, pos, bytes) {
+                        Some((v, len)) if self.sets[*i].matches(v, &self.flags) => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        _ => break,
+                    },
+                    Inst::Bol => {
+                        if pos != 0 {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::Eol => {
+                        if pos != input.len() {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::Word(assertion) => {
+                        if !assertion.holds(input, pos, bytes) {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::Save(slot) => {
+                        jobs.push(Job::Restore(*slot, captures[*slot]));
+                        captures[*slot] = pos;
+                        pc += 1;
+                    }
+                    Inst::BackReference(n) => {
+                        let (s, e) = (captures[2 * n], captures[2 * n + 1]);
+                        if s == UNSET || e == UNSET {
+                            break;
+                        }
+                        let Some(len) = self.match_back_reference(input, s..e, pos) else {
+                            break;
+                        };
+                        pc += 1;
+                        pos += len;
+                    }
+                    Inst::Split(first, second) => {
+                        jobs.push(Job::Explore(*second, pos));
+                        pc = *first;
+                    }
+                    Inst::Jump(target) => pc = *target,
+                    Inst::Match => {
+                        if end.is_some() {
+                            if end == Some(pos) {
+                                return Some(captures);
+                            }
+                            break;
+                        }
+                        if best.as_ref().map_or(true, |b| pos > b[1]) {
+                            best = Some(captures.clone());
+                        }
+                        if !longest || pos == input.len() {
+                            return best;
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Matches the text of a subexpression again at `pos`, returning its
+    /// length in bytes.
+    fn match_back_reference(&self, input: &[u8], text: Range<usize>, pos: usize) -> Option<usize> {
+        let bytes = self.flags.bytes;
+        let (mut i, mut j) = (text.start, pos);
+        while i < text.end {
+            let (a, alen) = decode(input, i, bytes)?;
+            let (b, blen) = decode(input, j, bytes)?;
+            if !self.units_equal(a, b) {
+                return None;
+            }
+            i += alen;
+            j += blen;
+        }
+        Some(j - pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bre(pattern: &str) -> Regex {
+        Regex::new(pattern, RegexFlags::default()).unwrap()
+    }
+
+    fn ere(pattern: &str) -> Regex {
+        let flags = RegexFlags {
+            extended: true,
+            ..Default::default()
+        };
+        Regex::new(pattern, flags).unwrap()
+    }
+
+    fn error(pattern: &str, extended: bool) -> RegexError {
+        let flags = RegexFlags {
+            extended,
+            ..Default::default()
+        };
+        Regex::new(pattern, flags).unwrap_err()
+    }
+
+    #[test]
+    fn test_leftmost_longest() {
+        assert_eq!(ere("a|ab|abc").find(b"xabcd"), Some(1..4));
+        assert_eq!(ere("(a|ab)(c|bcd)").find(b"abcd"), Some(0..4));
+        assert_eq!(bre("x*").find(b"abc"), Some(0..0));
+        assert_eq!(ere("b+").find(b"abbbc"), Some(1..4));
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert_eq!(bre("^ab").find(b"abab"), Some(0..2));
+        assert_eq!(bre("^ab").find_at(b"abab", 1), None);
+        assert_eq!(bre("ab$").find(b"abab"), Some(2..4));
+        assert_eq!(bre("a^b$c").find(b"a^b$c"), Some(0..5));
+        assert_eq!(ere("^a|b$").find(b"cab"), Some(2..3));
+        assert_eq!(bre("*a").find(b"x*a"), Some(1..3));
+    }
+
+    #[test]
+    fn test_back_references() {
+        let re = bre(r"\(a*\)b\1");
+        assert_eq!(re.find(b"aabaa"), Some(0..5));
+        assert_eq!(re.find(b"aaba"), Some(1..4));
+        assert_eq!(ere("(.)x\\1").find(b"axbyxy"), Some(3..6));
+        assert_eq!(error(r"\(a\)\2", false), RegexError::BackReference);
+    }
+
+    #[test]
+    fn test_captures() {
+        let re = ere("([a-z]+)=([0-9]*)(;)?");
+        assert_eq!(
+            re.captures(b"  key=42"),
+            Some(vec![Some(2..8), Some(2..5), Some(6..8), None])
+        );
+        assert_eq!(re.subexpressions(), 3);
+        assert_eq!(bre(r"\(ab\)*").captures(b"ababx").unwrap()[1], Some(2..4));
+    }
+
+    #[test]
+    fn test_intervals() {
+        assert_eq!(bre(r"a\{2,3\}").find(b"aaaa"), Some(0..3));
+        assert_eq!(ere("a{2}").find(b"baaa"), Some(1..3));
+        assert_eq!(ere("a{2,}").find(b"aaaaa"), Some(0..5));
+        assert_eq!(ere("a{,x").find(b"a{,x"), Some(0..4));
+        assert_eq!(error(r"a\{1", false), RegexError::Brace);
+        assert_eq!(error(r"a\{2,1\}", false), RegexError::BadBrace);
+        assert_eq!(error("{1,3}", true), RegexError::BadRepeat);
+        assert_eq!(error(r"\{1,3\}", false), RegexError::BadRepeat);
+    }
+
+    #[test]
+    fn test_brackets() {
+        assert!(bre("[]a]").is_match(b"]"));
+        assert!(bre("[^]a]").is_match(b"b"));
+        assert!(!bre("[^]a]").is_match(b"a"));
+        assert!(bre("[a-c-]").is_match(b"-"));
+        assert!(bre("[[:digit:][:upper:]]").is_match(b"x7"));
+        assert!(!bre("[[:digit:]]").is_match(b"x"));
+        assert!(bre("[[.-.]]").is_match(b"-"));
+        assert!(bre("[[=e=]]").is_match(b"e"));
+        assert!(bre(r"[\]").is_match(b"\\"));
+        assert_eq!(error("[a", false), RegexError::Bracket);
+        assert_eq!(error("[[:foo:]]", false), RegexError::CharClass);
+        assert_eq!(error("[z-a]", false), RegexError::Range);
+        assert_eq!(error("[[.ab.]]", false), RegexError::Collate);
+    }
+
+    #[test]
+    fn test_bracket_expression_end() {
+        assert_eq!(bracket_expression_end(b"[a]/", 0), Some(3));
+        assert_eq!(bracket_expression_end(b"x[]/]/", 1), Some(5));
+        assert_eq!(bracket_expression_end(b"[^]/]/", 0), Some(5));
+        assert_eq!(bracket_expression_end(br"[\]/", 0), Some(3));
+        assert_eq!(bracket_expression_end(b"[[:alpha:]/]", 0), Some(12));
+        assert_eq!(bracket_expression_end(b"[[.].]]", 0), Some(7));
+        assert_eq!(bracket_expression_end(b"[[=]=]]", 0), Some(7));
+        assert_eq!(bracket_expression_end(b"[a/", 0), None);
+        assert_eq!(bracket_expression_end(b"[[:alpha]/", 0), None);
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let flags = RegexFlags {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let re = Regex::new("[a-c]x\\(É\\)\\1", flags).unwrap();
+        assert_eq!(re.find("BXéÉ".as_bytes()), Some(0..6));
+    }
+
+    #[test]
+    fn test_multibyte_and_bytes() {
+        assert_eq!(bre("a.b").find("aéb".as_bytes()), Some(0..4));
+        assert_eq!(bre("a.b").find(b"a\xffb"), Some(0..3));
+        let bytes = RegexFlags {
+            bytes: true,
+            ..Default::default()
+        };
+        assert!(!Regex::new("a.b", bytes).unwrap().is_match("aéb".as_bytes()));
+        assert!(Regex::new("a..b", bytes)
+            .unwrap()
+            .is_match("aéb".as_bytes()));
+        assert_eq!(bre("[^a]").find("é".as_bytes()), Some(0..2));
+    }
+
+    #[test]
+    fn test_gnu_operators() {
+        assert_eq!(bre(r"ab\+").find(b"abbb"), Some(0..4));
+        assert_eq!(bre(r"ab\?c").find(b"ac"), Some(0..2));
+        assert_eq!(bre(r"cat\|dog").find(b"hotdog"), Some(3..6));
+        assert_eq!(ere("^*a").find(b"*a"), Some(0..2));
+    }
+
+    #[test]
+    fn test_gnu_word_operators() {
+        assert_eq!(bre(r"\<o\w*").find(b"foo on"), Some(4..6));
+        assert_eq!(bre(r"o\>").find(b"foo on"), Some(2..3));
+        assert_eq!(ere(r"\bon\b").find(b"onion on"), Some(6..8));
+        assert_eq!(ere(r"\Bn").find(b"nun"), Some(2..3));
+        assert_eq!(bre(r"\W\+").find(b"a, b"), Some(1..3));
+        assert_eq!(bre(r"\<é").find("xé é".as_bytes()), Some(4..6));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error(r"\(a", false), RegexError::Paren);
+        assert_eq!(error(r"a\)", false), RegexError::Paren);
+        assert_eq!(error("(a", true), RegexError::Paren);
+        assert_eq!(error("a\\", true), RegexError::Escape);
+        assert_eq!(ere("a)").find(b"a)"), Some(0..2));
+    }
+
+    #[test]
+    fn test_nested_empty_loops() {
+        assert_eq!(ere("(a*)*b").find(b"aab"), Some(0..3));
+        assert_eq!(ere("(a*)+$").find(b"aa"), Some(0..2));
+        assert_eq!(ere("(|a)*").find(b"aa"), Some(0..2));
+    }
+
+    #[test]
+    fn test_literal_prefilter() {
+        let re = ere("(key|path)=[a-z]+ user");
+        assert!(!re.is_match(b"key=abc id"));
+        assert_eq!(re.find(b"x path=abc user"), Some(2..15));
+        assert_eq!(bre("foo=[a-z]*").find(b"xfoo foo=bar"), Some(5..12));
+        assert_eq!(bre("ab*c").find_at(b"abc xabbc", 1), Some(5..9));
+    }
+
+    #[test]
+    fn test_repeated_matching() {
+        let re = ere("^a[bc]+d$|x");
+        for _ in 0..2 {
+            assert!(re.is_match(b"abcbd"));
+            assert!(!re.is_match(b"abcb"));
+            assert!(re.is_match(b"x"));
+            assert!(!re.is_match(b""));
+        }
+        assert!(ere("^$").is_match(b""));
+        assert!(bre("a*").is_match(b""));
+    }
+
+    #[test]
+    fn test_matches_span() {
+        let re = bre("ab*");
+        assert!(re.matches_span(b"xabbb", 1..3));
+        assert!(!re.matches_span(b"xabbb", 0..3));
+        assert!(!bre("ab$").matches_span(b"abc", 0..2));
+        assert!(bre(r"\(a\)\1").matches_span(b"aaa", 1..3));
+    }
+
+    #[test]
+    fn test_captures_within_match() {
+        let re = ere("(a|ab)(c|bcd)(d*)");
+        assert_eq!(
+            re.captures(b"xabcd"),
+            Some(vec![Some(1..5), Some(1..2), Some(2..5), Some(5..5)])
+        );
+        assert_eq!(
+            ere("(a*)(b|abc)").captures_at(b"aabc aabc", 4),
+            Some(vec![Some(5..9), Some(5..6), Some(6..9)])
+        );
+    }
+}
@@ -7,256 +7,1391 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::collections::HashMap;
+//! SCCS files: the history of a text file, kept as a table of deltas and a
+//! weave of the lines every delta inserted and deleted.
+//!
+//! Each control line of an SCCS file starts with SOH (`^A`).  The first
+//! line holds a checksum of the rest of the file.  The delta table follows,
+//! newest delta first, then the authorized users, the flags, the
+//! descriptive text and the body.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::CStr;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-#[derive(Debug)]
-pub struct SccsFile {
-    pub header: SccsHeader,
-    pub deltas: Vec<SccsDelta>,
-    pub edits: Vec<SccsEdit>,
-    pub user_info: SccsUserInfo,
-    pub stats: SccsStats,
+/// The byte that starts each control line.
+pub const SOH: u8 = 0x01;
+
+/// An SCCS identification string: release, level, branch and sequence.
+/// Components not given are zero, so a partial SID such as `1` or `1.2.1`
+/// can name a release or a branch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sid {
+    pub rel: u32,
+    pub lev: u32,
+    pub br: u32,
+    pub seq: u32,
 }
 
-#[derive(Debug)]
-pub struct SccsHeader {
-    pub format_version: String,
-    pub creation_date: String,
-    pub author: String,
-    pub description: String,
+impl Sid {
+    pub fn trunk(rel: u32, lev: u32) -> Self {
+        Sid {
+            rel,
+            lev,
+            br: 0,
+            seq: 0,
+        }
+    }
+
+    pub fn branch(rel: u32, lev: u32, br: u32, seq: u32) -> Self {
+        Sid { rel, lev, br, seq }
+    }
+
+    pub fn is_branch(&self) -> bool {
+        self.br > 0
+    }
+
+    /// The number of components given.
+    pub fn components(&self) -> usize {
+        if self.seq > 0 {
+            4
+        } else if self.br > 0 {
+            3
+        } else if self.lev > 0 {
+            2
+        } else {
+            1
+        }
+    }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.rel)?;
+        if self.lev > 0 {
+            write!(f, ".{}", self.lev)?;
+        }
+        if self.br > 0 {
+            write!(f, ".{}", self.br)?;
+        }
+        if self.seq > 0 {
+            write!(f, ".{}", self.seq)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Sid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid SID: {}", s);
+        let parts: Vec<&str> = s.split('.').collect();
+        if parts.is_empty() || parts.len() > 4 {
+            return Err(invalid());
+        }
+        let mut numbers = [0u32; 4];
+        for (i, part) in parts.iter().enumerate() {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            numbers[i] = part.parse().map_err(|_| invalid())?;
+            if numbers[i] == 0 || numbers[i] > 9999 {
+                return Err(invalid());
+            }
+        }
+        Ok(Sid {
+            rel: numbers[0],
+            lev: numbers[1],
+            br: numbers[2],
+            seq: numbers[3],
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaType {
+    /// A delta in effect.
+    Normal,
+
+    /// A delta removed by `rmdel`, kept in the table for the record.
+    Removed,
+}
+
+/// An entry of the delta table.
+#[derive(Clone, Debug)]
 pub struct SccsDelta {
-    pub sid: String,
+    pub delta_type: DeltaType,
+    pub sid: Sid,
+
+    /// The date as `YY/MM/DD` and the time as `HH:MM:SS`.
     pub date: String,
     pub time: String,
     pub author: String,
-    pub added_lines: usize,
-    pub deleted_lines: usize,
-    pub comments: String,
+
+    /// The serial number of the delta, and that of its predecessor.
+    pub serial: usize,
+    pub pred: usize,
+
+    /// The lines the delta inserted, deleted and left unchanged.
+    pub inserted: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+
+    /// Serial numbers of the deltas included, excluded and ignored when
+    /// the delta was made.
+    pub included: Vec<usize>,
+    pub excluded: Vec<usize>,
+    pub ignored: Vec<usize>,
+
+    /// Modification request numbers.
+    pub mrs: Vec<String>,
+    pub comments: Vec<String>,
 }
 
-#[derive(Debug)]
-pub enum SccsEdit {
-    Insert(String),
+impl SccsDelta {
+    /// The date and time as `YYYYMMDDHHMMSS`, which sorts in time order.
+    pub fn timestamp(&self) -> String {
+        timestamp(&self.date, &self.time)
+    }
+}
+
+/// A line of the body: a control line that starts or ends the lines a
+/// delta inserted or deleted, or a line of text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BodyLine {
+    Insert(usize),
     Delete(usize),
+    End(usize),
+    Text(Vec<u8>),
 }
 
 #[derive(Debug)]
+pub struct SccsHeader {
+    /// The checksum recorded in the file.
+    pub checksum: u16,
+}
+
+#[derive(Debug, Default)]
 pub struct SccsUserInfo {
-    pub users: HashMap<String, String>,
+    /// Login names and group IDs allowed to make deltas; everyone when
+    /// empty.  A name starting with `!` denies the user.
+    pub users: Vec<String>,
 }
 
 #[derive(Debug)]
-pub struct SccsStats {
-    pub total_deltas: usize,
-    pub total_lines: usize,
+pub struct SccsFile {
+    pub header: SccsHeader,
+
+    /// The delta table, newest delta first.
+    pub deltas: Vec<SccsDelta>,
+    pub user_info: SccsUserInfo,
+
+    /// Flags by letter, with their values, empty for flags without one.
+    pub flags: BTreeMap<char, String>,
+    pub description: Vec<String>,
+    pub body: Vec<BodyLine>,
+}
+
+/// How a delta takes part in a version being retrieved.
+#[derive(Clone, Copy, PartialEq)]
+enum Applied {
+    Included,
+    Excluded,
 }
 
 impl SccsFile {
-    pub fn from_string(s: &str) -> Result<Self, &'static str> {
-        let lines: Vec<&str> = s.lines().collect();
+    /// A new file with one delta holding `lines`.
+    pub fn new(delta: SccsDelta, lines: Vec<Vec<u8>>) -> Self {
+        let serial = delta.serial;
+        let mut delta = delta;
+        delta.inserted = lines.len();
+        let mut body = vec![BodyLine::Insert(serial)];
+        body.extend(lines.into_iter().map(BodyLine::Text));
+        body.push(BodyLine::End(serial));
+        SccsFile {
+            header: SccsHeader { checksum: 0 },
+            deltas: vec![delta],
+            user_info: SccsUserInfo::default(),
+            flags: BTreeMap::new(),
+            description: Vec::new(),
+            body,
+        }
+    }
 
-        let header = parse_header(&lines)?;
-        let deltas = parse_deltas(&lines)?;
-        let edits = parse_edits(&lines)?;
-        let user_info = parse_user_info(&lines)?;
-        let stats = parse_stats(&deltas)?;
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        Self::from_bytes(s.as_bytes())
+    }
 
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut parser = Parser::new(data);
+        let header = parser.header()?;
+        let deltas = parser.deltas()?;
+        let user_info = parser.users()?;
+        let flags = parser.flags()?;
+        let description = parser.description()?;
+        let body = parser.body()?;
         Ok(SccsFile {
             header,
             deltas,
-            edits,
             user_info,
-            stats,
+            flags,
+            description,
+            body,
         })
     }
 
-    pub fn serialize(&self) -> String {
-        serialize_sccs_file(self)
+    /// Reads and parses an SCCS file.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        Self::from_bytes(&data)
     }
-}
 
-fn parse_header(lines: &[&str]) -> Result<SccsHeader, &'static str> {
-    let mut format_version = String::new();
-    let mut creation_date = String::new();
-    let author = String::new();
-    let description = String::new();
+    /// Returns the file as written to disk, with its checksum.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut rest = Vec::new();
+        for delta in &self.deltas {
+            control(
+                &mut rest,
+                &format!(
+                    "s {:05}/{:05}/{:05}",
+                    delta.inserted, delta.deleted, delta.unchanged
+                ),
+            );
+            control(
+                &mut rest,
+                &format!(
+                    "d {} {} {} {} {} {} {}",
+                    match delta.delta_type {
+                        DeltaType::Normal => 'D',
+                        DeltaType::Removed => 'R',
+                    },
+                    delta.sid,
+                    delta.date,
+                    delta.time,
+                    delta.author,
+                    delta.serial,
+                    delta.pred
+                ),
+            );
+            for (letter, list) in [
+                ('i', &delta.included),
+                ('x', &delta.excluded),
+                ('g', &delta.ignored),
+            ] {
+                if !list.is_empty() {
+                    let list: Vec<String> = list.iter().map(|n| n.to_string()).collect();
+                    control(&mut rest, &format!("{} {}", letter, list.join(" ")));
+                }
+            }
+            for mr in &delta.mrs {
+                control(&mut rest, &format!("m {}", mr));
+            }
+            for comment in &delta.comments {
+                control(&mut rest, &format!("c {}", comment));
+            }
+            control(&mut rest, "e");
+        }
+
+        control(&mut rest, "u");
+        for user in &self.user_info.users {
+            rest.extend_from_slice(user.as_bytes());
+            rest.push(b'\n');
+        }
+        control(&mut rest, "U");
 
-    for line in lines {
-        if line.starts_with('h') {
-            format_version = line.to_string();
-        } else if line.starts_with('s') && creation_date.is_empty() {
-            creation_date = line.to_string();
-        } else if line.starts_with('d') {
-            break; // End of header section
+        for (letter, value) in &self.flags {
+            if value.is_empty() {
+                control(&mut rest, &format!("f {}", letter));
+            } else {
+                control(&mut rest, &format!("f {} {}", letter, value));
+            }
         }
+
+        control(&mut rest, "t");
+        for line in &self.description {
+            rest.extend_from_slice(line.as_bytes());
+            rest.push(b'\n');
+        }
+        control(&mut rest, "T");
+
+        for line in &self.body {
+            match line {
+                BodyLine::Insert(serial) => control(&mut rest, &format!("I {}", serial)),
+                BodyLine::Delete(serial) => control(&mut rest, &format!("D {}", serial)),
+                BodyLine::End(serial) => control(&mut rest, &format!("E {}", serial)),
+                BodyLine::Text(text) => {
+                    rest.extend_from_slice(text);
+                    rest.push(b'\n');
+                }
+            }
+        }
+
+        let mut result = Vec::with_capacity(rest.len() + 8);
+        control(&mut result, &format!("h{:05}", checksum(&rest)));
+        result.extend(rest);
+        result
+    }
+
+    /// Writes the file through a temporary `x.` file, read-only, replacing
+    /// the old one.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let temp = sibling(path, "x.");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o444)
+            .open(&temp)?;
+        file.write_all(&self.serialize())?;
+        drop(file);
+        fs::rename(&temp, path)
+    }
+
+    pub fn flag(&self, letter: char) -> Option<&str> {
+        self.flags.get(&letter).map(String::as_str)
+    }
+
+    pub fn delta_by_serial(&self, serial: usize) -> Option<&SccsDelta> {
+        self.deltas.iter().find(|d| d.serial == serial)
+    }
+
+    pub fn delta_by_sid(&self, sid: &Sid) -> Option<&SccsDelta> {
+        self.deltas
+            .iter()
+            .find(|d| d.sid == *sid && d.delta_type == DeltaType::Normal)
+    }
+
+    pub fn max_serial(&self) -> usize {
+        self.deltas.iter().map(|d| d.serial).max().unwrap_or(0)
+    }
+
+    /// The deltas in effect, newest first.
+    pub fn active_deltas(&self) -> impl Iterator<Item = &SccsDelta> {
+        self.deltas
+            .iter()
+            .filter(|d| d.delta_type == DeltaType::Normal)
+    }
+
+    /// Finds the delta a SID given to `get` names: the newest delta of the
+    /// trunk when no SID is given (or the one the `d` flag names), the
+    /// newest of a release, or of a branch, or the exact delta.
+    pub fn find_sid(&self, sid: Option<&Sid>) -> Result<&SccsDelta, String> {
+        let default;
+        let sid = match sid {
+            Some(sid) => sid,
+            None => match self.flag('d').map(Sid::from_str) {
+                Some(Ok(sid)) => {
+                    default = sid;
+                    &default
+                }
+                _ => {
+                    return self
+                        .active_deltas()
+                        .filter(|d| !d.sid.is_branch())
+                        .max_by_key(|d| d.sid)
+                        .ok_or_else(|| String::from("no deltas"));
+                }
+            },
+        };
+        let not_found = || format!("nonexistent SID: {}", sid);
+        match sid.components() {
+            1 => {
+                // The newest delta of the release, or of the newest release
+                // before it.
+                self.active_deltas()
+                    .filter(|d| !d.sid.is_branch() && d.sid.rel <= sid.rel)
+                    .max_by_key(|d| d.sid)
+                    .ok_or_else(not_found)
+            }
+            3 => self
+                .active_deltas()
+                .filter(|d| d.sid.rel == sid.rel && d.sid.lev == sid.lev && d.sid.br == sid.br)
+                .max_by_key(|d| d.sid.seq)
+                .ok_or_else(not_found),
+            _ => self.delta_by_sid(sid).ok_or_else(not_found),
+        }
+    }
+
+    /// The newest delta of a release, branches included, as `get -t` wants.
+    pub fn top_of_release(&self, rel: u32) -> Option<&SccsDelta> {
+        self.active_deltas()
+            .filter(|d| d.sid.rel == rel)
+            .max_by_key(|d| d.serial)
+    }
+
+    /// Works out the SID of the delta that editing `base` will make: the
+    /// next level of the trunk, or the next delta of a branch, or a new
+    /// branch when the successor is taken or `branch` asks for one.
+    /// `release` is the release `get -r` named, when it names one beyond
+    /// `base`.  The SIDs of removed deltas can be used again.
+    pub fn next_sid(&self, base: &Sid, release: Option<u32>, branch: bool) -> Sid {
+        let sids: HashSet<Sid> = self.active_deltas().map(|d| d.sid).collect();
+        if let Some(rel) = release.filter(|&rel| rel > base.rel) {
+            if !branch && !sids.contains(&Sid::trunk(rel, 1)) {
+                return Sid::trunk(rel, 1);
+            }
+        }
+        if !base.is_branch() {
+            let successor = Sid::trunk(base.rel, base.lev + 1);
+            let newest_in_release = self
+                .active_deltas()
+                .filter(|d| !d.sid.is_branch() && d.sid.rel == base.rel)
+                .all(|d| d.sid.lev <= base.lev);
+            if !branch && newest_in_release && !sids.contains(&successor) {
+                return successor;
+            }
+        } else {
+            let successor = Sid::branch(base.rel, base.lev, base.br, base.seq + 1);
+            let newest_on_branch = self.active_deltas().all(|d| {
+                !(d.sid.rel == base.rel
+                    && d.sid.lev == base.lev
+                    && d.sid.br == base.br
+                    && d.sid.seq > base.seq)
+            });
+            if !branch && newest_on_branch {
+                return successor;
+            }
+        }
+        let br = self
+            .active_deltas()
+            .filter(|d| d.sid.rel == base.rel && d.sid.lev == base.lev)
+            .map(|d| d.sid.br)
+            .max()
+            .unwrap_or(0);
+        Sid::branch(base.rel, base.lev, br + 1, 1)
+    }
+
+    /// Works out which deltas make up the version of the delta `serial`:
+    /// it and its predecessors, with the deltas they included and without
+    /// those they excluded, adjusted by `include` and `exclude`.
+    pub fn applied_serials(
+        &self,
+        serial: usize,
+        include: &[usize],
+        exclude: &[usize],
+    ) -> HashSet<usize> {
+        let by_serial: HashMap<usize, &SccsDelta> =
+            self.deltas.iter().map(|d| (d.serial, d)).collect();
+        let mut state: HashMap<usize, Applied> = HashMap::new();
+        for &s in exclude {
+            state.insert(s, Applied::Excluded);
+        }
+        for &s in include {
+            state.entry(s).or_insert(Applied::Included);
+        }
+        state.entry(serial).or_insert(Applied::Included);
+        for s in (1..=self.max_serial()).rev() {
+            if state.get(&s) != Some(&Applied::Included) {
+                continue;
+            }
+            let Some(delta) = by_serial.get(&s) else {
+                continue;
+            };
+            if delta.delta_type == DeltaType::Removed {
+                state.insert(s, Applied::Excluded);
+                continue;
+            }
+            if delta.pred > 0 {
+                state.entry(delta.pred).or_insert(Applied::Included);
+            }
+            for &i in &delta.included {
+                state.entry(i).or_insert(Applied::Included);
+            }
+            for &x in delta.excluded.iter().chain(&delta.ignored) {
+                state.entry(x).or_insert(Applied::Excluded);
+            }
+        }
+        state
+            .into_iter()
+            .filter(|&(s, applied)| {
+                applied == Applied::Included
+                    && by_serial
+                        .get(&s)
+                        .is_some_and(|d| d.delta_type == DeltaType::Normal)
+            })
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    /// Returns the lines of the version made of the `applied` deltas, each
+    /// with the serial number of the delta that inserted it.
+    pub fn extract(&self, applied: &HashSet<usize>) -> Vec<(usize, &[u8])> {
+        let mut lines = Vec::new();
+        let mut state = WeaveState::default();
+        for line in &self.body {
+            match line {
+                BodyLine::Text(text) => {
+                    if let Some(serial) = state.visible(applied) {
+                        lines.push((serial, text.as_slice()));
+                    }
+                }
+                control => state.apply(control),
+            }
+        }
+        lines
     }
 
-    if format_version.is_empty() || creation_date.is_empty() {
-        return Err("Missing header");
+    /// Adds a delta made from the version of the `applied` deltas by
+    /// changing its lines to `new_lines`, recording the counts of lines
+    /// inserted, deleted and unchanged in the delta.
+    pub fn add_delta(
+        &mut self,
+        mut delta: SccsDelta,
+        applied: &HashSet<usize>,
+        new_lines: &[Vec<u8>],
+    ) -> Vec<Hunk> {
+        let old_lines: Vec<Vec<u8>> = self
+            .extract(applied)
+            .into_iter()
+            .map(|(_, text)| text.to_vec())
+            .collect();
+        let hunks = diff(&old_lines, new_lines);
+        let serial = delta.serial;
+
+        let mut deleted = vec![false; old_lines.len()];
+        let mut inserts: HashMap<usize, Vec<Vec<u8>>> = HashMap::new();
+        for hunk in &hunks {
+            for flag in &mut deleted[hunk.old_start..hunk.old_start + hunk.old_len] {
+                *flag = true;
+            }
+            if hunk.new_len > 0 {
+                inserts.insert(
+                    hunk.old_start + hunk.old_len,
+                    new_lines[hunk.new_start..hunk.new_start + hunk.new_len].to_vec(),
+                );
+            }
+        }
+        delta.deleted = deleted.iter().filter(|&&d| d).count();
+        delta.inserted = hunks.iter().map(|h| h.new_len).sum();
+        delta.unchanged = old_lines.len() - delta.deleted;
+
+        let mut body = Vec::with_capacity(self.body.len() + new_lines.len() + 4);
+        let emit_insert =
+            |body: &mut Vec<BodyLine>, inserts: &mut HashMap<usize, Vec<Vec<u8>>>, at| {
+                if let Some(lines) = inserts.remove(&at) {
+                    body.push(BodyLine::Insert(serial));
+                    body.extend(lines.into_iter().map(BodyLine::Text));
+                    body.push(BodyLine::End(serial));
+                }
+            };
+        emit_insert(&mut body, &mut inserts, 0);
+        let mut state = WeaveState::default();
+        let mut visible = 0;
+        let mut deleting = false;
+        for line in std::mem::take(&mut self.body) {
+            match line {
+                BodyLine::Text(text) if state.visible(applied).is_some() => {
+                    if deleted[visible] != deleting {
+                        body.push(if deleting {
+                            BodyLine::End(serial)
+                        } else {
+                            BodyLine::Delete(serial)
+                        });
+                        deleting = !deleting;
+                    }
+                    body.push(BodyLine::Text(text));
+                    visible += 1;
+                    if inserts.contains_key(&visible) {
+                        if deleting {
+                            body.push(BodyLine::End(serial));
+                            deleting = false;
+                        }
+                        emit_insert(&mut body, &mut inserts, visible);
+                    }
+                }
+                line => {
+                    if deleting {
+                        body.push(BodyLine::End(serial));
+                        deleting = false;
+                    }
+                    state.apply(&line);
+                    body.push(line);
+                }
+            }
+        }
+        if deleting {
+            body.push(BodyLine::End(serial));
+        }
+        self.body = body;
+        self.deltas.insert(0, delta);
+        hunks
     }
 
-    Ok(SccsHeader {
-        format_version,
-        creation_date,
-        author,
-        description,
-    })
+    /// Removes the changes a delta made to the body and marks it removed,
+    /// as `rmdel` does.
+    pub fn remove_delta(&mut self, serial: usize) {
+        let mut body = Vec::with_capacity(self.body.len());
+        let mut inserting = false;
+        for line in std::mem::take(&mut self.body) {
+            match line {
+                BodyLine::Insert(s) if s == serial => inserting = true,
+                BodyLine::Delete(s) if s == serial => {}
+                BodyLine::End(s) if s == serial => inserting = false,
+                _ if inserting => {}
+                line => body.push(line),
+            }
+        }
+        self.body = body;
+        if let Some(delta) = self.deltas.iter_mut().find(|d| d.serial == serial) {
+            delta.delta_type = DeltaType::Removed;
+        }
+    }
+}
+
+fn control(out: &mut Vec<u8>, text: &str) {
+    out.push(SOH);
+    out.extend_from_slice(text.as_bytes());
+    out.push(b'\n');
+}
+
+/// The checksum of an SCCS file: the sum of the bytes after its first
+/// line, modulo 2^16.
+pub fn checksum(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)))
+}
+
+/// Checks the checksum recorded in the first line of raw file data.
+pub fn verify_checksum(data: &[u8]) -> bool {
+    let Some(end) = data.iter().position(|&b| b == b'\n') else {
+        return false;
+    };
+    let first = &data[..end];
+    if first.len() < 3 || first[0] != SOH || first[1] != b'h' {
+        return false;
+    }
+    let recorded = std::str::from_utf8(&first[2..])
+        .ok()
+        .and_then(|s| s.parse::<u16>().ok());
+    recorded == Some(checksum(&data[end + 1..]))
 }
 
-fn parse_edits(lines: &[&str]) -> Result<Vec<SccsEdit>, &'static str> {
-    let mut edits = Vec::new();
-    let mut current_insert = String::new();
-    let mut in_insert_section = false;
+/// Tracks the blocks of the weave that enclose a line.
+#[derive(Default)]
+struct WeaveState {
+    /// Open blocks: the serial number, and whether it inserts.
+    open: Vec<(usize, bool)>,
+}
 
-    for line in lines.iter() {
-        if line.starts_with("I ") {
-            in_insert_section = true;
-            current_insert.push_str(line);
-            current_insert.push('\n');
-        } else if line.starts_with("E ") && in_insert_section {
-            current_insert.push_str(line);
-            current_insert.push('\n');
-            edits.push(SccsEdit::Insert(current_insert.clone()));
-            current_insert.clear();
-            in_insert_section = false;
-        } else if in_insert_section {
-            current_insert.push_str(line);
-            current_insert.push('\n');
+impl WeaveState {
+    fn apply(&mut self, line: &BodyLine) {
+        match *line {
+            BodyLine::Insert(serial) => self.open.push((serial, true)),
+            BodyLine::Delete(serial) => self.open.push((serial, false)),
+            BodyLine::End(serial) => {
+                if let Some(i) = self.open.iter().rposition(|&(s, _)| s == serial) {
+                    self.open.remove(i);
+                }
+            }
+            BodyLine::Text(_) => {}
         }
     }
 
-    Ok(edits)
+    /// Whether a line in the open blocks is in the version of the
+    /// `applied` deltas: the innermost block inserting it must be applied
+    /// and no block deleting it.  Returns the delta that inserted it.
+    fn visible(&self, applied: &HashSet<usize>) -> Option<usize> {
+        let inserted_by = self
+            .open
+            .iter()
+            .rev()
+            .find(|&&(_, insert)| insert)
+            .map(|&(serial, _)| serial)?;
+        let deleted = self
+            .open
+            .iter()
+            .any(|&(serial, insert)| !insert && applied.contains(&serial));
+        (applied.contains(&inserted_by) && !deleted).then_some(inserted_by)
+    }
+}
+
+struct Parser<'a> {
+    lines: Vec<&'a [u8]>,
+    pos: usize,
 }
 
-fn parse_user_info(lines: &[&str]) -> Result<SccsUserInfo, &'static str> {
-    // Simplified user info parsing
-    let mut users = HashMap::new();
-    for line in lines.iter().skip_while(|l| !l.starts_with("users")).skip(1) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            return Err("Invalid user info format");
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let data = data.strip_suffix(b"\n").unwrap_or(data);
+        let lines = if data.is_empty() {
+            Vec::new()
+        } else {
+            data.split(|&b| b == b'\n').collect()
+        };
+        Parser { lines, pos: 0 }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} (line {})", message, self.pos + 1)
+    }
+
+    /// Returns the text of the next control line if it starts with
+    /// `keyletter`, and moves past it.
+    fn take(&mut self, keyletter: u8) -> Option<String> {
+        let line = self.lines.get(self.pos)?;
+        if line.len() >= 2 && line[0] == SOH && line[1] == keyletter {
+            self.pos += 1;
+            let rest = &line[2..];
+            let rest = rest.strip_prefix(b" ").unwrap_or(rest);
+            Some(String::from_utf8_lossy(rest).into_owned())
+        } else {
+            None
         }
-        users.insert(parts[0].to_string(), parts[1].to_string());
     }
-    Ok(SccsUserInfo { users })
+
+    fn expect(&mut self, keyletter: u8) -> Result<String, String> {
+        self.take(keyletter)
+            .ok_or_else(|| self.error("corrupted file"))
+    }
+
+    fn header(&mut self) -> Result<SccsHeader, String> {
+        let text = self
+            .take(b'h')
+            .ok_or_else(|| String::from("not an SCCS file"))?;
+        let checksum = text
+            .trim()
+            .parse()
+            .map_err(|_| self.error("bad checksum line"))?;
+        Ok(SccsHeader { checksum })
+    }
+
+    fn numbers(&self, text: &str) -> Result<Vec<usize>, String> {
+        text.split_whitespace()
+            .map(|n| n.parse().map_err(|_| self.error("bad serial number")))
+            .collect()
+    }
+
+    fn deltas(&mut self) -> Result<Vec<SccsDelta>, String> {
+        let mut deltas = Vec::new();
+        while let Some(stats) = self.take(b's') {
+            let counts: Vec<usize> = stats
+                .split('/')
+                .map(|n| {
+                    n.trim()
+                        .parse()
+                        .map_err(|_| self.error("bad delta statistics"))
+                })
+                .collect::<Result<_, _>>()?;
+            if counts.len() != 3 {
+                return Err(self.error("bad delta statistics"));
+            }
+            let entry = self.expect(b'd')?;
+            let parts: Vec<&str> = entry.split_whitespace().collect();
+            if parts.len() != 7 {
+                return Err(self.error("bad delta entry"));
+            }
+            let delta_type = match parts[0] {
+                "D" => DeltaType::Normal,
+                "R" => DeltaType::Removed,
+                _ => return Err(self.error("bad delta type")),
+            };
+            let number = |s: &str| s.parse().map_err(|_| self.error("bad serial number"));
+            let mut delta = SccsDelta {
+                delta_type,
+                sid: parts[1].parse().map_err(|e: String| self.error(&e))?,
+                date: parts[2].to_string(),
+                time: parts[3].to_string(),
+                author: parts[4].to_string(),
+                serial: number(parts[5])?,
+                pred: number(parts[6])?,
+                inserted: counts[0],
+                deleted: counts[1],
+                unchanged: counts[2],
+                included: Vec::new(),
+                excluded: Vec::new(),
+                ignored: Vec::new(),
+                mrs: Vec::new(),
+                comments: Vec::new(),
+            };
+            loop {
+                if let Some(list) = self.take(b'i') {
+                    delta.included = self.numbers(&list)?;
+                } else if let Some(list) = self.take(b'x') {
+                    delta.excluded = self.numbers(&list)?;
+                } else if let Some(list) = self.take(b'g') {
+                    delta.ignored = self.numbers(&list)?;
+                } else if let Some(mr) = self.take(b'm') {
+                    delta.mrs.push(mr);
+                } else if let Some(comment) = self.take(b'c') {
+                    delta.comments.push(comment);
+                } else {
+                    self.expect(b'e')?;
+                    break;
+                }
+            }
+            deltas.push(delta);
+        }
+        Ok(deltas)
+    }
+
+    /// Reads text lines up to a control line with `keyletter`.
+    fn text_until(&mut self, keyletter: u8) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            if self.take(keyletter).is_some() {
+                return Ok(lines);
+            }
+            match self.lines.get(self.pos) {
+                Some(line) if line.first() != Some(&SOH) => {
+                    lines.push(String::from_utf8_lossy(line).into_owned());
+                    self.pos += 1;
+                }
+                _ => return Err(self.error("corrupted file")),
+            }
+        }
+    }
+
+    fn users(&mut self) -> Result<SccsUserInfo, String> {
+        self.expect(b'u')?;
+        let users = self.text_until(b'U')?;
+        Ok(SccsUserInfo { users })
+    }
+
+    fn flags(&mut self) -> Result<BTreeMap<char, String>, String> {
+        let mut flags = BTreeMap::new();
+        while let Some(flag) = self.take(b'f') {
+            let mut chars = flag.chars();
+            let Some(letter) = chars.next() else {
+                return Err(self.error("bad flag"));
+            };
+            flags.insert(letter, chars.as_str().trim_start().to_string());
+        }
+        Ok(flags)
+    }
+
+    fn description(&mut self) -> Result<Vec<String>, String> {
+        self.expect(b't')?;
+        self.text_until(b'T')
+    }
+
+    fn body(&mut self) -> Result<Vec<BodyLine>, String> {
+        let mut body = Vec::new();
+        let mut open = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.first() == Some(&SOH) {
+                let text = String::from_utf8_lossy(&line[1..]).into_owned();
+                let mut parts = text.split_whitespace();
+                let keyletter = parts.next().unwrap_or("");
+                let serial: usize = parts
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| self.error("bad body control line"))?;
+                body.push(match keyletter {
+                    "I" => {
+                        open.push(serial);
+                        BodyLine::Insert(serial)
+                    }
+                    "D" => {
+                        open.push(serial);
+                        BodyLine::Delete(serial)
+                    }
+                    "E" => {
+                        let Some(i) = open.iter().rposition(|&s| s == serial) else {
+                            return Err(self.error("unmatched end of block"));
+                        };
+                        open.remove(i);
+                        BodyLine::End(serial)
+                    }
+                    _ => return Err(self.error("bad body control line")),
+                });
+            } else {
+                body.push(BodyLine::Text(line.to_vec()));
+            }
+            self.pos += 1;
+        }
+        if !open.is_empty() {
+            return Err(self.error("premature end of file"));
+        }
+        Ok(body)
+    }
 }
 
-fn parse_deltas(lines: &[&str]) -> Result<Vec<SccsDelta>, &'static str> {
-    let mut deltas = Vec::new();
-    let mut current_comments = String::new();
-    let mut in_delta_section = false;
+/// A run of lines that differ between two versions: `old_len` lines of the
+/// old one starting at `old_start` became `new_len` lines of the new one
+/// starting at `new_start`.  Indexes count from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
 
-    for line in lines.iter() {
-        if line.starts_with("d D") {
-            in_delta_section = true;
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() < 8 {
-                return Err("Invalid delta format");
+/// Compares two versions of a file by lines, with the Myers algorithm.
+pub fn diff(old: &[Vec<u8>], new: &[Vec<u8>]) -> Vec<Hunk> {
+    // Leave out the lines the versions start and end with in common.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
             }
-            deltas.push(SccsDelta {
-                sid: parts[2].to_string(),
-                date: parts[3].to_string(),
-                time: parts[4].to_string(),
-                author: parts[5].to_string(),
-                added_lines: usize::from_str(parts[6]).map_err(|_| "Invalid number format")?,
-                deleted_lines: usize::from_str(parts[7]).map_err(|_| "Invalid number format")?,
-                comments: String::new(),
+            v[i] = x;
+            if x >= n && y >= m {
+                trace.push(v.clone());
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk back through the search to find the matching lines.
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize - 1).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let i = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (mi, mj) in matches
+        .into_iter()
+        .chain(std::iter::once((a.len(), b.len())))
+    {
+        if mi > i || mj > j {
+            hunks.push(Hunk {
+                old_start: prefix + i,
+                old_len: mi - i,
+                new_start: prefix + j,
+                new_len: mj - j,
             });
-        } else if in_delta_section {
-            if let Some(st) = line.strip_prefix("c ") {
-                current_comments.push_str(st);
-                current_comments.push('\n');
-            } else if line.starts_with("e") {
-                if let Some(last_delta) = deltas.last_mut() {
-                    last_delta.comments = current_comments.clone();
+        }
+        i = mi + 1;
+        j = mj + 1;
+    }
+    hunks
+}
+
+/// Formats the differences the way `diff` does by default.
+pub fn format_diff(old: &[Vec<u8>], new: &[Vec<u8>], hunks: &[Hunk]) -> Vec<u8> {
+    let range = |start: usize, len: usize| {
+        if len <= 1 {
+            format!("{}", start + len.max(1))
+        } else {
+            format!("{},{}", start + 1, start + len)
+        }
+    };
+    let mut out = Vec::new();
+    for hunk in hunks {
+        let (kind, old_range, new_range) = match (hunk.old_len, hunk.new_len) {
+            (0, _) => (
+                'a',
+                format!("{}", hunk.old_start),
+                range(hunk.new_start, hunk.new_len),
+            ),
+            (_, 0) => (
+                'd',
+                range(hunk.old_start, hunk.old_len),
+                format!("{}", hunk.new_start),
+            ),
+            _ => (
+                'c',
+                range(hunk.old_start, hunk.old_len),
+                range(hunk.new_start, hunk.new_len),
+            ),
+        };
+        out.extend_from_slice(format!("{}{}{}\n", old_range, kind, new_range).as_bytes());
+        for line in &old[hunk.old_start..hunk.old_start + hunk.old_len] {
+            out.extend_from_slice(b"< ");
+            out.extend_from_slice(line);
+            out.push(b'\n');
+        }
+        if hunk.old_len > 0 && hunk.new_len > 0 {
+            out.extend_from_slice(b"---\n");
+        }
+        for line in &new[hunk.new_start..hunk.new_start + hunk.new_len] {
+            out.extend_from_slice(b"> ");
+            out.extend_from_slice(line);
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// Splits file contents into lines, without their newlines.
+pub fn split_lines(data: &[u8]) -> Vec<Vec<u8>> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    if data.is_empty() {
+        return Vec::new();
+    }
+    data.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect()
+}
+
+/// Converts a date as `YY/MM/DD` and a time as `HH:MM:SS` to
+/// `YYYYMMDDHHMMSS`.  Years before 69 are in the 2000s.
+pub fn timestamp(date: &str, time: &str) -> String {
+    let digits: String = date
+        .chars()
+        .chain(time.chars())
+        .filter(char::is_ascii_digit)
+        .collect();
+    let century = if digits.get(..2).is_some_and(|yy| yy < "69") {
+        "20"
+    } else {
+        "19"
+    };
+    format!("{}{}", century, digits)
+}
+
+/// Parses a cutoff date-time `YY[MM[DD[HH[MM[SS]]]]]`, in which any
+/// non-digits separate the fields, to the `YYYYMMDDHHMMSS` of the latest
+/// moment it names.
+pub fn parse_cutoff(text: &str) -> Result<String, String> {
+    let invalid = || format!("invalid date/time: {}", text);
+    let mut fields: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            current.push(c);
+            if current.len() == 2 {
+                fields.push(std::mem::take(&mut current));
+            }
+        } else if !current.is_empty() {
+            return Err(invalid());
+        }
+    }
+    if !current.is_empty() || fields.is_empty() || fields.len() > 6 {
+        return Err(invalid());
+    }
+    let defaults = ["", "12", "31", "23", "59", "59"];
+    let mut date = String::new();
+    for (i, default) in defaults.iter().enumerate() {
+        date.push_str(fields.get(i).map_or(default, String::as_str));
+    }
+    Ok(timestamp(&date[..6], &date[6..]))
+}
+
+/// The current local date as `YY/MM/DD` and time as `HH:MM:SS`.
+pub fn now() -> (String, String) {
+    unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&t, &mut tm);
+        (
+            format!(
+                "{:02}/{:02}/{:02}",
+                tm.tm_year % 100,
+                tm.tm_mon + 1,
+                tm.tm_mday
+            ),
+            format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec),
+        )
+    }
+}
+
+/// The login name of the real user, as SCCS records it.
+pub fn user_name() -> String {
+    unsafe {
+        let uid = libc::getuid();
+        let pw = libc::getpwuid(uid);
+        if !pw.is_null() {
+            return CStr::from_ptr((*pw).pw_name).to_string_lossy().into_owned();
+        }
+        std::env::var("LOGNAME").unwrap_or_else(|_| uid.to_string())
+    }
+}
+
+/// Whether a user may make deltas, by the user list of a file.
+pub fn user_allowed(users: &[String], name: &str) -> bool {
+    if users.is_empty() {
+        return true;
+    }
+    let gid = unsafe { libc::getgid() }.to_string();
+    let matches = |entry: &str| entry == name || entry == gid;
+    if users
+        .iter()
+        .any(|u| u.strip_prefix('!').is_some_and(matches))
+    {
+        return false;
+    }
+    users.iter().any(|u| !u.starts_with('!') && matches(u))
+}
+
+/// Whether a path names an SCCS file: its last component starts with `s.`.
+pub fn is_sccs_name(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("s."))
+}
+
+/// The file beside an SCCS file with another prefix, such as `p.` for the
+/// p-file or `z.` for the lock file.
+pub fn sibling(sfile: &Path, prefix: &str) -> PathBuf {
+    let name = sfile.file_name().unwrap_or_default().to_string_lossy();
+    let base = name.strip_prefix("s.").unwrap_or(&name);
+    sfile.with_file_name(format!("{}{}", prefix, base))
+}
+
+/// The g-file of an SCCS file: its name without `s.`, in the current
+/// directory.
+pub fn gfile_name(sfile: &Path) -> PathBuf {
+    let name = sfile.file_name().unwrap_or_default().to_string_lossy();
+    PathBuf::from(name.strip_prefix("s.").unwrap_or(&name))
+}
+
+/// Expands the file operands of an SCCS command: a directory stands for
+/// the SCCS files in it, and `-` for the names read from standard input.
+/// Other files in a directory are skipped silently.
+pub fn expand_operands(operands: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for operand in operands {
+        if operand.as_os_str() == "-" {
+            let mut input = String::new();
+            while matches!(io::stdin().read_line(&mut input), Ok(n) if n > 0) {
+                let name = input.trim_end_matches('\n');
+                if !name.is_empty() {
+                    files.push(PathBuf::from(name));
                 }
-                current_comments.clear();
-                in_delta_section = false;
+                input.clear();
             }
+        } else if operand.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(operand)
+                .map(|dir| {
+                    dir.flatten()
+                        .map(|entry| entry.path())
+                        .filter(|path| is_sccs_name(path) && path.is_file())
+                        .collect()
+                })
+                .unwrap_or_default();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(operand.clone());
         }
     }
+    files
+}
 
-    Ok(deltas)
+/// The lock on an SCCS file, held by creating its `z.` file, for commands
+/// that rewrite it.  The lock is released when dropped.
+pub struct Lock {
+    path: PathBuf,
 }
 
-fn parse_stats(deltas: &[SccsDelta]) -> Result<SccsStats, &'static str> {
-    let total_deltas = deltas.len();
-    let total_lines = deltas.iter().map(|d| d.added_lines + d.deleted_lines).sum();
+impl Lock {
+    pub fn acquire(sfile: &Path) -> Result<Self, String> {
+        let path = sibling(sfile, "z.");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o444)
+            .open(&path)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => format!("file locked: {}", path.display()),
+                _ => format!("{}: {}", path.display(), e),
+            })?;
+        let _ = writeln!(file, "{}", std::process::id());
+        Ok(Lock { path })
+    }
+}
 
-    Ok(SccsStats {
-        total_deltas,
-        total_lines,
-    })
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
 }
 
-fn serialize_sccs_file(sccs_file: &SccsFile) -> String {
-    let mut result = String::new();
-    result.push_str(&sccs_file.header.format_version);
-    result.push('\n');
-    result.push_str(&sccs_file.header.creation_date);
-    result.push('\n');
-    result.push_str(&sccs_file.header.author);
-    result.push('\n');
-    result.push_str(&sccs_file.header.description);
-    result.push('\n');
+/// A line of a p-file, which records a delta being made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PFileEntry {
+    /// The SID retrieved, and the one the new delta will have.
+    pub old: Sid,
+    pub new: Sid,
+    pub user: String,
+    pub date: String,
+    pub time: String,
+    pub include: Option<String>,
+    pub exclude: Option<String>,
+}
 
-    for delta in &sccs_file.deltas {
-        result.push_str(&format!(
-            "d D {} {} {} {} {} {}\n",
-            delta.sid, delta.date, delta.time, delta.author, delta.added_lines, delta.deleted_lines
-        ));
-        if !delta.comments.is_empty() {
-            result.push_str(&format!("c {}\n", delta.comments.trim_end()));
+impl PFileEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let mut entry = PFileEntry {
+            old: parts.next()?.parse().ok()?,
+            new: parts.next()?.parse().ok()?,
+            user: parts.next()?.to_string(),
+            date: parts.next()?.to_string(),
+            time: parts.next()?.to_string(),
+            include: None,
+            exclude: None,
+        };
+        for part in parts {
+            if let Some(list) = part.strip_prefix("-i") {
+                entry.include = Some(list.to_string());
+            } else if let Some(list) = part.strip_prefix("-x") {
+                entry.exclude = Some(list.to_string());
+            }
         }
+        Some(entry)
     }
+}
 
-    result.push_str("edits\n");
-    for edit in &sccs_file.edits {
-        match edit {
-            SccsEdit::Insert(line) => result.push_str(&format!("insert {}\n", line)),
-            SccsEdit::Delete(line_no) => result.push_str(&format!("delete {}\n", line_no)),
+impl fmt::Display for PFileEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.old, self.new, self.user, self.date, self.time
+        )?;
+        if let Some(list) = &self.include {
+            write!(f, " -i{}", list)?;
+        }
+        if let Some(list) = &self.exclude {
+            write!(f, " -x{}", list)?;
         }
+        Ok(())
     }
+}
 
-    result.push_str("users\n");
-    for (user, info) in &sccs_file.user_info.users {
-        result.push_str(&format!("{} {}\n", user, info));
+/// Reads the p-file of an SCCS file; a missing one has no entries.
+pub fn read_pfile(sfile: &Path) -> Result<Vec<PFileEntry>, String> {
+    let path = sibling(sfile, "p.");
+    match fs::read_to_string(&path) {
+        Ok(text) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                PFileEntry::parse(line)
+                    .ok_or_else(|| format!("{}: corrupted p-file", path.display()))
+            })
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
     }
+}
 
-    result.push_str(&format!(
-        "stats total_deltas:{} total_lines:{}\n",
-        sccs_file.stats.total_deltas, sccs_file.stats.total_lines
-    ));
+/// Writes the p-file of an SCCS file, removing it when no entries remain.
+pub fn write_pfile(sfile: &Path, entries: &[PFileEntry]) -> Result<(), String> {
+    let path = sibling(sfile, "p.");
+    let result = if entries.is_empty() {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    } else {
+        let text: String = entries.iter().map(|e| format!("{}\n", e)).collect();
+        fs::write(&path, text)
+    };
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
 
-    result
+/// Returns the command line arguments with the options in `letters`,
+/// whose values are optional and must be attached (such as `-y` and
+/// `-ycomment`), written as `-y=comment` for clap to parse.
+pub fn attach_optional_values(letters: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut operands = false;
+    for (i, arg) in std::env::args().enumerate() {
+        let mut chars = arg.chars();
+        let optional = match (chars.next(), chars.next()) {
+            (Some('-'), Some(letter)) => letters.contains(letter),
+            _ => false,
+        };
+        if i == 0 || operands || !optional || arg.len() == 2 || arg[2..].starts_with('=') {
+            operands |= i > 0 && arg == "--";
+            args.push(arg);
+        } else {
+            args.push(format!("{}={}", &arg[..2], &arg[2..]));
+        }
+    }
+    args
+}
+
+/// Parses a list of SIDs and ranges of them, such as `1.2,1.4-1.6`, to the
+/// serial numbers of the deltas it names.
+pub fn parse_sid_list(file: &SccsFile, list: &str) -> Result<Vec<usize>, String> {
+    let mut serials = Vec::new();
+    for item in list.split(',').filter(|s| !s.is_empty()) {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (first.parse::<Sid>()?, last.parse::<Sid>()?),
+            None => {
+                let sid = item.parse::<Sid>()?;
+                (sid, sid)
+            }
+        };
+        let found: Vec<usize> = file
+            .active_deltas()
+            .filter(|d| d.sid >= first && d.sid <= last)
+            .map(|d| d.serial)
+            .collect();
+        if found.is_empty() {
+            return Err(format!("nonexistent SID: {}", item));
+        }
+        serials.extend(found);
+    }
+    Ok(serials)
 }
 
 #[cfg(test)]
 mod sccstest {
     use super::*;
 
-    #[test]
-    fn basic_sccs_file_parse() {
-        let simple = r#"
-h23005
-s 00003/00000/00013
-d D 1.2 24/07/09 19:42:04 jgarzik 2 1
-c added more data
-e
-s 00013/00000/00000
-d D 1.1 24/07/09 19:38:28 jgarzik 1 0
-c date and time created 24/07/09 19:38:28 by jgarzik
-e
-u
-U
-f e 0
-t
-T
-I 1
+    const SIMPLE: &str = "\x01h23005
+\x01s 00003/00000/00013
+\x01d D 1.2 24/07/09 19:42:04 jgarzik 2 1
+\x01c added more data
+\x01e
+\x01s 00013/00000/00000
+\x01d D 1.1 24/07/09 19:38:28 jgarzik 1 0
+\x01c date and time created 24/07/09 19:38:28 by jgarzik
+\x01e
+\x01u
+\x01U
+\x01f e 0
+\x01t
+\x01T
+\x01I 1
 apple
 banana
 charlie
@@ -270,66 +1405,181 @@ juliet
 kilo
 lima
 mike
-E 1
-I 2
+\x01E 1
+\x01I 2
 november
 october
 pauly
-E 2
-"#;
+\x01E 2
+";
+
+    fn lines(text: &str) -> Vec<Vec<u8>> {
+        split_lines(text.as_bytes())
+    }
+
+    fn text(lines: &[(usize, &[u8])]) -> String {
+        lines
+            .iter()
+            .map(|(_, line)| format!("{}\n", String::from_utf8_lossy(line)))
+            .collect()
+    }
+
+    fn delta(sid: &str, serial: usize, pred: usize) -> SccsDelta {
+        SccsDelta {
+            delta_type: DeltaType::Normal,
+            sid: sid.parse().unwrap(),
+            date: String::from("24/07/10"),
+            time: String::from("10:00:00"),
+            author: String::from("jgarzik"),
+            serial,
+            pred,
+            inserted: 0,
+            deleted: 0,
+            unchanged: 0,
+            included: Vec::new(),
+            excluded: Vec::new(),
+            ignored: Vec::new(),
+            mrs: Vec::new(),
+            comments: Vec::new(),
+        }
+    }
 
-        let sccs_file = SccsFile::from_string(simple).expect("Failed to parse SCCS file");
+    #[test]
+    fn basic_sccs_file_parse() {
+        let sccs_file = SccsFile::from_string(SIMPLE).expect("Failed to parse SCCS file");
 
         // Verify header
-        assert_eq!(sccs_file.header.format_version, "h23005");
-        assert_eq!(sccs_file.header.creation_date, "s 00003/00000/00013");
-        assert_eq!(sccs_file.header.author, "");
-        assert_eq!(sccs_file.header.description, "");
+        assert_eq!(sccs_file.header.checksum, 23005);
 
         // Verify deltas
         assert_eq!(sccs_file.deltas.len(), 2);
 
-        assert_eq!(sccs_file.deltas[0].sid, "1.2");
+        assert_eq!(sccs_file.deltas[0].sid, Sid::trunk(1, 2));
         assert_eq!(sccs_file.deltas[0].date, "24/07/09");
         assert_eq!(sccs_file.deltas[0].time, "19:42:04");
         assert_eq!(sccs_file.deltas[0].author, "jgarzik");
-        assert_eq!(sccs_file.deltas[0].added_lines, 2);
-        assert_eq!(sccs_file.deltas[0].deleted_lines, 1);
-        assert_eq!(sccs_file.deltas[0].comments, "added more data\n");
-
-        assert_eq!(sccs_file.deltas[1].sid, "1.1");
-        assert_eq!(sccs_file.deltas[1].date, "24/07/09");
-        assert_eq!(sccs_file.deltas[1].time, "19:38:28");
-        assert_eq!(sccs_file.deltas[1].author, "jgarzik");
-        assert_eq!(sccs_file.deltas[1].added_lines, 1);
-        assert_eq!(sccs_file.deltas[1].deleted_lines, 0);
+        assert_eq!(sccs_file.deltas[0].serial, 2);
+        assert_eq!(sccs_file.deltas[0].pred, 1);
+        assert_eq!(sccs_file.deltas[0].inserted, 3);
+        assert_eq!(sccs_file.deltas[0].unchanged, 13);
+        assert_eq!(sccs_file.deltas[0].comments, ["added more data"]);
+
+        assert_eq!(sccs_file.deltas[1].sid, Sid::trunk(1, 1));
+        assert_eq!(sccs_file.deltas[1].serial, 1);
+        assert_eq!(sccs_file.deltas[1].pred, 0);
         assert_eq!(
             sccs_file.deltas[1].comments,
-            "date and time created 24/07/09 19:38:28 by jgarzik\n"
+            ["date and time created 24/07/09 19:38:28 by jgarzik"]
         );
 
-        // Verify edits
-        assert_eq!(sccs_file.edits.len(), 2);
+        // Verify users, flags and description
+        assert!(sccs_file.user_info.users.is_empty());
+        assert_eq!(sccs_file.flag('e'), Some("0"));
+        assert!(sccs_file.description.is_empty());
 
-        match &sccs_file.edits[0] {
-            SccsEdit::Insert(line) => {
-                assert_eq!(line, "I 1\napple\nbanana\ncharlie\ndelta\necho\nfoxtrot\ngolf\nhotel\nindia\njuliet\nkilo\nlima\nmike\nE 1\n");
-            }
-            _ => panic!("Unexpected edit type"),
-        }
+        // Verify the versions in the body
+        assert_eq!(sccs_file.body.len(), 20);
+        let v1 = sccs_file.extract(&sccs_file.applied_serials(1, &[], &[]));
+        assert_eq!(v1.len(), 13);
+        let v2 = sccs_file.extract(&sccs_file.applied_serials(2, &[], &[]));
+        assert_eq!(v2.len(), 16);
+        assert_eq!(v2[15], (2, b"pauly".as_slice()));
+    }
 
-        match &sccs_file.edits[1] {
-            SccsEdit::Insert(line) => {
-                assert_eq!(line, "I 2\nnovember\noctober\npauly\nE 2\n");
-            }
-            _ => panic!("Unexpected edit type"),
-        }
+    #[test]
+    fn serialize_round_trip() {
+        let sccs_file = SccsFile::from_string(SIMPLE).unwrap();
+        let data = sccs_file.serialize();
+        assert!(verify_checksum(&data));
+        assert_eq!(data, SIMPLE.as_bytes());
+
+        let corrupted = SIMPLE.replace("pauly", "paula");
+        assert!(!verify_checksum(corrupted.as_bytes()));
+    }
 
-        // Verify user info
-        assert_eq!(sccs_file.user_info.users.len(), 0);
+    #[test]
+    fn sid_parsing() {
+        assert_eq!("1.2".parse::<Sid>(), Ok(Sid::trunk(1, 2)));
+        assert_eq!("1.2.3.4".parse::<Sid>(), Ok(Sid::branch(1, 2, 3, 4)));
+        assert_eq!("3".parse::<Sid>().unwrap().components(), 1);
+        assert!("1.0".parse::<Sid>().is_err());
+        assert!("1..2".parse::<Sid>().is_err());
+        assert!("1.2.3.4.5".parse::<Sid>().is_err());
+        assert_eq!(Sid::branch(1, 2, 1, 1).to_string(), "1.2.1.1");
+    }
 
-        // Verify stats
-        assert_eq!(sccs_file.stats.total_deltas, 2);
-        assert_eq!(sccs_file.stats.total_lines, 4);
+    #[test]
+    fn deltas_and_branches() {
+        let mut file = SccsFile::new(delta("1.1", 1, 0), lines("a\nb\nc\nd\n"));
+        let applied = file.applied_serials(1, &[], &[]);
+        file.add_delta(delta("1.2", 2, 1), &applied, &lines("a\nB\nc\nd\ne\n"));
+        assert_eq!(file.deltas[0].inserted, 2);
+        assert_eq!(file.deltas[0].deleted, 1);
+        assert_eq!(file.deltas[0].unchanged, 3);
+
+        // A branch from 1.1, alongside 1.2.
+        assert_eq!(
+            file.next_sid(&Sid::trunk(1, 1), None, false),
+            Sid::branch(1, 1, 1, 1)
+        );
+        assert_eq!(
+            file.next_sid(&Sid::trunk(1, 2), None, false),
+            Sid::trunk(1, 3)
+        );
+        assert_eq!(
+            file.next_sid(&Sid::trunk(1, 2), Some(2), false),
+            Sid::trunk(2, 1)
+        );
+        let applied = file.applied_serials(1, &[], &[]);
+        file.add_delta(delta("1.1.1.1", 3, 1), &applied, &lines("x\na\nb\nc\n"));
+
+        let version =
+            |file: &SccsFile, serial| text(&file.extract(&file.applied_serials(serial, &[], &[])));
+        assert_eq!(version(&file, 1), "a\nb\nc\nd\n");
+        assert_eq!(version(&file, 2), "a\nB\nc\nd\ne\n");
+        assert_eq!(version(&file, 3), "x\na\nb\nc\n");
+
+        // 1.2 with the branch delta included.
+        let applied = file.applied_serials(2, &[3], &[]);
+        assert_eq!(text(&file.extract(&applied)), "x\na\nB\nc\ne\n");
+        // 1.2 without 1.1 leaves what 1.2 inserted.
+        let applied = file.applied_serials(2, &[], &[1]);
+        assert_eq!(text(&file.extract(&applied)), "B\ne\n");
+
+        let reparsed = SccsFile::from_bytes(&file.serialize()).unwrap();
+        assert_eq!(reparsed.body, file.body);
+
+        file.remove_delta(3);
+        assert_eq!(version(&file, 1), "a\nb\nc\nd\n");
+        assert_eq!(version(&file, 2), "a\nB\nc\nd\ne\n");
+        assert!(file.delta_by_sid(&Sid::branch(1, 1, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn diff_hunks() {
+        let old = lines("a\nb\nc\nd\n");
+        let new = lines("a\nc\nd\ne\n");
+        let hunks = diff(&old, &new);
+        assert_eq!(
+            String::from_utf8(format_diff(&old, &new, &hunks)).unwrap(),
+            "2d1\n< b\n4a4\n> e\n"
+        );
+        let new = lines("a\nx\ny\nd\n");
+        let hunks = diff(&old, &new);
+        assert_eq!(
+            String::from_utf8(format_diff(&old, &new, &hunks)).unwrap(),
+            "2,3c2,3\n< b\n< c\n---\n> x\n> y\n"
+        );
+        assert!(diff(&old, &old).is_empty());
+        assert_eq!(diff(&[], &old).len(), 1);
+    }
+
+    #[test]
+    fn cutoff_dates() {
+        assert_eq!(parse_cutoff("24").unwrap(), "20241231235959");
+        assert_eq!(parse_cutoff("24/07/09").unwrap(), "20240709235959");
+        assert_eq!(parse_cutoff("9907091230").unwrap(), "19990709123059");
+        assert!(parse_cutoff("2").is_err());
+        assert_eq!(timestamp("24/07/09", "19:42:04"), "20240709194204");
     }
 }
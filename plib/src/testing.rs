@@ -7,7 +7,9 @@
 // SPDX-License-Identifier: MIT
 //
 
+use std::fs;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::{Command, Output, Stdio};
 use std::thread;
 use std::time::Duration;
@@ -110,3 +112,12 @@ pub fn run_test_with_checker<F: FnMut(&TestPlan, &Output)>(plan: TestPlan, mut c
     let output = run_test_base(&plan.cmd, &plan.args, plan.stdin_data.as_bytes());
     checker(&plan, &output);
 }
+
+/// Creates an empty scratch directory for tests that write files. `name` must be unique among
+/// the tests of a test binary.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("posixutils-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
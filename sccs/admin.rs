@@ -0,0 +1,317 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{
+    attach_optional_values, expand_operands, is_sccs_name, now, split_lines, user_name,
+    verify_checksum, DeltaType, Lock, SccsDelta, SccsFile, Sid, SOH,
+};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = gettext("admin - create and administer SCCS files"),
+    disable_help_flag = true
+)]
+struct Args {
+    #[arg(long, action = clap::ArgAction::HelpLong)]
+    help: Option<bool>,
+
+    #[arg(short = 'n', help = gettext("Create new, empty SCCS files"))]
+    new: bool,
+
+    #[arg(
+        short = 'i',
+        require_equals = true,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "NAME",
+        help = gettext("Create a new SCCS file holding the text of NAME, or of standard input")
+    )]
+    input: Option<String>,
+
+    #[arg(short = 'r', value_name = "SID", help = gettext("The SID of the initial delta"))]
+    initial: Option<String>,
+
+    #[arg(
+        short = 't',
+        require_equals = true,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "NAME",
+        help = gettext("Take the descriptive text from NAME; remove it when NAME is omitted")
+    )]
+    text: Option<String>,
+
+    #[arg(short = 'f', value_name = "FLAG", help = gettext("Set a flag, with its value"))]
+    set_flags: Vec<String>,
+
+    #[arg(short = 'd', value_name = "FLAG", help = gettext("Delete a flag"))]
+    delete_flags: Vec<String>,
+
+    #[arg(
+        short = 'a',
+        value_name = "LOGIN",
+        help = gettext("Allow a login name or group ID to make deltas")
+    )]
+    add_users: Vec<String>,
+
+    #[arg(
+        short = 'e',
+        value_name = "LOGIN",
+        help = gettext("Remove a login name or group ID from the users allowed")
+    )]
+    erase_users: Vec<String>,
+
+    #[arg(
+        short = 'y',
+        require_equals = true,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "COMMENT",
+        help = gettext("The comment of the initial delta")
+    )]
+    comment: Option<String>,
+
+    #[arg(short = 'm', value_name = "MRLIST", help = gettext("The MR numbers of the initial delta"))]
+    mrs: Option<String>,
+
+    #[arg(short = 'h', help = gettext("Check the structure and checksum of SCCS files"))]
+    check: bool,
+
+    #[arg(short = 'z', help = gettext("Recompute the checksum of SCCS files"))]
+    recompute: bool,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+/// The flags admin knows, and whether they need a value.
+const FLAGS: &[(char, bool)] = &[
+    ('b', false),
+    ('c', true),
+    ('d', true),
+    ('f', true),
+    ('i', false),
+    ('j', false),
+    ('l', true),
+    ('m', true),
+    ('n', false),
+    ('q', false),
+    ('t', true),
+    ('v', false),
+];
+
+fn parse_flag(flag: &str) -> Result<(char, String), String> {
+    let mut chars = flag.chars();
+    let letter = chars.next().unwrap_or(' ');
+    let value = chars.as_str().to_string();
+    let Some(&(_, needs_value)) = FLAGS.iter().find(|(l, _)| *l == letter) else {
+        return Err(format!("{}: {}", gettext("unknown flag"), letter));
+    };
+    if needs_value && value.is_empty() {
+        return Err(format!("{}: {}", gettext("flag requires a value"), letter));
+    }
+    let valid = match letter {
+        'c' | 'f' => value
+            .parse::<u32>()
+            .is_ok_and(|rel| (1..=9999).contains(&rel)),
+        'd' => value.parse::<Sid>().is_ok(),
+        _ => true,
+    };
+    if !valid {
+        return Err(format!("{}: {}", gettext("invalid flag value"), flag));
+    }
+    Ok((letter, value))
+}
+
+/// Reads the lines of a file, or of standard input when `name` is empty.
+fn read_text(name: &str) -> Result<Vec<Vec<u8>>, String> {
+    let data = if name.is_empty() {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| e.to_string())?;
+        data
+    } else {
+        fs::read(name).map_err(|e| format!("{}: {}", name, e))?
+    };
+    Ok(split_lines(&data))
+}
+
+fn apply_changes(file: &mut SccsFile, args: &Args) -> Result<(), String> {
+    for flag in &args.set_flags {
+        let (letter, value) = parse_flag(flag)?;
+        file.flags.insert(letter, value);
+    }
+    for flag in &args.delete_flags {
+        let letter = flag.chars().next().unwrap_or(' ');
+        if !FLAGS.iter().any(|(l, _)| *l == letter) {
+            return Err(format!("{}: {}", gettext("unknown flag"), letter));
+        }
+        file.flags.remove(&letter);
+    }
+    for user in &args.add_users {
+        if !file.user_info.users.contains(user) {
+            file.user_info.users.push(user.clone());
+        }
+    }
+    file.user_info
+        .users
+        .retain(|user| !args.erase_users.contains(user));
+    if let Some(text) = &args.text {
+        file.description = if text.is_empty() {
+            Vec::new()
+        } else {
+            read_text(text)?
+                .into_iter()
+                .map(|line| String::from_utf8_lossy(&line).into_owned())
+                .collect()
+        };
+    }
+    Ok(())
+}
+
+fn create(path: &Path, args: &Args) -> Result<(), String> {
+    if !is_sccs_name(path) {
+        return Err(gettext("not an SCCS file"));
+    }
+    if path.exists() {
+        return Err(gettext("file exists"));
+    }
+    let sid = match &args.initial {
+        Some(sid) => {
+            let sid: Sid = sid.parse()?;
+            match sid.components() {
+                1 => Sid::trunk(sid.rel, 1),
+                2 => sid,
+                _ => return Err(format!("{}: {}", gettext("invalid SID"), sid)),
+            }
+        }
+        None => Sid::trunk(1, 1),
+    };
+    let lines = match &args.input {
+        Some(input) => read_text(input)?,
+        None => Vec::new(),
+    };
+    if let Some(n) = lines.iter().position(|line| line.first() == Some(&SOH)) {
+        return Err(format!("{} {}", gettext("illegal data on line"), n + 1));
+    }
+
+    let (date, time) = now();
+    let author = user_name();
+    let comments = match &args.comment {
+        Some(comment) if comment.is_empty() => Vec::new(),
+        Some(comment) => comment.lines().map(String::from).collect(),
+        None => vec![format!(
+            "date and time created {} {} by {}",
+            date, time, author
+        )],
+    };
+    let mrs: Vec<String> = args
+        .mrs
+        .iter()
+        .flat_map(|list| list.split([' ', ',', '\t']))
+        .filter(|mr| !mr.is_empty())
+        .map(String::from)
+        .collect();
+    let delta = SccsDelta {
+        delta_type: DeltaType::Normal,
+        sid,
+        date,
+        time,
+        author,
+        serial: 1,
+        pred: 0,
+        inserted: 0,
+        deleted: 0,
+        unchanged: 0,
+        included: Vec::new(),
+        excluded: Vec::new(),
+        ignored: Vec::new(),
+        mrs,
+        comments,
+    };
+    let mut file = SccsFile::new(delta, lines);
+    apply_changes(&mut file, args)?;
+    if !file.deltas[0].mrs.is_empty() && file.flag('v').is_none() {
+        return Err(gettext("MRs not allowed"));
+    }
+    file.write(path).map_err(|e| e.to_string())
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    SccsFile::from_bytes(&data)?;
+    if !verify_checksum(&data) {
+        return Err(gettext("corrupted file"));
+    }
+    Ok(())
+}
+
+fn update(path: &Path, args: &Args) -> Result<(), String> {
+    let _lock = Lock::acquire(path)?;
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let mut file = SccsFile::from_bytes(&data)?;
+    if !args.recompute {
+        if !verify_checksum(&data) {
+            return Err(gettext("corrupted file"));
+        }
+        apply_changes(&mut file, args)?;
+    }
+    file.write(path).map_err(|e| e.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse_from(attach_optional_values("ity"));
+
+    let creating = args.new || args.input.is_some();
+    if !creating && (args.initial.is_some() || args.comment.is_some() || args.mrs.is_some()) {
+        eprintln!(
+            "admin: {}",
+            gettext("-r, -y and -m are only allowed with -i or -n")
+        );
+        std::process::exit(1);
+    }
+    if args.input.is_some() && args.files.len() > 1 {
+        eprintln!("admin: {}", gettext("-i allows only one file"));
+        std::process::exit(1);
+    }
+
+    let files = if creating {
+        args.files.clone()
+    } else {
+        expand_operands(&args.files)
+    };
+    let mut exit_code = 0;
+    for path in &files {
+        let result = if creating {
+            create(path, &args)
+        } else if args.check {
+            check(path)
+        } else {
+            update(path, &args)
+        };
+        if let Err(message) = result {
+            eprintln!("admin: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,235 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{
+    attach_optional_values, expand_operands, format_diff, gfile_name, now, parse_sid_list,
+    read_pfile, split_lines, user_name, write_pfile, DeltaType, Lock, SccsDelta, SccsFile, Sid,
+    SOH,
+};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("delta - make a delta (change) to an SCCS file"))]
+struct Args {
+    #[arg(
+        short = 'r',
+        value_name = "SID",
+        help = gettext("The SID of the version being edited, when there are several")
+    )]
+    sid: Option<String>,
+
+    #[arg(short = 's', help = gettext("Suppress the informative output"))]
+    silent: bool,
+
+    #[arg(short = 'n', help = gettext("Keep the edited file"))]
+    keep: bool,
+
+    #[arg(short = 'g', value_name = "LIST", help = gettext("Ignore the deltas listed"))]
+    ignore: Option<String>,
+
+    #[arg(short = 'm', value_name = "MRLIST", help = gettext("The MR numbers of the delta"))]
+    mrs: Option<String>,
+
+    #[arg(
+        short = 'y',
+        require_equals = true,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "COMMENT",
+        help = gettext("The comment of the delta")
+    )]
+    comment: Option<String>,
+
+    #[arg(short = 'p', help = gettext("Write the differences made, as diff does"))]
+    print_diff: bool,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+/// Reads lines from standard input, up to the end of input or an empty
+/// line, after a prompt when the input is a terminal.
+fn read_lines(prompt: &str) -> Vec<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+    }
+    let mut lines = Vec::new();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The comments and MRs of the deltas, asked for once for all files.
+struct Commentary {
+    comments: Option<Vec<String>>,
+    mrs: Option<Vec<String>>,
+}
+
+impl Commentary {
+    fn comments(&mut self) -> Vec<String> {
+        self.comments
+            .get_or_insert_with(|| read_lines(&gettext("comments? ")))
+            .clone()
+    }
+
+    fn mrs(&mut self) -> Vec<String> {
+        self.mrs
+            .get_or_insert_with(|| {
+                read_lines(&gettext("MRs? "))
+                    .iter()
+                    .flat_map(|line| line.split([' ', ',', '\t']))
+                    .filter(|mr| !mr.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .clone()
+    }
+}
+
+fn delta(path: &Path, args: &Args, commentary: &mut Commentary) -> Result<(), String> {
+    let _lock = Lock::acquire(path)?;
+    let mut file = SccsFile::read(path)?;
+    let mut entries = read_pfile(path)?;
+
+    let user = user_name();
+    let requested: Option<Sid> = args.sid.as_deref().map(str::parse).transpose()?;
+    let mine: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.user == user && requested.map_or(true, |r| e.old == r || e.new == r))
+        .map(|(i, _)| i)
+        .collect();
+    let index = match mine[..] {
+        [] => return Err(gettext("not being edited by you")),
+        [index] => index,
+        _ => return Err(gettext("more than one edit: use -r to say which")),
+    };
+    let entry = entries[index].clone();
+
+    let gfile = gfile_name(path);
+    let data = fs::read(&gfile).map_err(|e| format!("{}: {}", gfile.display(), e))?;
+    let new_lines = split_lines(&data);
+    if let Some(n) = new_lines.iter().position(|line| line.first() == Some(&SOH)) {
+        return Err(format!("{} {}", gettext("illegal data on line"), n + 1));
+    }
+
+    let base = file
+        .delta_by_sid(&entry.old)
+        .ok_or_else(|| format!("{}: {}", gettext("nonexistent SID"), entry.old))?;
+    let list = |list: &Option<String>| match list {
+        Some(list) => parse_sid_list(&file, list),
+        None => Ok(Vec::new()),
+    };
+    let included = list(&entry.include)?;
+    let excluded = list(&entry.exclude)?;
+    let ignored = list(&args.ignore)?;
+    let mut exclude = excluded.clone();
+    exclude.extend(&ignored);
+    let applied = file.applied_serials(base.serial, &included, &exclude);
+    let pred = base.serial;
+
+    let mrs = match (&args.mrs, file.flag('v').is_some()) {
+        (Some(_), false) => return Err(gettext("MRs not allowed")),
+        (Some(list), true) => list
+            .split([' ', ',', '\t'])
+            .filter(|mr| !mr.is_empty())
+            .map(String::from)
+            .collect(),
+        (None, true) => commentary.mrs(),
+        (None, false) => Vec::new(),
+    };
+    if file.flag('v').is_some() && mrs.is_empty() {
+        return Err(gettext("MRs required"));
+    }
+    let comments = match &args.comment {
+        Some(comment) => comment.lines().map(String::from).collect(),
+        None => commentary.comments(),
+    };
+
+    let (date, time) = now();
+    let new = SccsDelta {
+        delta_type: DeltaType::Normal,
+        sid: entry.new,
+        date,
+        time,
+        author: user,
+        serial: file.max_serial() + 1,
+        pred,
+        inserted: 0,
+        deleted: 0,
+        unchanged: 0,
+        included,
+        excluded,
+        ignored,
+        mrs,
+        comments,
+    };
+    let old_lines: Vec<Vec<u8>> = file
+        .extract(&applied)
+        .into_iter()
+        .map(|(_, text)| text.to_vec())
+        .collect();
+    let hunks = file.add_delta(new, &applied, &new_lines);
+    file.write(path).map_err(|e| e.to_string())?;
+
+    entries.remove(index);
+    write_pfile(path, &entries)?;
+    if !args.keep {
+        fs::remove_file(&gfile).map_err(|e| format!("{}: {}", gfile.display(), e))?;
+    }
+
+    if !args.silent {
+        let made = &file.deltas[0];
+        let mut out = io::stdout().lock();
+        let _ = writeln!(out, "{}", made.sid);
+        if args.print_diff {
+            let _ = out.write_all(&format_diff(&old_lines, &new_lines, &hunks));
+        }
+        let _ = writeln!(out, "{} {}", made.inserted, gettext("inserted"));
+        let _ = writeln!(out, "{} {}", made.deleted, gettext("deleted"));
+        let _ = writeln!(out, "{} {}", made.unchanged, gettext("unchanged"));
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse_from(attach_optional_values("y"));
+
+    let mut commentary = Commentary {
+        comments: None,
+        mrs: None,
+    };
+    let mut exit_code = 0;
+    for path in expand_operands(&args.files) {
+        if let Err(message) = delta(&path, &args, &mut commentary) {
+            eprintln!("delta: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
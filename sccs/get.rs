@@ -0,0 +1,428 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{
+    expand_operands, gfile_name, now, parse_cutoff, parse_sid_list, read_pfile, user_allowed,
+    user_name, write_pfile, Lock, PFileEntry, SccsDelta, SccsFile, Sid,
+};
+use plib::PROJECT_NAME;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("get - get a version of an SCCS file"))]
+struct Args {
+    #[arg(short = 'r', value_name = "SID", help = gettext("The SID of the version to retrieve"))]
+    sid: Option<String>,
+
+    #[arg(
+        short = 'c',
+        value_name = "CUTOFF",
+        help = gettext("Leave out deltas made after the cutoff date-time")
+    )]
+    cutoff: Option<String>,
+
+    #[arg(short = 'e', help = gettext("Retrieve the version for editing, to make a delta"))]
+    edit: bool,
+
+    #[arg(short = 'b', help = gettext("Make the new delta on a branch (with -e)"))]
+    branch: bool,
+
+    #[arg(short = 'i', value_name = "LIST", help = gettext("Include the deltas listed"))]
+    include: Option<String>,
+
+    #[arg(short = 'x', value_name = "LIST", help = gettext("Exclude the deltas listed"))]
+    exclude: Option<String>,
+
+    #[arg(short = 'k', help = gettext("Do not expand identification keywords"))]
+    no_keywords: bool,
+
+    #[arg(short = 'l', help = gettext("Write a delta summary to an l-file"))]
+    summary: bool,
+
+    #[arg(short = 'p', help = gettext("Write the text to standard output"))]
+    stdout: bool,
+
+    #[arg(short = 's', help = gettext("Suppress the informative output"))]
+    silent: bool,
+
+    #[arg(short = 'm', help = gettext("Precede each line with the SID of the delta that inserted it"))]
+    sid_prefix: bool,
+
+    #[arg(short = 'n', help = gettext("Precede each line with the %M% keyword value"))]
+    module_prefix: bool,
+
+    #[arg(short = 'g', help = gettext("Do not retrieve the text"))]
+    no_text: bool,
+
+    #[arg(short = 't', help = gettext("Retrieve the newest delta of the release"))]
+    top: bool,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+/// The values identification keywords expand to.
+struct Keywords<'a> {
+    file: &'a SccsFile,
+    delta: &'a SccsDelta,
+    sfile: &'a Path,
+    module: String,
+    date: String,
+    time: String,
+}
+
+impl Keywords<'_> {
+    fn value(&self, letter: u8, line: usize) -> Option<String> {
+        let sid = self.delta.sid;
+        let american = |date: &str| {
+            let parts: Vec<&str> = date.split('/').collect();
+            format!("{}/{}/{}", parts[1], parts[2], parts[0])
+        };
+        Some(match letter {
+            b'M' => self.module.clone(),
+            b'I' => sid.to_string(),
+            b'R' => sid.rel.to_string(),
+            b'L' => sid.lev.to_string(),
+            b'B' => sid.br.to_string(),
+            b'S' => sid.seq.to_string(),
+            b'D' => self.date.clone(),
+            b'H' => american(&self.date),
+            b'T' => self.time.clone(),
+            b'E' => self.delta.date.clone(),
+            b'G' => american(&self.delta.date),
+            b'U' => self.delta.time.clone(),
+            b'Y' => self.file.flag('t').unwrap_or("").to_string(),
+            b'F' => self.sfile.display().to_string(),
+            b'P' => fs::canonicalize(self.sfile)
+                .unwrap_or_else(|_| self.sfile.to_path_buf())
+                .display()
+                .to_string(),
+            b'Q' => self.file.flag('q').unwrap_or("").to_string(),
+            b'C' => line.to_string(),
+            b'Z' => String::from("@(#)"),
+            b'W' => format!("@(#){}\t{}", self.module, sid),
+            b'A' => format!(
+                "@(#){} {} {}@(#)",
+                self.file.flag('t').unwrap_or(""),
+                self.module,
+                sid
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Expands the keywords in a line, returning whether there were any.
+    fn expand(&self, text: &[u8], line: usize, out: &mut Vec<u8>) -> bool {
+        let mut found = false;
+        let mut i = 0;
+        while i < text.len() {
+            if text[i] == b'%' && i + 2 < text.len() && text[i + 2] == b'%' {
+                if let Some(value) = self.value(text[i + 1], line) {
+                    out.extend_from_slice(value.as_bytes());
+                    found = true;
+                    i += 3;
+                    continue;
+                }
+            }
+            out.push(text[i]);
+            i += 1;
+        }
+        found
+    }
+}
+
+/// The delta summary of an l-file: a line for each delta, marked with `*`
+/// when not applied and the reason it was left out, then its MRs and
+/// comments.
+fn summary(
+    file: &SccsFile,
+    applied: &HashSet<usize>,
+    include: &[usize],
+    exclude: &[usize],
+    cut: &HashSet<usize>,
+) -> String {
+    let mut out = String::new();
+    for delta in file.active_deltas() {
+        let is_applied = applied.contains(&delta.serial);
+        let ignored = file
+            .active_deltas()
+            .any(|d| applied.contains(&d.serial) && d.ignored.contains(&delta.serial));
+        let code = if include.contains(&delta.serial) {
+            'I'
+        } else if exclude.contains(&delta.serial) {
+            'X'
+        } else if cut.contains(&delta.serial) {
+            'C'
+        } else {
+            ' '
+        };
+        out.push_str(&format!(
+            "{}{}{} {}\t{} {} {}\n",
+            if is_applied { ' ' } else { '*' },
+            if is_applied || ignored { ' ' } else { '*' },
+            code,
+            delta.sid,
+            delta.date,
+            delta.time,
+            delta.author
+        ));
+        for mr in &delta.mrs {
+            out.push_str(&format!("\t{}\n", mr));
+        }
+        for comment in &delta.comments {
+            out.push_str(&format!("\t{}\n", comment));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Checks that the flags and user list of a file allow a delta to the
+/// release of `sid`.
+fn check_edit(file: &SccsFile, sid: &Sid) -> Result<(), String> {
+    let user = user_name();
+    if !user_allowed(&file.user_info.users, &user) {
+        return Err(format!(
+            "{}: {}",
+            gettext("not authorized to make deltas"),
+            user
+        ));
+    }
+    if let Some(locked) = file.flag('l') {
+        let rel = sid.rel.to_string();
+        if locked == "a" || locked.split([',', ' ']).any(|r| r == rel) {
+            return Err(format!("{}: {}", gettext("release is locked"), sid.rel));
+        }
+    }
+    let release_flag = |letter| file.flag(letter).and_then(|v| v.parse::<u32>().ok());
+    if release_flag('f').is_some_and(|floor| sid.rel < floor) {
+        return Err(format!("{}: {}", gettext("release below floor"), sid.rel));
+    }
+    if release_flag('c').is_some_and(|ceiling| sid.rel > ceiling) {
+        return Err(format!("{}: {}", gettext("release above ceiling"), sid.rel));
+    }
+    Ok(())
+}
+
+fn get(path: &Path, args: &Args) -> Result<(), String> {
+    let file = SccsFile::read(path)?;
+    let requested: Option<Sid> = args.sid.as_deref().map(str::parse).transpose()?;
+
+    // Deltas made after the cutoff take no part in the version.
+    let cutoff = args.cutoff.as_deref().map(parse_cutoff).transpose()?;
+    let cut: HashSet<usize> = match &cutoff {
+        Some(cutoff) => file
+            .active_deltas()
+            .filter(|d| d.timestamp() > *cutoff)
+            .map(|d| d.serial)
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let delta = if args.top {
+        let rel = match requested {
+            Some(sid) => sid.rel,
+            None => file.active_deltas().map(|d| d.sid.rel).max().unwrap_or(0),
+        };
+        file.active_deltas()
+            .filter(|d| d.sid.rel == rel && !cut.contains(&d.serial))
+            .max_by_key(|d| d.serial)
+            .ok_or_else(|| format!("{}: {}", gettext("nonexistent SID"), rel))?
+    } else if requested.is_none() && cutoff.is_some() {
+        file.active_deltas()
+            .filter(|d| !d.sid.is_branch() && !cut.contains(&d.serial))
+            .max_by_key(|d| d.sid)
+            .ok_or_else(|| gettext("no deltas before the cutoff"))?
+    } else {
+        file.find_sid(requested.as_ref())?
+    };
+
+    let include = match &args.include {
+        Some(list) => parse_sid_list(&file, list)?,
+        None => Vec::new(),
+    };
+    let mut exclude = match &args.exclude {
+        Some(list) => parse_sid_list(&file, list)?,
+        None => Vec::new(),
+    };
+    exclude.extend(&cut);
+    let applied = file.applied_serials(delta.serial, &include, &exclude);
+    let lines = file.extract(&applied);
+
+    let gfile = gfile_name(path);
+    if !args.stdout && !args.no_text && is_writable(&gfile) {
+        return Err(format!(
+            "{} `{}' {}",
+            gettext("writable"),
+            gfile.display(),
+            gettext("exists")
+        ));
+    }
+    let mut new_sid = None;
+    if args.edit {
+        let release = requested.map(|sid| sid.rel);
+        let sid = file.next_sid(&delta.sid, release, args.branch && file.flag('b').is_some());
+        check_edit(&file, &sid)?;
+
+        let _lock = Lock::acquire(path)?;
+        let mut entries = read_pfile(path)?;
+        if let Some(entry) = entries.iter().find(|e| e.old == delta.sid || e.new == sid) {
+            if file.flag('j').is_none() || entry.new == sid {
+                return Err(format!("{}: {}", gettext("being edited"), entry));
+            }
+        }
+        let (date, time) = now();
+        entries.push(PFileEntry {
+            old: delta.sid,
+            new: sid,
+            user: user_name(),
+            date,
+            time,
+            include: args.include.clone(),
+            exclude: args.exclude.clone(),
+        });
+        write_pfile(path, &entries)?;
+        new_sid = Some(sid);
+    }
+
+    let mut messages = format!("{}\n", delta.sid);
+    let sids = |serials: &[usize]| -> String {
+        serials
+            .iter()
+            .filter_map(|&s| file.delta_by_serial(s))
+            .map(|d| format!("{}\n", d.sid))
+            .collect()
+    };
+    if !include.is_empty() {
+        messages.push_str(&format!("{}:\n{}", gettext("Included"), sids(&include)));
+    }
+    if !exclude.is_empty() {
+        messages.push_str(&format!("{}:\n{}", gettext("Excluded"), sids(&exclude)));
+    }
+    if let Some(sid) = new_sid {
+        messages.push_str(&format!("{} {}\n", gettext("new delta"), sid));
+    }
+
+    if !args.no_text {
+        let (date, time) = now();
+        let module = match file.flag('m') {
+            Some(module) => module.to_string(),
+            None => gfile.display().to_string(),
+        };
+        let keywords = Keywords {
+            file: &file,
+            delta,
+            sfile: path,
+            module: module.clone(),
+            date,
+            time,
+        };
+        let expand = !args.edit && !args.no_keywords;
+        let mut text = Vec::new();
+        let mut found = false;
+        for (n, (serial, line)) in lines.iter().enumerate() {
+            if args.module_prefix {
+                text.extend_from_slice(module.as_bytes());
+                text.push(b'\t');
+            }
+            if args.sid_prefix {
+                let sid = file
+                    .delta_by_serial(*serial)
+                    .map(|d| d.sid)
+                    .unwrap_or_default();
+                text.extend_from_slice(format!("{}\t", sid).as_bytes());
+            }
+            if expand {
+                found |= keywords.expand(line, n + 1, &mut text);
+            } else {
+                text.extend_from_slice(line);
+            }
+            text.push(b'\n');
+        }
+        if expand && !found {
+            if file.flag('i').is_some() {
+                return Err(gettext("No id keywords"));
+            }
+            eprintln!(
+                "get: {}: {}",
+                path.display(),
+                gettext("warning: No id keywords")
+            );
+        }
+
+        if args.stdout {
+            io::stdout().write_all(&text).map_err(|e| e.to_string())?;
+        } else {
+            let _ = fs::remove_file(&gfile);
+            let mode = if args.edit { 0o644 } else { 0o444 };
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(mode)
+                .open(&gfile)
+                .and_then(|mut f| f.write_all(&text))
+                .map_err(|e| format!("{}: {}", gfile.display(), e))?;
+        }
+    }
+    messages.push_str(&format!("{} {}\n", lines.len(), gettext("lines")));
+
+    if args.summary {
+        let lfile = PathBuf::from(format!(
+            "l.{}",
+            gfile.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        fs::write(&lfile, summary(&file, &applied, &include, &exclude, &cut))
+            .map_err(|e| format!("{}: {}", lfile.display(), e))?;
+    }
+
+    if !args.silent {
+        if args.stdout {
+            eprint!("{}", messages);
+        } else {
+            print!("{}", messages);
+        }
+    }
+    Ok(())
+}
+
+fn is_writable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o222 != 0)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let files = expand_operands(&args.files);
+    let mut exit_code = 0;
+    for path in &files {
+        if files.len() > 1 && !args.silent {
+            if args.stdout {
+                eprintln!("\n{}:", path.display());
+            } else {
+                println!("\n{}:", path.display());
+            }
+        }
+        if let Err(message) = get(path, &args) {
+            eprintln!("get: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,382 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{
+    attach_optional_values, expand_operands, gfile_name, parse_cutoff, BodyLine, DeltaType,
+    SccsDelta, SccsFile, Sid, SOH,
+};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("prs - print an SCCS file"))]
+struct Args {
+    #[arg(
+        short = 'd',
+        value_name = "DATASPEC",
+        help = gettext("The format of the output, with data keywords")
+    )]
+    dataspec: Option<String>,
+
+    #[arg(
+        short = 'r',
+        require_equals = true,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "SID",
+        help = gettext("The delta to print, the newest when SID is omitted")
+    )]
+    sid: Option<String>,
+
+    #[arg(
+        short = 'e',
+        conflicts_with = "later",
+        help = gettext("Print the deltas made before the one selected, and it")
+    )]
+    earlier: bool,
+
+    #[arg(short = 'l', help = gettext("Print the deltas made after the one selected, and it"))]
+    later: bool,
+
+    #[arg(
+        short = 'c',
+        value_name = "CUTOFF",
+        help = gettext("Select deltas by the cutoff date-time, with -e or -l")
+    )]
+    cutoff: Option<String>,
+
+    #[arg(short = 'a', help = gettext("Print removed deltas too"))]
+    all: bool,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+const DEFAULT_DATASPEC: &str = ":Dt:\t:DL:\nMRs:\n:MR:COMMENTS:\n:C:";
+
+/// Descriptions of the flags, for `:FL:`.
+const FLAG_NAMES: &[(char, &str)] = &[
+    ('b', "branch"),
+    ('c', "ceiling"),
+    ('d', "default SID"),
+    ('f', "floor"),
+    ('i', "id keywd err/warn"),
+    ('j', "joint edit"),
+    ('l', "locked releases"),
+    ('m', "module"),
+    ('n', "null delta"),
+    ('q', "csect name"),
+    ('t', "type"),
+    ('v', "validate MRs"),
+];
+
+fn yes_no(set: bool) -> String {
+    String::from(if set { "yes" } else { "no" })
+}
+
+/// Lines of text, each ending in a newline.
+fn lines<S: AsRef<str>>(lines: &[S]) -> String {
+    lines.iter().map(|l| format!("{}\n", l.as_ref())).collect()
+}
+
+struct Printer<'a> {
+    file: &'a SccsFile,
+    path: &'a Path,
+}
+
+impl Printer<'_> {
+    fn sids(&self, serials: &[usize]) -> String {
+        serials
+            .iter()
+            .filter_map(|&s| self.file.delta_by_serial(s))
+            .map(|d| d.sid.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn module(&self) -> String {
+        match self.file.flag('m') {
+            Some(module) => module.to_string(),
+            None => gfile_name(self.path).display().to_string(),
+        }
+    }
+
+    fn flag(&self, letter: char) -> String {
+        self.file.flag(letter).unwrap_or("").to_string()
+    }
+
+    /// The value of a data keyword for a delta.
+    fn value(&self, keyword: &str, delta: &SccsDelta) -> Option<String> {
+        let sid = delta.sid;
+        let date: Vec<&str> = delta.date.split('/').collect();
+        let time: Vec<&str> = delta.time.split(':').collect();
+        let part = |parts: &[&str], i: usize| parts.get(i).copied().unwrap_or("").to_string();
+        Some(match keyword {
+            "Dt" => format!(
+                "{} {} {} {} {} {} {}",
+                self.value("DT", delta)?,
+                sid,
+                delta.date,
+                delta.time,
+                delta.author,
+                delta.serial,
+                delta.pred
+            ),
+            "DL" => format!(
+                "{:05}/{:05}/{:05}",
+                delta.inserted, delta.deleted, delta.unchanged
+            ),
+            "Li" => format!("{:05}", delta.inserted),
+            "Ld" => format!("{:05}", delta.deleted),
+            "Lu" => format!("{:05}", delta.unchanged),
+            "DT" => String::from(match delta.delta_type {
+                DeltaType::Normal => "D",
+                DeltaType::Removed => "R",
+            }),
+            "I" => sid.to_string(),
+            "R" => sid.rel.to_string(),
+            "L" => sid.lev.to_string(),
+            "B" => sid.br.to_string(),
+            "S" => sid.seq.to_string(),
+            "D" => delta.date.clone(),
+            "Dy" => part(&date, 0),
+            "Dm" => part(&date, 1),
+            "Dd" => part(&date, 2),
+            "T" => delta.time.clone(),
+            "Th" => part(&time, 0),
+            "Tm" => part(&time, 1),
+            "Ts" => part(&time, 2),
+            "P" => delta.author.clone(),
+            "DS" => delta.serial.to_string(),
+            "DP" => delta.pred.to_string(),
+            "DI" => format!(
+                "{}/{}/{}",
+                self.sids(&delta.included),
+                self.sids(&delta.excluded),
+                self.sids(&delta.ignored)
+            ),
+            "Dn" => self.sids(&delta.included),
+            "Dx" => self.sids(&delta.excluded),
+            "Dg" => self.sids(&delta.ignored),
+            "MR" => lines(&delta.mrs),
+            "C" => lines(&delta.comments),
+            "UN" => {
+                if self.file.user_info.users.is_empty() {
+                    String::from("none\n")
+                } else {
+                    lines(&self.file.user_info.users)
+                }
+            }
+            "FL" => {
+                let mut out = String::new();
+                for (letter, value) in &self.file.flags {
+                    let name = FLAG_NAMES
+                        .iter()
+                        .find(|(l, _)| l == letter)
+                        .map_or("", |(_, name)| name);
+                    if value.is_empty() {
+                        out.push_str(&format!("\t{}\n", name));
+                    } else {
+                        out.push_str(&format!("\t{}\t{}\n", name, value));
+                    }
+                }
+                out
+            }
+            "Y" => self.flag('t'),
+            "MF" => yes_no(self.file.flag('v').is_some()),
+            "MP" => self.flag('v'),
+            "KF" => yes_no(self.file.flag('i').is_some()),
+            "KV" => self.flag('i'),
+            "BF" => yes_no(self.file.flag('b').is_some()),
+            "J" => yes_no(self.file.flag('j').is_some()),
+            "LK" => match self.file.flag('l') {
+                Some(locked) => locked.to_string(),
+                None => String::from("none"),
+            },
+            "Q" => self.flag('q'),
+            "M" => self.module(),
+            "FB" => match self.file.flag('f') {
+                Some(floor) => floor.to_string(),
+                None => String::from("none"),
+            },
+            "CB" => match self.file.flag('c') {
+                Some(ceiling) => ceiling.to_string(),
+                None => String::from("none"),
+            },
+            "Ds" => match self.file.flag('d') {
+                Some(sid) => sid.to_string(),
+                None => String::from("none"),
+            },
+            "ND" => yes_no(self.file.flag('n').is_some()),
+            "FD" => lines(&self.file.description),
+            "BD" => {
+                let mut out = Vec::new();
+                for line in &self.file.body {
+                    match line {
+                        BodyLine::Insert(s) => {
+                            out.extend(format!("{}I {}", SOH as char, s).bytes())
+                        }
+                        BodyLine::Delete(s) => {
+                            out.extend(format!("{}D {}", SOH as char, s).bytes())
+                        }
+                        BodyLine::End(s) => out.extend(format!("{}E {}", SOH as char, s).bytes()),
+                        BodyLine::Text(text) => out.extend_from_slice(text),
+                    }
+                    out.push(b'\n');
+                }
+                String::from_utf8_lossy(&out).into_owned()
+            }
+            "GB" => {
+                let applied = self.file.applied_serials(delta.serial, &[], &[]);
+                let mut out = Vec::new();
+                for (_, text) in self.file.extract(&applied) {
+                    out.extend_from_slice(text);
+                    out.push(b'\n');
+                }
+                String::from_utf8_lossy(&out).into_owned()
+            }
+            "W" => format!("@(#){}\t{}", self.module(), sid),
+            "A" => format!("@(#){} {} {}@(#)", self.flag('t'), self.module(), sid),
+            "Z" => String::from("@(#)"),
+            "F" => self
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            "PN" => fs::canonicalize(self.path)
+                .unwrap_or_else(|_| self.path.to_path_buf())
+                .display()
+                .to_string(),
+            _ => return None,
+        })
+    }
+
+    /// Expands a dataspec for a delta: data keywords between colons, and
+    /// `\t` and `\n` for tab and newline.
+    fn expand(&self, dataspec: &str, delta: &SccsDelta) -> String {
+        let mut out = String::new();
+        let mut rest = dataspec;
+        while let Some(c) = rest.chars().next() {
+            if c == ':' {
+                if let Some(end) = rest[1..].find(':') {
+                    if let Some(value) = self.value(&rest[1..end + 1], delta) {
+                        out.push_str(&value);
+                        rest = &rest[end + 2..];
+                        continue;
+                    }
+                }
+            } else if c == '\\' {
+                match rest[1..].chars().next() {
+                    Some('t') => {
+                        out.push('\t');
+                        rest = &rest[2..];
+                        continue;
+                    }
+                    Some('n') => {
+                        out.push('\n');
+                        rest = &rest[2..];
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        out
+    }
+}
+
+fn prs(path: &Path, args: &Args) -> Result<(), String> {
+    let file = SccsFile::read(path)?;
+    let printer = Printer { file: &file, path };
+
+    let deltas: Vec<&SccsDelta> = file
+        .deltas
+        .iter()
+        .filter(|d| args.all || d.delta_type == DeltaType::Normal)
+        .collect();
+    let selected: Vec<&SccsDelta> = if let Some(cutoff) = &args.cutoff {
+        let cutoff = parse_cutoff(cutoff)?;
+        deltas
+            .into_iter()
+            .filter(|d| {
+                if args.later {
+                    d.timestamp() >= cutoff
+                } else {
+                    d.timestamp() <= cutoff
+                }
+            })
+            .collect()
+    } else if args.sid.is_some() || args.earlier || args.later || args.dataspec.is_some() {
+        let sid = args.sid.as_deref().filter(|s| !s.is_empty());
+        let delta = match sid {
+            Some(sid) => file.find_sid(Some(&sid.parse::<Sid>()?))?,
+            None => file
+                .deltas
+                .iter()
+                .filter(|d| args.all || d.delta_type == DeltaType::Normal)
+                .max_by_key(|d| d.serial)
+                .ok_or_else(|| gettext("no deltas"))?,
+        };
+        deltas
+            .into_iter()
+            .filter(|d| {
+                if args.earlier {
+                    d.serial <= delta.serial
+                } else if args.later {
+                    d.serial >= delta.serial
+                } else {
+                    d.serial == delta.serial
+                }
+            })
+            .collect()
+    } else {
+        deltas
+    };
+
+    let mut out = String::new();
+    let dataspec = match &args.dataspec {
+        Some(dataspec) => dataspec.as_str(),
+        None => {
+            out.push_str(&format!("{}:\n\n", path.display()));
+            DEFAULT_DATASPEC
+        }
+    };
+    for delta in selected {
+        out.push_str(&printer.expand(dataspec, delta));
+        out.push('\n');
+    }
+    io::stdout()
+        .write_all(out.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse_from(attach_optional_values("r"));
+
+    let mut exit_code = 0;
+    for path in expand_operands(&args.files) {
+        if let Err(message) = prs(&path, &args) {
+            eprintln!("prs: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,89 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{expand_operands, read_pfile, user_name, Lock, SccsFile, Sid};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("rmdel - remove a delta from an SCCS file"))]
+struct Args {
+    #[arg(
+        short = 'r',
+        required = true,
+        value_name = "SID",
+        help = gettext("The SID of the delta to remove")
+    )]
+    sid: String,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+fn rmdel(path: &Path, sid: &Sid) -> Result<(), String> {
+    let _lock = Lock::acquire(path)?;
+    let mut file = SccsFile::read(path)?;
+    let delta = file
+        .delta_by_sid(sid)
+        .ok_or_else(|| format!("{}: {}", gettext("nonexistent SID"), sid))?;
+
+    // Only a delta no other delta was made from can be removed.
+    if file.active_deltas().any(|d| d.pred == delta.serial) {
+        return Err(format!("{}: {}", gettext("not a leaf delta"), sid));
+    }
+    if read_pfile(path)?.iter().any(|e| e.old == *sid) {
+        return Err(format!("{}: {}", gettext("delta is being edited"), sid));
+    }
+    let owner = fs::metadata(path).map(|m| m.uid()).unwrap_or(u32::MAX);
+    if delta.author != user_name() && owner != unsafe { libc::getuid() } {
+        return Err(format!(
+            "{}: {}",
+            gettext("not authorized to remove delta"),
+            sid
+        ));
+    }
+
+    let serial = delta.serial;
+    file.remove_delta(serial);
+    file.write(path).map_err(|e| e.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let sid: Sid = match args.sid.parse() {
+        Ok(sid) => sid,
+        Err(message) => {
+            eprintln!("rmdel: {}", message);
+            std::process::exit(1);
+        }
+    };
+    if sid.components() != 2 && sid.components() != 4 {
+        eprintln!("rmdel: {}: {}", gettext("ambiguous SID"), sid);
+        std::process::exit(1);
+    }
+
+    let mut exit_code = 0;
+    for path in expand_operands(&args.files) {
+        if let Err(message) = rmdel(&path, &sid) {
+            eprintln!("rmdel: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,63 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{expand_operands, is_sccs_name, read_pfile};
+use plib::PROJECT_NAME;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("sact - print current SCCS file-editing activity"))]
+struct Args {
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+fn sact(path: &Path, heading: bool) -> Result<(), String> {
+    if !is_sccs_name(path) || !path.is_file() {
+        return Err(gettext("not an SCCS file"));
+    }
+    let entries = read_pfile(path)?;
+    if heading {
+        println!("\n{}:", path.display());
+    }
+    if entries.is_empty() {
+        if heading {
+            println!("{}", gettext("No outstanding deltas"));
+        }
+        return Ok(());
+    }
+    for entry in entries {
+        println!(
+            "{} {} {} {} {}",
+            entry.old, entry.new, entry.user, entry.date, entry.time
+        );
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let files = expand_operands(&args.files);
+    let mut exit_code = 0;
+    for path in &files {
+        if let Err(message) = sact(path, files.len() > 1) {
+            eprintln!("sact: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
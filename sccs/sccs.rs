@@ -0,0 +1,430 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{gfile_name, is_sccs_name, read_pfile, SccsFile, Sid};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Parser)]
+#[command(version, about = gettext("sccs - front end for the SCCS subsystem"))]
+struct Args {
+    #[arg(short = 'r', help = gettext("Run the command as the real user"))]
+    real_user: bool,
+
+    #[arg(
+        short = 'd',
+        value_name = "PATH",
+        help = gettext("The root directory of the SCCS files")
+    )]
+    root: Option<PathBuf>,
+
+    #[arg(
+        short = 'p',
+        value_name = "PATH",
+        help = gettext("The directory of the SCCS files, instead of SCCS")
+    )]
+    sccs_dir: Option<PathBuf>,
+
+    #[arg(
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "COMMAND",
+        help = gettext("The SCCS command to run, with its options and files")
+    )]
+    command: Vec<String>,
+}
+
+/// The SCCS commands, with their options that take a value as a separate
+/// argument.
+const COMMANDS: &[(&str, &str)] = &[
+    ("admin", "adefmr"),
+    ("delta", "gmr"),
+    ("get", "cirx"),
+    ("prs", "cd"),
+    ("rmdel", "r"),
+    ("sact", ""),
+    ("unget", "r"),
+    ("val", "mry"),
+    ("what", ""),
+];
+
+struct Sccs {
+    root: Option<PathBuf>,
+    sccs_dir: PathBuf,
+}
+
+impl Sccs {
+    /// Converts a file operand to the SCCS file it stands for: `name` is
+    /// `SCCS/s.name`, and a directory its SCCS directory.  Names of SCCS
+    /// files are kept.
+    fn sfile(&self, name: &str) -> PathBuf {
+        let path = match &self.root {
+            Some(root) if Path::new(name).is_relative() => root.join(name),
+            _ => PathBuf::from(name),
+        };
+        if is_sccs_name(&path) {
+            return path;
+        }
+        if path.is_dir() {
+            let sccs = path.join(&self.sccs_dir);
+            return if sccs.is_dir() { sccs } else { path };
+        }
+        let base = path.file_name().unwrap_or_default().to_string_lossy();
+        path.with_file_name(&self.sccs_dir)
+            .join(format!("s.{}", base))
+    }
+
+    /// The SCCS directory for commands given no files.
+    fn default_dir(&self) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(&self.sccs_dir),
+            None => self.sccs_dir.clone(),
+        }
+    }
+
+    /// Splits the arguments of a command into options, with their values,
+    /// and the SCCS files the operands stand for.
+    fn split_args(&self, args: &[String], value_options: &str) -> (Vec<String>, Vec<PathBuf>) {
+        let mut options = Vec::new();
+        let mut files = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg.len() > 1 && arg.starts_with('-') {
+                options.push(arg.clone());
+                let letter = arg[1..].chars().next().unwrap_or(' ');
+                if arg.len() == 2 && value_options.contains(letter) {
+                    options.extend(args.next().cloned());
+                }
+            } else {
+                files.push(self.sfile(arg));
+            }
+        }
+        (options, files)
+    }
+
+    /// The SCCS files of the operands, or those of the SCCS directory.
+    fn sfiles(&self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let files = if files.is_empty() {
+            vec![self.default_dir()]
+        } else {
+            files
+        };
+        let mut sfiles = Vec::new();
+        for file in files {
+            if file.is_dir() {
+                let mut entries: Vec<PathBuf> = fs::read_dir(&file)
+                    .map(|dir| {
+                        dir.flatten()
+                            .map(|e| e.path())
+                            .filter(|p| is_sccs_name(p))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                entries.sort();
+                sfiles.extend(entries);
+            } else {
+                sfiles.push(file);
+            }
+        }
+        sfiles
+    }
+}
+
+/// The path of an SCCS command: beside this program, or found in PATH.
+fn command_path(name: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+fn run<S: AsRef<std::ffi::OsStr>>(name: &str, args: &[S]) -> io::Result<i32> {
+    let status = Command::new(command_path(name)).args(args).status()?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Runs a command for its standard output.
+fn capture<S: AsRef<std::ffi::OsStr>>(name: &str, args: &[S]) -> io::Result<(i32, Vec<u8>)> {
+    let output = Command::new(command_path(name))
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()?;
+    Ok((output.status.code().unwrap_or(1), output.stdout))
+}
+
+fn with_files(options: &[String], files: &[PathBuf]) -> Vec<String> {
+    let mut args = options.to_vec();
+    args.extend(files.iter().map(|f| f.display().to_string()));
+    args
+}
+
+/// `create`: makes SCCS files from the named files, keeping the originals
+/// as `,name`, then gets them.
+fn create(sccs: &Sccs, args: &[String]) -> io::Result<i32> {
+    let (options, sfiles) = sccs.split_args(args, "adefmr");
+    let names: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let mut status = 0;
+    for (name, sfile) in names.iter().zip(&sfiles) {
+        let mut admin = options.clone();
+        admin.push(format!("-i{}", name));
+        admin.push(sfile.display().to_string());
+        let code = run("admin", &admin)?;
+        if code != 0 {
+            status = code;
+            continue;
+        }
+        let path = Path::new(name.as_str());
+        let base = path.file_name().unwrap_or_default().to_string_lossy();
+        fs::rename(path, path.with_file_name(format!(",{}", base)))?;
+        status |= run("get", &[sfile])?;
+    }
+    Ok(status)
+}
+
+/// `fix`: removes a delta, leaving its text in the g-file to edit again.
+fn fix(sccs: &Sccs, args: &[String]) -> io::Result<i32> {
+    let (options, sfiles) = sccs.split_args(args, "r");
+    let Some(sid) =
+        options
+            .iter()
+            .position(|o| o.starts_with("-r"))
+            .map(|i| match &options[i][2..] {
+                "" => options.get(i + 1).cloned().unwrap_or_default(),
+                sid => sid.to_string(),
+            })
+    else {
+        eprintln!("sccs: fix: {}", gettext("-r SID required"));
+        return Ok(1);
+    };
+    let mut status = 0;
+    for sfile in &sfiles {
+        let pred = SccsFile::read(sfile).ok().and_then(|file| {
+            let sid: Sid = sid.parse().ok()?;
+            let delta = file.delta_by_sid(&sid)?;
+            file.delta_by_serial(delta.pred).map(|d| d.sid)
+        });
+        let (code, text) = capture(
+            "get",
+            &[
+                "-k",
+                "-p",
+                "-s",
+                &format!("-r{}", sid),
+                &sfile.display().to_string(),
+            ],
+        )?;
+        if code != 0 {
+            status = code;
+            continue;
+        }
+        let code = run(
+            "rmdel",
+            &[format!("-r{}", sid), sfile.display().to_string()],
+        )?;
+        if code != 0 {
+            status = code;
+            continue;
+        }
+        let gfile = gfile_name(sfile);
+        let mut get = vec![String::from("-e"), String::from("-g")];
+        get.extend(pred.map(|sid| format!("-r{}", sid)));
+        get.push(sfile.display().to_string());
+        status |= run("get", &get)?;
+        fs::write(&gfile, text)?;
+    }
+    Ok(status)
+}
+
+/// `diffs`: compares the files being edited with the versions they came
+/// from.
+fn diffs(sccs: &Sccs, args: &[String]) -> io::Result<i32> {
+    let (options, sfiles) = sccs.split_args(args, "r");
+    let (get_options, diff_options): (Vec<String>, Vec<String>) =
+        options.into_iter().partition(|o| o.starts_with("-r"));
+    let mut status = 0;
+    for sfile in &sfiles {
+        let gfile = gfile_name(sfile);
+        let mut get = vec![String::from("-s"), String::from("-k"), String::from("-p")];
+        get.extend(get_options.iter().cloned());
+        if get_options.is_empty() {
+            let user = plib::sccsfile::user_name();
+            if let Some(entry) = read_pfile(sfile)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|e| e.user == user)
+            {
+                get.push(format!("-r{}", entry.old));
+            }
+        }
+        get.push(sfile.display().to_string());
+        let (code, text) = capture("get", &get)?;
+        if code != 0 {
+            status = code;
+            continue;
+        }
+        let old = std::env::temp_dir().join(format!(
+            "sccs-diffs-{}-{}",
+            std::process::id(),
+            gfile.display()
+        ));
+        fs::write(&old, text)?;
+        println!("\n------- {} -------", gfile.display());
+        io::stdout().flush()?;
+        let mut diff = diff_options.clone();
+        diff.push(old.display().to_string());
+        diff.push(gfile.display().to_string());
+        let code = Command::new("diff")
+            .args(&diff)
+            .status()?
+            .code()
+            .unwrap_or(2);
+        let _ = fs::remove_file(&old);
+        if code > 1 {
+            status = code;
+        }
+    }
+    Ok(status)
+}
+
+/// `info`, `check` and `tell`: report the files being edited.  Returns
+/// whether any are.
+fn editing(sccs: &Sccs, args: &[String], tell: bool) -> bool {
+    let (_, files) = sccs.split_args(args, "");
+    let mut any = false;
+    for sfile in sccs.sfiles(files) {
+        for entry in read_pfile(&sfile).unwrap_or_default() {
+            let gfile = gfile_name(&sfile);
+            if tell {
+                println!("{}", gfile.display());
+            } else {
+                println!(
+                    "{}: {} {}",
+                    gfile.display(),
+                    gettext("being edited:"),
+                    entry
+                );
+            }
+            any = true;
+        }
+    }
+    any
+}
+
+/// `clean`: removes the g-files of files not being edited.
+fn clean(sccs: &Sccs, args: &[String]) -> io::Result<i32> {
+    let (_, files) = sccs.split_args(args, "");
+    for sfile in sccs.sfiles(files) {
+        if read_pfile(&sfile).unwrap_or_default().is_empty() {
+            match fs::remove_file(gfile_name(&sfile)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+    }
+    Ok(0)
+}
+
+fn sccs_command(sccs: &Sccs, command: &str, args: &[String]) -> io::Result<i32> {
+    if let Some((name, value_options)) = COMMANDS.iter().find(|(name, _)| *name == command) {
+        if *name == "what" {
+            return run(name, args);
+        }
+        let (options, files) = sccs.split_args(args, value_options);
+        return run(name, &with_files(&options, &files));
+    }
+    // Runs delta or unget, then get with `second` as its options.
+    let then = |first: &str, second: &[&str]| -> io::Result<i32> {
+        let (options, files) = sccs.split_args(args, "gmr");
+        let status = run(first, &with_files(&options, &files))?;
+        if status != 0 {
+            return Ok(status);
+        }
+        let second: Vec<String> = second.iter().map(|s| s.to_string()).collect();
+        run("get", &with_files(&second, &files))
+    };
+    match command {
+        "edit" => {
+            let (options, files) = sccs.split_args(args, "cirx");
+            let mut get = vec![String::from("-e")];
+            get.extend(options);
+            run("get", &with_files(&get, &files))
+        }
+        "delget" => then("delta", &[]),
+        "deledit" => then("delta", &["-e"]),
+        "unedit" => then("unget", &[]),
+        "print" => {
+            let (_, files) = sccs.split_args(args, "");
+            let mut status = 0;
+            for file in &files {
+                status |= run("prs", &[String::from("-e"), file.display().to_string()])?;
+                status |= run(
+                    "get",
+                    &[
+                        String::from("-p"),
+                        String::from("-m"),
+                        String::from("-s"),
+                        file.display().to_string(),
+                    ],
+                )?;
+            }
+            Ok(status)
+        }
+        "create" => create(sccs, args),
+        "fix" => fix(sccs, args),
+        "diffs" => diffs(sccs, args),
+        "clean" => clean(sccs, args),
+        "info" => {
+            if !editing(sccs, args, false) {
+                println!("{}", gettext("Nothing being edited"));
+            }
+            Ok(0)
+        }
+        "check" => Ok(i32::from(editing(sccs, args, false))),
+        "tell" => {
+            editing(sccs, args, true);
+            Ok(0)
+        }
+        _ => {
+            eprintln!("sccs: {}: {}", gettext("unknown command"), command);
+            Ok(1)
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    // Without the set-user-ID bit, the real user is the only user.
+    let _ = args.real_user;
+    let sccs = Sccs {
+        root: args.root,
+        sccs_dir: args.sccs_dir.unwrap_or_else(|| PathBuf::from("SCCS")),
+    };
+    let (command, command_args) = args.command.split_first().unwrap();
+    let status = match sccs_command(&sccs, command, command_args) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("sccs: {}: {}", command, e);
+            1
+        }
+    };
+
+    std::process::exit(status)
+}
@@ -0,0 +1,105 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{run_in, SAMPLE};
+use plib::scratch_dir;
+use std::fs;
+
+#[test]
+fn test_admin_create() {
+    let dir = scratch_dir("admin-create");
+    fs::write(dir.join("text"), "one\ntwo\n").unwrap();
+    fs::write(dir.join("desc"), "about the file\n").unwrap();
+
+    let (code, _, _) = run_in(
+        &dir,
+        "admin",
+        &[
+            "-itext", "-r3", "-yfirst", "-tdesc", "-fb", "-fmmod", "s.text",
+        ],
+        "",
+    );
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "prs", &["-d:I: :C::FD::FL:", "s.text"], "");
+    assert_eq!(
+        out,
+        "3.1 first\nabout the file\n\tbranch\n\tmodule\tmod\n\n"
+    );
+
+    // An existing file is not replaced.
+    let (code, _, err) = run_in(&dir, "admin", &["-n", "s.text"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "admin: s.text: file exists\n");
+
+    // The text can come from standard input, and -n makes empty files.
+    let (code, _, _) = run_in(&dir, "admin", &["-i", "s.piped"], "from stdin\n");
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "get", &["-p", "-s", "s.piped"], "");
+    assert_eq!(out, "from stdin\n");
+    let (code, _, _) = run_in(&dir, "admin", &["-n", "s.a", "s.b"], "");
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "prs", &["-d:I: :Li:", "s.a", "s.b"], "");
+    assert_eq!(out, "1.1 00000\n1.1 00000\n");
+
+    let (code, _, err) = run_in(&dir, "admin", &["-n", "text2"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "admin: text2: not an SCCS file\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_admin_flags_and_users() {
+    let dir = scratch_dir("admin-flags");
+    fs::copy(SAMPLE, dir.join("s.sample.c")).unwrap();
+
+    let (code, _, _) = run_in(
+        &dir,
+        "admin",
+        &["-fj", "-fd1.1", "-dt", "-aalice", "-abob", "s.sample.c"],
+        "",
+    );
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "prs", &["-d:J: :Ds: :Y:|:UN:", "s.sample.c"], "");
+    assert_eq!(out, "yes 1.1 |alice\nbob\n\n");
+    let (code, _, _) = run_in(&dir, "admin", &["-ealice", "s.sample.c"], "");
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "prs", &["-d:UN:", "s.sample.c"], "");
+    assert_eq!(out, "bob\n\n");
+
+    // The d flag changes the default delta.
+    let (_, _, err) = run_in(&dir, "get", &["-p", "-k", "s.sample.c"], "");
+    assert_eq!(err, "1.1\n4 lines\n");
+
+    let (code, _, err) = run_in(&dir, "admin", &["-fx", "s.sample.c"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "admin: s.sample.c: unknown flag: x\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_admin_checksum() {
+    let dir = scratch_dir("admin-checksum");
+    let sfile = dir.join("s.sample.c");
+    fs::copy(SAMPLE, &sfile).unwrap();
+    let (code, _, _) = run_in(&dir, "admin", &["-h", "s.sample.c"], "");
+    assert_eq!(code, 0);
+
+    let text = fs::read_to_string(&sfile).unwrap();
+    fs::remove_file(&sfile).unwrap();
+    fs::write(&sfile, text.replace("cherry", "cherri")).unwrap();
+    let (code, _, err) = run_in(&dir, "admin", &["-h", "s.sample.c"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "admin: s.sample.c: corrupted file\n");
+
+    let (code, _, _) = run_in(&dir, "admin", &["-z", "s.sample.c"], "");
+    assert_eq!(code, 0);
+    let (code, _, _) = run_in(&dir, "admin", &["-h", "s.sample.c"], "");
+    assert_eq!(code, 0);
+    fs::remove_dir_all(dir).unwrap();
+}
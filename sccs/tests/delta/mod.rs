@@ -0,0 +1,122 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::run_in;
+use plib::scratch_dir;
+use std::fs;
+use std::path::Path;
+
+fn create(dir: &Path, text: &str) {
+    fs::write(dir.join("f"), text).unwrap();
+    let (code, _, _) = run_in(dir, "admin", &["-if", "s.f"], "");
+    assert_eq!(code, 0);
+    fs::remove_file(dir.join("f")).unwrap();
+}
+
+#[test]
+fn test_delta_workflow() {
+    let dir = scratch_dir("delta-workflow");
+    create(&dir, "a\nb\nc\nd\n");
+
+    let (code, _, _) = run_in(&dir, "get", &["-e", "-s", "s.f"], "");
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "sact", &["s.f"], "");
+    assert!(out.starts_with("1.1 1.2 "));
+    fs::write(dir.join("f"), "a\nB\nc\nd\ne\n").unwrap();
+
+    // Comments are read from standard input without -y.
+    let (code, out, _) = run_in(&dir, "delta", &["-p", "s.f"], "changed b\nadded e\n");
+    assert_eq!(code, 0);
+    assert_eq!(
+        out,
+        "1.2\n2c2\n< b\n---\n> B\n4a5\n> e\n2 inserted\n1 deleted\n3 unchanged\n"
+    );
+    assert!(!dir.join("f").exists());
+    assert!(!dir.join("p.f").exists());
+    let (_, out, _) = run_in(&dir, "prs", &["-d:I: :C:", "-e", "s.f"], "");
+    assert!(out.starts_with("1.2 changed b\nadded e\n\n1.1 date and time created "));
+
+    // -n keeps the file; the new version has what was written.
+    run_in(&dir, "get", &["-e", "-s", "s.f"], "");
+    fs::write(dir.join("f"), "B\nc\nd\ne\n").unwrap();
+    let (code, out, _) = run_in(&dir, "delta", &["-n", "-yremove a", "s.f"], "");
+    assert_eq!(code, 0);
+    assert_eq!(out, "1.3\n0 inserted\n1 deleted\n4 unchanged\n");
+    assert!(dir.join("f").exists());
+    let (_, out, _) = run_in(&dir, "get", &["-p", "-s", "-r1.2", "s.f"], "");
+    assert_eq!(out, "a\nB\nc\nd\ne\n");
+
+    let (code, _, err) = run_in(&dir, "delta", &["-y", "s.f"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "delta: s.f: not being edited by you\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_delta_branches() {
+    let dir = scratch_dir("delta-branches");
+    create(&dir, "one\ntwo\n");
+    run_in(&dir, "get", &["-e", "-s", "s.f"], "");
+    fs::write(dir.join("f"), "one\ntwo\nthree\n").unwrap();
+    run_in(&dir, "delta", &["-s", "-ytrunk", "s.f"], "");
+
+    // Editing an older delta makes a branch; -b forces one when allowed.
+    let (_, out, _) = run_in(&dir, "get", &["-e", "-r1.1", "s.f"], "");
+    assert_eq!(out, "1.1\nnew delta 1.1.1.1\n2 lines\n");
+    fs::write(dir.join("f"), "zero\none\ntwo\n").unwrap();
+    run_in(&dir, "delta", &["-s", "-ybranch", "s.f"], "");
+    run_in(&dir, "admin", &["-fb", "s.f"], "");
+    let (_, out, _) = run_in(&dir, "get", &["-e", "-b", "-g", "s.f"], "");
+    assert_eq!(out, "1.2\nnew delta 1.2.1.1\n3 lines\n");
+    let (_, out, _) = run_in(&dir, "unget", &["s.f"], "");
+    assert_eq!(out, "1.2.1.1\n");
+    assert!(!dir.join("p.f").exists());
+
+    // A later release.
+    let (_, out, _) = run_in(&dir, "get", &["-e", "-r2", "-g", "s.f"], "");
+    assert_eq!(out, "1.2\nnew delta 2.1\n3 lines\n");
+    run_in(&dir, "unget", &["-s", "s.f"], "");
+
+    let (_, out, _) = run_in(&dir, "get", &["-p", "-s", "-r1.2", "-i1.1.1.1", "s.f"], "");
+    assert_eq!(out, "zero\none\ntwo\nthree\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_rmdel() {
+    let dir = scratch_dir("rmdel");
+    create(&dir, "one\n");
+    run_in(&dir, "get", &["-e", "-s", "s.f"], "");
+    fs::write(dir.join("f"), "one\ntwo\n").unwrap();
+    run_in(&dir, "delta", &["-s", "-y", "s.f"], "");
+
+    let (code, _, err) = run_in(&dir, "rmdel", &["-r1.1", "s.f"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "rmdel: s.f: not a leaf delta: 1.1\n");
+    run_in(&dir, "get", &["-e", "-g", "-s", "s.f"], "");
+    let (code, _, err) = run_in(&dir, "rmdel", &["-r1.2", "s.f"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "rmdel: s.f: delta is being edited: 1.2\n");
+    run_in(&dir, "unget", &["-s", "s.f"], "");
+
+    let (code, _, _) = run_in(&dir, "rmdel", &["-r1.2", "s.f"], "");
+    assert_eq!(code, 0);
+    let (_, out, _) = run_in(&dir, "prs", &["-a", "-d:DT: :I:", "-e", "s.f"], "");
+    assert_eq!(out, "R 1.2\nD 1.1\n");
+    let (_, out, err) = run_in(&dir, "get", &["-p", "s.f"], "");
+    assert_eq!(
+        (out.as_str(), err.as_str()),
+        ("one\n", "get: s.f: warning: No id keywords\n1.1\n1 lines\n")
+    );
+
+    // The SID of a removed delta is used again.
+    let (_, out, _) = run_in(&dir, "get", &["-e", "-g", "s.f"], "");
+    assert_eq!(out, "1.1\nnew delta 1.2\n1 lines\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,153 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{run_in, SAMPLE};
+use plib::{run_test, scratch_dir, TestPlan};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+fn get_test(args: &[&str], expected_out: &str, expected_err: &str) {
+    let mut args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+    args.push(String::from(SAMPLE));
+    run_test(TestPlan {
+        cmd: String::from("get"),
+        args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_out),
+        expected_err: String::from(expected_err),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_get_versions() {
+    get_test(
+        &["-p"],
+        "/* @(#)sample\t1.2 */\napple\nBANANA\ncherry\ndate\n",
+        "1.2\n5 lines\n",
+    );
+    get_test(
+        &["-p", "-r1.1"],
+        "/* @(#)sample\t1.1 */\napple\nbanana\ncherry\n",
+        "1.1\n4 lines\n",
+    );
+    get_test(
+        &["-p", "-r", "1.1.1"],
+        "/* @(#)sample\t1.1.1.1 */\napricot\napple\nbanana\ncherry\n",
+        "1.1.1.1\n5 lines\n",
+    );
+}
+
+#[test]
+fn test_get_include_exclude() {
+    get_test(
+        &["-p", "-s", "-i1.1.1.1"],
+        "/* @(#)sample\t1.2 */\napricot\napple\nBANANA\ncherry\ndate\n",
+        "",
+    );
+    get_test(
+        &["-p", "-k", "-r1.2", "-x1.1"],
+        "BANANA\ndate\n",
+        "1.2\nExcluded:\n1.1\n2 lines\n",
+    );
+}
+
+#[test]
+fn test_get_prefixes() {
+    get_test(
+        &["-p", "-s", "-k", "-m", "-n"],
+        "sample\t1.1\t/* %W% */\nsample\t1.1\tapple\nsample\t1.2\tBANANA\n\
+         sample\t1.1\tcherry\nsample\t1.2\tdate\n",
+        "",
+    );
+}
+
+#[test]
+fn test_get_keywords() {
+    let dir = scratch_dir("get-keywords");
+    fs::write(
+        dir.join("k.txt"),
+        "%M% %I% %R% %L% %B% %S% %Y% %Q% %C%\n%E% %G% %U% %Z% %A%\n",
+    )
+    .unwrap();
+    let (code, _, _) = run_in(
+        &dir,
+        "admin",
+        &["-ik.txt", "-ftprog", "-fqwhy", "s.k.txt"],
+        "",
+    );
+    assert_eq!(code, 0);
+    let (code, out, _) = run_in(&dir, "get", &["-p", "-s", "s.k.txt"], "");
+    assert_eq!(code, 0);
+    let mut lines = out.lines();
+    assert_eq!(lines.next(), Some("k.txt 1.1 1 1 0 0 prog why 1"));
+    let second: Vec<&str> = lines.next().unwrap().split(' ').collect();
+    assert_eq!(second[0].len(), 8);
+    assert_eq!(&second[3..], ["@(#)", "@(#)prog", "k.txt", "1.1@(#)"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_get_files() {
+    let dir = scratch_dir("get-files");
+    fs::copy(SAMPLE, dir.join("s.sample.c")).unwrap();
+
+    let (code, out, _) = run_in(&dir, "get", &["-r1.1", "s.sample.c"], "");
+    assert_eq!((code, out.as_str()), (0, "1.1\n4 lines\n"));
+    let gfile = dir.join("sample.c");
+    assert_eq!(
+        fs::metadata(&gfile).unwrap().permissions().mode() & 0o777,
+        0o444
+    );
+
+    // -g retrieves nothing; -e makes the file writable and records the edit.
+    let (code, out, _) = run_in(&dir, "get", &["-g", "-e", "s.sample.c"], "");
+    assert_eq!((code, out.as_str()), (0, "1.2\nnew delta 1.3\n5 lines\n"));
+    assert!(fs::read_to_string(dir.join("p.sample.c"))
+        .unwrap()
+        .starts_with("1.2 1.3 "));
+    let (code, out, _) = run_in(&dir, "get", &["-e", "-r1.2", "s.sample.c"], "");
+    assert_eq!(code, 1);
+    assert_eq!(out, "");
+
+    let (code, out, _) = run_in(&dir, "get", &["-e", "-r1.1.1.1", "s.sample.c"], "");
+    assert_eq!(
+        (code, out.as_str()),
+        (0, "1.1.1.1\nnew delta 1.1.1.2\n5 lines\n")
+    );
+    assert_eq!(
+        fs::read_to_string(&gfile).unwrap(),
+        "/* %W% */\napricot\napple\nbanana\ncherry\n"
+    );
+    assert_eq!(
+        fs::metadata(&gfile).unwrap().permissions().mode() & 0o777,
+        0o644
+    );
+
+    // The writable file is not overwritten.
+    let (code, _, err) = run_in(&dir, "get", &["s.sample.c"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "get: s.sample.c: writable `sample.c' exists\n");
+
+    let (code, _, _) = run_in(&dir, "get", &["-s", "-l", "-p", "-r1.1", "s.sample.c"], "");
+    assert_eq!(code, 0);
+    let summary = fs::read_to_string(dir.join("l.sample.c")).unwrap();
+    assert_eq!(
+        summary
+            .lines()
+            .filter(|l| l.contains("jgarzik"))
+            .collect::<Vec<_>>(),
+        [
+            "**  1.1.1.1\t24/07/09 12:00:00 jgarzik",
+            "**  1.2\t24/07/09 12:00:00 jgarzik",
+            "    1.1\t24/07/09 12:00:00 jgarzik",
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,71 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::SAMPLE;
+use plib::{run_test, TestPlan};
+
+fn prs_test(args: &[&str], expected_out: &str) {
+    let mut args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+    args.push(String::from(SAMPLE));
+    run_test(TestPlan {
+        cmd: String::from("prs"),
+        args,
+        stdin_data: String::new(),
+        expected_out: String::from(expected_out),
+        expected_err: String::new(),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_prs_default() {
+    prs_test(
+        &[],
+        "tests/s.sample.c:\n\n\
+         D 1.1.1.1 24/07/09 12:00:00 jgarzik 3 1\t00001/00000/00004\n\
+         MRs:\nCOMMENTS:\nbranch: apricot\n\n\
+         D 1.2 24/07/09 12:00:00 jgarzik 2 1\t00002/00001/00003\n\
+         MRs:\nCOMMENTS:\ncapitalize banana\n\n\
+         D 1.1 24/07/09 12:00:00 jgarzik 1 0\t00004/00000/00000\n\
+         MRs:\nCOMMENTS:\ninitial version\n\n",
+    );
+}
+
+#[test]
+fn test_prs_dataspec() {
+    prs_test(&["-d:I:"], "1.1.1.1\n");
+    prs_test(
+        &["-d:I: :P: :C:", "-r1.1"],
+        "1.1 jgarzik initial version\n\n",
+    );
+    prs_test(&["-d:I:", "-e", "-r1.2"], "1.2\n1.1\n");
+    prs_test(&["-d:I:", "-l", "-r1.2"], "1.1.1.1\n1.2\n");
+    prs_test(
+        &[
+            "-d:R:/:L:/:B:/:S: :Dy:-:Dm:-:Dd: :Th:h :DS: :DP: :DL:",
+            "-r1.1.1.1",
+        ],
+        "1/1/1/1 24-07-09 12h 3 1 00001/00000/00004\n",
+    );
+    prs_test(
+        &["-d:M:\\t:Y: :F: :MF: :BF: :Ds: :UN:", "-r1.2"],
+        "sample\tc s.sample.c no no none none\n\n",
+    );
+    prs_test(&["-d:W:", "-r1.2"], "@(#)sample\t1.2\n");
+    prs_test(
+        &["-d:GB:", "-r1.1.1.1"],
+        "/* %W% */\napricot\napple\nbanana\ncherry\n\n",
+    );
+}
+
+#[test]
+fn test_prs_cutoff() {
+    prs_test(&["-d:I:", "-e", "-c240710"], "1.1.1.1\n1.2\n1.1\n");
+    prs_test(&["-d:I:", "-l", "-c240710"], "");
+}
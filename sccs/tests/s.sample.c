@@ -0,0 +1,36 @@
+h22488
+s 00001/00000/00004
+d D 1.1.1.1 24/07/09 12:00:00 jgarzik 3 1
+c branch: apricot
+e
+s 00002/00001/00003
+d D 1.2 24/07/09 12:00:00 jgarzik 2 1
+c capitalize banana
+e
+s 00004/00000/00000
+d D 1.1 24/07/09 12:00:00 jgarzik 1 0
+c initial version
+e
+u
+U
+f m sample
+f t c
+t
+T
+I 1
+/* %W% */
+I 3
+apricot
+E 3
+apple
+D 2
+banana
+E 2
+I 2
+BANANA
+E 2
+cherry
+I 2
+date
+E 2
+E 1
@@ -1 +1,55 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod admin;
+mod delta;
+mod get;
+mod prs;
+mod sccs;
+mod val;
 mod what;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The SCCS file the tests read: 1.1, 1.2 made from it, and the branch
+/// delta 1.1.1.1.
+pub const SAMPLE: &str = "tests/s.sample.c";
+
+/// Runs an SCCS command in `dir`, returning its exit status, standard
+/// output and standard error.
+pub fn run_in(dir: &Path, cmd: &str, args: &[&str], stdin: &str) -> (i32, String, String) {
+    let bin = std::env::current_dir()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("target/debug")
+        .join(cmd);
+    let mut child = Command::new(bin)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
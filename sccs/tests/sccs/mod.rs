@@ -0,0 +1,97 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::run_in;
+use plib::scratch_dir;
+use std::fs;
+
+#[test]
+fn test_sccs_front_end() {
+    let dir = scratch_dir("front-end");
+    fs::create_dir(dir.join("SCCS")).unwrap();
+    fs::write(dir.join("prog.c"), "int x;\n").unwrap();
+
+    // create keeps the original as ,prog.c and gets a read-only copy.
+    let (code, out, _) = run_in(&dir, "sccs", &["create", "prog.c"], "");
+    assert_eq!((code, out.as_str()), (0, "1.1\n1 lines\n"));
+    assert!(dir.join("SCCS/s.prog.c").exists());
+    assert!(dir.join(",prog.c").exists());
+
+    let (code, out, _) = run_in(&dir, "sccs", &["edit", "prog.c"], "");
+    assert_eq!((code, out.as_str()), (0, "1.1\nnew delta 1.2\n1 lines\n"));
+    let (_, out, _) = run_in(&dir, "sccs", &["tell"], "");
+    assert_eq!(out, "prog.c\n");
+    let (code, out, _) = run_in(&dir, "sccs", &["check"], "");
+    assert_eq!(code, 1);
+    assert!(out.starts_with("prog.c: being edited: 1.1 1.2 "));
+
+    fs::write(dir.join("prog.c"), "int x;\nint y;\n").unwrap();
+    let (_, out, _) = run_in(&dir, "sccs", &["diffs", "prog.c"], "");
+    assert_eq!(out, "\n------- prog.c -------\n1a2\n> int y;\n");
+
+    let (code, out, _) = run_in(&dir, "sccs", &["delget", "-yadd y", "prog.c"], "");
+    assert_eq!(code, 0);
+    assert_eq!(
+        out,
+        "1.2\n1 inserted\n0 deleted\n1 unchanged\n1.2\n2 lines\n"
+    );
+    let (code, out, _) = run_in(&dir, "sccs", &["check"], "");
+    assert_eq!((code, out.as_str()), (0, ""));
+
+    // Options taking a separate value keep it.
+    let (_, out, _) = run_in(
+        &dir,
+        "sccs",
+        &["prs", "-d", ":I: :C:", "-r", "1.2", "prog.c"],
+        "",
+    );
+    assert_eq!(out, "1.2 add y\n\n");
+    let (_, out, _) = run_in(
+        &dir,
+        "sccs",
+        &["get", "-p", "-s", "-r", "1.1", "prog.c"],
+        "",
+    );
+    assert_eq!(out, "int x;\n");
+
+    let (code, _, _) = run_in(&dir, "sccs", &["clean"], "");
+    assert_eq!(code, 0);
+    assert!(!dir.join("prog.c").exists());
+
+    let (code, _, err) = run_in(&dir, "sccs", &["bogus"], "");
+    assert_eq!(code, 1);
+    assert_eq!(err, "sccs: unknown command: bogus\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_sccs_directories() {
+    let dir = scratch_dir("front-end-dirs");
+    fs::create_dir_all(dir.join("src/RCS")).unwrap();
+    fs::write(dir.join("src/a.txt"), "hello\n").unwrap();
+    let (code, _, _) = run_in(&dir, "admin", &["-isrc/a.txt", "src/RCS/s.a.txt"], "");
+    assert_eq!(code, 0);
+
+    // -p names the SCCS directory and -d the root of the tree.
+    let (_, out, _) = run_in(
+        &dir,
+        "sccs",
+        &["-d", "src", "-p", "RCS", "prs", "-d:F:", "a.txt"],
+        "",
+    );
+    assert_eq!(out, "s.a.txt\n");
+    let (_, out, _) = run_in(
+        &dir,
+        "sccs",
+        &["-d", "src", "-p", "RCS", "val", "a.txt"],
+        "",
+    );
+    assert_eq!(out, "");
+    fs::remove_dir_all(dir).unwrap();
+}
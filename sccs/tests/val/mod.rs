@@ -0,0 +1,62 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use crate::SAMPLE;
+use plib::{run_test, TestPlan};
+
+fn val_test(args: &[&str], stdin: &str, expected_out: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("val"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(stdin),
+        expected_out: String::from(expected_out),
+        expected_err: String::new(),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_val_valid() {
+    val_test(&[SAMPLE], "", "", 0);
+    val_test(&["-msample", "-y", "c", "-r1.1.1.1", SAMPLE], "", "", 0);
+}
+
+#[test]
+fn test_val_problems() {
+    val_test(
+        &["-mfoo", "-r1.9", SAMPLE],
+        "",
+        "tests/s.sample.c: SID does not exist\ntests/s.sample.c: %M%, -m mismatch\n",
+        0x05,
+    );
+    val_test(
+        &["-r1.x", "-yc", SAMPLE],
+        "",
+        "tests/s.sample.c: invalid delta (SID)\n",
+        0x08,
+    );
+    val_test(
+        &["tests/empty_file.txt"],
+        "",
+        "tests/empty_file.txt: can't open file or file not SCCS\n",
+        0x10,
+    );
+    val_test(&[], "", "missing file argument\n", 0x80);
+    val_test(&["-s", "-s", "-q", "tests/empty_file.txt"], "", "", 0x50);
+}
+
+#[test]
+fn test_val_stdin() {
+    val_test(
+        &["-"],
+        &format!("-ytext {}\n-r1.2 {}\n", SAMPLE, SAMPLE),
+        "tests/s.sample.c: %Y%, -y mismatch\n",
+        0x02,
+    );
+}
@@ -0,0 +1,88 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{expand_operands, gfile_name, read_pfile, user_name, write_pfile, Lock, Sid};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = gettext("unget - undo a previous get of an SCCS file"))]
+struct Args {
+    #[arg(
+        short = 'r',
+        value_name = "SID",
+        help = gettext("The SID of the new delta, when there are several being made")
+    )]
+    sid: Option<String>,
+
+    #[arg(short = 's', help = gettext("Suppress the SID on standard output"))]
+    silent: bool,
+
+    #[arg(short = 'n', help = gettext("Keep the retrieved file"))]
+    keep: bool,
+
+    #[arg(required = true, help = gettext("SCCS files"))]
+    files: Vec<PathBuf>,
+}
+
+fn unget(path: &Path, args: &Args) -> Result<(), String> {
+    let requested: Option<Sid> = args.sid.as_deref().map(str::parse).transpose()?;
+    let _lock = Lock::acquire(path)?;
+    let mut entries = read_pfile(path)?;
+    let user = user_name();
+    let mine: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.user == user && requested.map_or(true, |r| e.new == r))
+        .map(|(i, _)| i)
+        .collect();
+    let index = match mine[..] {
+        [] => return Err(gettext("not being edited by you")),
+        [index] => index,
+        _ => return Err(gettext("more than one edit: use -r to say which")),
+    };
+    let entry = entries.remove(index);
+    write_pfile(path, &entries)?;
+
+    if !args.keep {
+        let gfile = gfile_name(path);
+        match fs::remove_file(&gfile) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(format!("{}: {}", gfile.display(), e));
+            }
+            _ => {}
+        }
+    }
+    if !args.silent {
+        println!("{}", entry.new);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let mut exit_code = 0;
+    for path in expand_operands(&args.files) {
+        if let Err(message) = unget(&path, &args) {
+            eprintln!("unget: {}: {}", path.display(), message);
+            exit_code = 1;
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,187 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! val - validate SCCS files
+//!
+//! The exit status is a bit mask of the problems found, so the options
+//! are parsed by hand: an unknown or repeated option is one of them.
+
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::sccsfile::{gfile_name, is_sccs_name, verify_checksum, SccsFile, Sid};
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+const MISSING_FILE: i32 = 0x80;
+const BAD_OPTION: i32 = 0x40;
+const CORRUPTED: i32 = 0x20;
+const NOT_SCCS: i32 = 0x10;
+const INVALID_SID: i32 = 0x08;
+const NONEXISTENT_SID: i32 = 0x04;
+const TYPE_MISMATCH: i32 = 0x02;
+const MODULE_MISMATCH: i32 = 0x01;
+
+#[derive(Default)]
+struct Options {
+    silent: bool,
+    module: Option<String>,
+    sid: Option<String>,
+    module_type: Option<String>,
+}
+
+/// Parses the arguments of one invocation, returning the options, the
+/// files, and the problems found in the arguments.
+fn parse_args(args: &[String]) -> (Options, Vec<String>, i32) {
+    let mut options = Options::default();
+    let mut files = Vec::new();
+    let mut code = 0;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        let Some(option) = arg.strip_prefix('-').filter(|o| !o.is_empty()) else {
+            files.push(arg.clone());
+            continue;
+        };
+        let letter = option.chars().next().unwrap();
+        let slot = match letter {
+            's' if option.len() == 1 => {
+                if options.silent {
+                    code |= BAD_OPTION;
+                }
+                options.silent = true;
+                continue;
+            }
+            'm' => &mut options.module,
+            'r' => &mut options.sid,
+            'y' => &mut options.module_type,
+            _ => {
+                code |= BAD_OPTION;
+                continue;
+            }
+        };
+        let value = if option.len() > 1 {
+            option[1..].to_string()
+        } else if i < args.len() {
+            i += 1;
+            args[i - 1].clone()
+        } else {
+            code |= BAD_OPTION;
+            continue;
+        };
+        if slot.is_some() {
+            code |= BAD_OPTION;
+        }
+        *slot = Some(value);
+    }
+    (options, files, code)
+}
+
+fn validate(path: &Path, options: &Options) -> i32 {
+    if !is_sccs_name(path) {
+        return NOT_SCCS;
+    }
+    let Ok(data) = fs::read(path) else {
+        return NOT_SCCS;
+    };
+    let file = match SccsFile::from_bytes(&data) {
+        Ok(file) if verify_checksum(&data) => file,
+        _ => return CORRUPTED,
+    };
+
+    let mut code = 0;
+    if let Some(sid) = &options.sid {
+        match sid.parse::<Sid>() {
+            Err(_) => code |= INVALID_SID,
+            Ok(sid) => {
+                if file.find_sid(Some(&sid)).is_err() {
+                    code |= NONEXISTENT_SID;
+                }
+            }
+        }
+    }
+    if let Some(module_type) = &options.module_type {
+        if file.flag('t') != Some(module_type.as_str()) {
+            code |= TYPE_MISMATCH;
+        }
+    }
+    if let Some(module) = &options.module {
+        let actual = match file.flag('m') {
+            Some(name) => name.to_string(),
+            None => gfile_name(path).display().to_string(),
+        };
+        if *module != actual {
+            code |= MODULE_MISMATCH;
+        }
+    }
+    code
+}
+
+/// The message for each problem found with a file.
+fn messages(code: i32) -> Vec<String> {
+    [
+        (NOT_SCCS, "can't open file or file not SCCS"),
+        (CORRUPTED, "corrupted SCCS file"),
+        (INVALID_SID, "invalid delta (SID)"),
+        (NONEXISTENT_SID, "SID does not exist"),
+        (TYPE_MISMATCH, "%Y%, -y mismatch"),
+        (MODULE_MISMATCH, "%M%, -m mismatch"),
+    ]
+    .iter()
+    .filter(|(bit, _)| code & bit != 0)
+    .map(|(_, message)| gettext(*message))
+    .collect()
+}
+
+fn val(args: &[String]) -> i32 {
+    let (options, files, mut code) = parse_args(args);
+    if code & BAD_OPTION != 0 && !options.silent {
+        println!("{}", gettext("unknown or duplicate keyletter argument"));
+    }
+    if files.is_empty() {
+        if !options.silent {
+            println!("{}", gettext("missing file argument"));
+        }
+        return code | MISSING_FILE;
+    }
+    for file in &files {
+        let file_code = validate(Path::new(file), &options);
+        if !options.silent {
+            for message in messages(file_code) {
+                println!("{}: {}", file, message);
+            }
+        }
+        code |= file_code;
+    }
+    code
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let code = if args.len() == 1 && args[0] == "-" {
+        // Each line of standard input is a separate invocation.
+        let mut code = 0;
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+            code |= val(&args);
+        }
+        code
+    } else {
+        val(&args)
+    };
+
+    std::process::exit(code)
+}
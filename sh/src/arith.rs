@@ -0,0 +1,298 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Arithmetic expansion: C integer expressions on signed long integers,
+//! with assignment to shell variables.
+
+/// Access to the shell variables named in an expression.
+pub trait Variables {
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: String) -> Result<(), String>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+    End,
+}
+
+/// Operators, longest first.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=", "-=",
+    "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=",
+    "(", ")",
+];
+
+/// Parses an integer constant: decimal, octal with a leading 0, or
+/// hexadecimal with a leading 0x.
+pub fn parse_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    let value = value as i64;
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+struct Evaluator<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    token: Token,
+    vars: &'a mut dyn Variables,
+}
+
+type EvalResult = Result<i64, String>;
+
+impl Evaluator<'_> {
+    fn next(&mut self) -> Result<(), String> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        let Some(&c) = self.chars.get(self.pos) else {
+            self.token = Token::End;
+            return Ok(());
+        };
+        if c.is_ascii_digit() {
+            let start = self.pos;
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|c| c.is_ascii_alphanumeric())
+            {
+                self.pos += 1;
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            let value = parse_integer(&text).ok_or_else(|| format!("invalid number '{}'", text))?;
+            self.token = Token::Number(value);
+            return Ok(());
+        }
+        if c == '_' || c.is_ascii_alphabetic() {
+            let start = self.pos;
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|&c| c == '_' || c.is_ascii_alphanumeric())
+            {
+                self.pos += 1;
+            }
+            self.token = Token::Name(self.chars[start..self.pos].iter().collect());
+            return Ok(());
+        }
+        for op in OPERATORS {
+            let matches = op
+                .chars()
+                .enumerate()
+                .all(|(i, o)| self.chars.get(self.pos + i) == Some(&o));
+            if matches {
+                self.pos += op.len();
+                self.token = Token::Op(op);
+                return Ok(());
+            }
+        }
+        Err(format!("unexpected character '{}'", c))
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.token, Token::Op(o) if o == op)
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if !self.is_op(op) {
+            return Err(format!("expected '{}'", op));
+        }
+        self.next()
+    }
+
+    fn variable(&self, name: &str) -> EvalResult {
+        match self.vars.get(name) {
+            None => Ok(0),
+            Some(value) if value.trim().is_empty() => Ok(0),
+            Some(value) => parse_integer(value.trim())
+                .ok_or_else(|| format!("{}: invalid number '{}'", name, value)),
+        }
+    }
+
+    fn assignment(&mut self, eval: bool) -> EvalResult {
+        if let Token::Name(name) = self.token.clone() {
+            let (saved_pos, saved_token) = (self.pos, self.token.clone());
+            self.next()?;
+            if let Token::Op(op) = self.token {
+                if op.ends_with('=') && !matches!(op, "==" | "!=" | "<=" | ">=") {
+                    self.next()?;
+                    let value = self.assignment(eval)?;
+                    if !eval {
+                        return Ok(0);
+                    }
+                    let value = if op == "=" {
+                        value
+                    } else {
+                        let current = self.variable(&name)?;
+                        binary(&op[..op.len() - 1], current, value)?
+                    };
+                    self.vars.set(&name, value.to_string())?;
+                    return Ok(value);
+                }
+            }
+            self.pos = saved_pos;
+            self.token = saved_token;
+        }
+        self.conditional(eval)
+    }
+
+    fn conditional(&mut self, eval: bool) -> EvalResult {
+        let condition = self.binary_level(0, eval)?;
+        if !self.is_op("?") {
+            return Ok(condition);
+        }
+        self.next()?;
+        let then = self.assignment(eval && condition != 0)?;
+        self.expect(":")?;
+        let otherwise = self.conditional(eval && condition == 0)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary_level(&mut self, level: usize, eval: bool) -> EvalResult {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary(eval);
+        }
+        let mut left = self.binary_level(level + 1, eval)?;
+        loop {
+            let Token::Op(op) = self.token else {
+                return Ok(left);
+            };
+            if !LEVELS[level].contains(&op) {
+                return Ok(left);
+            }
+            self.next()?;
+            left = match op {
+                "&&" => {
+                    let right = self.binary_level(level + 1, eval && left != 0)?;
+                    (left != 0 && right != 0) as i64
+                }
+                "||" => {
+                    let right = self.binary_level(level + 1, eval && left == 0)?;
+                    (left != 0 || right != 0) as i64
+                }
+                _ => {
+                    let right = self.binary_level(level + 1, eval)?;
+                    if eval {
+                        binary(op, left, right)?
+                    } else {
+                        0
+                    }
+                }
+            };
+        }
+    }
+
+    fn unary(&mut self, eval: bool) -> EvalResult {
+        match self.token.clone() {
+            Token::Op(op @ ("+" | "-" | "~" | "!")) => {
+                self.next()?;
+                let value = self.unary(eval)?;
+                Ok(match op {
+                    "+" => value,
+                    "-" => value.wrapping_neg(),
+                    "~" => !value,
+                    _ => (value == 0) as i64,
+                })
+            }
+            Token::Op("(") => {
+                self.next()?;
+                let value = self.assignment(eval)?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Token::Number(n) => {
+                self.next()?;
+                Ok(n)
+            }
+            Token::Name(name) => {
+                self.next()?;
+                self.variable(&name)
+            }
+            Token::End => Err("expression expected".to_string()),
+            Token::Op(op) => Err(format!("unexpected '{}'", op)),
+        }
+    }
+}
+
+fn binary(op: &str, left: i64, right: i64) -> EvalResult {
+    Ok(match op {
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("division by zero".to_string()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        _ => unreachable!(),
+    })
+}
+
+/// Evaluates an arithmetic expression whose parameters have already been
+/// expanded.
+pub fn evaluate(expression: &str, vars: &mut dyn Variables) -> EvalResult {
+    let mut evaluator = Evaluator {
+        chars: expression.chars().collect(),
+        pos: 0,
+        token: Token::End,
+        vars,
+    };
+    evaluator.next()?;
+    if evaluator.token == Token::End {
+        return Ok(0);
+    }
+    let value = evaluator.assignment(true)?;
+    match evaluator.token {
+        Token::End => Ok(value),
+        Token::Op(op) => Err(format!("unexpected '{}'", op)),
+        _ => Err("syntax error in expression".to_string()),
+    }
+}
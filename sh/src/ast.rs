@@ -0,0 +1,159 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The syntax tree of the shell command language. Words are kept as they
+//! were written, quotes included, and are expanded when executed.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`, and `<<` or `<<-` with a here-document target
+    Input,
+    /// `>`
+    Output,
+    /// `>|`
+    Clobber,
+    /// `>>`
+    Append,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `<>`
+    ReadWrite,
+}
+
+#[derive(Debug)]
+pub struct HereDoc {
+    /// The body, read after the end of the line containing the operator.
+    pub body: RefCell<String>,
+
+    /// Part of the delimiter was quoted, so the body is not expanded.
+    pub quoted: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum RedirectTarget {
+    Word(String),
+    HereDoc(Rc<HereDoc>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    /// The file descriptor redirected; defaults to 0 or 1 by operator.
+    pub fd: Option<i32>,
+    pub op: RedirectOp,
+    pub target: RedirectTarget,
+}
+
+impl Redirect {
+    pub fn fd(&self) -> i32 {
+        self.fd.unwrap_or(match self.op {
+            RedirectOp::Input | RedirectOp::DupInput | RedirectOp::ReadWrite => 0,
+            _ => 1,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SimpleCommand {
+    /// `name=value` words preceding the command name.
+    pub assignments: Vec<String>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+
+    /// The item ended with `;&` rather than `;;`.
+    pub fallthrough: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum CompoundCommand {
+    Brace(List),
+    Subshell(List),
+    For {
+        name: String,
+        /// `None` when there is no `in`, meaning `"$@"`.
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    Case {
+        word: String,
+        items: Vec<CaseItem>,
+    },
+    If {
+        /// The `if` and `elif` conditions with their bodies.
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    While {
+        condition: List,
+        body: List,
+    },
+    Until {
+        condition: List,
+        body: List,
+    },
+}
+
+#[derive(Debug)]
+pub struct FunctionBody {
+    pub command: CompoundCommand,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    FunctionDefinition {
+        name: String,
+        body: Rc<FunctionBody>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    /// Preceded by `!`.
+    pub negated: bool,
+    pub commands: Vec<Command>,
+
+    /// The source text, for job control messages.
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AndOrOp {
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOp, Pipeline)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ListItem {
+    pub and_or: AndOr,
+
+    /// Terminated by `&`.
+    pub asynchronous: bool,
+}
+
+pub type List = Vec<ListItem>;
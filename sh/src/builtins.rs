@@ -0,0 +1,1326 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The builtin utilities.
+
+use crate::arith::parse_integer;
+use crate::exec::os_error;
+use crate::jobs::State;
+use crate::parser::{is_name, RESERVED_WORDS};
+use crate::shell::{read_line_fd, write_fd, ExecResult, Flow, Options, Shell, OPTIONS};
+use crate::trap::{self, SIGNALS};
+use plib::io::error_string;
+use plib::modestr::{self, ChmodMode};
+use std::path::{Path, PathBuf};
+
+const SPECIAL_BUILTINS: &[&str] = &[
+    "break", ":", "continue", ".", "eval", "exec", "exit", "export", "readonly", "return", "set",
+    "shift", "times", "trap", "unset",
+];
+
+const BUILTINS: &[&str] = &[
+    "alias", "bg", "cd", "command", "echo", "false", "fg", "getopts", "hash", "jobs", "kill",
+    "pwd", "read", "true", "type", "ulimit", "umask", "unalias", "wait",
+];
+
+pub fn is_special_builtin(name: &str) -> bool {
+    SPECIAL_BUILTINS.contains(&name)
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+/// Quotes a value so that the shell reads it back unchanged.
+pub fn quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./,:=+@%".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Removes `.` and `..` components from an absolute path without
+/// following symbolic links.
+fn logical_path(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(component),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+fn echo_escapes(text: &str, output: &mut String) -> bool {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('a') => output.push('\x07'),
+            Some('b') => output.push('\x08'),
+            Some('c') => return false,
+            Some('f') => output.push('\x0c'),
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some('v') => output.push('\x0b'),
+            Some('\\') => output.push('\\'),
+            Some('0') => {
+                let mut value = 0u32;
+                for _ in 0..3 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                output.push(char::from_u32(value).unwrap_or('?'));
+            }
+            Some(other) => {
+                output.push('\\');
+                output.push(other);
+            }
+            None => output.push('\\'),
+        }
+    }
+    true
+}
+
+fn format_time(ticks: libc::clock_t, per_second: f64) -> String {
+    let seconds = ticks as f64 / per_second;
+    format!("{}m{:.3}s", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+impl Shell {
+    fn out(&self, text: &str) -> i32 {
+        if write_fd(1, text.as_bytes()) {
+            0
+        } else {
+            self.print_error(&format!("write error: {}", os_error()));
+            1
+        }
+    }
+
+    /// Runs the builtin named by `args[0]`.
+    pub fn run_builtin(&mut self, args: &[String]) -> ExecResult {
+        match args[0].as_str() {
+            ":" | "true" => Ok(0),
+            "false" => Ok(1),
+            "." => self.builtin_dot(args),
+            "alias" => Ok(self.builtin_alias(args)),
+            "bg" | "fg" => Ok(self.builtin_fg_bg(args)),
+            "break" | "continue" => self.builtin_break(args),
+            "cd" => Ok(self.builtin_cd(args)),
+            "command" => Ok(self.builtin_command(args)),
+            "echo" => Ok(self.builtin_echo(args)),
+            "eval" => self.eval_string(&args[1..].join(" ")),
+            "exec" => self.builtin_exec(args),
+            "exit" => self.builtin_exit(args),
+            "export" | "readonly" => self.builtin_export(args),
+            "getopts" => Ok(self.builtin_getopts(args)),
+            "hash" => Ok(self.builtin_hash(args)),
+            "jobs" => Ok(self.builtin_jobs(args)),
+            "kill" => Ok(self.builtin_kill(args)),
+            "pwd" => Ok(self.builtin_pwd(args)),
+            "read" => Ok(self.builtin_read(args)),
+            "return" => self.builtin_return(args),
+            "set" => self.builtin_set(args),
+            "shift" => self.builtin_shift(args),
+            "times" => Ok(self.builtin_times()),
+            "trap" => self.builtin_trap(args),
+            "type" => Ok(self.builtin_type(args)),
+            "ulimit" => Ok(self.builtin_ulimit(args)),
+            "umask" => Ok(self.builtin_umask(args)),
+            "unalias" => Ok(self.builtin_unalias(args)),
+            "unset" => Ok(self.builtin_unset(args)),
+            "wait" => Ok(self.builtin_wait(args)),
+            name => {
+                self.print_error(&format!("{}: not a builtin", name));
+                Ok(1)
+            }
+        }
+    }
+
+    fn builtin_alias(&mut self, args: &[String]) -> i32 {
+        if args.len() == 1 {
+            let mut names: Vec<&String> = self.aliases.keys().collect();
+            names.sort();
+            let text: String = names
+                .iter()
+                .map(|name| format!("{}={}\n", name, quote(&self.aliases[*name])))
+                .collect();
+            return self.out(&text);
+        }
+        let mut status = 0;
+        for arg in &args[1..] {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    self.aliases.insert(name.to_string(), value.to_string());
+                }
+                None => match self.aliases.get(arg) {
+                    Some(value) => status |= self.out(&format!("{}={}\n", arg, quote(value))),
+                    None => {
+                        self.print_error(&format!("alias: {}: not found", arg));
+                        status = 1;
+                    }
+                },
+            }
+        }
+        status
+    }
+
+    fn builtin_unalias(&mut self, args: &[String]) -> i32 {
+        if args.get(1).is_some_and(|a| a == "-a") {
+            self.aliases.clear();
+            return 0;
+        }
+        let mut status = 0;
+        for name in &args[1..] {
+            if self.aliases.remove(name).is_none() {
+                self.print_error(&format!("unalias: {}: not found", name));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn builtin_break(&mut self, args: &[String]) -> ExecResult {
+        let n = match args.get(1) {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    self.print_error(&format!("{}: {}: invalid count", args[0], arg));
+                    return Err(Flow::Error(1));
+                }
+            },
+        };
+        if self.loop_depth == 0 {
+            return Ok(0);
+        }
+        let n = n.min(self.loop_depth);
+        Err(if args[0] == "break" {
+            Flow::Break(n)
+        } else {
+            Flow::Continue(n)
+        })
+    }
+
+    fn builtin_dot(&mut self, args: &[String]) -> ExecResult {
+        let Some(file) = args.get(1) else {
+            self.print_error(".: filename argument required");
+            return Err(Flow::Error(2));
+        };
+        let path = if file.contains('/') {
+            Some(PathBuf::from(file))
+        } else {
+            self.var("PATH")
+                .unwrap_or_default()
+                .split(':')
+                .find_map(|dir| {
+                    let dir = if dir.is_empty() { "." } else { dir };
+                    let path = Path::new(dir).join(file);
+                    path.is_file().then_some(path)
+                })
+        };
+        let text = match path.map(std::fs::read_to_string) {
+            Some(Ok(text)) => text,
+            Some(Err(err)) => {
+                self.print_error(&format!(".: {}: {}", file, error_string(&err)));
+                return Err(Flow::Error(1));
+            }
+            None => {
+                self.print_error(&format!(".: {}: not found", file));
+                return Err(Flow::Error(1));
+            }
+        };
+        let positional =
+            (args.len() > 2).then(|| std::mem::replace(&mut self.positional, args[2..].to_vec()));
+        self.dot_depth += 1;
+        let result = self.eval_string(&text);
+        self.dot_depth -= 1;
+        if let Some(positional) = positional {
+            self.positional = positional;
+        }
+        match result {
+            Err(Flow::Return(status)) => Ok(status),
+            result => result,
+        }
+    }
+
+    fn builtin_cd(&mut self, args: &[String]) -> i32 {
+        let mut physical = false;
+        let mut i = 1;
+        while let Some(arg) = args.get(i) {
+            match arg.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                "--" => {
+                    i += 1;
+                    break;
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        let mut print = false;
+        let dir = match args.get(i).map(String::as_str) {
+            None => match self.var("HOME") {
+                Some(home) if !home.is_empty() => home.to_string(),
+                _ => {
+                    self.print_error("cd: HOME not set");
+                    return 1;
+                }
+            },
+            Some("-") => match self.var("OLDPWD") {
+                Some(old) => {
+                    print = true;
+                    old.to_string()
+                }
+                None => {
+                    self.print_error("cd: OLDPWD not set");
+                    return 1;
+                }
+            },
+            Some(dir) => dir.to_string(),
+        };
+
+        let mut path = dir.clone();
+        let relative = !(dir.starts_with('/')
+            || dir == "."
+            || dir == ".."
+            || dir.starts_with("./")
+            || dir.starts_with("../"));
+        if relative {
+            if let Some(cdpath) = self.var("CDPATH") {
+                for entry in cdpath.split(':') {
+                    let candidate = if entry.is_empty() {
+                        format!("./{}", dir)
+                    } else {
+                        format!("{}/{}", entry.trim_end_matches('/'), dir)
+                    };
+                    if Path::new(&candidate).is_dir() {
+                        print |= !entry.is_empty();
+                        path = candidate;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let pwd = self.var("PWD").unwrap_or_default().to_string();
+        if !physical && !path.starts_with('/') {
+            path = if pwd.is_empty() {
+                match std::env::current_dir() {
+                    Ok(cwd) => format!("{}/{}", cwd.display(), path),
+                    Err(_) => path,
+                }
+            } else {
+                format!("{}/{}", pwd, path)
+            };
+        }
+        if !physical && path.starts_with('/') {
+            path = logical_path(&path);
+        }
+        if let Err(err) = std::env::set_current_dir(&path) {
+            self.print_error(&format!("cd: {}: {}", dir, error_string(&err)));
+            return 1;
+        }
+        let new_pwd = if physical {
+            std::env::current_dir()
+                .map(|d| d.to_string_lossy().into_owned())
+                .unwrap_or(path)
+        } else {
+            path
+        };
+        let _ = self.set_var("OLDPWD", pwd);
+        let _ = self.set_var("PWD", new_pwd.clone());
+        if print {
+            return self.out(&format!("{}\n", new_pwd));
+        }
+        0
+    }
+
+    fn builtin_pwd(&mut self, args: &[String]) -> i32 {
+        let physical = args[1..].iter().any(|a| a == "-P");
+        let cwd = match std::env::current_dir() {
+            Ok(cwd) => cwd,
+            Err(err) => {
+                self.print_error(&format!("pwd: {}", error_string(&err)));
+                return 1;
+            }
+        };
+        let logical = self.var("PWD").filter(|pwd| {
+            pwd.starts_with('/')
+                && !pwd.split('/').any(|c| c == "." || c == "..")
+                && std::fs::canonicalize(pwd).ok().as_ref() == Some(&cwd)
+        });
+        let dir = match logical {
+            Some(pwd) if !physical => pwd.to_string(),
+            _ => cwd.to_string_lossy().into_owned(),
+        };
+        self.out(&format!("{}\n", dir))
+    }
+
+    /// Describes how a command name would be found, for `command -v`,
+    /// `command -V` and `type`.
+    fn describe(&mut self, name: &str, verbose: bool) -> Option<String> {
+        if let Some(value) = self.aliases.get(name) {
+            return Some(if verbose {
+                format!("{} is an alias for {}", name, value)
+            } else {
+                format!("alias {}={}", name, quote(value))
+            });
+        }
+        let kind = if RESERVED_WORDS.contains(&name) {
+            Some("a reserved word")
+        } else if is_special_builtin(name) {
+            Some("a special shell builtin")
+        } else if self.functions.contains_key(name) {
+            Some("a function")
+        } else if is_builtin(name) {
+            Some("a shell builtin")
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            return Some(if verbose {
+                format!("{} is {}", name, kind)
+            } else {
+                name.to_string()
+            });
+        }
+        let path = self.find_utility(name)?;
+        if !path.is_file() {
+            return None;
+        }
+        let path = if path.is_absolute() {
+            path
+        } else {
+            std::env::current_dir().ok()?.join(path)
+        };
+        Some(if verbose {
+            format!("{} is {}", name, path.display())
+        } else {
+            path.display().to_string()
+        })
+    }
+
+    fn builtin_command(&mut self, args: &[String]) -> i32 {
+        let mut verbose = false;
+        let mut i = 1;
+        while let Some(arg) = args.get(i) {
+            match arg.as_str() {
+                "--" => {
+                    i += 1;
+                    break;
+                }
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    for c in arg[1..].chars() {
+                        match c {
+                            'v' => verbose = false,
+                            'V' => verbose = true,
+                            'p' => {}
+                            _ => {
+                                self.print_error(&format!("command: -{}: invalid option", c));
+                                return 2;
+                            }
+                        }
+                    }
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        let mut status = 0;
+        for name in &args[i..] {
+            match self.describe(name, verbose) {
+                Some(description) => status |= self.out(&format!("{}\n", description)),
+                None => {
+                    if verbose {
+                        self.print_error(&format!("{}: not found", name));
+                    }
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    fn builtin_type(&mut self, args: &[String]) -> i32 {
+        let mut status = 0;
+        for name in &args[1..] {
+            match self.describe(name, true) {
+                Some(description) => status |= self.out(&format!("{}\n", description)),
+                None => {
+                    self.print_error(&format!("type: {}: not found", name));
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    fn builtin_echo(&mut self, args: &[String]) -> i32 {
+        let mut operands = &args[1..];
+        let mut newline = true;
+        if operands.first().is_some_and(|a| a == "-n") {
+            newline = false;
+            operands = &operands[1..];
+        }
+        let mut output = String::new();
+        if !echo_escapes(&operands.join(" "), &mut output) {
+            newline = false;
+        }
+        if newline {
+            output.push('\n');
+        }
+        self.out(&output)
+    }
+
+    fn builtin_exec(&mut self, args: &[String]) -> ExecResult {
+        let operands = match args.get(1) {
+            Some(arg) if arg == "--" => &args[2..],
+            _ => &args[1..],
+        };
+        if operands.is_empty() {
+            return Ok(0);
+        }
+        let status = self.exec_utility(operands);
+        if self.options.interactive && !self.subshell {
+            Ok(status)
+        } else {
+            Err(Flow::Exit(status))
+        }
+    }
+
+    fn builtin_exit(&mut self, args: &[String]) -> ExecResult {
+        let status = match args.get(1) {
+            None => self.last_status,
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    self.print_error(&format!("exit: {}: numeric argument required", arg));
+                    2
+                }
+            },
+        };
+        Err(Flow::Exit(status))
+    }
+
+    fn builtin_return(&mut self, args: &[String]) -> ExecResult {
+        if self.function_depth == 0 && self.dot_depth == 0 {
+            self.print_error("return: not in a function or dot script");
+            return Ok(1);
+        }
+        let status = match args.get(1) {
+            None => self.last_status,
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    self.print_error(&format!("return: {}: numeric argument required", arg));
+                    return Err(Flow::Error(2));
+                }
+            },
+        };
+        Err(Flow::Return(status))
+    }
+
+    fn builtin_export(&mut self, args: &[String]) -> ExecResult {
+        let readonly = args[0] == "readonly";
+        let mut operands = &args[1..];
+        let mut print = false;
+        while let Some(arg) = operands.first() {
+            match arg.as_str() {
+                "-p" => print = true,
+                "--" => {
+                    operands = &operands[1..];
+                    break;
+                }
+                _ => break,
+            }
+            operands = &operands[1..];
+        }
+        if operands.is_empty() || print && operands.is_empty() {
+            let mut names: Vec<&String> = self
+                .vars
+                .iter()
+                .filter(|(_, v)| if readonly { v.readonly } else { v.exported })
+                .map(|(name, _)| name)
+                .collect();
+            names.sort();
+            let text: String = names
+                .iter()
+                .map(|name| match &self.vars[*name].value {
+                    Some(value) => format!("{} {}={}\n", args[0], name, quote(value)),
+                    None => format!("{} {}\n", args[0], name),
+                })
+                .collect();
+            return Ok(self.out(&text));
+        }
+        let mut status = 0;
+        for operand in operands {
+            let (name, value) = match operand.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (operand.as_str(), None),
+            };
+            if !is_name(name) {
+                self.print_error(&format!("{}: {}: bad variable name", args[0], name));
+                status = 1;
+                continue;
+            }
+            if let Some(value) = value {
+                if let Err(message) = self.set_var(name, value) {
+                    self.print_error(&format!("{}: {}", args[0], message));
+                    return Err(Flow::Error(1));
+                }
+            }
+            let var = self.vars.entry(name.to_string()).or_default();
+            if readonly {
+                var.readonly = true;
+            } else {
+                var.exported = true;
+            }
+        }
+        Ok(status)
+    }
+
+    fn set_option(&mut self, name: &str, value: bool) -> bool {
+        if !self.options.set(name, value) {
+            return false;
+        }
+        if name == "monitor" && value && self.tty < 0 {
+            self.init_job_control();
+        }
+        true
+    }
+
+    fn builtin_set(&mut self, args: &[String]) -> ExecResult {
+        if args.len() == 1 {
+            let mut names: Vec<&String> = self.vars.keys().collect();
+            names.sort();
+            let text: String = names
+                .iter()
+                .filter_map(|name| {
+                    let value = self.vars[*name].value.as_ref()?;
+                    Some(format!("{}={}\n", name, quote(value)))
+                })
+                .collect();
+            return Ok(self.out(&text));
+        }
+        let mut i = 1;
+        while i < args.len() {
+            let arg = &args[i];
+            if arg == "--" {
+                self.positional = args[i + 1..].to_vec();
+                return Ok(0);
+            }
+            if arg == "-" {
+                self.options.xtrace = false;
+                self.options.verbose = false;
+                i += 1;
+                break;
+            }
+            let (value, letters) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+                (Some(letters), _) => (true, letters),
+                (_, Some(letters)) => (false, letters),
+                _ => break,
+            };
+            for letter in letters.chars() {
+                if letter == 'o' {
+                    match args.get(i + 1) {
+                        Some(name) => {
+                            i += 1;
+                            if !self.set_option(name, value) {
+                                self.print_error(&format!("set: {}: invalid option name", name));
+                                return Err(Flow::Error(2));
+                            }
+                        }
+                        None => {
+                            let text: String = OPTIONS
+                                .iter()
+                                .map(|(_, name)| {
+                                    let on = self.options.get(name);
+                                    if value {
+                                        format!("{:15} {}\n", name, if on { "on" } else { "off" })
+                                    } else {
+                                        format!("set {}o {}\n", if on { '-' } else { '+' }, name)
+                                    }
+                                })
+                                .collect();
+                            self.out(&text);
+                        }
+                    }
+                    continue;
+                }
+                match Options::name_of(letter) {
+                    Some(name) => {
+                        self.set_option(name, value);
+                    }
+                    None => {
+                        self.print_error(&format!("set: -{}: invalid option", letter));
+                        return Err(Flow::Error(2));
+                    }
+                }
+            }
+            i += 1;
+        }
+        if i < args.len() {
+            self.positional = args[i..].to_vec();
+        }
+        Ok(0)
+    }
+
+    fn builtin_shift(&mut self, args: &[String]) -> ExecResult {
+        let n = match args.get(1) {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(n) => n,
+                Err(_) => {
+                    self.print_error(&format!("shift: {}: numeric argument required", arg));
+                    return Err(Flow::Error(2));
+                }
+            },
+        };
+        if n > self.positional.len() {
+            self.print_error(&format!("shift: {}: shift count out of range", n));
+            return Err(Flow::Error(1));
+        }
+        self.positional.drain(..n);
+        Ok(0)
+    }
+
+    fn builtin_times(&mut self) -> i32 {
+        let mut times: libc::tms = unsafe { std::mem::zeroed() };
+        unsafe { libc::times(&mut times) };
+        let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+        self.out(&format!(
+            "{} {}\n{} {}\n",
+            format_time(times.tms_utime, per_second),
+            format_time(times.tms_stime, per_second),
+            format_time(times.tms_cutime, per_second),
+            format_time(times.tms_cstime, per_second)
+        ))
+    }
+
+    fn builtin_trap(&mut self, args: &[String]) -> ExecResult {
+        let mut operands = &args[1..];
+        if operands.first().is_some_and(|a| a == "--") {
+            operands = &operands[1..];
+        }
+        if operands.is_empty() || operands[0] == "-p" {
+            let text: String = self
+                .traps
+                .iter()
+                .map(|(signal, action)| {
+                    let name = if *signal == 0 {
+                        "EXIT"
+                    } else {
+                        trap::signal_name(*signal).unwrap_or("?")
+                    };
+                    format!("trap -- {} {}\n", quote(action), name)
+                })
+                .collect();
+            return Ok(self.out(&text));
+        }
+        if operands[0] == "-l" {
+            let text: String = SIGNALS
+                .iter()
+                .map(|(name, number)| format!("{}) SIG{}\n", number, name))
+                .collect();
+            return Ok(self.out(&text));
+        }
+        let first = &operands[0];
+        let (action, signals) = if first.chars().all(|c| c.is_ascii_digit()) {
+            (None, operands)
+        } else if first == "-" {
+            (None, &operands[1..])
+        } else {
+            (Some(first.clone()), &operands[1..])
+        };
+        if signals.is_empty() {
+            self.print_error("trap: condition expected");
+            return Err(Flow::Error(2));
+        }
+        let mut status = 0;
+        for name in signals {
+            match trap::parse_signal(name) {
+                Some(signal) if signal != libc::SIGKILL && signal != libc::SIGSTOP => {
+                    self.set_trap(signal, action.clone());
+                }
+                _ => {
+                    self.print_error(&format!("trap: {}: bad trap", name));
+                    status = 1;
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    fn builtin_unset(&mut self, args: &[String]) -> i32 {
+        let mut functions = false;
+        let mut variables = false;
+        let mut operands = &args[1..];
+        while let Some(arg) = operands.first() {
+            match arg.as_str() {
+                "-f" => functions = true,
+                "-v" => variables = true,
+                "--" => {
+                    operands = &operands[1..];
+                    break;
+                }
+                _ => break,
+            }
+            operands = &operands[1..];
+        }
+        let mut status = 0;
+        for name in operands {
+            if functions {
+                self.functions.remove(name);
+                continue;
+            }
+            if !variables && !self.vars.contains_key(name) && self.functions.remove(name).is_some()
+            {
+                continue;
+            }
+            if let Err(message) = self.unset_var(name) {
+                self.print_error(&format!("unset: {}", message));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn builtin_getopts(&mut self, args: &[String]) -> i32 {
+        if args.len() < 3 {
+            self.print_error("getopts: usage: getopts optstring name [arg ...]");
+            return 2;
+        }
+        let optstring = &args[1];
+        let name = &args[2];
+        let params = if args.len() > 3 {
+            args[3..].to_vec()
+        } else {
+            self.positional.clone()
+        };
+        let silent = optstring.starts_with(':');
+
+        let optind_text = self.var("OPTIND").unwrap_or("1").to_string();
+        if optind_text != self.getopts_optind {
+            self.getopts_char = 0;
+        }
+        let mut optind: usize = optind_text.parse().unwrap_or(1).max(1);
+
+        let finish = |shell: &mut Shell, optind: usize, option: &str, arg: Option<String>| {
+            let mut status = 0;
+            if let Err(message) = shell.set_var(name, option.to_string()) {
+                shell.print_error(&format!("getopts: {}", message));
+                status = 2;
+            }
+            match arg {
+                Some(arg) => {
+                    let _ = shell.set_var("OPTARG", arg);
+                }
+                None => {
+                    let _ = shell.unset_var("OPTARG");
+                }
+            }
+            shell.getopts_optind = optind.to_string();
+            let _ = shell.set_var("OPTIND", optind.to_string());
+            status
+        };
+
+        if self.getopts_char == 0 {
+            match params.get(optind - 1) {
+                Some(arg) if arg == "--" => {
+                    finish(self, optind + 1, "?", None);
+                    return 1;
+                }
+                Some(arg) if arg.starts_with('-') && arg.len() > 1 => self.getopts_char = 1,
+                _ => {
+                    finish(self, optind, "?", None);
+                    return 1;
+                }
+            }
+        }
+
+        let arg: Vec<char> = params[optind - 1].chars().collect();
+        let option = arg[self.getopts_char];
+        self.getopts_char += 1;
+        let rest: String = arg[self.getopts_char..].iter().collect();
+        if rest.is_empty() {
+            optind += 1;
+            self.getopts_char = 0;
+        }
+
+        let spec = optstring
+            .char_indices()
+            .find(|&(_, c)| c == option && c != ':');
+        let Some((pos, _)) = spec else {
+            if silent {
+                return finish(self, optind, "?", Some(option.to_string()));
+            }
+            self.print_error(&format!("getopts: illegal option -- {}", option));
+            return finish(self, optind, "?", None);
+        };
+        if optstring[pos + option.len_utf8()..].starts_with(':') {
+            let value = if !rest.is_empty() {
+                optind += 1;
+                self.getopts_char = 0;
+                Some(rest)
+            } else if let Some(next) = params.get(optind - 1) {
+                optind += 1;
+                Some(next.clone())
+            } else {
+                None
+            };
+            return match value {
+                Some(value) => finish(self, optind, &option.to_string(), Some(value)),
+                None if silent => finish(self, optind, ":", Some(option.to_string())),
+                None => {
+                    self.print_error(&format!(
+                        "getopts: option requires an argument -- {}",
+                        option
+                    ));
+                    finish(self, optind, "?", None)
+                }
+            };
+        }
+        finish(self, optind, &option.to_string(), None)
+    }
+
+    fn builtin_hash(&mut self, args: &[String]) -> i32 {
+        if args.len() == 1 {
+            let mut entries: Vec<(&String, &PathBuf)> = self.hash.iter().collect();
+            entries.sort();
+            let text: String = entries
+                .iter()
+                .map(|(_, path)| format!("{}\n", path.display()))
+                .collect();
+            return self.out(&text);
+        }
+        let mut status = 0;
+        for name in &args[1..] {
+            if name == "-r" {
+                self.hash.clear();
+                continue;
+            }
+            if is_builtin(name) || is_special_builtin(name) || self.functions.contains_key(name) {
+                continue;
+            }
+            self.hash.remove(name);
+            if self.find_utility(name).is_none() {
+                self.print_error(&format!("hash: {}: not found", name));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn builtin_read(&mut self, args: &[String]) -> i32 {
+        let mut raw = false;
+        let mut delimiter = b'\n';
+        let mut i = 1;
+        while let Some(arg) = args.get(i) {
+            match arg.as_str() {
+                "-r" => raw = true,
+                "-d" => {
+                    i += 1;
+                    delimiter = args.get(i).and_then(|d| d.bytes().next()).unwrap_or(0);
+                }
+                "--" => {
+                    i += 1;
+                    break;
+                }
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    self.print_error(&format!("read: {}: invalid option", arg));
+                    return 2;
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        let names: Vec<String> = if i < args.len() {
+            args[i..].to_vec()
+        } else {
+            vec!["REPLY".to_string()]
+        };
+
+        // Read the line, noting which characters a backslash quoted.
+        let mut bytes = Vec::new();
+        let mut quoted = Vec::new();
+        let mut eof = false;
+        loop {
+            let Some(chunk) = read_line_fd(0, delimiter) else {
+                eof = true;
+                break;
+            };
+            let mut complete = chunk.last() == Some(&delimiter);
+            let chunk = if complete {
+                &chunk[..chunk.len() - 1]
+            } else {
+                eof = true;
+                &chunk[..]
+            };
+            let mut j = 0;
+            let mut continued = false;
+            while j < chunk.len() {
+                if chunk[j] == b'\\' && !raw {
+                    match chunk.get(j + 1) {
+                        Some(&b) => {
+                            bytes.push(b);
+                            quoted.push(true);
+                            j += 2;
+                        }
+                        None => {
+                            // A backslash before the delimiter continues
+                            // the line.
+                            continued = complete;
+                            j += 1;
+                        }
+                    }
+                    continue;
+                }
+                bytes.push(chunk[j]);
+                quoted.push(false);
+                j += 1;
+            }
+            if !continued {
+                break;
+            }
+            complete = false;
+            let _ = complete;
+        }
+
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let chars: Vec<(char, bool)> = if text.len() == bytes.len() {
+            text.char_indices().map(|(k, c)| (c, quoted[k])).collect()
+        } else {
+            text.chars().map(|c| (c, false)).collect()
+        };
+
+        let ifs = self.ifs();
+        let is_ifs = |&(c, q): &(char, bool)| !q && ifs.contains(c);
+        let is_white = |p: &(char, bool)| is_ifs(p) && matches!(p.0, ' ' | '\t' | '\n');
+        let mut pos = 0;
+        let mut status = if eof { 1 } else { 0 };
+        for (index, name) in names.iter().enumerate() {
+            while pos < chars.len() && is_white(&chars[pos]) {
+                pos += 1;
+            }
+            let value: String = if index + 1 == names.len() {
+                let mut end = chars.len();
+                while end > pos && is_white(&chars[end - 1]) {
+                    end -= 1;
+                }
+                let value = chars[pos..end].iter().map(|(c, _)| c).collect();
+                pos = end;
+                value
+            } else {
+                let start = pos;
+                while pos < chars.len() && !is_ifs(&chars[pos]) {
+                    pos += 1;
+                }
+                let value = chars[start..pos].iter().map(|(c, _)| c).collect();
+                while pos < chars.len() && is_white(&chars[pos]) {
+                    pos += 1;
+                }
+                if pos < chars.len() && is_ifs(&chars[pos]) {
+                    pos += 1;
+                }
+                value
+            };
+            if let Err(message) = self.set_var(name, value) {
+                self.print_error(&format!("read: {}", message));
+                status = 2;
+            }
+        }
+        status
+    }
+
+    fn builtin_umask(&mut self, args: &[String]) -> i32 {
+        let symbolic = args.get(1).is_some_and(|a| a == "-S");
+        let operand = args.iter().skip(1).find(|a| *a != "-S");
+        let mask = unsafe {
+            let mask = libc::umask(0);
+            libc::umask(mask);
+            mask as u32
+        };
+        let Some(operand) = operand else {
+            if !symbolic {
+                return self.out(&format!("{:04o}\n", mask));
+            }
+            let allowed = !mask & 0o777;
+            let who = |shift: u32| {
+                let bits = allowed >> shift;
+                let mut perms = String::new();
+                for (bit, c) in [(4, 'r'), (2, 'w'), (1, 'x')] {
+                    if bits & bit != 0 {
+                        perms.push(c);
+                    }
+                }
+                perms
+            };
+            return self.out(&format!("u={},g={},o={}\n", who(6), who(3), who(0)));
+        };
+        let new_mask = match modestr::parse(operand) {
+            Ok(ChmodMode::Absolute(mode)) => mode & 0o777,
+            Ok(ChmodMode::Symbolic(symbolic)) => !modestr::mutate(!mask & 0o777, &symbolic) & 0o777,
+            Err(err) => {
+                self.print_error(&format!("umask: {}: {}", operand, err));
+                return 1;
+            }
+        };
+        unsafe { libc::umask(new_mask as libc::mode_t) };
+        0
+    }
+
+    fn builtin_ulimit(&mut self, args: &[String]) -> i32 {
+        let operand = args[1..].iter().find(|a| *a != "-f");
+        if let Some(option) = operand.filter(|a| a.starts_with('-')) {
+            self.print_error(&format!("ulimit: {}: invalid option", option));
+            return 2;
+        }
+        let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+        unsafe { libc::getrlimit(libc::RLIMIT_FSIZE, &mut limit) };
+        let Some(operand) = operand else {
+            let text = if limit.rlim_cur == libc::RLIM_INFINITY {
+                "unlimited\n".to_string()
+            } else {
+                format!("{}\n", limit.rlim_cur / 512)
+            };
+            return self.out(&text);
+        };
+        let value = if operand == "unlimited" {
+            libc::RLIM_INFINITY
+        } else {
+            match operand.parse::<libc::rlim_t>() {
+                Ok(blocks) => blocks.saturating_mul(512),
+                Err(_) => {
+                    self.print_error(&format!("ulimit: {}: invalid limit", operand));
+                    return 1;
+                }
+            }
+        };
+        limit.rlim_cur = value;
+        limit.rlim_max = value;
+        if unsafe { libc::setrlimit(libc::RLIMIT_FSIZE, &limit) } < 0 {
+            self.print_error(&format!("ulimit: {}", os_error()));
+            return 1;
+        }
+        0
+    }
+
+    fn builtin_jobs(&mut self, args: &[String]) -> i32 {
+        let mut long = false;
+        let mut pids = false;
+        let mut specs = Vec::new();
+        for arg in &args[1..] {
+            match arg.as_str() {
+                "-l" => long = true,
+                "-p" => pids = true,
+                _ => specs.push(arg.clone()),
+            }
+        }
+        self.jobs.reap();
+        let ids: Vec<usize> = if specs.is_empty() {
+            self.jobs.list.iter().map(|j| j.id).collect()
+        } else {
+            let mut ids = Vec::new();
+            for spec in &specs {
+                match self.jobs.find(spec) {
+                    Ok(id) => ids.push(id),
+                    Err(message) => {
+                        self.print_error(&format!("jobs: {}", message));
+                        return 1;
+                    }
+                }
+            }
+            ids
+        };
+        let mut text = String::new();
+        for &id in &ids {
+            let job = self.jobs.get(id).unwrap();
+            if pids {
+                text.push_str(&format!("{}\n", job.pgid));
+            } else {
+                text.push_str(&self.jobs.format(job, long));
+            }
+        }
+        let status = self.out(&text);
+        for id in ids {
+            if matches!(self.jobs.get(id).map(|j| j.state), Some(State::Done(_))) {
+                self.jobs.remove(id);
+            }
+        }
+        status
+    }
+
+    fn builtin_fg_bg(&mut self, args: &[String]) -> i32 {
+        let foreground = args[0] == "fg";
+        if !self.options.monitor {
+            self.print_error(&format!("{}: no job control", args[0]));
+            return 1;
+        }
+        let specs: Vec<String> = if args.len() > 1 {
+            args[1..].to_vec()
+        } else {
+            vec!["%+".to_string()]
+        };
+        let mut status = 0;
+        for spec in &specs {
+            match self.jobs.find(spec) {
+                Ok(id) => status = self.continue_job(id, foreground),
+                Err(message) => {
+                    self.print_error(&format!("{}: {}", args[0], message));
+                    status = 1;
+                }
+            }
+            if foreground {
+                break;
+            }
+        }
+        status
+    }
+
+    fn builtin_wait(&mut self, args: &[String]) -> i32 {
+        if args.len() == 1 {
+            let ids: Vec<usize> = self.jobs.list.iter().map(|j| j.id).collect();
+            for id in ids {
+                let status = self.wait_job(id);
+                if status > 128 && self.trap_pending().is_some() {
+                    return status;
+                }
+            }
+            return 0;
+        }
+        let mut status = 0;
+        for spec in &args[1..] {
+            status = match self.jobs.find(spec) {
+                Ok(id) => self.wait_job(id),
+                Err(message) => {
+                    if spec.starts_with('%') {
+                        self.print_error(&format!("wait: {}", message));
+                    }
+                    127
+                }
+            };
+            if status > 128 && self.trap_pending().is_some() {
+                break;
+            }
+        }
+        status
+    }
+
+    fn builtin_kill(&mut self, args: &[String]) -> i32 {
+        let mut signal = libc::SIGTERM;
+        let mut operands = &args[1..];
+        match operands.first().map(String::as_str) {
+            Some("-l") => {
+                if let Some(status) = operands.get(1) {
+                    let number = status.parse::<i32>().unwrap_or(-1);
+                    let number = if number > 128 { number - 128 } else { number };
+                    return match trap::signal_name(number) {
+                        Some(name) => self.out(&format!("{}\n", name)),
+                        None => {
+                            self.print_error(&format!("kill: {}: invalid signal", status));
+                            1
+                        }
+                    };
+                }
+                let names: Vec<&str> = SIGNALS.iter().map(|(name, _)| *name).collect();
+                return self.out(&format!("{}\n", names.join(" ")));
+            }
+            Some("-s" | "-n") => {
+                let Some(name) = operands.get(1) else {
+                    self.print_error("kill: option requires an argument");
+                    return 2;
+                };
+                match trap::parse_signal(name) {
+                    Some(s) => signal = s,
+                    None => {
+                        self.print_error(&format!("kill: {}: invalid signal", name));
+                        return 2;
+                    }
+                }
+                operands = &operands[2..];
+            }
+            Some("--") => operands = &operands[1..],
+            Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+                match trap::parse_signal(&arg[1..]) {
+                    Some(s) => signal = s,
+                    None => {
+                        self.print_error(&format!("kill: {}: invalid signal", &arg[1..]));
+                        return 2;
+                    }
+                }
+                operands = &operands[1..];
+                if operands.first().is_some_and(|a| a == "--") {
+                    operands = &operands[1..];
+                }
+            }
+            _ => {}
+        }
+        if operands.is_empty() {
+            self.print_error("kill: usage: kill [-s signal] pid...");
+            return 2;
+        }
+        let mut status = 0;
+        for operand in operands {
+            let pid = if operand.starts_with('%') {
+                match self.jobs.find(operand) {
+                    Ok(id) => {
+                        let pgid = self.jobs.get(id).unwrap().pgid;
+                        if self.options.monitor {
+                            -pgid
+                        } else {
+                            pgid
+                        }
+                    }
+                    Err(message) => {
+                        self.print_error(&format!("kill: {}", message));
+                        status = 1;
+                        continue;
+                    }
+                }
+            } else {
+                match parse_integer(operand) {
+                    Some(pid) => pid as libc::pid_t,
+                    None => {
+                        self.print_error(&format!("kill: {}: invalid process id", operand));
+                        status = 1;
+                        continue;
+                    }
+                }
+            };
+            if unsafe { libc::kill(pid, signal) } < 0 {
+                self.print_error(&format!("kill: {}: {}", operand, os_error()));
+                status = 1;
+            } else if signal == libc::SIGCONT || signal == libc::SIGKILL || signal == libc::SIGTERM
+            {
+                self.jobs.reap();
+            }
+        }
+        status
+    }
+}
@@ -0,0 +1,948 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Running commands: lists, pipelines, compound commands, functions,
+//! builtins and utilities, and redirections.
+
+use crate::ast::*;
+use crate::builtins::{is_builtin, is_special_builtin};
+use crate::jobs::{Jobs, Process, State};
+use crate::parser::assignment_position;
+use crate::shell::{write_fd, ExecResult, Flow, Shell, Variable};
+use crate::trap;
+use plib::io::error_string;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The utilities searched for when `command -p` is used.
+const DEFAULT_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// File descriptors replaced by redirections, with the copies that restore
+/// them (-1 if they were closed).
+pub type SavedFds = Vec<(i32, i32)>;
+
+/// The message of the error of the last system call.
+pub fn os_error() -> String {
+    error_string(&std::io::Error::last_os_error())
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::X_OK) == 0 }
+}
+
+/// Searches PATH for a utility.
+fn search_path(name: &str, path: &str) -> Option<PathBuf> {
+    let mut found = None;
+    for dir in path.split(':') {
+        let dir = if dir.is_empty() { "." } else { dir };
+        let candidate = Path::new(dir).join(name);
+        if candidate.is_file() {
+            if is_executable(&candidate) {
+                return Some(candidate);
+            }
+            found.get_or_insert(candidate);
+        }
+    }
+    found
+}
+
+/// Writes the text of a here-document to an unlinked temporary file,
+/// returning a descriptor open on it for reading.
+fn here_doc_fd(text: &str) -> Result<i32, String> {
+    let dir = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let template = CString::new(format!("{}/shXXXXXX", dir)).unwrap();
+    let mut template = template.into_bytes_with_nul();
+    let fd = unsafe { libc::mkstemp(template.as_mut_ptr() as *mut libc::c_char) };
+    if fd < 0 {
+        return Err(format!("cannot create here-document: {}", os_error()));
+    }
+    unsafe {
+        libc::unlink(template.as_ptr() as *const libc::c_char);
+    }
+    let ok = write_fd(fd, text.as_bytes());
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+    if !ok {
+        unsafe { libc::close(fd) };
+        return Err(format!("cannot write here-document: {}", os_error()));
+    }
+    Ok(fd)
+}
+
+fn and_or_text(and_or: &AndOr) -> String {
+    let mut text = and_or.first.text.clone();
+    for (op, pipeline) in &and_or.rest {
+        text.push_str(match op {
+            AndOrOp::And => " && ",
+            AndOrOp::Or => " || ",
+        });
+        text.push_str(&pipeline.text);
+    }
+    text
+}
+
+impl Shell {
+    /// Prepares the state of a child process running a subshell.
+    pub fn enter_subshell(&mut self) {
+        self.subshell = true;
+        self.reset_traps_for_subshell();
+        self.options.monitor = false;
+        self.jobs = Jobs::default();
+    }
+
+    /// Forks a child process. Under job control the child is put in the
+    /// process group `pgid`, or a new one, and given the terminal if it is
+    /// in the foreground. Returns 0 in the child.
+    fn fork(&mut self, pgid: Option<libc::pid_t>, foreground: bool) -> Result<libc::pid_t, Flow> {
+        let job_control = self.options.monitor && !self.subshell;
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            self.print_error(&format!("fork: {}", os_error()));
+            return Err(Flow::Error(2));
+        }
+        if job_control {
+            let (process, group) = if pid == 0 {
+                (0, pgid.unwrap_or_else(|| unsafe { libc::getpid() }))
+            } else {
+                (pid, pgid.unwrap_or(pid))
+            };
+            unsafe { libc::setpgid(process, group) };
+            if foreground {
+                self.give_terminal(group);
+            }
+        }
+        if pid == 0 {
+            self.enter_subshell();
+        }
+        Ok(pid)
+    }
+
+    /// Ends a child process with the status of what it ran.
+    fn child_exit(&mut self, result: ExecResult) -> ! {
+        let status = match result {
+            Ok(status) => status,
+            Err(Flow::Exit(status) | Flow::Error(status) | Flow::Return(status)) => status,
+            Err(_) => self.last_status,
+        };
+        self.exit(status)
+    }
+
+    pub fn expansion_error(&mut self, message: String) -> Flow {
+        self.print_error(&message);
+        Flow::Error(2)
+    }
+
+    pub fn exec_list(&mut self, list: &List) -> ExecResult {
+        let mut status = 0;
+        for item in list {
+            if item.asynchronous {
+                self.exec_async(&item.and_or)?;
+                status = 0;
+            } else {
+                status = self.exec_and_or(&item.and_or)?;
+            }
+            self.run_pending_traps()?;
+        }
+        Ok(status)
+    }
+
+    fn exec_async(&mut self, and_or: &AndOr) -> Result<(), Flow> {
+        let job_control = self.options.monitor && !self.subshell;
+        let text = and_or_text(and_or);
+        let pid = self.fork(None, false)?;
+        if pid == 0 {
+            if !job_control {
+                for signal in [libc::SIGINT, libc::SIGQUIT] {
+                    trap::ignore(signal);
+                    self.ignored_on_entry.push(signal);
+                }
+                let null = CString::new("/dev/null").unwrap();
+                let fd = unsafe { libc::open(null.as_ptr(), libc::O_RDONLY) };
+                if fd > 0 {
+                    unsafe {
+                        libc::dup2(fd, 0);
+                        libc::close(fd);
+                    }
+                }
+            }
+            let result = self.exec_and_or(and_or);
+            self.child_exit(result);
+        }
+        let process = Process {
+            pid,
+            status: None,
+            stopped: None,
+        };
+        let id = self.jobs.add(pid, vec![process], &text, State::Running);
+        self.last_async = Some(pid);
+        if self.options.interactive && !self.subshell {
+            write_fd(2, format!("[{}] {}\n", id, pid).as_bytes());
+        }
+        Ok(())
+    }
+
+    fn exec_and_or(&mut self, and_or: &AndOr) -> ExecResult {
+        let total = and_or.rest.len();
+        let mut status = self.exec_checked_pipeline(&and_or.first, total == 0)?;
+        for (i, (op, pipeline)) in and_or.rest.iter().enumerate() {
+            let run = match op {
+                AndOrOp::And => status == 0,
+                AndOrOp::Or => status != 0,
+            };
+            if run {
+                status = self.exec_checked_pipeline(pipeline, i + 1 == total)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Runs a pipeline and exits if it fails under `errexit`, unless it is
+    /// negated or not the last of an AND-OR list.
+    fn exec_checked_pipeline(&mut self, pipeline: &Pipeline, last: bool) -> ExecResult {
+        let exempt = !last || pipeline.negated;
+        if exempt {
+            self.errexit_suppressed += 1;
+        }
+        let result = self.exec_pipeline(pipeline);
+        if exempt {
+            self.errexit_suppressed -= 1;
+        }
+        let status = result?;
+        if !exempt && status != 0 && self.options.errexit && self.errexit_suppressed == 0 {
+            return Err(Flow::Exit(status));
+        }
+        Ok(status)
+    }
+
+    /// Runs commands whose failure does not trigger `errexit`.
+    fn exec_condition(&mut self, list: &List) -> ExecResult {
+        self.errexit_suppressed += 1;
+        let result = self.exec_list(list);
+        self.errexit_suppressed -= 1;
+        result
+    }
+
+    fn exec_pipeline(&mut self, pipeline: &Pipeline) -> ExecResult {
+        let status = if pipeline.commands.len() == 1 {
+            self.exec_command(&pipeline.commands[0], &pipeline.text, false)?
+        } else {
+            self.exec_multiple(pipeline)?
+        };
+        let status = if pipeline.negated {
+            (status == 0) as i32
+        } else {
+            status
+        };
+        self.last_status = status;
+        Ok(status)
+    }
+
+    /// Runs the commands of a pipeline in child processes connected by
+    /// pipes.
+    fn exec_multiple(&mut self, pipeline: &Pipeline) -> ExecResult {
+        let count = pipeline.commands.len();
+        let mut pids = Vec::new();
+        let mut pgid = None;
+        let mut input = -1;
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            let mut fds = [-1; 2];
+            if i + 1 < count && unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+                self.print_error(&format!("pipe: {}", os_error()));
+                return Err(Flow::Error(2));
+            }
+            let pid = self.fork(pgid, true)?;
+            if pid == 0 {
+                unsafe {
+                    if input >= 0 {
+                        libc::dup2(input, 0);
+                        libc::close(input);
+                    }
+                    if i + 1 < count {
+                        libc::close(fds[0]);
+                        libc::dup2(fds[1], 1);
+                        libc::close(fds[1]);
+                    }
+                }
+                let result = self.exec_command(command, &pipeline.text, true);
+                self.child_exit(result);
+            }
+            pgid.get_or_insert(pid);
+            unsafe {
+                if input >= 0 {
+                    libc::close(input);
+                }
+                if i + 1 < count {
+                    libc::close(fds[1]);
+                }
+            }
+            input = fds[0];
+            pids.push(pid);
+        }
+        Ok(self.wait_foreground(pgid.unwrap_or(0), &pids, &pipeline.text))
+    }
+
+    /// Runs a command. `in_child` is set when the command is all that is
+    /// left for a child process to do, so a utility can replace it.
+    fn exec_command(&mut self, command: &Command, text: &str, in_child: bool) -> ExecResult {
+        match command {
+            Command::Simple(simple) => self.exec_simple(simple, text, in_child),
+            Command::Compound(compound, redirects) => self.with_redirects(redirects, |shell| {
+                shell.exec_compound(compound, text, in_child)
+            }),
+            Command::FunctionDefinition { name, body } => {
+                self.functions.insert(name.clone(), body.clone());
+                Ok(0)
+            }
+        }
+    }
+
+    /// Runs `f` with redirections that are undone afterwards. A failed
+    /// redirection is an error that makes a non-interactive shell exit.
+    fn with_redirects(
+        &mut self,
+        redirects: &[Redirect],
+        f: impl FnOnce(&mut Self) -> ExecResult,
+    ) -> ExecResult {
+        if redirects.is_empty() {
+            return f(self);
+        }
+        let mut saved = Vec::new();
+        if let Err(message) = self.redirect(redirects, Some(&mut saved)) {
+            self.restore(saved);
+            self.print_error(&message);
+            return Err(Flow::Error(1));
+        }
+        let result = f(self);
+        self.restore(saved);
+        result
+    }
+
+    /// Runs a loop body, returning `None` when the loop should stop.
+    fn exec_loop_body(&mut self, body: &List) -> Result<Option<i32>, Flow> {
+        match self.exec_list(body) {
+            Ok(status) => Ok(Some(status)),
+            Err(Flow::Break(n)) if n > 1 => Err(Flow::Break(n - 1)),
+            Err(Flow::Break(_)) => Ok(None),
+            Err(Flow::Continue(n)) if n > 1 => Err(Flow::Continue(n - 1)),
+            Err(Flow::Continue(_)) => Ok(Some(0)),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    fn exec_for(&mut self, name: &str, words: &Option<Vec<String>>, body: &List) -> ExecResult {
+        let values = match words {
+            Some(words) => match self.expand_words(words) {
+                Ok(values) => values,
+                Err(message) => return Err(self.expansion_error(message)),
+            },
+            None => self.positional.clone(),
+        };
+        let mut status = 0;
+        for value in values {
+            if let Err(message) = self.set_var(name, value) {
+                self.print_error(&message);
+                return Err(Flow::Error(1));
+            }
+            match self.exec_loop_body(body)? {
+                Some(s) => status = s,
+                None => return Ok(0),
+            }
+        }
+        Ok(status)
+    }
+
+    fn exec_while(&mut self, condition: &List, body: &List, until: bool) -> ExecResult {
+        let mut status = 0;
+        loop {
+            self.errexit_suppressed += 1;
+            let result = self.exec_loop_body(condition);
+            self.errexit_suppressed -= 1;
+            match result? {
+                None => return Ok(0),
+                Some(s) if (s == 0) == until => return Ok(status),
+                Some(_) => {}
+            }
+            match self.exec_loop_body(body)? {
+                Some(s) => status = s,
+                None => return Ok(0),
+            }
+        }
+    }
+
+    fn exec_case(&mut self, word: &str, items: &[CaseItem]) -> ExecResult {
+        let word = match self.expand_word(word) {
+            Ok(word) => word,
+            Err(message) => return Err(self.expansion_error(message)),
+        };
+        for (index, item) in items.iter().enumerate() {
+            for pattern in &item.patterns {
+                let pattern = match self.expand_pattern(pattern) {
+                    Ok(pattern) => pattern,
+                    Err(message) => return Err(self.expansion_error(message)),
+                };
+                if !pattern.matches(&word) {
+                    continue;
+                }
+                let mut status = 0;
+                for item in &items[index..] {
+                    status = self.exec_list(&item.body)?;
+                    if !item.fallthrough {
+                        break;
+                    }
+                }
+                return Ok(status);
+            }
+        }
+        Ok(0)
+    }
+
+    fn exec_compound(
+        &mut self,
+        compound: &CompoundCommand,
+        text: &str,
+        in_child: bool,
+    ) -> ExecResult {
+        match compound {
+            CompoundCommand::Brace(list) => self.exec_list(list),
+            CompoundCommand::Subshell(list) if in_child => self.exec_list(list),
+            CompoundCommand::Subshell(list) => {
+                let pid = self.fork(None, true)?;
+                if pid == 0 {
+                    let result = self.exec_list(list);
+                    self.child_exit(result);
+                }
+                Ok(self.wait_foreground(pid, &[pid], text))
+            }
+            CompoundCommand::For { name, words, body } => {
+                self.loop_depth += 1;
+                let result = self.exec_for(name, words, body);
+                self.loop_depth -= 1;
+                result
+            }
+            CompoundCommand::While { condition, body } => {
+                self.loop_depth += 1;
+                let result = self.exec_while(condition, body, false);
+                self.loop_depth -= 1;
+                result
+            }
+            CompoundCommand::Until { condition, body } => {
+                self.loop_depth += 1;
+                let result = self.exec_while(condition, body, true);
+                self.loop_depth -= 1;
+                result
+            }
+            CompoundCommand::Case { word, items } => self.exec_case(word, items),
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (condition, body) in branches {
+                    if self.exec_condition(condition)? == 0 {
+                        return self.exec_list(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.exec_list(body),
+                    None => Ok(0),
+                }
+            }
+        }
+    }
+
+    pub fn call_function(&mut self, body: Rc<FunctionBody>, args: Vec<String>) -> ExecResult {
+        let positional = std::mem::replace(&mut self.positional, args);
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let result = self.with_redirects(&body.redirects, |shell| {
+            shell.exec_compound(&body.command, "", false)
+        });
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.positional = positional;
+        match result {
+            Err(Flow::Return(status)) => Ok(status),
+            result => result,
+        }
+    }
+
+    /// Expands the words of a simple command. The operands of `export` and
+    /// `readonly` that are assignments are expanded as assignments.
+    fn expand_command_words(&mut self, words: &[String]) -> Result<Vec<String>, String> {
+        let declaration = words
+            .first()
+            .is_some_and(|w| w == "export" || w == "readonly");
+        if !declaration {
+            return self.expand_words(words);
+        }
+        let mut fields = Vec::new();
+        for word in words {
+            match assignment_position(word) {
+                Some(eq) => {
+                    let value = self.expand_assignment(&word[eq + 1..])?;
+                    fields.push(format!("{}={}", &word[..eq], value));
+                }
+                None => fields.extend(self.expand_words(std::slice::from_ref(word))?),
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Writes a command to standard error for `xtrace`.
+    fn trace(&mut self, assignments: &[(String, String)], fields: &[String]) {
+        let ps4 = self.var("PS4").unwrap_or_default().to_string();
+        let mut line = self.expand_prompt(&ps4);
+        let words: Vec<String> = assignments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .chain(fields.iter().cloned())
+            .collect();
+        line.push_str(&words.join(" "));
+        line.push('\n');
+        write_fd(2, line.as_bytes());
+    }
+
+    /// Sets variables for the duration of a builtin or function, returning
+    /// what they replaced.
+    fn set_temporary(
+        &mut self,
+        assignments: Vec<(String, String)>,
+    ) -> Result<Vec<(String, Option<Variable>)>, String> {
+        let mut saved = Vec::new();
+        for (name, value) in assignments {
+            let old = self.vars.get(&name).cloned();
+            if old.as_ref().is_some_and(|v| v.readonly) {
+                self.restore_temporary(saved);
+                return Err(format!("{}: is read only", name));
+            }
+            saved.push((name.clone(), old));
+            if name == "PATH" {
+                self.hash.clear();
+            }
+            let var = self.vars.entry(name).or_default();
+            var.value = Some(value);
+            var.exported = true;
+        }
+        Ok(saved)
+    }
+
+    fn restore_temporary(&mut self, saved: Vec<(String, Option<Variable>)>) {
+        for (name, old) in saved.into_iter().rev() {
+            if name == "PATH" {
+                self.hash.clear();
+            }
+            match old {
+                Some(var) => self.vars.insert(name, var),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+
+    fn exec_simple(&mut self, command: &SimpleCommand, text: &str, in_child: bool) -> ExecResult {
+        if let Some(var) = self.vars.get_mut("LINENO") {
+            if !var.readonly {
+                var.value = Some(command.line.to_string());
+            }
+        } else {
+            let _ = self.set_var("LINENO", command.line.to_string());
+        }
+        self.substitution_status = None;
+        let fields = match self.expand_command_words(&command.words) {
+            Ok(fields) => fields,
+            Err(message) => return Err(self.expansion_error(message)),
+        };
+        let mut assignments = Vec::new();
+        for assignment in &command.assignments {
+            let eq = assignment_position(assignment).unwrap();
+            match self.expand_assignment(&assignment[eq + 1..]) {
+                Ok(value) => assignments.push((assignment[..eq].to_string(), value)),
+                Err(message) => return Err(self.expansion_error(message)),
+            }
+        }
+        if self.options.xtrace {
+            self.trace(&assignments, &fields);
+        }
+
+        if fields.is_empty() {
+            for (name, value) in assignments {
+                if let Err(message) = self.set_var(&name, value) {
+                    self.print_error(&message);
+                    return Err(Flow::Error(1));
+                }
+            }
+            let status = self.substitution_status.unwrap_or(0);
+            let mut saved = Vec::new();
+            let result = self.redirect(&command.redirects, Some(&mut saved));
+            self.restore(saved);
+            return match result {
+                Ok(()) => Ok(status),
+                Err(message) => {
+                    self.print_error(&message);
+                    Ok(1)
+                }
+            };
+        }
+        self.dispatch(
+            fields,
+            assignments,
+            &command.redirects,
+            text,
+            in_child,
+            false,
+        )
+    }
+
+    /// Finds and runs the utility named by the first field.
+    pub fn dispatch(
+        &mut self,
+        fields: Vec<String>,
+        assignments: Vec<(String, String)>,
+        redirects: &[Redirect],
+        text: &str,
+        in_child: bool,
+        via_command: bool,
+    ) -> ExecResult {
+        let name = fields[0].as_str();
+
+        if name == "command" {
+            if let Some(result) =
+                self.exec_command_builtin(&fields, &assignments, redirects, text, in_child)
+            {
+                return result;
+            }
+        }
+
+        if name == "exec" && fields.len() == 1 {
+            // Redirections and assignments apply to the shell itself.
+            for (name, value) in assignments {
+                if let Err(message) = self.set_var(&name, value) {
+                    self.print_error(&message);
+                    return Err(Flow::Error(1));
+                }
+            }
+            if let Err(message) = self.redirect(redirects, None) {
+                self.print_error(&message);
+                return if via_command {
+                    Ok(1)
+                } else {
+                    Err(Flow::Error(1))
+                };
+            }
+            return Ok(0);
+        }
+
+        if !name.contains('/') {
+            if is_special_builtin(name) && !via_command {
+                for (name, value) in assignments {
+                    if let Err(message) = self.set_var(&name, value) {
+                        self.print_error(&message);
+                        return Err(Flow::Error(1));
+                    }
+                }
+                let mut saved = Vec::new();
+                if let Err(message) = self.redirect(redirects, Some(&mut saved)) {
+                    self.restore(saved);
+                    self.print_error(&message);
+                    return Err(Flow::Error(1));
+                }
+                let result = self.run_builtin(&fields);
+                self.restore(saved);
+                return result;
+            }
+
+            let function = if via_command {
+                None
+            } else {
+                self.functions.get(name).cloned()
+            };
+            if function.is_some() || is_builtin(name) || is_special_builtin(name) {
+                let saved_vars = match self.set_temporary(assignments) {
+                    Ok(saved) => saved,
+                    Err(message) => {
+                        self.print_error(&message);
+                        return Ok(1);
+                    }
+                };
+                let mut saved = Vec::new();
+                let result = match self.redirect(redirects, Some(&mut saved)) {
+                    Err(message) => {
+                        self.print_error(&message);
+                        if function.is_some() {
+                            Err(Flow::Error(1))
+                        } else {
+                            Ok(1)
+                        }
+                    }
+                    Ok(()) => match function {
+                        Some(body) => self.call_function(body, fields[1..].to_vec()),
+                        None => match self.run_builtin(&fields) {
+                            Err(Flow::Error(status)) if via_command => Ok(status),
+                            result => result,
+                        },
+                    },
+                };
+                self.restore(saved);
+                self.restore_temporary(saved_vars);
+                return result;
+            }
+        }
+
+        if in_child {
+            let status = self.exec_in_child(&fields, assignments, redirects);
+            self.exit(status);
+        }
+        let pid = self.fork(None, true)?;
+        if pid == 0 {
+            let status = self.exec_in_child(&fields, assignments, redirects);
+            self.exit(status);
+        }
+        Ok(self.wait_foreground(pid, &[pid], text))
+    }
+
+    /// Runs `command`, returning `None` to run the utility it names as
+    /// usual.
+    fn exec_command_builtin(
+        &mut self,
+        fields: &[String],
+        assignments: &[(String, String)],
+        redirects: &[Redirect],
+        text: &str,
+        in_child: bool,
+    ) -> Option<ExecResult> {
+        let mut i = 1;
+        let mut default_path = false;
+        while let Some(arg) = fields.get(i) {
+            match arg.as_str() {
+                "--" => {
+                    i += 1;
+                    break;
+                }
+                "-p" => default_path = true,
+                // -v and -V are handled by the builtin.
+                _ if arg.starts_with('-') && arg.len() > 1 => return None,
+                _ => break,
+            }
+            i += 1;
+        }
+        if i >= fields.len() {
+            return Some(Ok(0));
+        }
+        let mut assignments = assignments.to_vec();
+        if default_path {
+            assignments.push(("PATH".to_string(), DEFAULT_PATH.to_string()));
+        }
+        Some(self.dispatch(
+            fields[i..].to_vec(),
+            assignments,
+            redirects,
+            text,
+            in_child,
+            true,
+        ))
+    }
+
+    /// In a child process, sets up and executes a utility, returning the
+    /// exit status if it could not be executed.
+    fn exec_in_child(
+        &mut self,
+        fields: &[String],
+        assignments: Vec<(String, String)>,
+        redirects: &[Redirect],
+    ) -> i32 {
+        for (name, value) in assignments {
+            let var = self.vars.entry(name).or_default();
+            var.value = Some(value);
+            var.exported = true;
+        }
+        self.hash.clear();
+        if let Err(message) = self.redirect(redirects, None) {
+            self.print_error(&message);
+            return 1;
+        }
+        self.exec_utility(fields)
+    }
+
+    /// Finds the file a utility is run from.
+    pub fn find_utility(&mut self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+        if let Some(path) = self.hash.get(name) {
+            if path.is_file() {
+                return Some(path.clone());
+            }
+        }
+        let path = search_path(name, self.var("PATH").unwrap_or(DEFAULT_PATH))?;
+        self.hash.insert(name.to_string(), path.clone());
+        Some(path)
+    }
+
+    /// Replaces the shell with a utility, returning the exit status if it
+    /// could not be executed.
+    pub fn exec_utility(&mut self, fields: &[String]) -> i32 {
+        let Some(path) = self.find_utility(&fields[0]) else {
+            self.print_error(&format!("{}: not found", fields[0]));
+            return 127;
+        };
+        let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+            return 126;
+        };
+        let args: Vec<CString> = fields
+            .iter()
+            .filter_map(|f| CString::new(f.as_str()).ok())
+            .collect();
+        let env = self.environment();
+        let mut argv: Vec<*const libc::c_char> = args.iter().map(|a| a.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        let mut envp: Vec<*const libc::c_char> = env.iter().map(|e| e.as_ptr()).collect();
+        envp.push(std::ptr::null());
+
+        // Restore the signals the shell ignores for itself.
+        for &(_, signal) in trap::SIGNALS {
+            if !self.subshell
+                && !self.ignored_on_entry.contains(&signal)
+                && !self.traps.contains_key(&signal)
+                && trap::is_ignored(signal)
+            {
+                trap::default(signal);
+            }
+        }
+
+        unsafe { libc::execve(c_path.as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+        let error = std::io::Error::last_os_error();
+
+        if error.raw_os_error() == Some(libc::ENOEXEC) {
+            // A file that is not an executable is a shell script.
+            if let Ok(shell) = std::env::current_exe() {
+                if let Ok(shell) = CString::new(shell.as_os_str().as_bytes()) {
+                    let mut script_argv = vec![args[0].as_ptr(), c_path.as_ptr()];
+                    script_argv.extend(args[1..].iter().map(|a| a.as_ptr()));
+                    script_argv.push(std::ptr::null());
+                    unsafe { libc::execve(shell.as_ptr(), script_argv.as_ptr(), envp.as_ptr()) };
+                }
+            }
+        }
+        self.print_error(&format!("{}: {}", fields[0], error_string(&error)));
+        if error.raw_os_error() == Some(libc::ENOENT) {
+            127
+        } else {
+            126
+        }
+    }
+
+    /// Performs redirections. If `saved` is given, the descriptors replaced
+    /// are recorded so that `restore` can undo them.
+    pub fn redirect(
+        &mut self,
+        redirects: &[Redirect],
+        mut saved: Option<&mut SavedFds>,
+    ) -> Result<(), String> {
+        for redirect in redirects {
+            let fd = redirect.fd();
+
+            // The descriptor to copy onto fd, and whether to close it after,
+            // or None to close fd.
+            let source = match &redirect.target {
+                RedirectTarget::HereDoc(doc) => {
+                    let body = doc.body.borrow().clone();
+                    let text = if doc.quoted {
+                        body
+                    } else {
+                        self.expand_here_doc(&body)?
+                    };
+                    Some((here_doc_fd(&text)?, true))
+                }
+                RedirectTarget::Word(word) => {
+                    let target = self.expand_word(word)?;
+                    match redirect.op {
+                        RedirectOp::DupInput | RedirectOp::DupOutput => {
+                            if target == "-" {
+                                None
+                            } else {
+                                let n: i32 = target
+                                    .parse()
+                                    .map_err(|_| format!("{}: bad file descriptor", target))?;
+                                if unsafe { libc::fcntl(n, libc::F_GETFD) } < 0 {
+                                    return Err(format!("{}: bad file descriptor", n));
+                                }
+                                Some((n, false))
+                            }
+                        }
+                        op => Some((self.open_redirect(&target, op)?, true)),
+                    }
+                }
+            };
+
+            if let Some(saved) = saved.as_deref_mut() {
+                if !saved.iter().any(|(f, _)| *f == fd) {
+                    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+                    saved.push((fd, copy));
+                }
+            }
+            match source {
+                Some((source, close)) if source != fd => unsafe {
+                    libc::dup2(source, fd);
+                    if close {
+                        libc::close(source);
+                    }
+                },
+                Some(_) => {}
+                None => unsafe {
+                    libc::close(fd);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn open_redirect(&self, target: &str, op: RedirectOp) -> Result<i32, String> {
+        let flags = match op {
+            RedirectOp::Input => libc::O_RDONLY,
+            RedirectOp::Output | RedirectOp::Clobber => {
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC
+            }
+            RedirectOp::Append => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+            _ => libc::O_RDWR | libc::O_CREAT,
+        };
+        let flags = if op == RedirectOp::Output && self.options.noclobber {
+            match std::fs::metadata(target) {
+                Ok(m) if m.is_file() => {
+                    return Err(format!("{}: cannot overwrite existing file", target))
+                }
+                Ok(_) => flags,
+                Err(_) => flags | libc::O_EXCL,
+            }
+        } else {
+            flags
+        };
+        let path = CString::new(target).map_err(|_| format!("{}: invalid file name", target))?;
+        let fd = unsafe { libc::open(path.as_ptr(), flags, 0o666 as libc::c_uint) };
+        if fd < 0 {
+            return Err(format!("{}: {}", target, os_error()));
+        }
+        Ok(fd)
+    }
+
+    /// Undoes redirections.
+    pub fn restore(&mut self, saved: SavedFds) {
+        for (fd, copy) in saved.into_iter().rev() {
+            unsafe {
+                if copy >= 0 {
+                    libc::dup2(copy, fd);
+                    libc::close(copy);
+                } else {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+}
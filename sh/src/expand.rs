@@ -0,0 +1,782 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Word expansions: tilde expansion, parameter expansion, command
+//! substitution and arithmetic expansion, then field splitting, pathname
+//! expansion and quote removal.
+
+use crate::arith;
+use crate::exec::os_error;
+use crate::jobs::decode_status;
+use crate::parser::{command_substitution_end, is_name};
+use crate::pattern::{expand_pathname, Pattern};
+use crate::shell::{Flow, Shell};
+use std::ffi::{CStr, CString};
+
+/// A character of an expanded word.
+#[derive(Clone, Copy, Debug)]
+struct Ch {
+    c: char,
+
+    /// Quoted, so not special in patterns and not a field separator.
+    quoted: bool,
+
+    /// The result of an unquoted expansion, subject to field splitting.
+    split: bool,
+}
+
+#[derive(Default)]
+struct Field {
+    chars: Vec<Ch>,
+
+    /// The word had quotes, so the field is kept even if empty.
+    quoted: bool,
+}
+
+/// The fields a word expands to, before field splitting.
+struct Out {
+    fields: Vec<Field>,
+
+    /// `"$@"` may produce several fields.
+    multiple: bool,
+}
+
+impl Out {
+    fn new(multiple: bool) -> Self {
+        Out {
+            fields: vec![Field::default()],
+            multiple,
+        }
+    }
+
+    fn current(&mut self) -> &mut Field {
+        self.fields.last_mut().unwrap()
+    }
+
+    fn push(&mut self, c: char, quoted: bool, split: bool) {
+        self.current().chars.push(Ch { c, quoted, split });
+    }
+
+    fn mark_quoted(&mut self) {
+        self.current().quoted = true;
+    }
+
+    fn break_field(&mut self, quoted: bool) {
+        self.fields.push(Field {
+            chars: Vec::new(),
+            quoted,
+        });
+    }
+
+    fn into_string(self) -> String {
+        self.fields
+            .iter()
+            .map(|f| f.chars.iter().map(|ch| ch.c).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Ctx {
+    /// Inside double quotes.
+    dquote: bool,
+
+    /// In a here-document body, where double quotes are not special.
+    heredoc: bool,
+
+    /// Unquoted expansions are subject to field splitting.
+    split: bool,
+
+    /// A tilde at the start of the text begins a tilde prefix.
+    tilde: bool,
+
+    /// In an assignment value, where a tilde may also follow a colon.
+    assignment: bool,
+}
+
+impl Ctx {
+    fn quoted(&self) -> bool {
+        self.dquote || self.heredoc
+    }
+}
+
+/// Returns the position of the `'` ending a single-quoted string.
+fn single_quote_end(chars: &[char], start: usize) -> usize {
+    (start..chars.len())
+        .find(|&i| chars[i] == '\'')
+        .unwrap_or(chars.len())
+}
+
+/// Returns the position of the `"` ending a double-quoted string.
+fn double_quote_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '"' => return i,
+            '$' => i = dollar_end(chars, i),
+            '`' => i = backquote_end(chars, i + 1) + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Returns the position of the `` ` `` ending a command substitution.
+fn backquote_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '`' => return i,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Returns the position of the `}` ending a parameter expansion.
+fn brace_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '}' => return i,
+            '\'' => i = single_quote_end(chars, i + 1) + 1,
+            '"' => i = double_quote_end(chars, i + 1) + 1,
+            '`' => i = backquote_end(chars, i + 1) + 1,
+            '$' => i = dollar_end(chars, i),
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Returns the position of the first `)` of the `))` ending an arithmetic
+/// expansion.
+fn arithmetic_end(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' if depth == 0 => return i,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Returns the position of the `)` ending a `$(` command substitution.
+fn substitution_end(chars: &[char], start: usize) -> usize {
+    let text: String = chars[start..].iter().collect();
+    command_substitution_end(&text).map_or(chars.len(), |end| start + end)
+}
+
+/// Returns the position after the expansion starting with the `$` at `i`.
+fn dollar_end(chars: &[char], i: usize) -> usize {
+    match (chars.get(i + 1), chars.get(i + 2)) {
+        (Some('{'), _) => brace_end(chars, i + 2) + 1,
+        (Some('('), Some('(')) => arithmetic_end(chars, i + 3) + 2,
+        (Some('('), _) => substitution_end(chars, i + 2) + 1,
+        _ => i + 1,
+    }
+}
+
+/// Removes the backslashes that quote `$`, `` ` `` and `\` (and `"` within
+/// double quotes) in an old-style command substitution.
+fn unescape_backquoted(chars: &[char], dquote: bool) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            if let Some(&next) = chars.get(i + 1) {
+                if matches!(next, '$' | '`' | '\\') || (dquote && next == '"') {
+                    text.push(next);
+                    i += 2;
+                    continue;
+                }
+            }
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+    text
+}
+
+/// The home directory of a user.
+fn home_directory(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+/// Splits a field at the field separators in `ifs`.
+fn split_field(field: Field, ifs: &str) -> Vec<Vec<Ch>> {
+    let is_separator = |ch: &Ch| ch.split && ifs.contains(ch.c);
+    let is_white = |ch: &Ch| is_separator(ch) && matches!(ch.c, ' ' | '\t' | '\n');
+    let chars = &field.chars;
+    let mut fields = Vec::new();
+    let mut current = Vec::new();
+    let mut started = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = &chars[i];
+        if !is_separator(ch) {
+            current.push(*ch);
+            started = true;
+            i += 1;
+            continue;
+        }
+        // A separator is white space, or one other character with any
+        // white space around it.
+        while i < chars.len() && is_white(&chars[i]) {
+            i += 1;
+        }
+        let delimited = i < chars.len() && is_separator(&chars[i]);
+        if delimited {
+            i += 1;
+            while i < chars.len() && is_white(&chars[i]) {
+                i += 1;
+            }
+        }
+        if started || delimited {
+            fields.push(std::mem::take(&mut current));
+            started = false;
+        }
+    }
+    if started || (fields.is_empty() && field.quoted) {
+        fields.push(current);
+    }
+    fields
+}
+
+impl Shell {
+    /// The value of a parameter, other than `@` and `*`.
+    pub fn parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "#" => Some(self.positional.len().to_string()),
+            "?" => Some(self.last_status.to_string()),
+            "-" => Some(self.options.letters()),
+            "$" => Some(self.shell_pid.to_string()),
+            "!" => self.last_async.map(|pid| pid.to_string()),
+            "0" => Some(self.name.clone()),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => {
+                let n: usize = name.parse().ok()?;
+                self.positional.get(n.checked_sub(1)?).cloned()
+            }
+            _ => self.var(name).map(str::to_string),
+        }
+    }
+
+    fn push_value(&self, value: &str, ctx: Ctx, out: &mut Out) {
+        let quoted = ctx.quoted();
+        for c in value.chars() {
+            out.push(c, quoted, !quoted && ctx.split);
+        }
+    }
+
+    /// Expands `$@` or `$*`.
+    fn push_positional(&self, at: bool, ctx: Ctx, out: &mut Out) {
+        if out.multiple && (at || !ctx.quoted()) {
+            for (i, param) in self.positional.iter().enumerate() {
+                if i > 0 {
+                    out.break_field(ctx.quoted());
+                }
+                self.push_value(param, ctx, out);
+            }
+            return;
+        }
+        let separator = if at {
+            " ".to_string()
+        } else {
+            match self.var("IFS") {
+                Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                None => " ".to_string(),
+            }
+        };
+        self.push_value(&self.positional.join(&separator), ctx, out);
+    }
+
+    fn expand_parameter(&mut self, name: &str, ctx: Ctx, out: &mut Out) -> Result<(), String> {
+        if name == "@" || name == "*" {
+            self.push_positional(name == "@", ctx, out);
+            return Ok(());
+        }
+        match self.parameter(name) {
+            Some(value) => self.push_value(&value, ctx, out),
+            None if self.options.nounset => return Err(format!("{}: parameter not set", name)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Expands text to a single string without field splitting, as for the
+    /// word in `${name=word}`.
+    fn expand_chars_to_string(&mut self, chars: &[char]) -> Result<String, String> {
+        let mut out = Out::new(false);
+        let ctx = Ctx {
+            tilde: true,
+            ..Default::default()
+        };
+        self.expand_into(chars, ctx, &mut out)?;
+        Ok(out.into_string())
+    }
+
+    fn expand_chars_to_pattern(&mut self, chars: &[char]) -> Result<Pattern, String> {
+        let mut out = Out::new(false);
+        let ctx = Ctx {
+            tilde: true,
+            ..Default::default()
+        };
+        self.expand_into(chars, ctx, &mut out)?;
+        let chars: Vec<(char, bool)> = out
+            .fields
+            .iter()
+            .flat_map(|f| f.chars.iter().map(|ch| (ch.c, ch.quoted)))
+            .collect();
+        Ok(Pattern::new(&chars))
+    }
+
+    /// Expands the text between `${` and `}`.
+    fn expand_braced(&mut self, inner: &[char], ctx: Ctx, out: &mut Out) -> Result<(), String> {
+        let text: String = inner.iter().collect();
+        let bad = || format!("${{{}}}: bad substitution", text);
+
+        let name_len = |chars: &[char]| -> usize {
+            match chars.first() {
+                Some(c) if *c == '_' || c.is_ascii_alphabetic() => chars
+                    .iter()
+                    .take_while(|c| **c == '_' || c.is_ascii_alphanumeric())
+                    .count(),
+                Some(c) if c.is_ascii_digit() => {
+                    chars.iter().take_while(|c| c.is_ascii_digit()).count()
+                }
+                Some('@' | '*' | '#' | '?' | '-' | '$' | '!') => 1,
+                _ => 0,
+            }
+        };
+
+        // ${#name} is the length of the value.
+        if inner.len() > 1 && inner[0] == '#' && name_len(&inner[1..]) == inner.len() - 1 {
+            let name: String = inner[1..].iter().collect();
+            let length = match name.as_str() {
+                "@" | "*" => self.positional.len(),
+                _ => match self.parameter(&name) {
+                    Some(value) => value.chars().count(),
+                    None if self.options.nounset => {
+                        return Err(format!("{}: parameter not set", name))
+                    }
+                    None => 0,
+                },
+            };
+            self.push_value(&length.to_string(), ctx, out);
+            return Ok(());
+        }
+
+        let len = name_len(inner);
+        if len == 0 {
+            return Err(bad());
+        }
+        let name: String = inner[..len].iter().collect();
+        let rest = &inner[len..];
+        if rest.is_empty() {
+            return self.expand_parameter(&name, ctx, out);
+        }
+
+        let (colon, rest) = match rest.first() {
+            Some(':') => (true, &rest[1..]),
+            _ => (false, rest),
+        };
+        let Some(&op) = rest.first() else {
+            return Err(bad());
+        };
+        let longest = rest.len() > 1 && rest[1] == op && matches!(op, '#' | '%');
+        let word = &rest[if longest { 2 } else { 1 }..];
+
+        let value = match name.as_str() {
+            "@" | "*" if self.positional.is_empty() => None,
+            "@" | "*" => Some(self.positional.join(" ")),
+            _ => self.parameter(&name),
+        };
+        let set = value.as_ref().is_some_and(|v| !(colon && v.is_empty()));
+        let word_ctx = Ctx {
+            tilde: true,
+            assignment: false,
+            ..ctx
+        };
+
+        match op {
+            '-' if set => self.expand_parameter(&name, ctx, out),
+            '-' => self.expand_into(word, word_ctx, out),
+            '+' if set => self.expand_into(word, word_ctx, out),
+            '+' => Ok(()),
+            '=' if set => self.expand_parameter(&name, ctx, out),
+            '=' => {
+                if !is_name(&name) {
+                    return Err(format!("{}: cannot assign in this way", name));
+                }
+                let value = self.expand_chars_to_string(word)?;
+                self.set_var(&name, value.clone())?;
+                self.push_value(&value, ctx, out);
+                Ok(())
+            }
+            '?' if set => self.expand_parameter(&name, ctx, out),
+            '?' => {
+                let message = self.expand_chars_to_string(word)?;
+                let message = if !message.is_empty() {
+                    message
+                } else if value.is_some() {
+                    "parameter null".to_string()
+                } else {
+                    "parameter not set".to_string()
+                };
+                Err(format!("{}: {}", name, message))
+            }
+            '#' | '%' if !colon => {
+                if value.is_none() && self.options.nounset {
+                    return Err(format!("{}: parameter not set", name));
+                }
+                let value = value.unwrap_or_default();
+                let pattern = self.expand_chars_to_pattern(word)?;
+                let result = if op == '#' {
+                    pattern.remove_prefix(&value, longest)
+                } else {
+                    pattern.remove_suffix(&value, longest)
+                };
+                self.push_value(result, ctx, out);
+                Ok(())
+            }
+            _ => Err(bad()),
+        }
+    }
+
+    /// Expands the expansion starting with the `$` at `i`, returning the
+    /// position after it.
+    fn expand_dollar(
+        &mut self,
+        chars: &[char],
+        i: usize,
+        ctx: Ctx,
+        out: &mut Out,
+    ) -> Result<usize, String> {
+        match (chars.get(i + 1).copied(), chars.get(i + 2)) {
+            (Some('{'), _) => {
+                let end = brace_end(chars, i + 2);
+                self.expand_braced(&chars[i + 2..end], ctx, out)?;
+                Ok(end + 1)
+            }
+            (Some('('), Some('(')) => {
+                let end = arithmetic_end(chars, i + 3);
+                let value = self.arithmetic(&chars[i + 3..end])?;
+                self.push_value(&value.to_string(), ctx, out);
+                Ok(end + 2)
+            }
+            (Some('('), _) => {
+                let end = substitution_end(chars, i + 2);
+                let text: String = chars[i + 2..end].iter().collect();
+                let value = self.command_substitution(&text)?;
+                self.push_value(&value, ctx, out);
+                Ok(end + 1)
+            }
+            (Some(c), _) if c == '_' || c.is_ascii_alphabetic() => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| **c == '_' || c.is_ascii_alphanumeric())
+                    .count();
+                let name: String = chars[i + 1..i + 1 + len].iter().collect();
+                self.expand_parameter(&name, ctx, out)?;
+                Ok(i + 1 + len)
+            }
+            (Some(c), _) if c.is_ascii_digit() || "@*#?-$!".contains(c) => {
+                self.expand_parameter(&c.to_string(), ctx, out)?;
+                Ok(i + 2)
+            }
+            _ => {
+                out.push('$', ctx.quoted(), false);
+                Ok(i + 1)
+            }
+        }
+    }
+
+    /// Expands a tilde prefix starting at `i`, returning the position after
+    /// it, or `None` if it is not a tilde prefix.
+    fn expand_tilde(&self, chars: &[char], i: usize, ctx: Ctx, out: &mut Out) -> Option<usize> {
+        let end = (i + 1..chars.len())
+            .find(|&j| chars[j] == '/' || (ctx.assignment && chars[j] == ':'))
+            .unwrap_or(chars.len());
+        let login = &chars[i + 1..end];
+        if login
+            .iter()
+            .any(|c| matches!(c, '\\' | '\'' | '"' | '$' | '`'))
+        {
+            return None;
+        }
+        let home = if login.is_empty() {
+            self.var("HOME").map(str::to_string)?
+        } else {
+            home_directory(&login.iter().collect::<String>())?
+        };
+        for c in home.chars() {
+            out.push(c, true, false);
+        }
+        Some(end)
+    }
+
+    fn expand_into(&mut self, chars: &[char], ctx: Ctx, out: &mut Out) -> Result<(), String> {
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\\' if ctx.quoted() => match chars.get(i + 1) {
+                    Some(&next)
+                        if matches!(next, '$' | '`' | '\\' | '\n')
+                            || (next == '"' && !ctx.heredoc) =>
+                    {
+                        if next != '\n' {
+                            out.push(next, true, false);
+                        }
+                        i += 2;
+                    }
+                    _ => {
+                        out.push('\\', true, false);
+                        i += 1;
+                    }
+                },
+                '\\' => {
+                    match chars.get(i + 1) {
+                        Some('\n') => {}
+                        Some(&next) => {
+                            out.push(next, true, false);
+                            out.mark_quoted();
+                        }
+                        None => out.push('\\', false, false),
+                    }
+                    i += 2;
+                }
+                '\'' if !ctx.quoted() => {
+                    let end = single_quote_end(chars, i + 1);
+                    for &q in &chars[i + 1..end] {
+                        out.push(q, true, false);
+                    }
+                    out.mark_quoted();
+                    i = end + 1;
+                }
+                '"' if !ctx.quoted() => {
+                    let end = double_quote_end(chars, i + 1);
+                    let inner = &chars[i + 1..end];
+                    // "$@" with no positional parameters expands to nothing.
+                    let text: String = inner.iter().collect();
+                    if !(self.positional.is_empty() && (text == "$@" || text == "${@}")) {
+                        out.mark_quoted();
+                    }
+                    let inner_ctx = Ctx {
+                        dquote: true,
+                        tilde: false,
+                        ..ctx
+                    };
+                    self.expand_into(inner, inner_ctx, out)?;
+                    i = end + 1;
+                }
+                '$' => i = self.expand_dollar(chars, i, ctx, out)?,
+                '`' => {
+                    let end = backquote_end(chars, i + 1);
+                    let text = unescape_backquoted(&chars[i + 1..end], ctx.dquote);
+                    let value = self.command_substitution(&text)?;
+                    self.push_value(&value, ctx, out);
+                    i = end + 1;
+                }
+                '~' if !ctx.quoted()
+                    && ((i == 0 && ctx.tilde)
+                        || (ctx.assignment && i > 0 && chars[i - 1] == ':')) =>
+                {
+                    match self.expand_tilde(chars, i, ctx, out) {
+                        Some(end) => i = end,
+                        None => {
+                            out.push('~', false, false);
+                            i += 1;
+                        }
+                    }
+                }
+                _ => {
+                    out.push(c, ctx.quoted(), false);
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, chars: &[char]) -> Result<i64, String> {
+        let mut out = Out::new(false);
+        let ctx = Ctx {
+            dquote: true,
+            heredoc: true,
+            ..Default::default()
+        };
+        self.expand_into(chars, ctx, &mut out)?;
+        let expression = out.into_string();
+        arith::evaluate(&expression, self)
+            .map_err(|e| format!("arithmetic expression: {}: \"{}\"", e, expression))
+    }
+
+    /// Runs `text` in a subshell, returning its output without trailing
+    /// newlines.
+    pub fn command_substitution(&mut self, text: &str) -> Result<String, String> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(format!("pipe: {}", os_error()));
+        }
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            unsafe {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+            return Err(format!("fork: {}", os_error()));
+        }
+        if pid == 0 {
+            unsafe {
+                libc::close(fds[0]);
+                libc::dup2(fds[1], 1);
+                libc::close(fds[1]);
+            }
+            self.enter_subshell();
+            let status = match self.eval_string(text) {
+                Ok(status) => status,
+                Err(Flow::Exit(status) | Flow::Error(status) | Flow::Return(status)) => status,
+                Err(_) => self.last_status,
+            };
+            self.exit(status);
+        }
+
+        unsafe { libc::close(fds[1]) };
+        let mut output = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n as usize]);
+        }
+        unsafe { libc::close(fds[0]) };
+
+        let mut status = 0;
+        while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+            if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                break;
+            }
+        }
+        self.substitution_status = Some(decode_status(status));
+
+        while output.last() == Some(&b'\n') {
+            output.pop();
+        }
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Expands words into fields, with field splitting and pathname
+    /// expansion.
+    pub fn expand_words(&mut self, words: &[String]) -> Result<Vec<String>, String> {
+        let mut result = Vec::new();
+        let ctx = Ctx {
+            split: true,
+            tilde: true,
+            ..Default::default()
+        };
+        for word in words {
+            let chars: Vec<char> = word.chars().collect();
+            let mut out = Out::new(true);
+            self.expand_into(&chars, ctx, &mut out)?;
+            let ifs = self.ifs();
+            for field in out.fields {
+                for chars in split_field(field, &ifs) {
+                    let special = chars
+                        .iter()
+                        .any(|ch| !ch.quoted && matches!(ch.c, '*' | '?' | '['));
+                    if special && !self.options.noglob {
+                        let pairs: Vec<(char, bool)> =
+                            chars.iter().map(|ch| (ch.c, ch.quoted)).collect();
+                        let paths = expand_pathname(&pairs);
+                        if !paths.is_empty() {
+                            result.extend(paths);
+                            continue;
+                        }
+                    }
+                    result.push(chars.iter().map(|ch| ch.c).collect());
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Expands a word to a single string, without field splitting or
+    /// pathname expansion.
+    pub fn expand_word(&mut self, word: &str) -> Result<String, String> {
+        let chars: Vec<char> = word.chars().collect();
+        self.expand_chars_to_string(&chars)
+    }
+
+    /// Expands the value of an assignment, where tilde prefixes may also
+    /// follow colons.
+    pub fn expand_assignment(&mut self, value: &str) -> Result<String, String> {
+        let chars: Vec<char> = value.chars().collect();
+        let mut out = Out::new(false);
+        let ctx = Ctx {
+            tilde: true,
+            assignment: true,
+            ..Default::default()
+        };
+        self.expand_into(&chars, ctx, &mut out)?;
+        Ok(out.into_string())
+    }
+
+    /// Expands a word used as a pattern, in which quoted characters stand
+    /// for themselves.
+    pub fn expand_pattern(&mut self, word: &str) -> Result<Pattern, String> {
+        let chars: Vec<char> = word.chars().collect();
+        self.expand_chars_to_pattern(&chars)
+    }
+
+    /// Expands the body of a here-document whose delimiter was not quoted.
+    pub fn expand_here_doc(&mut self, body: &str) -> Result<String, String> {
+        let chars: Vec<char> = body.chars().collect();
+        let mut out = Out::new(false);
+        let ctx = Ctx {
+            heredoc: true,
+            ..Default::default()
+        };
+        self.expand_into(&chars, ctx, &mut out)?;
+        Ok(out.into_string())
+    }
+
+    /// Expands a prompt string; errors leave it as it is.
+    pub fn expand_prompt(&mut self, prompt: &str) -> String {
+        let status = self.last_status;
+        let expanded = self
+            .expand_here_doc(prompt)
+            .unwrap_or_else(|_| prompt.to_string());
+        self.last_status = status;
+        expanded
+    }
+}
@@ -0,0 +1,486 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The job table: asynchronous lists and stopped pipelines, and waiting
+//! for child processes.
+
+use crate::shell::{write_fd, Shell};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// Stopped by the signal.
+    Stopped(i32),
+    /// Finished with the exit status.
+    Done(i32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub status: Option<i32>,
+    pub stopped: Option<i32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: libc::pid_t,
+    pub processes: Vec<Process>,
+    pub text: String,
+    pub state: State,
+
+    /// The current state has been reported.
+    pub notified: bool,
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    pub list: Vec<Job>,
+
+    /// Job ids, least recently used first, for `%+` and `%-`.
+    order: Vec<usize>,
+}
+
+/// The exit status for a status from `waitpid`.
+pub fn decode_status(status: i32) -> i32 {
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else if libc::WIFSTOPPED(status) {
+        128 + libc::WSTOPSIG(status)
+    } else {
+        0
+    }
+}
+
+fn state_text(state: State) -> String {
+    match state {
+        State::Running => "Running".to_string(),
+        State::Stopped(signal) => match crate::trap::signal_name(signal) {
+            Some("TTIN") => "Stopped (SIGTTIN)".to_string(),
+            Some("TTOU") => "Stopped (SIGTTOU)".to_string(),
+            _ => "Stopped".to_string(),
+        },
+        State::Done(0) => "Done".to_string(),
+        State::Done(status) if status > 128 => match crate::trap::signal_name(status - 128) {
+            Some(name) => format!("Terminated (SIG{})", name),
+            None => format!("Done({})", status),
+        },
+        State::Done(status) => format!("Done({})", status),
+    }
+}
+
+impl Jobs {
+    pub fn add(
+        &mut self,
+        pgid: libc::pid_t,
+        processes: Vec<Process>,
+        text: &str,
+        state: State,
+    ) -> usize {
+        let id = self.list.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+        self.list.push(Job {
+            id,
+            pgid,
+            processes,
+            text: text.to_string(),
+            state,
+            notified: false,
+        });
+        self.touch(id);
+        id
+    }
+
+    /// Makes the job the current one.
+    pub fn touch(&mut self, id: usize) {
+        self.order.retain(|&i| i != id);
+        self.order.push(id);
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        self.list.retain(|j| j.id != id);
+        self.order.retain(|&i| i != id);
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.list.iter().find(|j| j.id == id)
+    }
+
+    fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.list.iter_mut().find(|j| j.id == id)
+    }
+
+    /// The current and previous jobs: stopped jobs come first, then the
+    /// most recently started.
+    fn current_and_previous(&self) -> (Option<usize>, Option<usize>) {
+        let stopped = |id: &usize| {
+            self.get(*id)
+                .is_some_and(|j| matches!(j.state, State::Stopped(_)))
+        };
+        let mut ids: Vec<usize> = self.order.iter().rev().copied().filter(stopped).collect();
+        ids.extend(self.order.iter().rev().copied().filter(|id| !stopped(id)));
+        (ids.first().copied(), ids.get(1).copied())
+    }
+
+    /// Finds the job named by a job id such as `%1`, `%+`, `%-`, `%name`
+    /// or `%?text`, or by a process id.
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let not_found = || format!("{}: no such job", spec);
+        let Some(name) = spec.strip_prefix('%') else {
+            let pid: libc::pid_t = spec.parse().map_err(|_| not_found())?;
+            return self
+                .list
+                .iter()
+                .find(|j| j.processes.iter().any(|p| p.pid == pid))
+                .map(|j| j.id)
+                .ok_or_else(not_found);
+        };
+        let (current, previous) = self.current_and_previous();
+        let found = match name {
+            "" | "%" | "+" => current,
+            "-" => previous,
+            _ => {
+                if let Ok(n) = name.parse::<usize>() {
+                    self.get(n).map(|j| j.id)
+                } else {
+                    let matching: Vec<usize> = self
+                        .list
+                        .iter()
+                        .filter(|j| match name.strip_prefix('?') {
+                            Some(text) => j.text.contains(text),
+                            None => j.text.starts_with(name),
+                        })
+                        .map(|j| j.id)
+                        .collect();
+                    if matching.len() > 1 {
+                        return Err(format!("{}: ambiguous job", spec));
+                    }
+                    matching.first().copied()
+                }
+            }
+        };
+        found.ok_or_else(not_found)
+    }
+
+    /// Records a status reported by `waitpid`.
+    pub fn update(&mut self, pid: libc::pid_t, status: i32) {
+        let Some(job) = self
+            .list
+            .iter_mut()
+            .find(|j| j.processes.iter().any(|p| p.pid == pid))
+        else {
+            return;
+        };
+        let process = job.processes.iter_mut().find(|p| p.pid == pid).unwrap();
+        if libc::WIFSTOPPED(status) {
+            process.stopped = Some(libc::WSTOPSIG(status));
+        } else if libc::WIFCONTINUED(status) {
+            process.stopped = None;
+        } else {
+            process.stopped = None;
+            process.status = Some(decode_status(status));
+        }
+
+        let previous = job.state;
+        job.state = if job.processes.iter().all(|p| p.status.is_some()) {
+            State::Done(job.processes.last().unwrap().status.unwrap())
+        } else if let Some(signal) = job
+            .processes
+            .iter()
+            .filter(|p| p.status.is_none())
+            .find_map(|p| p.stopped)
+        {
+            State::Stopped(signal)
+        } else {
+            State::Running
+        };
+        if job.state != previous {
+            job.notified = false;
+        }
+    }
+
+    /// Collects the statuses of children that have changed state.
+    pub fn reap(&mut self) {
+        if self.list.is_empty() {
+            return;
+        }
+        loop {
+            let mut status = 0;
+            let pid = unsafe {
+                libc::waitpid(
+                    -1,
+                    &mut status,
+                    libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED,
+                )
+            };
+            if pid <= 0 {
+                break;
+            }
+            self.update(pid, status);
+        }
+    }
+
+    /// Formats a line of `jobs` output.
+    pub fn format(&self, job: &Job, long: bool) -> String {
+        let (current, previous) = self.current_and_previous();
+        let marker = if Some(job.id) == current {
+            '+'
+        } else if Some(job.id) == previous {
+            '-'
+        } else {
+            ' '
+        };
+        if long {
+            let mut lines = String::new();
+            for (i, process) in job.processes.iter().enumerate() {
+                if i == 0 {
+                    lines.push_str(&format!(
+                        "[{}]{} {:5} {:24}{}\n",
+                        job.id,
+                        marker,
+                        process.pid,
+                        state_text(job.state),
+                        job.text
+                    ));
+                } else {
+                    lines.push_str(&format!("     {:5}\n", process.pid));
+                }
+            }
+            lines
+        } else {
+            format!(
+                "[{}]{}  {:24}{}\n",
+                job.id,
+                marker,
+                state_text(job.state),
+                job.text
+            )
+        }
+    }
+
+    /// Reports jobs whose state has changed. Interactive shells forget
+    /// finished jobs once they are reported; other shells keep them for
+    /// `wait`.
+    pub fn notify(&mut self, interactive: bool) {
+        self.reap();
+        if !interactive {
+            return;
+        }
+        let mut output = String::new();
+        for job in &self.list {
+            if !job.notified && job.state != State::Running {
+                output.push_str(&self.format(job, false));
+            }
+        }
+        write_fd(2, output.as_bytes());
+        for job in &mut self.list {
+            job.notified = true;
+        }
+        let done: Vec<usize> = self
+            .list
+            .iter()
+            .filter(|j| matches!(j.state, State::Done(_)))
+            .map(|j| j.id)
+            .collect();
+        for id in done {
+            self.remove(id);
+        }
+    }
+
+    pub fn set_running(&mut self, id: usize) {
+        if let Some(job) = self.get_mut(id) {
+            job.state = State::Running;
+            job.notified = true;
+            for process in &mut job.processes {
+                process.stopped = None;
+            }
+        }
+        self.touch(id);
+    }
+}
+
+impl Shell {
+    /// Opens the terminal and puts the shell in its own foreground process
+    /// group. Turns `monitor` off if there is no terminal to control.
+    pub fn init_job_control(&mut self) {
+        let path = b"/dev/tty\0";
+        let fd = unsafe { libc::open(path.as_ptr() as *const libc::c_char, libc::O_RDWR) };
+        if fd < 0 {
+            self.options.monitor = false;
+            return;
+        }
+        let tty = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+        unsafe { libc::close(fd) };
+        if tty < 0 {
+            self.options.monitor = false;
+            return;
+        }
+
+        // Wait until the shell is in the foreground.
+        loop {
+            let foreground = unsafe { libc::tcgetpgrp(tty) };
+            let group = unsafe { libc::getpgrp() };
+            if foreground < 0 {
+                unsafe { libc::close(tty) };
+                self.options.monitor = false;
+                return;
+            }
+            if foreground == group {
+                break;
+            }
+            unsafe { libc::kill(0, libc::SIGTTIN) };
+        }
+
+        for signal in [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU] {
+            crate::trap::ignore(signal);
+        }
+        let pid = unsafe { libc::getpid() };
+        unsafe {
+            libc::setpgid(0, pid);
+            libc::tcsetpgrp(tty, pid);
+        }
+        self.tty = tty;
+        self.pgid = pid;
+    }
+
+    /// Waits for the processes of a foreground pipeline, returning the
+    /// status of the pipeline. A stopped pipeline becomes a job.
+    pub fn wait_foreground(&mut self, pgid: libc::pid_t, pids: &[libc::pid_t], text: &str) -> i32 {
+        let mut processes: Vec<Process> = pids
+            .iter()
+            .map(|&pid| Process {
+                pid,
+                status: None,
+                stopped: None,
+            })
+            .collect();
+        let flags = if self.options.monitor {
+            libc::WUNTRACED
+        } else {
+            0
+        };
+        let mut stopped = None;
+        for process in &mut processes {
+            loop {
+                let mut status = 0;
+                let r = unsafe { libc::waitpid(process.pid, &mut status, flags) };
+                if r < 0 {
+                    if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    process.status = Some(127);
+                } else if libc::WIFSTOPPED(status) {
+                    process.stopped = Some(libc::WSTOPSIG(status));
+                    stopped = process.stopped;
+                } else {
+                    process.status = Some(decode_status(status));
+                }
+                break;
+            }
+        }
+        self.take_terminal();
+
+        if let Some(signal) = stopped {
+            let id = self.jobs.add(pgid, processes, text, State::Stopped(signal));
+            let job = self.jobs.get(id).unwrap();
+            let line = format!("\n{}", self.jobs.format(job, false));
+            write_fd(2, line.as_bytes());
+            if let Some(job) = self.jobs.list.iter_mut().find(|j| j.id == id) {
+                job.notified = true;
+            }
+            return 128 + signal;
+        }
+        self.pipeline_status(&processes)
+    }
+
+    fn pipeline_status(&self, processes: &[Process]) -> i32 {
+        let mut statuses = processes.iter().map(|p| p.status.unwrap_or(0));
+        if self.options.pipefail {
+            statuses.rfind(|&s| s != 0).unwrap_or(0)
+        } else {
+            statuses.next_back().unwrap_or(0)
+        }
+    }
+
+    /// Gives the terminal to a process group, under job control.
+    pub fn give_terminal(&self, pgid: libc::pid_t) {
+        if self.options.monitor && self.tty >= 0 && !self.subshell {
+            unsafe { libc::tcsetpgrp(self.tty, pgid) };
+        }
+    }
+
+    /// Takes the terminal back after a foreground job.
+    pub fn take_terminal(&self) {
+        self.give_terminal(self.pgid);
+    }
+
+    /// Waits for a job to finish, returning its status, or the signal
+    /// number plus 128 if a trapped signal arrives first.
+    pub fn wait_job(&mut self, id: usize) -> i32 {
+        loop {
+            match self.jobs.get(id).map(|j| j.state) {
+                None => return 127,
+                Some(State::Done(_)) => {
+                    let processes = self.jobs.get(id).unwrap().processes.clone();
+                    self.jobs.remove(id);
+                    return self.pipeline_status(&processes);
+                }
+                Some(State::Stopped(signal)) if !self.options.monitor => return 128 + signal,
+                _ => {}
+            }
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::WUNTRACED) };
+            if pid < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    if let Some(signal) = self.trap_pending() {
+                        return 128 + signal;
+                    }
+                    continue;
+                }
+                // No children left: the job's processes were lost.
+                return 127;
+            }
+            self.jobs.update(pid, status);
+            if let Some(State::Stopped(signal)) = self.jobs.get(id).map(|j| j.state) {
+                return 128 + signal;
+            }
+        }
+    }
+
+    /// Continues a stopped job in the foreground or background.
+    pub fn continue_job(&mut self, id: usize, foreground: bool) -> i32 {
+        let job = self.jobs.get(id).unwrap().clone();
+        self.jobs.set_running(id);
+        if foreground {
+            write_fd(1, format!("{}\n", job.text).as_bytes());
+            self.give_terminal(job.pgid);
+        } else {
+            write_fd(1, format!("[{}] {} &\n", job.id, job.text).as_bytes());
+        }
+        let target = if self.options.monitor {
+            -job.pgid
+        } else {
+            job.pgid
+        };
+        unsafe { libc::kill(target, libc::SIGCONT) };
+        if !foreground {
+            return 0;
+        }
+        let pids: Vec<libc::pid_t> = job
+            .processes
+            .iter()
+            .filter(|p| p.status.is_none())
+            .map(|p| p.pid)
+            .collect();
+        self.jobs.remove(id);
+        self.wait_foreground(job.pgid, &pids, &job.text)
+    }
+}
@@ -0,0 +1,190 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod arith;
+mod ast;
+mod builtins;
+mod exec;
+mod expand;
+mod jobs;
+mod parser;
+mod pattern;
+mod shell;
+mod trap;
+
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use parser::{LineSource, Parser, StringSource};
+use plib::{io::error_string, PROJECT_NAME};
+use shell::{FdSource, Flow, Input, Options, Shell};
+use std::rc::Rc;
+
+/// What the shell reads its commands from.
+enum Source {
+    Stdin,
+    String(String),
+    File(String),
+}
+
+struct Args {
+    source: Source,
+    interactive: bool,
+    name: Option<String>,
+    positional: Vec<String>,
+    options: Vec<(String, bool)>,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "{}",
+        gettext("usage: sh [-abCefhimnuvx] [-o option]... [command_file [argument...]]\n       sh -c [-abCefhimnuvx] [-o option]... command_string [command_name [argument...]]\n       sh -s [-abCefhimnuvx] [-o option]... [argument...]")
+    );
+    std::process::exit(2)
+}
+
+/// Parses the command line. The options are those of `set`, with `-c`,
+/// `-i` and `-s` in addition.
+fn parse_args() -> Args {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let mut command = false;
+    let mut stdin = false;
+    let mut interactive = false;
+    let mut options = Vec::new();
+    let mut i = 0;
+    while i < argv.len() {
+        let arg = &argv[i];
+        if arg == "--" || arg == "-" {
+            i += 1;
+            break;
+        }
+        let (value, letters) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(letters), _) if !letters.is_empty() => (true, letters),
+            (_, Some(letters)) if !letters.is_empty() => (false, letters),
+            _ => break,
+        };
+        for letter in letters.chars() {
+            match letter {
+                'c' => command = true,
+                's' => stdin = true,
+                'i' => interactive = value,
+                'o' => {
+                    i += 1;
+                    match argv.get(i) {
+                        Some(name) => options.push((name.clone(), value)),
+                        None => usage(),
+                    }
+                }
+                _ => match Options::name_of(letter) {
+                    Some(name) => options.push((name.to_string(), value)),
+                    None => {
+                        eprintln!("sh: -{}: {}", letter, gettext("invalid option"));
+                        usage();
+                    }
+                },
+            }
+        }
+        i += 1;
+    }
+
+    let mut operands = argv[i..].iter().cloned();
+    let (source, name) = if command {
+        let Some(text) = operands.next() else {
+            eprintln!("sh: -c: {}", gettext("option requires an argument"));
+            usage();
+        };
+        (Source::String(text), operands.next())
+    } else if stdin {
+        (Source::Stdin, None)
+    } else {
+        match operands.next() {
+            Some(file) => (Source::File(file.clone()), Some(file)),
+            None => (Source::Stdin, None),
+        }
+    };
+    Args {
+        source,
+        interactive,
+        name,
+        positional: operands.collect(),
+        options,
+    }
+}
+
+fn run(args: Args, shell: &mut Shell) -> Result<i32, Flow> {
+    let source: Box<dyn LineSource> = match &args.source {
+        Source::String(text) => Box::new(StringSource::new(text)),
+        Source::File(path) => match std::fs::read(path) {
+            Ok(bytes) => Box::new(StringSource::new(&String::from_utf8_lossy(&bytes))),
+            Err(err) => {
+                shell.print_error(&format!("{}: {}", path, error_string(&err)));
+                let status = if err.kind() == std::io::ErrorKind::NotFound {
+                    127
+                } else {
+                    126
+                };
+                return Ok(status);
+            }
+        },
+        Source::Stdin => Box::new(FdSource { fd: 0 }),
+    };
+
+    if shell.options.interactive {
+        if let Some(env) = shell.var("ENV").map(str::to_string) {
+            if unsafe { libc::getuid() == libc::geteuid() && libc::getgid() == libc::getegid() } {
+                let path = shell.expand_word(&env).unwrap_or_default();
+                if let Ok(text) = std::fs::read_to_string(&path) {
+                    shell.eval_string(&text)?;
+                }
+            }
+        }
+    }
+
+    let input = Input {
+        source,
+        state: Rc::clone(&shell.input_state),
+    };
+    let mut parser = Parser::new(Box::new(input));
+    shell.run(&mut parser, true)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = parse_args();
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| std::env::args().next().unwrap_or_else(|| "sh".to_string()));
+    let mut shell = Shell::new(name, args.positional.clone());
+
+    let tty = |fd| unsafe { libc::isatty(fd) == 1 };
+    shell.options.interactive = args.interactive
+        || (matches!(args.source, Source::Stdin) && args.name.is_none() && tty(0) && tty(2));
+    shell.options.monitor = shell.options.interactive;
+    for (name, value) in &args.options {
+        if !shell.options.set(name, *value) {
+            shell.print_error(&format!("{}: {}", name, gettext("invalid option name")));
+            std::process::exit(2);
+        }
+    }
+
+    shell.init_variables();
+    shell.init_signals();
+    if shell.options.monitor {
+        shell.init_job_control();
+    }
+
+    let status = match run(args, &mut shell) {
+        Ok(status) => status,
+        Err(Flow::Exit(status) | Flow::Error(status) | Flow::Return(status)) => status,
+        Err(Flow::Break(_) | Flow::Continue(_)) => shell.last_status,
+    };
+    shell.exit(status)
+}
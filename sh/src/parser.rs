@@ -0,0 +1,1152 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Token recognition and parsing of the shell grammar. Input is read a line
+//! at a time, as the parser needs it, so that each complete command can be
+//! run before the next one is read.
+
+use crate::ast::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Where the parser gets its input from.
+pub trait LineSource {
+    /// Returns the next line, including its newline, or `None` at the end
+    /// of the input. `continuation` is true when the line continues a
+    /// command, which interactive shells prompt for with PS2.
+    fn read_line(&mut self, continuation: bool) -> Option<String>;
+}
+
+/// Reads lines from a string.
+pub struct StringSource {
+    text: String,
+    pos: usize,
+}
+
+impl StringSource {
+    pub fn new(text: &str) -> Self {
+        StringSource {
+            text: text.to_string(),
+            pos: 0,
+        }
+    }
+}
+
+impl LineSource for StringSource {
+    fn read_line(&mut self, _continuation: bool) -> Option<String> {
+        if self.pos >= self.text.len() {
+            return None;
+        }
+        let rest = &self.text[self.pos..];
+        let len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        self.pos += len;
+        Some(rest[..len].to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub message: String,
+
+    /// The input ended in the middle of a command.
+    pub incomplete: bool,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: syntax error: {}", self.line, self.message)
+    }
+}
+
+type ParseResult<T> = Result<T, SyntaxError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    AndIf,
+    OrIf,
+    DSemi,
+    SemiAnd,
+    DLess,
+    DLessDash,
+    DGreat,
+    LessAnd,
+    GreatAnd,
+    LessGreat,
+    Clobber,
+    Pipe,
+    Amp,
+    Semi,
+    Less,
+    Great,
+    LParen,
+    RParen,
+}
+
+/// The operators, longest first.
+const OPERATORS: &[(&str, Op)] = &[
+    ("<<-", Op::DLessDash),
+    ("&&", Op::AndIf),
+    ("||", Op::OrIf),
+    (";;", Op::DSemi),
+    (";&", Op::SemiAnd),
+    ("<<", Op::DLess),
+    (">>", Op::DGreat),
+    ("<&", Op::LessAnd),
+    (">&", Op::GreatAnd),
+    ("<>", Op::LessGreat),
+    (">|", Op::Clobber),
+    ("|", Op::Pipe),
+    ("&", Op::Amp),
+    (";", Op::Semi),
+    ("<", Op::Less),
+    (">", Op::Great),
+    ("(", Op::LParen),
+    (")", Op::RParen),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Word(String),
+    IoNumber(i32),
+    Op(Op),
+    Newline,
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+}
+
+/// The reserved words, recognized only where a command name may appear.
+pub const RESERVED_WORDS: &[&str] = &[
+    "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if", "in", "then",
+    "until", "while",
+];
+
+/// The words that end a compound list.
+const LIST_TERMINATORS: &[&str] = &["}", "do", "done", "elif", "else", "esac", "fi", "then"];
+
+struct PendingHereDoc {
+    doc: Rc<HereDoc>,
+    delimiter: String,
+    strip_tabs: bool,
+}
+
+pub fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// If `word` is an assignment, returns the position of its `=`.
+pub fn assignment_position(word: &str) -> Option<usize> {
+    let eq = word.find('=')?;
+    is_name(&word[..eq]).then_some(eq)
+}
+
+fn is_metachar(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')'
+    )
+}
+
+/// Removes the quotes from a here-document delimiter, reporting whether
+/// there were any.
+fn unquote_delimiter(word: &str) -> (String, bool) {
+    let mut out = String::new();
+    let mut quoted = false;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                quoted = true;
+                out.extend(chars.next());
+            }
+            '\'' => {
+                quoted = true;
+                out.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                quoted = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => out.extend(chars.next()),
+                        _ => out.push(c),
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    (out, quoted)
+}
+
+/// Given the text following `$(`, finds the position of the `)` that ends
+/// the command substitution.
+pub fn command_substitution_end(text: &str) -> Option<usize> {
+    let mut parser = Parser::from_string(text);
+    parser.parse_compound_list().ok()?;
+    let token = parser.next_token().ok()?;
+    (token.kind == Kind::Op(Op::RParen)).then_some(token.start)
+}
+
+pub struct Parser {
+    source: Box<dyn LineSource>,
+    buf: Vec<char>,
+    pos: usize,
+    eof: bool,
+
+    /// Line number of `buf[0]`.
+    base_line: usize,
+
+    /// Further input continues a command.
+    continuation: bool,
+
+    peeked: Option<Token>,
+    here_docs: Vec<PendingHereDoc>,
+
+    aliases: HashMap<String, String>,
+
+    /// Aliases being substituted, with the end of their text in `buf`.
+    active_aliases: Vec<(String, usize)>,
+
+    /// The previous alias ended in a blank, so the next word is also
+    /// checked for aliases.
+    alias_next: bool,
+}
+
+impl Parser {
+    pub fn new(source: Box<dyn LineSource>) -> Self {
+        Parser {
+            source,
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
+            base_line: 1,
+            continuation: false,
+            peeked: None,
+            here_docs: Vec::new(),
+            aliases: HashMap::new(),
+            active_aliases: Vec::new(),
+            alias_next: false,
+        }
+    }
+
+    pub fn from_string(text: &str) -> Self {
+        Self::new(Box::new(StringSource::new(text)))
+    }
+
+    fn line_at(&self, pos: usize) -> usize {
+        self.base_line
+            + self.buf[..pos.min(self.buf.len())]
+                .iter()
+                .filter(|&&c| c == '\n')
+                .count()
+    }
+
+    pub fn line(&self) -> usize {
+        self.line_at(self.pos)
+    }
+
+    fn error<T>(&self, message: String) -> ParseResult<T> {
+        Err(SyntaxError {
+            line: self.line(),
+            message,
+            incomplete: false,
+        })
+    }
+
+    fn eof_error<T>(&self, looking_for: &str) -> ParseResult<T> {
+        Err(SyntaxError {
+            line: self.line(),
+            message: format!("unexpected end of file while looking for '{}'", looking_for),
+            incomplete: true,
+        })
+    }
+
+    /// Returns the character `offset` places ahead, reading more input if
+    /// needed.
+    fn ch(&mut self, offset: usize) -> Option<char> {
+        while self.pos + offset >= self.buf.len() {
+            if self.eof {
+                return None;
+            }
+            match self.source.read_line(self.continuation) {
+                Some(line) => self.buf.extend(line.chars()),
+                None => self.eof = true,
+            }
+        }
+        Some(self.buf[self.pos + offset])
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.buf[start..end].iter().collect()
+    }
+
+    /// Discards the rest of the current input line, after a syntax error in
+    /// an interactive shell.
+    pub fn discard_line(&mut self) {
+        self.peeked = None;
+        self.here_docs.clear();
+        self.active_aliases.clear();
+        while self.pos < self.buf.len() {
+            self.pos += 1;
+            if self.buf[self.pos - 1] == '\n' {
+                break;
+            }
+        }
+        self.forget_consumed();
+    }
+
+    /// Drops the input already parsed.
+    fn forget_consumed(&mut self) {
+        if self.peeked.is_none() && self.active_aliases.is_empty() {
+            self.base_line = self.line_at(self.pos);
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.ch(0) {
+                Some(' ' | '\t') => self.pos += 1,
+                Some('\\') if self.ch(1) == Some('\n') => self.pos += 2,
+                Some('#') => {
+                    while !matches!(self.ch(0), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn lex(&mut self) -> ParseResult<Token> {
+        self.skip_blanks();
+        let start = self.pos;
+        let Some(c) = self.ch(0) else {
+            return Ok(Token {
+                kind: Kind::Eof,
+                start,
+                end: start,
+            });
+        };
+        if c == '\n' {
+            self.pos += 1;
+            self.read_here_docs()?;
+            return Ok(Token {
+                kind: Kind::Newline,
+                start,
+                end: start + 1,
+            });
+        }
+        if matches!(c, ';' | '&' | '|' | '<' | '>' | '(' | ')') {
+            for (text, op) in OPERATORS {
+                let matches = text.chars().enumerate().all(|(i, t)| self.ch(i) == Some(t));
+                if matches {
+                    self.pos += text.len();
+                    return Ok(Token {
+                        kind: Kind::Op(*op),
+                        start,
+                        end: self.pos,
+                    });
+                }
+            }
+        }
+
+        let mut word = String::new();
+        self.scan_word(&mut word)?;
+        let end = self.pos;
+        if !word.is_empty()
+            && word.chars().all(|c| c.is_ascii_digit())
+            && matches!(self.ch(0), Some('<' | '>'))
+        {
+            if let Ok(fd) = word.parse() {
+                return Ok(Token {
+                    kind: Kind::IoNumber(fd),
+                    start,
+                    end,
+                });
+            }
+        }
+        Ok(Token {
+            kind: Kind::Word(word),
+            start,
+            end,
+        })
+    }
+
+    fn scan_word(&mut self, word: &mut String) -> ParseResult<()> {
+        while let Some(c) = self.ch(0) {
+            match c {
+                '\\' => {
+                    if self.ch(1) == Some('\n') {
+                        self.pos += 2;
+                        continue;
+                    }
+                    self.pos += 1;
+                    word.push('\\');
+                    if let Some(next) = self.ch(0) {
+                        word.push(next);
+                        self.pos += 1;
+                    }
+                }
+                '\'' => self.scan_single_quote(word)?,
+                '"' => self.scan_double_quote(word)?,
+                '`' => self.scan_backquote(word)?,
+                '$' => self.scan_dollar(word)?,
+                _ if is_metachar(c) => break,
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_single_quote(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push('\'');
+        self.pos += 1;
+        loop {
+            let Some(c) = self.ch(0) else {
+                return self.eof_error("'");
+            };
+            word.push(c);
+            self.pos += 1;
+            if c == '\'' {
+                return Ok(());
+            }
+        }
+    }
+
+    fn scan_double_quote(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push('"');
+        self.pos += 1;
+        loop {
+            let Some(c) = self.ch(0) else {
+                return self.eof_error("\"");
+            };
+            match c {
+                '"' => {
+                    word.push('"');
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\\' => {
+                    if self.ch(1) == Some('\n') {
+                        self.pos += 2;
+                        continue;
+                    }
+                    word.push('\\');
+                    self.pos += 1;
+                    if let Some(next) = self.ch(0) {
+                        word.push(next);
+                        self.pos += 1;
+                    }
+                }
+                '`' => self.scan_backquote(word)?,
+                '$' => self.scan_dollar(word)?,
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn scan_backquote(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push('`');
+        self.pos += 1;
+        loop {
+            let Some(c) = self.ch(0) else {
+                return self.eof_error("`");
+            };
+            self.pos += 1;
+            match c {
+                '`' => {
+                    word.push('`');
+                    return Ok(());
+                }
+                '\\' => {
+                    if self.ch(0) == Some('\n') {
+                        self.pos += 1;
+                        continue;
+                    }
+                    word.push('\\');
+                    if let Some(next) = self.ch(0) {
+                        word.push(next);
+                        self.pos += 1;
+                    }
+                }
+                _ => word.push(c),
+            }
+        }
+    }
+
+    fn scan_dollar(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push('$');
+        self.pos += 1;
+        match (self.ch(0), self.ch(1)) {
+            (Some('('), Some('(')) => self.scan_arithmetic(word),
+            (Some('('), _) => self.scan_command_substitution(word),
+            (Some('{'), _) => self.scan_braced_parameter(word),
+            _ => Ok(()),
+        }
+    }
+
+    fn scan_arithmetic(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push_str("((");
+        self.pos += 2;
+        let mut depth = 0;
+        loop {
+            let Some(c) = self.ch(0) else {
+                return self.eof_error("))");
+            };
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 && self.ch(1) == Some(')') => {
+                    word.push_str("))");
+                    self.pos += 2;
+                    return Ok(());
+                }
+                ')' => depth -= 1,
+                '\\' if self.ch(1) == Some('\n') => {
+                    self.pos += 2;
+                    continue;
+                }
+                _ => {}
+            }
+            word.push(c);
+            self.pos += 1;
+        }
+    }
+
+    /// Parses the commands of `$(...)` to find where it ends, keeping their
+    /// text in the word.
+    fn scan_command_substitution(&mut self, word: &mut String) -> ParseResult<()> {
+        let start = self.pos;
+        self.pos += 1;
+        let saved_aliases = std::mem::take(&mut self.aliases);
+        let saved_alias_next = std::mem::replace(&mut self.alias_next, false);
+        let result = self
+            .parse_compound_list()
+            .and_then(|_| match self.next_token()? {
+                Token {
+                    kind: Kind::Op(Op::RParen),
+                    ..
+                } => Ok(()),
+                Token {
+                    kind: Kind::Eof, ..
+                } => self.eof_error(")"),
+                token => self.unexpected(&token),
+            });
+        self.aliases = saved_aliases;
+        self.alias_next = saved_alias_next;
+        result?;
+        word.push_str(&self.text(start, self.pos));
+        Ok(())
+    }
+
+    fn scan_braced_parameter(&mut self, word: &mut String) -> ParseResult<()> {
+        word.push('{');
+        self.pos += 1;
+        loop {
+            let Some(c) = self.ch(0) else {
+                return self.eof_error("}");
+            };
+            match c {
+                '}' => {
+                    word.push('}');
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\\' => {
+                    if self.ch(1) == Some('\n') {
+                        self.pos += 2;
+                        continue;
+                    }
+                    word.push('\\');
+                    self.pos += 1;
+                    if let Some(next) = self.ch(0) {
+                        word.push(next);
+                        self.pos += 1;
+                    }
+                }
+                '\'' => self.scan_single_quote(word)?,
+                '"' => self.scan_double_quote(word)?,
+                '`' => self.scan_backquote(word)?,
+                '$' => self.scan_dollar(word)?,
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Reads the bodies of the here-documents started on the line just
+    /// ended.
+    fn read_here_docs(&mut self) -> ParseResult<()> {
+        for pending in std::mem::take(&mut self.here_docs) {
+            let mut body = String::new();
+            loop {
+                if self.ch(0).is_none() {
+                    break;
+                }
+                let mut line = String::new();
+                while let Some(c) = self.ch(0) {
+                    self.pos += 1;
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                let line = if pending.strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == pending.delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            *pending.doc.body.borrow_mut() = body;
+        }
+        Ok(())
+    }
+
+    fn peek(&mut self) -> ParseResult<&Token> {
+        if self.peeked.is_none() {
+            let token = self.lex()?;
+            self.peeked = Some(token);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    fn next_token(&mut self) -> ParseResult<Token> {
+        self.peek()?;
+        let token = self.peeked.take().unwrap();
+        self.continuation = true;
+        Ok(token)
+    }
+
+    fn peek_kind(&mut self) -> ParseResult<Kind> {
+        Ok(self.peek()?.kind.clone())
+    }
+
+    fn peek_op(&mut self, op: Op) -> ParseResult<bool> {
+        Ok(self.peek()?.kind == Kind::Op(op))
+    }
+
+    /// Whether the next token is the reserved word `word`.
+    fn peek_reserved(&mut self, word: &str) -> ParseResult<bool> {
+        Ok(matches!(&self.peek()?.kind, Kind::Word(w) if w == word))
+    }
+
+    fn unexpected<T>(&self, token: &Token) -> ParseResult<T> {
+        let description = match &token.kind {
+            Kind::Word(w) => format!("'{}'", w),
+            Kind::IoNumber(n) => format!("'{}'", n),
+            Kind::Op(_) => format!("'{}'", self.text(token.start, token.end)),
+            Kind::Newline => "newline".to_string(),
+            Kind::Eof => {
+                return Err(SyntaxError {
+                    line: self.line(),
+                    message: "unexpected end of file".to_string(),
+                    incomplete: true,
+                })
+            }
+        };
+        self.error(format!("unexpected {}", description))
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> ParseResult<()> {
+        let token = self.next_token()?;
+        match &token.kind {
+            Kind::Word(w) if w == word => Ok(()),
+            Kind::Eof => self.eof_error(word),
+            _ => self.unexpected(&token),
+        }
+    }
+
+    fn expect_op(&mut self, op: Op, text: &str) -> ParseResult<()> {
+        let token = self.next_token()?;
+        match &token.kind {
+            Kind::Op(o) if *o == op => Ok(()),
+            Kind::Eof => self.eof_error(text),
+            _ => self.unexpected(&token),
+        }
+    }
+
+    /// Skips newlines.
+    fn linebreak(&mut self) -> ParseResult<()> {
+        while self.peek()?.kind == Kind::Newline {
+            self.next_token()?;
+        }
+        Ok(())
+    }
+
+    /// Substitutes an alias for the next token if it is an alias name.
+    fn substitute_alias(&mut self) -> ParseResult<()> {
+        loop {
+            let pos = self.pos;
+            self.active_aliases.retain(|(_, end)| pos < *end);
+            let token = self.peek()?.clone();
+            let Kind::Word(word) = &token.kind else {
+                return Ok(());
+            };
+            let Some(value) = self.aliases.get(word) else {
+                return Ok(());
+            };
+            if self.active_aliases.iter().any(|(name, _)| name == word) {
+                return Ok(());
+            }
+            let value: Vec<char> = value.chars().collect();
+            self.alias_next = value.last().is_some_and(|c| *c == ' ' || *c == '\t');
+            let end = token.start + value.len();
+            self.buf.splice(token.start..token.end, value);
+            for (_, alias_end) in &mut self.active_aliases {
+                *alias_end = *alias_end + end - token.end;
+            }
+            self.active_aliases.push((word.clone(), end));
+            self.pos = token.start;
+            self.peeked = None;
+        }
+    }
+
+    /// Parses the next complete command, which ends at a newline. Returns
+    /// `None` at the end of the input.
+    pub fn parse_complete_command(
+        &mut self,
+        aliases: &HashMap<String, String>,
+    ) -> ParseResult<Option<List>> {
+        self.aliases.clone_from(aliases);
+        self.forget_consumed();
+        self.continuation = false;
+        loop {
+            match self.peek_kind()? {
+                Kind::Eof => return Ok(None),
+                Kind::Newline => {
+                    self.peeked = None;
+                    self.forget_consumed();
+                    self.continuation = false;
+                }
+                _ => break,
+            }
+        }
+        let list = self.parse_list()?;
+        let token = self.next_token()?;
+        match token.kind {
+            Kind::Newline | Kind::Eof => Ok(Some(list)),
+            _ => self.unexpected(&token),
+        }
+    }
+
+    /// Parses a list on one line: and-or lists separated by `;` or `&`.
+    fn parse_list(&mut self) -> ParseResult<List> {
+        let mut list = Vec::new();
+        loop {
+            let and_or = self.parse_and_or()?;
+            let asynchronous = self.peek_op(Op::Amp)?;
+            let separated = asynchronous || self.peek_op(Op::Semi)?;
+            if separated {
+                self.next_token()?;
+            }
+            list.push(ListItem {
+                and_or,
+                asynchronous,
+            });
+            if !separated || matches!(self.peek_kind()?, Kind::Newline | Kind::Eof) {
+                return Ok(list);
+            }
+        }
+    }
+
+    /// Whether the next token ends a compound list.
+    fn at_list_end(&mut self) -> ParseResult<bool> {
+        Ok(match &self.peek()?.kind {
+            Kind::Word(w) => LIST_TERMINATORS.contains(&w.as_str()),
+            Kind::Op(op) => matches!(op, Op::RParen | Op::DSemi | Op::SemiAnd),
+            Kind::Eof => true,
+            _ => false,
+        })
+    }
+
+    /// Parses a list that may span lines, up to a reserved word or operator
+    /// that ends it.
+    fn parse_compound_list(&mut self) -> ParseResult<List> {
+        let mut list = Vec::new();
+        self.linebreak()?;
+        while !self.at_list_end()? {
+            let and_or = self.parse_and_or()?;
+            let asynchronous = self.peek_op(Op::Amp)?;
+            let mut separated = asynchronous || self.peek_op(Op::Semi)?;
+            if separated {
+                self.next_token()?;
+            }
+            list.push(ListItem {
+                and_or,
+                asynchronous,
+            });
+            if self.peek()?.kind == Kind::Newline {
+                separated = true;
+                self.linebreak()?;
+            }
+            if !separated {
+                break;
+            }
+        }
+        Ok(list)
+    }
+
+    /// Parses a non-empty compound list, as required after `then`, `do` and
+    /// so on.
+    fn parse_body(&mut self, before: &str) -> ParseResult<List> {
+        let list = self.parse_compound_list()?;
+        if list.is_empty() {
+            let token = self.peek()?.clone();
+            if token.kind == Kind::Eof {
+                return self.eof_error(before);
+            }
+            return self.unexpected(&token);
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> ParseResult<AndOr> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let op = match self.peek_kind()? {
+                Kind::Op(Op::AndIf) => AndOrOp::And,
+                Kind::Op(Op::OrIf) => AndOrOp::Or,
+                _ => return Ok(AndOr { first, rest }),
+            };
+            self.next_token()?;
+            self.linebreak()?;
+            rest.push((op, self.parse_pipeline()?));
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> ParseResult<Pipeline> {
+        self.substitute_alias()?;
+        let start = self.peek()?.start;
+        let negated = self.peek_reserved("!")?;
+        if negated {
+            self.next_token()?;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek_op(Op::Pipe)? {
+            self.next_token()?;
+            self.linebreak()?;
+            commands.push(self.parse_command()?);
+        }
+        let end = self.pos.min(self.buf.len());
+        let text = self
+            .text(start.min(end), end)
+            .trim_end_matches(|c: char| c.is_whitespace() || c == ';' || c == '&')
+            .to_string();
+        Ok(Pipeline {
+            negated,
+            commands,
+            text,
+        })
+    }
+
+    fn parse_command(&mut self) -> ParseResult<Command> {
+        self.substitute_alias()?;
+        let token = self.peek()?.clone();
+        let compound = match &token.kind {
+            Kind::Op(Op::LParen) => {
+                self.next_token()?;
+                let list = self.parse_body("(")?;
+                self.expect_op(Op::RParen, ")")?;
+                CompoundCommand::Subshell(list)
+            }
+            Kind::Word(w) if RESERVED_WORDS.contains(&w.as_str()) => match w.as_str() {
+                "{" => {
+                    self.next_token()?;
+                    let list = self.parse_body("}")?;
+                    self.expect_reserved("}")?;
+                    CompoundCommand::Brace(list)
+                }
+                "if" => self.parse_if()?,
+                "while" | "until" => self.parse_loop(w == "while")?,
+                "for" => self.parse_for()?,
+                "case" => self.parse_case()?,
+                _ => return self.unexpected(&token),
+            },
+            _ => return self.parse_simple_command(),
+        };
+        let redirects = self.parse_redirects()?;
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    fn parse_if(&mut self) -> ParseResult<CompoundCommand> {
+        self.next_token()?;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.parse_body("then")?;
+            self.expect_reserved("then")?;
+            let body = self.parse_body("fi")?;
+            branches.push((condition, body));
+            if self.peek_reserved("elif")? {
+                self.next_token()?;
+                continue;
+            }
+            if self.peek_reserved("else")? {
+                self.next_token()?;
+                otherwise = Some(self.parse_body("fi")?);
+            }
+            self.expect_reserved("fi")?;
+            return Ok(CompoundCommand::If {
+                branches,
+                otherwise,
+            });
+        }
+    }
+
+    fn parse_do_group(&mut self) -> ParseResult<List> {
+        self.expect_reserved("do")?;
+        let body = self.parse_body("done")?;
+        self.expect_reserved("done")?;
+        Ok(body)
+    }
+
+    fn parse_loop(&mut self, is_while: bool) -> ParseResult<CompoundCommand> {
+        self.next_token()?;
+        let condition = self.parse_body("do")?;
+        let body = self.parse_do_group()?;
+        Ok(if is_while {
+            CompoundCommand::While { condition, body }
+        } else {
+            CompoundCommand::Until { condition, body }
+        })
+    }
+
+    fn parse_for(&mut self) -> ParseResult<CompoundCommand> {
+        self.next_token()?;
+        let token = self.next_token()?;
+        let name = match &token.kind {
+            Kind::Word(w) if is_name(w) => w.clone(),
+            _ => return self.unexpected(&token),
+        };
+        self.linebreak()?;
+        let mut words = None;
+        if self.peek_reserved("in")? {
+            self.next_token()?;
+            let mut list = Vec::new();
+            while let Kind::Word(w) = self.peek_kind()? {
+                self.next_token()?;
+                list.push(w);
+            }
+            words = Some(list);
+            let token = self.next_token()?;
+            if !matches!(token.kind, Kind::Newline | Kind::Op(Op::Semi)) {
+                return self.unexpected(&token);
+            }
+        } else if self.peek_op(Op::Semi)? {
+            self.next_token()?;
+        }
+        self.linebreak()?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    fn parse_case(&mut self) -> ParseResult<CompoundCommand> {
+        self.next_token()?;
+        let token = self.next_token()?;
+        let Kind::Word(word) = token.kind.clone() else {
+            return self.unexpected(&token);
+        };
+        self.linebreak()?;
+        self.expect_reserved("in")?;
+        self.linebreak()?;
+
+        let mut items = Vec::new();
+        loop {
+            if self.peek_reserved("esac")? {
+                self.next_token()?;
+                return Ok(CompoundCommand::Case { word, items });
+            }
+            if self.peek_op(Op::LParen)? {
+                self.next_token()?;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let token = self.next_token()?;
+                match token.kind {
+                    Kind::Word(w) => patterns.push(w),
+                    Kind::Eof => return self.eof_error("esac"),
+                    _ => return self.unexpected(&token),
+                }
+                if !self.peek_op(Op::Pipe)? {
+                    break;
+                }
+                self.next_token()?;
+            }
+            self.expect_op(Op::RParen, ")")?;
+            let body = self.parse_compound_list()?;
+            let fallthrough = self.peek_op(Op::SemiAnd)?;
+            if fallthrough || self.peek_op(Op::DSemi)? {
+                self.next_token()?;
+                self.linebreak()?;
+            } else if !self.peek_reserved("esac")? {
+                let token = self.peek()?.clone();
+                if token.kind == Kind::Eof {
+                    return self.eof_error("esac");
+                }
+                return self.unexpected(&token);
+            }
+            items.push(CaseItem {
+                patterns,
+                body,
+                fallthrough,
+            });
+        }
+    }
+
+    /// Parses a redirection if the next token starts one.
+    fn parse_redirect(&mut self) -> ParseResult<Option<Redirect>> {
+        let fd = match self.peek_kind()? {
+            Kind::IoNumber(fd) => {
+                self.next_token()?;
+                Some(fd)
+            }
+            _ => None,
+        };
+        let op = match self.peek_kind()? {
+            Kind::Op(op) => op,
+            _ if fd.is_some() => {
+                let token = self.peek()?.clone();
+                return self.unexpected(&token);
+            }
+            _ => return Ok(None),
+        };
+        let redirect_op = match op {
+            Op::Less | Op::DLess | Op::DLessDash => RedirectOp::Input,
+            Op::Great => RedirectOp::Output,
+            Op::Clobber => RedirectOp::Clobber,
+            Op::DGreat => RedirectOp::Append,
+            Op::LessAnd => RedirectOp::DupInput,
+            Op::GreatAnd => RedirectOp::DupOutput,
+            Op::LessGreat => RedirectOp::ReadWrite,
+            _ if fd.is_some() => {
+                let token = self.peek()?.clone();
+                return self.unexpected(&token);
+            }
+            _ => return Ok(None),
+        };
+        self.next_token()?;
+        let token = self.next_token()?;
+        let Kind::Word(word) = token.kind.clone() else {
+            return self.unexpected(&token);
+        };
+        let target = if matches!(op, Op::DLess | Op::DLessDash) {
+            let (delimiter, quoted) = unquote_delimiter(&word);
+            let doc = Rc::new(HereDoc {
+                body: RefCell::new(String::new()),
+                quoted,
+            });
+            self.here_docs.push(PendingHereDoc {
+                doc: doc.clone(),
+                delimiter,
+                strip_tabs: op == Op::DLessDash,
+            });
+            RedirectTarget::HereDoc(doc)
+        } else {
+            RedirectTarget::Word(word)
+        };
+        Ok(Some(Redirect {
+            fd,
+            op: redirect_op,
+            target,
+        }))
+    }
+
+    fn parse_redirects(&mut self) -> ParseResult<Vec<Redirect>> {
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(redirects)
+    }
+
+    fn parse_simple_command(&mut self) -> ParseResult<Command> {
+        let mut command = SimpleCommand {
+            line: self.line(),
+            ..Default::default()
+        };
+        loop {
+            if let Some(redirect) = self.parse_redirect()? {
+                command.redirects.push(redirect);
+                continue;
+            }
+            if command.words.is_empty() || self.alias_next {
+                self.alias_next = false;
+                self.substitute_alias()?;
+            }
+            let Kind::Word(word) = self.peek_kind()? else {
+                break;
+            };
+            if command.words.is_empty() && assignment_position(&word).is_some() {
+                self.next_token()?;
+                command.assignments.push(word);
+                continue;
+            }
+            self.next_token()?;
+            let is_function = command.words.is_empty()
+                && command.assignments.is_empty()
+                && command.redirects.is_empty()
+                && is_name(&word)
+                && self.peek_op(Op::LParen)?;
+            if is_function {
+                return self.parse_function(word);
+            }
+            command.words.push(word);
+        }
+        if command.words.is_empty()
+            && command.assignments.is_empty()
+            && command.redirects.is_empty()
+        {
+            let token = self.peek()?.clone();
+            return self.unexpected(&token);
+        }
+        Ok(Command::Simple(command))
+    }
+
+    fn parse_function(&mut self, name: String) -> ParseResult<Command> {
+        self.next_token()?;
+        self.expect_op(Op::RParen, ")")?;
+        self.linebreak()?;
+        let token = self.peek()?.clone();
+        match self.parse_command()? {
+            Command::Compound(command, redirects) => Ok(Command::FunctionDefinition {
+                name,
+                body: Rc::new(FunctionBody { command, redirects }),
+            }),
+            _ => self.unexpected(&token),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Pattern matching notation, used by `case`, the `${x#pattern}` family of
+//! parameter expansions and pathname expansion.
+
+use std::fs;
+
+#[derive(Clone, Debug)]
+enum BracketItem {
+    Char(char),
+    Range(char, char),
+    Class(String),
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    Bracket {
+        items: Vec<BracketItem>,
+        negated: bool,
+    },
+}
+
+fn class_matches(class: &str, c: char) -> bool {
+    match class {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => !c.is_whitespace() && !c.is_control(),
+        "lower" => c.is_lowercase(),
+        "print" => !c.is_control(),
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::Any | Token::Star => true,
+            Token::Bracket { items, negated } => {
+                let found = items.iter().any(|item| match item {
+                    BracketItem::Char(x) => *x == c,
+                    BracketItem::Range(a, b) => (*a..=*b).contains(&c),
+                    BracketItem::Class(class) => class_matches(class, c),
+                });
+                found != *negated
+            }
+        }
+    }
+}
+
+/// A compiled pattern. Characters that were quoted in the shell word only
+/// match themselves.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+/// Parses the bracket expression starting after the `[` at `chars[start]`,
+/// returning it and the position after its `]`.
+fn parse_bracket(chars: &[(char, bool)], start: usize) -> Option<(Token, usize)> {
+    let mut i = start;
+    let mut negated = false;
+    if matches!(chars.get(i), Some(('!' | '^', false))) {
+        negated = true;
+        i += 1;
+    }
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let &(c, quoted) = chars.get(i)?;
+        if c == ']' && !quoted && !first {
+            return Some((Token::Bracket { items, negated }, i + 1));
+        }
+        first = false;
+        if c == '[' && !quoted && matches!(chars.get(i + 1), Some((':', false))) {
+            let rest = &chars[i + 2..];
+            if let Some(end) = rest
+                .windows(2)
+                .position(|w| w[0] == (':', false) && w[1] == (']', false))
+            {
+                let class: String = rest[..end].iter().map(|(c, _)| c).collect();
+                items.push(BracketItem::Class(class));
+                i += end + 4;
+                continue;
+            }
+        }
+        if matches!(chars.get(i + 1), Some(('-', false)))
+            && chars.get(i + 2).is_some_and(|&(c, q)| c != ']' || q)
+        {
+            items.push(BracketItem::Range(c, chars[i + 2].0));
+            i += 3;
+            continue;
+        }
+        items.push(BracketItem::Char(c));
+        i += 1;
+    }
+}
+
+impl Pattern {
+    /// Compiles a pattern from characters paired with whether they were
+    /// quoted.
+    pub fn new(chars: &[(char, bool)]) -> Self {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (c, quoted) = chars[i];
+            i += 1;
+            if quoted {
+                tokens.push(Token::Literal(c));
+                continue;
+            }
+            match c {
+                '*' => {
+                    if !matches!(tokens.last(), Some(Token::Star)) {
+                        tokens.push(Token::Star);
+                    }
+                }
+                '?' => tokens.push(Token::Any),
+                '\\' if i < chars.len() => {
+                    tokens.push(Token::Literal(chars[i].0));
+                    i += 1;
+                }
+                '[' => match parse_bracket(chars, i) {
+                    Some((token, next)) => {
+                        tokens.push(token);
+                        i = next;
+                    }
+                    None => tokens.push(Token::Literal('[')),
+                },
+                _ => tokens.push(Token::Literal(c)),
+            }
+        }
+        Pattern { tokens }
+    }
+
+    /// Whether the pattern has any special characters.
+    pub fn is_literal(&self) -> bool {
+        self.tokens.iter().all(|t| matches!(t, Token::Literal(_)))
+    }
+
+    /// The text matched by a literal pattern.
+    fn literal_text(&self) -> String {
+        self.tokens
+            .iter()
+            .filter_map(|t| match t {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the pattern matches all of `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.matches_chars(&text)
+    }
+
+    fn matches_chars(&self, text: &[char]) -> bool {
+        let tokens = &self.tokens;
+        let (mut t, mut p) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+        loop {
+            if p < tokens.len() {
+                if let Token::Star = tokens[p] {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                if t < text.len() && tokens[p].matches(text[t]) {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+            } else if t == text.len() {
+                return true;
+            }
+            match backtrack {
+                Some((star, start)) if start < text.len() => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    t = start + 1;
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether the pattern matches a file name. A leading period must be
+    /// matched explicitly.
+    fn matches_file_name(&self, name: &str) -> bool {
+        if name.starts_with('.') && !matches!(self.tokens.first(), Some(Token::Literal('.'))) {
+            return false;
+        }
+        self.matches(name)
+    }
+
+    /// Removes the shortest or longest prefix of `text` matched by the
+    /// pattern.
+    pub fn remove_prefix<'a>(&self, text: &'a str, longest: bool) -> &'a str {
+        let chars: Vec<char> = text.chars().collect();
+        let mut ends: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect();
+        if longest {
+            ends.reverse();
+        }
+        for end in ends {
+            let count = text[..end].chars().count();
+            if self.matches_chars(&chars[..count]) {
+                return &text[end..];
+            }
+        }
+        text
+    }
+
+    /// Removes the shortest or longest suffix of `text` matched by the
+    /// pattern.
+    pub fn remove_suffix<'a>(&self, text: &'a str, longest: bool) -> &'a str {
+        let chars: Vec<char> = text.chars().collect();
+        let mut starts: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect();
+        if !longest {
+            starts.reverse();
+        }
+        for start in starts {
+            let count = text[..start].chars().count();
+            if self.matches_chars(&chars[count..]) {
+                return &text[..start];
+            }
+        }
+        text
+    }
+}
+
+/// Pathname expansion: returns the sorted paths matched by the pattern, or
+/// nothing if none match.
+pub fn expand_pathname(chars: &[(char, bool)]) -> Vec<String> {
+    let components: Vec<Pattern> = chars.split(|&(c, _)| c == '/').map(Pattern::new).collect();
+    if components.iter().all(Pattern::is_literal) {
+        return Vec::new();
+    }
+    let absolute = chars.first().is_some_and(|&(c, _)| c == '/');
+    let mut paths = vec![if absolute {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+    let components = if absolute {
+        &components[1..]
+    } else {
+        &components[..]
+    };
+    let last = components.len() - 1;
+    for (index, component) in components.iter().enumerate() {
+        let mut next = Vec::new();
+        for path in paths {
+            let separator = if index == last { "" } else { "/" };
+            if component.is_literal() {
+                let candidate = format!("{}{}", path, component.literal_text());
+                if index < last || fs::symlink_metadata(&candidate).is_ok() {
+                    next.push(format!("{}{}", candidate, separator));
+                }
+                continue;
+            }
+            let dir = if path.is_empty() { "." } else { &path };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| component.matches_file_name(name))
+                .collect();
+            names.sort();
+            for name in names {
+                next.push(format!("{}{}{}", path, name, separator));
+            }
+        }
+        paths = next;
+    }
+    // Paths ending in a slash matched only directories.
+    paths.retain(|p| !p.ends_with('/') || fs::metadata(p).is_ok_and(|m| m.is_dir()));
+    paths.sort();
+    paths
+}
@@ -0,0 +1,543 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The state of the shell: variables, parameters, options, functions and
+//! aliases, and the loop that reads and runs commands.
+
+use crate::arith::Variables;
+use crate::ast::FunctionBody;
+use crate::jobs::Jobs;
+use crate::parser::{is_name, LineSource, Parser};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// How control leaves a command other than by completing.
+#[derive(Debug)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    Return(i32),
+    Exit(i32),
+
+    /// An error that makes a non-interactive shell exit, and an interactive
+    /// one abandon the command line.
+    Error(i32),
+}
+
+pub type ExecResult = Result<i32, Flow>;
+
+#[derive(Clone, Debug, Default)]
+pub struct Variable {
+    /// `None` for a variable that is exported or readonly but unset.
+    pub value: Option<String>,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+/// The options set with `set`, by letter and name.
+pub const OPTIONS: &[(Option<char>, &str)] = &[
+    (Some('a'), "allexport"),
+    (Some('b'), "notify"),
+    (Some('C'), "noclobber"),
+    (Some('e'), "errexit"),
+    (Some('f'), "noglob"),
+    (Some('h'), "hashall"),
+    (Some('m'), "monitor"),
+    (Some('n'), "noexec"),
+    (Some('u'), "nounset"),
+    (Some('v'), "verbose"),
+    (Some('x'), "xtrace"),
+    (None, "ignoreeof"),
+    (None, "nolog"),
+    (None, "pipefail"),
+    (None, "vi"),
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub allexport: bool,
+    pub notify: bool,
+    pub noclobber: bool,
+    pub errexit: bool,
+    pub noglob: bool,
+    pub hashall: bool,
+    pub interactive: bool,
+    pub monitor: bool,
+    pub noexec: bool,
+    pub nounset: bool,
+    pub verbose: bool,
+    pub xtrace: bool,
+    pub ignoreeof: bool,
+    pub nolog: bool,
+    pub pipefail: bool,
+    pub vi: bool,
+}
+
+impl Options {
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "allexport" => &mut self.allexport,
+            "notify" => &mut self.notify,
+            "noclobber" => &mut self.noclobber,
+            "errexit" => &mut self.errexit,
+            "noglob" => &mut self.noglob,
+            "hashall" => &mut self.hashall,
+            "monitor" => &mut self.monitor,
+            "noexec" => &mut self.noexec,
+            "nounset" => &mut self.nounset,
+            "verbose" => &mut self.verbose,
+            "xtrace" => &mut self.xtrace,
+            "ignoreeof" => &mut self.ignoreeof,
+            "nolog" => &mut self.nolog,
+            "pipefail" => &mut self.pipefail,
+            "vi" => &mut self.vi,
+            _ => return None,
+        })
+    }
+
+    pub fn get(&mut self, name: &str) -> bool {
+        self.flag(name).is_some_and(|f| *f)
+    }
+
+    /// Sets the option called `name`, returning false if there is none.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.flag(name) {
+            Some(flag) => {
+                *flag = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn name_of(letter: char) -> Option<&'static str> {
+        OPTIONS
+            .iter()
+            .find(|(l, _)| *l == Some(letter))
+            .map(|(_, name)| *name)
+    }
+
+    /// The value of `$-`.
+    pub fn letters(&self) -> String {
+        let mut options = self.clone();
+        let mut letters: String = OPTIONS
+            .iter()
+            .filter_map(|(letter, name)| letter.filter(|_| options.get(name)))
+            .collect();
+        if self.interactive {
+            letters.push('i');
+        }
+        letters
+    }
+}
+
+/// The state shared with the input of the shell, for prompting and `-v`.
+#[derive(Default)]
+pub struct InputState {
+    pub prompt: bool,
+    pub ps1: String,
+    pub ps2: String,
+    pub verbose: bool,
+}
+
+/// Writes all of `bytes` to a file descriptor. The shell writes without
+/// buffering so that nothing is written twice after a fork.
+pub fn write_fd(fd: i32, bytes: &[u8]) -> bool {
+    let mut written = 0;
+    while written < bytes.len() {
+        let n = unsafe {
+            libc::write(
+                fd,
+                bytes[written..].as_ptr() as *const libc::c_void,
+                bytes.len() - written,
+            )
+        };
+        if n < 0 {
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return false;
+        }
+        written += n as usize;
+    }
+    true
+}
+
+/// Reads one line from a file descriptor, a byte at a time so that nothing
+/// past the newline is consumed. Returns `None` at end of file.
+pub fn read_line_fd(fd: i32, delimiter: u8) -> Option<Vec<u8>> {
+    let mut line = Vec::new();
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if n < 0 {
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                if crate::trap::take_interrupt() {
+                    // An interrupt at the prompt discards the line.
+                    line.clear();
+                    write_fd(2, b"\n");
+                    return Some(b"\n".to_vec());
+                }
+                continue;
+            }
+            return if line.is_empty() { None } else { Some(line) };
+        }
+        if n == 0 {
+            return if line.is_empty() { None } else { Some(line) };
+        }
+        line.push(byte);
+        if byte == delimiter {
+            return Some(line);
+        }
+    }
+}
+
+/// Lines read from a file descriptor.
+pub struct FdSource {
+    pub fd: i32,
+}
+
+impl LineSource for FdSource {
+    fn read_line(&mut self, _continuation: bool) -> Option<String> {
+        read_line_fd(self.fd, b'\n').map(|line| String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+/// Wraps the source of the shell's commands to write prompts and, with
+/// `-v`, to echo the input.
+pub struct Input {
+    pub source: Box<dyn LineSource>,
+    pub state: Rc<RefCell<InputState>>,
+}
+
+impl LineSource for Input {
+    fn read_line(&mut self, continuation: bool) -> Option<String> {
+        {
+            let state = self.state.borrow();
+            if state.prompt {
+                let prompt = if continuation { &state.ps2 } else { &state.ps1 };
+                write_fd(2, prompt.as_bytes());
+            }
+        }
+        let line = self.source.read_line(continuation)?;
+        if self.state.borrow().verbose {
+            write_fd(2, line.as_bytes());
+        }
+        Some(line)
+    }
+}
+
+pub struct Shell {
+    pub vars: HashMap<String, Variable>,
+
+    /// `$0`
+    pub name: String,
+    pub positional: Vec<String>,
+    pub functions: HashMap<String, Rc<FunctionBody>>,
+    pub aliases: HashMap<String, String>,
+    pub options: Options,
+
+    /// `$?`
+    pub last_status: i32,
+
+    /// `$!`
+    pub last_async: Option<libc::pid_t>,
+
+    /// `$$`, which subshells inherit.
+    pub shell_pid: libc::pid_t,
+
+    /// The actions of traps, by signal number, with 0 for EXIT.
+    pub traps: BTreeMap<i32, String>,
+
+    /// Signals ignored when the shell started, which cannot be trapped.
+    pub ignored_on_entry: Vec<i32>,
+
+    pub jobs: Jobs,
+
+    /// Remembered locations of utilities.
+    pub hash: HashMap<String, PathBuf>,
+
+    pub function_depth: usize,
+    pub dot_depth: usize,
+    pub loop_depth: usize,
+
+    /// Nonzero while running commands whose failure does not trigger
+    /// `errexit`, such as `if` conditions.
+    pub errexit_suppressed: usize,
+
+    /// This is a subshell running in a child process.
+    pub subshell: bool,
+
+    /// The terminal used for job control, and the shell's process group.
+    pub tty: i32,
+    pub pgid: libc::pid_t,
+
+    /// Where `getopts` is within a group of options, and the OPTIND it
+    /// last set.
+    pub getopts_char: usize,
+    pub getopts_optind: String,
+
+    /// The status of the last command substitution of a command.
+    pub substitution_status: Option<i32>,
+
+    pub input_state: Rc<RefCell<InputState>>,
+}
+
+impl Variables for Shell {
+    fn get(&self, name: &str) -> Option<String> {
+        self.var(name).map(str::to_string)
+    }
+
+    fn set(&mut self, name: &str, value: String) -> Result<(), String> {
+        self.set_var(name, value)
+    }
+}
+
+impl Shell {
+    pub fn new(name: String, positional: Vec<String>) -> Self {
+        let mut shell = Shell {
+            vars: HashMap::new(),
+            name,
+            positional,
+            functions: HashMap::new(),
+            aliases: HashMap::new(),
+            options: Options::default(),
+            last_status: 0,
+            last_async: None,
+            shell_pid: unsafe { libc::getpid() },
+            traps: BTreeMap::new(),
+            ignored_on_entry: Vec::new(),
+            jobs: Jobs::default(),
+            hash: HashMap::new(),
+            function_depth: 0,
+            dot_depth: 0,
+            loop_depth: 0,
+            errexit_suppressed: 0,
+            subshell: false,
+            tty: -1,
+            pgid: 0,
+            getopts_char: 0,
+            getopts_optind: String::new(),
+            substitution_status: None,
+            input_state: Rc::new(RefCell::new(InputState::default())),
+        };
+        for (name, value) in std::env::vars() {
+            if is_name(&name) {
+                shell.vars.insert(
+                    name,
+                    Variable {
+                        value: Some(value),
+                        exported: true,
+                        readonly: false,
+                    },
+                );
+            }
+        }
+        shell
+    }
+
+    /// Sets the variables the shell itself maintains.
+    pub fn init_variables(&mut self) {
+        let ps1 = if unsafe { libc::geteuid() } == 0 {
+            "# "
+        } else {
+            "$ "
+        };
+        for (name, value) in [("PS1", ps1), ("PS2", "> "), ("PS4", "+ ")] {
+            if self.var(name).is_none() {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value: Some(value.to_string()),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+        self.vars.insert(
+            "IFS".to_string(),
+            Variable {
+                value: Some(" \t\n".to_string()),
+                ..Default::default()
+            },
+        );
+        let _ = self.set_var("OPTIND", "1".to_string());
+        let _ = self.set_var("PPID", unsafe { libc::getppid() }.to_string());
+
+        // PWD is inherited if it names the current directory.
+        let current = std::env::current_dir().ok();
+        let inherited = self.var("PWD").map(PathBuf::from);
+        let valid = match (&inherited, &current) {
+            (Some(pwd), Some(current)) => {
+                pwd.is_absolute() && std::fs::canonicalize(pwd).ok().as_ref() == Some(current)
+            }
+            _ => false,
+        };
+        if !valid {
+            if let Some(current) = current {
+                let _ = self.set_var("PWD", current.to_string_lossy().into_owned());
+            }
+        }
+        if let Some(pwd) = self.vars.get_mut("PWD") {
+            pwd.exported = true;
+        }
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).and_then(|v| v.value.as_deref())
+    }
+
+    pub fn set_var(&mut self, name: &str, value: String) -> Result<(), String> {
+        let allexport = self.options.allexport;
+        let var = self.vars.entry(name.to_string()).or_default();
+        if var.readonly {
+            return Err(format!("{}: is read only", name));
+        }
+        var.value = Some(value);
+        var.exported |= allexport;
+        if name == "PATH" {
+            self.hash.clear();
+        }
+        Ok(())
+    }
+
+    pub fn unset_var(&mut self, name: &str) -> Result<(), String> {
+        match self.vars.get(name) {
+            Some(var) if var.readonly => Err(format!("{}: is read only", name)),
+            _ => {
+                self.vars.remove(name);
+                if name == "PATH" {
+                    self.hash.clear();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The environment of utilities: the exported variables.
+    pub fn environment(&self) -> Vec<CString> {
+        self.vars
+            .iter()
+            .filter(|(_, v)| v.exported)
+            .filter_map(|(name, v)| {
+                let value = v.value.as_ref()?;
+                CString::new(format!("{}={}", name, value)).ok()
+            })
+            .collect()
+    }
+
+    pub fn ifs(&self) -> String {
+        self.var("IFS").unwrap_or(" \t\n").to_string()
+    }
+
+    pub fn print_error(&self, message: &str) {
+        write_fd(2, format!("sh: {}\n", message).as_bytes());
+    }
+
+    /// Updates the prompts and verbosity of the input.
+    pub fn prepare_input(&mut self) {
+        let prompt = self.options.interactive && !self.subshell;
+        let ps1 = if prompt {
+            let ps1 = self.var("PS1").unwrap_or_default().to_string();
+            self.expand_prompt(&ps1)
+        } else {
+            String::new()
+        };
+        let ps2 = self.var("PS2").unwrap_or_default().to_string();
+        let mut state = self.input_state.borrow_mut();
+        state.prompt = prompt;
+        state.ps1 = ps1;
+        state.ps2 = ps2;
+        state.verbose = self.options.verbose;
+    }
+
+    /// Reads and runs commands from `parser` until the end of the input,
+    /// returning the exit status of the last command.
+    pub fn run(&mut self, parser: &mut Parser, top_level: bool) -> ExecResult {
+        let mut status = 0;
+        let mut eof_count = 0;
+        loop {
+            if top_level && self.options.interactive {
+                self.jobs.notify(true);
+                self.prepare_input();
+            } else if top_level {
+                self.jobs.notify(false);
+                self.input_state.borrow_mut().verbose = self.options.verbose;
+            }
+            let aliases = self.aliases.clone();
+            let list = match parser.parse_complete_command(&aliases) {
+                Ok(Some(list)) => list,
+                Ok(None) => {
+                    if top_level && self.options.interactive && self.options.ignoreeof {
+                        eof_count += 1;
+                        if eof_count < 10 {
+                            self.print_error("use \"exit\" to leave the shell");
+                            continue;
+                        }
+                    }
+                    return Ok(status);
+                }
+                Err(err) => {
+                    self.print_error(&err.to_string());
+                    self.last_status = 2;
+                    if top_level && self.options.interactive && !err.incomplete {
+                        parser.discard_line();
+                        continue;
+                    }
+                    return Err(Flow::Error(2));
+                }
+            };
+            eof_count = 0;
+            if self.options.noexec && !self.options.interactive {
+                continue;
+            }
+            match self.exec_list(&list) {
+                Ok(s) => status = s,
+                Err(Flow::Error(s)) if top_level && self.options.interactive => {
+                    self.last_status = s;
+                    status = s;
+                }
+                Err(Flow::Break(_) | Flow::Continue(_)) if top_level => {}
+                Err(flow) => return Err(flow),
+            }
+        }
+    }
+
+    /// Runs the commands in `text`, as for `eval` and traps.
+    pub fn eval_string(&mut self, text: &str) -> ExecResult {
+        let mut parser = Parser::from_string(text);
+        self.run(&mut parser, false)
+    }
+
+    /// Runs the EXIT trap, if any, once.
+    pub fn run_exit_trap(&mut self) {
+        if let Some(action) = self.traps.remove(&0) {
+            let status = self.last_status;
+            if let Err(Flow::Exit(s) | Flow::Error(s)) = self.eval_string(&action) {
+                self.last_status = s;
+                return;
+            }
+            self.last_status = status;
+        }
+    }
+
+    /// Leaves the shell, or the subshell in a child process.
+    pub fn exit(&mut self, status: i32) -> ! {
+        self.last_status = status;
+        self.run_exit_trap();
+        let status = self.last_status;
+        if self.subshell {
+            unsafe { libc::_exit(status & 0xff) }
+        }
+        std::process::exit(status & 0xff)
+    }
+}
@@ -0,0 +1,246 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Signals: their names, the shell's own dispositions, and the traps set
+//! with the `trap` builtin. Signal handlers only record that a signal
+//! arrived; trap actions are run between commands.
+
+use crate::shell::{Flow, Shell};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("SYS", libc::SIGSYS),
+];
+
+const MAX_SIGNAL: usize = 65;
+
+static PENDING: [AtomicBool; MAX_SIGNAL] = [const { AtomicBool::new(false) }; MAX_SIGNAL];
+static ANY_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// SIGINT interrupts reading a command line rather than running a trap.
+static INTERRUPT_LINE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handler(signal: libc::c_int) {
+    if (signal as usize) < MAX_SIGNAL {
+        PENDING[signal as usize].store(true, Ordering::SeqCst);
+        ANY_PENDING.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    SIGNALS.iter().find(|(_, s)| *s == signal).map(|(n, _)| *n)
+}
+
+/// Parses a signal name, with or without SIG, or number. EXIT is 0.
+pub fn parse_signal(text: &str) -> Option<i32> {
+    if let Ok(n) = text.parse::<i32>() {
+        return (n == 0 || signal_name(n).is_some()).then_some(n);
+    }
+    let upper = text.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if name == "EXIT" {
+        return Some(0);
+    }
+    SIGNALS.iter().find(|(n, _)| *n == name).map(|(_, s)| *s)
+}
+
+fn set_disposition(signal: i32, disposition: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = disposition;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// Records the signal when it arrives. System calls are interrupted rather
+/// than restarted, so that waiting for a command can be cut short.
+pub fn catch(signal: i32) {
+    set_disposition(
+        signal,
+        handler as *const extern "C" fn(libc::c_int) as libc::sighandler_t,
+    );
+}
+
+pub fn ignore(signal: i32) {
+    set_disposition(signal, libc::SIG_IGN);
+}
+
+pub fn default(signal: i32) {
+    set_disposition(signal, libc::SIG_DFL);
+}
+
+pub fn is_ignored(signal: i32) -> bool {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(signal, std::ptr::null(), &mut action);
+        action.sa_sigaction == libc::SIG_IGN
+    }
+}
+
+/// Whether an interrupt arrived while reading a command line.
+pub fn take_interrupt() -> bool {
+    INTERRUPT_LINE.load(Ordering::SeqCst)
+        && PENDING[libc::SIGINT as usize].swap(false, Ordering::SeqCst)
+}
+
+/// Takes the signals that arrived since the last call.
+pub fn take_pending() -> Vec<i32> {
+    if !ANY_PENDING.swap(false, Ordering::SeqCst) {
+        return Vec::new();
+    }
+    (1..MAX_SIGNAL)
+        .filter(|&s| PENDING[s].swap(false, Ordering::SeqCst))
+        .map(|s| s as i32)
+        .collect()
+}
+
+/// The signals an interactive shell handles itself.
+const INTERACTIVE_SIGNALS: &[i32] = &[libc::SIGINT, libc::SIGQUIT, libc::SIGTERM];
+const JOB_CONTROL_SIGNALS: &[i32] = &[libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+impl Shell {
+    /// Sets the shell's own dispositions for its mode.
+    pub fn init_signals(&mut self) {
+        self.ignored_on_entry = SIGNALS
+            .iter()
+            .map(|(_, s)| *s)
+            .filter(|&s| is_ignored(s))
+            .collect();
+        if self.options.interactive {
+            INTERRUPT_LINE.store(true, Ordering::SeqCst);
+            catch(libc::SIGINT);
+            ignore(libc::SIGQUIT);
+            ignore(libc::SIGTERM);
+        }
+        if self.options.monitor {
+            for &signal in JOB_CONTROL_SIGNALS {
+                ignore(signal);
+            }
+        }
+    }
+
+    /// The disposition a signal has when no trap is set for it.
+    fn reset_signal(&self, signal: i32) {
+        if self.ignored_on_entry.contains(&signal) {
+            ignore(signal);
+        } else if self.options.interactive && !self.subshell && signal == libc::SIGINT {
+            catch(signal);
+        } else if self.options.interactive
+            && !self.subshell
+            && INTERACTIVE_SIGNALS.contains(&signal)
+            || self.options.monitor && !self.subshell && JOB_CONTROL_SIGNALS.contains(&signal)
+        {
+            ignore(signal);
+        } else {
+            default(signal);
+        }
+    }
+
+    /// Sets the action for a signal; an empty action ignores it and `None`
+    /// restores the default.
+    pub fn set_trap(&mut self, signal: i32, action: Option<String>) {
+        if signal != 0 && self.ignored_on_entry.contains(&signal) && !self.options.interactive {
+            return;
+        }
+        match action {
+            Some(action) => {
+                if signal != 0 {
+                    if action.is_empty() {
+                        ignore(signal);
+                    } else {
+                        catch(signal);
+                    }
+                }
+                self.traps.insert(signal, action);
+            }
+            None => {
+                self.traps.remove(&signal);
+                if signal != 0 {
+                    self.reset_signal(signal);
+                }
+            }
+        }
+    }
+
+    /// In a new subshell: traps other than ignored signals are reset, and
+    /// signals the interactive shell handled get their defaults.
+    pub fn reset_traps_for_subshell(&mut self) {
+        self.traps.retain(|_, action| action.is_empty());
+        INTERRUPT_LINE.store(false, Ordering::SeqCst);
+        for &signal in INTERACTIVE_SIGNALS.iter().chain(JOB_CONTROL_SIGNALS) {
+            if !self.traps.contains_key(&signal) {
+                self.reset_signal(signal);
+            }
+        }
+        for (_, signal) in SIGNALS {
+            if !self.traps.contains_key(signal) && !self.ignored_on_entry.contains(signal) {
+                let _ = PENDING[*signal as usize].swap(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Runs the actions of trapped signals that have arrived.
+    pub fn run_pending_traps(&mut self) -> Result<(), Flow> {
+        for signal in take_pending() {
+            let Some(action) = self.traps.get(&signal).cloned() else {
+                // An interrupt abandons the command line.
+                if signal == libc::SIGINT && self.options.interactive && !self.subshell {
+                    return Err(Flow::Error(128 + signal));
+                }
+                continue;
+            };
+            if action.is_empty() {
+                continue;
+            }
+            let status = self.last_status;
+            match self.eval_string(&action) {
+                Ok(_) => self.last_status = status,
+                Err(Flow::Return(_)) => self.last_status = status,
+                Err(flow) => return Err(flow),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a trapped signal arrived, so that `wait` should return.
+    pub fn trap_pending(&self) -> Option<i32> {
+        (1..MAX_SIGNAL as i32).find(|&s| {
+            PENDING[s as usize].load(Ordering::SeqCst)
+                && self.traps.get(&s).is_some_and(|a| !a.is_empty())
+        })
+    }
+}
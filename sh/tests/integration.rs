@@ -0,0 +1,145 @@
+use plib::{run_test, TestPlan};
+
+fn test_sh(args: &[&str], stdin_data: &str, expected_output: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("sh"),
+        args: args.iter().map(|s| s.to_string()).collect(),
+        stdin_data: String::from(stdin_data),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code,
+    });
+}
+
+fn test_sh_error(args: &[&str], expected_error: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("sh"),
+        args: args.iter().map(|s| s.to_string()).collect(),
+        stdin_data: String::new(),
+        expected_out: String::new(),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
+macro_rules! test_script {
+    ($test_name:ident) => {
+        test_sh(
+            &[concat!("tests/sh/", stringify!($test_name), ".sh")],
+            "",
+            include_str!(concat!("sh/", stringify!($test_name), ".out")),
+            0,
+        )
+    };
+}
+
+#[test]
+fn test_sh_parameter_expansion() {
+    test_script!(parameter_expansion);
+}
+
+#[test]
+fn test_sh_field_splitting() {
+    test_script!(field_splitting);
+}
+
+#[test]
+fn test_sh_pathname_expansion() {
+    test_script!(pathname_expansion);
+}
+
+#[test]
+fn test_sh_arithmetic() {
+    test_script!(arithmetic);
+}
+
+#[test]
+fn test_sh_compound_commands() {
+    test_script!(compound_commands);
+}
+
+#[test]
+fn test_sh_functions() {
+    test_script!(functions);
+}
+
+#[test]
+fn test_sh_here_documents() {
+    test_script!(here_documents);
+}
+
+#[test]
+fn test_sh_redirections() {
+    test_script!(redirections);
+}
+
+#[test]
+fn test_sh_builtins() {
+    test_script!(builtins);
+}
+
+#[test]
+fn test_sh_command_string() {
+    test_sh(
+        &["-c", "echo \"$0 $1 $#\"", "name", "arg"],
+        "",
+        "name arg 1\n",
+        0,
+    );
+}
+
+#[test]
+fn test_sh_commands_from_stdin() {
+    test_sh(&[], "x=1\necho $x\nexit 3\necho no\n", "1\n", 3);
+}
+
+#[test]
+fn test_sh_exit_status_of_last_command() {
+    test_sh(&["-c", "true; false"], "", "", 1);
+}
+
+#[test]
+fn test_sh_errexit() {
+    test_sh(
+        &["-e", "-c", "echo before; false; echo after"],
+        "",
+        "before\n",
+        1,
+    );
+}
+
+#[test]
+fn test_sh_command_not_found() {
+    test_sh_error(
+        &["-c", "no_such_command_xyz"],
+        "sh: no_such_command_xyz: not found\n",
+        127,
+    );
+}
+
+#[test]
+fn test_sh_syntax_error() {
+    test_sh_error(
+        &["-c", "if true; then echo x; fi fi"],
+        "sh: line 1: syntax error: unexpected 'fi'\n",
+        2,
+    );
+}
+
+#[test]
+fn test_sh_cd_error() {
+    test_sh_error(
+        &["-c", "cd /nonexistent"],
+        "sh: cd: /nonexistent: No such file or directory\n",
+        1,
+    );
+}
+
+#[test]
+fn test_sh_redirection_error() {
+    test_sh_error(
+        &["-c", "echo x > /nonexistent/file"],
+        "sh: /nonexistent/file: No such file or directory\n",
+        1,
+    );
+}
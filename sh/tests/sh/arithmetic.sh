@@ -0,0 +1,11 @@
+x=7
+echo $((x + 3 * 2)) $(((x + 3) * 2))
+echo $((x / 2)) $((x % 3)) $((-x))
+echo $((x << 2)) $((x >> 1)) $((x & 3)) $((x | 8)) $((x ^ 1)) $((~x))
+echo $((x > 5 ? 1 : 0)) $((x == 7 && 0)) $((0 || x))
+echo $((010)) $((0x1f))
+: $((y = 4)) $((y += 2))
+echo $y
+i=0
+while [ $i -lt 5 ]; do i=$((i + 1)); done
+echo $i
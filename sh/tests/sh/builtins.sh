@@ -0,0 +1,24 @@
+set -- a b c
+shift
+echo "$# $1"
+eval 'echo "eval $1"'
+while getopts ab:c opt -a -b value -c operand; do
+    echo "$opt ${OPTARG-none}"
+done
+echo "OPTIND=$OPTIND"
+read x y <<EOF2
+first second third
+EOF2
+echo "$x|$y"
+readonly constant=1
+(constant=2) 2>/dev/null || echo "readonly"
+alias say='echo alias:'
+eval 'say expanded'
+unset x
+echo "${x-unset}"
+trap 'echo trapped' USR1
+kill -USR1 $$
+trap - USR1
+trap 'echo exiting' EXIT
+command -v cd
+type echo
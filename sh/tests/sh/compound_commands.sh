@@ -0,0 +1,23 @@
+for word in one two three; do
+    case $word in
+    one) echo first ;;
+    t*) echo "starts with t: $word" ;;
+    esac
+done
+if [ -d / ]; then echo root; else echo none; fi
+n=0
+until [ $n -ge 3 ]; do n=$((n + 1)); done
+echo "n=$n"
+for i in 1 2 3; do
+    for j in a b c; do
+        [ $j = b ] && continue 2
+        echo "$i$j"
+    done
+done
+{ echo brace; echo group; } | tr a-z A-Z
+(cd /; pwd)
+echo "subshell status $(exit 3; echo no) $?"
+false || echo "or list"
+true && echo "and list"
+! true
+echo "negated $?"
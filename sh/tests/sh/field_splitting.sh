@@ -0,0 +1,14 @@
+set -- "a b" c
+for arg in "$@"; do echo "[$arg]"; done
+for arg in $*; do echo "<$arg>"; done
+echo "$*"
+IFS=:
+echo "$*"
+list="x::y:"
+set -- $list
+echo $#
+for f in $list; do echo "($f)"; done
+IFS=' '
+empty=
+set -- $empty "" $empty
+echo $#
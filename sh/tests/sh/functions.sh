@@ -0,0 +1,18 @@
+greet() {
+    echo "hello, $1 ($#)"
+    return 2
+}
+greet world extra
+echo "status $?"
+count() {
+    if [ "$1" -gt 0 ]; then
+        echo "$1"
+        count $(($1 - 1))
+    fi
+}
+count 3
+x=global
+change() { x=changed; }
+change
+echo "$x"
+echo "$@" "$#"
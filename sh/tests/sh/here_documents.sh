@@ -0,0 +1,16 @@
+name=world
+cat <<EOF2
+hello $name
+$(echo substituted) $((1 + 1))
+EOF2
+cat <<'EOF2'
+hello $name
+EOF2
+cat <<-EOF2
+	tabs are stripped
+	EOF2
+cat <<A; cat <<B
+first
+A
+second
+B
@@ -0,0 +1,12 @@
+unset u
+e=
+v=value
+echo "${u-default}" "${e-default}" "${e:-default}"
+echo "${u+set}" "${v+set}" "${e:+set}"
+echo "${u=assigned}" "$u"
+echo "${#v}"
+path=/usr/local/lib/libfoo.so.1
+echo "${path#*/}" "${path##*/}"
+echo "${path%.*}" "${path%%.*}"
+echo "${v:?}"
+(echo "${unset_var:?is not set}") 2>/dev/null || echo "error status $?"
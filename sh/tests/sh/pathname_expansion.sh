@@ -0,0 +1,15 @@
+dir=${TMPDIR:-/tmp}/sh-glob.$$
+mkdir "$dir"
+cd "$dir" || exit 1
+touch a.txt b.txt c.log .hidden
+echo *.txt
+echo *
+echo .h*
+echo [ab]*
+echo [!a]*.txt
+echo nomatch*
+echo "*.txt"
+set -f
+echo *.txt
+set +f
+cd / && rm -rf "$dir"
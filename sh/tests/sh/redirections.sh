@@ -0,0 +1,16 @@
+tmp=${TMPDIR:-/tmp}/sh-redirections.$$
+echo one >"$tmp"
+echo two >>"$tmp"
+cat <"$tmp"
+exec 3<"$tmp"
+read first <&3
+read second <&3
+exec 3<&-
+echo "$first $second"
+{ echo to stderr >&2; } 2>&1
+ls "$tmp.missing" 2>/dev/null || echo missing
+set -C
+(echo clobber >"$tmp") 2>/dev/null || echo noclobber
+echo forced >|"$tmp"
+cat "$tmp"
+rm -f "$tmp"
@@ -12,8 +12,8 @@
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::regex::{Regex, RegexFlags};
 use plib::PROJECT_NAME;
-use regex::Regex;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, Error, ErrorKind, Read, Write};
 use std::path::PathBuf;
@@ -236,7 +236,7 @@ fn csplit_file(args: &Args, ctx: SplitOps, new_files: &mut Vec<String>) -> io::R
                 if line == "\n" {
                     line = String::new();
                 }
-                if regex.is_match(&line) {
+                if regex.is_match(line.strip_suffix('\n').unwrap_or(&line).as_bytes()) {
                     match offset.cmp(&0) {
                         std::cmp::Ordering::Less => {
                             let mut lines_vec: Vec<&str> = lines.lines().collect();
@@ -452,7 +452,7 @@ fn parse_op_rx(opstr: &str, delim: char) -> io::Result<Operand> {
     // parse string sandwiched between two delimiter chars
     let end_pos = res.unwrap();
     let re_str = &opstr[1..end_pos];
-    let res = Regex::new(re_str);
+    let res = Regex::new(re_str, RegexFlags::default());
     if res.is_err() {
         return Err(Error::new(ErrorKind::Other, "invalid regex"));
     }
@@ -499,13 +499,17 @@ fn parse_op_rx(opstr: &str, delim: char) -> io::Result<Operand> {
 ///
 fn parse_op_repeat(opstr: &str) -> io::Result<Operand> {
     // a regex fully describes what must be parsed
-    let re = Regex::new(r"^\{(\d*|[*])}$").unwrap();
+    let flags = RegexFlags {
+        extended: true,
+        ..Default::default()
+    };
+    let re = Regex::new(r"^\{([0-9]*|[*])\}$", flags).unwrap();
 
     // grab and parse capture #1, if matched
-    match re.captures(opstr) {
+    match re.captures(opstr.as_bytes()) {
         None => {}
         Some(caps) => {
-            let numstr = caps.get(1).unwrap().as_str();
+            let numstr = &opstr[caps[1].clone().unwrap()];
             if numstr == "*" {
                 return Ok(Operand::Repeat(usize::MAX));
             }
@@ -903,7 +907,7 @@ mod tests {
             suppress: false,
             filename: PathBuf::from("tests/assets/test_file_c"),
             operands: vec![
-                String::from(r"%main\(%"),
+                String::from("%main(%"),
                 String::from("/^}/+1"),
                 String::from("{3}"),
             ],
@@ -960,7 +964,7 @@ mod tests {
             suppress: false,
             filename: PathBuf::from("tests/assets/test_file_c"),
             operands: vec![
-                String::from(r"%main\(%+1"),
+                String::from("%main(%+1"),
                 String::from("/^}/+1"),
                 String::from("{3}"),
             ],
@@ -1016,7 +1020,7 @@ mod tests {
             suppress: false,
             filename: PathBuf::from("tests/assets/test_file_c"),
             operands: vec![
-                String::from(r"%main\(%-1"),
+                String::from("%main(%-1"),
                 String::from("/^}/+1"),
                 String::from("{3}"),
             ],
@@ -1073,7 +1077,7 @@ mod tests {
             suppress: false,
             filename: PathBuf::from("tests/assets/test_file_c"),
             operands: vec![
-                String::from(r"%main\(%"),
+                String::from("%main(%"),
                 String::from("/^}/"),
                 String::from("{3}"),
             ],
@@ -1130,7 +1134,7 @@ mod tests {
             suppress: false,
             filename: PathBuf::from("tests/assets/test_file_c"),
             operands: vec![
-                String::from(r"%main\(%"),
+                String::from("%main(%"),
                 String::from("/^}/-1"),
                 String::from("{3}"),
             ],
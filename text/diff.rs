@@ -25,7 +25,10 @@ use diff_util::{
     functions::check_existance,
 };
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
-use plib::PROJECT_NAME;
+use plib::{
+    regex::{Regex, RegexFlags},
+    PROJECT_NAME,
+};
 
 /// diff - compare two files
 #[derive(Parser, Clone)]
@@ -35,6 +38,22 @@ struct Args {
     #[arg(short = 'b', long = "ignore-space-change")]
     ignore_eol_space: bool,
 
+    /// Ignore changes whose lines are all blank
+    #[arg(short = 'B', long = "ignore-blank-lines")]
+    ignore_blank_lines: bool,
+
+    /// Ignore case differences
+    #[arg(short = 'i', long = "ignore-case")]
+    ignore_case: bool,
+
+    /// Ignore changes whose lines all match the basic regular expression <RE>
+    #[arg(short = 'I', long = "ignore-matching-lines", value_name = "RE")]
+    ignore_matching_lines: Vec<String>,
+
+    /// Ignore all white space
+    #[arg(short = 'w', long = "ignore-all-space")]
+    ignore_all_space: bool,
+
     /// Output 3 lines of copied context
     #[arg(short)]
     context3: bool,
@@ -63,8 +82,33 @@ struct Args {
     #[arg(short='U', value_parser = clap::value_parser!(u32).range(0..))]
     unified: Option<u32>,
 
-    /// First comparison file (or directory, if -r is specified)
-    file1: String,
+    /// Output the files side by side
+    #[arg(short = 'y', long)]
+    side_by_side: bool,
+
+    /// Output at most <N> columns with -y
+    #[arg(short = 'W', long, default_value_t = 130, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+
+    /// Only report whether the files differ
+    #[arg(short = 'q', long)]
+    brief: bool,
+
+    /// Treat absent files as empty
+    #[arg(short = 'N', long = "new-file")]
+    new_file: bool,
+
+    /// Skip files and directories whose name matches the pattern <PAT>
+    #[arg(short = 'x', long = "exclude", value_name = "PAT")]
+    exclude: Vec<String>,
+
+    /// Skip files and directories whose name matches a pattern in <FILE>
+    #[arg(short = 'X', long = "exclude-from", value_name = "FILE")]
+    exclude_from: Vec<PathBuf>,
+
+    /// Compare <FILE1> to each of the operands
+    #[arg(long, value_name = "FILE1")]
+    from_file: Option<PathBuf>,
 
     #[arg(long, value_parser= clap::value_parser!(String))]
     label: Option<String>,
@@ -72,8 +116,10 @@ struct Args {
     #[arg(long, value_parser= clap::value_parser!(String))]
     label2: Option<String>,
 
-    /// Second comparison file (or directory, if -r is specified)
-    file2: String,
+    /// The two comparison files (or directories, if -r is specified), or the
+    /// files to compare with the --from-file operand
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
 }
 
 impl From<&Args> for OutputFormat {
@@ -97,29 +143,61 @@ impl From<&Args> for OutputFormat {
             OutputFormat::Context(n as usize)
         } else if let Some(n) = args.unified {
             OutputFormat::Unified(n as usize)
+        } else if args.side_by_side {
+            OutputFormat::SideBySide(args.width as usize)
         } else {
             OutputFormat::Default
         }
     }
 }
 
-fn check_difference(args: Args) -> io::Result<DiffExitStatus> {
-    setlocale(LocaleCategory::LcAll, "");
-    textdomain(PROJECT_NAME)?;
-    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
-
-    let path1 = PathBuf::from(&args.file1);
-    let path2 = PathBuf::from(&args.file2);
+/// Compares a pair of operands, each of which may be a file or a directory.
+fn compare(
+    path1: PathBuf,
+    path2: PathBuf,
+    format_options: &FormatOptions,
+    recurse: bool,
+) -> io::Result<DiffExitStatus> {
+    let path1_exists = path1.exists();
+    let path2_exists = path2.exists();
 
-    let path1_exists = check_existance(&path1)?;
-    let path2_exists = check_existance(&path2)?;
+    // with -N one of the files may be absent, it is compared as empty
+    if !(format_options.new_file && (path1_exists || path2_exists)) {
+        let path1_exists = check_existance(&path1)?;
+        let path2_exists = check_existance(&path2)?;
 
-    if !path1_exists || !path2_exists {
-        return Ok(DiffExitStatus::Trouble);
+        if !path1_exists || !path2_exists {
+            return Ok(DiffExitStatus::Trouble);
+        }
     }
 
+    // a file never differs from itself
     if path1 == path2 {
-        return Ok(DiffExitStatus::Trouble);
+        return Ok(DiffExitStatus::NotDifferent);
+    }
+
+    let path1_is_file = !path1_exists || fs::metadata(&path1)?.is_file();
+    let path2_is_file = !path2_exists || fs::metadata(&path2)?.is_file();
+
+    if path1_is_file && path2_is_file {
+        FileDiff::file_diff(path1, path2, format_options, None)
+    } else if !path1_is_file && !path2_is_file {
+        DirDiff::dir_diff(path1, path2, format_options, recurse)
+    } else {
+        FileDiff::file_dir_diff(path1, path2, format_options)
+    }
+}
+
+fn check_difference(args: Args) -> io::Result<DiffExitStatus> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    if args.from_file.is_none() && args.files.len() != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "two files to compare are required",
+        ));
     }
 
     let output_format: OutputFormat = (&args).into();
@@ -130,18 +208,43 @@ fn check_difference(args: Args) -> io::Result<DiffExitStatus> {
         args.label,
         args.label2,
     );
-    let format_options = format_options.unwrap();
-
-    let path1_is_file = fs::metadata(&path1)?.is_file();
-    let path2_is_file = fs::metadata(&path2)?.is_file();
+    let mut format_options = format_options.unwrap();
+    format_options.ignore_all_white_spaces = args.ignore_all_space;
+    format_options.ignore_case = args.ignore_case;
+    format_options.ignore_blank_lines = args.ignore_blank_lines;
+    for pattern in &args.ignore_matching_lines {
+        let regex = Regex::new(pattern, RegexFlags::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        format_options.ignore_matching_lines.push(regex);
+    }
+    format_options.brief = args.brief;
+    format_options.new_file = args.new_file;
+    format_options.exclude_patterns = args.exclude;
+    for path in &args.exclude_from {
+        let patterns = fs::read_to_string(path)?;
+        format_options
+            .exclude_patterns
+            .extend(patterns.lines().map(String::from));
+    }
 
-    if path1_is_file && path2_is_file {
-        FileDiff::file_diff(path1, path2, &format_options, None)
-    } else if !path1_is_file && !path2_is_file {
-        DirDiff::dir_diff(path1, path2, &format_options, args.recurse)
-    } else {
-        FileDiff::file_dir_diff(path1, path2, &format_options)
+    let Some(from_file) = args.from_file else {
+        let [path1, path2] = <[PathBuf; 2]>::try_from(args.files).expect("two files were checked");
+        return compare(path1, path2, &format_options, args.recurse);
+    };
+
+    // the comparisons go on after trouble with one of them, like with
+    // the files of a directory
+    let mut exit_status = DiffExitStatus::NotDifferent;
+    for path in args.files {
+        let inner_exit_status = compare(from_file.clone(), path, &format_options, args.recurse)
+            .unwrap_or_else(|error| {
+                eprintln!("diff: {}", error);
+                DiffExitStatus::Trouble
+            });
+        exit_status = exit_status.combine(inner_exit_status);
     }
+
+    Ok(exit_status)
 }
 
 fn main() -> DiffExitStatus {
@@ -1,5 +1,25 @@
+use std::{borrow::Cow, ffi::OsStr, str::from_utf8};
+
+use plib::regex::Regex;
+
+use super::functions::pattern_matches;
+
 pub struct FormatOptions {
     pub ignore_trailing_white_spaces: bool,
+    /// Compare lines without any of their white space (`-w`).
+    pub ignore_all_white_spaces: bool,
+    /// Compare lines without regard to case (`-i`).
+    pub ignore_case: bool,
+    /// Ignore changes in which all lines are blank (`-B`).
+    pub ignore_blank_lines: bool,
+    /// Ignore changes in which all lines match one of these (`-I`).
+    pub ignore_matching_lines: Vec<Regex>,
+    /// Only report whether files differ (`-q`).
+    pub brief: bool,
+    /// Treat absent files as empty (`-N`).
+    pub new_file: bool,
+    /// File name patterns skipped when comparing directories (`-x`, `-X`).
+    pub exclude_patterns: Vec<String>,
     pub output_format: OutputFormat,
     label1: Option<String>,
     label2: Option<String>,
@@ -18,6 +38,13 @@ impl FormatOptions {
 
         Ok(Self {
             ignore_trailing_white_spaces,
+            ignore_all_white_spaces: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_matching_lines: Vec::new(),
+            brief: false,
+            new_file: false,
+            exclude_patterns: Vec::new(),
             output_format,
             label1,
             label2,
@@ -31,6 +58,70 @@ impl FormatOptions {
     pub fn label2(&self) -> &Option<String> {
         &self.label2
     }
+
+    /// Returns the text that `line` is compared by, with the differences that
+    /// are to be ignored removed.
+    pub fn comparison_key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let Ok(line) = from_utf8(line) else {
+            return self.byte_comparison_key(line);
+        };
+        let mut key = if self.ignore_all_white_spaces {
+            Cow::Owned(line.split_whitespace().collect())
+        } else if self.ignore_trailing_white_spaces {
+            Cow::Borrowed(line.trim_end())
+        } else {
+            Cow::Borrowed(line)
+        };
+        if self.ignore_case {
+            key = Cow::Owned(key.to_lowercase());
+        }
+        match key {
+            Cow::Borrowed(key) => Cow::Borrowed(key.as_bytes()),
+            Cow::Owned(key) => Cow::Owned(key.into_bytes()),
+        }
+    }
+
+    /// Like [`FormatOptions::comparison_key`] for a line that is not UTF-8,
+    /// in which only ASCII white space and letters are recognized.
+    fn byte_comparison_key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut key = if self.ignore_all_white_spaces {
+            Cow::Owned(
+                line.iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .collect(),
+            )
+        } else if self.ignore_trailing_white_spaces {
+            Cow::Borrowed(line.trim_ascii_end())
+        } else {
+            Cow::Borrowed(line)
+        };
+        if self.ignore_case {
+            key = Cow::Owned(key.to_ascii_lowercase());
+        }
+        key
+    }
+
+    /// Whether changes to `line` are ignored with `-B` or `-I`.
+    pub fn is_ignorable(&self, line: &[u8]) -> bool {
+        (self.ignore_blank_lines && self.comparison_key(line).is_empty())
+            || self
+                .ignore_matching_lines
+                .iter()
+                .any(|regex| regex.is_match(line))
+    }
+
+    /// Whether a directory entry named `file_name` is skipped.
+    pub fn is_excluded(&self, file_name: &OsStr) -> bool {
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| pattern_matches(pattern, file_name))
+    }
+
+    /// Whether some changes may be ignored, see [`FormatOptions::is_ignorable`].
+    pub fn ignores_changes(&self) -> bool {
+        self.ignore_blank_lines || !self.ignore_matching_lines.is_empty()
+    }
 }
 
 #[allow(dead_code)]
@@ -40,5 +131,7 @@ pub enum OutputFormat {
     Context(usize),
     EditScript,
     ForwardEditScript,
+    /// Both files next to each other, in lines of the given width.
+    SideBySide(usize),
     Unified(usize),
 }
@@ -18,6 +18,15 @@ impl DiffExitStatus {
             DiffExitStatus::Trouble => EXIT_STATUS_TROUBLE,
         }
     }
+
+    /// The status of two comparisons together, the more severe of the two.
+    pub fn combine(self, other: DiffExitStatus) -> DiffExitStatus {
+        if other.status_code() > self.status_code() {
+            other
+        } else {
+            self
+        }
+    }
 }
 
 impl Termination for DiffExitStatus {
@@ -14,9 +14,15 @@ pub struct DirData {
 }
 
 impl DirData {
-    pub fn load(path: PathBuf) -> io::Result<Self> {
+    /// Reads the entries of the directory at `path`. If `absent_is_empty`, a
+    /// directory which does not exist has no entries, as with `-N`.
+    pub fn load(path: PathBuf, absent_is_empty: bool) -> io::Result<Self> {
         let mut files: HashMap<OsString, DirEntry> = Default::default();
 
+        if absent_is_empty && !path.exists() {
+            return Ok(Self { path, files });
+        }
+
         let entries = fs::read_dir(&path)?;
 
         for entry in entries {
@@ -1,4 +1,9 @@
-use std::{collections::HashSet, ffi::OsString, io, path::PathBuf};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
 
 use crate::diff_util::{
     constants::COULD_NOT_UNWRAP_FILENAME, diff_exit_status::DiffExitStatus, file_diff::FileDiff,
@@ -34,8 +39,8 @@ impl<'a> DirDiff<'a> {
         format_options: &FormatOptions,
         recursive: bool,
     ) -> io::Result<DiffExitStatus> {
-        let mut dir1: DirData = DirData::load(path1)?;
-        let mut dir2: DirData = DirData::load(path2)?;
+        let mut dir1: DirData = DirData::load(path1, format_options.new_file)?;
+        let mut dir2: DirData = DirData::load(path2, format_options.new_file)?;
 
         let mut dir_diff = DirDiff::new(&mut dir1, &mut dir2, format_options, recursive);
         dir_diff.analyze()
@@ -44,24 +49,12 @@ impl<'a> DirDiff<'a> {
     fn analyze(&mut self) -> io::Result<DiffExitStatus> {
         let mut exit_status = DiffExitStatus::NotDifferent;
 
-        fn is_file(file_name: &OsString, dir_data: &DirData) -> io::Result<bool> {
-            let is_file = dir_data
+        fn is_file(file_name: &OsString, dir_data: &DirData) -> io::Result<Option<bool>> {
+            dir_data
                 .files()
-                .get_key_value(file_name)
-                .unwrap_or_else(|| {
-                    panic!(
-                        "Could not find file in {}",
-                        dir_data
-                            .path()
-                            .to_str()
-                            .unwrap_or(COULD_NOT_UNWRAP_FILENAME)
-                    )
-                })
-                .1
-                .file_type()?
-                .is_file();
-
-            Ok(is_file)
+                .get(file_name)
+                .map(|entry| Ok(entry.file_type()?.is_file()))
+                .transpose()
         }
 
         let mut dir1_files_name = self.dir1.files().keys().collect::<Vec<&OsString>>();
@@ -71,151 +64,159 @@ impl<'a> DirDiff<'a> {
         let mut unique_files_name = HashSet::<&OsString>::from_iter(dir1_files_name)
             .iter()
             .cloned()
+            .filter(|file_name| !self.format_options.is_excluded(file_name))
             .collect::<Vec<&OsString>>();
         unique_files_name.sort();
 
         for file_name in unique_files_name {
-            let in_dir1 = self.dir1.files().contains_key(file_name);
-            let in_dir2 = self.dir2.files().contains_key(file_name);
-
-            match (in_dir1, in_dir2) {
-                (true, true) => {
-                    let in_dir1_is_file = is_file(file_name, self.dir1)?;
-                    let in_dir2_is_file = is_file(file_name, self.dir2)?;
-
-                    let path1 = self.dir1.path().join(file_name);
-                    let path2 = self.dir2.path().join(file_name);
-
-                    if in_dir1_is_file && in_dir2_is_file {
-                        let mut show_if_different = String::from("diff ");
-
-                        match self.format_options.output_format {
-                            crate::diff_util::common::OutputFormat::Debug => {
-                                show_if_different.push_str("--debug ")
-                            }
-                            crate::diff_util::common::OutputFormat::Default => {}
-                            crate::diff_util::common::OutputFormat::Context(ctx) => {
-                                show_if_different.push_str(format!("-C {} ", ctx).as_str())
-                            }
-                            crate::diff_util::common::OutputFormat::EditScript => {
-                                show_if_different.push_str("-e ")
-                            }
-                            crate::diff_util::common::OutputFormat::ForwardEditScript => {
-                                show_if_different.push_str("-f ")
-                            }
-                            crate::diff_util::common::OutputFormat::Unified(ufd) => {
-                                show_if_different.push_str(format!("-U {} ", ufd).as_str())
-                            }
-                        }
-
-                        if self.recursive {
-                            show_if_different.push_str("-r ");
-                        }
-
-                        if self.format_options.ignore_trailing_white_spaces {
-                            show_if_different.push_str("-b ");
-                        }
-
-                        if let Some(label1) = &self.format_options.label1() {
-                            show_if_different.push_str(format!("--label {} ", label1).as_str())
-                        }
-
-                        if let Some(label2) = &self.format_options.label2() {
-                            show_if_different.push_str(format!("--label2 {} ", label2).as_str())
-                        }
-
-                        if let Some(label1) = &self.format_options.label1() {
-                            show_if_different.push_str(format!("{} ", label1).as_str())
-                        } else {
-                            show_if_different
-                                .push_str(path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME));
-                            show_if_different.push(' ');
-                        }
-
-                        if let Some(label2) = &self.format_options.label2() {
-                            show_if_different.push_str(format!("{} ", label2).as_str())
-                        } else {
-                            show_if_different
-                                .push_str(path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME));
-                            show_if_different.push(' ');
-                        }
-
-                        let inner_exit_status = FileDiff::file_diff(
-                            path1,
-                            path2,
-                            self.format_options,
-                            Some(show_if_different),
-                        )?;
-
-                        if exit_status.status_code() < inner_exit_status.status_code() {
-                            exit_status = inner_exit_status;
-                        }
-                    } else if !in_dir1_is_file && !in_dir2_is_file {
-                        if self.recursive {
-                            Self::dir_diff(
-                                self.dir1.path().join(file_name),
-                                self.dir2.path().join(file_name),
-                                self.format_options,
-                                self.recursive,
-                            )?;
-                        } else {
-                            println!(
-                                "Common subdirectories: \"{}\" and \"{}\"",
-                                self.dir1
-                                    .path()
-                                    .join(file_name)
-                                    .to_str()
-                                    .unwrap_or(COULD_NOT_UNWRAP_FILENAME),
-                                self.dir2
-                                    .path()
-                                    .join(file_name)
-                                    .to_str()
-                                    .unwrap_or(COULD_NOT_UNWRAP_FILENAME)
-                            );
-                        }
-                    } else {
-                        let (file, dir) = if in_dir1_is_file && !in_dir2_is_file {
-                            (
-                                path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
-                                path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
-                            )
-                        } else {
-                            (
-                                path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
-                                path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
-                            )
-                        };
+            let mut in_dir1_is_file = is_file(file_name, self.dir1)?;
+            let mut in_dir2_is_file = is_file(file_name, self.dir2)?;
+
+            // with -N an absent entry is compared as an empty one of the same kind
+            if self.format_options.new_file {
+                in_dir1_is_file = in_dir1_is_file.or(in_dir2_is_file);
+                in_dir2_is_file = in_dir2_is_file.or(in_dir1_is_file);
+            }
+
+            let path1 = self.dir1.path().join(file_name);
+            let path2 = self.dir2.path().join(file_name);
 
+            let inner_exit_status = match (in_dir1_is_file, in_dir2_is_file) {
+                (Some(true), Some(true)) => {
+                    let show_if_different = self.show_if_different(&path1, &path2);
+                    FileDiff::file_diff(path1, path2, self.format_options, Some(show_if_different))?
+                }
+                (Some(false), Some(false)) => {
+                    if self.recursive {
+                        Self::dir_diff(path1, path2, self.format_options, self.recursive)?
+                    } else {
                         println!(
-                            "File \"{}\" is a directory while file \"{}\" is a regular file",
-                            dir, file
+                            "Common subdirectories: \"{}\" and \"{}\"",
+                            path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
+                            path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME)
                         );
+                        DiffExitStatus::NotDifferent
                     }
                 }
-                (true, false) => {
+                (Some(in_dir1_is_file), Some(_)) => {
+                    let (file, dir) = if in_dir1_is_file {
+                        (
+                            path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
+                            path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
+                        )
+                    } else {
+                        (
+                            path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
+                            path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
+                        )
+                    };
+
+                    println!(
+                        "File \"{}\" is a directory while file \"{}\" is a regular file",
+                        dir, file
+                    );
+                    DiffExitStatus::Different
+                }
+                (Some(_), None) => {
                     println!(
                         "Only in {}: {}",
                         self.dir1.path_str(),
                         file_name.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME)
-                    )
+                    );
+                    DiffExitStatus::Different
                 }
-                (false, true) => {
+                (None, Some(_)) => {
                     println!(
                         "Only in {}: {}",
                         self.dir2.path_str(),
                         file_name.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME)
-                    )
+                    );
+                    DiffExitStatus::Different
                 }
-                (false, false) => {
+                (None, None) => {
                     eprintln!(
                         "At least one of directories should contain file \"{}\"",
                         file_name.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME)
                     );
                     return Ok(DiffExitStatus::Trouble);
                 }
-            }
+            };
+
+            exit_status = exit_status.combine(inner_exit_status);
         }
 
         Ok(exit_status)
     }
+
+    /// The line printed before the differences of two files in the
+    /// directories, like the command that shows them.
+    fn show_if_different(&self, path1: &Path, path2: &Path) -> String {
+        let mut show_if_different = String::from("diff ");
+
+        match self.format_options.output_format {
+            crate::diff_util::common::OutputFormat::Debug => show_if_different.push_str("--debug "),
+            crate::diff_util::common::OutputFormat::Default => {}
+            crate::diff_util::common::OutputFormat::Context(ctx) => {
+                show_if_different.push_str(format!("-C {} ", ctx).as_str())
+            }
+            crate::diff_util::common::OutputFormat::EditScript => show_if_different.push_str("-e "),
+            crate::diff_util::common::OutputFormat::ForwardEditScript => {
+                show_if_different.push_str("-f ")
+            }
+            crate::diff_util::common::OutputFormat::SideBySide(width) => {
+                show_if_different.push_str(format!("-y -W {} ", width).as_str())
+            }
+            crate::diff_util::common::OutputFormat::Unified(ufd) => {
+                show_if_different.push_str(format!("-U {} ", ufd).as_str())
+            }
+        }
+
+        if self.recursive {
+            show_if_different.push_str("-r ");
+        }
+
+        if self.format_options.ignore_trailing_white_spaces {
+            show_if_different.push_str("-b ");
+        }
+
+        if self.format_options.ignore_all_white_spaces {
+            show_if_different.push_str("-w ");
+        }
+
+        if self.format_options.ignore_case {
+            show_if_different.push_str("-i ");
+        }
+
+        if self.format_options.ignore_blank_lines {
+            show_if_different.push_str("-B ");
+        }
+
+        if self.format_options.new_file {
+            show_if_different.push_str("-N ");
+        }
+
+        if let Some(label1) = &self.format_options.label1() {
+            show_if_different.push_str(format!("--label {} ", label1).as_str())
+        }
+
+        if let Some(label2) = &self.format_options.label2() {
+            show_if_different.push_str(format!("--label2 {} ", label2).as_str())
+        }
+
+        if let Some(label1) = &self.format_options.label1() {
+            show_if_different.push_str(format!("{} ", label1).as_str())
+        } else {
+            show_if_different.push_str(path1.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME));
+            show_if_different.push(' ');
+        }
+
+        if let Some(label2) = &self.format_options.label2() {
+            show_if_different.push_str(format!("{} ", label2).as_str())
+        } else {
+            show_if_different.push_str(path2.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME));
+            show_if_different.push(' ');
+        }
+
+        show_if_different
+    }
 }
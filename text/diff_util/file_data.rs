@@ -1,11 +1,13 @@
-use std::{fs::File, io, mem::take, path::PathBuf, str::from_utf8, time::SystemTime};
+use std::{borrow::Cow, fs, io, mem::take, path::PathBuf, time::SystemTime};
 
 use super::constants::COULD_NOT_UNWRAP_FILENAME;
 
 #[derive(Debug)]
 pub struct FileData<'a> {
     path: PathBuf,
-    lines: Vec<&'a str>,
+    lines: Vec<&'a [u8]>,
+    /// The text each line is compared by, see `FormatOptions::comparison_key`.
+    keys: Vec<Cow<'a, [u8]>>,
     modified: SystemTime,
     ends_with_newline: bool,
 }
@@ -17,40 +19,40 @@ impl<'a> FileData<'a> {
 
     pub fn get_file(
         path: PathBuf,
-        lines: Vec<&'a str>,
+        lines: Vec<&'a [u8]>,
+        keys: Vec<Cow<'a, [u8]>>,
         ends_with_newline: bool,
     ) -> io::Result<Self> {
-        let file = File::open(&path)?;
-        let modified = file.metadata()?.modified()?;
+        // absent files, which are compared as empty with -N, date from the epoch
+        let modified = match fs::metadata(&path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => SystemTime::UNIX_EPOCH,
+            Err(error) => return Err(error),
+        };
 
         Ok(Self {
             path,
             lines,
+            keys,
             modified,
             ends_with_newline,
         })
     }
 
-    pub fn lines(&self) -> &Vec<&str> {
+    pub fn lines(&self) -> &Vec<&[u8]> {
         &self.lines
     }
 
-    pub fn line(&self, index: usize) -> &str {
+    pub fn line(&self, index: usize) -> &[u8] {
         self.lines[index]
     }
 
-    pub fn modified(&self) -> SystemTime {
-        self.modified
+    pub fn key(&self, index: usize) -> &[u8] {
+        &self.keys[index]
     }
 
-    pub fn name(&self) -> &str {
-        if let Some(os_str) = self.path.file_name() {
-            if let Some(str_slice) = os_str.to_str() {
-                return str_slice;
-            }
-        }
-
-        COULD_NOT_UNWRAP_FILENAME
+    pub fn modified(&self) -> SystemTime {
+        self.modified
     }
 
     pub fn path(&self) -> &str {
@@ -77,7 +79,7 @@ impl<'a> LineReader<'a> {
 }
 
 impl<'a> Iterator for LineReader<'a> {
-    type Item = &'a str;
+    type Item = &'a [u8];
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut carriage = false;
@@ -86,9 +88,7 @@ impl<'a> Iterator for LineReader<'a> {
             match iter.next() {
                 Some((i, b'\n')) => break i + 1,
                 None => {
-                    return (!self.content.is_empty()).then(|| {
-                        from_utf8(take(&mut self.content)).expect("Failed to convert to str")
-                    });
+                    return (!self.content.is_empty()).then(|| take(&mut self.content));
                 }
                 Some((_, &it)) => carriage = it == b'\r',
             }
@@ -98,6 +98,6 @@ impl<'a> Iterator for LineReader<'a> {
             line_len -= 1;
         }
         self.content = rest;
-        Some(from_utf8(&line[..line_len - 1]).expect("Failed to convert to str"))
+        Some(&line[..line_len - 1])
     }
 }
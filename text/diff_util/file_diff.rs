@@ -3,8 +3,9 @@ use super::{
     constants::COULD_NOT_UNWRAP_FILENAME,
     diff_exit_status::DiffExitStatus,
     file_data::{FileData, LineReader},
-    functions::{check_existance, is_binary, system_time_to_rfc2822},
+    functions::{check_existance, is_binary, print_line, system_time_to_rfc2822},
     hunks::Hunks,
+    side_by_side::SideBySide,
 };
 
 use crate::diff_util::constants::NO_NEW_LINE_AT_END_OF_FILE;
@@ -12,11 +13,9 @@ use crate::diff_util::constants::NO_NEW_LINE_AT_END_OF_FILE;
 use std::{
     cmp::Reverse,
     collections::HashMap,
-    fmt::Write,
-    fs::{read_to_string, File},
-    io::{self, BufReader, Read},
-    os::unix::fs::MetadataExt,
-    path::PathBuf,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
 };
 
 pub struct FileDiff<'a> {
@@ -56,34 +55,30 @@ impl<'a> FileDiff<'a> {
         format_options: &FormatOptions,
         show_if_different: Option<String>,
     ) -> io::Result<DiffExitStatus> {
-        if is_binary(&path1)? || is_binary(&path2)? {
-            Self::binary_file_diff(&path1, &path2)
+        let content1 = Self::read_content(&path1, format_options)?;
+        let content2 = Self::read_content(&path2, format_options)?;
+
+        if is_binary(&content1) || is_binary(&content2) {
+            Self::binary_file_diff(&path1, &path2, &content1, &content2, format_options)
         } else {
-            let content1 = read_to_string(&path1)?.into_bytes();
             let linereader1 = LineReader::new(&content1);
             let ends_with_newline1 = linereader1.ends_with_newline();
-            let mut lines1 = Vec::new();
-            for line in linereader1 {
-                if !format_options.ignore_trailing_white_spaces {
-                    lines1.push(line);
-                } else {
-                    lines1.push(line.trim_end());
-                }
-            }
+            let lines1: Vec<&[u8]> = linereader1.collect();
+            let keys1 = lines1
+                .iter()
+                .map(|line| format_options.comparison_key(line))
+                .collect();
 
-            let content2 = read_to_string(&path2)?.into_bytes();
             let linereader2 = LineReader::new(&content2);
             let ends_with_newline2 = linereader2.ends_with_newline();
-            let mut lines2 = Vec::new();
-            for line in linereader2 {
-                if !format_options.ignore_trailing_white_spaces {
-                    lines2.push(line);
-                } else {
-                    lines2.push(line.trim_end());
-                }
-            }
-            let mut file1 = FileData::get_file(path1, lines1, ends_with_newline1)?;
-            let mut file2 = FileData::get_file(path2, lines2, ends_with_newline2)?;
+            let lines2: Vec<&[u8]> = linereader2.collect();
+            let keys2 = lines2
+                .iter()
+                .map(|line| format_options.comparison_key(line))
+                .collect();
+
+            let mut file1 = FileData::get_file(path1, lines1, keys1, ends_with_newline1)?;
+            let mut file2 = FileData::get_file(path2, lines2, keys2, ends_with_newline2)?;
 
             let mut diff = FileDiff::new(&mut file1, &mut file2, format_options);
 
@@ -101,13 +96,34 @@ impl<'a> FileDiff<'a> {
                 &mut lcs_indices,
             );
 
+            // An unterminated final line never matches a terminated one, so
+            // that the missing newline shows up in the output.
+            for (i, lcs_index) in lcs_indices.iter_mut().enumerate() {
+                let unterminated1 = i + 1 == num_lines1 && !ends_with_newline1;
+                let unterminated2 = *lcs_index != -1
+                    && *lcs_index as usize + 1 == num_lines2
+                    && !ends_with_newline2;
+                if unterminated1 != unterminated2 {
+                    *lcs_index = -1;
+                }
+            }
+
             diff.hunks
                 .create_hunks_from_lcs(&lcs_indices, num_lines1, num_lines2);
 
+            if format_options.ignores_changes() {
+                diff.hunks
+                    .remove_ignorable(diff.file1, diff.file2, format_options);
+            }
+
             if diff.hunks.hunk_count() > 0 {
                 diff.set_different(true);
             }
 
+            if format_options.brief {
+                return Ok(diff.print_brief());
+            }
+
             if diff.are_different() {
                 if let Some(show_if_different) = show_if_different {
                     println!("{}", show_if_different);
@@ -130,7 +146,7 @@ impl<'a> FileDiff<'a> {
             let path1_file = path1_file.file_name().expect(COULD_NOT_UNWRAP_FILENAME);
             let path2 = path2.join(path1_file);
 
-            if !check_existance(&path2)? {
+            if !format_options.new_file && !check_existance(&path2)? {
                 return Ok(DiffExitStatus::Trouble);
             }
 
@@ -140,7 +156,7 @@ impl<'a> FileDiff<'a> {
             let path2_file = path2_file.file_name().expect(COULD_NOT_UNWRAP_FILENAME);
             let path1 = path1.join(path2_file);
 
-            if !check_existance(&path1)? {
+            if !format_options.new_file && !check_existance(&path1)? {
                 return Ok(DiffExitStatus::Trouble);
             }
 
@@ -148,66 +164,83 @@ impl<'a> FileDiff<'a> {
         }
     }
 
-    fn binary_file_diff(file1_path: &PathBuf, file2_path: &PathBuf) -> io::Result<DiffExitStatus> {
-        let differ_report = format!(
-            "Binary files {} and {} differ",
+    /// Reads the file at `path`, an absent file reads as empty with `-N`.
+    fn read_content(path: &Path, format_options: &FormatOptions) -> io::Result<Vec<u8>> {
+        match fs::read(path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound && format_options.new_file => {
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    fn binary_file_diff(
+        file1_path: &Path,
+        file2_path: &Path,
+        content1: &[u8],
+        content2: &[u8],
+        format_options: &FormatOptions,
+    ) -> io::Result<DiffExitStatus> {
+        if content1 == content2 {
+            return Ok(DiffExitStatus::NotDifferent);
+        }
+
+        println!(
+            "{} {} and {} differ",
+            if format_options.brief {
+                "Files"
+            } else {
+                "Binary files"
+            },
             file1_path.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME),
             file2_path.to_str().unwrap_or(COULD_NOT_UNWRAP_FILENAME)
         );
 
-        let file1 = File::open(file1_path)?;
-        let file2 = File::open(file2_path)?;
-
-        if file1.metadata()?.size() != file2.metadata()?.size() {
-            println!("{}", differ_report);
-            return Ok(DiffExitStatus::Different);
-        }
-
-        let file1 = BufReader::new(file1);
-        let file2 = BufReader::new(file2);
+        Ok(DiffExitStatus::Different)
+    }
 
-        for bytes_pair in file1.bytes().zip(file2.bytes()) {
-            let (b1, b2) = (bytes_pair.0?, bytes_pair.1?);
-            if b1 != b2 {
-                println!("{}", differ_report);
-                return Ok(DiffExitStatus::Different);
-            }
+    /// Reports only whether the files differ, for `-q`.
+    fn print_brief(&self) -> DiffExitStatus {
+        if self.are_different() {
+            println!(
+                "Files {} and {} differ",
+                self.file1.path(),
+                self.file2.path()
+            );
+            DiffExitStatus::Different
+        } else {
+            DiffExitStatus::NotDifferent
         }
-
-        Ok(DiffExitStatus::NotDifferent)
     }
 
     fn print(&mut self) -> io::Result<DiffExitStatus> {
         self.order_hunks_by_output_format();
 
-        if let OutputFormat::Context(context) = self.format_options.output_format {
-            let _ = self.print_context(context);
+        if let OutputFormat::SideBySide(width) = self.format_options.output_format {
+            // the whole files are shown, even if they are identical
+            self.print_side_by_side(width);
+        } else if self.hunks.hunk_count() == 0 {
+            // identical files produce no output in any format
+        } else if let OutputFormat::Context(context) = self.format_options.output_format {
+            self.print_context(context);
         } else if let OutputFormat::Unified(unified) = self.format_options.output_format {
-            let _ = self.print_unified(unified);
+            self.print_unified(unified);
         } else {
-            let hunks_count = self.hunks.hunks().len();
-
-            for hunk_index in 0..hunks_count {
-                let hunk = self.hunks.hunk_at_mut(hunk_index);
+            for hunk in self.hunks.hunks_mut() {
                 match self.format_options.output_format {
                     OutputFormat::Debug => hunk.print_debug(self.file1, self.file2),
-                    OutputFormat::Default => {
-                        hunk.print_default(self.file1, self.file2, hunk_index == hunks_count - 1)
+                    OutputFormat::Default => hunk.print_default(self.file1, self.file2),
+                    OutputFormat::EditScript | OutputFormat::ForwardEditScript => {
+                        hunk.print_edit_script(self.file2)
                     }
-                    OutputFormat::EditScript => hunk.print_edit_script(
-                        self.file1,
-                        self.file2,
-                        hunk_index == hunks_count - 1,
-                    ),
                     OutputFormat::Context(_) => {
                         eprintln!("OutputFormat::Context should be handled in other place");
                         return Ok(DiffExitStatus::Trouble);
                     }
-                    OutputFormat::ForwardEditScript => hunk.print_edit_script(
-                        self.file1,
-                        self.file2,
-                        hunk_index == hunks_count - 1,
-                    ),
+                    OutputFormat::SideBySide(_) => {
+                        eprintln!("OutputFormat::SideBySide should be handled in other place");
+                        return Ok(DiffExitStatus::Trouble);
+                    }
                     OutputFormat::Unified(_) => {
                         eprintln!("OutputFormat::Unified should be handled in other place");
                         return Ok(DiffExitStatus::Trouble);
@@ -237,7 +270,7 @@ impl<'a> FileDiff<'a> {
         lcs_indices: &mut Vec<i32>,
     ) {
         // collect common elements at the beginning
-        while (x0 < x1) && (y0 < y1) && (file1.line(x0) == file2.line(y0)) {
+        while (x0 < x1) && (y0 < y1) && (file1.key(x0) == file2.key(y0)) {
             lcs_indices[x0] = y0 as i32;
             x0 += 1;
             y0 += 1;
@@ -249,28 +282,28 @@ impl<'a> FileDiff<'a> {
         }
 
         // collect common elements at the end
-        while (x0 < x1) && (y0 < y1) && (file1.line(x1 - 1) == file2.line(y1 - 1)) {
+        while (x0 < x1) && (y0 < y1) && (file1.key(x1 - 1) == file2.key(y1 - 1)) {
             lcs_indices[x1 - 1] = (y1 - 1) as i32;
             x1 -= 1;
             y1 -= 1;
         }
 
         // build histogram
-        let mut hist: HashMap<&str, Vec<i32>> = HashMap::new();
+        let mut hist: HashMap<&[u8], Vec<i32>> = HashMap::new();
         for i in x0..x1 {
-            if let Some(rec) = hist.get_mut(file1.line(i)) {
+            if let Some(rec) = hist.get_mut(file1.key(i)) {
                 rec[0] += 1_i32;
                 rec[1] = i as i32;
             } else {
-                hist.insert(file1.line(i), vec![1, i as i32, 0, -1]);
+                hist.insert(file1.key(i), vec![1, i as i32, 0, -1]);
             }
         }
         for i in y0..y1 {
-            if let Some(rec) = hist.get_mut(file2.line(i)) {
+            if let Some(rec) = hist.get_mut(file2.key(i)) {
                 rec[2] += 1_i32;
                 rec[3] = i as i32;
             } else {
-                hist.insert(file2.line(i), vec![0, -1, 1, i as i32]);
+                hist.insert(file2.key(i), vec![0, -1, 1, i as i32]);
             }
         }
 
@@ -305,6 +338,7 @@ impl<'a> FileDiff<'a> {
             OutputFormat::Context(_) => self.order_hunks_ascending(),
             OutputFormat::EditScript => self.order_hunks_descending(),
             OutputFormat::ForwardEditScript => self.order_hunks_ascending(),
+            OutputFormat::SideBySide(_) => self.order_hunks_ascending(),
             OutputFormat::Unified(_) => self.order_hunks_ascending(),
         }
     }
@@ -321,7 +355,47 @@ impl<'a> FileDiff<'a> {
             .sort_by_key(|hunk| Reverse((hunk.ln1_end(), hunk.ln2_end())));
     }
 
-    fn print_context(&mut self, context: usize) -> Result<(), std::fmt::Error> {
+    /// Splits the hunks into groups whose surrounding context overlaps, each
+    /// group being printed as one context or unified hunk. Returns the hunk
+    /// index range of every group together with the line ranges it covers in
+    /// both files, context included.
+    fn context_groups(&self, context: usize) -> Vec<ContextGroup> {
+        let hunks = self.hunks.hunks();
+        let num_lines1 = self.file1.lines().len();
+        let mut groups = Vec::new();
+        let mut first = 0;
+        while first < hunks.len() {
+            let mut last = first;
+            while last + 1 < hunks.len()
+                && hunks[last + 1].ln1_start() - hunks[last].ln1_end() <= context * 2
+            {
+                last += 1;
+            }
+
+            let before = hunks[first].ln1_start().min(context);
+            let after = (num_lines1 - hunks[last].ln1_end()).min(context);
+            groups.push(ContextGroup {
+                hunks: first..last + 1,
+                start1: hunks[first].ln1_start() - before,
+                end1: hunks[last].ln1_end() + after,
+                start2: hunks[first].ln2_start() - before,
+                end2: hunks[last].ln2_end() + after,
+            });
+            first = last + 1;
+        }
+        groups
+    }
+
+    /// Prints `line` of `file` with `prefix`, adding the "No newline" marker
+    /// if it is an unterminated final line.
+    fn print_file_line(file: &FileData, line: usize, prefix: &str) {
+        print_line(prefix, file.line(line));
+        if line + 1 == file.lines().len() && !file.ends_with_newline() {
+            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
+        }
+    }
+
+    fn print_context(&mut self, context: usize) {
         println!(
             "*** {}",
             Self::get_header(self.file1, self.format_options.label1())
@@ -331,124 +405,57 @@ impl<'a> FileDiff<'a> {
             Self::get_header(self.file2, self.format_options.label2())
         );
 
-        let mut diff_disp = ContextDiffDisplay::new();
-
-        for hunk in self.hunks.hunks() {
-            // move cursor to the start of context for first hunk
-            if diff_disp.curr_pos1 == 0 {
-                if hunk.ln1_start() > context {
-                    // this is guaranteed to be >= 0 due to the above conditions
-                    diff_disp
-                        .update_curr_pos(hunk.ln1_start() - context, hunk.ln2_start() - context);
+        for group in self.context_groups(context) {
+            let hunks = &self.hunks.hunks()[group.hunks.clone()];
+            println!("***************");
+
+            println!("*** {} ****", context_range(group.start1, group.end1));
+            if hunks.iter().any(|hunk| hunk.ln1_end() > hunk.ln1_start()) {
+                let mut pos = group.start1;
+                for hunk in hunks {
+                    let prefix = if hunk.ln2_end() > hunk.ln2_start() {
+                        "! "
+                    } else {
+                        "- "
+                    };
+                    for line in pos..hunk.ln1_start() {
+                        Self::print_file_line(self.file1, line, "  ");
+                    }
+                    for line in hunk.ln1_start()..hunk.ln1_end() {
+                        Self::print_file_line(self.file1, line, prefix);
+                    }
+                    pos = hunk.ln1_end();
+                }
+                for line in pos..group.end1 {
+                    Self::print_file_line(self.file1, line, "  ");
                 }
-                diff_disp.set_context_start();
-            }
-
-            // do we have enough context between hunks?
-            if (diff_disp.curr_pos1 != 0) && (hunk.ln1_start() - diff_disp.curr_pos1 > context * 2)
-            {
-                // add context after the previous hunk
-                diff_disp.write_line(
-                    self.file1,
-                    diff_disp.curr_pos1,
-                    diff_disp.curr_pos1 + context,
-                    "  ",
-                    true,
-                )?;
-                diff_disp.write_line(
-                    self.file2,
-                    diff_disp.curr_pos2,
-                    diff_disp.curr_pos2 + context,
-                    "  ",
-                    false,
-                )?;
-                // update current position and print the whole section
-                diff_disp.update_curr_pos(hunk.ln1_start() - context, hunk.ln2_start() - context);
-                // check if we have something to display other than just context
-                let print_lines1 = diff_disp.hunk_lines[0]
-                    .split('\n')
-                    .any(|x| x.starts_with('-') || x.starts_with('!'));
-                diff_disp.print_section(true, print_lines1);
-                let print_lines2 = diff_disp.hunk_lines[1]
-                    .split('\n')
-                    .any(|x| x.starts_with('-') || x.starts_with('!'));
-                diff_disp.print_section(false, print_lines2);
             }
 
-            // add context before current hunk
-            diff_disp.write_line(
-                self.file1,
-                diff_disp.curr_pos1,
-                hunk.ln1_start(),
-                "  ",
-                true,
-            )?;
-            // add delete hunk
-            let h1_prefix = if hunk.ln2_end() - hunk.ln2_start() > 0 {
-                "! "
-            } else {
-                "- "
-            };
-            diff_disp.write_line(
-                self.file1,
-                hunk.ln1_start(),
-                hunk.ln1_end(),
-                h1_prefix,
-                true,
-            )?;
-
-            // context before insertion
-            diff_disp.write_line(
-                self.file2,
-                diff_disp.curr_pos2,
-                hunk.ln2_start(),
-                "  ",
-                false,
-            )?;
-            // add insert hunk
-            let h2_prefix = if hunk.ln1_end() - hunk.ln1_start() > 0 {
-                "! "
-            } else {
-                "+ "
-            };
-            diff_disp.write_line(
-                self.file2,
-                hunk.ln2_start(),
-                hunk.ln2_end(),
-                h2_prefix,
-                false,
-            )?;
-        }
-
-        // print final hunk
-        if !diff_disp.hunk_lines.is_empty() {
-            // display the remaining context if possible
-            if diff_disp.curr_pos1 < self.file1.lines().len() {
-                let end1 = self.file1.lines().len().min(diff_disp.curr_pos1 + context);
-                let end2 = self.file2.lines().len().min(diff_disp.curr_pos2 + context);
-                diff_disp.write_line(self.file1, diff_disp.curr_pos1, end1, " ", true)?;
-                diff_disp.write_line(self.file2, diff_disp.curr_pos2, end2, " ", false)?;
+            println!("--- {} ----", context_range(group.start2, group.end2));
+            if hunks.iter().any(|hunk| hunk.ln2_end() > hunk.ln2_start()) {
+                let mut pos = group.start2;
+                for hunk in hunks {
+                    let prefix = if hunk.ln1_end() > hunk.ln1_start() {
+                        "! "
+                    } else {
+                        "+ "
+                    };
+                    for line in pos..hunk.ln2_start() {
+                        Self::print_file_line(self.file2, line, "  ");
+                    }
+                    for line in hunk.ln2_start()..hunk.ln2_end() {
+                        Self::print_file_line(self.file2, line, prefix);
+                    }
+                    pos = hunk.ln2_end();
+                }
+                for line in pos..group.end2 {
+                    Self::print_file_line(self.file2, line, "  ");
+                }
             }
-            let print_lines1 = diff_disp.hunk_lines[0]
-                .split('\n')
-                .any(|x| x.starts_with('-') || x.starts_with('!'));
-            diff_disp.print_section(true, print_lines1);
-            let print_lines2 = diff_disp.hunk_lines[1]
-                .split('\n')
-                .any(|x| x.starts_with('+') || x.starts_with('!'));
-            diff_disp.print_section(false, print_lines2);
-        }
-
-        if !self.file1.ends_with_newline() {
-            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-        }
-        if !self.file2.ends_with_newline() {
-            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
         }
-        Ok(())
     }
 
-    fn print_unified(&mut self, unified: usize) -> Result<(), std::fmt::Error> {
+    fn print_unified(&mut self, unified: usize) {
         println!(
             "--- {}",
             Self::get_header(self.file1, self.format_options.label1())
@@ -458,59 +465,64 @@ impl<'a> FileDiff<'a> {
             Self::get_header(self.file2, self.format_options.label2())
         );
 
-        let mut diff_disp = UnifiedDiffDisplay::new();
+        for group in self.context_groups(unified) {
+            println!(
+                "@@ -{} +{} @@",
+                unified_range(group.start1, group.end1),
+                unified_range(group.start2, group.end2)
+            );
 
-        for hunk in self.hunks.hunks() {
-            // move cursor to the start of context for first hunk
-            if diff_disp.curr_pos1 == 0 {
-                if hunk.ln1_start() > unified {
-                    // this is guaranteed to be >= 0 due to the above conditions
-                    diff_disp
-                        .update_curr_pos(hunk.ln1_start() - unified, hunk.ln2_start() - unified);
+            let mut pos = group.start1;
+            for hunk in &self.hunks.hunks()[group.hunks] {
+                for line in pos..hunk.ln1_start() {
+                    Self::print_file_line(self.file1, line, " ");
+                }
+                for line in hunk.ln1_start()..hunk.ln1_end() {
+                    Self::print_file_line(self.file1, line, "-");
                 }
-                diff_disp.set_context_start();
+                for line in hunk.ln2_start()..hunk.ln2_end() {
+                    Self::print_file_line(self.file2, line, "+");
+                }
+                pos = hunk.ln1_end();
             }
-
-            // do we have enough context between hunks?
-            if (diff_disp.curr_pos1 != 0) && (hunk.ln1_start() - diff_disp.curr_pos1 > unified * 2)
-            {
-                // add context after the previous hunk
-                diff_disp.write_line(
-                    self.file1,
-                    diff_disp.curr_pos1,
-                    diff_disp.curr_pos1 + unified,
-                    " ",
-                )?;
-                // update current position and print the whole section
-                diff_disp.update_curr_pos(hunk.ln1_start() - unified, hunk.ln2_start() - unified);
-                diff_disp.print_section();
+            for line in pos..group.end1 {
+                Self::print_file_line(self.file1, line, " ");
             }
-
-            // add context before current hunk
-            diff_disp.write_line(self.file1, diff_disp.curr_pos1, hunk.ln1_start(), " ")?;
-            // add delete hunk
-            diff_disp.write_line(self.file1, hunk.ln1_start(), hunk.ln1_end(), "-")?;
-            // add insert hunk
-            diff_disp.write_line(self.file2, hunk.ln2_start(), hunk.ln2_end(), "+")?;
         }
+    }
 
-        // print final hunk
-        if !diff_disp.hunk_lines.is_empty() {
-            // display the remaining context if possible
-            if diff_disp.curr_pos1 < self.file1.lines().len() {
-                let end = self.file1.lines().len().min(diff_disp.curr_pos1 + unified);
-                diff_disp.write_line(self.file1, diff_disp.curr_pos1, end, " ")?;
+    fn print_side_by_side(&self, width: usize) {
+        let columns = SideBySide::new(width);
+        let mut pos1 = 0;
+        let mut pos2 = 0;
+        for hunk in self.hunks.hunks() {
+            columns.print_common(
+                self.file1,
+                self.file2,
+                pos1..hunk.ln1_start(),
+                pos2..hunk.ln2_start(),
+            );
+            let len1 = hunk.ln1_end() - hunk.ln1_start();
+            let len2 = hunk.ln2_end() - hunk.ln2_start();
+            for i in 0..len1.max(len2) {
+                let left = (i < len1).then(|| self.file1.line(hunk.ln1_start() + i));
+                let right = (i < len2).then(|| self.file2.line(hunk.ln2_start() + i));
+                let separator = match (left, right) {
+                    (Some(_), Some(_)) => b'|',
+                    (Some(_), None) => b'<',
+                    _ => b'>',
+                };
+                print_line("", &columns.format_line(left, separator, right));
             }
-            diff_disp.print_section();
-        }
-
-        if !self.file1.ends_with_newline() {
-            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-        }
-        if !self.file2.ends_with_newline() {
-            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-        }
-        Ok(())
+            pos1 = hunk.ln1_end();
+            pos2 = hunk.ln2_end();
+        }
+        columns.print_common(
+            self.file1,
+            self.file2,
+            pos1..self.file1.lines().len(),
+            pos2..self.file2.lines().len(),
+        );
     }
 
     pub fn get_header(file: &FileData, label: &Option<String>) -> String {
@@ -526,196 +538,33 @@ impl<'a> FileDiff<'a> {
     }
 }
 
-pub struct UnifiedDiffDisplay {
-    curr_pos1: usize,
-    curr_pos2: usize,
-    //`context_start` and `hunk_len` to get the values for the hunk header
-    //keep track of the lines where context start
-    context_start1: usize,
-    context_start2: usize,
-    //keep track of the length of the current hunk
-    hunk1_len: usize,
-    hunk2_len: usize,
-    hunk_lines: String,
+/// A run of hunks printed together in context or unified output, with the
+/// zero-based, half-open line ranges it spans in each file.
+struct ContextGroup {
+    hunks: Range<usize>,
+    start1: usize,
+    end1: usize,
+    start2: usize,
+    end2: usize,
 }
 
-impl UnifiedDiffDisplay {
-    pub fn new() -> Self {
-        Self {
-            curr_pos1: 0,
-            curr_pos2: 0,
-            context_start1: 0,
-            context_start2: 0,
-            hunk1_len: 0,
-            hunk2_len: 0,
-            hunk_lines: String::new(),
-        }
-    }
-
-    pub fn write_line(
-        &mut self,
-        file: &FileData,
-        start: usize,
-        end: usize,
-        prefix: &str,
-    ) -> Result<(), std::fmt::Error> {
-        let mut offset = 0;
-        for i in start..end {
-            writeln!(self.hunk_lines, "{prefix}{}", file.line(i))?;
-            offset += 1;
-        }
-        if prefix == " " {
-            self.curr_pos1 += offset;
-            self.hunk1_len += offset;
-            self.hunk2_len += offset;
-        } else if prefix == "-" {
-            self.curr_pos1 += offset;
-            self.hunk1_len += offset;
-        } else if prefix == "+" {
-            self.hunk2_len += offset;
-        }
-        Ok(())
-    }
-
-    pub fn set_context_start(&mut self) {
-        self.context_start1 = self.curr_pos1 + 1;
-        self.context_start2 = self.curr_pos2 + 1;
-    }
-    pub fn update_curr_pos(&mut self, curr1: usize, curr2: usize) {
-        self.curr_pos1 = curr1;
-        self.curr_pos2 = curr2;
-    }
-
-    pub fn print_section(&mut self) {
-        println!(
-            "@@ -{},{} +{},{} @@",
-            self.context_start1, self.hunk1_len, self.context_start2, self.hunk2_len
-        );
-        self.print_hunk();
-        self.context_start1 = self.curr_pos1 + 1;
-        self.context_start2 = self.curr_pos2 + 1;
-        self.hunk1_len = 0;
-        self.hunk2_len = 0;
-    }
-
-    pub fn print_hunk(&mut self) {
-        if self.hunk_lines.ends_with('\n') {
-            self.hunk_lines.pop();
-        }
-        println!("{}", self.hunk_lines);
-        self.hunk_lines.clear();
+/// Formats a line range for a context hunk header: `first,last`, or a single
+/// number when the range spans at most one line.
+fn context_range(start: usize, end: usize) -> String {
+    if end > start + 1 {
+        format!("{},{}", start + 1, end)
+    } else {
+        format!("{}", end)
     }
 }
 
-pub struct ContextDiffDisplay {
-    curr_pos1: usize,
-    curr_pos2: usize,
-    //`context_start` and `hunk_len` to get the values for the hunk header
-    //keep track of the lines where context start
-    context_start1: usize,
-    context_start2: usize,
-    //keep track of the length of the current hunk
-    hunk1_len: usize,
-    hunk2_len: usize,
-    hunk_lines: [String; 2],
-}
-
-impl ContextDiffDisplay {
-    pub fn new() -> Self {
-        Self {
-            curr_pos1: 0,
-            curr_pos2: 0,
-            context_start1: 0,
-            context_start2: 0,
-            hunk1_len: 0,
-            hunk2_len: 0,
-            hunk_lines: [String::new(), String::new()],
-        }
-    }
-
-    pub fn set_context_start(&mut self) {
-        self.context_start1 = self.curr_pos1 + 1;
-        self.context_start2 = self.curr_pos2 + 1;
-    }
-    pub fn update_curr_pos(&mut self, curr1: usize, curr2: usize) {
-        self.curr_pos1 = curr1;
-        self.curr_pos2 = curr2;
-    }
-
-    pub fn write_line(
-        &mut self,
-        file: &FileData,
-        start: usize,
-        end: usize,
-        prefix: &str,
-        is_file1: bool,
-    ) -> Result<(), std::fmt::Error> {
-        let mut offset = 0;
-        let file_index = if is_file1 { 0 } else { 1 };
-
-        for i in start..end {
-            writeln!(self.hunk_lines[file_index], "{prefix}{}", file.line(i))?;
-            offset += 1;
-        }
-        if prefix.starts_with(" ") {
-            if is_file1 {
-                self.curr_pos1 += offset;
-                self.hunk1_len += offset;
-            } else {
-                self.curr_pos2 += offset;
-                self.hunk2_len += offset;
-            }
-        } else if prefix.starts_with("-") {
-            self.curr_pos1 += offset;
-            self.hunk1_len += offset;
-        } else if prefix.starts_with("+") {
-            self.curr_pos2 += offset;
-            self.hunk2_len += offset;
-        } else if prefix.starts_with("!") {
-            if is_file1 {
-                self.curr_pos1 += offset;
-                self.hunk1_len += offset;
-            } else {
-                self.curr_pos2 += offset;
-                self.hunk2_len += offset;
-            }
-        }
-        Ok(())
-    }
-
-    pub fn print_section(&mut self, is_file1: bool, print_lines: bool) {
-        if is_file1 {
-            println!(
-                "***************\n*** {},{} ****",
-                self.context_start1,
-                self.context_start1 + self.hunk1_len - 1
-            );
-            if print_lines {
-                self.print_hunk(true);
-            }
-            self.context_start1 = self.curr_pos1 + 1;
-            self.hunk1_len = 0;
-        } else {
-            println!(
-                "--- {},{} ----",
-                self.context_start2,
-                self.context_start2 + self.hunk2_len - 1
-            );
-            if print_lines {
-                self.print_hunk(false);
-            }
-            self.context_start2 = self.curr_pos2 + 1;
-            self.hunk2_len = 0;
-        }
-    }
-
-    pub fn print_hunk(&mut self, is_file1: bool) {
-        let file_index = if is_file1 { 0 } else { 1 };
-        let lines = &mut self.hunk_lines[file_index];
-        if lines.ends_with('\n') {
-            lines.pop();
-        }
-        println!("{}", lines);
-        self.hunk_lines[file_index].clear();
+/// Formats a line range for a unified hunk header: `first,count`, where the
+/// count is omitted for a single line and an empty range names the line
+/// before it.
+fn unified_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        count => format!("{},{}", start + 1, count),
     }
 }
@@ -1,31 +1,43 @@
 use chrono::{DateTime, Local};
+use plib::fnmatch::fnmatch;
 use std::{
-    fs::File,
-    io::{self, Read},
-    path::{Path, PathBuf},
+    ffi::{CString, OsStr},
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::Path,
     time::SystemTime,
 };
 
-use super::constants::UTF8_NOT_ALLOWED_BYTES;
 use crate::diff_util::constants::COULD_NOT_UNWRAP_FILENAME;
 
 pub fn system_time_to_rfc2822(system_time: SystemTime) -> String {
     Into::<DateTime<Local>>::into(system_time).to_rfc2822()
 }
 
-pub fn is_binary(file_path: &PathBuf) -> io::Result<bool> {
-    let mut file = File::open(file_path)?;
-    let mut buffer = [0; 1024];
+/// Whether `content` is to be compared as binary data rather than as lines of
+/// text: like GNU diff, if there is a NUL byte near the start.  Text in any
+/// encoding is compared as lines of bytes.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(1024).any(|&byte| byte == 0)
+}
 
-    if let Ok(count) = file.read(&mut buffer) {
-        for buf_item in buffer.iter().take(count) {
-            if UTF8_NOT_ALLOWED_BYTES.contains(buf_item) {
-                return Ok(true);
-            }
-        }
-    }
+/// Prints `line` after `prefix` like `println!`, without requiring the line to
+/// be UTF-8.
+pub fn print_line(prefix: &str, line: &[u8]) {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(prefix.as_bytes())
+        .and_then(|()| stdout.write_all(line))
+        .and_then(|()| stdout.write_all(b"\n"))
+        .expect("failed printing to stdout");
+}
 
-    Ok(false)
+/// Matches `file_name` against the shell pattern `pattern`.
+pub fn pattern_matches(pattern: &str, file_name: &OsStr) -> bool {
+    let Ok(pattern) = CString::new(pattern) else {
+        return false;
+    };
+    fnmatch(&pattern, file_name.as_bytes(), 0)
 }
 
 pub fn check_existance(path_buf: &Path) -> io::Result<bool> {
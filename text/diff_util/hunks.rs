@@ -1,6 +1,6 @@
 use crate::diff_util::constants::NO_NEW_LINE_AT_END_OF_FILE;
 
-use super::file_data::FileData;
+use super::{common::FormatOptions, file_data::FileData, functions::print_line};
 
 #[derive(Clone, Default)]
 pub enum Change {
@@ -73,50 +73,37 @@ impl Hunk {
         self.ln2_end
     }
 
-    pub fn print_default(&mut self, file1: &FileData, file2: &FileData, is_last: bool) {
+    pub fn print_default(&mut self, file1: &FileData, file2: &FileData) {
         match self.kind {
             Change::None => {}
             Change::Insert => {
                 println!("{}a{}", self.ln1_start, self.f2_range(true));
-
-                for i in self.ln2_start..self.ln2_end {
-                    println!("> {}", file2.line(i));
-                }
+                Self::print_lines(file2, self.ln2_start, self.ln2_end, "> ");
             }
             Change::Delete => {
                 println!("{}d{}", self.f1_range(true), self.ln2_end);
-
-                for i in self.ln1_start..self.ln1_end {
-                    println!("< {}", file1.line(i));
-                }
-
-                if is_last && !file1.ends_with_newline() {
-                    println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-                }
+                Self::print_lines(file1, self.ln1_start, self.ln1_end, "< ");
             }
             Change::Substitute => {
                 println!("{}c{}", self.f1_range(true), self.f2_range(true));
-
-                for i in self.ln1_start..self.ln1_end {
-                    println!("< {}", file1.line(i));
-                }
-
-                if is_last && !file1.ends_with_newline() {
-                    println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-                }
-
+                Self::print_lines(file1, self.ln1_start, self.ln1_end, "< ");
                 println!("---");
-                for i in self.ln2_start..self.ln2_end {
-                    println!("> {}", file2.line(i));
-                }
-
-                if is_last && !file2.ends_with_newline() {
-                    println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
-                }
+                Self::print_lines(file2, self.ln2_start, self.ln2_end, "> ");
             }
         }
     }
 
+    /// Prints lines `start..end` of `file` with `prefix`, followed by the
+    /// "No newline" marker if the last of them is an unterminated final line.
+    fn print_lines(file: &FileData, start: usize, end: usize, prefix: &str) {
+        for i in start..end {
+            print_line(prefix, file.line(i));
+        }
+        if end == file.lines().len() && end > start && !file.ends_with_newline() {
+            println!("{}", NO_NEW_LINE_AT_END_OF_FILE);
+        }
+    }
+
     pub fn print_debug(&mut self, file1: &FileData, file2: &FileData) {
         println!(
             "{}-{} ({}) <> {}-{} ({})",
@@ -132,63 +119,59 @@ impl Hunk {
             Change::None => {}
             Change::Insert => {
                 for i in self.ln2_start..self.ln2_end {
-                    println!("+ \"{}\"", file2.line(i));
+                    println!("+ \"{}\"", String::from_utf8_lossy(file2.line(i)));
                 }
             }
             Change::Delete => {
                 for i in self.ln1_start..self.ln1_end {
-                    println!("- \"{}\"", file1.line(i));
+                    println!("- \"{}\"", String::from_utf8_lossy(file1.line(i)));
                 }
             }
             Change::Substitute => {
                 for i in self.ln1_start..self.ln1_end {
-                    println!("- \"{}\"", file1.line(i));
+                    println!("- \"{}\"", String::from_utf8_lossy(file1.line(i)));
                 }
                 for i in self.ln2_start..self.ln2_end {
-                    println!("+ \"{}\"", file2.line(i));
+                    println!("+ \"{}\"", String::from_utf8_lossy(file2.line(i)));
                 }
             }
         }
     }
 
-    pub fn print_edit_script(&mut self, file1: &FileData, file2: &FileData, is_last: bool) {
+    pub fn print_edit_script(&mut self, file2: &FileData) {
         match &self.kind {
             Change::None => {}
             Change::Insert => {
                 println!("{}a", self.ln1_end);
-                for i in self.ln2_start..self.ln2_end {
-                    println!("{}", file2.line(i));
-                }
-
-                println!(".")
+                Self::print_ed_text(file2, self.ln2_start, self.ln2_end);
             }
             Change::Delete => {
                 println!("{}d", self.f1_range(true));
             }
             Change::Substitute => {
                 println!("{}c", self.f1_range(true));
-                for i in self.ln2_start..self.ln2_end {
-                    println!("{}", file2.line(i));
-                }
-
-                println!(".")
+                Self::print_ed_text(file2, self.ln2_start, self.ln2_end);
             }
         }
+    }
 
-        if is_last && !file1.ends_with_newline() {
-            println!(
-                "diff: {}:{}\n",
-                file1.name(),
-                &NO_NEW_LINE_AT_END_OF_FILE[1..]
-            );
-        }
-
-        if is_last && !file2.ends_with_newline() {
-            println!(
-                "diff: {}:{}\n",
-                file2.name(),
-                &NO_NEW_LINE_AT_END_OF_FILE[1..]
-            );
+    /// Prints the text of an `a` or `c` command. A line consisting of a single
+    /// period would end the text early, so it is written as `..`, the text is
+    /// closed, the extra period removed and appending resumes after it.
+    fn print_ed_text(file: &FileData, start: usize, end: usize) {
+        for i in start..end {
+            let line = file.line(i);
+            if line == b"." {
+                println!("..\n.\ns/.//");
+                if i + 1 < end {
+                    println!("a");
+                }
+                continue;
+            }
+            print_line("", line);
+            if i + 1 == end {
+                println!(".");
+            }
         }
     }
 }
@@ -215,69 +198,55 @@ impl Hunks {
         &mut self.hunks
     }
 
-    pub fn hunk_at_mut(&mut self, index: usize) -> &mut Hunk {
-        &mut self.hunks[index]
-    }
-
     pub fn hunk_count(&self) -> usize {
         self.hunks.len()
     }
 
+    /// Turns the LCS produced by `FileDiff::histogram_lcs` into hunks: every
+    /// run of unmatched lines between two matched lines (or the start/end of
+    /// the files) becomes one insertion, deletion or substitution.
     pub fn create_hunks_from_lcs(
         &mut self,
         lcs_indices: &[i32],
         num_lines1: usize,
         num_lines2: usize,
     ) {
-        let mut hunk_start1 = 0;
-        let mut hunk_end1: usize;
-        let mut hunk_start2 = 0;
-        let mut hunk_end2: usize;
-        let mut prev_val = -2_i32;
-        for (i, lcs_index) in lcs_indices.iter().enumerate() {
-            if (lcs_index == &-1) && (prev_val != -1) {
-                // We reach a new deletion/substitution block
-                hunk_start1 = i;
-                hunk_start2 = if prev_val == -2 {
-                    0
-                } else {
-                    (prev_val + 1) as usize
-                };
-            } else if (i != 0)
-                && (prev_val != -1)
-                && (lcs_index != &-1)
-                && (lcs_index != &(prev_val + 1))
-            {
-                // there was an insertion (but no deletion)
-                // no -1 values but a bump in the values, eg [136, 145]
-                hunk_start1 = i;
-                hunk_start2 = (prev_val + 1) as usize;
-                hunk_end1 = i;
-                hunk_end2 = *lcs_index as usize;
-                self.add_hunk(hunk_start1, hunk_end1, hunk_start2, hunk_end2);
+        let mut pos1 = 0;
+        let mut pos2 = 0;
+        loop {
+            let next_match = (pos1..num_lines1).find(|&i| lcs_indices[i] != -1);
+            let (match1, match2) = match next_match {
+                Some(i) => (i, lcs_indices[i] as usize),
+                None => (num_lines1, num_lines2),
+            };
+
+            if match1 > pos1 || match2 > pos2 {
+                self.add_hunk(pos1, match1, pos2, match2);
             }
-            if (lcs_index != &-1) && (prev_val == -1) {
-                // we reach the end of deletion/substitution block
-                hunk_end1 = i;
-                hunk_end2 = *lcs_index as usize;
-                self.add_hunk(hunk_start1, hunk_end1, hunk_start2, hunk_end2);
+
+            if next_match.is_none() {
+                break;
             }
-            prev_val = *lcs_index;
+            pos1 = match1 + 1;
+            pos2 = match2 + 1;
         }
+    }
 
-        // final hunk: we might have only -1 at the end
-        if lcs_indices[lcs_indices.len() - 1] == -1 {
-            hunk_end1 = num_lines1;
-            hunk_end2 = num_lines2;
-            self.add_hunk(hunk_start1, hunk_end1, hunk_start2, hunk_end2);
-        } else if lcs_indices[lcs_indices.len() - 1] < ((num_lines2 - 1) as i32) {
-            // there might be some insertions after the last lcs block
-            hunk_start1 = num_lines1 - 1;
-            hunk_end1 = num_lines1 - 1;
-            hunk_start2 = (lcs_indices[lcs_indices.len() - 1] + 1) as usize;
-            hunk_end2 = num_lines2;
-            self.add_hunk(hunk_start1, hunk_end1, hunk_start2, hunk_end2);
-        }
+    /// Removes the hunks in which every line is ignorable, see
+    /// `FormatOptions::is_ignorable`.
+    pub fn remove_ignorable(
+        &mut self,
+        file1: &FileData,
+        file2: &FileData,
+        format_options: &FormatOptions,
+    ) {
+        self.hunks.retain(|hunk| {
+            let lines1 = (hunk.ln1_start..hunk.ln1_end).map(|i| file1.line(i));
+            let lines2 = (hunk.ln2_start..hunk.ln2_end).map(|i| file2.line(i));
+            !lines1
+                .chain(lines2)
+                .all(|line| format_options.is_ignorable(line))
+        });
     }
 
     pub fn add_hunk(
@@ -7,3 +7,4 @@ pub(crate) mod file_data;
 pub(crate) mod file_diff;
 pub(crate) mod functions;
 pub(crate) mod hunks;
+pub(crate) mod side_by_side;
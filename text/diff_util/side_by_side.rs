@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+use super::{file_data::FileData, functions::print_line};
+
+const TAB_SIZE: usize = 8;
+
+/// The least number of columns between the two halves of a line.
+const GUTTER_WIDTH_MINIMUM: usize = 3;
+
+/// The layout of side by side output, which is compatible with GNU diff: each
+/// file gets half of the line, with a separator between them that marks how
+/// the lines differ.
+pub struct SideBySide {
+    /// The width of each half of a line.
+    half_width: usize,
+    /// The column the second file starts at.
+    column2_offset: usize,
+}
+
+impl SideBySide {
+    pub fn new(width: usize) -> Self {
+        let offset = (width + TAB_SIZE + GUTTER_WIDTH_MINIMUM) / (2 * TAB_SIZE) * TAB_SIZE;
+        let half_width = offset
+            .saturating_sub(GUTTER_WIDTH_MINIMUM)
+            .min(width.saturating_sub(offset));
+        Self {
+            half_width,
+            column2_offset: if half_width > 0 { offset } else { width },
+        }
+    }
+
+    /// Pads `out` from column `from` to column `to` with tabs and spaces,
+    /// returning `to`.
+    fn tab_from_to(out: &mut Vec<u8>, mut from: usize, to: usize) -> usize {
+        let mut tab = from + TAB_SIZE - from % TAB_SIZE;
+        while tab <= to {
+            out.push(b'\t');
+            from = tab;
+            tab += TAB_SIZE;
+        }
+        while from < to {
+            out.push(b' ');
+            from += 1;
+        }
+        to
+    }
+
+    /// Appends as much of `line` as fits into half of the output, returning the
+    /// column reached. Tabs are kept as long as they stay in the half. A byte
+    /// that is not part of a UTF-8 character takes a column of its own.
+    fn print_half_line(&self, out: &mut Vec<u8>, line: &[u8]) -> usize {
+        let mut in_position = 0;
+        let mut out_position = 0;
+        let characters = line.utf8_chunks().flat_map(|chunk| {
+            let valid = chunk.valid();
+            valid
+                .char_indices()
+                .map(|(i, c)| &valid.as_bytes()[i..i + c.len_utf8()])
+                .chain(chunk.invalid().chunks(1))
+        });
+        for c in characters {
+            if c == b"\t" {
+                let spaces = TAB_SIZE - in_position % TAB_SIZE;
+                if in_position == out_position && out_position + spaces < self.half_width {
+                    out_position += spaces;
+                    out.extend_from_slice(c);
+                }
+                in_position += spaces;
+            } else {
+                if in_position < self.half_width {
+                    out_position = in_position + 1;
+                    out.extend_from_slice(c);
+                }
+                in_position += 1;
+            }
+        }
+        out_position
+    }
+
+    /// Formats a line of output from a line of each file, either of which may
+    /// be missing. `separator` is ' ' for common lines, '|' for changed ones,
+    /// '<' and '>' for lines only in the first or the second file.
+    pub fn format_line(&self, left: Option<&[u8]>, separator: u8, right: Option<&[u8]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut column = 0;
+        if let Some(left) = left {
+            column = self.print_half_line(&mut out, left);
+        }
+        if separator != b' ' {
+            column = Self::tab_from_to(
+                &mut out,
+                column,
+                (self.half_width + self.column2_offset - 1) / 2,
+            ) + 1;
+            out.push(separator);
+        }
+        if let Some(right) = right.filter(|right| !right.is_empty()) {
+            Self::tab_from_to(&mut out, column, self.column2_offset);
+            self.print_half_line(&mut out, right);
+        }
+        out
+    }
+
+    /// Prints lines which are common to both files. The ranges may differ in
+    /// length if changes in between were ignored, the surplus lines are
+    /// marked with '(' or ')'.
+    pub fn print_common(
+        &self,
+        file1: &FileData,
+        file2: &FileData,
+        lines1: Range<usize>,
+        lines2: Range<usize>,
+    ) {
+        let len1 = lines1.len();
+        let len2 = lines2.len();
+        for (line1, line2) in lines1.clone().zip(lines2.clone()) {
+            print_line(
+                "",
+                &self.format_line(Some(file1.line(line1)), b' ', Some(file2.line(line2))),
+            );
+        }
+        for line2 in lines2.skip(len1) {
+            print_line("", &self.format_line(None, b')', Some(file2.line(line2))));
+        }
+        for line1 in lines1.skip(len2) {
+            print_line("", &self.format_line(Some(file1.line(line1)), b'(', None));
+        }
+    }
+}
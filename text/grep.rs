@@ -7,22 +7,24 @@
 // SPDX-License-Identifier: MIT
 //
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use gettextrs::{bind_textdomain_codeset, textdomain};
-use libc::{regcomp, regex_t, regexec, regfree, REG_EXTENDED, REG_ICASE, REG_NOMATCH};
+use plib::regex::{Regex, RegexFlags};
 use plib::PROJECT_NAME;
 use std::{
-    ffi::CString,
+    collections::VecDeque,
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Write},
     path::{Path, PathBuf},
-    ptr,
 };
 
 /// grep - search a file for a pattern.
 #[derive(Parser)]
-#[command(version, about)]
+#[command(version, about, disable_help_flag = true)]
 struct Args {
+    #[arg(long, action = clap::ArgAction::HelpLong)]
+    help: Option<bool>,
+
     /// Match using extended regular expressions.
     #[arg(short = 'E', long)]
     extended_regexp: bool,
@@ -72,6 +74,55 @@ struct Args {
     #[arg(short = 'x', long)]
     line_regexp: bool,
 
+    /// Select only those lines containing matches that form whole words.
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+
+    /// Print only the matched parts of selected lines, each on its own output line.
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+
+    /// Print NUM lines of trailing context after selected lines.
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+
+    /// Print NUM lines of leading context before selected lines.
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+
+    /// Print NUM lines of leading and trailing context around selected lines.
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+
+    /// Read all files under each directory, recursively, following symbolic links only if they
+    /// are on the command line.
+    #[arg(short = 'r', long)]
+    recursive: bool,
+
+    /// Read all files under each directory, recursively, following all symbolic links.
+    #[arg(short = 'R', long)]
+    dereference_recursive: bool,
+
+    /// Process a binary file as if it were text.
+    #[arg(short = 'a', long)]
+    text: bool,
+
+    /// Assume that binary files do not match.
+    #[arg(short = 'I')]
+    without_match: bool,
+
+    /// How to treat files that contain NUL bytes.
+    #[arg(long, value_name = "TYPE", default_value = "binary")]
+    binary_files: BinaryFiles,
+
+    /// Print the file name for each match.
+    #[arg(short = 'H', long, overrides_with = "no_filename")]
+    with_filename: bool,
+
+    /// Suppress the prefixing of file names on output.
+    #[arg(short = 'h', long, overrides_with = "with_filename")]
+    no_filename: bool,
+
     /// Specify one or more patterns to be used during the search for input. This operand shall be
     /// treated as if it were specified as -e regexp.
     #[arg(name = "PATTERNS")]
@@ -145,7 +196,11 @@ impl Args {
         self.regexp.dedup();
 
         if self.input_files.is_empty() {
-            self.input_files.push(String::from("-"))
+            if self.recursive || self.dereference_recursive {
+                self.input_files.push(String::from("."))
+            } else {
+                self.input_files.push(String::from("-"))
+            }
         }
     }
 
@@ -186,15 +241,58 @@ impl Args {
             self.fixed_strings,
             self.ignore_case,
             self.line_regexp,
+            self.word_regexp,
         )?;
 
+        let recursion = if self.dereference_recursive {
+            Some(ftw::TraverseDirectoryOpts {
+                follow_symlinks_on_args: true,
+                follow_symlinks: true,
+                ..Default::default()
+            })
+        } else if self.recursive {
+            Some(ftw::TraverseDirectoryOpts {
+                follow_symlinks_on_args: true,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+
+        // Recursion prints file names, unless the only operand is a file.
+        let with_filename = if self.with_filename {
+            true
+        } else if self.no_filename {
+            false
+        } else {
+            self.input_files.len() > 1
+                || (recursion.is_some() && Path::new(&self.input_files[0]).is_dir())
+        };
+
+        let binary_files = if self.text {
+            BinaryFiles::Text
+        } else if self.without_match {
+            BinaryFiles::WithoutMatch
+        } else {
+            self.binary_files
+        };
+
+        let before_context = self.before_context.or(self.context).unwrap_or(0);
+        let after_context = self.after_context.or(self.context).unwrap_or(0);
+
         Ok(GrepModel {
             any_matches: false,
             any_errors: self.any_errors,
             line_number: self.line_number,
             no_messages: self.no_messages,
             invert_match: self.invert_match,
-            multiple_inputs: self.input_files.len() > 1,
+            with_filename,
+            only_matching: self.only_matching,
+            before_context,
+            after_context,
+            any_groups: false,
+            binary_files,
+            recursion,
             output_mode,
             patterns,
             input_files: self.input_files,
@@ -202,10 +300,10 @@ impl Args {
     }
 }
 
-/// Newtype over `Vec[libc::regex_t]`. Provides functionality for matching input data.
+/// Fixed strings or regular expressions. Provides functionality for matching input data.
 enum Patterns {
-    Fixed(Vec<String>, bool, bool),
-    Regex(Vec<regex_t>),
+    Fixed(Vec<String>, bool, bool, bool),
+    Regex(Vec<Regex>, bool, bool),
 }
 
 impl Patterns {
@@ -218,6 +316,7 @@ impl Patterns {
     /// * `fixed_string` - `bool` indicating whether pattern is fixed string or regex.
     /// * `ignore_case` - `bool` indicating whether to ignore case.
     /// * `line_regexp` - `bool` indicating whether to match the entire input.
+    /// * `word_regexp` - `bool` indicating whether matches must form whole words.
     ///
     /// # Errors
     ///
@@ -232,110 +331,217 @@ impl Patterns {
         fixed_string: bool,
         ignore_case: bool,
         line_regexp: bool,
+        word_regexp: bool,
     ) -> Result<Self, String> {
         if fixed_string {
             Ok(Self::Fixed(
                 patterns
                     .into_iter()
-                    .map(|p| if ignore_case { p.to_lowercase() } else { p })
+                    .map(|p| if ignore_case { fold_case(&p) } else { p })
                     .collect(),
                 ignore_case,
                 line_regexp,
+                word_regexp,
             ))
         } else {
-            let mut ps = vec![];
-
-            let mut cflags = 0;
-            if extended_regexp {
-                cflags |= REG_EXTENDED;
-            }
-            if ignore_case {
-                cflags |= REG_ICASE;
-            }
-            for mut pattern in patterns {
-                // macOS version of [regcomp](regcomp) from `libc`
-                // provides additional check for empty regex. In this case,
-                // an error [REG_EMPTY](https://developer.apple.com/library/archive/documentation/System/Conceptual/ManPages_iPhoneOS/man3/regcomp.3.html)
-                // will be returned.
-
-                // Therefore, an empty pattern is replaced with ".*".
-                #[cfg(target_os = "macos")]
-                {
-                    pattern = if pattern.is_empty() {
-                        String::from(".*")
-                    } else {
-                        pattern
-                    };
-                }
-                pattern = if line_regexp {
-                    format!("^{pattern}$")
-                } else {
-                    pattern
-                };
-
-                let c_pattern = CString::new(pattern).map_err(|err| err.to_string())?;
-                let mut regex = unsafe { std::mem::zeroed::<regex_t>() };
-
-                let result = unsafe { regcomp(&mut regex, c_pattern.as_ptr(), cflags) };
-                if result != 0 {
-                    return Err(format!(
-                        "Error compiling regex '{}'",
-                        c_pattern.to_string_lossy()
-                    ));
-                }
-                ps.push(regex);
-            }
-            Ok(Self::Regex(ps))
+            let flags = RegexFlags {
+                extended: extended_regexp,
+                ignore_case,
+                ..Default::default()
+            };
+            let ps = patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern, flags)
+                        .map_err(|_| format!("Error compiling regex '{pattern}'"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Self::Regex(ps, line_regexp, word_regexp))
         }
     }
 
-    /// Checks if input string matches the present patterns.
+    /// Checks if input line matches the present patterns.
     ///
     /// # Arguments
     ///
-    /// * `input` - object that implements [AsRef](AsRef) for [str](str) and describes line.
+    /// * `input` - object that implements [AsRef](AsRef) for `[u8]` and describes line.
     ///
     /// # Returns
     ///
     /// Returns [bool](bool) - `true` if input matches present patterns, else `false`.
-    fn matches(&self, input: impl AsRef<str>) -> bool {
-        let input = input.as_ref();
+    fn matches(&self, input: impl AsRef<[u8]>) -> bool {
+        match self {
+            // Without -w or -x only whether some pattern matches counts, not where.
+            Patterns::Regex(patterns, false, false) => {
+                patterns.iter().any(|p| p.is_match(input.as_ref()))
+            }
+            _ => self.find(input.as_ref(), 0).is_some(),
+        }
+    }
+
+    /// Finds the leftmost, then longest, match of the present patterns in `input` that starts at
+    /// or after byte offset `start`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the byte offsets of the start and the end of the match, if any.
+    fn find(&self, input: &[u8], start: usize) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        let mut consider = |found: Option<(usize, usize)>| {
+            if let Some((s, e)) = found {
+                if best.map_or(true, |(bs, be)| s < bs || (s == bs && e > be)) {
+                    best = Some((s, e));
+                }
+            }
+        };
         match self {
-            Patterns::Fixed(patterns, ignore_case, line_regexp) => {
-                let input = if *ignore_case {
-                    input.to_lowercase()
+            Patterns::Fixed(patterns, ignore_case, line_regexp, word_regexp) => {
+                // Case folding keeps the byte offsets of `input` intact.
+                let folded;
+                let haystack = if *ignore_case {
+                    folded = match std::str::from_utf8(input) {
+                        Ok(input) => fold_case(input).into_bytes(),
+                        Err(_) => input.to_ascii_lowercase(),
+                    };
+                    &folded
                 } else {
-                    input.to_string()
+                    input
                 };
-                patterns.iter().any(|p| {
+                for p in patterns {
+                    let p = p.as_bytes();
                     if *line_regexp {
-                        input == *p
-                    } else {
-                        input.contains(p)
+                        if start == 0 && haystack == p {
+                            consider(Some((0, haystack.len())));
+                        }
+                        continue;
                     }
-                })
+                    consider(find_from(
+                        haystack,
+                        start,
+                        *word_regexp,
+                        |from| {
+                            find_bytes(&haystack[from..], p).map(|i| (from + i, from + i + p.len()))
+                        },
+                        // A fixed string has no shorter matches.
+                        |_, _| false,
+                    ));
+                }
             }
-            Patterns::Regex(patterns) => {
-                let c_input = CString::new(input).unwrap();
-                patterns.iter().any(|p| unsafe {
-                    regexec(p, c_input.as_ptr(), 0, ptr::null_mut(), 0) != REG_NOMATCH
-                })
+            Patterns::Regex(patterns, line_regexp, word_regexp) => {
+                for p in patterns {
+                    if *line_regexp {
+                        // The longest match starting at the line start covers the line, if
+                        // any does.
+                        if start == 0 && p.find(input) == Some(0..input.len()) {
+                            consider(Some((0, input.len())));
+                        }
+                        continue;
+                    }
+                    consider(find_from(
+                        input,
+                        start,
+                        *word_regexp,
+                        |from| p.find_at(input, from).map(|m| (m.start, m.end)),
+                        |s, e| p.matches_span(input, s..e),
+                    ));
+                }
             }
         }
+        best
     }
 }
 
-impl Drop for Patterns {
-    fn drop(&mut self) {
-        match &self {
-            Patterns::Fixed(_, _, _) => {}
-            Patterns::Regex(regexes) => {
-                for regex in regexes {
-                    unsafe { regfree(regex as *const regex_t as *mut regex_t) }
-                }
+/// Lowercases the characters of `s` whose lowercase form has the same length, so that byte
+/// offsets into the result are also offsets into `s`.
+fn fold_case(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) if l.len_utf8() == c.len_utf8() => l,
+                _ => c,
             }
+        })
+        .collect()
+}
+
+/// Finds the first occurrence of `needle` in `haystack`.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Length of the character starting at `input[i]`; bytes that are not valid UTF-8 count as
+/// characters of their own.
+fn char_len(input: &[u8], i: usize) -> usize {
+    (1..=4)
+        .find(|&len| {
+            input
+                .get(i..i + len)
+                .is_some_and(|c| std::str::from_utf8(c).is_ok())
+        })
+        .unwrap_or(1)
+}
+
+/// Checks whether the bytes are a single word character, alphanumeric or `_`.
+fn is_word_char(bytes: &[u8]) -> bool {
+    let mut chars = std::str::from_utf8(bytes).unwrap_or_default().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_alphanumeric() || c == '_',
+        _ => false,
+    }
+}
+
+/// Checks that `input[start..end]` is neither preceded nor followed by a word character.
+fn is_word_bounded(input: &[u8], start: usize, end: usize) -> bool {
+    let before = (1..=4.min(start)).any(|len| is_word_char(&input[start - len..start]));
+    let after = end < input.len() && is_word_char(&input[end..end + char_len(input, end)]);
+    !before && !after
+}
+
+/// Repeats `search` from `start` until it finds a match, which must form a whole word if
+/// `word_regexp` is set. When the longest match at some position does not, the shorter ones
+/// there for which `matches_span` holds are tried, longest first.
+fn find_from(
+    input: &[u8],
+    start: usize,
+    word_regexp: bool,
+    mut search: impl FnMut(usize) -> Option<(usize, usize)>,
+    matches_span: impl Fn(usize, usize) -> bool,
+) -> Option<(usize, usize)> {
+    let mut from = start;
+    while from <= input.len() {
+        let (s, e) = search(from)?;
+        if !word_regexp || is_word_bounded(input, s, e) {
+            return Some((s, e));
+        }
+        if let Some(end) = (s..e)
+            .rev()
+            .find(|&end| is_word_bounded(input, s, end) && matches_span(s, end))
+        {
+            return Some((s, end));
         }
+        if s == input.len() {
+            break;
+        }
+        from = s + char_len(input, s);
     }
+    None
+}
+
+/// Represents how `grep` treats binary files.
+#[derive(Clone, Copy, Eq, PartialEq, ValueEnum)]
+enum BinaryFiles {
+    /// Report only that a binary file matches
+    Binary,
+    /// Process binary files as if they were text
+    Text,
+    /// Assume that binary files do not match
+    WithoutMatch,
 }
 
 /// Represents possible `grep` output modes.
@@ -354,7 +560,13 @@ struct GrepModel {
     line_number: bool,
     no_messages: bool,
     invert_match: bool,
-    multiple_inputs: bool,
+    with_filename: bool,
+    only_matching: bool,
+    before_context: usize,
+    after_context: usize,
+    any_groups: bool,
+    binary_files: BinaryFiles,
+    recursion: Option<ftw::TraverseDirectoryOpts>,
     output_mode: OutputMode,
     patterns: Patterns,
     input_files: Vec<String>,
@@ -371,19 +583,15 @@ impl GrepModel {
             if input_name == "-" {
                 let reader = Box::new(BufReader::new(io::stdin()));
                 self.process_input("(standard input)", reader);
-            } else {
-                match File::open(&input_name) {
-                    Ok(file) => {
-                        let reader = Box::new(BufReader::new(file));
-                        self.process_input(&input_name, reader)
-                    }
-                    Err(err) => {
-                        self.any_errors = true;
-                        if !self.no_messages {
-                            eprintln!("{}: {}", input_name, err);
-                        }
+            } else if self.recursion.is_some() && Path::new(&input_name).is_dir() {
+                for path in self.walk(&input_name) {
+                    self.process_file(&path);
+                    if self.any_matches && self.output_mode == OutputMode::Quiet {
+                        return 0;
                     }
                 }
+            } else {
+                self.process_file(&input_name);
             }
             if self.any_matches && self.output_mode == OutputMode::Quiet {
                 return 0;
@@ -399,27 +607,150 @@ impl GrepModel {
         }
     }
 
+    /// Opens the file named `input_name` and processes its content.
+    fn process_file(&mut self, input_name: &str) {
+        match File::open(input_name) {
+            Ok(file) => {
+                let reader = Box::new(BufReader::new(file));
+                self.process_input(input_name, reader)
+            }
+            Err(err) => {
+                self.any_errors = true;
+                if !self.no_messages {
+                    eprintln!("{}: {}", input_name, err);
+                }
+            }
+        }
+    }
+
+    /// Collects the names of the files under the directory `dir`, in sorted order. A search of
+    /// the working directory, when no file operands were specified, names them without the
+    /// leading `./`.
+    fn walk(&mut self, dir: &str) -> Vec<String> {
+        let opts = self.recursion.clone().unwrap_or_default();
+        let mut files = vec![];
+        let mut errors = vec![];
+        ftw::traverse_directory(
+            dir,
+            |entry| {
+                let Some(metadata) = entry.metadata() else {
+                    return Ok(false);
+                };
+                if metadata.is_dir() {
+                    return Ok(true);
+                }
+                // Symbolic links that are not followed are skipped.
+                if !metadata.file_type().is_symlink() {
+                    files.push(entry.path().to_string());
+                }
+                Ok(false)
+            },
+            |_| Ok(()),
+            |entry, error| {
+                errors.push(format!("{}: {}", entry.path(), error.inner()));
+            },
+            opts,
+        );
+
+        if !errors.is_empty() {
+            self.any_errors = true;
+            if !self.no_messages {
+                for error in errors {
+                    eprintln!("{error}");
+                }
+            }
+        }
+
+        files.sort();
+        if dir == "." {
+            for file in &mut files {
+                if let Some(stripped) = file.strip_prefix("./") {
+                    *file = stripped.to_string();
+                }
+            }
+        }
+        files
+    }
+
+    /// Writes a selected line, or a context line, with its prefix.
+    fn print_line(&self, input_name: &str, line_number: u64, separator: char, line: &[u8]) {
+        let mut result = Vec::with_capacity(line.len() + 1);
+        if self.with_filename {
+            result.extend_from_slice(input_name.as_bytes());
+            result.push(separator as u8);
+        }
+        if self.line_number {
+            result.extend_from_slice(format!("{line_number}{separator}").as_bytes());
+        }
+        result.extend_from_slice(line);
+        result.push(b'\n');
+        // Errors writing to standard output, such as a closed pipe, end the output silently.
+        let _ = io::stdout().lock().write_all(&result);
+    }
+
+    /// Writes each non-empty match in a selected line on its own output line.
+    fn print_matches(&self, input_name: &str, line_number: u64, line: &[u8]) {
+        let mut start = 0;
+        while let Some((s, e)) = self.patterns.find(line, start) {
+            if e > s {
+                self.print_line(input_name, line_number, ':', &line[s..e]);
+                start = e;
+            } else if s < line.len() {
+                start = s + char_len(line, s);
+            } else {
+                break;
+            }
+        }
+    }
+
     /// Reads lines from buffer and processes them.
     ///
+    /// Lines are handled as bytes, so input need not be valid UTF-8. Input that contains a NUL
+    /// byte is binary: unless [BinaryFiles::Text] is chosen, the first selected line in it
+    /// produces a single "Binary file ... matches" message instead of the lines.
+    ///
     /// # Arguments
     ///
     /// * `input_name` - [str](str) that represents content source name.
     /// * `reader` - [Box](Box) that contains object that implements [BufRead] and reads lines.
     fn process_input(&mut self, input_name: &str, mut reader: Box<dyn BufRead>) {
+        let with_context =
+            !self.only_matching && (self.before_context > 0 || self.after_context > 0);
+        // Lines preceding the next selected line, kept for leading context.
+        let mut before: VecDeque<(u64, Vec<u8>)> = VecDeque::with_capacity(self.before_context);
+        // Lines of trailing context still to be written.
+        let mut after_remaining = 0;
+        // Number of the last line written from this input.
+        let mut last_printed: Option<u64> = None;
+
+        let mut binary = match reader.fill_buf() {
+            Ok(buffer) => buffer.contains(&0),
+            Err(_) => false,
+        };
+        if binary && self.binary_files == BinaryFiles::WithoutMatch {
+            return;
+        }
+
         let mut line_number: u64 = 0;
+        let mut line = Vec::new();
         loop {
-            let mut line = String::new();
+            line.clear();
             line_number += 1;
-            match reader.read_line(&mut line) {
+            match reader.read_until(b'\n', &mut line) {
                 Ok(n_read) => {
                     if n_read == 0 {
                         break;
                     }
-                    let trimmed = if line.ends_with('\n') {
-                        &line[..line.len() - 1]
-                    } else {
-                        &line
+                    let trimmed = match line.strip_suffix(b"\n") {
+                        Some(trimmed) => trimmed,
+                        None => &line,
                     };
+                    if !binary && trimmed.contains(&0) {
+                        binary = true;
+                        if self.binary_files == BinaryFiles::WithoutMatch {
+                            break;
+                        }
+                    }
 
                     let init_matches = self.patterns.matches(trimmed);
                     let matches = if self.invert_match {
@@ -441,25 +772,45 @@ impl GrepModel {
                                 return;
                             }
                             OutputMode::Default => {
-                                let result = format!(
-                                    "{}{}{}",
-                                    if self.multiple_inputs {
-                                        format!("{input_name}:")
-                                    } else {
-                                        String::new()
-                                    },
-                                    if self.line_number {
-                                        format!("{line_number}:")
-                                    } else {
-                                        String::new()
-                                    },
-                                    trimmed
-                                );
-                                println!("{result}");
+                                if binary && self.binary_files == BinaryFiles::Binary {
+                                    println!("Binary file {input_name} matches");
+                                    break;
+                                } else if self.only_matching {
+                                    if !self.invert_match {
+                                        self.print_matches(input_name, line_number, trimmed);
+                                    }
+                                } else if with_context {
+                                    // A group that does not continue the previous one is
+                                    // separated from it by a "--" line.
+                                    let first = before.front().map_or(line_number, |(n, _)| *n);
+                                    let continues = last_printed.is_some_and(|n| n + 1 == first);
+                                    if self.any_groups && !continues {
+                                        println!("--");
+                                    }
+                                    self.any_groups = true;
+                                    for (n, context) in before.drain(..) {
+                                        self.print_line(input_name, n, '-', &context);
+                                    }
+                                    self.print_line(input_name, line_number, ':', trimmed);
+                                    last_printed = Some(line_number);
+                                    after_remaining = self.after_context;
+                                } else {
+                                    self.print_line(input_name, line_number, ':', trimmed);
+                                }
+                            }
+                        }
+                    } else if with_context && self.output_mode == OutputMode::Default {
+                        if after_remaining > 0 {
+                            self.print_line(input_name, line_number, '-', trimmed);
+                            last_printed = Some(line_number);
+                            after_remaining -= 1;
+                        } else if self.before_context > 0 {
+                            if before.len() == self.before_context {
+                                before.pop_front();
                             }
+                            before.push_back((line_number, trimmed.to_vec()));
                         }
                     }
-                    line.clear();
                 }
                 Err(err) => {
                     self.any_errors = true;
@@ -469,11 +820,12 @@ impl GrepModel {
                             input_name, line_number, err
                         );
                     }
+                    break;
                 }
             }
         }
         if let OutputMode::Count(count) = &mut self.output_mode {
-            if self.multiple_inputs {
+            if self.with_filename {
                 println!("{input_name}:{count}");
             } else {
                 println!("{count}");
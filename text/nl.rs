@@ -9,8 +9,8 @@
 
 use clap::{Parser, ValueEnum};
 use gettextrs::{bind_textdomain_codeset, setlocale, textdomain, LocaleCategory};
+use plib::regex::{Regex, RegexFlags};
 use plib::PROJECT_NAME;
-use regex::Regex;
 use std::fs;
 use std::io::{self, BufRead, Read};
 use std::path::PathBuf;
@@ -111,7 +111,7 @@ impl FromStr for LineNumberingStyle {
             "n" => Ok(LineNumberingStyle::None),
             s => {
                 if let Some(re) = s.strip_prefix('p') {
-                    if let Ok(regexp) = Regex::new(re) {
+                    if let Ok(regexp) = Regex::new(re, RegexFlags::default()) {
                         Ok(LineNumberingStyle::Regex(regexp))
                     } else {
                         Err(format!("invalid regular expression: {re}"))
@@ -131,7 +131,7 @@ impl std::fmt::Display for LineNumberingStyle {
             LineNumberingStyle::NonEmpty => write!(f, "t"),
             LineNumberingStyle::None => write!(f, "n"),
             LineNumberingStyle::Regex(re) => {
-                write!(f, "p{}", re)
+                write!(f, "p{}", re.as_str())
             }
         }
     }
@@ -280,7 +280,7 @@ fn nl_main(args: &Args) -> io::Result<()> {
                     }
                     LineNumberingStyle::None => print_spacer(),
                     LineNumberingStyle::Regex(regexp) => {
-                        if regexp.is_match(&line_buffer) {
+                        if regexp.is_match(line_buffer.as_bytes()) {
                             print_line_number(&mut line_number, &mut line_number_overflowed)?;
                         } else {
                             print_spacer();
@@ -0,0 +1,558 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod patch_util;
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use patch_util::{
+    apply_hunks, locate, run_ed_script, ApplyOptions, DiffFormat, FilePatch, Hunk, HunkResult,
+    PatchBody,
+};
+use plib::PROJECT_NAME;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const DEV_NULL: &str = "/dev/null";
+
+/// patch - apply changes to files
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Save a copy of each modified file, with the suffix .orig
+    #[arg(short = 'b', long = "backup")]
+    backup: bool,
+
+    /// Interpret the patch as a context diff
+    #[arg(short = 'c', long = "context", group = "format")]
+    context: bool,
+
+    /// Change to DIR before doing anything else
+    #[arg(short = 'd', long = "directory", value_name = "DIR")]
+    directory: Option<PathBuf>,
+
+    /// Mark changes with #ifdef DEFINE ... #endif instead of applying them
+    #[arg(short = 'D', long = "ifdef", value_name = "DEFINE")]
+    ifdef: Option<String>,
+
+    /// Interpret the patch as an ed script
+    #[arg(short = 'e', long = "ed", group = "format")]
+    ed: bool,
+
+    /// Read the patch from PATCHFILE instead of standard input
+    #[arg(short = 'i', long = "input", value_name = "PATCHFILE")]
+    input: Option<PathBuf>,
+
+    /// Match any sequence of blanks in the patch with any sequence of blanks
+    /// in the input file
+    #[arg(short = 'l', long = "ignore-whitespace")]
+    loose_whitespace: bool,
+
+    /// Interpret the patch as a normal diff
+    #[arg(short = 'n', long = "normal", group = "format")]
+    normal: bool,
+
+    /// Ignore patches that appear to be reversed or already applied
+    #[arg(short = 'N', long = "forward")]
+    forward: bool,
+
+    /// Write the patched files to OUTFILE instead of modifying them
+    #[arg(short = 'o', long = "output", value_name = "OUTFILE")]
+    output: Option<PathBuf>,
+
+    /// Strip NUM leading components from file names found in the patch
+    #[arg(short = 'p', long = "strip", value_name = "NUM")]
+    strip: Option<usize>,
+
+    /// Reverse the sense of the patch
+    #[arg(short = 'R', long = "reverse")]
+    reverse: bool,
+
+    /// Write rejected hunks to REJECTFILE instead of FILE.rej
+    #[arg(short = 'r', long = "reject-file", value_name = "REJECTFILE")]
+    reject_file: Option<PathBuf>,
+
+    /// Interpret the patch as a unified diff
+    #[arg(short = 'u', long = "unified", group = "format")]
+    unified: bool,
+
+    /// Ignore up to NUM lines of context when looking for a place to apply
+    /// a hunk
+    #[arg(short = 'F', long = "fuzz", value_name = "NUM", default_value_t = 2)]
+    fuzz: usize,
+
+    /// Work silently unless an error occurs
+    #[arg(short = 's', long = "silent", alias = "quiet")]
+    silent: bool,
+
+    /// Print the results of applying the patch without changing any files
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// File to patch; by default the name is taken from the patch
+    file: Option<PathBuf>,
+}
+
+impl Args {
+    fn forced_format(&self) -> Option<DiffFormat> {
+        if self.context {
+            Some(DiffFormat::Context)
+        } else if self.ed {
+            Some(DiffFormat::Ed)
+        } else if self.normal {
+            Some(DiffFormat::Normal)
+        } else if self.unified {
+            Some(DiffFormat::Unified)
+        } else {
+            None
+        }
+    }
+}
+
+/// The contents of a file, one entry per line, each line keeping its
+/// newline.
+type Lines = Vec<String>;
+
+fn split_lines(text: &str) -> Lines {
+    text.split_inclusive('\n').map(String::from).collect()
+}
+
+/// Removes `strip` leading components from `name`, or all but the last one
+/// if `strip` is `None`. Returns `None` if there are not enough components.
+fn strip_name(name: &str, strip: Option<usize>) -> Option<PathBuf> {
+    let Some(count) = strip else {
+        return Path::new(name).file_name().map(PathBuf::from);
+    };
+    let mut rest = name;
+    for _ in 0..count {
+        let (_, tail) = rest.split_once('/')?;
+        rest = tail.trim_start_matches('/');
+    }
+    if rest.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(rest))
+    }
+}
+
+fn hunk_count(patch: &FilePatch) -> usize {
+    match &patch.body {
+        PatchBody::Hunks(hunks) => hunks.len(),
+        PatchBody::Ed(_) => 1,
+    }
+}
+
+fn plural_hunks(n: usize) -> String {
+    if n == 1 {
+        gettext("hunk")
+    } else {
+        gettext("hunks")
+    }
+}
+
+struct Patcher {
+    args: Args,
+
+    /// Patched contents of every file seen so far, so that later patches to
+    /// the same file apply to the intermediate version.
+    contents: HashMap<PathBuf, Lines>,
+
+    backed_up: HashSet<PathBuf>,
+
+    /// The `-o` file, opened on first use.
+    output: Option<Box<dyn Write>>,
+
+    /// Rejects for the `-r` file, written when all patches are done.
+    rejects: String,
+
+    exit_code: i32,
+}
+
+impl Patcher {
+    fn new(args: Args) -> Self {
+        Self {
+            args,
+            contents: HashMap::new(),
+            backed_up: HashSet::new(),
+            output: None,
+            rejects: String::new(),
+            exit_code: 0,
+        }
+    }
+
+    fn message(&self, text: &str) {
+        if !self.args.silent {
+            println!("{}", text);
+        }
+    }
+
+    /// Chooses the file a patch applies to: the file operand, else the first
+    /// of the names in the patch that exists. A patch that creates a file
+    /// uses the new name.
+    fn target(&self, patch: &FilePatch, hunks: Option<&[Hunk]>) -> Option<PathBuf> {
+        if let Some(file) = &self.args.file {
+            return Some(file.clone());
+        }
+
+        let candidates: Vec<PathBuf> = [&patch.old_name, &patch.new_name, &patch.index_name]
+            .into_iter()
+            .flatten()
+            .filter(|name| name.as_str() != DEV_NULL)
+            .filter_map(|name| strip_name(name, self.args.strip))
+            .collect();
+        if let Some(found) = candidates
+            .iter()
+            .find(|path| self.contents.contains_key(*path) || path.exists())
+        {
+            return Some(found.clone());
+        }
+
+        let creates_file = patch.old_name.as_deref() == Some(DEV_NULL)
+            || hunks.is_some_and(|hunks| {
+                hunks
+                    .iter()
+                    .all(|hunk| hunk.old_start == 0 && hunk.old_len() == 0)
+            });
+        if creates_file {
+            return patch
+                .new_name
+                .as_deref()
+                .filter(|name| *name != DEV_NULL)
+                .and_then(|name| strip_name(name, self.args.strip))
+                .or_else(|| candidates.first().cloned());
+        }
+        None
+    }
+
+    fn load(&self, path: &Path) -> io::Result<Lines> {
+        if let Some(lines) = self.contents.get(path) {
+            return Ok(lines.clone());
+        }
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(split_lines(&text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn process(&mut self, mut patch: FilePatch, line: usize) -> io::Result<()> {
+        if self.args.reverse {
+            match &mut patch.body {
+                PatchBody::Hunks(hunks) => hunks.iter_mut().for_each(Hunk::reverse),
+                PatchBody::Ed(_) => {
+                    return Err(io::Error::other(gettext("ed scripts cannot be reversed")));
+                }
+            }
+            std::mem::swap(&mut patch.old_name, &mut patch.new_name);
+        }
+
+        let hunks = match &patch.body {
+            PatchBody::Hunks(hunks) => Some(hunks.as_slice()),
+            PatchBody::Ed(_) => None,
+        };
+        let Some(target) = self.target(&patch, hunks) else {
+            eprintln!(
+                "{}",
+                gettext!("patch: can't find file to patch at input line {}", line)
+            );
+            let count = hunk_count(&patch);
+            self.message(&gettext!(
+                "No file to patch.  Skipping patch.\n{} out of {} {} ignored",
+                count,
+                count,
+                plural_hunks(count)
+            ));
+            self.exit_code = self.exit_code.max(1);
+            return Ok(());
+        };
+
+        if self.args.dry_run {
+            self.message(&gettext!("checking file {}", target.display()));
+        } else {
+            self.message(&gettext!("patching file {}", target.display()));
+        }
+
+        let lines = self.load(&target)?;
+        let patched = match &patch.body {
+            PatchBody::Ed(commands) => {
+                let mut lines = lines;
+                if let Err(n) = run_ed_script(commands, &mut lines) {
+                    return Err(io::Error::other(gettext!(
+                        "ed script command {} failed",
+                        n + 1
+                    )));
+                }
+                lines
+            }
+            PatchBody::Hunks(hunks) => match self.apply(&patch, hunks, &target, &lines)? {
+                Some(lines) => lines,
+                None => return Ok(()),
+            },
+        };
+
+        self.write(&patch, &target, patched)
+    }
+
+    /// Applies the hunks of `patch` to `lines`, reporting on each one and
+    /// writing rejects. Returns `None` if the patch was skipped.
+    fn apply(
+        &mut self,
+        patch: &FilePatch,
+        hunks: &[Hunk],
+        target: &Path,
+        lines: &[String],
+    ) -> io::Result<Option<Lines>> {
+        let options = ApplyOptions {
+            fuzz: self.args.fuzz,
+            loose_whitespace: self.args.loose_whitespace,
+            ifdef: self.args.ifdef.clone(),
+        };
+
+        // A first hunk that only applies in reverse suggests the patch has
+        // already been applied.
+        if let Some(first) = hunks.first() {
+            let mut reversed = first.clone();
+            reversed.reverse();
+            if locate(first, lines, 0, 0, &options).is_none()
+                && first
+                    .old_lines()
+                    .map(|l| &l.text)
+                    .ne(first.new_lines().map(|l| &l.text))
+                && locate(&reversed, lines, 0, 0, &options).is_some()
+            {
+                self.message(&gettext(
+                    "Reversed (or previously applied) patch detected!  Skipping patch.",
+                ));
+                if self.args.forward {
+                    return Ok(None);
+                }
+                let failed: Vec<&Hunk> = hunks.iter().collect();
+                self.reject(patch, target, &failed, &gettext("ignored"))?;
+                return Ok(None);
+            }
+        }
+
+        let (patched, results) = apply_hunks(hunks, lines, &options);
+
+        let mut failed = Vec::new();
+        for (n, (hunk, result)) in hunks.iter().zip(&results).enumerate() {
+            match result {
+                HunkResult::Applied { line, offset, fuzz } => {
+                    let mut text = String::new();
+                    if *fuzz > 0 {
+                        text.push_str(&gettext!(" with fuzz {}", fuzz));
+                    }
+                    if *offset != 0 {
+                        let unit = if offset.abs() == 1 {
+                            gettext("line")
+                        } else {
+                            gettext("lines")
+                        };
+                        text.push_str(&gettext!(" (offset {} {})", offset, unit));
+                    }
+                    if !text.is_empty() {
+                        self.message(&gettext!("Hunk #{} succeeded at {}{}.", n + 1, line, text));
+                    }
+                }
+                HunkResult::Failed => {
+                    self.message(&gettext!(
+                        "Hunk #{} FAILED at {}.",
+                        n + 1,
+                        hunk.old_start + 1
+                    ));
+                    failed.push(hunk);
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            self.reject(patch, target, &failed, &gettext("FAILED"))?;
+        }
+        Ok(Some(patched))
+    }
+
+    /// Saves `failed` hunks to the reject file.
+    fn reject(
+        &mut self,
+        patch: &FilePatch,
+        target: &Path,
+        failed: &[&Hunk],
+        reason: &str,
+    ) -> io::Result<()> {
+        self.exit_code = self.exit_code.max(1);
+
+        let old_name = patch
+            .old_name
+            .clone()
+            .unwrap_or_else(|| target.display().to_string());
+        let new_name = patch
+            .new_name
+            .clone()
+            .unwrap_or_else(|| target.display().to_string());
+        let mut text = String::new();
+        if patch.format == DiffFormat::Unified {
+            text.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+            failed.iter().for_each(|hunk| hunk.write_unified(&mut text));
+        } else {
+            text.push_str(&format!("*** {}\n--- {}\n", old_name, new_name));
+            failed.iter().for_each(|hunk| hunk.write_context(&mut text));
+        }
+
+        let reject_path = match (&self.args.reject_file, &self.args.output) {
+            (Some(path), _) => path.clone(),
+            (None, Some(output)) if output.as_os_str() != "-" => {
+                PathBuf::from(format!("{}.rej", output.display()))
+            }
+            _ => PathBuf::from(format!("{}.rej", target.display())),
+        };
+
+        let total = hunk_count(patch);
+        let what = if self.args.dry_run {
+            String::new()
+        } else {
+            gettext!(" -- saving rejects to file {}", reject_path.display())
+        };
+        self.message(&gettext!(
+            "{} out of {} {} {}{}",
+            failed.len(),
+            total,
+            plural_hunks(total),
+            reason,
+            what
+        ));
+
+        if self.args.dry_run {
+            return Ok(());
+        }
+        if self.args.reject_file.is_some() {
+            self.rejects.push_str(&text);
+            Ok(())
+        } else {
+            fs::write(reject_path, text)
+        }
+    }
+
+    /// Stores the patched contents of `target`, in place or in the `-o`
+    /// file.
+    fn write(&mut self, patch: &FilePatch, target: &Path, lines: Lines) -> io::Result<()> {
+        if self.args.dry_run {
+            self.contents.insert(target.to_path_buf(), lines);
+            return Ok(());
+        }
+
+        if let Some(output) = &self.args.output {
+            if self.output.is_none() {
+                self.output = Some(if output.as_os_str() == "-" {
+                    Box::new(io::stdout())
+                } else {
+                    Box::new(File::create(output)?)
+                });
+            }
+            let out = self.output.as_mut().unwrap();
+            for line in &lines {
+                out.write_all(line.as_bytes())?;
+            }
+            out.flush()?;
+        } else {
+            let exists = target.exists();
+            if self.args.backup && exists && self.backed_up.insert(target.to_path_buf()) {
+                fs::copy(target, format!("{}.orig", target.display()))?;
+            }
+
+            let removes_file = patch.new_name.as_deref() == Some(DEV_NULL) && lines.is_empty();
+            if removes_file {
+                if exists {
+                    fs::remove_file(target)?;
+                }
+            } else {
+                if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(target, lines.concat())?;
+            }
+        }
+
+        self.contents.insert(target.to_path_buf(), lines);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.args.reject_file {
+            if !self.rejects.is_empty() {
+                fs::write(path, &self.rejects)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn patch(args: Args) -> io::Result<i32> {
+    if let Some(dir) = &args.directory {
+        env::set_current_dir(dir)?;
+    }
+
+    let mut text = String::new();
+    match &args.input {
+        Some(path) if path.as_os_str() != "-" => {
+            File::open(path)?.read_to_string(&mut text)?;
+        }
+        _ => {
+            io::stdin().read_to_string(&mut text)?;
+        }
+    }
+
+    let mut parser = patch_util::Parser::new(&text, args.forced_format());
+    let mut patcher = Patcher::new(args);
+    let mut found = false;
+    loop {
+        let line = parser.line_number();
+        match parser.next_patch() {
+            Ok(Some(patch)) => {
+                found = true;
+                patcher.process(patch, line)?;
+            }
+            Ok(None) => break,
+            Err(err) => {
+                patcher.finish()?;
+                eprintln!("patch: **** {}", err);
+                return Ok(2);
+            }
+        }
+    }
+    patcher.finish()?;
+
+    if !found {
+        eprintln!(
+            "patch: **** {}",
+            gettext("Only garbage was found in the patch input.")
+        );
+        return Ok(2);
+    }
+    Ok(patcher.exit_code)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let args = Args::parse();
+
+    let exit_code = patch(args).unwrap_or_else(|err| {
+        eprintln!("patch: {}", err);
+        2
+    });
+
+    std::process::exit(exit_code)
+}
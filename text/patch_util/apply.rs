@@ -0,0 +1,263 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Locates hunks in a file, allowing for offsets and fuzz, and applies them.
+
+use super::parser::{Hunk, HunkLine, LineKind};
+
+pub struct ApplyOptions {
+    /// Maximum number of context lines that may be ignored at either end of
+    /// a hunk.
+    pub fuzz: usize,
+
+    /// Compare lines with any run of whitespace matching any other.
+    pub loose_whitespace: bool,
+
+    /// Merge the changes into `#ifdef` blocks instead of applying them.
+    pub ifdef: Option<String>,
+}
+
+/// Where a hunk was found.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    /// Index of the first line matched, after ignoring `prefix` lines.
+    pub start: usize,
+
+    /// Number of leading and trailing context lines ignored.
+    pub prefix: usize,
+    pub suffix: usize,
+
+    pub fuzz: usize,
+}
+
+pub enum HunkResult {
+    Applied {
+        /// One-based line number of the first line of the old text.
+        line: usize,
+        offset: isize,
+        fuzz: usize,
+    },
+    Failed,
+}
+
+fn strip_newline(line: &str) -> &str {
+    line.strip_suffix('\n').unwrap_or(line)
+}
+
+fn lines_match(file_line: &str, hunk_line: &str, loose_whitespace: bool) -> bool {
+    let file_line = strip_newline(file_line);
+    if loose_whitespace {
+        file_line
+            .split_whitespace()
+            .eq(hunk_line.split_whitespace())
+    } else {
+        file_line == hunk_line
+    }
+}
+
+/// Pushes `line` onto `out`, first terminating the previous line if it was
+/// an unterminated last line.
+fn push_line(out: &mut Vec<String>, line: String) {
+    if let Some(last) = out.last_mut() {
+        if !last.ends_with('\n') {
+            last.push('\n');
+        }
+    }
+    out.push(line);
+}
+
+/// Searches `lines[min_start..]` for the old text of `hunk`, starting at its
+/// expected position shifted by `offset` and moving outwards. Context is
+/// ignored progressively, up to `fuzz` lines at each end.
+pub fn locate(
+    hunk: &Hunk,
+    lines: &[String],
+    min_start: usize,
+    offset: isize,
+    options: &ApplyOptions,
+) -> Option<Placement> {
+    let old: Vec<&HunkLine> = hunk.old_lines().collect();
+    let (leading, trailing) = hunk.context_len();
+
+    // A hunk with less context at one end than at the other was made at the
+    // beginning or end of the file: that end gets correspondingly less fuzz,
+    // and stays anchored to the beginning or end until the fuzz exceeds the
+    // missing context.
+    let context = leading.max(trailing);
+    for fuzz in 0..=options.fuzz.min(context) {
+        let prefix = (fuzz + leading).saturating_sub(context);
+        let suffix = (fuzz + trailing).saturating_sub(context);
+        let pattern = &old[prefix..old.len() - suffix];
+        if lines.len() < pattern.len() {
+            continue;
+        }
+        let max_start = lines.len() - pattern.len();
+        if min_start > max_start {
+            continue;
+        }
+
+        let at_start = fuzz + leading < context && hunk.old_start == 0;
+        let at_end = fuzz + trailing < context;
+
+        let matches_at = |start: usize| {
+            (!at_start || start == 0)
+                && (!at_end || start + pattern.len() == lines.len())
+                && pattern
+                    .iter()
+                    .zip(&lines[start..])
+                    .all(|(p, l)| lines_match(l, &p.text, options.loose_whitespace))
+        };
+
+        let expected = (hunk.old_start + prefix) as isize + offset;
+        let expected = expected.clamp(min_start as isize, max_start as isize) as usize;
+        for distance in 0..=lines.len() {
+            let after = expected + distance;
+            let before = expected.checked_sub(distance).filter(|&b| b >= min_start);
+            if after > max_start && before.is_none() {
+                break;
+            }
+            if after <= max_start && matches_at(after) {
+                return Some(Placement {
+                    start: after,
+                    prefix,
+                    suffix,
+                    fuzz,
+                });
+            }
+            if let Some(before) = before.filter(|_| distance > 0) {
+                if matches_at(before) {
+                    return Some(Placement {
+                        start: before,
+                        prefix,
+                        suffix,
+                        fuzz,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Appends the new text of `hunk` to `out`, taking the lines of the old text
+/// from `lines` starting at `placement.start`. Returns the index of the first
+/// line after the old text.
+fn apply_at(
+    hunk: &Hunk,
+    placement: &Placement,
+    lines: &[String],
+    out: &mut Vec<String>,
+    ifdef: Option<&str>,
+) -> usize {
+    let body = &hunk.lines[placement.prefix..hunk.lines.len() - placement.suffix];
+    let new_len = hunk.new_len();
+    let mut new_index = placement.prefix;
+    let mut pos = placement.start;
+
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+    for (i, line) in body.iter().enumerate() {
+        match line.kind {
+            LineKind::Context => {
+                push_line(out, lines[pos].clone());
+                pos += 1;
+                new_index += 1;
+            }
+            LineKind::Delete => {
+                deleted.push(lines[pos].clone());
+                pos += 1;
+            }
+            LineKind::Insert => {
+                new_index += 1;
+                if new_index == new_len && hunk.new_no_newline {
+                    inserted.push(line.text.clone());
+                } else {
+                    inserted.push(format!("{}\n", line.text));
+                }
+            }
+        }
+
+        let change_ends = body
+            .get(i + 1)
+            .map_or(true, |next| next.kind == LineKind::Context);
+        if line.kind != LineKind::Context && change_ends {
+            write_change(out, &mut deleted, &mut inserted, ifdef);
+        }
+    }
+    pos
+}
+
+/// Writes one change, as is or wrapped in preprocessor conditionals.
+fn write_change(
+    out: &mut Vec<String>,
+    deleted: &mut Vec<String>,
+    inserted: &mut Vec<String>,
+    ifdef: Option<&str>,
+) {
+    let Some(name) = ifdef else {
+        for line in inserted.drain(..) {
+            push_line(out, line);
+        }
+        deleted.clear();
+        return;
+    };
+
+    if deleted.is_empty() {
+        push_line(out, format!("#ifdef {}\n", name));
+    } else {
+        push_line(out, format!("#ifndef {}\n", name));
+        for line in deleted.drain(..) {
+            push_line(out, line);
+        }
+        if !inserted.is_empty() {
+            push_line(out, "#else\n".to_string());
+        }
+    }
+    for line in inserted.drain(..) {
+        push_line(out, line);
+    }
+    push_line(out, "#endif\n".to_string());
+}
+
+/// Applies `hunks` in order to `lines`, returning the patched lines and what
+/// happened to each hunk. Hunks that cannot be located are left out.
+pub fn apply_hunks(
+    hunks: &[Hunk],
+    lines: &[String],
+    options: &ApplyOptions,
+) -> (Vec<String>, Vec<HunkResult>) {
+    let mut out = Vec::with_capacity(lines.len());
+    let mut results = Vec::with_capacity(hunks.len());
+    let mut pos = 0;
+    let mut offset = 0;
+
+    for hunk in hunks {
+        let Some(placement) = locate(hunk, lines, pos, offset, options) else {
+            results.push(HunkResult::Failed);
+            continue;
+        };
+        let old_start = placement.start - placement.prefix;
+        offset = old_start as isize - hunk.old_start as isize;
+
+        for line in &lines[pos..placement.start] {
+            push_line(&mut out, line.clone());
+        }
+        pos = apply_at(hunk, &placement, lines, &mut out, options.ifdef.as_deref());
+        results.push(HunkResult::Applied {
+            line: old_start + 1,
+            offset,
+            fuzz: placement.fuzz,
+        });
+    }
+
+    for line in &lines[pos..] {
+        push_line(&mut out, line.clone());
+    }
+    (out, results)
+}
@@ -0,0 +1,97 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! The subset of `ed` that `diff -e` scripts use.
+
+/// One command of an ed script.
+#[derive(Debug)]
+pub enum EdCommand {
+    /// `start,end` followed by `a`, `c` or `d`, with the text to add.
+    Edit {
+        start: usize,
+        end: usize,
+        cmd: char,
+        text: Vec<String>,
+    },
+
+    /// `a` without an address: append after the current line.
+    AppendHere(Vec<String>),
+
+    /// `s/.//`: remove the first character of the current line, which
+    /// `diff -e` uses to restore lines consisting of a single period.
+    RemoveFirstChar,
+}
+
+/// Runs `commands` on `lines`. Each line keeps its terminating newline, if
+/// any. Fails with the index of the offending command.
+pub fn run(commands: &[EdCommand], lines: &mut Vec<String>) -> Result<(), usize> {
+    let terminated = |text: &[String]| -> Vec<String> {
+        text.iter().map(|line| format!("{}\n", line)).collect()
+    };
+    // Current line, one-based; zero before the first line.
+    let mut current = lines.len();
+
+    for (n, command) in commands.iter().enumerate() {
+        match command {
+            EdCommand::Edit {
+                start,
+                end,
+                cmd,
+                text,
+            } => {
+                let (start, end) = (*start, *end);
+                if start > end || end > lines.len() || (start == 0 && *cmd != 'a') {
+                    return Err(n);
+                }
+                // Text added after an unterminated last line starts a new line.
+                if *cmd == 'a' && end == lines.len() {
+                    if let Some(last) = lines.last_mut().filter(|l| !l.ends_with('\n')) {
+                        last.push('\n');
+                    }
+                }
+                let (first, last) = if *cmd == 'a' {
+                    (end, end)
+                } else {
+                    (start - 1, end)
+                };
+                let text = if *cmd == 'd' {
+                    Vec::new()
+                } else {
+                    terminated(text)
+                };
+                let added = text.len();
+                lines.splice(first..last, text);
+                // After a deletion the line that followed becomes current, or
+                // the new last line if there is none.
+                current = if added > 0 {
+                    first + added
+                } else if first < lines.len() {
+                    first + 1
+                } else {
+                    lines.len()
+                };
+            }
+            EdCommand::AppendHere(text) => {
+                let text = terminated(text);
+                let added = text.len();
+                lines.splice(current..current, text);
+                current += added;
+            }
+            EdCommand::RemoveFirstChar => {
+                let Some(line) = current.checked_sub(1).and_then(|i| lines.get_mut(i)) else {
+                    return Err(n);
+                };
+                if line.starts_with('.') {
+                    line.remove(0);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,16 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+mod apply;
+mod ed;
+mod parser;
+
+pub use apply::{apply_hunks, locate, ApplyOptions, HunkResult};
+pub use ed::run as run_ed_script;
+pub use parser::{DiffFormat, FilePatch, Hunk, Parser, PatchBody};
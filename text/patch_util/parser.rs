@@ -0,0 +1,735 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+//! Splits a patch into per-file patches and parses the hunks of the normal,
+//! context, unified and ed-script formats into a common representation.
+
+use super::ed::EdCommand;
+use std::fmt;
+
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffFormat {
+    Normal,
+    Context,
+    Unified,
+    Ed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineKind {
+    Context,
+    Delete,
+    Insert,
+}
+
+#[derive(Clone, Debug)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// One hunk, in whichever format it was written. `old_start` and
+/// `new_start` are the zero-based indices of the first line of the old and
+/// new text; for an empty text it is the index of the line it precedes.
+#[derive(Clone, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub new_start: usize,
+    pub lines: Vec<HunkLine>,
+    pub old_no_newline: bool,
+    pub new_no_newline: bool,
+}
+
+impl Hunk {
+    pub fn old_lines(&self) -> impl Iterator<Item = &HunkLine> {
+        self.lines.iter().filter(|l| l.kind != LineKind::Insert)
+    }
+
+    pub fn new_lines(&self) -> impl Iterator<Item = &HunkLine> {
+        self.lines.iter().filter(|l| l.kind != LineKind::Delete)
+    }
+
+    pub fn old_len(&self) -> usize {
+        self.old_lines().count()
+    }
+
+    pub fn new_len(&self) -> usize {
+        self.new_lines().count()
+    }
+
+    /// Number of context lines before the first and after the last change.
+    pub fn context_len(&self) -> (usize, usize) {
+        let is_context = |l: &&HunkLine| l.kind == LineKind::Context;
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = if leading == self.lines.len() {
+            0
+        } else {
+            self.lines.iter().rev().take_while(is_context).count()
+        };
+        (leading, trailing)
+    }
+
+    /// Swaps the old and new texts, for `-R`.
+    pub fn reverse(&mut self) {
+        for line in &mut self.lines {
+            line.kind = match line.kind {
+                LineKind::Context => LineKind::Context,
+                LineKind::Delete => LineKind::Insert,
+                LineKind::Insert => LineKind::Delete,
+            };
+        }
+        // Keep deletions ahead of insertions within each change.
+        let mut i = 0;
+        while i < self.lines.len() {
+            if self.lines[i].kind == LineKind::Context {
+                i += 1;
+                continue;
+            }
+            let end = (i..self.lines.len())
+                .find(|&j| self.lines[j].kind == LineKind::Context)
+                .unwrap_or(self.lines.len());
+            self.lines[i..end].sort_by_key(|l| l.kind == LineKind::Insert);
+            i = end;
+        }
+        std::mem::swap(&mut self.old_start, &mut self.new_start);
+        std::mem::swap(&mut self.old_no_newline, &mut self.new_no_newline);
+    }
+
+    /// Writes the hunk in unified format, as used for reject files.
+    pub fn write_unified(&self, out: &mut String) {
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            unified_range(self.old_start, self.old_len()),
+            unified_range(self.new_start, self.new_len())
+        ));
+        let last_old = self.lines.iter().rposition(|l| l.kind != LineKind::Insert);
+        let last_new = self.lines.iter().rposition(|l| l.kind != LineKind::Delete);
+        for (i, line) in self.lines.iter().enumerate() {
+            let prefix = match line.kind {
+                LineKind::Context => ' ',
+                LineKind::Delete => '-',
+                LineKind::Insert => '+',
+            };
+            out.push_str(&format!("{}{}\n", prefix, line.text));
+            if (self.old_no_newline && Some(i) == last_old)
+                || (self.new_no_newline && Some(i) == last_new)
+            {
+                out.push_str(NO_NEWLINE_MARKER);
+                out.push('\n');
+            }
+        }
+    }
+
+    /// Writes the hunk in context format, as used for reject files.
+    pub fn write_context(&self, out: &mut String) {
+        // A change that both deletes and inserts lines is marked with `!`.
+        let mut changed = vec![false; self.lines.len()];
+        let mut i = 0;
+        while i < self.lines.len() {
+            let end = (i..self.lines.len())
+                .find(|&j| self.lines[j].kind == LineKind::Context)
+                .unwrap_or(self.lines.len());
+            let block = &self.lines[i..end];
+            let is_change = block.iter().any(|l| l.kind == LineKind::Delete)
+                && block.iter().any(|l| l.kind == LineKind::Insert);
+            changed[i..end].fill(is_change);
+            i = end + 1;
+        }
+
+        out.push_str("***************\n");
+        let sections = [
+            (
+                LineKind::Delete,
+                self.old_start,
+                self.old_len(),
+                self.old_no_newline,
+            ),
+            (
+                LineKind::Insert,
+                self.new_start,
+                self.new_len(),
+                self.new_no_newline,
+            ),
+        ];
+        for (n, (kind, start, len, no_newline)) in sections.into_iter().enumerate() {
+            let range = context_range(start, len);
+            if n == 0 {
+                out.push_str(&format!("*** {} ****\n", range));
+            } else {
+                out.push_str(&format!("--- {} ----\n", range));
+            }
+            if !self.lines.iter().any(|l| l.kind == kind) {
+                continue;
+            }
+            let lines: Vec<_> = self
+                .lines
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| l.kind == kind || l.kind == LineKind::Context)
+                .collect();
+            for (j, &(i, line)) in lines.iter().enumerate() {
+                let prefix = if line.kind == LineKind::Context {
+                    "  "
+                } else if changed[i] {
+                    "! "
+                } else if kind == LineKind::Delete {
+                    "- "
+                } else {
+                    "+ "
+                };
+                out.push_str(&format!("{}{}\n", prefix, line.text));
+                if no_newline && j + 1 == lines.len() {
+                    out.push_str(NO_NEWLINE_MARKER);
+                    out.push('\n');
+                }
+            }
+        }
+    }
+}
+
+fn unified_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn context_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{}", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, start + len),
+    }
+}
+
+/// The changes a patch makes to one file.
+#[derive(Debug)]
+pub enum PatchBody {
+    Hunks(Vec<Hunk>),
+    Ed(Vec<EdCommand>),
+}
+
+#[derive(Debug)]
+pub struct FilePatch {
+    pub format: DiffFormat,
+    pub old_name: Option<String>,
+    pub new_name: Option<String>,
+    pub index_name: Option<String>,
+    pub body: PatchBody,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Parses a decimal line number, as found in hunk headers.
+fn number(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// First and last line numbers of a range in a hunk header.
+type LineRange = (usize, usize);
+
+/// Parses `N` or `N,M`.
+fn line_range(s: &str) -> Option<LineRange> {
+    match s.split_once(',') {
+        Some((a, b)) => Some((number(a)?, number(b)?)),
+        None => number(s).map(|n| (n, n)),
+    }
+}
+
+/// Parses a normal-format command line such as `3,4c5`.
+fn normal_command(line: &str) -> Option<(LineRange, char, LineRange)> {
+    let pos = line.find(['a', 'c', 'd'])?;
+    let cmd = line[pos..].chars().next()?;
+    Some((
+        line_range(&line[..pos])?,
+        cmd,
+        line_range(&line[pos + 1..])?,
+    ))
+}
+
+/// Parses an ed-script command line such as `3,4c`, as written by `diff -e`.
+fn ed_command(line: &str) -> Option<(LineRange, char)> {
+    let cmd = line.chars().last()?;
+    if !matches!(cmd, 'a' | 'c' | 'd') {
+        return None;
+    }
+    Some((line_range(&line[..line.len() - 1])?, cmd))
+}
+
+/// Extracts the file name from a `---`, `+++` or `***` header line, dropping
+/// the timestamp that follows a tab.
+fn header_name(rest: &str) -> Option<String> {
+    let name = rest.split('\t').next().unwrap_or(rest).trim_end();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+pub struct Parser<'a> {
+    lines: Vec<&'a str>,
+    pos: usize,
+    forced: Option<DiffFormat>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str, forced: Option<DiffFormat>) -> Self {
+        Self {
+            lines: text.lines().collect(),
+            pos: 0,
+            forced,
+        }
+    }
+
+    /// Line number of the current line, for diagnostics.
+    pub fn line_number(&self) -> usize {
+        self.pos + 1
+    }
+
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.lines.get(self.pos + offset).copied()
+    }
+
+    fn error<T>(&self, message: &str) -> ParseResult<T> {
+        Err(ParseError {
+            line: self.line_number(),
+            message: message.to_string(),
+        })
+    }
+
+    fn accepts(&self, format: DiffFormat) -> bool {
+        self.forced.map_or(true, |forced| forced == format)
+    }
+
+    /// Detects the format of a patch starting at the current line.
+    fn detect(&self) -> Option<DiffFormat> {
+        let line = self.peek(0)?;
+        let next = self.peek(1).unwrap_or("");
+        let after = self.peek(2).unwrap_or("");
+        if self.accepts(DiffFormat::Unified)
+            && line.starts_with("--- ")
+            && next.starts_with("+++ ")
+            && after.starts_with("@@ -")
+        {
+            Some(DiffFormat::Unified)
+        } else if self.accepts(DiffFormat::Context)
+            && line.starts_with("*** ")
+            && next.starts_with("--- ")
+            && after.starts_with("***************")
+        {
+            Some(DiffFormat::Context)
+        } else if self.accepts(DiffFormat::Normal) && normal_command(line).is_some() {
+            Some(DiffFormat::Normal)
+        } else if self.accepts(DiffFormat::Ed) && ed_command(line).is_some() {
+            Some(DiffFormat::Ed)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the next file patch, skipping any leading garbage, or `None`
+    /// at the end of the input.
+    pub fn next_patch(&mut self) -> ParseResult<Option<FilePatch>> {
+        let mut index_name = None;
+        while let Some(line) = self.peek(0) {
+            if let Some(rest) = line.strip_prefix("Index:") {
+                index_name = header_name(rest.trim_start());
+                self.pos += 1;
+                continue;
+            }
+            let Some(format) = self.detect() else {
+                self.pos += 1;
+                continue;
+            };
+
+            let mut patch = FilePatch {
+                format,
+                old_name: None,
+                new_name: None,
+                index_name,
+                body: PatchBody::Hunks(Vec::new()),
+            };
+            match format {
+                DiffFormat::Unified | DiffFormat::Context => {
+                    patch.old_name = header_name(&self.lines[self.pos][4..]);
+                    patch.new_name = header_name(&self.lines[self.pos + 1][4..]);
+                    self.pos += 2;
+                    let hunks = if format == DiffFormat::Unified {
+                        self.unified_hunks()?
+                    } else {
+                        self.context_hunks()?
+                    };
+                    patch.body = PatchBody::Hunks(hunks);
+                }
+                DiffFormat::Normal => patch.body = PatchBody::Hunks(self.normal_hunks()?),
+                DiffFormat::Ed => patch.body = PatchBody::Ed(self.ed_commands()?),
+            }
+            return Ok(Some(patch));
+        }
+        Ok(None)
+    }
+
+    /// Consumes a "No newline at end of file" marker, if present.
+    fn no_newline_marker(&mut self) -> bool {
+        match self.peek(0) {
+            Some(line) if line.starts_with("\\ ") => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn unified_hunks(&mut self) -> ParseResult<Vec<Hunk>> {
+        let mut hunks = Vec::new();
+        while let Some(header) = self.peek(0).and_then(|l| l.strip_prefix("@@ -")) {
+            let Some((old, new)) = header
+                .split_once(" @@")
+                .and_then(|(ranges, _)| ranges.split_once(" +"))
+            else {
+                return self.error("malformed hunk header");
+            };
+            let parse = |s: &str| match s.split_once(',') {
+                Some((start, len)) => Some((number(start)?, number(len)?)),
+                None => Some((number(s)?, 1)),
+            };
+            let (Some((old_start, mut old_left)), Some((new_start, mut new_left))) =
+                (parse(old), parse(new))
+            else {
+                return self.error("malformed hunk header");
+            };
+            self.pos += 1;
+
+            let mut hunk = Hunk {
+                old_start: if old_left == 0 {
+                    old_start
+                } else {
+                    old_start - 1
+                },
+                new_start: if new_left == 0 {
+                    new_start
+                } else {
+                    new_start - 1
+                },
+                lines: Vec::new(),
+                old_no_newline: false,
+                new_no_newline: false,
+            };
+            while old_left > 0 || new_left > 0 {
+                let Some(line) = self.peek(0) else {
+                    return self.error("unexpected end of hunk");
+                };
+                // Some mailers strip the space off empty context lines.
+                let (prefix, text) = match line.chars().next() {
+                    Some(c) => (c, &line[c.len_utf8()..]),
+                    None => (' ', ""),
+                };
+                let kind = match prefix {
+                    ' ' if old_left > 0 && new_left > 0 => LineKind::Context,
+                    '-' if old_left > 0 => LineKind::Delete,
+                    '+' if new_left > 0 => LineKind::Insert,
+                    '\\' => {
+                        self.pos += 1;
+                        self.mark_no_newline(&mut hunk);
+                        continue;
+                    }
+                    _ => return self.error("malformed patch"),
+                };
+                if kind != LineKind::Insert {
+                    old_left -= 1;
+                }
+                if kind != LineKind::Delete {
+                    new_left -= 1;
+                }
+                hunk.lines.push(HunkLine {
+                    kind,
+                    text: text.to_string(),
+                });
+                self.pos += 1;
+            }
+            if self.no_newline_marker() {
+                self.mark_no_newline(&mut hunk);
+            }
+            hunks.push(hunk);
+        }
+        if hunks.is_empty() {
+            return self.error("malformed patch");
+        }
+        Ok(hunks)
+    }
+
+    /// Applies a unified-format "No newline" marker to the line before it.
+    fn mark_no_newline(&self, hunk: &mut Hunk) {
+        match hunk.lines.last().map(|l| l.kind) {
+            Some(LineKind::Context) => {
+                hunk.old_no_newline = true;
+                hunk.new_no_newline = true;
+            }
+            Some(LineKind::Delete) => hunk.old_no_newline = true,
+            Some(LineKind::Insert) => hunk.new_no_newline = true,
+            None => {}
+        }
+    }
+
+    /// Reads one section of a context hunk: lines starting with two-character
+    /// prefixes, plus an optional trailing "No newline" marker.
+    fn context_section(&mut self, changes: &[&str]) -> (Vec<(char, String)>, bool) {
+        let mut lines = Vec::new();
+        let mut no_newline = false;
+        while let Some(line) = self.peek(0) {
+            let prefix = line.get(..2).unwrap_or(line);
+            if prefix == "  " || changes.contains(&prefix) {
+                lines.push((prefix.chars().next().unwrap(), line[2..].to_string()));
+            } else if line.starts_with("\\ ") {
+                no_newline = true;
+            } else {
+                break;
+            }
+            self.pos += 1;
+        }
+        (lines, no_newline)
+    }
+
+    fn context_hunks(&mut self) -> ParseResult<Vec<Hunk>> {
+        let mut hunks = Vec::new();
+        while self
+            .peek(0)
+            .is_some_and(|l| l.starts_with("***************"))
+        {
+            self.pos += 1;
+            let Some(old_range) = self
+                .peek(0)
+                .and_then(|l| l.strip_prefix("*** "))
+                .and_then(|l| l.strip_suffix(" ****"))
+                .and_then(line_range)
+            else {
+                return self.error("malformed hunk header");
+            };
+            self.pos += 1;
+            let (old, old_no_newline) = self.context_section(&["- ", "! "]);
+
+            let Some(new_range) = self
+                .peek(0)
+                .and_then(|l| l.strip_prefix("--- "))
+                .and_then(|l| l.strip_suffix(" ----"))
+                .and_then(line_range)
+            else {
+                return self.error("malformed hunk header");
+            };
+            self.pos += 1;
+            let (new, new_no_newline) = self.context_section(&["+ ", "! "]);
+
+            let lines = merge_context_sections(&old, &new).ok_or_else(|| ParseError {
+                line: self.line_number(),
+                message: "context mangled in hunk".to_string(),
+            })?;
+            let mut hunk = Hunk {
+                old_start: 0,
+                new_start: 0,
+                lines,
+                old_no_newline,
+                new_no_newline,
+            };
+            // A range names the line before an empty text.
+            hunk.old_start = if hunk.old_len() == 0 {
+                old_range.1
+            } else {
+                old_range.0.saturating_sub(1)
+            };
+            hunk.new_start = if hunk.new_len() == 0 {
+                new_range.1
+            } else {
+                new_range.0.saturating_sub(1)
+            };
+            if old.is_empty() && new.is_empty() {
+                hunk.old_no_newline = false;
+                hunk.new_no_newline = false;
+            } else if old.is_empty() {
+                hunk.old_no_newline = new_no_newline && new.last().is_some_and(|l| l.0 == ' ');
+            } else if new.is_empty() {
+                hunk.new_no_newline = old_no_newline && old.last().is_some_and(|l| l.0 == ' ');
+            }
+            hunks.push(hunk);
+        }
+        if hunks.is_empty() {
+            return self.error("malformed patch");
+        }
+        Ok(hunks)
+    }
+
+    fn normal_hunks(&mut self) -> ParseResult<Vec<Hunk>> {
+        let mut hunks = Vec::new();
+        while let Some(((a1, a2), cmd, (b1, b2))) = self.peek(0).and_then(normal_command) {
+            self.pos += 1;
+            let mut hunk = Hunk {
+                old_start: if cmd == 'a' { a1 } else { a1.saturating_sub(1) },
+                new_start: if cmd == 'd' { b1 } else { b1.saturating_sub(1) },
+                lines: Vec::new(),
+                old_no_newline: false,
+                new_no_newline: false,
+            };
+            if cmd != 'a' {
+                for _ in a1..=a2 {
+                    match self.peek(0).and_then(|l| l.strip_prefix("< ")) {
+                        Some(text) => hunk.lines.push(HunkLine {
+                            kind: LineKind::Delete,
+                            text: text.to_string(),
+                        }),
+                        None => return self.error("malformed patch"),
+                    }
+                    self.pos += 1;
+                }
+                hunk.old_no_newline = self.no_newline_marker();
+            }
+            if cmd == 'c' {
+                if self.peek(0) != Some("---") {
+                    return self.error("malformed patch");
+                }
+                self.pos += 1;
+            }
+            if cmd != 'd' {
+                for _ in b1..=b2 {
+                    match self.peek(0).and_then(|l| l.strip_prefix("> ")) {
+                        Some(text) => hunk.lines.push(HunkLine {
+                            kind: LineKind::Insert,
+                            text: text.to_string(),
+                        }),
+                        None => return self.error("malformed patch"),
+                    }
+                    self.pos += 1;
+                }
+                hunk.new_no_newline = self.no_newline_marker();
+            }
+            hunks.push(hunk);
+        }
+        Ok(hunks)
+    }
+
+    fn ed_commands(&mut self) -> ParseResult<Vec<EdCommand>> {
+        let mut commands = Vec::new();
+        while let Some(line) = self.peek(0) {
+            let command = if let Some(((start, end), cmd)) = ed_command(line) {
+                self.pos += 1;
+                let mut text = Vec::new();
+                if cmd != 'd' {
+                    loop {
+                        match self.peek(0) {
+                            Some(".") => break,
+                            Some(line) => text.push(line.to_string()),
+                            None => return self.error("unterminated ed text"),
+                        }
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                }
+                EdCommand::Edit {
+                    start,
+                    end,
+                    cmd,
+                    text,
+                }
+            } else if line == "a" {
+                self.pos += 1;
+                let mut text = Vec::new();
+                loop {
+                    match self.peek(0) {
+                        Some(".") => break,
+                        Some(line) => text.push(line.to_string()),
+                        None => return self.error("unterminated ed text"),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                EdCommand::AppendHere(text)
+            } else if line == "s/.//" {
+                self.pos += 1;
+                EdCommand::RemoveFirstChar
+            } else if line == "w" || line == "q" {
+                self.pos += 1;
+                continue;
+            } else {
+                break;
+            };
+            commands.push(command);
+        }
+        Ok(commands)
+    }
+}
+
+/// Merges the old and new sections of a context hunk into a single list of
+/// lines. Either section may be missing when it has no changes, in which
+/// case it consists of the context lines of the other one.
+fn merge_context_sections(old: &[(char, String)], new: &[(char, String)]) -> Option<Vec<HunkLine>> {
+    let line = |kind, text: &String| HunkLine {
+        kind,
+        text: text.clone(),
+    };
+    if old.is_empty() || new.is_empty() {
+        let (section, kind) = if old.is_empty() {
+            (new, LineKind::Insert)
+        } else {
+            (old, LineKind::Delete)
+        };
+        return Some(
+            section
+                .iter()
+                .map(|(c, text)| line(if *c == ' ' { LineKind::Context } else { kind }, text))
+                .collect(),
+        );
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && old[i].0 == '-' {
+            lines.push(line(LineKind::Delete, &old[i].1));
+            i += 1;
+        } else if j < new.len() && new[j].0 == '+' {
+            lines.push(line(LineKind::Insert, &new[j].1));
+            j += 1;
+        } else if (i < old.len() && old[i].0 == '!') || (j < new.len() && new[j].0 == '!') {
+            while i < old.len() && old[i].0 == '!' {
+                lines.push(line(LineKind::Delete, &old[i].1));
+                i += 1;
+            }
+            while j < new.len() && new[j].0 == '!' {
+                lines.push(line(LineKind::Insert, &new[j].1));
+                j += 1;
+            }
+        } else if i < old.len() && j < new.len() {
+            lines.push(line(LineKind::Context, &old[i].1));
+            i += 1;
+            j += 1;
+        } else {
+            return None;
+        }
+    }
+    Some(lines)
+}
@@ -0,0 +1,1241 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use gettextrs::{bind_textdomain_codeset, gettext, setlocale, textdomain, LocaleCategory};
+use plib::regex::{bracket_expression_end, Regex, RegexFlags};
+use plib::PROJECT_NAME;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    mem,
+    path::PathBuf,
+    rc::Rc,
+};
+
+/// sed - stream editor
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Suppress the default output of the pattern space at the end of each cycle.
+    #[arg(short = 'n')]
+    quiet: bool,
+
+    /// Add the editing commands in SCRIPT to the script.
+    #[arg(short = 'e', value_name = "SCRIPT")]
+    expression: Vec<String>,
+
+    /// Add the editing commands in the file SCRIPT_FILE to the script.
+    #[arg(short = 'f', value_name = "SCRIPT_FILE")]
+    script_file: Vec<PathBuf>,
+
+    /// Use extended regular expressions instead of basic regular expressions.
+    #[arg(short = 'E')]
+    extended_regexp: bool,
+
+    /// The script, unless -e or -f is given, followed by the files to edit.
+    /// If no files are given, the standard input is used.
+    operands: Vec<String>,
+}
+
+enum Address {
+    Line(usize),
+    Last,
+    /// `None` stands for the empty regex, i.e. the last regex used.
+    Regex(Option<Rc<Regex>>),
+}
+
+enum ReplacementPart {
+    Literal(Vec<u8>),
+    /// `&` is group 0, `\1` to `\9` are groups 1 to 9.
+    Group(usize),
+}
+
+struct Substitution {
+    regex: Option<Rc<Regex>>,
+    replacement: Vec<ReplacementPart>,
+    /// Replace the `occurrence`-th match (and, with `global`, all after it).
+    occurrence: usize,
+    global: bool,
+    print: bool,
+    write: Option<usize>,
+}
+
+enum Function {
+    /// `{`: the index of the command following the matching `}`.
+    BlockStart(usize),
+    BlockEnd,
+    Append(Vec<u8>),
+    Insert(Vec<u8>),
+    Change(Vec<u8>),
+    /// `b`: the index of the command to continue at.
+    Branch(usize),
+    /// `t`: the index of the command to continue at.
+    Test(usize),
+    Label,
+    Delete,
+    DeleteFirstLine,
+    Get,
+    GetAppend,
+    Hold,
+    HoldAppend,
+    Exchange,
+    List,
+    Next,
+    NextAppend,
+    Print,
+    PrintFirstLine,
+    Quit,
+    LineNumber,
+    ReadFile(PathBuf),
+    WriteFile(usize),
+    Substitute(Box<Substitution>),
+    Transliterate(Vec<(char, char)>),
+}
+
+struct Command {
+    addr1: Option<Address>,
+    addr2: Option<Address>,
+    negated: bool,
+    function: Function,
+}
+
+/// A file written by the `w` command or the `w` flag of `s`.
+enum Output {
+    Stdout,
+    Stderr,
+    File(BufWriter<File>),
+}
+
+/// The `w` files of a script.  All of them are created before any input is
+/// read, and each file is opened once no matter how many commands write to it.
+#[derive(Default)]
+struct WriteFiles {
+    indices: HashMap<PathBuf, usize>,
+    outputs: Vec<Output>,
+}
+
+impl WriteFiles {
+    fn open(&mut self, path: PathBuf) -> Result<usize, String> {
+        if let Some(&index) = self.indices.get(&path) {
+            return Ok(index);
+        }
+        let output = match path.to_str() {
+            Some("/dev/stdout") => Output::Stdout,
+            Some("/dev/stderr") => Output::Stderr,
+            _ => {
+                let file = File::create(&path)
+                    .map_err(|err| gettext!("couldn't open file {}: {}", path.display(), err))?;
+                Output::File(BufWriter::new(file))
+            }
+        };
+        let index = self.outputs.len();
+        self.outputs.push(output);
+        self.indices.insert(path, index);
+        Ok(index)
+    }
+}
+
+/// Translates a script into the list of commands it consists of.
+///
+/// Blocks and branches are resolved to command indices, so executing the
+/// script only needs a program counter.
+struct ScriptParser<'a> {
+    script: &'a [u8],
+    pos: usize,
+    line: usize,
+    extended: bool,
+    commands: Vec<Command>,
+    /// Indices of the `{` commands whose `}` has not been seen yet.
+    open_blocks: Vec<usize>,
+    labels: HashMap<Vec<u8>, usize>,
+    /// Branch commands, by index, and the labels they jump to.
+    jumps: Vec<(usize, Vec<u8>)>,
+    write_files: WriteFiles,
+}
+
+impl<'a> ScriptParser<'a> {
+    fn new(script: &'a [u8], extended: bool) -> Self {
+        ScriptParser {
+            script,
+            pos: 0,
+            line: 1,
+            extended,
+            commands: Vec::new(),
+            open_blocks: Vec::new(),
+            labels: HashMap::new(),
+            jumps: Vec::new(),
+            write_files: WriteFiles::default(),
+        }
+    }
+
+    fn error(&self, message: String) -> String {
+        gettext!("script line {}: {}", self.line, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.script.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse(mut self) -> Result<(Vec<Command>, WriteFiles), String> {
+        loop {
+            while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b';')) {
+                self.next();
+            }
+            match self.peek() {
+                None => break,
+                Some(b'#') => {
+                    self.skip_comment();
+                    continue;
+                }
+                _ => {}
+            }
+            self.parse_command()?;
+        }
+
+        if !self.open_blocks.is_empty() {
+            return Err(self.error(gettext("unmatched `{'")));
+        }
+        for (index, label) in mem::take(&mut self.jumps) {
+            let target = if label.is_empty() {
+                self.commands.len()
+            } else {
+                *self.labels.get(&label).ok_or_else(|| {
+                    gettext!(
+                        "can't find label for jump to `{}'",
+                        String::from_utf8_lossy(&label)
+                    )
+                })?
+            };
+            match &mut self.commands[index].function {
+                Function::Branch(t) | Function::Test(t) => *t = target,
+                _ => unreachable!(),
+            }
+        }
+
+        Ok((self.commands, self.write_files))
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.next() {
+            if c == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<(), String> {
+        let addr1 = self.parse_address()?;
+        let addr2 = if addr1.is_some() && self.peek() == Some(b',') {
+            self.pos += 1;
+            self.skip_blanks();
+            match self.parse_address()? {
+                Some(addr) => Some(addr),
+                None => return Err(self.error(gettext("unexpected `,'"))),
+            }
+        } else {
+            None
+        };
+
+        self.skip_blanks();
+        let negated = self.peek() == Some(b'!');
+        if negated {
+            self.pos += 1;
+            self.skip_blanks();
+        }
+
+        let Some(c) = self.next() else {
+            return Err(self.error(gettext("missing command")));
+        };
+        let index = self.commands.len();
+        let function = match c {
+            b'{' => {
+                self.open_blocks.push(index);
+                Function::BlockStart(0)
+            }
+            b'}' => {
+                if addr1.is_some() || negated {
+                    return Err(self.error(gettext("} doesn't want any addresses")));
+                }
+                let Some(start) = self.open_blocks.pop() else {
+                    return Err(self.error(gettext("unexpected `}'")));
+                };
+                self.commands[start].function = Function::BlockStart(index + 1);
+                Function::BlockEnd
+            }
+            b'a' => Function::Append(self.parse_text()?),
+            b'i' => Function::Insert(self.parse_text()?),
+            b'c' => Function::Change(self.parse_text()?),
+            b':' => {
+                if addr1.is_some() {
+                    return Err(self.error(gettext(": doesn't want any addresses")));
+                }
+                let label = self.parse_label();
+                if label.is_empty() {
+                    return Err(self.error(gettext("\":\" lacks a label")));
+                }
+                if self.labels.insert(label, index).is_some() {
+                    return Err(self.error(gettext("duplicate label")));
+                }
+                Function::Label
+            }
+            b'b' | b't' => {
+                let label = self.parse_label();
+                self.jumps.push((index, label));
+                if c == b'b' {
+                    Function::Branch(0)
+                } else {
+                    Function::Test(0)
+                }
+            }
+            b'd' => Function::Delete,
+            b'D' => Function::DeleteFirstLine,
+            b'g' => Function::Get,
+            b'G' => Function::GetAppend,
+            b'h' => Function::Hold,
+            b'H' => Function::HoldAppend,
+            b'x' => Function::Exchange,
+            b'l' => Function::List,
+            b'n' => Function::Next,
+            b'N' => Function::NextAppend,
+            b'p' => Function::Print,
+            b'P' => Function::PrintFirstLine,
+            b'q' => {
+                if addr2.is_some() {
+                    return Err(self.error(gettext("command only uses one address")));
+                }
+                Function::Quit
+            }
+            b'=' => Function::LineNumber,
+            b'r' => Function::ReadFile(self.parse_filename()?),
+            b'w' => {
+                let path = self.parse_filename()?;
+                Function::WriteFile(self.write_files.open(path)?)
+            }
+            b's' => Function::Substitute(Box::new(self.parse_substitution()?)),
+            b'y' => Function::Transliterate(self.parse_transliteration()?),
+            c => return Err(self.error(gettext!("unknown command: `{}'", char::from(c)))),
+        };
+
+        self.commands.push(Command {
+            addr1,
+            addr2,
+            negated,
+            function,
+        });
+
+        // Commands taking a text, label or file name end at the end of the
+        // line, and `{` may be followed by a command right away; all others
+        // may be followed by another command after a `;`.
+        if !matches!(
+            c,
+            b'{' | b'a' | b'i' | b'c' | b':' | b'b' | b't' | b'r' | b'w'
+        ) {
+            self.skip_blanks();
+            match self.peek() {
+                None | Some(b';' | b'\n' | b'}' | b'#') => {}
+                Some(c) => {
+                    return Err(self.error(gettext!(
+                        "extra characters after command: `{}'",
+                        char::from(c)
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_address(&mut self) -> Result<Option<Address>, String> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.script[start..self.pos])
+                    .unwrap()
+                    .parse::<usize>()
+                    .map_err(|err| self.error(err.to_string()))?;
+                if number == 0 {
+                    return Err(self.error(gettext("invalid usage of line address 0")));
+                }
+                Ok(Some(Address::Line(number)))
+            }
+            Some(b'$') => {
+                self.pos += 1;
+                Ok(Some(Address::Last))
+            }
+            Some(b'/') => {
+                self.pos += 1;
+                Ok(Some(Address::Regex(self.parse_regex(b'/')?)))
+            }
+            Some(b'\\') => {
+                self.pos += 1;
+                match self.next() {
+                    Some(b'\n' | b'\\') | None => {
+                        Err(self.error(gettext("unexpected address delimiter")))
+                    }
+                    Some(delimiter) => Ok(Some(Address::Regex(self.parse_regex(delimiter)?))),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads a regex terminated by `delimiter` and compiles it.  Within it,
+    /// `\n` stands for a newline and an escaped delimiter for the delimiter
+    /// itself; bracket expressions are copied verbatim.
+    fn parse_regex(&mut self, delimiter: u8) -> Result<Option<Rc<Regex>>, String> {
+        let unterminated = |parser: &Self| parser.error(gettext("unterminated regular expression"));
+        let mut pattern = Vec::new();
+
+        loop {
+            match self.next() {
+                None | Some(b'\n') => return Err(unterminated(self)),
+                Some(c) if c == delimiter => break,
+                Some(b'\\') => match self.next() {
+                    None => return Err(unterminated(self)),
+                    Some(b'n') => pattern.push(b'\n'),
+                    Some(c) if c == delimiter => pattern.push(c),
+                    Some(c) => pattern.extend_from_slice(&[b'\\', c]),
+                },
+                Some(b'[') => {
+                    pattern.push(b'[');
+                    self.copy_bracket_expression(&mut pattern)
+                        .ok_or_else(|| unterminated(self))?;
+                }
+                Some(c) => pattern.push(c),
+            }
+        }
+
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        let flags = RegexFlags {
+            extended: self.extended,
+            ..Default::default()
+        };
+        let regex = Regex::new(&pattern, flags).map_err(|err| self.error(err.to_string()))?;
+        Ok(Some(Rc::new(regex)))
+    }
+
+    /// Copies a bracket expression, whose `[` has already been copied, up to
+    /// and including its closing `]`.  It may not span lines, but `\n` in it
+    /// stands for a newline.
+    fn copy_bracket_expression(&mut self, pattern: &mut Vec<u8>) -> Option<()> {
+        let end = bracket_expression_end(self.script, self.pos - 1)?;
+        let expression = &self.script[self.pos..end];
+        if expression.contains(&b'\n') {
+            return None;
+        }
+        let mut i = 0;
+        while i < expression.len() {
+            if expression[i..].starts_with(b"\\n") {
+                pattern.push(b'\n');
+                i += 2;
+            } else {
+                pattern.push(expression[i]);
+                i += 1;
+            }
+        }
+        self.pos = end;
+        Some(())
+    }
+
+    fn parse_replacement(&mut self, delimiter: u8) -> Result<Vec<ReplacementPart>, String> {
+        let mut parts = Vec::new();
+        let mut literal = Vec::new();
+
+        loop {
+            let c = match self.next() {
+                None | Some(b'\n') => {
+                    return Err(self.error(gettext("unterminated `s' command")));
+                }
+                Some(c) => c,
+            };
+            let group = match c {
+                c if c == delimiter => break,
+                b'&' => Some(0),
+                b'\\' => match self.next() {
+                    None => return Err(self.error(gettext("unterminated `s' command"))),
+                    Some(d @ b'0'..=b'9') => Some((d - b'0') as usize),
+                    Some(b'n') => {
+                        literal.push(b'\n');
+                        None
+                    }
+                    Some(b't') => {
+                        literal.push(b'\t');
+                        None
+                    }
+                    Some(c) => {
+                        literal.push(c);
+                        None
+                    }
+                },
+                c => {
+                    literal.push(c);
+                    None
+                }
+            };
+            if let Some(group) = group {
+                if !literal.is_empty() {
+                    parts.push(ReplacementPart::Literal(mem::take(&mut literal)));
+                }
+                parts.push(ReplacementPart::Group(group));
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(ReplacementPart::Literal(literal));
+        }
+        Ok(parts)
+    }
+
+    fn parse_substitution(&mut self) -> Result<Substitution, String> {
+        let delimiter = match self.next() {
+            Some(b'\n' | b'\\') | None => {
+                return Err(self.error(gettext("unterminated `s' command")));
+            }
+            Some(c) => c,
+        };
+        let regex = self.parse_regex(delimiter)?;
+        let replacement = self.parse_replacement(delimiter)?;
+
+        if let Some(regex) = &regex {
+            for part in &replacement {
+                if let ReplacementPart::Group(n) = part {
+                    if *n > regex.subexpressions() {
+                        return Err(
+                            self.error(gettext!("invalid reference \\{} on `s' command's RHS", n))
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut substitution = Substitution {
+            regex,
+            replacement,
+            occurrence: 1,
+            global: false,
+            print: false,
+            write: None,
+        };
+        let mut occurrence = None;
+        loop {
+            match self.peek() {
+                Some(b'g') => substitution.global = true,
+                Some(b'p') => substitution.print = true,
+                Some(b'0'..=b'9') => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(b'0'..=b'9')) {
+                        self.pos += 1;
+                    }
+                    let number = std::str::from_utf8(&self.script[start..self.pos])
+                        .unwrap()
+                        .parse::<usize>()
+                        .map_err(|err| self.error(err.to_string()))?;
+                    if number == 0 {
+                        return Err(
+                            self.error(gettext("number option to `s' command may not be zero"))
+                        );
+                    }
+                    if occurrence.replace(number).is_some() {
+                        return Err(self.error(gettext("multiple number options to `s' command")));
+                    }
+                    continue;
+                }
+                Some(b'w') => {
+                    self.pos += 1;
+                    let path = self.parse_filename()?;
+                    substitution.write = Some(self.write_files.open(path)?);
+                    break;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        substitution.occurrence = occurrence.unwrap_or(1);
+
+        Ok(substitution)
+    }
+
+    fn parse_transliteration(&mut self) -> Result<Vec<(char, char)>, String> {
+        let delimiter = match self.next() {
+            Some(b'\n' | b'\\') | None => {
+                return Err(self.error(gettext("unterminated `y' command")));
+            }
+            Some(c) => c,
+        };
+
+        let mut strings = [Vec::new(), Vec::new()];
+        for string in strings.iter_mut() {
+            loop {
+                match self.next() {
+                    None | Some(b'\n') => {
+                        return Err(self.error(gettext("unterminated `y' command")));
+                    }
+                    Some(c) if c == delimiter => break,
+                    Some(b'\\') => match self.next() {
+                        Some(b'n') => string.push(b'\n'),
+                        Some(b'\\') => string.push(b'\\'),
+                        Some(c) if c == delimiter => string.push(c),
+                        _ => return Err(self.error(gettext("unknown escape in `y' command"))),
+                    },
+                    Some(c) => string.push(c),
+                }
+            }
+        }
+
+        let from: Vec<char> = String::from_utf8_lossy(&strings[0]).chars().collect();
+        let to: Vec<char> = String::from_utf8_lossy(&strings[1]).chars().collect();
+        if from.len() != to.len() {
+            return Err(self.error(gettext("strings for `y' command are different lengths")));
+        }
+        Ok(from.into_iter().zip(to).collect())
+    }
+
+    /// Reads the text argument of `a`, `i` and `c`.
+    ///
+    /// Both the POSIX form, where the text starts on the line after `a\`,
+    /// and the one-line form `a text` are accepted.  Lines of the text are
+    /// joined by escaped newlines; other backslashes are removed.
+    fn parse_text(&mut self) -> Result<Vec<u8>, String> {
+        self.skip_blanks();
+        if self.peek() == Some(b'\\') {
+            self.pos += 1;
+            if self.peek() == Some(b'\n') {
+                self.next();
+            }
+        } else if matches!(self.peek(), None | Some(b'\n')) {
+            return Err(self.error(gettext("expected \\ after `a', `c' or `i'")));
+        }
+
+        let mut text = Vec::new();
+        while let Some(c) = self.next() {
+            match c {
+                b'\\' => match self.next() {
+                    Some(c) => text.push(c),
+                    None => break,
+                },
+                b'\n' => break,
+                c => text.push(c),
+            }
+        }
+        Ok(text)
+    }
+
+    /// Reads a label, which ends at a newline or a semicolon.
+    fn parse_label(&mut self) -> Vec<u8> {
+        self.skip_blanks();
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some(b'\n' | b';')) {
+            self.pos += 1;
+        }
+        let mut label = self.script[start..self.pos].to_vec();
+        while matches!(label.last(), Some(b' ' | b'\t')) {
+            label.pop();
+        }
+        label
+    }
+
+    /// Reads the file name of `r` and `w`, which extends to the end of the line.
+    fn parse_filename(&mut self) -> Result<PathBuf, String> {
+        self.skip_blanks();
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some(b'\n')) {
+            self.pos += 1;
+        }
+        let name = &self.script[start..self.pos];
+        if name.is_empty() {
+            return Err(self.error(gettext("missing filename in r/w commands")));
+        }
+        Ok(PathBuf::from(String::from_utf8_lossy(name).into_owned()))
+    }
+}
+
+/// The input files, read line by line with one line of lookahead, so that
+/// the `$` address can tell whether the current line is the last one.
+struct Input {
+    files: VecDeque<String>,
+    reader: Option<(String, Box<dyn BufRead>)>,
+    next_line: Option<Vec<u8>>,
+    line_number: usize,
+    any_errors: bool,
+}
+
+impl Input {
+    fn new(files: Vec<String>) -> Self {
+        let mut input = Input {
+            files: files.into(),
+            reader: None,
+            next_line: None,
+            line_number: 0,
+            any_errors: false,
+        };
+        input.next_line = input.fetch();
+        input
+    }
+
+    fn fetch(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let Some((name, reader)) = &mut self.reader else {
+                let name = self.files.pop_front()?;
+                if name == "-" {
+                    self.reader = Some((name, Box::new(BufReader::new(io::stdin()))));
+                } else {
+                    match File::open(&name) {
+                        Ok(file) => self.reader = Some((name, Box::new(BufReader::new(file)))),
+                        Err(err) => {
+                            eprintln!("sed: {}", gettext!("can't read {}: {}", name, err));
+                            self.any_errors = true;
+                        }
+                    }
+                }
+                continue;
+            };
+
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => self.reader = None,
+                Ok(_) => {
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                    }
+                    return Some(line);
+                }
+                Err(err) => {
+                    eprintln!("sed: {}", gettext!("read error on {}: {}", name, err));
+                    self.any_errors = true;
+                    self.reader = None;
+                }
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Option<Vec<u8>> {
+        let line = self.next_line.take()?;
+        self.line_number += 1;
+        self.next_line = self.fetch();
+        Some(line)
+    }
+
+    fn is_last_line(&self) -> bool {
+        self.next_line.is_none()
+    }
+}
+
+/// Output queued by `a` and `r`, written at the end of the cycle or when
+/// `n` or `N` read the next line.
+enum Appended {
+    Text(Vec<u8>),
+    File(PathBuf),
+}
+
+/// How a cycle ended.
+enum Flow {
+    /// The end of the script was reached.
+    EndOfScript,
+    /// `d`, or `D` without a newline: start the next cycle without
+    /// writing the pattern space.
+    Delete,
+    /// `D`: start the next cycle without reading a new line.
+    Restart,
+    /// `q`, or `n`/`N` at the end of the input.
+    Quit,
+}
+
+struct Editor {
+    quiet: bool,
+    input: Input,
+    out: BufWriter<io::Stdout>,
+    write_files: Vec<Output>,
+    pattern_space: Vec<u8>,
+    hold_space: Vec<u8>,
+    append_queue: Vec<Appended>,
+    /// For each command, whether its address range is active.
+    in_range: Vec<bool>,
+    last_regex: Option<Rc<Regex>>,
+    /// Whether a substitution was made since the last input line or `t`.
+    substituted: bool,
+}
+
+impl Editor {
+    fn run(&mut self, commands: &[Command]) -> Result<(), String> {
+        let mut restart = false;
+        loop {
+            if !restart {
+                match self.input.read_line() {
+                    Some(line) => self.pattern_space = line,
+                    None => break,
+                }
+                self.substituted = false;
+            }
+            restart = false;
+
+            let flow = self.execute(commands)?;
+            match flow {
+                Flow::EndOfScript | Flow::Quit => {
+                    if !self.quiet {
+                        self.write_pattern_space()?;
+                    }
+                }
+                Flow::Delete => {}
+                Flow::Restart => restart = true,
+            }
+            self.flush_append_queue()?;
+            if let Flow::Quit = flow {
+                break;
+            }
+        }
+
+        self.out.flush().map_err(|err| err.to_string())?;
+        for output in &mut self.write_files {
+            if let Output::File(file) = output {
+                file.flush().map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, commands: &[Command]) -> Result<Flow, String> {
+        let mut pc = 0;
+        while let Some(command) = commands.get(pc) {
+            pc += 1;
+            if !self.selects(pc - 1, command)? {
+                if let Function::BlockStart(end) = command.function {
+                    pc = end;
+                }
+                continue;
+            }
+
+            match &command.function {
+                Function::BlockStart(_) | Function::BlockEnd | Function::Label => {}
+                Function::Append(text) => self.append_queue.push(Appended::Text(text.clone())),
+                Function::Insert(text) => self.write_line(text)?,
+                Function::Change(text) => {
+                    // In a range, the text replaces the whole range.
+                    if command.addr2.is_none() || command.negated || !self.in_range[pc - 1] {
+                        self.write_line(text)?;
+                    }
+                    return Ok(Flow::Delete);
+                }
+                Function::Branch(target) => pc = *target,
+                Function::Test(target) => {
+                    if self.substituted {
+                        self.substituted = false;
+                        pc = *target;
+                    }
+                }
+                Function::Delete => return Ok(Flow::Delete),
+                Function::DeleteFirstLine => {
+                    match self.pattern_space.iter().position(|&b| b == b'\n') {
+                        Some(pos) => {
+                            self.pattern_space.drain(..=pos);
+                            return Ok(Flow::Restart);
+                        }
+                        None => return Ok(Flow::Delete),
+                    }
+                }
+                Function::Get => self.pattern_space = self.hold_space.clone(),
+                Function::GetAppend => {
+                    self.pattern_space.push(b'\n');
+                    self.pattern_space.extend_from_slice(&self.hold_space);
+                }
+                Function::Hold => self.hold_space = self.pattern_space.clone(),
+                Function::HoldAppend => {
+                    self.hold_space.push(b'\n');
+                    self.hold_space.extend_from_slice(&self.pattern_space);
+                }
+                Function::Exchange => mem::swap(&mut self.pattern_space, &mut self.hold_space),
+                Function::List => self.list()?,
+                Function::Next => {
+                    if self.input.is_last_line() {
+                        return Ok(Flow::Quit);
+                    }
+                    if !self.quiet {
+                        self.write_pattern_space()?;
+                    }
+                    self.flush_append_queue()?;
+                    self.pattern_space = self.input.read_line().unwrap();
+                }
+                Function::NextAppend => {
+                    if self.input.is_last_line() {
+                        return Ok(Flow::Quit);
+                    }
+                    self.flush_append_queue()?;
+                    let line = self.input.read_line().unwrap();
+                    self.pattern_space.push(b'\n');
+                    self.pattern_space.extend_from_slice(&line);
+                }
+                Function::Print => self.write_pattern_space()?,
+                Function::PrintFirstLine => {
+                    let end = self
+                        .pattern_space
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .unwrap_or(self.pattern_space.len());
+                    let line = self.pattern_space[..end].to_vec();
+                    self.write_line(&line)?;
+                }
+                Function::Quit => return Ok(Flow::Quit),
+                Function::LineNumber => {
+                    let number = self.input.line_number.to_string();
+                    self.write_line(number.as_bytes())?;
+                }
+                Function::ReadFile(path) => self.append_queue.push(Appended::File(path.clone())),
+                Function::WriteFile(index) => self.write_to_file(*index)?,
+                Function::Substitute(substitution) => self.substitute(substitution)?,
+                Function::Transliterate(map) => self.transliterate(map),
+            }
+        }
+        Ok(Flow::EndOfScript)
+    }
+
+    /// Returns whether the addresses of `command`, the `index`-th command,
+    /// select the pattern space, updating the state of its range.
+    fn selects(&mut self, index: usize, command: &Command) -> Result<bool, String> {
+        let selected = match (&command.addr1, &command.addr2) {
+            (None, _) => true,
+            (Some(addr1), None) => self.matches(addr1)?,
+            (Some(addr1), Some(addr2)) => {
+                if self.in_range[index] {
+                    let end = match addr2 {
+                        Address::Line(n) => self.input.line_number >= *n,
+                        addr2 => self.matches(addr2)?,
+                    };
+                    if end {
+                        self.in_range[index] = false;
+                    }
+                    true
+                } else if self.matches(addr1)? {
+                    // A line number at or before the first line ends the
+                    // range right away.
+                    let single_line =
+                        matches!(addr2, Address::Line(n) if *n <= self.input.line_number);
+                    self.in_range[index] = !single_line;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        Ok(selected != command.negated)
+    }
+
+    fn matches(&mut self, address: &Address) -> Result<bool, String> {
+        Ok(match address {
+            Address::Line(n) => self.input.line_number == *n,
+            Address::Last => self.input.is_last_line(),
+            Address::Regex(regex) => {
+                let regex = self.resolve_regex(regex)?;
+                regex.is_match(&self.pattern_space)
+            }
+        })
+    }
+
+    /// Returns `regex`, or the last regex used if it is the empty regex.
+    fn resolve_regex(&mut self, regex: &Option<Rc<Regex>>) -> Result<Rc<Regex>, String> {
+        match regex {
+            Some(regex) => {
+                self.last_regex = Some(regex.clone());
+                Ok(regex.clone())
+            }
+            None => self
+                .last_regex
+                .clone()
+                .ok_or_else(|| gettext("no previous regular expression")),
+        }
+    }
+
+    fn substitute(&mut self, substitution: &Substitution) -> Result<(), String> {
+        let regex = self.resolve_regex(&substitution.regex)?;
+        let bytes = &self.pattern_space;
+
+        let mut result = Vec::with_capacity(bytes.len());
+        let mut copied = 0;
+        let mut start = 0;
+        let mut count = 0;
+        let mut previous_end = None;
+        let mut replaced = false;
+
+        while start <= bytes.len() {
+            let Some(captures) = regex.captures_at(bytes, start) else {
+                break;
+            };
+            let whole = captures[0].clone().unwrap();
+            let (match_start, match_end) = (whole.start, whole.end);
+
+            // An empty match right after the previous match is not a match
+            // of its own, e.g. `s/b*/x/g` turns "abc" into "xaxcx".
+            if match_start == match_end && previous_end == Some(match_start) {
+                if match_start >= bytes.len() {
+                    break;
+                }
+                start = match_start + 1;
+                continue;
+            }
+
+            count += 1;
+            if count >= substitution.occurrence {
+                result.extend_from_slice(&bytes[copied..match_start]);
+                for part in &substitution.replacement {
+                    match part {
+                        ReplacementPart::Literal(literal) => result.extend_from_slice(literal),
+                        ReplacementPart::Group(n) => {
+                            if let Some(Some(group)) = captures.get(*n) {
+                                result.extend_from_slice(&bytes[group.clone()]);
+                            }
+                        }
+                    }
+                }
+                copied = match_end;
+                replaced = true;
+                if !substitution.global {
+                    break;
+                }
+            }
+
+            previous_end = Some(match_end);
+            if match_start == match_end {
+                if match_end >= bytes.len() {
+                    break;
+                }
+                start = match_end + 1;
+            } else {
+                start = match_end;
+            }
+        }
+
+        if !replaced {
+            return Ok(());
+        }
+        result.extend_from_slice(&bytes[copied..]);
+        self.pattern_space = result;
+        self.substituted = true;
+
+        if substitution.print {
+            self.write_pattern_space()?;
+        }
+        if let Some(index) = substitution.write {
+            self.write_to_file(index)?;
+        }
+        Ok(())
+    }
+
+    fn transliterate(&mut self, map: &[(char, char)]) {
+        let translate = |c: char| {
+            map.iter()
+                .find(|(from, _)| *from == c)
+                .map_or(c, |(_, to)| *to)
+        };
+        match std::str::from_utf8(&self.pattern_space) {
+            Ok(text) => self.pattern_space = text.chars().map(translate).collect::<String>().into(),
+            Err(_) => {
+                // Not valid UTF-8: only single-byte characters can be mapped.
+                for b in self.pattern_space.iter_mut() {
+                    if b.is_ascii() {
+                        let c = translate(char::from(*b));
+                        if c.is_ascii() {
+                            *b = c as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the pattern space in the unambiguous form of the `l` command.
+    fn list(&mut self) -> Result<(), String> {
+        const LINE_LENGTH: usize = 70;
+
+        let mut output = Vec::new();
+        let mut column = 0;
+        for &b in &self.pattern_space {
+            let escaped = match b {
+                b'\\' => "\\\\".to_string(),
+                0x07 => "\\a".to_string(),
+                0x08 => "\\b".to_string(),
+                0x0c => "\\f".to_string(),
+                b'\n' => "\\n".to_string(),
+                b'\r' => "\\r".to_string(),
+                b'\t' => "\\t".to_string(),
+                0x0b => "\\v".to_string(),
+                b' '..=b'~' => char::from(b).to_string(),
+                b => format!("\\{:03o}", b),
+            };
+            if column + escaped.len() > LINE_LENGTH - 1 {
+                output.extend_from_slice(b"\\\n");
+                column = 0;
+            }
+            column += escaped.len();
+            output.extend_from_slice(escaped.as_bytes());
+        }
+        output.push(b'$');
+        self.write_line(&output)
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> Result<(), String> {
+        self.out
+            .write_all(line)
+            .and_then(|_| self.out.write_all(b"\n"))
+            .map_err(|err| err.to_string())
+    }
+
+    fn write_pattern_space(&mut self) -> Result<(), String> {
+        let pattern_space = mem::take(&mut self.pattern_space);
+        let result = self.write_line(&pattern_space);
+        self.pattern_space = pattern_space;
+        result
+    }
+
+    fn write_to_file(&mut self, index: usize) -> Result<(), String> {
+        let result = match &mut self.write_files[index] {
+            Output::Stdout => self
+                .out
+                .write_all(&self.pattern_space)
+                .and_then(|_| self.out.write_all(b"\n")),
+            Output::Stderr => {
+                let mut stderr = io::stderr();
+                stderr
+                    .write_all(&self.pattern_space)
+                    .and_then(|_| stderr.write_all(b"\n"))
+            }
+            Output::File(file) => file
+                .write_all(&self.pattern_space)
+                .and_then(|_| file.write_all(b"\n")),
+        };
+        result.map_err(|err| err.to_string())
+    }
+
+    fn flush_append_queue(&mut self) -> Result<(), String> {
+        for appended in mem::take(&mut self.append_queue) {
+            match appended {
+                Appended::Text(text) => self.write_line(&text)?,
+                Appended::File(path) => {
+                    // Files that cannot be read are silently ignored.
+                    let mut contents = Vec::new();
+                    if File::open(path)
+                        .and_then(|mut file| file.read_to_end(&mut contents))
+                        .is_ok()
+                    {
+                        self.out
+                            .write_all(&contents)
+                            .map_err(|err| err.to_string())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assembles the script from the `-e` and `-f` options, in the order they
+/// were given, or else from the first operand.
+fn read_script(args: &mut Args, matches: &ArgMatches) -> Result<Vec<u8>, String> {
+    let mut pieces: Vec<(usize, Vec<u8>)> = Vec::new();
+
+    if let Some(indices) = matches.indices_of("expression") {
+        for (index, expression) in indices.zip(&args.expression) {
+            pieces.push((index, expression.as_bytes().to_vec()));
+        }
+    }
+    if let Some(indices) = matches.indices_of("script_file") {
+        for (index, path) in indices.zip(&args.script_file) {
+            let mut script = if path.as_os_str() == "-" {
+                let mut script = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut script)
+                    .map_err(|err| err.to_string())?;
+                script
+            } else {
+                fs::read(path)
+                    .map_err(|err| gettext!("couldn't open file {}: {}", path.display(), err))?
+            };
+            if script.last() == Some(&b'\n') {
+                script.pop();
+            }
+            pieces.push((index, script));
+        }
+    }
+
+    if pieces.is_empty() {
+        if args.operands.is_empty() {
+            return Err(gettext("no script specified"));
+        }
+        return Ok(args.operands.remove(0).into_bytes());
+    }
+
+    pieces.sort_by_key(|(index, _)| *index);
+    let pieces: Vec<Vec<u8>> = pieces.into_iter().map(|(_, piece)| piece).collect();
+    Ok(pieces.join(&b'\n'))
+}
+
+fn sed(mut args: Args, matches: &ArgMatches) -> Result<i32, String> {
+    let script = read_script(&mut args, matches)?;
+
+    // A script starting with "#n" on a line of its own acts like -n.
+    let quiet = args.quiet || script == b"#n" || script.starts_with(b"#n\n");
+
+    let (commands, write_files) = ScriptParser::new(&script, args.extended_regexp).parse()?;
+
+    let mut files = args.operands;
+    if files.is_empty() {
+        files.push(String::from("-"));
+    }
+
+    let mut editor = Editor {
+        quiet,
+        input: Input::new(files),
+        out: BufWriter::new(io::stdout()),
+        write_files: write_files.outputs,
+        pattern_space: Vec::new(),
+        hold_space: Vec::new(),
+        append_queue: Vec::new(),
+        in_range: vec![false; commands.len()],
+        last_regex: None,
+        substituted: false,
+    };
+    editor.run(&commands)?;
+
+    Ok(if editor.input.any_errors { 2 } else { 0 })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setlocale(LocaleCategory::LcAll, "");
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+
+    let exit_code = sed(args, &matches).unwrap_or_else(|err| {
+        eprintln!("sed: {}", err);
+        1
+    });
+
+    std::process::exit(exit_code)
+}
@@ -9,11 +9,14 @@
 
 use std::cmp::Ordering;
 
-use std::io::{ErrorKind, Read};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
 use std::{
-    fs::File,
+    env,
+    fs::{self, File, OpenOptions},
     io::{self, BufRead, BufWriter, Error, Write},
-    path::PathBuf,
+    mem,
+    path::{Path, PathBuf},
 };
 
 use clap::Parser;
@@ -76,6 +79,14 @@ struct Args {
     #[arg(short = 'k')]
     key_definition: Vec<String>,
 
+    /// Use at most SIZE bytes of memory before writing sorted runs to temporary files
+    #[arg(short = 'S', value_name = "SIZE", value_parser = parse_buffer_size)]
+    buffer_size: Option<usize>,
+
+    /// Create temporary files in DIR instead of $TMPDIR or /tmp
+    #[arg(short = 'T', value_name = "DIR")]
+    temporary_directory: Option<PathBuf>,
+
     /// Input files
     filenames: Vec<PathBuf>,
 }
@@ -763,162 +774,412 @@ fn create_ranges(
     Ok(ranges)
 }
 
-/// Sorts strings based on specified sorting criteria and writes the result to the output.
+/// Memory budget for sorting in memory when `-S` is not given.
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Maximum number of runs merged at once.  Larger numbers of runs are merged
+/// in several passes so the number of open temporary files stays bounded.
+const MERGE_FAN_IN: usize = 16;
+
+/// Parses the argument of `-S`.
 ///
-/// This function reads lines from the provided input reader and sorts them based on the
-/// specified sorting criteria in the `Args` struct. It supports sorting by key ranges,
-/// dictionary ordering, case folding, numeric sorting, and other options. The sorted
-/// lines are then written to the output, which can be a file or the standard output.
+/// The size is a decimal number, optionally followed by a unit: `b` for bytes,
+/// `K`, `M`, `G` or `T` for powers of 1024, or `%` for a percentage of the
+/// physical memory.  A number without a unit is taken to be in kibibytes.
 ///
 /// # Arguments
 ///
-/// * `args` - A reference to an `Args` struct containing sorting and configuration options.
-/// * `reader` - A boxed reader implementing the `Read` trait, representing the input source.
+/// * `size` - The argument as given on the command line.
 ///
 /// # Returns
 ///
-/// A `Result` indicating success or failure:
-/// * `Ok(())` if the sorting and writing process completes successfully.
-/// * `Err(Box<dyn Error>)` if an error occurs during sorting, reading, or writing.
-///
-fn sort_lines(args: &Args, lines: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut result_lines = lines.clone();
-    let mut duplicates = vec![];
+/// The memory budget in bytes, or an error message if `size` is malformed or zero.
+///
+fn parse_buffer_size(size: &str) -> Result<usize, String> {
+    let invalid = || format!("invalid buffer size: '{}'", size);
+
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    let bytes = match unit {
+        "b" => Some(number),
+        "" | "k" | "K" => number.checked_mul(1 << 10),
+        "m" | "M" => number.checked_mul(1 << 20),
+        "g" | "G" => number.checked_mul(1 << 30),
+        "t" | "T" => number.checked_mul(1 << 40),
+        "%" if number <= 100 => physical_memory().map(|memory| memory / 100 * number),
+        _ => None,
+    };
 
-    if !args.key_definition.is_empty() {
-        let key_range = &args.key_definition[0];
+    bytes
+        .and_then(|bytes| usize::try_from(bytes).ok())
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(invalid)
+}
 
-        if key_range.is_empty() {
-            return Err(Box::new(Error::new(
-                ErrorKind::Other,
-                "key must be non-empty",
-            )));
+/// Returns the amount of physical memory in bytes, if the system reports it.
+fn physical_memory() -> Option<u64> {
+    let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if pages <= 0 || page_size <= 0 {
+        None
+    } else {
+        Some(pages as u64 * page_size as u64)
+    }
+}
+
+/// Returns the directory for temporary files: the argument of `-T`, else
+/// `$TMPDIR`, else `/tmp`.
+fn temporary_directory(args: &Args) -> PathBuf {
+    args.temporary_directory
+        .clone()
+        .or_else(|| {
+            env::var_os("TMPDIR")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+}
+
+/// Creates a new temporary file in `dir`, opened for reading and writing.
+///
+/// The file is unlinked right after it is created, so it goes away as soon
+/// as the returned handle is closed, even if `sort` is interrupted.
+fn create_temporary_file(dir: &Path) -> io::Result<File> {
+    let pid = std::process::id();
+    let mut n = 0u64;
+    loop {
+        let path = dir.join(format!("sort{}.{}", pid, n));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(err) => {
+                let message = format!("cannot create temporary file in {}: {}", dir.display(), err);
+                return Err(Error::new(err.kind(), message));
+            }
         }
+    }
+}
 
-        let ranges = create_ranges(key_range, args)?;
-        let ranges_2 = match args.key_definition.get(1) {
-            Some(key_range_2) => Some(create_ranges(key_range_2, args)?),
-            None => None,
-        };
+/// A sort key: where the key starts and, optionally, where it ends.
+type Key = (RangeField, Option<RangeField>);
 
-        // Sort strings by keys
-        result_lines.sort_by(|a, b| {
-            let mut ordering = compare_key(a, b, &ranges, args.field_separator);
-            if let Ordering::Equal = ordering {
-                if let Some(ranges_2) = &ranges_2 {
-                    let ordering_2 = compare_key(a, b, ranges_2, args.field_separator);
-                    if let Ordering::Equal = ordering_2 {
-                        duplicates.push(a.to_string());
-                    }
-                    ordering = ordering_2
-                }
+/// A sequence of lines read from an input file or a sorted run.
+type Lines = Box<dyn Iterator<Item = io::Result<String>>>;
+
+/// Returns the lines read from `file`.
+fn file_lines(file: impl Read + 'static) -> Lines {
+    Box::new(io::BufReader::new(file).lines())
+}
+
+/// The ordering requested on the command line.
+///
+/// The same comparator is used to sort runs in memory, to merge them and to
+/// check the order of the input, so all of them agree on which lines are equal.
+struct Comparator {
+    /// The `-k` keys, in order of precedence.  When empty, whole lines are compared.
+    keys: Vec<Key>,
+    field_separator: Option<char>,
+    dictionary_order: bool,
+    fold_case: bool,
+    ignore_nonprintable: bool,
+    numeric_sort: bool,
+    reverse: bool,
+}
+
+impl Comparator {
+    fn new(args: &Args) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut keys = Vec::with_capacity(args.key_definition.len());
+        for key_range in &args.key_definition {
+            if key_range.is_empty() {
+                return Err(Box::new(Error::other("key must be non-empty")));
             }
-            ordering
-        });
-        if args.unique {
-            result_lines.retain(|line| !duplicates.contains(line));
+            keys.push(create_ranges(key_range, args)?);
         }
-    } else {
-        result_lines.sort_by(|a, b| {
-            let ord = compare_lines(
-                a,
-                b,
-                args.dictionary_order,
-                args.fold_case,
-                args.ignore_nonprintable,
-                args.numeric_sort,
+
+        Ok(Comparator {
+            keys,
+            field_separator: args.field_separator,
+            dictionary_order: args.dictionary_order,
+            fold_case: args.fold_case,
+            ignore_nonprintable: args.ignore_nonprintable,
+            numeric_sort: args.numeric_sort,
+            reverse: args.reverse,
+        })
+    }
+
+    /// Compares two lines.  Lines comparing equal are duplicates for `-u`.
+    fn compare(&self, line1: &str, line2: &str) -> Ordering {
+        if self.keys.is_empty() {
+            let ordering = compare_lines(
+                line1,
+                line2,
+                self.dictionary_order,
+                self.fold_case,
+                self.ignore_nonprintable,
+                self.numeric_sort,
             );
-            if let Ordering::Equal = ord {
-                duplicates.push(a.to_string());
-            }
-            ord
-        });
+            return if self.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+        }
 
-        if args.unique {
-            result_lines.retain(|line| !duplicates.contains(line));
+        // `-r` has already been folded into every key by `generate_range`.
+        for key in &self.keys {
+            let ordering = compare_key(line1, line2, key, self.field_separator);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
         }
+        Ordering::Equal
     }
 
-    if args.reverse {
-        result_lines.reverse();
+    /// Sorts `lines` in place, keeping equal lines in input order.  With
+    /// `unique`, only the first of each set of equal lines is kept.
+    fn sort(&self, lines: &mut Vec<String>, unique: bool) {
+        lines.sort_by(|a, b| self.compare(a, b));
+        if unique {
+            lines.dedup_by(|line, kept| self.compare(kept, line) == Ordering::Equal);
+        }
     }
+}
+
+/// Checks whether the input is already sorted, for `-c` and `-C`.
+///
+/// # Arguments
+///
+/// * `args` - A reference to an `Args` struct containing sorting and configuration options.
+/// * `cmp` - The comparator built from `args`.
+/// * `lines` - The lines of the input.
+///
+/// # Returns
+///
+/// `Ok(())` if the input is in order, or an error describing the first line
+/// out of order (or, with `-c -u`, the first duplicate key).
+///
+fn check_order(
+    args: &Args,
+    cmp: &Comparator,
+    lines: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sorted_lines = lines.clone();
+    cmp.sort(&mut sorted_lines, false);
 
     if args.check_order_without_war_mess {
-        if find_first_difference(&lines, &result_lines).is_some() {
-            return Err(Box::new(Error::new(
-                ErrorKind::Other,
+        if find_first_difference(&lines, &sorted_lines).is_some() {
+            return Err(Box::new(Error::other(
                 "The order of the lines is not correct",
             )));
-        } else {
-            return Ok(());
-        }
-    } else if args.check_order {
-        if args.unique && !duplicates.is_empty() {
-            let message = format!("Duplicate key was found! `{}`", duplicates.first().unwrap());
-            return Err(Box::new(Error::new(ErrorKind::Other, message)));
-        }
-        if let Some((index, line)) = find_first_difference(&lines, &result_lines) {
-            let message = format!(
-                "The order of the lines is not correct on line {}:`{}`",
-                index + 1,
-                line
-            );
-            return Err(Box::new(Error::new(ErrorKind::Other, message)));
         }
         return Ok(());
-    } else if let Some(file_path) = &args.output_file {
-        // Open the file for writing
-        let file_out = File::create(file_path)?;
-        let mut writer = BufWriter::new(file_out);
+    }
 
-        // Write the sorted strings to a file
-        for line in result_lines {
-            writeln!(writer, "{}", line)?;
+    if args.unique {
+        let duplicate = sorted_lines
+            .windows(2)
+            .find(|pair| cmp.compare(&pair[0], &pair[1]) == Ordering::Equal);
+        if let Some(pair) = duplicate {
+            let message = format!("Duplicate key was found! `{}`", pair[0]);
+            return Err(Box::new(Error::other(message)));
         }
-    } else {
-        let result = result_lines.join("\n");
-        println!("{result}");
+    }
+    if let Some((index, line)) = find_first_difference(&lines, &sorted_lines) {
+        let message = format!(
+            "The order of the lines is not correct on line {}:`{}`",
+            index + 1,
+            line
+        );
+        return Err(Box::new(Error::other(message)));
     }
 
     Ok(())
 }
 
-/// Merges contents from multiple sorted files into a single output.
+/// Merges sorted sequences of lines and writes the result to `out`.
 ///
-/// This function takes a vector of mutable references to readers (`paths`) representing
-/// sorted input files and an optional output file path (`output_path`). It reads from each
-/// input file sequentially and writes the contents to the output file or the standard output.
+/// Of lines that compare equal, those from earlier inputs are written first,
+/// which keeps the merge stable; with `unique`, only the first of them is written.
 ///
 /// # Arguments
 ///
-/// * `paths` - A mutable reference to a vector of readers (`Vec<Box<dyn Read>>`) representing
-///             sorted input files.
-/// * `output_path` - An optional string (`Option<String>`) representing the output file path.
-///                   If `Some`, the merged contents are written to the specified file; if `None`,
-///                   the contents are written to the standard output.
+/// * `inputs` - The sorted sequences of lines, in input order.
+/// * `cmp` - The comparator the inputs are sorted by.
+/// * `unique` - Whether to suppress all but the first of each set of equal lines.
+/// * `out` - Where the merged lines are written.
 ///
 /// # Returns
 ///
-/// An `io::Result` indicating success or failure:
-/// * `Ok(())` if the merging process completes successfully.
-/// * `Err(io::Error)` if an error occurs during file I/O or copying.
+/// An `io::Result` indicating whether reading and writing succeeded.
 ///
-fn merge_files(paths: &mut Vec<Box<dyn Read>>, output_path: &Option<PathBuf>) -> io::Result<()> {
-    let mut output_file: Box<dyn Write> = match output_path {
-        Some(path) => Box::new(File::create(path)?),
-        None => Box::new(io::stdout()),
-    };
+fn merge(
+    mut inputs: Vec<Lines>,
+    cmp: &Comparator,
+    unique: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut heads = Vec::with_capacity(inputs.len());
+    for input in inputs.iter_mut() {
+        heads.push(input.next().transpose()?);
+    }
 
-    for path in paths {
-        let mut input_file = path;
+    let mut last_line: Option<String> = None;
+    loop {
+        // The number of inputs is small, so a linear scan for the smallest
+        // head is cheaper than maintaining a heap.
+        let mut next: Option<usize> = None;
+        for (i, head) in heads.iter().enumerate() {
+            let Some(line) = head else {
+                continue;
+            };
+            match next.and_then(|j| heads[j].as_ref()) {
+                Some(smallest) if cmp.compare(line, smallest) != Ordering::Less => {}
+                _ => next = Some(i),
+            }
+        }
+        let Some(i) = next else {
+            break;
+        };
+
+        let line = heads[i].take().unwrap();
+        heads[i] = inputs[i].next().transpose()?;
 
-        // Copy the contents of the input file to the output file or stdout
-        io::copy(&mut input_file, &mut output_file)?;
+        if unique {
+            if let Some(last_line) = &last_line {
+                if cmp.compare(last_line, &line) == Ordering::Equal {
+                    continue;
+                }
+            }
+        }
+        writeln!(out, "{}", line)?;
+        if unique {
+            last_line = Some(line);
+        }
     }
 
     Ok(())
 }
 
+/// Sorts its input with a bounded amount of memory.
+///
+/// Lines are collected until they exceed the memory budget; each such chunk
+/// is then sorted and written to a temporary file as a sorted run.  At the
+/// end, the runs are merged into the output.  Input that fits within the
+/// budget never touches the disk.
+struct ExternalSorter<'a> {
+    cmp: &'a Comparator,
+    unique: bool,
+    buffer_size: usize,
+    temporary_directory: PathBuf,
+
+    /// The lines not yet written to a run, and the memory they occupy.
+    chunk: Vec<String>,
+    chunk_size: usize,
+
+    /// The sorted runs, in input order, rewound for reading.
+    runs: Vec<File>,
+}
+
+impl<'a> ExternalSorter<'a> {
+    fn new(args: &Args, cmp: &'a Comparator) -> Self {
+        ExternalSorter {
+            cmp,
+            unique: args.unique,
+            buffer_size: args.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+            temporary_directory: temporary_directory(args),
+            chunk: Vec::new(),
+            chunk_size: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, line: String) -> io::Result<()> {
+        self.chunk_size += line.len() + mem::size_of::<String>();
+        self.chunk.push(line);
+        if self.chunk_size >= self.buffer_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the current chunk and writes it to a new run.
+    fn spill(&mut self) -> io::Result<()> {
+        self.cmp.sort(&mut self.chunk, self.unique);
+
+        let mut run = create_temporary_file(&self.temporary_directory)?;
+        let mut writer = BufWriter::new(&mut run);
+        for line in self.chunk.drain(..) {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        run.seek(SeekFrom::Start(0))?;
+
+        self.runs.push(run);
+        self.chunk_size = 0;
+        Ok(())
+    }
+
+    /// Writes all lines pushed so far, in sorted order, to `out`.
+    fn finish(mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.runs.is_empty() {
+            self.cmp.sort(&mut self.chunk, self.unique);
+            for line in &self.chunk {
+                writeln!(out, "{}", line)?;
+            }
+            return Ok(());
+        }
+        if !self.chunk.is_empty() {
+            self.spill()?;
+        }
+
+        // Merge the earliest runs first and put the result in front, so that
+        // equal lines keep their input order across passes.
+        let mut runs = mem::take(&mut self.runs);
+        while runs.len() > MERGE_FAN_IN {
+            let batch = runs.drain(..MERGE_FAN_IN).map(file_lines).collect();
+            let mut merged = create_temporary_file(&self.temporary_directory)?;
+            let mut writer = BufWriter::new(&mut merged);
+            merge(batch, self.cmp, self.unique, &mut writer)?;
+            writer.flush()?;
+            drop(writer);
+            merged.seek(SeekFrom::Start(0))?;
+            runs.insert(0, merged);
+        }
+
+        let runs = runs.into_iter().map(file_lines).collect();
+        merge(runs, self.cmp, self.unique, out)
+    }
+}
+
+/// Returns true if `reader_path` and `output_path` name the same file.
+fn is_same_file(reader_path: &Path, output_path: &Path) -> bool {
+    match (fs::metadata(reader_path), fs::metadata(output_path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Opens the output named by `-o`, or standard output.
+fn open_output(output_path: &Option<PathBuf>) -> io::Result<Box<dyn Write>> {
+    Ok(match output_path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
 /// Merges consecutive empty strings in the input vector with the nearest non-empty string.
 /// Spaces are added to empty strings to align them with the nearest non-empty string.
 ///
@@ -974,8 +1235,10 @@ fn merge_empty_lines(vec: Vec<&str>) -> Vec<String> {
 /// * `Err(Box<dyn Error>)` if an error occurs during sorting or merging.
 ///
 fn sort(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let cmp = Comparator::new(args)?;
+
     let mut readers: Vec<Box<dyn Read>> = if (args.filenames.len() == 1
-        && args.filenames[0] == PathBuf::from("-"))
+        && args.filenames[0] == Path::new("-"))
         || args.filenames.is_empty()
     {
         vec![Box::new(io::stdin().lock())]
@@ -987,17 +1250,49 @@ fn sort(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
         bufs
     };
 
+    if args.check_order || args.check_order_without_war_mess {
+        let mut lines = Vec::new();
+        for reader in readers {
+            for line in io::BufReader::new(reader).lines() {
+                lines.push(line?);
+            }
+        }
+        return check_order(args, &cmp, lines);
+    }
+
     if args.merge_only {
-        merge_files(&mut readers, &args.output_file)?;
+        // `sort -m -o file file` would truncate one of its inputs before
+        // reading it, so such inputs are copied aside first.
+        if let Some(output_path) = &args.output_file {
+            for (reader, path) in readers.iter_mut().zip(&args.filenames) {
+                if is_same_file(path, output_path) {
+                    let mut copy = create_temporary_file(&temporary_directory(args))?;
+                    io::copy(reader, &mut copy)?;
+                    copy.seek(SeekFrom::Start(0))?;
+                    *reader = Box::new(copy);
+                }
+            }
+        }
+
+        let inputs = readers.into_iter().map(file_lines).collect();
+        let mut out = open_output(&args.output_file)?;
+        merge(inputs, &cmp, args.unique, &mut out)?;
+        out.flush()?;
         return Ok(());
     }
-    let mut all_lines: Vec<String> = Vec::new();
+
+    let mut sorter = ExternalSorter::new(args, &cmp);
     for reader in readers {
-        let reader = io::BufReader::new(reader);
-        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
-        all_lines.extend(lines);
+        for line in io::BufReader::new(reader).lines() {
+            sorter.push(line?)?;
+        }
     }
-    sort_lines(args, all_lines)?;
+
+    // The output is only opened once all input has been read, so that
+    // `sort -o file file` works.
+    let mut out = open_output(&args.output_file)?;
+    sorter.finish(&mut out)?;
+    out.flush()?;
 
     Ok(())
 }
@@ -1031,4 +1326,18 @@ mod tests {
         let result = merge_empty_lines(vec!["line1", "line2", "", "", "", "lineN"]);
         assert_eq!(result, vec!["line1", "line2", "   lineN"]);
     }
+
+    #[test]
+    fn test_parse_buffer_size() {
+        assert_eq!(parse_buffer_size("100b"), Ok(100));
+        assert_eq!(parse_buffer_size("2"), Ok(2048));
+        assert_eq!(parse_buffer_size("2K"), Ok(2048));
+        assert_eq!(parse_buffer_size("3M"), Ok(3 << 20));
+        assert_eq!(parse_buffer_size("1G"), Ok(1 << 30));
+        assert!(parse_buffer_size("10%").unwrap() > 0);
+        assert!(parse_buffer_size("0").is_err());
+        assert!(parse_buffer_size("101%").is_err());
+        assert!(parse_buffer_size("1X").is_err());
+        assert!(parse_buffer_size("K").is_err());
+    }
 }
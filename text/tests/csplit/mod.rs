@@ -72,7 +72,7 @@ fn test_csplit_c_code_by_regex() {
             "-f",
             "code_c",
             "tests/assets/test_file_c",
-            r"%main\(%",
+            "%main(%",
             "/^}/+1",
             "{3}",
         ],
@@ -92,7 +92,7 @@ fn test_csplit_c_code_by_regex_negative_offset() {
             "-f",
             "code_c_neg",
             "tests/assets/test_file_c",
-            r"%main\(%",
+            "%main(%",
             "/^}/-2",
             "{3}",
         ],
@@ -113,7 +113,7 @@ fn test_csplit_c_code_by_regex_suppress() {
             "-f",
             "code_c_s",
             "tests/assets/test_file_c",
-            r"%main\(%",
+            "%main(%",
             "/^}/+1",
             "{3}",
         ],
@@ -135,7 +135,7 @@ fn test_csplit_c_code_by_regex_with_number() {
             "-n",
             "3",
             "tests/assets/test_file_c",
-            r"%main\(%",
+            "%main(%",
             "/^}/+1",
             "{3}",
         ],
@@ -12,7 +12,7 @@
 mod constants;
 
 use constants::{EXIT_STATUS_DIFFERENCE, EXIT_STATUS_NO_DIFFERENCE};
-use plib::{run_test, TestPlan};
+use plib::{run_test, run_test_u8, TestPlan, TestPlanU8};
 use std::{collections::HashMap, path::PathBuf, process::Stdio, sync::LazyLock};
 
 fn diff_test(args: &[&str], expected_output: &str, expected_diff_exit_status: u8) {
@@ -72,6 +72,46 @@ fn f1_txt_with_eol_spaces_path() -> String {
         .to_string()
 }
 
+fn ignore1_txt_path() -> String {
+    diff_base_path()
+        .join("ignore1.txt")
+        .to_str()
+        .expect("Could not unwrap ignore1_txt_path")
+        .to_string()
+}
+
+fn ignore2_txt_path() -> String {
+    diff_base_path()
+        .join("ignore2.txt")
+        .to_str()
+        .expect("Could not unwrap ignore2_txt_path")
+        .to_string()
+}
+
+fn tree1_dir_path() -> String {
+    diff_base_path()
+        .join("tree1")
+        .to_str()
+        .expect("Could not unwrap tree1_dir_path")
+        .to_string()
+}
+
+fn tree2_dir_path() -> String {
+    diff_base_path()
+        .join("tree2")
+        .to_str()
+        .expect("Could not unwrap tree2_dir_path")
+        .to_string()
+}
+
+fn exclude_txt_path() -> String {
+    diff_base_path()
+        .join("exclude.txt")
+        .to_str()
+        .expect("Could not unwrap exclude_txt_path")
+        .to_string()
+}
+
 struct DiffTestHelper {
     content: String,
     file1_path: String,
@@ -433,3 +473,204 @@ fn test_diff_unified_two_labels() {
         EXIT_STATUS_DIFFERENCE,
     );
 }
+
+#[test]
+fn test_diff_ignoring_case() {
+    diff_test(
+        &["-i", &ignore1_txt_path(), &ignore2_txt_path()],
+        "2,3c2,3\n< second  line\n< # comment one\n---\n> second line\n> # comment two\n\
+         4a5\n> \n5a7\n> fourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_ignoring_all_spaces() {
+    diff_test(
+        &["-w", &ignore1_txt_path(), &ignore2_txt_path()],
+        "1c1\n< First Line\n---\n> first line\n3c3\n< # comment one\n---\n> # comment two\n\
+         4a5\n> \n5a7\n> fourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_ignoring_blank_lines() {
+    diff_test(
+        &["-B", &ignore1_txt_path(), &ignore2_txt_path()],
+        "1,3c1,3\n< First Line\n< second  line\n< # comment one\n---\n\
+         > first line\n> second line\n> # comment two\n5a7\n> fourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_ignoring_matching_lines() {
+    diff_test(
+        &[
+            "-w",
+            "-i",
+            "-I",
+            "^#",
+            &ignore1_txt_path(),
+            &ignore2_txt_path(),
+        ],
+        "4a5\n> \n5a7\n> fourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_side_by_side() {
+    diff_test(
+        &["-y", "-W", "60", &ignore1_txt_path(), &ignore2_txt_path()],
+        "First Line\t\t     |\tfirst line\n\
+         second  line\t\t     |\tsecond line\n\
+         # comment one\t\t     |\t# comment two\n\
+         middle\t\t\t\tmiddle\n\
+         \t\t\t     >\n\
+         third line\t\t\tthird line\n\
+         \t\t\t     >\tfourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_side_by_side_narrow() {
+    diff_test(
+        &[
+            "-y",
+            "-W",
+            "30",
+            "-w",
+            "-i",
+            &ignore1_txt_path(),
+            &ignore2_txt_path(),
+        ],
+        "First Line\tfirst line\n\
+         second  line\tsecond line\n\
+         # comment one |\t# comment two\n\
+         middle\t\tmiddle\n\
+         \t      >\n\
+         third line\tthird line\n\
+         \t      >\tfourth line\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_binary_files() {
+    let file1 = format!("{}/data.bin", tree1_dir_path());
+    let file2 = format!("{}/data.bin", tree2_dir_path());
+
+    diff_test(
+        &[&file1, &file2],
+        &format!("Binary files {file1} and {file2} differ\n"),
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_brief_identical() {
+    let file1 = format!("{}/same.txt", tree1_dir_path());
+    let file2 = format!("{}/same.txt", tree2_dir_path());
+
+    diff_test(&["-q", &file1, &file2], "", EXIT_STATUS_NO_DIFFERENCE);
+}
+
+#[test]
+fn test_diff_new_file() {
+    let file1 = format!("{}/only1.txt", tree1_dir_path());
+    let file2 = format!("{}/only1.txt", tree2_dir_path());
+
+    diff_test(
+        &["-N", &file1, &file2],
+        "1d0\n< only\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_from_file() {
+    let from_file = format!("{}/same.txt", tree1_dir_path());
+    let file1 = format!("{}/same.txt", tree2_dir_path());
+    let file2 = format!("{}/changed.txt", tree2_dir_path());
+
+    diff_test(
+        &["--from-file", &from_file, &file1, &file2],
+        "1c1\n< same\n---\n> new\n",
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_directories_brief_excluding() {
+    let (dir1, dir2) = (tree1_dir_path(), tree2_dir_path());
+
+    diff_test(
+        &["-r", "-q", "-x", "*.log", &dir1, &dir2],
+        &format!(
+            "Files {dir1}/changed.txt and {dir2}/changed.txt differ\n\
+             Files {dir1}/data.bin and {dir2}/data.bin differ\n\
+             Only in {dir1}: only1.txt\n"
+        ),
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_directories_excluding_from_file() {
+    let (dir1, dir2) = (tree1_dir_path(), tree2_dir_path());
+
+    diff_test(
+        &["-q", "-X", &exclude_txt_path(), &dir1, &dir2],
+        &format!(
+            "Files {dir1}/changed.txt and {dir2}/changed.txt differ\n\
+             Files {dir1}/data.bin and {dir2}/data.bin differ\n\
+             Only in {dir1}: only1.txt\n"
+        ),
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_directories_new_file() {
+    let (dir1, dir2) = (tree1_dir_path(), tree2_dir_path());
+
+    diff_test(
+        &["-N", "-x", "*.log", &dir1, &dir2],
+        &format!(
+            "diff -N {dir1}/changed.txt {dir2}/changed.txt \n1c1\n< old\n---\n> new\n\
+             Binary files {dir1}/data.bin and {dir2}/data.bin differ\n\
+             diff -N {dir1}/only1.txt {dir2}/only1.txt \n1d0\n< only\n"
+        ),
+        EXIT_STATUS_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_same_file() {
+    diff_test(
+        &[&f1_txt_path(), &f1_txt_path()],
+        "",
+        EXIT_STATUS_NO_DIFFERENCE,
+    );
+}
+
+#[test]
+fn test_diff_latin1_text() {
+    let file1 = diff_base_path().join("latin1-1.txt");
+    let file2 = diff_base_path().join("latin1-2.txt");
+
+    run_test_u8(TestPlanU8 {
+        cmd: String::from("diff"),
+        args: vec![
+            file1.to_str().unwrap().to_string(),
+            file2.to_str().unwrap().to_string(),
+        ],
+        stdin_data: Vec::new(),
+        expected_out: b"2c2\n< d\xe9j\xe0 vu\n---\n> d\xe9j\xe0 lu\n".to_vec(),
+        expected_err: Vec::new(),
+        expected_exit_code: i32::from(EXIT_STATUS_DIFFERENCE),
+    });
+}
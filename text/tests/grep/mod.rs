@@ -130,8 +130,8 @@ fn test_basic_regexp_03() {
         &[BRE, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -230,8 +230,8 @@ fn test_basic_regexp_line_number_03() {
         &["-n", BRE, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -290,7 +290,7 @@ fn test_basic_regexp_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -353,8 +353,8 @@ fn test_extended_regexp_03() {
         &["-E", ERE, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -459,8 +459,8 @@ fn test_extended_regexp_line_number_03() {
         &["-E", "-n", ERE, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -525,7 +525,7 @@ fn test_extended_regexp_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -594,8 +594,8 @@ fn test_fixed_strings_03() {
         &["-F", FIXED, INVALID_LINE_INPUT_FILE],
         "",
         "line_{1}\np_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -706,8 +706,8 @@ fn test_fixed_strings_line_number_03() {
         &["-F", "-n", FIXED, INVALID_LINE_INPUT_FILE],
         "",
         "1:line_{1}\n3:p_line_{2}_s\n",
-        "tests/grep/invalid_line: Error reading line 2 (stream did not contain valid UTF-8)\n",
-        2,
+        "",
+        0,
     );
 }
 
@@ -772,7 +772,7 @@ fn test_fixed_strings_no_messages_with_error_05() {
         "",
         "line_{1}\np_line_{2}_s\n",
         "",
-        2,
+        0,
     );
 }
 
@@ -1389,3 +1389,152 @@ fn test_long_names_files() {
             0,
         );
 }
+
+const CONTEXT_INPUT: &str = "a\nb\nfoo\nc\nd\ne\nf\nfoo bar\ng\n";
+const TREE_DIR: &str = "tests/grep/tree";
+
+#[test]
+fn test_context() {
+    grep_test(
+        &["-n", "-C", "1", "foo"],
+        CONTEXT_INPUT,
+        "2-b\n3:foo\n4-c\n--\n7-f\n8:foo bar\n9-g\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_after_context() {
+    grep_test(
+        &["-A", "1", "foo"],
+        CONTEXT_INPUT,
+        "foo\nc\n--\nfoo bar\ng\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_before_context_adjacent_groups() {
+    grep_test(
+        &["-B", "4", "foo"],
+        CONTEXT_INPUT,
+        "a\nb\nfoo\nc\nd\ne\nf\nfoo bar\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_only_matching() {
+    grep_test(&["-o", "o*"], CONTEXT_INPUT, "oo\noo\n", "", 0);
+    grep_test(&["-on", "[a-z]o"], CONTEXT_INPUT, "3:fo\n8:fo\n", "", 0);
+}
+
+#[test]
+fn test_word_regexp() {
+    grep_test(&["-w", "fo*"], "food\nfoo\nx f y\n", "foo\nx f y\n", "", 0);
+    grep_test(
+        &["-Fwi", "FOO"],
+        "food\nfoo_\nthe Foo.\n",
+        "the Foo.\n",
+        "",
+        0,
+    );
+    // The longest match at the start is not a word, but a shorter one is.
+    grep_test(
+        &["-w", "ab[ c]*"],
+        "ab c_\nab cd\n",
+        "ab c_\nab cd\n",
+        "",
+        0,
+    );
+    grep_test(&["-ow", "ab[ c]*"], "ab c_\n", "ab\n", "", 0);
+}
+
+#[test]
+fn test_recursive() {
+    grep_test(
+        &["-r", "foo", TREE_DIR],
+        "",
+        "tests/grep/tree/a:foo bar\ntests/grep/tree/sub/b:food\ntests/grep/tree/sub/b:foo\n",
+        "",
+        0,
+    );
+    grep_test(&["-rc", "-h", "food", TREE_DIR], "", "0\n1\n", "", 0);
+}
+
+#[test]
+fn test_with_filename() {
+    grep_test(
+        &["-H", "bar", "tests/grep/tree/a"],
+        "",
+        "tests/grep/tree/a:foo bar\n",
+        "",
+        0,
+    );
+    grep_test(&["-r", "bar", "tests/grep/tree/a"], "", "foo bar\n", "", 0);
+}
+
+const BINARY_INPUT_FILE: &str = "tests/grep/binary";
+
+#[test]
+fn test_binary_file_matches() {
+    grep_test(
+        &["match", BINARY_INPUT_FILE],
+        "",
+        "Binary file tests/grep/binary matches\n",
+        "",
+        0,
+    );
+    grep_test(&["-c", "line\\|more", BINARY_INPUT_FILE], "", "2\n", "", 0);
+    grep_test(&["nothing", BINARY_INPUT_FILE], "", "", "", 1);
+}
+
+#[test]
+fn test_binary_files_as_text() {
+    grep_test(
+        &["-a", "-n", "match", BINARY_INPUT_FILE],
+        "",
+        "2:\0\u{1}\u{2} match here\n",
+        "",
+        0,
+    );
+    grep_test(
+        &["--binary-files=text", "-o", "m[a-z]*", BINARY_INPUT_FILE],
+        "",
+        "match\nmore\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_binary_files_without_match() {
+    grep_test(&["-I", "match", BINARY_INPUT_FILE], "", "", "", 1);
+    grep_test(
+        &[
+            "--binary-files=without-match",
+            "-l",
+            "line",
+            BINARY_INPUT_FILE,
+            INPUT_FILE_1,
+        ],
+        "",
+        "tests/grep/f_1\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_invalid_utf8_only_matching() {
+    grep_test(
+        &["-o", "line_{[0-9]}", INVALID_LINE_INPUT_FILE],
+        "",
+        "line_{1}\nline_{2}\n",
+        "",
+        0,
+    );
+}
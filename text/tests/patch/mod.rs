@@ -0,0 +1,294 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, run_test_with_checker, scratch_dir, TestPlan};
+use std::{fs, process::Command};
+
+const OLD: &str = "tests/patch/old.txt";
+const NEW: &str = "tests/patch/new.txt";
+
+fn patch_test(args: &[&str], patch: &str, expected_output: &str, expected_exit_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("patch"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(patch),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(""),
+        expected_exit_code,
+    });
+}
+
+/// Runs our own `diff` on the test files.
+fn diff(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_diff"))
+        .args(args)
+        .output()
+        .expect("failed to run diff");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn round_trip(format: &[&str]) {
+    let mut args = format.to_vec();
+    args.extend([OLD, NEW]);
+    let patch = diff(&args);
+    let expected = fs::read_to_string(NEW).unwrap();
+    patch_test(&["-s", "-o", "-", OLD], &patch, &expected, 0);
+}
+
+#[test]
+fn test_patch_normal_round_trip() {
+    round_trip(&[]);
+}
+
+#[test]
+fn test_patch_context_round_trip() {
+    round_trip(&["-c"]);
+    round_trip(&["-C", "1"]);
+}
+
+#[test]
+fn test_patch_unified_round_trip() {
+    round_trip(&["-u"]);
+    round_trip(&["-U", "0"]);
+}
+
+#[test]
+fn test_patch_ed_round_trip() {
+    round_trip(&["-e"]);
+}
+
+#[test]
+fn test_patch_reverse() {
+    let patch = diff(&["-u", OLD, NEW]);
+    let expected = fs::read_to_string(OLD).unwrap();
+    patch_test(&["-s", "-R", "-o", "-", NEW], &patch, &expected, 0);
+}
+
+#[test]
+fn test_patch_forced_format() {
+    run_test(TestPlan {
+        cmd: String::from("patch"),
+        args: vec![String::from("-u"), String::from(OLD)],
+        stdin_data: diff(&["-c", OLD, NEW]),
+        expected_out: String::new(),
+        expected_err: String::from("patch: **** Only garbage was found in the patch input.\n"),
+        expected_exit_code: 2,
+    });
+}
+
+#[test]
+fn test_patch_offset_and_fuzz() {
+    // The hunk claims to be seven lines further down than it is, and its
+    // first line of context no longer matches.
+    let patch = "\
+--- old.txt
++++ new.txt
+@@ -9,5 +9,5 @@
+ The name that can be named is NOT the eternal name.
+ The Nameless is the origin of Heaven and Earth;
+-The Named is the mother of all things.
++The named is the mother of all things.
+ Therefore let there always be non-being,
+   so we may see their subtlety,
+";
+    let expected = fs::read_to_string(OLD)
+        .unwrap()
+        .replace("The Named", "The named");
+    patch_test(
+        &["-o", "-", OLD],
+        patch,
+        &format!(
+            "patching file {}\nHunk #1 succeeded at 2 with fuzz 1 (offset -7 lines).\n{}",
+            OLD, expected
+        ),
+        0,
+    );
+    patch_test(
+        &["-F", "0", "-r", "/dev/null", "-o", "-", OLD],
+        patch,
+        &format!(
+            "patching file {}\nHunk #1 FAILED at 9.\n\
+             1 out of 1 hunk FAILED -- saving rejects to file /dev/null\n{}",
+            OLD,
+            fs::read_to_string(OLD).unwrap()
+        ),
+        1,
+    );
+}
+
+#[test]
+fn test_patch_reject_file() {
+    let dir = scratch_dir("patch-reject");
+    let reject = dir.join("out.rej");
+    let patch = "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,2 @@
+-The Way that can be told of is not the eternal Way;
++The Way that can be told of is the eternal Way;
+ The name that can be named is not the eternal name.
+@@ -12,3 +12,2 @@
+ They both may be called deep and profound.
+-Deeper and deeper,
+ The door of all subtleties!
+";
+    run_test_with_checker(
+        TestPlan {
+            cmd: String::from("patch"),
+            args: vec![
+                String::from("-s"),
+                String::from("-r"),
+                reject.to_str().unwrap().to_string(),
+                String::from("-o"),
+                dir.join("out").to_str().unwrap().to_string(),
+                String::from(OLD),
+            ],
+            stdin_data: String::from(patch),
+            expected_out: String::new(),
+            expected_err: String::new(),
+            expected_exit_code: 1,
+        },
+        |plan, output| {
+            assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+        },
+    );
+
+    let patched = fs::read_to_string(dir.join("out")).unwrap();
+    assert!(patched.starts_with("The Way that can be told of is the eternal Way;\n"));
+    assert_eq!(
+        fs::read_to_string(&reject).unwrap(),
+        "\
+--- old.txt
++++ new.txt
+@@ -12,3 +12,2 @@
+ They both may be called deep and profound.
+-Deeper and deeper,
+ The door of all subtleties!
+"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_patch_strip_and_backup() {
+    let dir = scratch_dir("patch-strip");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::copy(OLD, dir.join("src/tao.txt")).unwrap();
+
+    let patch = diff(&[
+        "-u",
+        "--label",
+        "a/src/tao.txt",
+        "--label2",
+        "b/src/tao.txt",
+        OLD,
+        NEW,
+    ]);
+    patch_test(
+        &["-p1", "-b", "-d", dir.to_str().unwrap()],
+        &patch,
+        "patching file src/tao.txt\n",
+        0,
+    );
+
+    assert_eq!(
+        fs::read_to_string(dir.join("src/tao.txt")).unwrap(),
+        fs::read_to_string(NEW).unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("src/tao.txt.orig")).unwrap(),
+        fs::read_to_string(OLD).unwrap()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_patch_already_applied() {
+    let patch = diff(&["-u", OLD, NEW]);
+    patch_test(
+        &["-N", "--dry-run", NEW],
+        &patch,
+        &format!(
+            "checking file {}\nReversed (or previously applied) patch detected!  Skipping patch.\n",
+            NEW
+        ),
+        0,
+    );
+}
+
+#[test]
+fn test_patch_no_newline_at_end() {
+    let patch = "\
+--- a
++++ b
+@@ -1,3 +1,2 @@
+ The Way that can be told of is not the eternal Way;
+-The name that can be named is not the eternal name.
+-The Nameless is the origin of Heaven and Earth;
++The name that can be named is not the eternal name.
+\\ No newline at end of file
+";
+    let dir = scratch_dir("patch-no-newline");
+    let file = dir.join("tao.txt");
+    fs::write(
+        &file,
+        "The Way that can be told of is not the eternal Way;\n\
+         The name that can be named is not the eternal name.\n\
+         The Nameless is the origin of Heaven and Earth;\n",
+    )
+    .unwrap();
+
+    patch_test(&["-s", file.to_str().unwrap()], patch, "", 0);
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "The Way that can be told of is not the eternal Way;\n\
+         The name that can be named is not the eternal name."
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_patch_ifdef() {
+    let patch = "\
+2c2
+< The name that can be named is not the eternal name.
+---
+> The name that can be named is the eternal name.
+";
+    let old = fs::read_to_string(OLD).unwrap();
+    let mut lines: Vec<&str> = old.lines().collect();
+    lines.splice(
+        1..2,
+        [
+            "#ifndef TAO",
+            "The name that can be named is not the eternal name.",
+            "#else",
+            "The name that can be named is the eternal name.",
+            "#endif",
+        ],
+    );
+    patch_test(
+        &["-s", "-D", "TAO", "-o", "-", OLD],
+        patch,
+        &format!("{}\n", lines.join("\n")),
+        0,
+    );
+}
+
+#[test]
+fn test_patch_only_garbage() {
+    run_test(TestPlan {
+        cmd: String::from("patch"),
+        args: vec![String::from(OLD)],
+        stdin_data: String::from("this is not a patch\n"),
+        expected_out: String::new(),
+        expected_err: String::from("patch: **** Only garbage was found in the patch input.\n"),
+        expected_exit_code: 2,
+    });
+}
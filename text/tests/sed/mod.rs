@@ -0,0 +1,281 @@
+//
+// Copyright (c) 2024 Hemi Labs, Inc.
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::{run_test, TestPlan};
+
+const LINES: &str = "one\ntwo\nthree\nfour\nfive\nsix\n";
+
+fn sed_test(args: &[&str], test_data: &str, expected_output: &str) {
+    sed_test_with_error(args, test_data, expected_output, "", 0);
+}
+
+fn sed_test_with_error(
+    args: &[&str],
+    test_data: &str,
+    expected_output: &str,
+    expected_error: &str,
+    expected_exit_code: i32,
+) {
+    run_test(TestPlan {
+        cmd: String::from("sed"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_err: String::from(expected_error),
+        expected_exit_code,
+    });
+}
+
+#[test]
+fn test_sed_print_line() {
+    sed_test(&["-n", "2p"], LINES, "two\n");
+}
+
+#[test]
+fn test_sed_delete_range() {
+    sed_test(&["2,4d"], LINES, "one\nfive\nsix\n");
+}
+
+#[test]
+fn test_sed_regex_range() {
+    sed_test(&["-n", "/two/,/four/p"], LINES, "two\nthree\nfour\n");
+}
+
+#[test]
+fn test_sed_range_end_before_start() {
+    sed_test(&["-n", "/two/,1p"], LINES, "two\n");
+}
+
+#[test]
+fn test_sed_last_line() {
+    sed_test(&["-n", "$p"], LINES, "six\n");
+}
+
+#[test]
+fn test_sed_negation() {
+    sed_test(&["3!d"], LINES, "three\n");
+}
+
+#[test]
+fn test_sed_substitute() {
+    sed_test(&["s/o/0/g"], LINES, "0ne\ntw0\nthree\nf0ur\nfive\nsix\n");
+    sed_test(&["s/e/E/2"], "eee\n", "eEe\n");
+    sed_test(&["s/e/E/2g"], "eee\n", "eEE\n");
+}
+
+#[test]
+fn test_sed_substitute_empty_matches() {
+    sed_test(&["s/b*/x/g"], "abc\n", "xaxcx\n");
+    sed_test(&["s/x*/-/g"], "abc\n", "-a-b-c-\n");
+}
+
+#[test]
+fn test_sed_substitute_groups() {
+    sed_test(&["s/\\(a\\)\\(b\\)/\\2\\1/g"], "abab\n", "baba\n");
+    sed_test(&["-E", "s/(a)(b)/\\2\\1/g"], "abab\n", "baba\n");
+    sed_test(&["s/.*/[&]/"], "x\n", "[x]\n");
+    sed_test(&["s/x/\\&/"], "x\n", "&\n");
+}
+
+#[test]
+fn test_sed_substitute_newline() {
+    sed_test(&["s/ /\\n/"], "a b\n", "a\nb\n");
+    sed_test(&["s/ /\\\n/"], "a b\n", "a\nb\n");
+    sed_test(&["N;s/\\n/+/"], "a\nb\n", "a+b\n");
+}
+
+#[test]
+fn test_sed_substitute_after_nul() {
+    sed_test(&["s/b/X/g"], "ab\0ba\n", "aX\0Xa\n");
+    sed_test(&["-n", "/c/p"], "a\0c\n", "a\0c\n");
+}
+
+#[test]
+fn test_sed_substitute_delimiters() {
+    sed_test(&["s|/|_|g"], "/a/b\n", "_a_b\n");
+    sed_test(&["s/[/]/_/"], "/a\n", "_a\n");
+    sed_test(&["s,a\\,b,X,"], "a,b\n", "X\n");
+}
+
+#[test]
+fn test_sed_substitute_print() {
+    sed_test(&["-n", "s/t/T/p"], LINES, "Two\nThree\n");
+}
+
+#[test]
+fn test_sed_empty_regex() {
+    sed_test(&["-n", "/t/s//T/p"], LINES, "Two\nThree\n");
+}
+
+#[test]
+fn test_sed_transliterate() {
+    sed_test(&["y/abc/xyz/"], "aabbcc\n", "xxyyzz\n");
+    sed_test(&["N;y/\\n/ /"], "a\nb\n", "a b\n");
+}
+
+#[test]
+fn test_sed_insert_append_change() {
+    sed_test(&["1i\\\nfirst"], "a\nb\n", "first\na\nb\n");
+    sed_test(&["1a\\\nafter\\\nmore"], "a\nb\n", "a\nafter\nmore\nb\n");
+    sed_test(&["$a end"], "a\nb\n", "a\nb\nend\n");
+    sed_test(&["2i\\  indented"], "a\nb\n", "a\n  indented\nb\n");
+    sed_test(&["-e", "a\\", "-e", "x"], "a\n", "a\nx\n");
+}
+
+#[test]
+fn test_sed_change_range() {
+    sed_test(&["2,4c\\\nchanged"], LINES, "one\nchanged\nfive\nsix\n");
+    sed_test(&["2,5!c\\\nX"], LINES, "X\ntwo\nthree\nfour\nfive\nX\n");
+}
+
+#[test]
+fn test_sed_append_survives_delete() {
+    sed_test(&["a\\\nX\nd"], "a\nb\n", "X\nX\n");
+}
+
+#[test]
+fn test_sed_next() {
+    sed_test(&["n;d"], LINES, "one\nthree\nfive\n");
+    sed_test(&["$!N;s/\\n/-/"], "1\n2\n3\n", "1-2\n3\n");
+}
+
+#[test]
+fn test_sed_multiline_delete_print() {
+    sed_test(&["$!N;P;D"], "1\n2\n3\n", "1\n2\n3\n");
+}
+
+#[test]
+fn test_sed_hold_space() {
+    // tac
+    sed_test(&["1!G;h;$!d"], "1\n2\n3\n", "3\n2\n1\n");
+    sed_test(&["-n", "h;n;G;p"], "1\n2\n3\n4\n", "2\n1\n4\n3\n");
+    sed_test(&["x;$!d"], "1\n2\n3\n", "2\n");
+    sed_test(&["1h;1!H;$!d;x;s/\\n/+/g"], "1\n2\n3\n", "1+2+3\n");
+}
+
+#[test]
+fn test_sed_branches() {
+    sed_test(&[":a;N;$!ba;s/\\n/,/g"], "1\n2\n3\n", "1,2,3\n");
+    sed_test(
+        &["s/e/E/;ta;s/$/ no/;b;:a;s/$/ yes/"],
+        "one\ntwo\n",
+        "onE yes\ntwo no\n",
+    );
+}
+
+#[test]
+fn test_sed_blocks() {
+    sed_test(&["-n", "/t/{s/t/T/;p}"], LINES, "Two\nThree\n");
+    sed_test(&["/two/,4{/three/d}"], LINES, "one\ntwo\nfour\nfive\nsix\n");
+    sed_test(&["-n", "2{p;q}"], LINES, "two\n");
+}
+
+#[test]
+fn test_sed_quit() {
+    sed_test(&["2q"], LINES, "one\ntwo\n");
+    sed_test(&["1a\\\nX\n1q"], LINES, "one\nX\n");
+}
+
+#[test]
+fn test_sed_line_number() {
+    sed_test(&["-n", "/o/="], LINES, "1\n2\n4\n");
+}
+
+#[test]
+fn test_sed_list() {
+    sed_test(&["-n", "l"], "a\tb\\\u{1}\n", "a\\tb\\\\\\001$\n");
+    let long = "a".repeat(100);
+    let expected = format!("{}\\\n{}$\n", "a".repeat(69), "a".repeat(31));
+    sed_test(&["-n", "l"], &format!("{}\n", long), &expected);
+}
+
+#[test]
+fn test_sed_comment_suppresses_output() {
+    sed_test(&["#n\n2p"], LINES, "two\n");
+}
+
+#[test]
+fn test_sed_script_order() {
+    sed_test(&["-e", "s/a/b/", "-e", "s/b/c/"], "a\n", "c\n");
+}
+
+#[test]
+fn test_sed_script_file() {
+    sed_test(
+        &["-f", "tests/sed/script.sed", "-"],
+        "hello\nworld\n",
+        "HELLO\nworld\n--\n",
+    );
+}
+
+#[test]
+fn test_sed_read_file() {
+    sed_test(
+        &["1r tests/sed/script.sed"],
+        "a\nb\n",
+        "a\n# uppercase the first line\n1y/ehlo/EHLO/\n$a\\\n--\nb\n",
+    );
+    sed_test(&["1r /nonexistent/file"], "a\n", "a\n");
+}
+
+#[test]
+fn test_sed_write_stdout() {
+    sed_test(&["-n", "/o/w /dev/stdout"], LINES, "one\ntwo\nfour\n");
+}
+
+#[test]
+fn test_sed_errors() {
+    sed_test_with_error(
+        &["k"],
+        "",
+        "",
+        "sed: script line 1: unknown command: `k'\n",
+        1,
+    );
+    sed_test_with_error(
+        &["s/a/b"],
+        "",
+        "",
+        "sed: script line 1: unterminated `s' command\n",
+        1,
+    );
+    sed_test_with_error(&["/x/{p"], "", "", "sed: script line 1: unmatched `{'\n", 1);
+    sed_test_with_error(
+        &["bnowhere"],
+        "",
+        "",
+        "sed: can't find label for jump to `nowhere'\n",
+        1,
+    );
+    sed_test_with_error(
+        &["s/\\(a\\)/\\2/"],
+        "",
+        "",
+        "sed: script line 1: invalid reference \\2 on `s' command's RHS\n",
+        1,
+    );
+    sed_test_with_error(
+        &["y/ab/c/"],
+        "",
+        "",
+        "sed: script line 1: strings for `y' command are different lengths\n",
+        1,
+    );
+}
+
+#[test]
+fn test_sed_missing_input_file() {
+    sed_test_with_error(
+        &["-n", "$=", "/nonexistent/file", "-"],
+        "a\nb\n",
+        "2\n",
+        "sed: can't read /nonexistent/file: No such file or directory (os error 2)\n",
+        2,
+    );
+}
@@ -613,3 +613,84 @@ fn test_files_sort_2() {
             "",
         );
 }
+
+/// Returns the numbers 0..n, one per line, in a scrambled order.
+fn scrambled_numbers(n: usize) -> String {
+    (0..n).map(|i| format!("{}\n", i * 7919 % n)).collect()
+}
+
+#[test]
+fn test_external_sort() {
+    // A 1 KiB buffer forces dozens of runs and more than one merge pass.
+    let expected: String = (0..5000).map(|i| format!("{}\n", i)).collect();
+    sort_test(
+        &["-n", "-S", "1K", "-T", "/tmp", "-"],
+        &scrambled_numbers(5000),
+        &expected,
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_external_sort_unique() {
+    // Duplicates end up in different runs and must be removed while merging.
+    let input = scrambled_numbers(2000).repeat(2);
+    let expected: String = (0..2000).map(|i| format!("{}\n", i)).collect();
+    sort_test(&["-n", "-u", "-S", "1K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_external_sort_stable() {
+    // Lines with equal keys keep their input order across runs.
+    let input: String = (0..1000).map(|i| format!("{} {}\n", i % 2, i)).collect();
+    let expected: String = (0..1000)
+        .filter(|i| i % 2 == 0)
+        .chain((0..1000).filter(|i| i % 2 == 1))
+        .map(|i| format!("{} {}\n", i % 2, i))
+        .collect();
+    sort_test(&["-k1,1", "-S", "1K", "-"], &input, &expected, 0, "");
+}
+
+#[test]
+fn test_external_sort_bad_tmpdir() {
+    sort_test(
+        &["-S", "1K", "-T", "/nonexistent/dir", "-"],
+        &scrambled_numbers(1000),
+        "",
+        1,
+        "cannot create temporary file in /nonexistent/dir: No such file or directory (os error 2)\n",
+    );
+}
+
+#[test]
+fn test_unique() {
+    sort_test(&["-u", "-"], "b\na\nb\nc\na\n", "a\nb\nc\n", 0, "");
+}
+
+#[test]
+fn test_unique_key() {
+    sort_test(
+        &["-u", "-k2,2", "-"],
+        "x 1\ny 1\nz 0\n",
+        "z 0\nx 1\n",
+        0,
+        "",
+    );
+}
+
+#[test]
+fn test_reverse_key() {
+    sort_test(&["-r", "-k1,1", "-"], "a\nc\nb\n", "c\nb\na\n", 0, "");
+}
+
+#[test]
+fn test_merge() {
+    sort_test(
+        &["-m", "tests/assets/in_seq", "tests/assets/in_seq"],
+        "",
+        "1\n1\n2\n2\n3\n3\n4\n4\n5\n5\n6\n6\n",
+        0,
+        "",
+    );
+}
@@ -17,7 +17,9 @@ mod head;
 mod join;
 mod nl;
 mod paste;
+mod patch;
 mod pr;
+mod sed;
 mod sort;
 mod tail;
 mod tr;